@@ -0,0 +1,256 @@
+//! Derive macros for figtree's `FromNode` and `ToNode` traits, which convert between
+//! nodes and structs without going through serde.  These are re-exported by figtree
+//! with its `derive` feature.
+//!
+//! Each field of the struct is read from, and written to, an attribute of the node with
+//! the same key, unless it says otherwise with a `#[figtree(...)]` attribute:
+//!
+//! - `rename = "key"` uses a different key, or name for subnodes
+//! - `argument` reads the field from the next argument of the node, counting the
+//!   fields marked as arguments in order
+//! - `arguments` reads every argument from there onwards into a `Vec`
+//! - `child` reads the field from the first subnode with the field's name, with
+//!   `FromNode`
+//! - `children` reads every subnode with the field's name into a `Vec`, with `FromNode`
+//! - `default` uses `Default::default()` if the attribute, argument or subnode is
+//!   missing, and `default = "path"` calls the function at that path instead
+//!
+//! Attributes, arguments and subnodes are otherwise required, except for `Option`
+//! fields, which are `None` if they're missing.  They are also left out when the struct
+//! is written as a node, if they are `None`.
+//!
+//! # Examples
+//! ```
+//! extern crate figtree;
+//!
+//! use figtree::{Figtree, FromNode, ParserOptions, ToNode};
+//!
+//! #[derive(FromNode, ToNode, Debug, PartialEq)]
+//! struct Server {
+//!     #[figtree(argument)]
+//!     name: String,
+//!     #[figtree(rename = "listen_port")]
+//!     port: u16,
+//!     #[figtree(default)]
+//!     workers: u32,
+//!     #[figtree(children, rename = "upstream")]
+//!     upstreams: Vec<Upstream>,
+//! }
+//!
+//! #[derive(FromNode, ToNode, Debug, PartialEq)]
+//! struct Upstream {
+//!     host: String,
+//!     weight: Option<i64>,
+//! }
+//!
+//! fn main() {
+//!     let input = "server 'web' {
+//!         listen_port = 8080
+//!         upstream { host = 'a', weight = 2 }
+//!         upstream { host = 'b' }
+//!     }";
+//!     let options = ParserOptions::new().node_arguments(true).repeated_nodes(true);
+//!     let doc = Figtree::from_string(input).with_options(options).parse().unwrap();
+//!     let server = Server::from_node(&doc["server"]).unwrap();
+//!     assert_eq!((server.name.as_str(), server.port, server.workers), ("web", 8080, 0));
+//!     assert_eq!(server.upstreams[1], Upstream { host: "b".into(), weight: None });
+//!     assert_eq!(Server::from_node(&server.to_node()).unwrap(), server);
+//! }
+//! ```
+
+extern crate proc_macro;
+extern crate proc_macro2;
+#[macro_use]
+extern crate quote;
+extern crate syn;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as Tokens;
+use syn::{Data, DeriveInput, Fields, Ident, Type};
+
+/// Derive `FromNode` for a struct with named fields
+#[proc_macro_derive(FromNode, attributes(figtree))]
+pub fn derive_from_node(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+    expand(&input, from_node).unwrap_or_else(syn::Error::into_compile_error).into()
+}
+
+/// Derive `ToNode` for a struct with named fields
+#[proc_macro_derive(ToNode, attributes(figtree))]
+pub fn derive_to_node(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+    expand(&input, to_node).unwrap_or_else(syn::Error::into_compile_error).into()
+}
+
+/// Where in the node a field is kept
+enum Kind {
+    Attr,
+    /// an argument, with its index
+    Argument(usize),
+    /// every argument from this index onwards
+    Arguments(usize),
+    Child,
+    Children,
+}
+
+/// What to use for a field that's missing from the node
+enum Fallback {
+    Required,
+    Trait,
+    Function(syn::Path),
+}
+
+/// A field of the struct, and how it's kept in the node
+struct Field {
+    ident: Ident,
+    /// the key or name of the field in the node
+    key: String,
+    kind: Kind,
+    fallback: Fallback,
+    optional: bool,
+}
+
+fn expand(input: &DeriveInput, derive: fn(&DeriveInput, &[Field]) -> Tokens)
+          -> syn::Result<Tokens> {
+    let fields = match input.data {
+        Data::Struct(ref data) => match data.fields {
+            Fields::Named(ref fields) => &fields.named,
+            _ => return Err(syn::Error::new_spanned(input, "expected a struct with named fields")),
+        },
+        _ => return Err(syn::Error::new_spanned(input, "expected a struct with named fields")),
+    };
+    let mut parsed = Vec::new();
+    let mut arguments = 0;
+    let mut rest = None;
+    for field in fields {
+        let parsed_field = parse_field(field, &mut arguments)?;
+        if let Kind::Argument(_) | Kind::Arguments(_) = parsed_field.kind {
+            if let Some(ident) = rest {
+                let message = format!("`{}` already takes the rest of the arguments", ident);
+                return Err(syn::Error::new_spanned(field, message));
+            }
+        }
+        if let Kind::Arguments(_) = parsed_field.kind {
+            rest = Some(parsed_field.ident.clone());
+        }
+        parsed.push(parsed_field);
+    }
+    Ok(derive(input, &parsed))
+}
+
+fn parse_field(field: &syn::Field, arguments: &mut usize) -> syn::Result<Field> {
+    let ident = field.ident.clone().expect("fields are named");
+    let mut key = ident.to_string().trim_start_matches("r#").to_string();
+    let mut kind = Kind::Attr;
+    let mut fallback = Fallback::Required;
+    for attr in field.attrs.iter().filter(|attr| attr.path().is_ident("figtree")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("rename") {
+                key = meta.value()?.parse::<syn::LitStr>()?.value();
+            } else if meta.path.is_ident("argument") {
+                kind = Kind::Argument(*arguments);
+                *arguments += 1;
+            } else if meta.path.is_ident("arguments") {
+                kind = Kind::Arguments(*arguments);
+            } else if meta.path.is_ident("child") {
+                kind = Kind::Child;
+            } else if meta.path.is_ident("children") {
+                kind = Kind::Children;
+            } else if meta.path.is_ident("default") {
+                fallback = if meta.input.peek(syn::Token![=]) {
+                    Fallback::Function(meta.value()?.parse::<syn::LitStr>()?.parse()?)
+                } else {
+                    Fallback::Trait
+                };
+            } else {
+                return Err(meta.error("unknown figtree attribute"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(Field { ident, key, kind, fallback, optional: is_option(&field.ty) })
+}
+
+/// Check if a type is an `Option`, going by its name
+fn is_option(ty: &Type) -> bool {
+    match *ty {
+        Type::Path(ref path) => path.path.segments.last()
+            .is_some_and(|segment| segment.ident == "Option"),
+        _ => false,
+    }
+}
+
+fn from_node(input: &DeriveInput, fields: &[Field]) -> Tokens {
+    let name = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+    let fields = fields.iter().map(|field| {
+        let (ident, key) = (&field.ident, &field.key);
+        let default = match field.fallback {
+            Fallback::Required if !field.optional => None,
+            Fallback::Required | Fallback::Trait => Some(quote!(::std::default::Default::default)),
+            Fallback::Function(ref path) => Some(quote!(#path)),
+        };
+        let value = match (&field.kind, default) {
+            (&Kind::Attr, None) => quote!(node.require_attr(#key)?),
+            (&Kind::Attr, Some(default)) =>
+                quote!(node.convert_attr(#key)?.unwrap_or_else(#default)),
+            (&Kind::Argument(index), None) => quote!(node.require_argument(#index)?),
+            (&Kind::Argument(index), Some(default)) =>
+                quote!(node.convert_argument(#index)?.unwrap_or_else(#default)),
+            (&Kind::Arguments(index), _) => quote!(node.convert_arguments(#index)?),
+            (&Kind::Child, _) if field.optional => quote!(node.convert_child(#key)?),
+            (&Kind::Child, None) => quote!(node.require_child(#key)?),
+            (&Kind::Child, Some(default)) =>
+                quote!(node.convert_child(#key)?.unwrap_or_else(#default)),
+            (&Kind::Children, _) => quote!(node.convert_children(#key)?),
+        };
+        quote!(#ident: #value)
+    });
+    quote! {
+        impl #impl_generics ::figtree::FromNode for #name #type_generics #where_clause {
+            fn from_node(node: &::figtree::Node)
+                         -> ::std::result::Result<Self, ::figtree::FromNodeError> {
+                ::std::result::Result::Ok(#name { #(#fields,)* })
+            }
+        }
+    }
+}
+
+fn to_node(input: &DeriveInput, fields: &[Field]) -> Tokens {
+    let name = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+    let fields = fields.iter().map(|field| {
+        let (ident, key) = (&field.ident, &field.key);
+        let write = |value: Tokens| match field.kind {
+            Kind::Attr => quote!(node.insert_attr(#key, ::figtree::ToValue::to_value(#value));),
+            Kind::Argument(_) => quote!(node.push_argument(::figtree::ToValue::to_value(#value));),
+            Kind::Child => quote!(node.append_node(#key, ::figtree::ToNode::to_node(#value));),
+            Kind::Arguments(_) => quote! {
+                for argument in #value {
+                    node.push_argument(::figtree::ToValue::to_value(argument));
+                }
+            },
+            Kind::Children => quote! {
+                for child in #value {
+                    node.append_node(#key, ::figtree::ToNode::to_node(child));
+                }
+            },
+        };
+        match field.kind {
+            Kind::Attr | Kind::Argument(_) | Kind::Child if field.optional => {
+                let write = write(quote!(value));
+                quote!(if let ::std::option::Option::Some(ref value) = self.#ident { #write })
+            },
+            _ => write(quote!(&self.#ident)),
+        }
+    });
+    quote! {
+        impl #impl_generics ::figtree::ToNode for #name #type_generics #where_clause {
+            fn to_node(&self) -> ::figtree::Node {
+                let mut node = ::figtree::Node::new();
+                #(#fields)*
+                node
+            }
+        }
+    }
+}
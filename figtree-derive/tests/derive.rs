@@ -0,0 +1,120 @@
+extern crate figtree;
+
+use figtree::types::*;
+use figtree::{Figtree, FromNode, FromNodeError, NodeItem, ParserOptions, ToNode};
+
+#[derive(FromNode, ToNode, Debug, PartialEq)]
+struct Listen {
+    #[figtree(argument)]
+    port: u16,
+    #[figtree(argument)]
+    protocol: Option<String>,
+    #[figtree(default = "default_backlog")]
+    backlog: i64,
+    tags: Vec<String>,
+    #[figtree(child)]
+    tls: Option<Tls>,
+    #[figtree(child, default)]
+    limits: Limits,
+}
+
+#[derive(FromNode, ToNode, Debug, PartialEq)]
+struct Tls {
+    cert: String,
+}
+
+#[derive(FromNode, ToNode, Debug, PartialEq, Default)]
+struct Limits {
+    #[figtree(rename = "max_connections")]
+    connections: Option<u32>,
+}
+
+#[derive(FromNode, ToNode, Debug, PartialEq)]
+struct Route {
+    #[figtree(arguments)]
+    weights: Vec<u8>,
+    #[figtree(children, rename = "listen")]
+    listeners: Vec<Listen>,
+    r#type: Option<String>,
+}
+
+fn default_backlog() -> i64 {
+    128
+}
+
+fn parse(input: &str) -> Document {
+    let options = ParserOptions::new().node_arguments(true).repeated_nodes(true);
+    Figtree::from_string(input).with_options(options).parse().unwrap()
+}
+
+#[test]
+fn converts_nodes_to_structs() {
+    let doc = parse("
+        route 1 2 {
+            type = 'static'
+            listen 80 { tags = [] }
+            listen 443 'tcp' {
+                backlog = 5, tags = ['secure']
+                tls { cert = 'a.pem' }
+                limits { max_connections = 10 }
+            }
+        }
+    ");
+    let route = Route::from_node(&doc["route"]).unwrap();
+    assert_eq!(route, Route {
+        weights: vec![1, 2],
+        listeners: vec![
+            Listen {
+                port: 80,
+                protocol: None,
+                backlog: 128,
+                tags: vec![],
+                tls: None,
+                limits: Limits { connections: None },
+            },
+            Listen {
+                port: 443,
+                protocol: Some("tcp".into()),
+                backlog: 5,
+                tags: vec!["secure".into()],
+                tls: Some(Tls { cert: "a.pem".into() }),
+                limits: Limits { connections: Some(10) },
+            },
+        ],
+        r#type: Some("static".into()),
+    });
+
+    let node = route.to_node();
+    assert_eq!(node.arguments(), &[Value::new_int(1), Value::new_int(2)][..]);
+    assert_eq!(node.get("type"), Some(&Value::from("static")));
+    let secure = node.children_named("listen").nth(1).unwrap();
+    assert_eq!(secure.arguments(), &[Value::new_int(443), Value::from("tcp")][..]);
+    assert_eq!(secure.child("limits").and_then(|limits| limits.get("max_connections")),
+               Some(&Value::new_int(10)));
+    assert_eq!(Route::from_node(&node), Ok(route));
+}
+
+#[test]
+fn reports_missing_and_invalid_fields() {
+    let doc = parse("
+        a { tags = [] }
+        b 99999 { tags = [] }
+        c 1 {}
+        d 1 { tags = [1] }
+        e 1 { tags = [], tls {} }
+    ");
+    let error = Listen::from_node(&doc["a"]).unwrap_err();
+    assert_eq!(error.item, NodeItem::Argument(0));
+    assert_eq!(error.to_string(), "node \"a\" is missing argument 1 (at line 2, column 9)");
+    let error = Listen::from_node(&doc["b"]).unwrap_err();
+    assert_eq!(error.to_string(),
+               "argument 1 in node \"b\": integer 99999 is out of range (at line 3, column 9)");
+    let error = Listen::from_node(&doc["c"]).unwrap_err();
+    assert_eq!((error.item, error.error), (NodeItem::Attr("tags".into()), None));
+    let error = Listen::from_node(&doc["d"]).unwrap_err();
+    assert_eq!(error.to_string(), concat!("attribute \"tags\" in node \"d\": expected a value of",
+                                          " type string, found integer (at line 5, column 22)"));
+    let error: FromNodeError = Listen::from_node(&doc["e"]).unwrap_err();
+    assert_eq!(error.to_string(),
+               "node \"tls\" is missing attribute \"cert\" (at line 6, column 26)");
+}
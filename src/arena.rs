@@ -0,0 +1,492 @@
+//! A read-only document tree that is allocated in a bump arena.
+//!
+//! Parsing a large document into a `types::Document` makes a separate allocation for
+//! every name, string, and list.  The types in this module put all of them into a
+//! `bumpalo::Bump` arena instead, which is much faster to fill, and which frees them all
+//! at once when it is dropped.  They can't be modified once they are built, but can be
+//! converted into the normal types with `to_document`, `to_node`, and `to_value`.
+//!
+//! Requires the `arena` feature.  The `bumpalo` crate is re-exported to provide the
+//! arena.
+//!
+//! # Examples
+//! ```
+//! # use figtree::arena::{Document, Value};
+//! # use std::io::Cursor;
+//! use figtree::bumpalo::Bump;
+//!
+//! let arena = Bump::new();
+//! let doc = Document::parse(&arena, Cursor::new("server { hosts = ['a', 'b'] }")).unwrap();
+//! let hosts = doc.child("server").and_then(|server| server.get("hosts"));
+//! assert_eq!(hosts.and_then(Value::get_list).map(|hosts| hosts.len()), Some(2));
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+use std::time::Duration;
+
+use bumpalo::Bump;
+use bumpalo::collections::Vec as BumpVec;
+
+use super::datetime::DateTime;
+use super::lexer::Lexer;
+use super::parser::{Parser, ParseEvent, ParsedValue, ParseError, DuplicateKeyPolicy};
+use super::position::Span;
+use super::types;
+
+/// The key-value pairs of a dict or a node's attributes, in the order they were written
+pub type Entries<'a> = &'a [(&'a str, Value<'a>)];
+
+/// A value in an arena document.  The equivalent of `types::Value`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Value<'a> {
+    Str(&'a str),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Ident(&'a str),
+    DateTime(DateTime),
+    Duration(Duration),
+    Bytes(&'a [u8]),
+    Dict(Entries<'a>),
+    List(&'a [Value<'a>]),
+    Null,
+}
+
+impl<'a> Value<'a> {
+    /// Extract the contained value if it is a string.
+    pub fn get_str(&self) -> Option<&'a str> {
+        match *self {
+            Value::Str(s) => Some(s),
+            _ => None
+        }
+    }
+
+    /// Extract the contained value if it is an integer
+    pub fn get_int(&self) -> Option<i64> {
+        match *self {
+            Value::Int(s) => Some(s),
+            _ => None
+        }
+    }
+
+    /// Extract the contained value if it is a float
+    pub fn get_float(&self) -> Option<f64> {
+        match *self {
+            Value::Float(s) => Some(s),
+            _ => None
+        }
+    }
+
+    /// Extract the contained value if it is a boolean
+    pub fn get_bool(&self) -> Option<bool> {
+        match *self {
+            Value::Bool(s) => Some(s),
+            _ => None
+        }
+    }
+
+    /// Extract the contained value if it is an identifier
+    pub fn get_ident(&self) -> Option<&'a str> {
+        match *self {
+            Value::Ident(s) => Some(s),
+            _ => None
+        }
+    }
+
+    /// Extract the contained value if it is a bytes value
+    pub fn get_bytes(&self) -> Option<&'a [u8]> {
+        match *self {
+            Value::Bytes(s) => Some(s),
+            _ => None
+        }
+    }
+
+    /// Extract the contained key-value pairs if this is a dict
+    pub fn get_dict(&self) -> Option<Entries<'a>> {
+        match *self {
+            Value::Dict(s) => Some(s),
+            _ => None
+        }
+    }
+
+    /// Extract the contained values if this is a list
+    pub fn get_list(&self) -> Option<&'a [Value<'a>]> {
+        match *self {
+            Value::List(s) => Some(s),
+            _ => None
+        }
+    }
+
+    /// Copy this value out of the arena
+    pub fn to_value(&self) -> types::Value {
+        match *self {
+            Value::Str(s) => types::Value::new_string(s),
+            Value::Int(i) => types::Value::new_int(i),
+            Value::Float(f) => types::Value::new_float(f),
+            Value::Bool(b) => types::Value::new_bool(b),
+            Value::Ident(i) => types::Value::new_ident(i),
+            Value::DateTime(d) => types::Value::new_datetime(d),
+            Value::Duration(d) => types::Value::new_duration(d),
+            Value::Bytes(b) => types::Value::new_bytes(b),
+            Value::Dict(entries) => types::Value::Dict(entries.iter()
+                .map(|&(key, ref value)| (key.to_string(), value.to_value()))
+                .collect()),
+            Value::List(values) => types::Value::List(values.iter().map(Value::to_value).collect()),
+            Value::Null => types::Value::new_null(),
+        }
+    }
+}
+
+/// A node in an arena document.  The equivalent of `types::Node`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Node<'a> {
+    name: &'a str,
+    arguments: &'a [Value<'a>],
+    children: &'a [Node<'a>],
+    attributes: Entries<'a>,
+}
+
+impl<'a> Node<'a> {
+    /// The name of this node
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    /// The positional arguments given after this node's name, in order
+    pub fn arguments(&self) -> &'a [Value<'a>] {
+        self.arguments
+    }
+
+    /// The subnodes of this node, in order
+    pub fn children(&self) -> &'a [Node<'a>] {
+        self.children
+    }
+
+    /// Get the first subnode with the given name
+    pub fn child(&self, name: &str) -> Option<&'a Node<'a>> {
+        self.children.iter().find(|node| node.name == name)
+    }
+
+    /// Get an iterable of all of the subnodes with the given name, in order
+    pub fn children_named<'b>(&self, name: &'b str) -> impl Iterator<Item = &'a Node<'a>> + 'b
+        where 'a: 'b {
+
+        self.children.iter().filter(move |node| node.name == name)
+    }
+
+    /// The (key, value) pairs of this node's attributes, in order
+    pub fn properties(&self) -> Entries<'a> {
+        self.attributes
+    }
+
+    /// Get the value of the attribute with the given key
+    pub fn get(&self, key: &str) -> Option<&'a Value<'a>> {
+        self.attributes.iter().find(|attr| attr.0 == key).map(|attr| &attr.1)
+    }
+
+    /// Copy this node out of the arena
+    pub fn to_node(&self) -> types::Node {
+        let mut node = types::Node::named(self.name);
+        node.arguments_mut().extend(self.arguments.iter().map(Value::to_value));
+        for &(key, ref value) in self.attributes {
+            node.insert_attr(key, value.to_value());
+        }
+        for child in self.children {
+            node.append_node(child.name, child.to_node());
+        }
+        node
+    }
+}
+
+/// A document whose contents are allocated in an arena.  The equivalent of
+/// `types::Document`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Document<'a> {
+    nodes: &'a [Node<'a>],
+}
+
+impl<'a> Document<'a> {
+    /// Parse a document from a reader, allocating it in `arena`.
+    ///
+    /// # Failures
+    /// Fails if the document can't be parsed, returning the error and the span of the
+    /// input that it occurred in.
+    pub fn parse<R: Read + 'static>(arena: &'a Bump, reader: R)
+        -> Result<Self, (ParseError, Span)> {
+
+        Self::from_parser(arena, Parser::parse(Lexer::lex(reader)))
+    }
+
+    /// Build a document from the events produced by a parser, allocating it in `arena`.
+    /// The parser's options are used, so `ParserOptions::repeated_nodes` and
+    /// `ParserOptions::duplicate_keys` work in the same way as they do for a `Figtree`.
+    ///
+    /// # Failures
+    /// Fails if the document can't be parsed, returning the error and the span of the
+    /// input that it occurred in.
+    pub fn from_parser(arena: &'a Bump, parser: Parser) -> Result<Self, (ParseError, Span)> {
+        let mut builder = Builder { arena, parser };
+        builder.build()
+    }
+
+    /// The nodes of the document, in order
+    pub fn children(&self) -> &'a [Node<'a>] {
+        self.nodes
+    }
+
+    /// Get the first node with the given name
+    pub fn child(&self, name: &str) -> Option<&'a Node<'a>> {
+        self.nodes.iter().find(|node| node.name == name)
+    }
+
+    /// Copy this document out of the arena
+    pub fn to_document(&self) -> types::Document {
+        let mut doc = types::Document::new();
+        for node in self.nodes {
+            doc.append_node(node.name, node.to_node());
+        }
+        doc
+    }
+}
+
+type BuildResult<T> = Result<T, (ParseError, Span)>;
+
+/// Builds an arena document from parse events, in the same way that a `Figtree` builds a
+/// `types::Document`
+struct Builder<'a> {
+    arena: &'a Bump,
+    parser: Parser,
+}
+
+impl<'a> Builder<'a> {
+    fn next(&mut self) -> BuildResult<ParseEvent> {
+        match self.parser.next() {
+            Some(Ok((event, _))) => Ok(event),
+            Some(Err(error)) => Err(error),
+            None => unreachable!("EOF occurred that cannot happen at this time."),
+        }
+    }
+
+    fn build(&mut self) -> BuildResult<Document<'a>> {
+        match self.next()? {
+            ParseEvent::FileStart => {},
+            ev => unreachable!("ParseEvent {:?} occurred that cannot happen at this time.", ev),
+        }
+        let node = self.parse_node("", ParseEvent::FileEnd)?;
+        Ok(Document { nodes: node.children })
+    }
+
+    /// Parse the contents of a node, up to the `end` event
+    fn parse_node(&mut self, name: &'a str, end: ParseEvent) -> BuildResult<Node<'a>> {
+        let mut arguments = BumpVec::new_in(self.arena);
+        let mut children = BumpVec::new_in(self.arena);
+        let mut attributes = EntryBuilder::new(self.arena);
+        let mut names = HashSet::new();
+        loop {
+            match self.parser.next() {
+                Some(Ok((ParseEvent::NodeStart(child, _), span))) => {
+                    if !names.insert(child.clone()) && !self.parser.options().repeated_nodes {
+                        return Err((ParseError::RepeatedNode(child), span));
+                    }
+                    let child = self.arena.alloc_str(&child);
+                    children.push(self.parse_node(child, ParseEvent::NodeEnd)?);
+                },
+                Some(Ok((ParseEvent::Argument(value), _))) => arguments.push(self.scalar(value)),
+                Some(Ok((ParseEvent::Key(key, _), span))) => {
+                    let value = self.parse_value()?;
+                    attributes.add(self.parser.options().duplicate_keys, &key, span, value)?;
+                },
+                Some(Ok((ref event, _))) if *event == end => break,
+                Some(Ok((ev, _))) =>
+                    unreachable!("ParseEvent {:?} occurred that cannot happen at this time.", ev),
+                Some(Err(error)) => return Err(error),
+                None => unreachable!("EOF occurred that cannot happen at this time."),
+            }
+        }
+        Ok(Node {
+            name,
+            arguments: arguments.into_bump_slice(),
+            children: children.into_bump_slice(),
+            attributes: attributes.entries.into_bump_slice(),
+        })
+    }
+
+    /// Copy a value that isn't a list or dict into the arena
+    fn scalar(&self, value: ParsedValue) -> Value<'a> {
+        match value {
+            ParsedValue::Str(s) => Value::Str(self.arena.alloc_str(&s)),
+            ParsedValue::Int(i) => Value::Int(i),
+            ParsedValue::Float(f) => Value::Float(f),
+            ParsedValue::Bool(b) => Value::Bool(b),
+            ParsedValue::Ident(i) => Value::Ident(self.arena.alloc_str(&i)),
+            ParsedValue::DateTime(d) => Value::DateTime(d),
+            ParsedValue::Duration(d) => Value::Duration(d),
+            ParsedValue::Bytes(b) => Value::Bytes(self.arena.alloc_slice_copy(&b)),
+            ParsedValue::Null => Value::Null,
+        }
+    }
+
+    fn parse_value(&mut self) -> BuildResult<Value<'a>> {
+        let value = match self.next()? {
+            ParseEvent::Value(value) => self.scalar(value),
+            ParseEvent::ListStart => {
+                let mut list = BumpVec::new_in(self.arena);
+                while !matches!(self.parser.peek(), Some(&Ok((ParseEvent::ListEnd, _)))) {
+                    list.push(self.parse_value()?);
+                }
+                self.next()?;
+                Value::List(list.into_bump_slice())
+            },
+            ParseEvent::DictStart => {
+                let mut dict = EntryBuilder::new(self.arena);
+                loop {
+                    match self.parser.next() {
+                        Some(Ok((ParseEvent::Key(key, _), span))) => {
+                            let value = self.parse_value()?;
+                            dict.add(self.parser.options().duplicate_keys, &key, span, value)?;
+                        },
+                        Some(Ok((ParseEvent::DictEnd, _))) => break,
+                        Some(Err(error)) => return Err(error),
+                        ev => unreachable!("ParseEvent {:?} occurred that cannot happen at this time.", ev),
+                    }
+                }
+                Value::Dict(dict.entries.into_bump_slice())
+            },
+            ev => unreachable!("ParseEvent {:?} occurred that cannot happen at this time.", ev),
+        };
+        Ok(value)
+    }
+}
+
+/// The key-value pairs of a node or dict being built, which applies the
+/// `DuplicateKeyPolicy` as they are added
+struct EntryBuilder<'a> {
+    arena: &'a Bump,
+    entries: BumpVec<'a, (&'a str, Value<'a>)>,
+    /// the position of each key in `entries`, and where it first appeared
+    index: HashMap<String, (usize, Span)>,
+    /// keys whose values have already been collected into a list
+    collected: HashSet<String>,
+}
+
+impl<'a> EntryBuilder<'a> {
+    fn new(arena: &'a Bump) -> Self {
+        EntryBuilder {
+            arena,
+            entries: BumpVec::new_in(arena),
+            index: HashMap::new(),
+            collected: HashSet::new(),
+        }
+    }
+
+    fn add(&mut self, policy: DuplicateKeyPolicy, key: &str, span: Span, value: Value<'a>)
+        -> BuildResult<()> {
+
+        let (index, first) = match self.index.get(key) {
+            Some(&(index, ref first)) => (index, first.clone()),
+            None => {
+                self.index.insert(key.to_string(), (self.entries.len(), span));
+                self.entries.push((self.arena.alloc_str(key), value));
+                return Ok(());
+            },
+        };
+
+        let existing = &mut self.entries[index].1;
+        match policy {
+            DuplicateKeyPolicy::LastWins => *existing = value,
+            DuplicateKeyPolicy::FirstWins => {},
+            DuplicateKeyPolicy::Error =>
+                return Err((ParseError::DuplicateKey(key.to_string(), first), span)),
+            DuplicateKeyPolicy::CollectList => {
+                let mut list = BumpVec::new_in(self.arena);
+                match *existing {
+                    Value::List(values) if self.collected.contains(key) =>
+                        list.extend_from_slice(values),
+                    value => {
+                        self.collected.insert(key.to_string());
+                        list.push(value);
+                    },
+                }
+                list.push(value);
+                *existing = Value::List(list.into_bump_slice());
+            },
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::figtree::Figtree;
+    use super::super::parser::ParserOptions;
+    use super::super::position::Position;
+    use std::io::Cursor;
+
+    #[test]
+    fn matches_normal_documents() {
+        let input = "node 1 'two' { s = 'x', i = 1, f = 1.5, b = true, id = !y,
+                            d = { k = [1, [], {}] }, n = null, bytes = b\"\\x01\",
+                            key = 'old', key = 'new'
+                            sub { deeper { } } other { } }
+                     second { }";
+        let arena = Bump::new();
+        let options = ParserOptions::new().node_arguments(true);
+        let parser = Parser::with_options(Lexer::lex(Cursor::new(input)), options.clone());
+        let doc = Document::from_parser(&arena, parser).unwrap();
+        let expected = Figtree::from_string(input).with_options(options).parse().unwrap();
+        assert_eq!(doc.to_document(), expected);
+
+        let node = doc.child("node").unwrap();
+        assert_eq!(node.name(), "node");
+        assert_eq!(node.arguments(), &[Value::Int(1), Value::Str("two")]);
+        assert_eq!(node.get("key"), Some(&Value::Str("new")));
+        assert_eq!(node.get("d").and_then(Value::get_dict).map(|dict| dict[0].0), Some("k"));
+        assert_eq!(node.children().iter().map(Node::name).collect::<Vec<_>>(), vec!["sub", "other"]);
+        assert_eq!(node.properties().len(), 9);
+        assert!(node.child("sub").unwrap().child("deeper").is_some());
+    }
+
+    #[test]
+    fn repeated_nodes() {
+        let arena = Bump::new();
+        let input = "up { h = 'a' } up { h = 'b' }";
+        let error = Document::parse(&arena, Cursor::new(input)).unwrap_err();
+        assert_eq!(error, (ParseError::RepeatedNode("up".to_string()), Span::new(Position::at(0, 15), Position::at(0, 19))));
+
+        let options = ParserOptions::new().repeated_nodes(true);
+        let parser = Parser::with_options(Lexer::lex(Cursor::new(input)), options);
+        let doc = Document::from_parser(&arena, parser).unwrap();
+        let root = Node { name: "", arguments: &[], children: doc.children(), attributes: &[] };
+        let hosts: Vec<_> = root.children_named("up").map(|up| up.get("h").unwrap().get_str()).collect();
+        assert_eq!(hosts, vec![Some("a"), Some("b")]);
+    }
+
+    #[test]
+    fn errors() {
+        let arena = Bump::new();
+        let error = Document::parse(&arena, Cursor::new("node { list = [1 2] }")).unwrap_err();
+        assert_eq!(error.1, Span::new(Position::at(0, 17), Position::at(0, 18)));
+    }
+
+    #[test]
+    fn duplicate_keys() {
+        let input = "node { a = 1, a = 2, d = { k = 'x', k = 'y', k = 'z' } }";
+        let parse = |arena, policy| {
+            let options = ParserOptions::new().duplicate_keys(policy);
+            Document::from_parser(arena, Parser::with_options(Lexer::lex(Cursor::new(input)), options))
+        };
+        let arena = Bump::new();
+        for &policy in &[DuplicateKeyPolicy::LastWins, DuplicateKeyPolicy::FirstWins,
+                         DuplicateKeyPolicy::Error, DuplicateKeyPolicy::CollectList] {
+            let options = ParserOptions::new().duplicate_keys(policy);
+            let expected = Figtree::from_string(input).with_options(options).parse();
+            assert_eq!(parse(&arena, policy).map(|doc| doc.to_document()), expected);
+        }
+        let doc = parse(&arena, DuplicateKeyPolicy::CollectList).unwrap();
+        let node = doc.child("node").unwrap();
+        assert_eq!(node.get("a"), Some(&Value::List(&[Value::Int(1), Value::Int(2)])));
+        assert_eq!(node.properties().len(), 2);
+    }
+}
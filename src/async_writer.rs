@@ -0,0 +1,316 @@
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio::io::AsyncWrite;
+
+use super::parser::ParseEvent;
+use super::writer::{FlushPolicy, Layout, WriteError};
+
+/// Writes a document from a stream of `ParseEvent`s to a tokio `AsyncWrite`, such as a
+/// `TcpStream`.  This is the async counterpart of `Writer`, and lays documents out in
+/// the same way.
+///
+/// Each event is formatted straight away, and collected into a buffer that's written out
+/// by the futures returned from `write_event` and `flush`.  The buffer is written once it
+/// holds more than the writer's capacity, and whenever the output is flushed (see
+/// `FlushPolicy`), so with a capacity of `0` every event is written as it's given.
+///
+/// # Examples
+/// In an async function, each future would be `.await`ed - here they're run with a
+/// simple `block_on` function instead.
+///
+/// ```
+/// # use figtree::{AsyncWriter, Parser};
+/// # use std::future::Future;
+/// # use std::sync::Arc;
+/// # use std::task::{Context, Poll, Wake, Waker};
+/// # struct Noop;
+/// # impl Wake for Noop { fn wake(self: Arc<Self>) {} }
+/// # fn block_on<F: Future>(future: F) -> F::Output {
+/// #     let waker = Waker::from(Arc::new(Noop));
+/// #     let mut future = Box::pin(future);
+/// #     loop {
+/// #         if let Poll::Ready(output) = future.as_mut().poll(&mut Context::from_waker(&waker)) {
+/// #             return output;
+/// #         }
+/// #     }
+/// # }
+/// // the output could be a socket instead
+/// let mut writer = AsyncWriter::new(Vec::new());
+/// for result in Parser::from_bytes(b"a { b = [1, 2] }") {
+///     block_on(writer.write_event(result.unwrap().0)).unwrap();
+/// }
+/// assert_eq!(writer.into_inner(), b"a {\n    b: [1, 2],\n}\n");
+/// ```
+pub struct AsyncWriter<W> {
+    output: W,
+    buffer: Vec<u8>,
+    /// how much of the buffer has been written already
+    written: usize,
+    capacity: usize,
+    flush: FlushPolicy,
+    layout: Layout,
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWriter<W> {
+    /// Construct a writer that writes each event to `output` as soon as it's given
+    pub fn new(output: W) -> Self {
+        Self::with_capacity(0, output)
+    }
+
+    /// Construct a writer that collects up to `capacity` bytes of output before writing
+    /// them to `output`
+    pub fn with_capacity(capacity: usize, output: W) -> Self {
+        AsyncWriter {
+            output,
+            buffer: Vec::with_capacity(capacity),
+            written: 0,
+            capacity,
+            flush: FlushPolicy::End,
+            layout: Layout::default(),
+        }
+    }
+
+    /// Set when the output is flushed
+    pub fn flush_policy(mut self, policy: FlushPolicy) -> Self {
+        self.flush = policy;
+        self
+    }
+
+    /// Get a reference to the output being written to.  Anything in the buffer hasn't
+    /// been written to it yet.
+    pub fn get_ref(&self) -> &W {
+        &self.output
+    }
+
+    /// The output that has been collected, but not yet written
+    pub fn buffer(&self) -> &[u8] {
+        &self.buffer[self.written..]
+    }
+
+    /// Stop writing, and get back the output.  Anything still in the buffer is lost, but
+    /// the buffer is always written out by the end of the document.
+    pub fn into_inner(self) -> W {
+        self.output
+    }
+
+    /// Write the next event of the document, which is finished once the future is.  The
+    /// output is flushed if the writer's `FlushPolicy` says to, and always when
+    /// `FileEnd` is written.
+    ///
+    /// # Failures
+    /// Fails if the event can't come next in the document, in which case nothing is
+    /// written, or if the output can't be written to.
+    pub fn write_event(&mut self, event: ParseEvent) -> WriteEvent<'_, W> {
+        let text = self.layout.text(event);
+        let flush = text.is_ok() && self.layout.should_flush(self.flush);
+        let mut write = self.write_text(text);
+        if flush {
+            write.writer.buffer.extend_from_slice(write.writer.layout.finish_line().as_bytes());
+            write.flush = true;
+        }
+        write
+    }
+
+    /// Write a comment on its own line.  See `Writer::write_comment`.
+    ///
+    /// # Failures
+    /// Fails in the same way as `Writer::write_comment`.
+    pub fn write_comment(&mut self, comment: &str) -> WriteEvent<'_, W> {
+        let text = self.layout.comment(comment);
+        self.write_text(text)
+    }
+
+    /// Write a comment at the end of the line that has just been written.  See
+    /// `Writer::write_trailing_comment`.
+    ///
+    /// # Failures
+    /// Fails in the same way as `Writer::write_trailing_comment`.
+    pub fn write_trailing_comment(&mut self, comment: &str) -> WriteEvent<'_, W> {
+        let text = self.layout.trailing_comment(comment);
+        self.write_text(text)
+    }
+
+    /// Write anything in the buffer to the output, and flush the output
+    ///
+    /// # Failures
+    /// Fails if the output can't be written to.
+    pub fn flush(&mut self) -> WriteEvent<'_, W> {
+        let end = self.layout.finish_line();
+        self.buffer.extend_from_slice(end.as_bytes());
+        WriteEvent { writer: self, error: None, flush: true }
+    }
+
+    fn write_text(&mut self, text: Result<String, WriteError>) -> WriteEvent<'_, W> {
+        match text {
+            Ok(text) => {
+                self.buffer.extend_from_slice(text.as_bytes());
+                WriteEvent { writer: self, error: None, flush: false }
+            },
+            Err(error) => WriteEvent { writer: self, error: Some(error), flush: false },
+        }
+    }
+
+    /// Write out the buffer until it holds no more than `limit` bytes
+    fn poll_write_buffer(&mut self, cx: &mut Context, limit: usize) -> Poll<io::Result<()>> {
+        while self.buffer.len() - self.written > limit {
+            let output = Pin::new(&mut self.output);
+            match output.poll_write(cx, &self.buffer[self.written..]) {
+                Poll::Ready(Ok(0)) => return Poll::Ready(Err(io::ErrorKind::WriteZero.into())),
+                Poll::Ready(Ok(written)) => self.written += written,
+                Poll::Ready(Err(error)) => return Poll::Ready(Err(error)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        if self.written == self.buffer.len() {
+            self.buffer.clear();
+            self.written = 0;
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// The future returned by `AsyncWriter::write_event` and `AsyncWriter::flush`
+#[must_use = "futures do nothing unless they are awaited"]
+pub struct WriteEvent<'a, W: 'a> {
+    writer: &'a mut AsyncWriter<W>,
+    error: Option<WriteError>,
+    flush: bool,
+}
+
+impl<'a, W: AsyncWrite + Unpin> Future for WriteEvent<'a, W> {
+    type Output = Result<(), WriteError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = &mut *self;
+        if let Some(error) = this.error.take() {
+            return Poll::Ready(Err(error));
+        }
+        let limit = if this.flush { 0 } else { this.writer.capacity };
+        match this.writer.poll_write_buffer(cx, limit) {
+            Poll::Ready(Ok(())) => {},
+            Poll::Ready(Err(error)) => return Poll::Ready(Err(error.into())),
+            Poll::Pending => return Poll::Pending,
+        }
+        if !this.flush {
+            return Poll::Ready(Ok(()));
+        }
+        Pin::new(&mut this.writer.output).poll_flush(cx).map_err(WriteError::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::parser::{Parser, ParsedValue};
+    use std::sync::Arc;
+    use std::task::{Wake, Waker};
+
+    struct Noop;
+
+    impl Wake for Noop {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let waker = Waker::from(Arc::new(Noop));
+        let mut context = Context::from_waker(&waker);
+        let mut future = Box::pin(future);
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+                return output;
+            }
+        }
+    }
+
+    /// An output that is only ready every other time it's polled, and then only takes a
+    /// few bytes at a time
+    #[derive(Default)]
+    struct Slow {
+        ready: bool,
+        written: Vec<u8>,
+        flushes: usize,
+    }
+
+    impl Slow {
+        fn wait(&mut self, cx: &mut Context) -> bool {
+            self.ready = !self.ready;
+            if !self.ready {
+                cx.waker().wake_by_ref();
+            }
+            self.ready
+        }
+    }
+
+    impl AsyncWrite for Slow {
+        fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context, bytes: &[u8])
+                      -> Poll<io::Result<usize>> {
+            if !self.wait(cx) {
+                return Poll::Pending;
+            }
+            let length = bytes.len().min(5);
+            self.written.extend_from_slice(&bytes[..length]);
+            Poll::Ready(Ok(length))
+        }
+
+        fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+            if !self.wait(cx) {
+                return Poll::Pending;
+            }
+            self.flushes += 1;
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn events(input: &str) -> Vec<ParseEvent> {
+        Parser::from_bytes(input.as_bytes()).map(|result| result.unwrap().0).collect()
+    }
+
+    #[test]
+    fn writes_asynchronously() {
+        let input = "a { b = 'c', d = [1, { e = 2 }] } f {}";
+        let expected = "a {\n    b: \"c\",\n    d: [1, { e: 2 }],\n}\nf {}\n";
+
+        let mut writer = AsyncWriter::new(Slow::default());
+        for event in events(input) {
+            block_on(writer.write_event(event)).unwrap();
+            assert!(writer.buffer().is_empty());
+        }
+        let output = writer.into_inner();
+        assert_eq!((String::from_utf8(output.written).unwrap(), output.flushes), (expected.into(), 1));
+
+        let mut writer = AsyncWriter::with_capacity(32, Slow::default())
+            .flush_policy(FlushPolicy::EachNode);
+        for event in events(input).into_iter().take(4) {
+            block_on(writer.write_event(event)).unwrap();
+        }
+        assert!(writer.get_ref().written.is_empty());
+        assert_eq!(writer.buffer(), b"a {\n    b: \"c\",");
+        block_on(writer.flush()).unwrap();
+        assert_eq!(writer.get_ref().written, b"a {\n    b: \"c\",\n");
+        for event in events(input).into_iter().skip(4) {
+            block_on(writer.write_event(event)).unwrap();
+            assert!(writer.buffer().len() <= 32);
+        }
+        let output = writer.into_inner();
+        assert_eq!((String::from_utf8(output.written).unwrap(), output.flushes), (expected.into(), 4));
+    }
+
+    #[test]
+    fn rejects_unexpected_events() {
+        let mut writer = AsyncWriter::new(Vec::new());
+        block_on(writer.write_event(ParseEvent::FileStart)).unwrap();
+        let value = ParseEvent::Value(ParsedValue::Null);
+        match block_on(writer.write_event(value.clone())) {
+            Err(WriteError::UnexpectedEvent(event)) => assert_eq!(event, value),
+            _ => panic!("a value can't start a document"),
+        }
+        assert!(writer.into_inner().is_empty());
+    }
+}
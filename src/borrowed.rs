@@ -0,0 +1,169 @@
+use std::borrow::Cow;
+
+use super::lexer::{Lexer, StrSource};
+use super::parser::{Parser, ParserOptions, ParseEvent, ParsedValue, ParseError};
+use super::position::Span;
+
+/// A parse event from a `StrParser`, with names and strings borrowed from the input.
+///
+/// Names, keys, strings and identifiers are `Cow::Borrowed` slices of the input whenever
+/// they appear there exactly as written - that is, unless they contain escapes or line
+/// endings that are normalized, are made up of several concatenated strings, or are
+/// triple-quoted strings.
+#[derive(Debug, PartialEq, Clone)]
+pub enum BorrowedEvent<'a> {
+    /// The start of a node.  The flag is `true` if the name was a string literal.
+    NodeStart(Cow<'a, str>, bool),
+    /// A key.  The flag is `true` if the key was a string literal.
+    Key(Cow<'a, str>, bool),
+    /// A string value
+    Str(Cow<'a, str>),
+    /// An identifier value (`!ident`)
+    Ident(Cow<'a, str>),
+    /// Any other event.  Node arguments (`ParseEvent::Argument`) are also passed on as
+    /// they are, so their strings are never borrowed.
+    Other(ParseEvent),
+}
+
+/// The results produced by a `StrParser`
+pub type BorrowedResult<'a> = Result<(BorrowedEvent<'a>, Span), (ParseError, Span)>;
+
+/// A parser over a string, which produces events that borrow from that string.  Created
+/// by `Parser::from_str`.
+///
+/// The string is lexed in place, and the lexer keeps the byte offsets of each
+/// identifier and string that it finds written out exactly, so that their text can be
+/// sliced out of the input.  The lexer still builds the text of each token as it reads
+/// it, so borrowing saves the events from owning their text, not the lexer from
+/// allocating it.
+///
+/// # Examples
+/// ```
+/// # use figtree::{Parser, BorrowedEvent};
+/// # use std::borrow::Cow;
+/// let input = "node { key = 'value', escaped = 'tab\\t' }";
+/// let strings: Vec<_> = Parser::from_str(input)
+///     .filter_map(|result| match result.unwrap().0 {
+///         BorrowedEvent::Str(string) => Some(string),
+///         _ => None,
+///     })
+///     .collect();
+/// assert!(matches!(strings[0], Cow::Borrowed("value")));
+/// assert!(matches!(strings[1], Cow::Owned(_)));
+/// ```
+pub struct StrParser<'a> {
+    input: &'a str,
+    /// where the offsets of the lexer start from, after any byte order mark
+    base: usize,
+    parser: Parser<StrSource<'a>>,
+}
+
+impl<'a> StrParser<'a> {
+    pub fn new(input: &'a str, options: ParserOptions) -> Self {
+        let mut lexer = Lexer::from_str(input);
+        lexer.set_collect_texts(true);
+        let base = if input.starts_with('\u{FEFF}') { 3 } else { 0 };
+        StrParser { input, base, parser: Parser::with_options(lexer, options) }
+    }
+
+    /// Borrow the text of an event from the input, if the lexer found it there as the
+    /// token that `found` picks out of the event's span
+    fn borrow<F>(&mut self, text: String, span: &Span, found: F) -> Cow<'a, str>
+        where F: Fn(&Span) -> bool {
+
+        let input: &'a str = self.input;
+        match self.parser.lexer_mut().next_text(span) {
+            Some((token, range)) if found(&token) =>
+                Cow::Borrowed(&input[self.base + range.start..self.base + range.end]),
+            _ => Cow::Owned(text),
+        }
+    }
+
+    fn convert(&mut self, event: ParseEvent, span: &Span) -> BorrowedEvent<'a> {
+        match event {
+            ParseEvent::NodeStart(name, quoted) => {
+                let name = self.borrow(name, span, |token| token.start == span.start);
+                BorrowedEvent::NodeStart(name, quoted)
+            },
+            ParseEvent::Key(key, quoted) => {
+                let key = self.borrow(key, span, |token| token.start == span.start);
+                BorrowedEvent::Key(key, quoted)
+            },
+            // concatenated strings never appear verbatim, so the string must be the whole
+            // of the span
+            ParseEvent::Value(ParsedValue::Str(string)) =>
+                BorrowedEvent::Str(self.borrow(string, span, |token| token == span)),
+            // the identifier comes at the end of the span, after the `!`
+            ParseEvent::Value(ParsedValue::Ident(ident)) =>
+                BorrowedEvent::Ident(self.borrow(ident, span, |token| token.end == span.end)),
+            event => BorrowedEvent::Other(event),
+        }
+    }
+}
+
+impl<'a> Iterator for StrParser<'a> {
+    type Item = BorrowedResult<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.parser.next().map(|result| result.map(|(event, span)| {
+            let event = self.convert(event, &span);
+            (event, span)
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn events(input: &str) -> Vec<BorrowedEvent<'_>> {
+        StrParser::new(input, ParserOptions::new()).map(|result| result.unwrap().0).collect()
+    }
+
+    #[test]
+    fn borrows_verbatim_text() {
+        let events = events("\u{FEFF}node {\r\n  \"ключ\": 'значение',\n\tlist = [!ident, ! spaced]\n  'sub' {}\n}");
+        let texts: Vec<_> = events.iter().filter_map(|event| match *event {
+            BorrowedEvent::NodeStart(ref text, _) | BorrowedEvent::Key(ref text, _) |
+            BorrowedEvent::Str(ref text) | BorrowedEvent::Ident(ref text) => Some(text),
+            BorrowedEvent::Other(_) => None,
+        }).collect();
+        assert_eq!(texts, vec!["node", "ключ", "значение", "list", "ident", "spaced", "sub"]);
+        assert!(texts.iter().all(|text| matches!(**text, Cow::Borrowed(_))));
+    }
+
+    #[test]
+    fn borrows_from_long_lines() {
+        // every entry is on the same line, so the parser mustn't look for each of them
+        // from the start of the line
+        let input = format!("node {{ {} }}", (0..32000)
+            .map(|i| format!("k{} = 'v{}'", i, i)).collect::<Vec<_>>().join(", "));
+        let events = events(&input);
+        assert_eq!(events.len(), 64004);
+        assert_eq!(events[64001], BorrowedEvent::Str(Cow::Borrowed("v31999")));
+        assert!(events.iter().all(|event| match *event {
+            BorrowedEvent::NodeStart(ref text, _) | BorrowedEvent::Key(ref text, _) |
+            BorrowedEvent::Str(ref text) => matches!(*text, Cow::Borrowed(_)),
+            _ => true,
+        }));
+    }
+
+    #[test]
+    fn copies_transformed_text() {
+        let events = events("`odd node` { 'a\\'b': 'x' 'y', r'raw' = '''\n  long\n  ''', plain = 'z' }");
+        let texts: Vec<_> = events.iter().filter_map(|event| match *event {
+            BorrowedEvent::NodeStart(ref text, _) | BorrowedEvent::Key(ref text, _) |
+            BorrowedEvent::Str(ref text) => Some((text.to_string(), matches!(*text, Cow::Borrowed(_)))),
+            _ => None,
+        }).collect();
+        assert_eq!(texts, vec![
+            ("odd node".to_string(), true),
+            ("a'b".to_string(), false),
+            ("xy".to_string(), false),
+            ("raw".to_string(), true),
+            ("long".to_string(), false),
+            ("plain".to_string(), true),
+            ("z".to_string(), true),
+        ]);
+    }
+}
@@ -0,0 +1,137 @@
+use super::intern::Symbol;
+use super::types::{Document, Node, Value};
+
+/// Builds a `Node` one entry at a time.  Created by `Node::builder`.
+///
+/// # Examples
+/// ```
+/// # use figtree::types::*;
+/// let server = Node::builder("server")
+///     .prop("host", "localhost")
+///     .prop("port", 80)
+///     .child(Node::builder("tls").prop("enabled", true))
+///     .build();
+/// assert_eq!(server.name(), "server");
+/// assert_eq!(server.get_i64("port"), Ok(80));
+/// assert_eq!(server.child("tls").map(Node::name), Some("tls"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct NodeBuilder {
+    node: Node,
+}
+
+impl NodeBuilder {
+    pub fn new<S>(name: S) -> Self where S: Into<Symbol> {
+        NodeBuilder { node: Node::named(name) }
+    }
+
+    /// Add a positional argument to the end of the node's arguments
+    pub fn arg<V>(mut self, value: V) -> Self where V: Into<Value> {
+        self.node.push_argument(value.into());
+        self
+    }
+
+    /// Add an attribute to the node, replacing any attribute with the same key
+    pub fn prop<K, V>(mut self, key: K, value: V) -> Self
+        where K: Into<Symbol>, V: Into<Value> {
+
+        self.node.insert_attr(key, value.into());
+        self
+    }
+
+    /// Add a subnode to the end of the node.  The subnode keeps its own name.
+    pub fn child<N>(mut self, child: N) -> Self where N: Into<Node> {
+        let child = child.into();
+        self.node.append_node(child.name().to_string(), child);
+        self
+    }
+
+    /// Finish building the node
+    pub fn build(self) -> Node {
+        self.node
+    }
+}
+
+impl From<NodeBuilder> for Node {
+    fn from(builder: NodeBuilder) -> Node {
+        builder.build()
+    }
+}
+
+/// Builds a `Document` one node at a time.  Created by `Document::builder`.
+///
+/// # Examples
+/// ```
+/// # use figtree::types::*;
+/// let doc = Document::builder()
+///     .node(Node::builder("upstream").prop("host", "a"))
+///     .node(Node::builder("upstream").prop("host", "b"))
+///     .build();
+/// assert_eq!(doc.children_named("upstream").count(), 2);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct DocumentBuilder {
+    document: Document,
+}
+
+impl DocumentBuilder {
+    pub fn new() -> Self {
+        DocumentBuilder { document: Document::new() }
+    }
+
+    /// Add a node to the end of the document.  The node keeps its own name.
+    pub fn node<N>(mut self, node: N) -> Self where N: Into<Node> {
+        let node = node.into();
+        self.document.append_node(node.name().to_string(), node);
+        self
+    }
+
+    /// Finish building the document
+    pub fn build(self) -> Document {
+        self.document
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::types::Dict;
+
+    #[test]
+    fn builds_nodes() {
+        let mut env = Dict::new();
+        env.insert("PATH".to_string(), Value::from("/bin"));
+        let node = Node::builder("service")
+            .prop("name", "web")
+            .prop("replicas", 3)
+            .prop("ratio", 0.5)
+            .prop("env", env)
+            .prop("ports", vec![Value::from(80), Value::from(443)])
+            .child(Node::builder("check").prop("path", "/health"))
+            .child(Node::builder("check").prop("path", "/ready").build())
+            .build();
+
+        let mut expected = Node::new();
+        expected.insert_attr("name", Value::new_string("web"));
+        expected.insert_attr("replicas", Value::new_int(3));
+        expected.insert_attr("ratio", Value::new_float(0.5));
+        expected.insert_attr("env", node["env"].clone());
+        expected.insert_attr("ports", Value::List(vec![Value::new_int(80), Value::new_int(443)]));
+        expected.append_node("check", Node::new()).insert_attr("path", Value::new_string("/health"));
+        expected.append_node("check", Node::new()).insert_attr("path", Value::new_string("/ready"));
+        assert_eq!(node, expected);
+        assert_eq!(node.name(), "service");
+        assert_eq!(node.children().map(Node::name).collect::<Vec<_>>(), vec!["check", "check"]);
+    }
+
+    #[test]
+    fn builds_documents() {
+        let doc = Document::builder()
+            .node(Node::builder("a"))
+            .node(Node::builder("b").child(Node::builder("c")))
+            .build();
+        assert_eq!(doc.children().map(Node::name).collect::<Vec<_>>(), vec!["a", "b"]);
+        assert_eq!(doc["b"][0].name(), "c");
+        assert!(DocumentBuilder::new().build().is_empty());
+    }
+}
@@ -0,0 +1,986 @@
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::time::Duration;
+
+use super::datetime::DateTime;
+use super::parser::{ParseEvent, ParsedValue};
+use super::types::{Dict, Document, Node, Value};
+
+/// How deeply nodes, lists and dicts can be nested in CBOR that's being read, so that
+/// data from elsewhere can't overflow the stack
+const MAX_DEPTH: usize = 512;
+
+/// The tag for an RFC 3339 datetime string
+const DATETIME_TAG: u64 = 0;
+/// The tag for an identifier string
+const IDENT_TAG: u64 = 39;
+/// The tag for a duration, as a map of seconds (key `1`) and nanoseconds (key `-9`)
+const DURATION_TAG: u64 = 1002;
+
+/// The byte that ends an array or map of indefinite length
+const BREAK: u8 = 0xff;
+
+/// The error produced when CBOR can't be read as a document, or events can't be written
+/// as CBOR
+#[derive(Debug)]
+pub enum CborError {
+    /// The input couldn't be read from, or the output couldn't be written to
+    Io(io::Error),
+    /// The CBOR is malformed, or isn't the encoding of a document, at this byte offset
+    Invalid(String, usize),
+    /// The event can't come next in the document.  Nothing is written for it.
+    UnexpectedEvent(ParseEvent),
+}
+
+impl fmt::Display for CborError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CborError::Io(ref error) => write!(f, "could not read or write CBOR: {}", error),
+            CborError::Invalid(ref message, offset) => write!(f, "{} (at byte {})", message, offset),
+            CborError::UnexpectedEvent(ref event) => write!(f, "unexpected event {:?}", event),
+        }
+    }
+}
+
+impl Error for CborError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            CborError::Io(ref error) => Some(error),
+            CborError::Invalid(..) | CborError::UnexpectedEvent(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for CborError {
+    fn from(error: io::Error) -> Self {
+        CborError::Io(error)
+    }
+}
+
+/// Write the head of a data item: its major type, and a count or value
+fn write_head<W: Write>(out: &mut W, major: u8, value: u64) -> io::Result<()> {
+    let major = major << 5;
+    if value < 24 {
+        out.write_all(&[major | value as u8])
+    } else if value <= 0xff {
+        out.write_all(&[major | 24, value as u8])
+    } else if value <= 0xffff {
+        out.write_all(&[major | 25])?;
+        out.write_all(&(value as u16).to_be_bytes())
+    } else if value <= 0xffff_ffff {
+        out.write_all(&[major | 26])?;
+        out.write_all(&(value as u32).to_be_bytes())
+    } else {
+        out.write_all(&[major | 27])?;
+        out.write_all(&value.to_be_bytes())
+    }
+}
+
+/// Start an array or map of indefinite length
+fn write_indefinite<W: Write>(out: &mut W, major: u8) -> io::Result<()> {
+    out.write_all(&[(major << 5) | 31])
+}
+
+fn write_int<W: Write>(out: &mut W, int: i64) -> io::Result<()> {
+    if int < 0 {
+        write_head(out, 1, !(int as u64))
+    } else {
+        write_head(out, 0, int as u64)
+    }
+}
+
+fn write_text<W: Write>(out: &mut W, text: &str) -> io::Result<()> {
+    write_head(out, 3, text.len() as u64)?;
+    out.write_all(text.as_bytes())
+}
+
+/// Write a value.  Floats are written in single precision if that loses nothing, and
+/// dicts are written with their keys sorted, so the same value is always written the
+/// same way.
+fn write_value<W: Write>(out: &mut W, value: &Value) -> io::Result<()> {
+    match *value {
+        Value::Str(ref string) => write_text(out, string),
+        Value::Ident(ref ident) => {
+            write_head(out, 6, IDENT_TAG)?;
+            write_text(out, ident)
+        },
+        Value::Int(int) => write_int(out, int),
+        Value::Float(float) => {
+            if float.is_nan() || f64::from(float as f32) == float {
+                out.write_all(&[0xfa])?;
+                out.write_all(&(float as f32).to_bits().to_be_bytes())
+            } else {
+                out.write_all(&[0xfb])?;
+                out.write_all(&float.to_bits().to_be_bytes())
+            }
+        },
+        Value::Bool(boolean) => out.write_all(&[if boolean { 0xf5 } else { 0xf4 }]),
+        Value::Null => out.write_all(&[0xf6]),
+        Value::DateTime(ref datetime) => {
+            write_head(out, 6, DATETIME_TAG)?;
+            write_text(out, &datetime.to_string())
+        },
+        Value::Duration(ref duration) => {
+            write_head(out, 6, DURATION_TAG)?;
+            let nanos = duration.subsec_nanos();
+            write_head(out, 5, if nanos == 0 { 1 } else { 2 })?;
+            write_int(out, 1)?;
+            write_head(out, 0, duration.as_secs())?;
+            if nanos != 0 {
+                write_int(out, -9)?;
+                write_head(out, 0, u64::from(nanos))?;
+            }
+            Ok(())
+        },
+        Value::Bytes(ref bytes) => {
+            write_head(out, 2, bytes.len() as u64)?;
+            out.write_all(bytes)
+        },
+        Value::List(ref items) => {
+            write_head(out, 4, items.len() as u64)?;
+            items.iter().try_for_each(|item| write_value(out, item))
+        },
+        Value::Dict(ref dict) => {
+            let mut entries: Vec<_> = dict.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            write_head(out, 5, entries.len() as u64)?;
+            entries.into_iter().try_for_each(|(key, value)| {
+                write_text(out, key)?;
+                write_value(out, value)
+            })
+        },
+        Value::Node(ref node) => write_value(out, &Value::Dict(node.to_dict())),
+    }
+}
+
+/// Write a node as an array of its name, its arguments, and then its entries: each
+/// attribute as a map with a single key, and each subnode as an array in the same way
+fn write_node<W: Write>(out: &mut W, name: &str, node: &Node) -> io::Result<()> {
+    let entries = node.iter_attrs().count() + node.children().count();
+    write_head(out, 4, 2 + entries as u64)?;
+    write_text(out, name)?;
+    write_head(out, 4, node.arguments().len() as u64)?;
+    for argument in node.arguments() {
+        write_value(out, argument)?;
+    }
+    for (key, value) in node.iter_attrs() {
+        write_head(out, 5, 1)?;
+        write_text(out, key)?;
+        write_value(out, value)?;
+    }
+    for child in node.children() {
+        write_node(out, child.name(), child)?;
+    }
+    Ok(())
+}
+
+/// Convert a half-precision float to a double
+fn half(bits: u16) -> f64 {
+    let exponent = i32::from((bits >> 10) & 0x1f);
+    let mantissa = f64::from(bits & 0x3ff);
+    let value = match exponent {
+        0 => mantissa * 2f64.powi(-24),
+        31 if mantissa == 0.0 => f64::INFINITY,
+        31 => f64::NAN,
+        exponent => (mantissa + 1024.0) * 2f64.powi(exponent - 25),
+    };
+    if bits & 0x8000 != 0 { -value } else { value }
+}
+
+/// Reads the items of the encoding from bytes, keeping track of where it is for errors
+struct Decoder<R> {
+    input: R,
+    offset: usize,
+    depth: usize,
+}
+
+impl<R: BufRead> Decoder<R> {
+    fn new(input: R) -> Self {
+        Decoder { input, offset: 0, depth: 0 }
+    }
+
+    fn error<T, S: Into<String>>(&self, message: S, offset: usize) -> Result<T, CborError> {
+        Err(CborError::Invalid(message.into(), offset))
+    }
+
+    fn read_exact(&mut self, buffer: &mut [u8]) -> Result<(), CborError> {
+        match self.input.read_exact(buffer) {
+            Ok(()) => {
+                self.offset += buffer.len();
+                Ok(())
+            },
+            Err(ref error) if error.kind() == io::ErrorKind::UnexpectedEof => {
+                self.error("unexpected end of input", self.offset)
+            },
+            Err(error) => Err(CborError::Io(error)),
+        }
+    }
+
+    fn byte(&mut self) -> Result<u8, CborError> {
+        let mut buffer = [0];
+        self.read_exact(&mut buffer)?;
+        Ok(buffer[0])
+    }
+
+    fn peek(&mut self) -> Result<Option<u8>, CborError> {
+        Ok(self.input.fill_buf()?.first().cloned())
+    }
+
+    fn uint(&mut self, bytes: usize) -> Result<u64, CborError> {
+        let mut buffer = [0; 8];
+        self.read_exact(&mut buffer[8 - bytes..])?;
+        Ok(u64::from_be_bytes(buffer))
+    }
+
+    /// Read the head of a data item: its major type, its additional information, and its
+    /// argument, which is `None` for strings, arrays and maps of indefinite length
+    fn head(&mut self) -> Result<(u8, u8, Option<u64>), CborError> {
+        let start = self.offset;
+        let byte = self.byte()?;
+        let (major, info) = (byte >> 5, byte & 0x1f);
+        let argument = match info {
+            0..=23 => Some(u64::from(info)),
+            24 => Some(self.uint(1)?),
+            25 => Some(self.uint(2)?),
+            26 => Some(self.uint(4)?),
+            27 => Some(self.uint(8)?),
+            31 if byte == BREAK => return self.error("unexpected break", start),
+            31 if matches!(major, 2..=5) => None,
+            _ => return self.error(format!("invalid initial byte 0x{:02x}", byte), start),
+        };
+        Ok((major, info, argument))
+    }
+
+    /// Check whether an array or map has ended, counting off the item that follows if it
+    /// hasn't.  Arrays and maps of indefinite length end with a break.
+    fn at_end(&mut self, remaining: &mut Option<u64>) -> Result<bool, CborError> {
+        match *remaining {
+            Some(0) => Ok(true),
+            Some(ref mut count) => {
+                *count -= 1;
+                Ok(false)
+            },
+            None if self.peek()? == Some(BREAK) => {
+                self.byte()?;
+                Ok(true)
+            },
+            None => Ok(false),
+        }
+    }
+
+    fn enter(&mut self) -> Result<(), CborError> {
+        if self.depth == MAX_DEPTH {
+            return self.error("the data is nested too deeply", self.offset);
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    /// Read the contents of a byte or text string, which can be split into chunks if it
+    /// has an indefinite length
+    fn string(&mut self, major: u8, length: Option<u64>) -> Result<Vec<u8>, CborError> {
+        let mut bytes = Vec::new();
+        let length = match length {
+            Some(length) => length,
+            None => {
+                loop {
+                    if self.peek()? == Some(BREAK) {
+                        self.byte()?;
+                        return Ok(bytes);
+                    }
+                    let start = self.offset;
+                    match self.head()? {
+                        (chunk, _, Some(length)) if chunk == major => {
+                            bytes.extend(self.string(major, Some(length))?);
+                        },
+                        _ => return self.error("invalid string chunk", start),
+                    }
+                }
+            },
+        };
+        // the bytes aren't allocated up front, so a huge length can't use up memory
+        let read = (&mut self.input).take(length).read_to_end(&mut bytes)?;
+        self.offset += read;
+        if (read as u64) < length {
+            return self.error("unexpected end of input", self.offset);
+        }
+        Ok(bytes)
+    }
+
+    fn text(&mut self) -> Result<String, CborError> {
+        let start = self.offset;
+        match self.head()? {
+            (3, _, length) => String::from_utf8(self.string(3, length)?)
+                .or_else(|_| self.error("invalid UTF-8 in a string", start)),
+            _ => self.error("expected a string", start),
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, CborError> {
+        let start = self.offset;
+        let (major, info, argument) = self.head()?;
+        match major {
+            4 => {
+                self.enter()?;
+                let mut items = Vec::new();
+                let mut remaining = argument;
+                while !self.at_end(&mut remaining)? {
+                    items.push(self.value()?);
+                }
+                self.depth -= 1;
+                Ok(Value::List(items))
+            },
+            5 => {
+                self.enter()?;
+                let mut dict = Dict::new();
+                let mut remaining = argument;
+                while !self.at_end(&mut remaining)? {
+                    let key = self.text()?;
+                    dict.insert(key, self.value()?);
+                }
+                self.depth -= 1;
+                Ok(Value::Dict(dict))
+            },
+            _ => self.scalar(major, info, argument, start),
+        }
+    }
+
+    /// Read a value that isn't an array or a map, after its head
+    fn scalar(&mut self, major: u8, info: u8, argument: Option<u64>, start: usize)
+              -> Result<Value, CborError> {
+        let length = argument;
+        let argument = argument.unwrap_or_default();
+        match major {
+            0 if argument <= i64::MAX as u64 => Ok(Value::Int(argument as i64)),
+            1 if argument <= i64::MAX as u64 => Ok(Value::Int(!argument as i64)),
+            0 | 1 => self.error("integer out of range", start),
+            2 => Ok(Value::Bytes(self.string(2, length)?)),
+            3 => String::from_utf8(self.string(3, length)?)
+                .map(Value::Str)
+                .or_else(|_| self.error("invalid UTF-8 in a string", start)),
+            6 => match argument {
+                DATETIME_TAG => {
+                    let text = self.text()?;
+                    match text.parse::<DateTime>() {
+                        Ok(datetime) => Ok(Value::DateTime(datetime)),
+                        Err(_) => self.error(format!("invalid datetime {:?}", text), start),
+                    }
+                },
+                IDENT_TAG => self.text().map(Value::Ident),
+                DURATION_TAG => self.duration().map(Value::Duration),
+                tag => self.error(format!("unsupported tag {}", tag), start),
+            },
+            7 => match info {
+                20 => Ok(Value::Bool(false)),
+                21 => Ok(Value::Bool(true)),
+                22 | 23 => Ok(Value::Null),
+                25 => Ok(Value::Float(half(argument as u16))),
+                26 => Ok(Value::Float(f64::from(f32::from_bits(argument as u32)))),
+                27 => Ok(Value::Float(f64::from_bits(argument))),
+                _ => self.error("unsupported simple value", start),
+            },
+            _ => unreachable!("the major type has three bits"),
+        }
+    }
+
+    fn duration(&mut self) -> Result<Duration, CborError> {
+        let start = self.offset;
+        let mut remaining = match self.head()? {
+            (5, _, remaining) => remaining,
+            _ => return self.error("expected a duration", start),
+        };
+        let (mut secs, mut nanos) = (0, 0);
+        while !self.at_end(&mut remaining)? {
+            match (self.value()?, self.value()?) {
+                (Value::Int(1), Value::Int(value)) if value >= 0 => secs = value as u64,
+                (Value::Int(-9), Value::Int(value)) if (0..1_000_000_000).contains(&value) => {
+                    nanos = value as u32;
+                },
+                _ => return self.error("invalid duration", start),
+            }
+        }
+        Ok(Duration::new(secs, nanos))
+    }
+
+    /// Read the head of a node and its name, returning how many more items it has
+    fn node_start(&mut self) -> Result<(String, Option<u64>), CborError> {
+        let start = self.offset;
+        let mut remaining = match self.head()? {
+            (4, _, remaining) => remaining,
+            _ => return self.error("expected a node", start),
+        };
+        if self.at_end(&mut remaining)? {
+            return self.error("a node needs a name", start);
+        }
+        self.enter()?;
+        Ok((self.text()?, remaining))
+    }
+
+    /// Read a node's arguments, which can be left out if the node has nothing else
+    fn arguments(&mut self) -> Result<Vec<Value>, CborError> {
+        let start = self.offset;
+        match self.value()? {
+            Value::List(arguments) => Ok(arguments),
+            _ => self.error("expected a list of arguments", start),
+        }
+    }
+
+    /// Check that an entry of a node is an attribute, returning `true`, or a subnode
+    fn is_attr(&mut self) -> Result<bool, CborError> {
+        match self.peek()? {
+            Some(0xa1) => {
+                self.byte()?;
+                Ok(true)
+            },
+            Some(byte) if byte >> 5 == 4 => Ok(false),
+            _ => self.error("expected an attribute or a subnode", self.offset),
+        }
+    }
+
+    fn node(&mut self) -> Result<(String, Node), CborError> {
+        let (name, mut remaining) = self.node_start()?;
+        let mut node = Node::new();
+        if !self.at_end(&mut remaining)? {
+            for argument in self.arguments()? {
+                node.push_argument(argument);
+            }
+            while !self.at_end(&mut remaining)? {
+                if self.is_attr()? {
+                    let key = self.text()?;
+                    node.insert_attr(key, self.value()?);
+                } else {
+                    let (name, child) = self.node()?;
+                    node.append_node(name, child);
+                }
+            }
+        }
+        self.depth -= 1;
+        Ok((name, node))
+    }
+
+    /// Read a value as events, keeping the order of dicts' entries
+    fn value_events(&mut self, queue: &mut VecDeque<ParseEvent>) -> Result<(), CborError> {
+        let start = self.offset;
+        let (major, info, argument) = self.head()?;
+        match major {
+            4 | 5 => {
+                self.enter()?;
+                let (start, end) = if major == 4 {
+                    (ParseEvent::ListStart, ParseEvent::ListEnd)
+                } else {
+                    (ParseEvent::DictStart, ParseEvent::DictEnd)
+                };
+                queue.push_back(start);
+                let mut remaining = argument;
+                while !self.at_end(&mut remaining)? {
+                    if major == 5 {
+                        queue.push_back(ParseEvent::Key(self.text()?, false));
+                    }
+                    self.value_events(queue)?;
+                }
+                queue.push_back(end);
+                self.depth -= 1;
+            },
+            _ => {
+                let value = self.scalar(major, info, argument, start)?;
+                queue.push_back(ParseEvent::Value(parsed_value(value).expect("it's a scalar")));
+            },
+        }
+        Ok(())
+    }
+}
+
+/// Convert a value back into a `ParsedValue`, if it isn't a list or dict
+fn parsed_value(value: Value) -> Option<ParsedValue> {
+    Some(match value {
+        Value::Str(string) => ParsedValue::Str(string),
+        Value::Int(int) => ParsedValue::Int(int),
+        Value::Float(float) => ParsedValue::Float(float),
+        Value::Bool(boolean) => ParsedValue::Bool(boolean),
+        Value::Ident(ident) => ParsedValue::Ident(ident),
+        Value::DateTime(datetime) => ParsedValue::DateTime(datetime),
+        Value::Duration(duration) => ParsedValue::Duration(duration),
+        Value::Bytes(bytes) => ParsedValue::Bytes(bytes),
+        Value::Null => ParsedValue::Null,
+        Value::List(_) | Value::Dict(_) | Value::Node(_) => return None,
+    })
+}
+
+/// What a `CborWriter` is in the middle of
+enum Frame {
+    /// A node, and whether its list of arguments is still open
+    Node { arguments: bool },
+    /// An attribute of a node, waiting for its value
+    Attr,
+    List,
+    /// A dict, and whether it's waiting for the value of a key
+    Dict { key: bool },
+}
+
+/// Writes the CBOR encoding of a document from a stream of `ParseEvent`s, without
+/// building a `Document`, in the same way as `Writer` writes figtree documents.  The
+/// output is read by `Document::from_cbor` and `CborReader` in the same way as the
+/// output of `Document::to_cbor`, but is written with indefinite lengths, as the number
+/// of nodes and entries isn't known until they've been written.
+///
+/// # Examples
+/// ```
+/// # use figtree::{CborReader, CborWriter, Parser};
+/// let input = "server { port = 80, tls { cert = 'a.pem' } }";
+/// let events: Vec<_> = Parser::from_bytes(input.as_bytes()).map(|result| result.unwrap().0)
+///     .collect();
+/// let mut writer = CborWriter::new(Vec::new());
+/// writer.write_events(events.clone()).unwrap();
+/// let cbor = writer.into_inner();
+/// let read: Result<Vec<_>, _> = CborReader::new(&cbor[..]).collect();
+/// assert_eq!(read.unwrap(), events);
+/// ```
+pub struct CborWriter<W> {
+    output: W,
+    stack: Vec<Frame>,
+    started: bool,
+    finished: bool,
+}
+
+impl<W: Write> CborWriter<W> {
+    /// Construct a writer that writes each event to `output` as soon as it's given
+    pub fn new(output: W) -> Self {
+        CborWriter { output, stack: Vec::new(), started: false, finished: false }
+    }
+
+    /// Get a reference to the output
+    pub fn get_ref(&self) -> &W {
+        &self.output
+    }
+
+    /// Take the output back from the writer
+    pub fn into_inner(self) -> W {
+        self.output
+    }
+
+    /// Write the encoding of an event.  Events have to come in the order that a `Parser`
+    /// produces them, starting with `FileStart` and ending with `FileEnd`.
+    ///
+    /// # Failures
+    /// Fails if the event can't come next in the document, or if the output can't be
+    /// written to.
+    pub fn write_event(&mut self, event: ParseEvent) -> Result<(), CborError> {
+        let expected = match event {
+            ParseEvent::FileStart => !self.started,
+            _ if !self.started || self.finished => false,
+            ParseEvent::FileEnd => self.stack.is_empty(),
+            ParseEvent::NodeStart(..) => {
+                matches!(self.stack.last(), None | Some(&Frame::Node { .. }))
+            },
+            ParseEvent::NodeEnd => matches!(self.stack.last(), Some(&Frame::Node { .. })),
+            ParseEvent::Argument(_) => {
+                matches!(self.stack.last(), Some(&Frame::Node { arguments: true }))
+            },
+            ParseEvent::Key(..) => {
+                matches!(self.stack.last(),
+                         Some(&Frame::Node { .. }) | Some(&Frame::Dict { key: false }))
+            },
+            ParseEvent::Value(_) | ParseEvent::ListStart | ParseEvent::DictStart => {
+                matches!(self.stack.last(), Some(&Frame::Attr) | Some(&Frame::List)
+                         | Some(&Frame::Dict { key: true }))
+            },
+            ParseEvent::ListEnd => matches!(self.stack.last(), Some(&Frame::List)),
+            ParseEvent::DictEnd => matches!(self.stack.last(), Some(&Frame::Dict { key: false })),
+        };
+        if !expected {
+            return Err(CborError::UnexpectedEvent(event));
+        }
+
+        match event {
+            ParseEvent::FileStart => {
+                self.started = true;
+                write_indefinite(&mut self.output, 4)?;
+            },
+            ParseEvent::FileEnd => {
+                self.finished = true;
+                self.output.write_all(&[BREAK])?;
+                self.output.flush()?;
+            },
+            ParseEvent::NodeStart(name, _) => {
+                self.close_arguments()?;
+                write_indefinite(&mut self.output, 4)?;
+                write_text(&mut self.output, &name)?;
+                write_indefinite(&mut self.output, 4)?;
+                self.stack.push(Frame::Node { arguments: true });
+            },
+            ParseEvent::NodeEnd => {
+                self.close_arguments()?;
+                self.stack.pop();
+                self.output.write_all(&[BREAK])?;
+            },
+            ParseEvent::Argument(value) => {
+                write_value(&mut self.output, &Value::from_parsed_value(value))?;
+            },
+            ParseEvent::Key(key, _) => {
+                if let Some(&mut Frame::Dict { ref mut key }) = self.stack.last_mut() {
+                    *key = true;
+                } else {
+                    self.close_arguments()?;
+                    write_head(&mut self.output, 5, 1)?;
+                    self.stack.push(Frame::Attr);
+                }
+                write_text(&mut self.output, &key)?;
+            },
+            ParseEvent::Value(value) => {
+                write_value(&mut self.output, &Value::from_parsed_value(value))?;
+                self.end_value();
+            },
+            ParseEvent::ListStart => {
+                write_indefinite(&mut self.output, 4)?;
+                self.stack.push(Frame::List);
+            },
+            ParseEvent::DictStart => {
+                write_indefinite(&mut self.output, 5)?;
+                self.stack.push(Frame::Dict { key: false });
+            },
+            ParseEvent::ListEnd | ParseEvent::DictEnd => {
+                self.stack.pop();
+                self.output.write_all(&[BREAK])?;
+                self.end_value();
+            },
+        }
+        Ok(())
+    }
+
+    /// Write every event from `events`, stopping at the first error
+    pub fn write_events<I>(&mut self, events: I) -> Result<(), CborError>
+        where I: IntoIterator<Item = ParseEvent> {
+
+        events.into_iter().try_for_each(|event| self.write_event(event))
+    }
+
+    /// End the list of arguments of the innermost node, if it's still open
+    fn close_arguments(&mut self) -> io::Result<()> {
+        if let Some(&mut Frame::Node { ref mut arguments }) = self.stack.last_mut() {
+            if *arguments {
+                *arguments = false;
+                self.output.write_all(&[BREAK])?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Finish the attribute or dict entry that a value has just been written for
+    fn end_value(&mut self) {
+        match self.stack.last_mut() {
+            Some(&mut Frame::Attr) => {
+                self.stack.pop();
+            },
+            Some(&mut Frame::Dict { ref mut key }) => *key = false,
+            _ => {},
+        }
+    }
+}
+
+/// Where a `CborReader` is in the document
+enum ReadFrame {
+    /// The list of nodes of the document, and how many are left
+    Document(Option<u64>),
+    /// A node, how many items it has left, and whether its arguments are still to come
+    Node { remaining: Option<u64>, arguments: bool },
+}
+
+/// Reads the CBOR encoding of a document as a stream of `ParseEvent`s, without building
+/// a `Document`.  The events can be passed to a `Writer`, or to anything else that takes
+/// parser events, without having to lex and parse the text of the document again.
+///
+/// Each list or dict is read in one go, but nodes are read a piece at a time, so the
+/// encoding of a large document doesn't have to be read into memory.  Arguments that
+/// are lists or dicts can't be events, so they're an error here, although
+/// `Document::from_cbor` can read them.
+pub struct CborReader<R> {
+    decoder: Decoder<BufReader<R>>,
+    stack: Vec<ReadFrame>,
+    queue: VecDeque<ParseEvent>,
+    finished: bool,
+}
+
+impl<R: Read> CborReader<R> {
+    pub fn new(input: R) -> Self {
+        CborReader {
+            decoder: Decoder::new(BufReader::new(input)),
+            stack: Vec::new(),
+            queue: VecDeque::new(),
+            finished: false,
+        }
+    }
+
+    /// Read until there's at least one event in the queue
+    fn read(&mut self) -> Result<(), CborError> {
+        let CborReader { ref mut decoder, ref mut stack, ref mut queue, .. } = *self;
+        if stack.is_empty() {
+            let start = decoder.offset;
+            return match decoder.head()? {
+                (4, _, remaining) => {
+                    stack.push(ReadFrame::Document(remaining));
+                    queue.push_back(ParseEvent::FileStart);
+                    Ok(())
+                },
+                _ => decoder.error("expected a list of nodes", start),
+            };
+        }
+        while queue.is_empty() {
+            match stack.last_mut() {
+                Some(&mut ReadFrame::Document(ref mut remaining)) => {
+                    if decoder.at_end(remaining)? {
+                        if decoder.peek()?.is_some() {
+                            let offset = decoder.offset;
+                            return decoder.error("unexpected data after the document", offset);
+                        }
+                        queue.push_back(ParseEvent::FileEnd);
+                        self.finished = true;
+                        return Ok(());
+                    }
+                    let (name, remaining) = decoder.node_start()?;
+                    queue.push_back(ParseEvent::NodeStart(name, false));
+                    stack.push(ReadFrame::Node { remaining, arguments: true });
+                },
+                Some(&mut ReadFrame::Node { ref mut remaining, ref mut arguments }) => {
+                    if decoder.at_end(remaining)? {
+                        stack.pop();
+                        decoder.depth -= 1;
+                        queue.push_back(ParseEvent::NodeEnd);
+                    } else if *arguments {
+                        *arguments = false;
+                        let start = decoder.offset;
+                        for argument in decoder.arguments()? {
+                            match parsed_value(argument) {
+                                Some(argument) => queue.push_back(ParseEvent::Argument(argument)),
+                                None => {
+                                    let message = "an argument can't be a list or dict";
+                                    return decoder.error(message, start);
+                                },
+                            }
+                        }
+                    } else if decoder.is_attr()? {
+                        queue.push_back(ParseEvent::Key(decoder.text()?, false));
+                        decoder.value_events(queue)?;
+                    } else {
+                        let (name, remaining) = decoder.node_start()?;
+                        queue.push_back(ParseEvent::NodeStart(name, false));
+                        stack.push(ReadFrame::Node { remaining, arguments: true });
+                    }
+                },
+                None => unreachable!("the document is still open"),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Iterator for CborReader<R> {
+    type Item = Result<ParseEvent, CborError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.queue.is_empty() && !self.finished {
+            if let Err(error) = self.read() {
+                // nothing more is read after an error
+                self.finished = true;
+                self.queue.clear();
+                return Some(Err(error));
+            }
+        }
+        self.queue.pop_front().map(Ok)
+    }
+}
+
+impl Document {
+    /// Encode the document as CBOR (RFC 8949), for caching parsed documents or sending
+    /// them elsewhere without having to parse them again.  Every value keeps its type:
+    /// datetimes, identifiers and durations are written with the standard tags for them
+    /// (0, 39 and 1002).  Comments and spans aren't kept.
+    ///
+    /// The document is an array of nodes, and each node is an array of its name, an array
+    /// of its arguments, and its entries in order: each attribute as a map with a single
+    /// key, and each subnode as another node.
+    ///
+    /// # Examples
+    /// ```
+    /// # use figtree::Figtree;
+    /// # use figtree::types::Document;
+    /// let doc = Figtree::from_string("a { b = 1 }").parse().unwrap();
+    /// let cbor = doc.to_cbor();
+    /// assert_eq!(cbor, [0x81, 0x83, 0x61, b'a', 0x80, 0xa1, 0x61, b'b', 0x01]);
+    /// assert_eq!(Document::from_cbor(&cbor).unwrap(), doc);
+    /// ```
+    pub fn to_cbor(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        let _ = write_head(&mut out, 4, self.children().count() as u64);
+        for node in self.children() {
+            let _ = write_node(&mut out, node.name(), node);
+        }
+        out
+    }
+
+    /// Decode a document from the CBOR written by `to_cbor` or a `CborWriter`
+    ///
+    /// # Failures
+    /// Fails if the CBOR is malformed, isn't the encoding of a document, has tags other
+    /// than those that `to_cbor` writes, or is nested more than 512 levels deep.
+    pub fn from_cbor(bytes: &[u8]) -> Result<Document, CborError> {
+        let mut decoder = Decoder::new(bytes);
+        let mut document = Document::new();
+        let mut remaining = match decoder.head()? {
+            (4, _, remaining) => remaining,
+            _ => return decoder.error("expected a list of nodes", 0),
+        };
+        while !decoder.at_end(&mut remaining)? {
+            let (name, node) = decoder.node()?;
+            document.append_node(name, node);
+        }
+        if decoder.peek()?.is_some() {
+            return decoder.error("unexpected data after the document", decoder.offset);
+        }
+        Ok(document)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::figtree::Figtree;
+    use super::super::lexer::Lexer;
+    use super::super::parser::{Parser, ParserOptions};
+    use super::*;
+
+    fn parse(input: &str) -> Document {
+        let options = ParserOptions::new().node_arguments(true).repeated_nodes(true);
+        Figtree::from_string(input).with_options(options).parse().unwrap()
+    }
+
+    fn events(input: &str) -> Vec<ParseEvent> {
+        let options = ParserOptions::new().node_arguments(true).repeated_nodes(true);
+        let lexer = Lexer::lex(io::Cursor::new(input.to_string()));
+        Parser::with_options(lexer, options).map(|result| result.unwrap().0).collect()
+    }
+
+    fn value(value: Value) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_value(&mut out, &value).unwrap();
+        out
+    }
+
+    fn error(bytes: &[u8]) -> String {
+        Document::from_cbor(bytes).unwrap_err().to_string()
+    }
+
+    const INPUT: &str = "
+        server 'web' 8080 {
+            name = 'a',
+            ratio = 0.1,
+            half = 1.5,
+            big = -9223372036854775807,
+            timeout = 250ms,
+            started = 2024-01-01T12:00:00Z,
+            mode = !fast,
+            key = b\"\\x00\\xff\",
+            ports = [80, [443], null, true],
+            env = { home = '/root', path = ['/bin'] }
+            upstream { host = 'a' }
+            upstream 'b' { weight = 2 }
+        }
+        empty {}
+    ";
+
+    #[test]
+    fn encodes_values() {
+        assert_eq!(value(Value::Int(10)), [0x0a]);
+        assert_eq!(value(Value::Int(500)), [0x19, 0x01, 0xf4]);
+        assert_eq!(value(Value::Int(-1)), [0x20]);
+        assert_eq!(value(Value::Int(-1000)), [0x39, 0x03, 0xe7]);
+        assert_eq!(value(Value::Float(1.5)), [0xfa, 0x3f, 0xc0, 0x00, 0x00]);
+        assert_eq!(value(Value::Float(0.1)), [0xfb, 0x3f, 0xb9, 0x99, 0x99, 0x99, 0x99, 0x99, 0x9a]);
+        assert_eq!(value(Value::Bool(true)), [0xf5]);
+        assert_eq!(value(Value::Null), [0xf6]);
+        assert_eq!(value(Value::from("ab")), [0x62, b'a', b'b']);
+        assert_eq!(value(Value::new_ident("a")), [0xd8, 0x27, 0x61, b'a']);
+        assert_eq!(value(Value::new_bytes(vec![1, 2])), [0x42, 0x01, 0x02]);
+        assert_eq!(value(Value::new_duration(Duration::new(2, 5))),
+                   [0xd9, 0x03, 0xea, 0xa2, 0x01, 0x02, 0x28, 0x05]);
+        assert_eq!(value(Value::List(vec![Value::Int(1)])), [0x81, 0x01]);
+    }
+
+    #[test]
+    fn decodes_values() {
+        let decode = |bytes: &[u8]| Decoder::new(bytes).value().unwrap();
+        assert_eq!(decode(&[0xf9, 0x3e, 0x00]), Value::Float(1.5));
+        assert_eq!(decode(&[0xf9, 0x7c, 0x00]), Value::Float(f64::INFINITY));
+        assert_eq!(decode(&[0xf9, 0x00, 0x01]), Value::Float(5.960464477539063e-8));
+        assert_eq!(decode(&[0xf7]), Value::Null);
+        assert_eq!(decode(&[0x7f, 0x61, b'a', 0x62, b'b', b'c', 0xff]), Value::from("abc"));
+        assert_eq!(decode(&[0x9f, 0x01, 0x9f, 0xff, 0xff]),
+                   Value::List(vec![Value::Int(1), Value::List(vec![])]));
+        assert_eq!(decode(&[0x3b, 0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]),
+                   Value::Int(i64::MIN));
+    }
+
+    #[test]
+    fn round_trips_documents() {
+        let doc = parse(INPUT);
+        assert_eq!(Document::from_cbor(&doc.to_cbor()).unwrap(), doc);
+        assert_eq!(Document::from_cbor(&Document::new().to_cbor()).unwrap(), Document::new());
+
+        let mut writer = CborWriter::new(Vec::new());
+        writer.write_events(events(INPUT)).unwrap();
+        let cbor = writer.into_inner();
+        assert_eq!(Document::from_cbor(&cbor).unwrap(), doc);
+        let read: Result<Vec<_>, _> = CborReader::new(&cbor[..]).collect();
+        assert_eq!(read.unwrap(), events(INPUT));
+        let read: Result<Vec<_>, _> = CborReader::new(&doc.to_cbor()[..]).collect();
+        assert_eq!(read.unwrap().len(), events(INPUT).len());
+    }
+
+    #[test]
+    fn rejects_invalid_data() {
+        assert_eq!(error(&[]), "unexpected end of input (at byte 0)");
+        assert_eq!(error(&[0x01]), "expected a list of nodes (at byte 0)");
+        assert_eq!(error(&[0x81, 0x01]), "expected a node (at byte 1)");
+        assert_eq!(error(&[0x81, 0x80]), "a node needs a name (at byte 1)");
+        assert_eq!(error(&[0x81, 0x82, 0x61, b'a', 0x01]),
+                   "expected a list of arguments (at byte 4)");
+        assert_eq!(error(&[0x81, 0x83, 0x61, b'a', 0x80, 0x01]),
+                   "expected an attribute or a subnode (at byte 5)");
+        assert_eq!(error(&[0x81, 0x83, 0x61, b'a', 0x80, 0xa1, 0x61, b'b', 0xc1, 0x00]),
+                   "unsupported tag 1 (at byte 8)");
+        assert_eq!(error(&[0x81, 0x82, 0x61, 0xff, 0x80]), "invalid UTF-8 in a string (at byte 2)");
+        assert_eq!(error(&[0x81, 0x82, 0x7a, 0xff, 0xff, 0xff, 0xff]),
+                   "unexpected end of input (at byte 7)");
+        assert_eq!(error(&[0x80, 0x00]), "unexpected data after the document (at byte 1)");
+        assert_eq!(error(&[0x9f, 0x1c]), "invalid initial byte 0x1c (at byte 1)");
+
+        let mut nested = vec![0x81, 0x83, 0x61, b'a', 0x80, 0xa1, 0x61, b'b'];
+        nested.extend(vec![0x81; 1000]);
+        nested.push(0x01);
+        assert_eq!(error(&nested), "the data is nested too deeply (at byte 520)");
+
+        let list_argument = [0x81, 0x82, 0x61, b'a', 0x81, 0x80];
+        let read: Vec<_> = CborReader::new(&list_argument[..]).collect();
+        assert_eq!(read.last().unwrap().as_ref().unwrap_err().to_string(),
+                   "an argument can't be a list or dict (at byte 4)");
+        assert!(Document::from_cbor(&list_argument).is_ok());
+    }
+
+    #[test]
+    fn rejects_unexpected_events() {
+        let mut writer = CborWriter::new(Vec::new());
+        assert!(writer.write_event(ParseEvent::NodeEnd).is_err());
+        writer.write_event(ParseEvent::FileStart).unwrap();
+        writer.write_event(ParseEvent::NodeStart("a".into(), false)).unwrap();
+        writer.write_event(ParseEvent::Key("b".into(), false)).unwrap();
+        assert!(writer.write_event(ParseEvent::NodeEnd).is_err());
+        writer.write_event(ParseEvent::Value(ParsedValue::Int(1))).unwrap();
+        assert!(writer.write_event(ParseEvent::Argument(ParsedValue::Int(1))).is_err());
+        assert!(writer.write_event(ParseEvent::FileEnd).is_err());
+        writer.write_event(ParseEvent::NodeEnd).unwrap();
+        writer.write_event(ParseEvent::FileEnd).unwrap();
+        assert!(writer.write_event(ParseEvent::FileStart).is_err());
+        assert_eq!(writer.into_inner(),
+                   [0x9f, 0x9f, 0x61, b'a', 0x9f, 0xff, 0xa1, 0x61, b'b', 0x01, 0xff, 0xff]);
+    }
+}
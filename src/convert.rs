@@ -0,0 +1,286 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+use super::datetime::DateTime;
+use super::types::Value;
+
+/// The error produced when a `Value` can't be converted into a Rust type with `TryFrom`.
+///
+/// # Examples
+/// ```
+/// # use figtree::types::*;
+/// # use figtree::ConvertError;
+/// # use std::convert::TryFrom;
+/// # use std::io::Cursor;
+/// struct Server {
+///     host: String,
+///     ports: Vec<u16>,
+/// }
+///
+/// fn server(node: &Node) -> Result<Server, ConvertError> {
+///     let host = String::try_from(&node["host"])?;
+///     Ok(Server { host, ports: Vec::try_from(&node["ports"])? })
+/// }
+///
+/// let input = "a { host = 'x', ports = [80] } b { host = 'y', ports = [-1] }";
+/// let doc = Document::parse(Cursor::new(input)).unwrap();
+/// assert_eq!(server(&doc["a"]).unwrap().ports, vec![80]);
+/// assert_eq!(server(&doc["b"]).err(), Some(ConvertError::OutOfRange(-1)));
+/// ```
+#[derive(Debug, PartialEq, Clone)]
+pub enum ConvertError {
+    /// The value is a different type.  Contains the names of the expected and found types
+    /// (`"string"`, `"integer"`, etc.).
+    WrongType(&'static str, &'static str),
+    /// The value is an integer that doesn't fit in the type being converted to
+    OutOfRange(i64),
+}
+
+impl fmt::Display for ConvertError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConvertError::WrongType(expected, found) =>
+                write!(f, "expected a value of type {}, found {}", expected, found),
+            ConvertError::OutOfRange(int) => write!(f, "integer {} is out of range", int),
+        }
+    }
+}
+
+impl Error for ConvertError {}
+
+/// Get the contents of a value with `convert`, or a `WrongType` error
+fn expect<'a, T, F>(value: &'a Value, expected: &'static str, convert: F) -> Result<T, ConvertError>
+    where F: FnOnce(&'a Value) -> Option<T> {
+
+    convert(value).ok_or_else(|| ConvertError::WrongType(expected, value.type_name()))
+}
+
+impl<'a> TryFrom<&'a Value> for &'a str {
+    type Error = ConvertError;
+
+    fn try_from(value: &'a Value) -> Result<Self, ConvertError> {
+        expect(value, "string", Value::get_str)
+    }
+}
+
+impl<'a> TryFrom<&'a Value> for String {
+    type Error = ConvertError;
+
+    fn try_from(value: &'a Value) -> Result<Self, ConvertError> {
+        expect(value, "string", Value::get_str).map(str::to_string)
+    }
+}
+
+/// Integers are checked to fit in the type they are converted to
+macro_rules! try_from_int {
+    ($($int:ty),*) => {$(
+        impl<'a> TryFrom<&'a Value> for $int {
+            type Error = ConvertError;
+
+            fn try_from(value: &'a Value) -> Result<Self, ConvertError> {
+                let int = expect(value, "integer", Value::get_int)?;
+                <$int>::try_from(int).map_err(|_| ConvertError::OutOfRange(int))
+            }
+        }
+    )*};
+}
+
+try_from_int!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+/// Integers are converted to floats, as with `Node::get_f64`
+impl<'a> TryFrom<&'a Value> for f64 {
+    type Error = ConvertError;
+
+    fn try_from(value: &'a Value) -> Result<Self, ConvertError> {
+        expect(value, "float", |value| match *value {
+            Value::Int(int) => Some(int as f64),
+            ref value => value.get_float(),
+        })
+    }
+}
+
+impl<'a> TryFrom<&'a Value> for bool {
+    type Error = ConvertError;
+
+    fn try_from(value: &'a Value) -> Result<Self, ConvertError> {
+        expect(value, "boolean", Value::get_bool)
+    }
+}
+
+impl<'a> TryFrom<&'a Value> for DateTime {
+    type Error = ConvertError;
+
+    fn try_from(value: &'a Value) -> Result<Self, ConvertError> {
+        expect(value, "date and time", Value::get_datetime)
+    }
+}
+
+impl<'a> TryFrom<&'a Value> for Duration {
+    type Error = ConvertError;
+
+    fn try_from(value: &'a Value) -> Result<Self, ConvertError> {
+        expect(value, "duration", Value::get_duration)
+    }
+}
+
+impl<'a> TryFrom<&'a Value> for &'a [u8] {
+    type Error = ConvertError;
+
+    fn try_from(value: &'a Value) -> Result<Self, ConvertError> {
+        expect(value, "bytes", Value::get_bytes)
+    }
+}
+
+/// Each item of a list is converted, failing with the first item that can't be
+impl<'a, T> TryFrom<&'a Value> for Vec<T> where T: TryFrom<&'a Value, Error = ConvertError> {
+    type Error = ConvertError;
+
+    fn try_from(value: &'a Value) -> Result<Self, ConvertError> {
+        expect(value, "list", Value::get_list)?.iter().map(T::try_from).collect()
+    }
+}
+
+/// Each value of a dict is converted, failing with the first value that can't be
+impl<'a, T> TryFrom<&'a Value> for HashMap<String, T>
+    where T: TryFrom<&'a Value, Error = ConvertError> {
+
+    type Error = ConvertError;
+
+    fn try_from(value: &'a Value) -> Result<Self, ConvertError> {
+        expect(value, "dict", Value::get_dict)?.iter()
+            .map(|(key, value)| T::try_from(value).map(|value| (key.clone(), value)))
+            .collect()
+    }
+}
+
+/// `null` is converted to `None`, and anything else is converted to the contained type
+impl<'a, T> TryFrom<&'a Value> for Option<T> where T: TryFrom<&'a Value, Error = ConvertError> {
+    type Error = ConvertError;
+
+    fn try_from(value: &'a Value) -> Result<Self, ConvertError> {
+        if value.is_null() { Ok(None) } else { T::try_from(value).map(Some) }
+    }
+}
+
+/// Types that can be converted into values.  This is the inverse of the `TryFrom`
+/// conversions, and is used to write the fields of a struct as attributes and arguments
+/// (see `ToNode`).
+///
+/// # Examples
+/// ```
+/// # use figtree::ToValue;
+/// # use figtree::types::Value;
+/// let ports: Vec<u16> = vec![80, 443];
+/// assert_eq!(ports.to_value(), Value::List(vec![Value::new_int(80), Value::new_int(443)]));
+/// assert_eq!(None::<bool>.to_value(), Value::Null);
+/// ```
+pub trait ToValue {
+    /// Convert to a value
+    fn to_value(&self) -> Value;
+}
+
+macro_rules! to_value {
+    ($($type:ty => |$value:ident| $convert:expr),* $(,)*) => {$(
+        impl ToValue for $type {
+            fn to_value(&self) -> Value {
+                let $value = self;
+                $convert
+            }
+        }
+    )*};
+}
+
+to_value! {
+    str => |value| Value::Str(value.to_string()),
+    String => |value| Value::Str(value.clone()),
+    i8 => |value| Value::Int((*value).into()),
+    i16 => |value| Value::Int((*value).into()),
+    i32 => |value| Value::Int((*value).into()),
+    i64 => |value| Value::Int(*value),
+    isize => |value| Value::Int(*value as i64),
+    u8 => |value| Value::Int((*value).into()),
+    u16 => |value| Value::Int((*value).into()),
+    u32 => |value| Value::Int((*value).into()),
+    // integers too big for a value are clamped to the biggest one there is
+    u64 => |value| Value::Int(i64::try_from(*value).unwrap_or(i64::MAX)),
+    usize => |value| Value::Int(i64::try_from(*value).unwrap_or(i64::MAX)),
+    f64 => |value| Value::Float(*value),
+    bool => |value| Value::Bool(*value),
+    DateTime => |value| Value::DateTime(*value),
+    Duration => |value| Value::Duration(*value),
+    Value => |value| value.clone(),
+}
+
+impl<T: ToValue + ?Sized> ToValue for &T {
+    fn to_value(&self) -> Value {
+        (**self).to_value()
+    }
+}
+
+impl<T: ToValue> ToValue for Vec<T> {
+    fn to_value(&self) -> Value {
+        Value::List(self.iter().map(T::to_value).collect())
+    }
+}
+
+impl<T: ToValue> ToValue for HashMap<String, T> {
+    fn to_value(&self) -> Value {
+        Value::Dict(self.iter().map(|(key, value)| (key.clone(), value.to_value())).collect())
+    }
+}
+
+/// `None` is converted to `null`
+impl<T: ToValue> ToValue for Option<T> {
+    fn to_value(&self) -> Value {
+        self.as_ref().map_or(Value::Null, T::to_value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::types::Document;
+    use std::io::Cursor;
+
+    #[test]
+    fn converts_values() {
+        let doc = Document::parse(Cursor::new("a {
+            name = 'web', port = 8080, ratio = 1, up = true, wait = 5s,
+            ports = [80, 443], env = { a = 1, b = 2 }, opt = null, hosts = ['a', 1]
+        }")).unwrap();
+        let node = &doc["a"];
+        assert_eq!(<&str>::try_from(&node["name"]), Ok("web"));
+        assert_eq!(String::try_from(&node["name"]), Ok("web".to_string()));
+        assert_eq!(u16::try_from(&node["port"]), Ok(8080));
+        assert_eq!(u8::try_from(&node["port"]), Err(ConvertError::OutOfRange(8080)));
+        assert_eq!(f64::try_from(&node["ratio"]), Ok(1.0));
+        assert_eq!(bool::try_from(&node["up"]), Ok(true));
+        assert_eq!(Duration::try_from(&node["wait"]), Ok(Duration::from_secs(5)));
+        assert_eq!(Vec::<u32>::try_from(&node["ports"]), Ok(vec![80, 443]));
+        assert_eq!(HashMap::<String, i64>::try_from(&node["env"]).unwrap()["b"], 2);
+        assert_eq!(Option::<i64>::try_from(&node["opt"]), Ok(None));
+        assert_eq!(Option::<i64>::try_from(&node["port"]), Ok(Some(8080)));
+
+        assert_eq!(Vec::<String>::try_from(&node["hosts"]),
+            Err(ConvertError::WrongType("string", "integer")));
+        assert_eq!(i64::try_from(&node["name"]).unwrap_err().to_string(),
+            "expected a value of type integer, found string");
+        assert_eq!(u64::try_from(&Value::new_int(-1)).unwrap_err().to_string(),
+            "integer -1 is out of range");
+    }
+
+    #[test]
+    fn converts_to_values() {
+        assert_eq!("web".to_string().to_value(), Value::new_string("web"));
+        assert_eq!(8080u16.to_value(), Value::new_int(8080));
+        assert_eq!(u64::MAX.to_value(), Value::new_int(i64::MAX));
+        assert_eq!(vec![Some(1.5), None].to_value(),
+            Value::List(vec![Value::new_float(1.5), Value::Null]));
+        let mut env = HashMap::new();
+        env.insert("a".to_string(), vec![true]);
+        assert_eq!(HashMap::<String, Vec<bool>>::try_from(&env.to_value()), Ok(env));
+    }
+}
@@ -0,0 +1,225 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// Represents an RFC 3339 style date and time (`2024-01-01T12:00:00Z`)
+///
+/// The UTC offset is optional - if it is missing, the date and time are local to
+/// wherever the document is being read.
+///
+/// # Examples
+/// ```
+/// # use figtree::DateTime;
+/// let datetime: DateTime = "2024-01-01T12:30:00.5+01:00".parse().unwrap();
+/// assert_eq!(datetime.year, 2024);
+/// assert_eq!(datetime.minute, 30);
+/// assert_eq!(datetime.nanosecond, 500_000_000);
+/// assert_eq!(datetime.offset, Some(60));
+/// assert_eq!(datetime.to_string(), "2024-01-01T12:30:00.5+01:00");
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct DateTime {
+    /// full year (`0` to `9999`)
+    pub year: u16,
+    /// month of the year (`1` to `12`)
+    pub month: u8,
+    /// day of the month (`1` to `31`)
+    pub day: u8,
+    /// hour of the day (`0` to `23`)
+    pub hour: u8,
+    /// minute of the hour (`0` to `59`)
+    pub minute: u8,
+    /// second of the minute (`0` to `60`, to allow for leap seconds)
+    pub second: u8,
+    /// fraction of the second, in nanoseconds
+    pub nanosecond: u32,
+    /// offset from UTC in minutes, or `None` for a local date and time
+    pub offset: Option<i16>,
+}
+
+/// The error returned when a string is not a valid date and time
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct DateTimeError;
+
+impl fmt::Display for DateTimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid date and time")
+    }
+}
+
+impl Error for DateTimeError {}
+
+fn is_leap_year(year: u16) -> bool {
+    year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400))
+}
+
+fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        2 => if is_leap_year(year) { 29 } else { 28 },
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+impl DateTime {
+    /// Test whether every field is within its allowed range
+    pub fn is_valid(&self) -> bool {
+        self.year <= 9999 &&
+            (1..=12).contains(&self.month) &&
+            self.day >= 1 && self.day <= days_in_month(self.year, self.month) &&
+            self.hour <= 23 && self.minute <= 59 && self.second <= 60 &&
+            self.nanosecond < 1_000_000_000 &&
+            self.offset.is_none_or(|offset| offset.abs() < 24 * 60)
+    }
+}
+
+/// A cursor over the characters of a date and time being parsed
+struct Cursor<'a> {
+    chars: ::std::iter::Peekable<::std::str::Chars<'a>>,
+}
+
+impl<'a> Cursor<'a> {
+    fn expect(&mut self, expected: &[char]) -> Result<char, DateTimeError> {
+        match self.chars.next() {
+            Some(ch) if expected.contains(&ch) => Ok(ch),
+            _ => Err(DateTimeError),
+        }
+    }
+
+    fn digits(&mut self, count: usize) -> Result<u32, DateTimeError> {
+        let mut value = 0;
+        for _ in 0..count {
+            match self.chars.next().and_then(|ch| ch.to_digit(10)) {
+                Some(digit) => value = value * 10 + digit,
+                None => return Err(DateTimeError),
+            }
+        }
+        Ok(value)
+    }
+}
+
+impl FromStr for DateTime {
+    type Err = DateTimeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut cursor = Cursor { chars: s.chars().peekable() };
+
+        let year = cursor.digits(4)? as u16;
+        cursor.expect(&['-'])?;
+        let month = cursor.digits(2)? as u8;
+        cursor.expect(&['-'])?;
+        let day = cursor.digits(2)? as u8;
+        cursor.expect(&['T', 't'])?;
+        let hour = cursor.digits(2)? as u8;
+        cursor.expect(&[':'])?;
+        let minute = cursor.digits(2)? as u8;
+        cursor.expect(&[':'])?;
+        let second = cursor.digits(2)? as u8;
+
+        let mut nanosecond = 0;
+        if cursor.chars.peek() == Some(&'.') {
+            cursor.chars.next();
+            let mut digits = 0;
+            while let Some(digit) = cursor.chars.peek().and_then(|ch| ch.to_digit(10)) {
+                cursor.chars.next();
+                // anything more precise than a nanosecond is ignored
+                if digits < 9 {
+                    nanosecond = nanosecond * 10 + digit;
+                    digits += 1;
+                }
+            }
+            if digits == 0 {
+                return Err(DateTimeError);
+            }
+            nanosecond *= 10u32.pow(9 - digits);
+        }
+
+        let offset = match cursor.chars.next() {
+            None => None,
+            Some('Z') | Some('z') => Some(0),
+            Some(sign @ '+') | Some(sign @ '-') => {
+                let hours = cursor.digits(2)? as i16;
+                cursor.expect(&[':'])?;
+                let minutes = cursor.digits(2)? as i16;
+                if minutes > 59 {
+                    return Err(DateTimeError);
+                }
+                let offset = hours * 60 + minutes;
+                Some(if sign == '-' { -offset } else { offset })
+            },
+            Some(_) => return Err(DateTimeError),
+        };
+
+        if cursor.chars.next().is_some() {
+            return Err(DateTimeError);
+        }
+
+        let datetime = DateTime { year, month, day, hour, minute, second, nanosecond, offset };
+        if datetime.is_valid() {
+            Ok(datetime)
+        } else {
+            Err(DateTimeError)
+        }
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second)?;
+
+        if self.nanosecond > 0 {
+            let fraction = format!("{:09}", self.nanosecond);
+            write!(f, ".{}", fraction.trim_end_matches('0'))?;
+        }
+
+        match self.offset {
+            None => Ok(()),
+            Some(0) => write!(f, "Z"),
+            Some(offset) => {
+                let sign = if offset < 0 { '-' } else { '+' };
+                write!(f, "{}{:02}:{:02}", sign, offset.abs() / 60, offset.abs() % 60)
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_datetimes() {
+        assert_eq!("2024-01-01T12:00:00Z".parse(), Ok(DateTime {
+            year: 2024, month: 1, day: 1, hour: 12, minute: 0, second: 0,
+            nanosecond: 0, offset: Some(0),
+        }));
+        assert_eq!("1999-12-31t23:59:60.123456789123-05:30".parse(), Ok(DateTime {
+            year: 1999, month: 12, day: 31, hour: 23, minute: 59, second: 60,
+            nanosecond: 123_456_789, offset: Some(-330),
+        }));
+        assert_eq!("2000-02-29T00:00:00".parse::<DateTime>().map(|dt| dt.offset), Ok(None));
+    }
+
+    #[test]
+    fn invalid_datetimes() {
+        for input in &[
+            "", "2024", "2024-01-01", "2024-1-01T00:00:00", "2024-01-01 00:00:00",
+            "2024-13-01T00:00:00", "2023-02-29T00:00:00", "2024-01-01T24:00:00",
+            "2024-01-01T00:60:00", "2024-01-01T00:00:00.", "2024-01-01T00:00:00+1:00",
+            "2024-01-01T00:00:00+24:00", "2024-01-01T00:00:00Zx",
+        ] {
+            assert_eq!(input.parse::<DateTime>(), Err(DateTimeError), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn display_datetimes() {
+        for input in &[
+            "2024-01-01T12:00:00Z", "2024-01-01T12:00:00", "2024-01-01T12:00:00.25+01:00",
+            "0001-06-30T01:02:03.000000001-00:45",
+        ] {
+            assert_eq!(&input.parse::<DateTime>().unwrap().to_string(), input);
+        }
+    }
+}
@@ -0,0 +1,1263 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::io::Read;
+use std::marker::PhantomData;
+
+use serde::de::{self, DeserializeSeed, IgnoredAny, IntoDeserializer, Visitor};
+use serde::de::value::{BorrowedStrDeserializer, MapDeserializer, SeqDeserializer};
+
+use super::borrowed::{BorrowedEvent, BorrowedResult, StrParser};
+use super::parser::{Parser, ParseError, ParseEvent, ParsedValue, ParserOptions};
+use super::position::Span;
+use super::spanned;
+
+/// The key that the arguments of a node are given under when the node is deserialized as
+/// a map or struct.  Use `#[serde(rename = "$arguments")]` to get hold of them.
+pub const ARGUMENTS_KEY: &str = "$arguments";
+
+/// The error produced when a document can't be deserialized
+#[derive(Debug, PartialEq)]
+pub enum DeserializeError {
+    /// The document isn't valid, and couldn't be parsed
+    Parse(ParseError, Span),
+    /// The document doesn't have the shape of the type being deserialized, such as a
+    /// missing field or a string where an integer was expected
+    Message(String),
+    /// The same as `Message`, but found inside a node, dict or list.  The path is made up
+    /// of the keys and node names leading to where the problem was found, such as
+    /// `server.pool.size`, with the indices of list items and runs of nodes in brackets
+    /// (`upstream[1].host`).  The span is that of the last event read before it was found.
+    At {
+        /// what the problem is
+        message: String,
+        /// where in the document the problem is
+        path: String,
+        /// the span of the document that the problem was found in
+        span: Span,
+    },
+}
+
+impl DeserializeError {
+    /// The span of the document that the error happened in, if it's known
+    pub fn span(&self) -> Option<&Span> {
+        match *self {
+            DeserializeError::Parse(_, ref span) | DeserializeError::At { ref span, .. } =>
+                Some(span),
+            DeserializeError::Message(_) => None,
+        }
+    }
+
+    /// The path to the part of the document that the error happened in, if it's known
+    pub fn path(&self) -> Option<&str> {
+        match *self {
+            DeserializeError::At { ref path, .. } => Some(path),
+            _ => None,
+        }
+    }
+
+    /// Add the key or index of the entry that the error happened in to the start of its
+    /// path, with the span of the last event if it didn't already have one
+    fn within(self, segment: &str, de: &Deserializer) -> Self {
+        match self {
+            DeserializeError::Message(message) =>
+                DeserializeError::At { message, path: segment.into(), span: de.last.clone() },
+            DeserializeError::At { message, path, span } => {
+                let separator = if path.starts_with('[') { "" } else { "." };
+                let path = format!("{}{}{}", segment, separator, path);
+                DeserializeError::At { message, path, span }
+            },
+            error => error,
+        }
+    }
+}
+
+impl fmt::Display for DeserializeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DeserializeError::Parse(ref error, ref span) =>
+                write!(f, "{} at {}", error, span.start),
+            DeserializeError::Message(ref message) => f.write_str(message),
+            DeserializeError::At { ref message, ref path, ref span } =>
+                write!(f, "{} at {} (line {}, column {})", message, path, span.start.line + 1,
+                       span.start.pos + 1),
+        }
+    }
+}
+
+impl Error for DeserializeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            DeserializeError::Parse(ref error, _) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl de::Error for DeserializeError {
+    fn custom<T: fmt::Display>(message: T) -> Self {
+        DeserializeError::Message(message.to_string())
+    }
+}
+
+type Result<T> = ::std::result::Result<T, DeserializeError>;
+
+/// Deserialize an instance of `T` from a document in a string.  Names, keys and strings
+/// are borrowed from the string wherever they appear in it exactly as written (see
+/// `BorrowedEvent`), so they can be deserialized into `&str`s and `#[serde(borrow)]`
+/// `Cow`s that point into it.  This isn't free of allocations: the lexer still builds
+/// the text of each token as it reads it (see `StrParser`).  Deserializing a string with
+/// escapes in it as a `&str` fails with an "expected a borrowed string" error.
+///
+/// # Examples
+/// ```
+/// # extern crate serde;
+/// # use serde::Deserialize;
+/// #[derive(Deserialize)]
+/// struct Config {
+///     server: Server,
+/// }
+///
+/// #[derive(Deserialize)]
+/// struct Server {
+///     host: String,
+///     ports: Vec<u16>,
+///     tls: Option<Tls>,
+/// }
+///
+/// #[derive(Deserialize)]
+/// struct Tls {
+///     cert: String,
+/// }
+///
+/// let input = "server { host = 'example.com', ports = [80, 443], tls { cert = 'a.pem' } }";
+/// let config: Config = figtree::from_str(input).unwrap();
+/// assert_eq!(config.server.ports, vec![80, 443]);
+/// assert_eq!(config.server.tls.unwrap().cert, "a.pem");
+/// ```
+///
+/// # Failures
+/// Fails if the document can't be parsed, or doesn't have the shape of `T`.
+pub fn from_str<'de, T: de::Deserialize<'de>>(input: &'de str) -> Result<T> {
+    let mut deserializer = Deserializer::from_str(input);
+    let value = T::deserialize(&mut deserializer)?;
+    deserializer.end()?;
+    Ok(value)
+}
+
+/// Deserialize an instance of `T` from a document read from `reader`
+///
+/// # Failures
+/// Fails if the document can't be parsed, or doesn't have the shape of `T`.
+pub fn from_reader<R: Read + 'static, T: de::DeserializeOwned>(reader: R) -> Result<T> {
+    let mut deserializer = Deserializer::from_parser(Parser::from_reader(reader));
+    let value = T::deserialize(&mut deserializer)?;
+    deserializer.end()?;
+    Ok(value)
+}
+
+/// Deserializes Rust types from the events of a document, without building a `Document`
+/// first.
+///
+/// A document is deserialized as a map from the names of its top-level nodes to the
+/// nodes, and each node as a map from its keys and the names of its subnodes to their
+/// values.  This means that nodes can be deserialized into structs and maps, and
+/// attributes into any type that fits their value.  In addition:
+///
+/// - A sequence can be deserialized from a run of nodes with the same name, one after
+///   the other, such as `upstream {} upstream {}`.
+/// - Any arguments of a node are given under the key `ARGUMENTS_KEY`, or can be
+///   deserialized as a tuple.  A node with a single argument and no entries, such as
+///   `port 8080 {}`, can also be deserialized as that argument.
+/// - Enums can be deserialized from strings and identifiers (for unit variants), and
+///   from dicts and nodes with a single entry, whose key is the variant.
+/// - Durations are deserialized as maps of `secs` and `nanos`, as
+///   `std::time::Duration` expects, and datetimes as strings.
+/// - A `Spanned` value is deserialized along with the span of the value or node it's
+///   read from.  Spans aren't kept for arguments, which can't be `Spanned`.
+///
+/// Types that don't say what they expect, such as `#[serde(flatten)]` fields and untagged
+/// enums, see runs of nodes as sequences, and nodes with nothing but a single argument as
+/// that argument.  A run of one node is still a map to them, though.
+pub struct Deserializer<'de> {
+    events: Box<dyn Iterator<Item = BorrowedResult<'de>> + 'de>,
+    /// events that have been read ahead, with their spans
+    peeked: VecDeque<(BorrowedEvent<'de>, Span)>,
+    /// the span of the last event that was read
+    last: Span,
+}
+
+impl<'de> Deserializer<'de> {
+    /// Construct a deserializer over the events of a parser, such as a `StrParser`
+    pub fn new<I>(events: I) -> Self where I: IntoIterator<Item = BorrowedResult<'de>> + 'de {
+        Deserializer {
+            events: Box::new(events.into_iter()),
+            peeked: VecDeque::new(),
+            last: Span::new(Default::default(), Default::default()),
+        }
+    }
+
+    /// Construct a deserializer over a document in a string, which borrows from the
+    /// string in the same way as `from_str`
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(input: &'de str) -> Self {
+        Self::from_str_with_options(input, ParserOptions::new())
+    }
+
+    /// Construct a deserializer over a string, parsing it with `options`
+    pub fn from_str_with_options(input: &'de str, options: ParserOptions) -> Self {
+        Self::new(StrParser::new(input, options))
+    }
+
+    /// Construct a deserializer over the events of a `Parser`
+    pub fn from_parser(parser: Parser) -> Self {
+        Self::new(parser.map(|result| result.map(|(event, span)| (owned(event), span))))
+    }
+
+    /// Check that the whole of the document has been deserialized
+    ///
+    /// # Failures
+    /// Fails if there are any events left over, or there is a parse error in the rest
+    /// of the document.
+    pub fn end(&mut self) -> Result<()> {
+        match self.peek()? {
+            None => Ok(()),
+            Some(event) => Err(unexpected(event)),
+        }
+    }
+
+    fn peek(&mut self) -> Result<Option<&BorrowedEvent<'de>>> {
+        self.peek_nth(0)
+    }
+
+    /// Look at the event `n` events ahead, without reading any of them
+    fn peek_nth(&mut self, n: usize) -> Result<Option<&BorrowedEvent<'de>>> {
+        while self.peeked.len() <= n {
+            match self.events.next() {
+                Some(Ok(event)) => self.peeked.push_back(event),
+                Some(Err((error, span))) => return Err(DeserializeError::Parse(error, span)),
+                None => return Ok(None),
+            }
+        }
+        Ok(self.peeked.get(n).map(|(event, _)| event))
+    }
+
+    /// The span of the next event, or of the last one if there are none left
+    fn peek_span(&mut self) -> Result<Span> {
+        self.peek()?;
+        Ok(self.peeked.front().map_or_else(|| self.last.clone(), |(_, span)| span.clone()))
+    }
+
+    fn next(&mut self) -> Result<BorrowedEvent<'de>> {
+        self.peek()?;
+        match self.peeked.pop_front() {
+            Some((event, span)) => {
+                self.last = span;
+                Ok(event)
+            },
+            None => Err(de::Error::custom("unexpected end of the document")),
+        }
+    }
+
+    /// Deserialize a `Spanned` value, which starts at `start` and ends wherever the last
+    /// event it's made from does
+    fn spanned<V: Visitor<'de>>(&mut self, start: Span, kind: Spanning<'de>, visitor: V)
+                                -> Result<V::Value> {
+        visitor.visit_map(SpannedEntries { de: self, start, kind: Some(kind), index: 0 })
+    }
+
+    fn expect(&mut self, expected: ParseEvent) -> Result<()> {
+        match self.next()? {
+            BorrowedEvent::Other(ref event) if *event == expected => Ok(()),
+            event => Err(unexpected(&event)),
+        }
+    }
+
+    /// Take the arguments of a node that has just started
+    fn arguments(&mut self) -> Result<Vec<ParsedValue>> {
+        let mut arguments = Vec::new();
+        while let Some(&BorrowedEvent::Other(ParseEvent::Argument(_))) = self.peek()? {
+            if let BorrowedEvent::Other(ParseEvent::Argument(value)) = self.next()? {
+                arguments.push(value);
+            }
+        }
+        Ok(arguments)
+    }
+}
+
+/// Convert an event with owned strings into one that can be deserialized
+fn owned<'de>(event: ParseEvent) -> BorrowedEvent<'de> {
+    match event {
+        ParseEvent::NodeStart(name, quoted) => BorrowedEvent::NodeStart(Cow::Owned(name), quoted),
+        ParseEvent::Key(key, quoted) => BorrowedEvent::Key(Cow::Owned(key), quoted),
+        ParseEvent::Value(ParsedValue::Str(string)) => BorrowedEvent::Str(Cow::Owned(string)),
+        ParseEvent::Value(ParsedValue::Ident(ident)) => BorrowedEvent::Ident(Cow::Owned(ident)),
+        event => BorrowedEvent::Other(event),
+    }
+}
+
+fn unexpected(event: &BorrowedEvent) -> DeserializeError {
+    de::Error::custom(format_args!("unexpected {:?} in the document", event))
+}
+
+/// The whole document
+impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
+    type Error = DeserializeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.expect(ParseEvent::FileStart)?;
+        visitor.visit_map(Entries::new(self, Vec::new()))
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V>(self, _: &'static str, visitor: V) -> Result<V::Value>
+            where V: Visitor<'de> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V>(self, _: &'static str, _: &'static [&'static str], visitor: V)
+                           -> Result<V::Value> where V: Visitor<'de> {
+        self.expect(ParseEvent::FileStart)?;
+        visitor.visit_enum(Entries::new(self, Vec::new()))
+    }
+
+    fn deserialize_struct<V>(self, name: &'static str, _: &'static [&'static str], visitor: V)
+                             -> Result<V::Value> where V: Visitor<'de> {
+        if name == spanned::NAME {
+            let start = self.peek_span()?;
+            return self.spanned(start, Spanning::Document, visitor);
+        }
+        self.deserialize_any(visitor)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        unit unit_struct seq tuple tuple_struct map identifier ignored_any
+    }
+}
+
+/// What a `Spanned` value is read from
+enum Spanning<'de> {
+    Document,
+    Node(Cow<'de, str>, bool),
+    Value,
+}
+
+/// The fields of a `Spanned` value.  The value comes before the end of the span, which
+/// isn't known until the value has been read.
+struct SpannedEntries<'a, 'de: 'a> {
+    de: &'a mut Deserializer<'de>,
+    start: Span,
+    kind: Option<Spanning<'de>>,
+    index: usize,
+}
+
+impl<'a, 'de> de::MapAccess<'de> for SpannedEntries<'a, 'de> {
+    type Error = DeserializeError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>> {
+        const KEYS: &[&str] = &[spanned::START_LINE, spanned::START_POS, spanned::VALUE,
+                                spanned::END_LINE, spanned::END_POS];
+        match KEYS.get(self.index) {
+            Some(key) => seed.deserialize(BorrowedStrDeserializer::new(key)).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value> {
+        self.index += 1;
+        let position = match self.index {
+            1 => self.start.start.line,
+            2 => self.start.start.pos,
+            3 => return match self.kind.take() {
+                Some(Spanning::Document) => seed.deserialize(&mut *self.de),
+                Some(Spanning::Node(name, run)) =>
+                    seed.deserialize(NodeDeserializer { de: &mut *self.de, name, run }),
+                Some(Spanning::Value) => seed.deserialize(ValueDeserializer { de: &mut *self.de }),
+                None => Err(de::Error::custom("a span's value was read twice")),
+            },
+            4 => self.de.last.end.line,
+            _ => self.de.last.end.pos,
+        };
+        seed.deserialize(IntoDeserializer::<DeserializeError>::into_deserializer(position))
+    }
+}
+
+/// What the value of the entry that has just been read is
+enum Pending<'de> {
+    None,
+    Attr(Cow<'de, str>),
+    Node(Cow<'de, str>),
+    Arguments(Vec<ParsedValue>),
+}
+
+/// The entries of a document, node or dict, read up to the event that ends them
+struct Entries<'a, 'de: 'a> {
+    de: &'a mut Deserializer<'de>,
+    arguments: Vec<ParsedValue>,
+    pending: Pending<'de>,
+}
+
+impl<'a, 'de> Entries<'a, 'de> {
+    fn new(de: &'a mut Deserializer<'de>, arguments: Vec<ParsedValue>) -> Self {
+        Entries { de, arguments, pending: Pending::None }
+    }
+
+    /// Check that nothing comes after the entry of an enum
+    fn end_variant<T>(mut self, value: T) -> Result<T> {
+        match de::MapAccess::next_key_seed(&mut self, PhantomData::<IgnoredAny>)? {
+            None => Ok(value),
+            Some(_) => Err(de::Error::custom("expected a single entry for an enum")),
+        }
+    }
+}
+
+impl<'a, 'de> de::MapAccess<'de> for Entries<'a, 'de> {
+    type Error = DeserializeError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>> {
+        if !self.arguments.is_empty() {
+            self.pending = Pending::Arguments(self.arguments.split_off(0));
+            return seed.deserialize(ARGUMENTS_KEY.into_deserializer()).map(Some)
+                .map_err(|error: DeserializeError| error.within(ARGUMENTS_KEY, self.de));
+        }
+        match self.de.next()? {
+            BorrowedEvent::Key(name, _) => {
+                self.pending = Pending::Attr(name.clone());
+                seed.deserialize(Scalar::Str(name.clone())).map(Some)
+                    .map_err(|error| error.within(&name, self.de))
+            },
+            BorrowedEvent::NodeStart(name, _) => {
+                self.pending = Pending::Node(name.clone());
+                seed.deserialize(Scalar::Str(name.clone())).map(Some)
+                    .map_err(|error| error.within(&name, self.de))
+            },
+            BorrowedEvent::Other(ParseEvent::NodeEnd)
+            | BorrowedEvent::Other(ParseEvent::DictEnd)
+            | BorrowedEvent::Other(ParseEvent::FileEnd) => Ok(None),
+            event => Err(unexpected(&event)),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value> {
+        match ::std::mem::replace(&mut self.pending, Pending::None) {
+            Pending::Attr(name) => seed.deserialize(ValueDeserializer { de: &mut *self.de })
+                .map_err(|error| error.within(&name, self.de)),
+            Pending::Node(name) => {
+                let node = NodeDeserializer { de: &mut *self.de, name: name.clone(), run: true };
+                seed.deserialize(node).map_err(|error| error.within(&name, self.de))
+            },
+            Pending::Arguments(arguments) => {
+                let arguments = arguments.into_iter().map(Scalar::Value);
+                seed.deserialize(SeqDeserializer::new(arguments))
+                    .map_err(|error| error.within(ARGUMENTS_KEY, self.de))
+            },
+            Pending::None => Err(de::Error::custom("a value was read before its key")),
+        }
+    }
+}
+
+impl<'a, 'de> de::EnumAccess<'de> for Entries<'a, 'de> {
+    type Error = DeserializeError;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(mut self, seed: V) -> Result<(V::Value, Self)> {
+        match de::MapAccess::next_key_seed(&mut self, seed)? {
+            Some(variant) => Ok((variant, self)),
+            None => Err(de::Error::custom("expected an entry for an enum")),
+        }
+    }
+}
+
+impl<'a, 'de> de::VariantAccess<'de> for Entries<'a, 'de> {
+    type Error = DeserializeError;
+
+    fn unit_variant(mut self) -> Result<()> {
+        de::MapAccess::next_value::<IgnoredAny>(&mut self)?;
+        self.end_variant(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(mut self, seed: T) -> Result<T::Value> {
+        let value = de::MapAccess::next_value_seed(&mut self, seed)?;
+        self.end_variant(value)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(mut self, len: usize, visitor: V) -> Result<V::Value> {
+        let value = de::MapAccess::next_value_seed(&mut self, TupleSeed(len, visitor))?;
+        self.end_variant(value)
+    }
+
+    fn struct_variant<V>(mut self, fields: &'static [&'static str], visitor: V)
+                         -> Result<V::Value> where V: Visitor<'de> {
+        let value = de::MapAccess::next_value_seed(&mut self, StructSeed(fields, visitor))?;
+        self.end_variant(value)
+    }
+}
+
+/// Deserializes a tuple with a visitor, for tuple variants
+struct TupleSeed<V>(usize, V);
+
+impl<'de, V: Visitor<'de>> DeserializeSeed<'de> for TupleSeed<V> {
+    type Value = V::Value;
+
+    fn deserialize<D: de::Deserializer<'de>>(self, deserializer: D)
+                                             -> ::std::result::Result<V::Value, D::Error> {
+        deserializer.deserialize_tuple(self.0, self.1)
+    }
+}
+
+/// Deserializes a struct with a visitor, for struct variants
+struct StructSeed<V>(&'static [&'static str], V);
+
+impl<'de, V: Visitor<'de>> DeserializeSeed<'de> for StructSeed<V> {
+    type Value = V::Value;
+
+    fn deserialize<D: de::Deserializer<'de>>(self, deserializer: D)
+                                             -> ::std::result::Result<V::Value, D::Error> {
+        deserializer.deserialize_struct("", self.0, self.1)
+    }
+}
+
+/// A node whose `NodeStart` has just been read
+struct NodeDeserializer<'a, 'de: 'a> {
+    de: &'a mut Deserializer<'de>,
+    name: Cow<'de, str>,
+    /// whether the node can be the start of a run of nodes with the same name
+    run: bool,
+}
+
+impl<'a, 'de> NodeDeserializer<'a, 'de> {
+    fn deserialize_entries<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let arguments = self.de.arguments()?;
+        visitor.visit_map(Entries::new(self.de, arguments))
+    }
+
+    /// Check if the node has a single argument and nothing else
+    fn has_only_argument(&mut self) -> Result<bool> {
+        Ok(matches!(self.de.peek()?, Some(&BorrowedEvent::Other(ParseEvent::Argument(_))))
+            && matches!(self.de.peek_nth(1)?, Some(&BorrowedEvent::Other(ParseEvent::NodeEnd))))
+    }
+
+    /// Check if the node is followed by another with the same name, which means reading
+    /// ahead past the end of the node
+    fn is_repeated(&mut self) -> Result<bool> {
+        let (mut n, mut depth) = (0, 0);
+        loop {
+            match self.de.peek_nth(n)? {
+                Some(&BorrowedEvent::NodeStart(..)) => depth += 1,
+                Some(&BorrowedEvent::Other(ParseEvent::NodeEnd)) if depth == 0 => break,
+                Some(&BorrowedEvent::Other(ParseEvent::NodeEnd)) => depth -= 1,
+                Some(_) => {},
+                None => return Ok(false),
+            }
+            n += 1;
+        }
+        Ok(matches!(self.de.peek_nth(n + 1)?,
+                    Some(BorrowedEvent::NodeStart(name, _)) if *name == self.name))
+    }
+
+    /// Take the node's only argument, if it has one and nothing else
+    fn only_argument(&mut self) -> Result<Option<Scalar<'de>>> {
+        if let Some(&BorrowedEvent::Other(ParseEvent::Argument(_))) = self.de.peek()? {
+            let mut arguments = self.de.arguments()?;
+            if arguments.len() == 1 {
+                if let Some(&BorrowedEvent::Other(ParseEvent::NodeEnd)) = self.de.peek()? {
+                    self.de.next()?;
+                    return Ok(arguments.pop().map(Scalar::Value));
+                }
+            }
+            return Err(de::Error::custom(format_args!(
+                "expected node `{}` to have a single argument and no entries", self.name)));
+        }
+        Ok(None)
+    }
+}
+
+macro_rules! forward_to_argument {
+    ($($method:ident)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(mut self, visitor: V) -> Result<V::Value> {
+                match self.only_argument()? {
+                    Some(argument) => de::Deserializer::$method(argument, visitor),
+                    None => self.deserialize_entries(visitor),
+                }
+            }
+        )*
+    };
+}
+
+impl<'a, 'de> de::Deserializer<'de> for NodeDeserializer<'a, 'de> {
+    type Error = DeserializeError;
+
+    /// When the type being deserialized doesn't say what it expects, such as with
+    /// `#[serde(flatten)]` and untagged enums, a run of nodes with the same name is
+    /// deserialized as a sequence, and a node with nothing but a single argument as that
+    /// argument.  Anything else is a map.
+    fn deserialize_any<V: Visitor<'de>>(mut self, visitor: V) -> Result<V::Value> {
+        if self.run && self.is_repeated()? {
+            return self.deserialize_seq(visitor);
+        }
+        if self.has_only_argument()? {
+            if let Some(argument) = self.only_argument()? {
+                return argument.deserialize_any(visitor);
+            }
+        }
+        self.deserialize_entries(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_entries(visitor)
+    }
+
+    fn deserialize_struct<V>(self, name: &'static str, _: &'static [&'static str], visitor: V)
+                             -> Result<V::Value> where V: Visitor<'de> {
+        if name == spanned::NAME {
+            let start = self.de.last.clone();
+            return self.de.spanned(start, Spanning::Node(self.name, self.run), visitor);
+        }
+        self.deserialize_entries(visitor)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_entries(visitor)
+    }
+
+    forward_to_argument! {
+        deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64
+        deserialize_i128 deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64
+        deserialize_u128 deserialize_f32 deserialize_f64 deserialize_char deserialize_str
+        deserialize_string deserialize_bytes deserialize_byte_buf deserialize_identifier
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        if let Some(&BorrowedEvent::Other(ParseEvent::NodeEnd)) = self.de.peek()? {
+            self.de.next()?;
+            return visitor.visit_unit();
+        }
+        self.deserialize_entries(visitor)
+    }
+
+    fn deserialize_unit_struct<V>(self, _: &'static str, visitor: V) -> Result<V::Value>
+            where V: Visitor<'de> {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V>(self, _: &'static str, visitor: V) -> Result<V::Value>
+            where V: Visitor<'de> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    /// A run of nodes with the same name
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_seq(Repeated { de: self.de, name: self.name, index: 0 })
+    }
+
+    /// The arguments of the node, which mustn't have any entries
+    fn deserialize_tuple<V: Visitor<'de>>(self, _: usize, visitor: V) -> Result<V::Value> {
+        let arguments = self.de.arguments()?;
+        let arguments = arguments.into_iter().map(Scalar::Value);
+        let value = visitor.visit_seq(SeqDeserializer::new(arguments))?;
+        self.de.expect(ParseEvent::NodeEnd)?;
+        Ok(value)
+    }
+
+    fn deserialize_tuple_struct<V>(self, _: &'static str, len: usize, visitor: V)
+                                   -> Result<V::Value> where V: Visitor<'de> {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_enum<V>(mut self, name: &'static str, variants: &'static [&'static str],
+                           visitor: V) -> Result<V::Value> where V: Visitor<'de> {
+        match self.only_argument()? {
+            Some(argument) => de::Deserializer::deserialize_enum(argument, name, variants, visitor),
+            None => visitor.visit_enum(Entries::new(self.de, Vec::new())),
+        }
+    }
+
+}
+
+/// A run of nodes with the same name, where the first has already started
+struct Repeated<'a, 'de: 'a> {
+    de: &'a mut Deserializer<'de>,
+    name: Cow<'de, str>,
+    /// the index of the next node in the run
+    index: usize,
+}
+
+impl<'a, 'de> de::SeqAccess<'de> for Repeated<'a, 'de> {
+    type Error = DeserializeError;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
+            where T: DeserializeSeed<'de> {
+        if self.index > 0 {
+            match self.de.peek()? {
+                Some(BorrowedEvent::NodeStart(name, _)) if *name == self.name => {},
+                _ => return Ok(None),
+            }
+            self.de.next()?;
+        }
+        self.index += 1;
+        let node = NodeDeserializer { de: &mut *self.de, name: self.name.clone(), run: false };
+        seed.deserialize(node).map(Some)
+            .map_err(|error| error.within(&format!("[{}]", self.index - 1), self.de))
+    }
+}
+
+/// A value in an attribute, list or dict, which is read from the events
+struct ValueDeserializer<'a, 'de: 'a> {
+    de: &'a mut Deserializer<'de>,
+}
+
+impl<'a, 'de> de::Deserializer<'de> for ValueDeserializer<'a, 'de> {
+    type Error = DeserializeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.de.next()? {
+            BorrowedEvent::Other(ParseEvent::ListStart) =>
+                visitor.visit_seq(Items { de: self.de, index: 0 }),
+            BorrowedEvent::Other(ParseEvent::DictStart) =>
+                visitor.visit_map(Entries::new(self.de, Vec::new())),
+            event => scalar(event)?.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.de.next()? {
+            BorrowedEvent::Other(ParseEvent::ListStart) =>
+                visitor.visit_seq(Items { de: self.de, index: 0 }),
+            BorrowedEvent::Other(ParseEvent::DictStart) =>
+                visitor.visit_map(Entries::new(self.de, Vec::new())),
+            event => scalar(event)?.deserialize_seq(visitor),
+        }
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_byte_buf(visitor)
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.de.next()? {
+            BorrowedEvent::Other(ParseEvent::ListStart) =>
+                visitor.visit_seq(Items { de: self.de, index: 0 }),
+            BorrowedEvent::Other(ParseEvent::DictStart) =>
+                visitor.visit_map(Entries::new(self.de, Vec::new())),
+            event => scalar(event)?.deserialize_byte_buf(visitor),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        if let Some(&BorrowedEvent::Other(ParseEvent::Value(ParsedValue::Null))) = self.de.peek()? {
+            self.de.next()?;
+            return visitor.visit_none();
+        }
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V>(self, _: &'static str, visitor: V) -> Result<V::Value>
+            where V: Visitor<'de> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V>(self, name: &'static str, variants: &'static [&'static str],
+                           visitor: V) -> Result<V::Value> where V: Visitor<'de> {
+        match self.de.next()? {
+            BorrowedEvent::Other(ParseEvent::DictStart) =>
+                visitor.visit_enum(Entries::new(self.de, Vec::new())),
+            event => scalar(event)?.deserialize_enum(name, variants, visitor),
+        }
+    }
+
+    fn deserialize_struct<V>(self, name: &'static str, _: &'static [&'static str], visitor: V)
+                             -> Result<V::Value> where V: Visitor<'de> {
+        if name == spanned::NAME {
+            let start = self.de.peek_span()?;
+            return self.de.spanned(start, Spanning::Value, visitor);
+        }
+        self.deserialize_any(visitor)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        unit unit_struct tuple tuple_struct map identifier ignored_any
+    }
+}
+
+/// The items of a list, up to its `ListEnd`
+struct Items<'a, 'de: 'a> {
+    de: &'a mut Deserializer<'de>,
+    index: usize,
+}
+
+impl<'a, 'de> de::SeqAccess<'de> for Items<'a, 'de> {
+    type Error = DeserializeError;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
+            where T: DeserializeSeed<'de> {
+        if let Some(&BorrowedEvent::Other(ParseEvent::ListEnd)) = self.de.peek()? {
+            self.de.next()?;
+            return Ok(None);
+        }
+        self.index += 1;
+        seed.deserialize(ValueDeserializer { de: &mut *self.de }).map(Some)
+            .map_err(|error| error.within(&format!("[{}]", self.index - 1), self.de))
+    }
+}
+
+/// A single value, such as a string or an integer
+enum Scalar<'de> {
+    Str(Cow<'de, str>),
+    Value(ParsedValue),
+}
+
+fn scalar(event: BorrowedEvent) -> Result<Scalar> {
+    match event {
+        BorrowedEvent::Str(string) | BorrowedEvent::Ident(string) => Ok(Scalar::Str(string)),
+        BorrowedEvent::Other(ParseEvent::Value(value)) => Ok(Scalar::Value(value)),
+        event => Err(unexpected(&event)),
+    }
+}
+
+impl<'de> IntoDeserializer<'de, DeserializeError> for Scalar<'de> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+impl<'de> de::Deserializer<'de> for Scalar<'de> {
+    type Error = DeserializeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let value = match self {
+            Scalar::Str(Cow::Borrowed(string)) => return visitor.visit_borrowed_str(string),
+            Scalar::Str(Cow::Owned(string)) => return visitor.visit_string(string),
+            Scalar::Value(value) => value,
+        };
+        match value {
+            ParsedValue::Str(string) | ParsedValue::Ident(string) => visitor.visit_string(string),
+            ParsedValue::Int(int) => visitor.visit_i64(int),
+            ParsedValue::Float(float) => visitor.visit_f64(float),
+            ParsedValue::Bool(boolean) => visitor.visit_bool(boolean),
+            ParsedValue::DateTime(datetime) => visitor.visit_string(datetime.to_string()),
+            ParsedValue::Duration(duration) => {
+                let nanos = duration.subsec_nanos().into();
+                let fields = vec![("secs", duration.as_secs()), ("nanos", nanos)];
+                visitor.visit_map(MapDeserializer::new(fields.into_iter()))
+            },
+            // most formats have no bytes type, so bytes are a sequence of numbers unless
+            // they're asked for as bytes
+            ParsedValue::Bytes(bytes) => visitor.visit_seq(SeqDeserializer::new(bytes.into_iter())),
+            ParsedValue::Null => visitor.visit_unit(),
+        }
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_byte_buf(visitor)
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self {
+            Scalar::Value(ParsedValue::Bytes(bytes)) => visitor.visit_byte_buf(bytes),
+            scalar => scalar.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self {
+            Scalar::Value(ParsedValue::Null) => visitor.visit_none(),
+            scalar => visitor.visit_some(scalar),
+        }
+    }
+
+    fn deserialize_newtype_struct<V>(self, _: &'static str, visitor: V) -> Result<V::Value>
+            where V: Visitor<'de> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V>(self, _: &'static str, _: &'static [&'static str], visitor: V)
+                           -> Result<V::Value> where V: Visitor<'de> {
+        match self {
+            Scalar::Str(Cow::Borrowed(string)) =>
+                visitor.visit_enum(BorrowedStrDeserializer::new(string)),
+            Scalar::Str(Cow::Owned(string)) => visitor.visit_enum(string.into_deserializer()),
+            Scalar::Value(ParsedValue::Str(string)) | Scalar::Value(ParsedValue::Ident(string)) =>
+                visitor.visit_enum(string.into_deserializer()),
+            scalar => scalar.deserialize_any(visitor),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string seq
+        unit unit_struct tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate serde_json;
+
+    use super::*;
+    use super::super::position::Position;
+    use super::super::types::{Dict, Value};
+    use std::collections::HashMap;
+    use std::io::Cursor;
+    use std::time::Duration;
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Config {
+        name: String,
+        server: Server,
+        upstream: Vec<Upstream>,
+        #[serde(default)]
+        debug: bool,
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Server {
+        #[serde(rename = "$arguments")]
+        address: (String, u16),
+        timeout: Duration,
+        started: String,
+        mode: Mode,
+        limits: HashMap<String, Option<u32>>,
+        tls: Option<Tls>,
+        workers: u8,
+        shape: Shape,
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Upstream {
+        host: String,
+        weight: f32,
+        key: Vec<u8>,
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Tls {
+        cert: String,
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    #[serde(rename_all = "lowercase")]
+    enum Mode {
+        Fast,
+        Safe,
+        Custom(String),
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    #[serde(rename_all = "lowercase")]
+    enum Shape {
+        Point(i32, i32),
+        Circle { radius: u32 },
+    }
+
+    fn parse<'de, T: de::Deserialize<'de>>(input: &'de str) -> Result<T> {
+        let options = ParserOptions::new().node_arguments(true);
+        let mut deserializer = Deserializer::from_str_with_options(input, options);
+        let value = T::deserialize(&mut deserializer)?;
+        deserializer.end()?;
+        Ok(value)
+    }
+
+    #[test]
+    fn deserializes_structs() {
+        let input = "
+            name { }
+            server 'localhost' 8080 {
+                timeout = 1m30s, started = 2020-01-02T03:04:05Z, mode = !fast,
+                limits = { a = 1, b = null },
+                workers 4 {}
+                shape { circle { radius = 2 } }
+            }
+            upstream { host = 'a', weight = 1, key = b'\\x00a' }
+            upstream { host = 'b', weight = 0.5, key = [1, 2] }
+        ";
+        assert!(parse::<Config>(input).is_err());
+
+        let input = input.replace("name { }", "name 'main' {}");
+        let config: Config = parse(&input).unwrap();
+        assert_eq!(config, Config {
+            name: "main".into(),
+            server: Server {
+                address: ("localhost".into(), 8080),
+                timeout: Duration::from_secs(90),
+                started: "2020-01-02T03:04:05Z".into(),
+                mode: Mode::Fast,
+                limits: vec![("a".to_string(), Some(1)), ("b".to_string(), None)]
+                    .into_iter().collect(),
+                tls: None,
+                workers: 4,
+                shape: Shape::Circle { radius: 2 },
+            },
+            upstream: vec![
+                Upstream { host: "a".into(), weight: 1.0, key: vec![0, 97] },
+                Upstream { host: "b".into(), weight: 0.5, key: vec![1, 2] },
+            ],
+            debug: false,
+        });
+
+        let input = input.replace("!fast", "{ custom = 'x' }")
+            .replace("{ circle { radius = 2 } }", "{ point 1 -2 {} }");
+        let server = parse::<Config>(&input).unwrap().server;
+        assert_eq!((server.mode, server.shape), (Mode::Custom("x".into()), Shape::Point(1, -2)));
+        assert_eq!(from_str::<HashMap<String, Tls>>("a { cert = 'x' }").unwrap()["a"].cert, "x");
+        assert_eq!(from_reader::<_, Tls>(Cursor::new("cert {}")).unwrap_err(),
+                   DeserializeError::At {
+                       message: "invalid type: map, expected a string".into(),
+                       path: "cert".into(),
+                       span: Span::new(Position::new(), Position::at(0, 6)),
+                   });
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Borrowed<'a> {
+        name: &'a str,
+        #[serde(borrow)]
+        label: Cow<'a, str>,
+        tags: HashMap<&'a str, Vec<&'a str>>,
+        mode: Mode,
+    }
+
+    #[test]
+    fn borrows_strings() {
+        let input = "a { name = 'x', label = 'tab\\t', tags = { 'y z' = ['w', !v] },
+                         mode = !safe }";
+        let doc: HashMap<&str, Borrowed> = from_str(input).unwrap();
+        let borrowed = &doc["a"];
+        assert_eq!((borrowed.name, borrowed.mode == Mode::Safe), ("x", true));
+        assert!(matches!(borrowed.label, Cow::Owned(ref label) if label == "tab\t"));
+        assert_eq!(borrowed.tags["y z"], vec!["w", "v"]);
+
+        let escaped = input.replace("'x'", "'\\n'");
+        let error = from_str::<HashMap<String, Borrowed>>(&escaped).unwrap_err();
+        assert_eq!(error.to_string(),
+                   "invalid type: string \"\\n\", expected a borrowed string at a.name (line 1, column 12)");
+        let owned: HashMap<String, Upstream> =
+            from_reader(Cursor::new("a { host = 'h', weight = 1, key = [] }")).unwrap();
+        assert_eq!(owned["a"].host, "h");
+    }
+
+    #[test]
+    fn borrows_from_long_lines() {
+        let input = format!("a {{ {} }}", (0..32000)
+            .map(|i| format!("k{} = 'v{}'", i, i)).collect::<Vec<_>>().join(", "));
+        let doc: HashMap<&str, HashMap<&str, &str>> = from_str(&input).unwrap();
+        assert_eq!(doc["a"].len(), 32000);
+        assert_eq!(doc["a"]["k31999"], "v31999");
+
+        // and in the same way with options
+        let doc: HashMap<&str, HashMap<&str, &str>> = parse(&input).unwrap();
+        assert_eq!(doc["a"]["k0"], "v0");
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Listener {
+        port: u16,
+        #[serde(flatten)]
+        options: Options,
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Options {
+        backend: Vec<Backend>,
+        auth: Auth,
+        #[serde(flatten)]
+        rest: HashMap<String, Any>,
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    #[serde(untagged)]
+    enum Backend {
+        Address { host: String, port: u16 },
+        Socket { path: String },
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    #[serde(tag = "type", rename_all = "lowercase")]
+    enum Auth {
+        None,
+        Token { token: String },
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    #[serde(untagged)]
+    enum Any {
+        Int(i64),
+        Str(String),
+        List(Vec<Any>),
+        Map(HashMap<String, Any>),
+    }
+
+    #[test]
+    fn deserializes_flattened_structs() {
+        let input = "
+            listen {
+                port = 80, timeout = 5, tags = ['a', !b]
+                backend { host = 'a', port = 81 }
+                backend { path = '/tmp/b' }
+                auth { type = 'token', token = 'x' }
+                workers 4 {}
+                limits { size = 1 }
+            }
+        ";
+        let listen = &parse::<HashMap<String, Listener>>(input).unwrap()["listen"];
+        assert_eq!(listen.port, 80);
+        assert_eq!(listen.options.backend, vec![
+            Backend::Address { host: "a".into(), port: 81 },
+            Backend::Socket { path: "/tmp/b".into() },
+        ]);
+        assert_eq!(listen.options.auth, Auth::Token { token: "x".into() });
+        let rest = &listen.options.rest;
+        assert_eq!((&rest["timeout"], &rest["workers"]), (&Any::Int(5), &Any::Int(4)));
+        assert_eq!(rest["tags"], Any::List(vec![Any::Str("a".into()), Any::Str("b".into())]));
+        assert_eq!(rest["limits"], Any::Map(vec![("size".into(), Any::Int(1))].into_iter().collect()));
+
+        // a single node can only be a sequence when the type says so
+        let single = input.replace("backend { path = '/tmp/b' }", "");
+        let error = parse::<HashMap<String, Listener>>(&single).unwrap_err();
+        assert_eq!(error.to_string(),
+                   "invalid type: map, expected a sequence at listen (line 9, column 13)");
+
+        let error = parse::<HashMap<String, Backend>>("a { host = 'a' }").unwrap_err();
+        assert_eq!(error.to_string(),
+                   "data did not match any variant of untagged enum Backend at a (line 1, column 16)");
+        let auth: HashMap<String, Auth> = from_str("a { type = 'none' }").unwrap();
+        assert_eq!(auth["a"], Auth::None);
+
+        #[derive(Deserialize, Debug)]
+        #[serde(deny_unknown_fields)]
+        struct Strict {
+            #[allow(dead_code)]
+            port: u16,
+        }
+        let error = parse::<HashMap<String, Strict>>("a { port = 1, host = 'x' }").unwrap_err();
+        assert_eq!(error.to_string(),
+                   "unknown field `host`, expected `port` at a.host (line 1, column 15)");
+        let error = parse::<HashMap<String, Strict>>("a 1 { port = 1 }").unwrap_err();
+        assert_eq!(error.to_string(),
+                   "unknown field `$arguments`, expected `port` at a.$arguments (line 1, column 3)");
+    }
+
+    #[test]
+    fn deserializes_values() {
+        let input = "
+            a 'x' {
+                b = [1, 2.5, !c, null], d = { e = true, f = b'g' }, h = 1s5ns
+                i {}
+                i { j = 2 }
+            }
+            k 3 {}
+        ";
+        let doc: Value = parse(input).unwrap();
+        let mut a = Dict::new();
+        let list: Vec<Value> = vec![1.into(), 2.5.into(), "c".into(), Value::Null];
+        a.insert("$arguments".into(), vec![Value::from("x")].into());
+        a.insert("b".into(), list.into());
+        let mut d = Dict::new();
+        d.insert("e".into(), true.into());
+        // bytes are a sequence of numbers unless they're asked for as bytes
+        d.insert("f".into(), vec![Value::from(103)].into());
+        a.insert("d".into(), d.into());
+        let mut h = Dict::new();
+        h.insert("secs".into(), 1.into());
+        h.insert("nanos".into(), 5.into());
+        a.insert("h".into(), h.into());
+        let mut j = Dict::new();
+        j.insert("j".into(), 2.into());
+        a.insert("i".into(), vec![Value::from(Dict::new()), j.into()].into());
+        let mut expected = Dict::new();
+        expected.insert("a".into(), a.into());
+        expected.insert("k".into(), 3.into());
+        assert_eq!(doc, Value::from(expected));
+
+        let json: serde_json::Value = parse(input).unwrap();
+        assert_eq!(json, serde_json::json!({
+            "a": {
+                "$arguments": ["x"],
+                "b": [1, 2.5, "c", null],
+                "d": { "e": true, "f": [103] },
+                "h": { "secs": 1, "nanos": 5 },
+                "i": [{}, { "j": 2 }],
+            },
+            "k": 3,
+        }));
+        let values: HashMap<String, HashMap<String, Value>> = from_str("a { b = 'c' }").unwrap();
+        assert_eq!(values["a"]["b"], Value::from("c"));
+    }
+
+    #[test]
+    fn deserializes_bytes() {
+        struct Buf(Vec<u8>);
+
+        impl<'de> de::Deserialize<'de> for Buf {
+            fn deserialize<D: de::Deserializer<'de>>(deserializer: D)
+                                                     -> ::std::result::Result<Self, D::Error> {
+                struct BufVisitor;
+                impl<'de> Visitor<'de> for BufVisitor {
+                    type Value = Buf;
+                    fn expecting(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                        f.write_str("bytes")
+                    }
+                    fn visit_byte_buf<E: de::Error>(self, bytes: Vec<u8>)
+                                                    -> ::std::result::Result<Buf, E> {
+                        Ok(Buf(bytes))
+                    }
+                }
+                deserializer.deserialize_byte_buf(BufVisitor)
+            }
+        }
+
+        let input = "a { key = b'hi' }";
+        let json: serde_json::Value = from_str(input).unwrap();
+        assert_eq!(json, serde_json::json!({ "a": { "key": [104, 105] } }));
+        let back: HashMap<String, HashMap<String, Vec<u8>>> = serde_json::from_value(json).unwrap();
+        assert_eq!(back["a"]["key"], b"hi");
+
+        let bufs: HashMap<String, HashMap<String, Buf>> = from_str(input).unwrap();
+        assert_eq!(bufs["a"]["key"].0, b"hi");
+        let bytes: HashMap<String, HashMap<String, Vec<u8>>> = from_str(input).unwrap();
+        assert_eq!(bytes["a"]["key"], b"hi");
+    }
+
+    #[test]
+    fn reports_errors() {
+        let error = from_str::<HashMap<String, Tls>>("a { cert = 1 }").unwrap_err();
+        assert_eq!(error.to_string(),
+                   "invalid type: integer `1`, expected a string at a.cert (line 1, column 12)");
+        assert_eq!(error.path(), Some("a.cert"));
+        let error = from_str::<HashMap<String, Tls>>("a { }").unwrap_err();
+        assert_eq!(error.to_string(), "missing field `cert` at a (line 1, column 5)");
+        let error = from_str::<HashMap<String, Tls>>("a { cert = 'x' ").unwrap_err();
+        assert!(matches!(error, DeserializeError::Parse(ParseError::UnexpectedEndOfFile, _)));
+        let error = parse::<HashMap<String, u8>>("a 1 { b = 2 }").unwrap_err();
+        assert_eq!(error.to_string(), concat!("expected node `a` to have a single argument and no",
+                                              " entries at a (line 1, column 3)"));
+        let error = from_str::<HashMap<String, Mode>>("a { fast = null, safe = null }").unwrap_err();
+        assert_eq!(error.to_string(), "expected a single entry for an enum at a (line 1, column 18)");
+
+        let input = concat!(
+            "server {\n",
+            "    pool { size = 'big' }\n",
+            "}\n",
+        );
+        let error = from_str::<HashMap<String, HashMap<String, HashMap<String, u8>>>>(input);
+        let error = error.unwrap_err();
+        assert_eq!(error.to_string(), concat!("invalid type: string \"big\", expected u8 at",
+                                              " server.pool.size (line 2, column 19)"));
+        assert_eq!(error.span(), Some(&Span::new(Position::at(1, 18), Position::at(1, 23))));
+        let input = "a { ports = [1, 2, -3] } a { ports = [] } a { ports = [1, 'x'] }";
+        let error = from_str::<HashMap<String, Vec<HashMap<String, Vec<u16>>>>>(input);
+        assert_eq!(error.unwrap_err().to_string(),
+                   "invalid value: integer `-3`, expected u16 at a[0].ports[2] (line 1, column 20)");
+    }
+}
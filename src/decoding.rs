@@ -0,0 +1,149 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::io::prelude::*;
+
+use encoding_rs::{Decoder, DecoderResult, Encoding};
+
+/// The error produced by a `DecodingReader` when the input contains bytes that are not
+/// valid in its encoding.  Contains the name of the encoding.
+#[derive(Debug)]
+pub struct DecodeError(pub &'static str);
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "input is not valid {}", self.0)
+    }
+}
+
+impl Error for DecodeError {}
+
+const BUFFER_SIZE: usize = 8 * 1024;
+
+/// Wraps a reader of input in some encoding, and produces UTF-8.
+///
+/// A byte order mark at the start of the input overrides the declared encoding.  All the
+/// input that could be decoded is produced before a decoding error is returned, so that
+/// the error can be reported at the right position.
+pub struct DecodingReader<R: Read> {
+    reader: R,
+    decoder: Decoder,
+    input: Vec<u8>,
+    output: Vec<u8>,
+    output_start: usize,
+    eof: bool,
+    flushed: bool,
+    failed: bool,
+}
+
+impl<R: Read> DecodingReader<R> {
+    pub fn new(reader: R, encoding: &'static Encoding) -> Self {
+        DecodingReader {
+            reader,
+            decoder: encoding.new_decoder(),
+            input: Vec::new(),
+            output: Vec::new(),
+            output_start: 0,
+            eof: false,
+            flushed: false,
+            failed: false,
+        }
+    }
+
+    /// Decode some more input into the output buffer.  Returns `false` once all of the
+    /// input has been decoded.
+    fn decode(&mut self) -> io::Result<bool> {
+        if self.flushed {
+            return Ok(false);
+        }
+
+        if self.input.is_empty() {
+            let mut buffer = [0; BUFFER_SIZE];
+            let read = self.reader.read(&mut buffer)?;
+            self.input.extend_from_slice(&buffer[..read]);
+            self.eof = read == 0;
+        }
+
+        self.output.resize(BUFFER_SIZE * 3, 0);
+        self.output_start = 0;
+        let (result, read, written) = self.decoder.decode_to_utf8_without_replacement(
+            &self.input, &mut self.output, self.eof);
+        self.input.drain(..read);
+        self.output.truncate(written);
+
+        match result {
+            DecoderResult::Malformed(_, _) => self.failed = true,
+            DecoderResult::InputEmpty => self.flushed = self.eof,
+            DecoderResult::OutputFull => {},
+        }
+        Ok(true)
+    }
+}
+
+impl<R: Read> Read for DecodingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if self.output_start < self.output.len() {
+                let available = &self.output[self.output_start..];
+                let count = available.len().min(buf.len());
+                buf[..count].copy_from_slice(&available[..count]);
+                self.output_start += count;
+                return Ok(count);
+            }
+
+            if self.failed {
+                let name = self.decoder.encoding().name();
+                return Err(io::Error::new(io::ErrorKind::InvalidData, DecodeError(name)));
+            }
+
+            if !self.decode()? {
+                return Ok(0);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::{WINDOWS_1252, SHIFT_JIS, UTF_8};
+    use std::io::Cursor;
+
+    fn decode(input: &'static [u8], encoding: &'static Encoding) -> (String, Option<io::Error>) {
+        let mut reader = DecodingReader::new(Cursor::new(input), encoding);
+        let mut output = Vec::new();
+        let mut buffer = [0; 3];
+        loop {
+            match reader.read(&mut buffer) {
+                Ok(0) => return (String::from_utf8(output).unwrap(), None),
+                Ok(n) => output.extend_from_slice(&buffer[..n]),
+                Err(err) => return (String::from_utf8(output).unwrap(), Some(err)),
+            }
+        }
+    }
+
+    #[test]
+    fn decodes_input() {
+        let (output, err) = decode(b"caf\xe9 \x80", WINDOWS_1252);
+        assert_eq!(output, "café €");
+        assert!(err.is_none());
+
+        // byte order marks override the declared encoding
+        let (output, err) = decode(b"\xFF\xFEh\x00\xe9\x00", WINDOWS_1252);
+        assert_eq!(output, "hé");
+        assert!(err.is_none());
+
+        let (output, err) = decode(b"", UTF_8);
+        assert_eq!(output, "");
+        assert!(err.is_none());
+    }
+
+    #[test]
+    fn reports_malformed_input() {
+        let (output, err) = decode(b"ab\x81\x20cd", SHIFT_JIS);
+        assert_eq!(output, "ab");
+        let err = err.unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.get_ref().unwrap().to_string(), "input is not valid Shift_JIS");
+    }
+}
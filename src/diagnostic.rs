@@ -0,0 +1,222 @@
+use std::fmt;
+
+use super::lexer::LexError;
+use super::parser::ParseError;
+use super::position::{Position, Span};
+
+/// An error with the span of the input it's about and an optional hint for fixing it,
+/// which can be rendered with the lines of the input it points at, in the style of the
+/// Rust compiler:
+///
+/// ```text
+/// error[E0001]: unexpected `}`
+///  --> config.fig:2:8
+///   |
+/// 2 |     a: }
+///   |        ^
+/// ```
+///
+/// A `Diagnostic` can be made from the `(ParseError, Span)` pairs that a `Parser`
+/// produces and `Figtree::parse` returns, or from a `(ParseError, Position)` pair, and
+/// gets a help message for the errors that have an obvious fix.
+///
+/// # Examples
+/// ```
+/// # use figtree::{Diagnostic, Figtree};
+/// let source = "server {\n    tls = \"yes\n}";
+/// let error = Figtree::from_string(source).parse().unwrap_err();
+/// let diagnostic = Diagnostic::from(error).file("server.fig");
+/// assert_eq!(diagnostic.render(source), concat!(
+///     "error[E0102]: invalid token: unclosed string\n",
+///     " --> server.fig:2:11\n",
+///     "  |\n",
+///     "2 |     tls = \"yes\n",
+///     "  |           ^^^^\n",
+///     "  = help: strings have to be closed with the same quote that opens them\n",
+/// ));
+/// ```
+#[derive(Debug, PartialEq, Clone)]
+pub struct Diagnostic {
+    /// What the problem is
+    pub message: String,
+    /// The part of the input the problem is in
+    pub span: Span,
+    /// A hint about how to fix the problem
+    pub help: Option<String>,
+    /// The name of the file the input came from, to show alongside the position
+    pub file: Option<String>,
+    /// The code of the error, like `E0001` (see `ParseError::code`)
+    pub code: Option<&'static str>,
+}
+
+impl Diagnostic {
+    /// Construct a diagnostic for a message about a span of the input
+    pub fn new<S: Into<String>>(message: S, span: Span) -> Self {
+        Diagnostic { message: message.into(), span, help: None, file: None, code: None }
+    }
+
+    /// Add a hint about how to fix the problem
+    pub fn help<S: Into<String>>(mut self, help: S) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+
+    /// Set the name of the file the input came from
+    pub fn file<S: Into<String>>(mut self, file: S) -> Self {
+        self.file = Some(file.into());
+        self
+    }
+
+    /// Render the diagnostic with the lines of `source` that its span covers, with carets
+    /// under the span.  Spans that cover more than one line are underlined on their
+    /// first line only.
+    pub fn render(&self, source: &str) -> String {
+        let start = &self.span.start;
+        let line = source.lines().nth(start.line).unwrap_or("");
+        let number = (start.line + 1).to_string();
+        let gutter = " ".repeat(number.len());
+
+        let mut out = match self.code {
+            Some(code) => format!("error[{}]: {}\n", code, self.message),
+            None => format!("error: {}\n", self.message),
+        };
+        match self.file {
+            Some(ref file) => out.push_str(&format!("{}--> {}:{}:{}\n", gutter, file,
+                                                    start.line + 1, start.pos + 1)),
+            None => out.push_str(&format!("{}--> {}\n", gutter, start)),
+        }
+        out.push_str(&format!("{} |\n", gutter));
+        out.push_str(&format!("{} | {}\n", number, line));
+
+        // tabs are kept in the padding, so the carets line up under the line itself
+        let padding: String = line.chars().take(start.pos)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let length = line.chars().count();
+        let end = if self.span.end.line == start.line { self.span.end.pos } else { length };
+        let width = end.min(length).saturating_sub(start.pos).max(1);
+        out.push_str(&format!("{} | {}{}\n", gutter, padding, "^".repeat(width)));
+        if let Some(ref help) = self.help {
+            out.push_str(&format!("{} = help: {}\n", gutter, help));
+        }
+        out
+    }
+}
+
+/// Diagnostics are displayed as their message and position, without the input
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.file {
+            Some(ref file) => write!(f, "{} (in {}, at {})", self.message, file, self.span.start),
+            None => write!(f, "{} (at {})", self.message, self.span.start),
+        }
+    }
+}
+
+/// A hint about how to fix a parse error, for the errors that have an obvious fix
+fn parse_help(error: &ParseError) -> Option<String> {
+    let help = match *error {
+        ParseError::LexError(LexError::UnclosedCommentError) => {
+            "block comments have to be closed with `*/`"
+        },
+        ParseError::LexError(LexError::UnclosedStringError) => {
+            "strings have to be closed with the same quote that opens them"
+        },
+        ParseError::LexError(LexError::UnclosedIdentError) => {
+            "identifiers have to be closed with the same quote that opens them"
+        },
+        ParseError::LexError(LexError::InvalidEscape(_)) => {
+            "a backslash on its own in a string is written as `\\\\`"
+        },
+        ParseError::LexError(LexError::InvalidDigitSeparator) => {
+            "`_` can only be used between two digits, as in `1_000`"
+        },
+        ParseError::UnexpectedEndOfFile => {
+            "every `{` and `[` needs a matching `}` or `]` before the end of the input"
+        },
+        ParseError::RepeatedNode(_) => {
+            "nodes with the same name are allowed with `ParserOptions::repeated_nodes`"
+        },
+        ParseError::NonFiniteFloat => {
+            "infinite and NaN floats are forbidden by the parser's `NonFinitePolicy`"
+        },
+        ParseError::TooManyEvents => "the limit is set with `ParserOptions::max_events`",
+        ParseError::DuplicateKey(_, ref first) => {
+            return Some(format!("the key was first used at {}", first.start));
+        },
+        _ => return None,
+    };
+    Some(help.to_string())
+}
+
+impl From<(ParseError, Span)> for Diagnostic {
+    fn from((error, span): (ParseError, Span)) -> Self {
+        let help = parse_help(&error);
+        Diagnostic { message: error.to_string(), span, help, file: None, code: Some(error.code()) }
+    }
+}
+
+impl From<(ParseError, Position)> for Diagnostic {
+    fn from((error, position): (ParseError, Position)) -> Self {
+        Diagnostic::from((error, Span::new(position.clone(), position)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::super::lexer::Lexer;
+    use super::super::parser::Parser;
+    use super::*;
+
+    fn parse_error(source: &str) -> (ParseError, Span) {
+        Parser::parse(Lexer::lex(Cursor::new(source.to_string())))
+            .find_map(Result::err)
+            .unwrap()
+    }
+
+    #[test]
+    fn renders_spans() {
+        let source = "node {\n\tkey = [1, 2] 3\n}";
+        let diagnostic = Diagnostic::from(parse_error(source));
+        assert_eq!(diagnostic.to_string(),
+                   "unexpected integer 3, expected `}` or `,` (at line 2, column 15)");
+        assert_eq!(diagnostic.render(source), concat!(
+            "error[E0001]: unexpected integer 3, expected `}` or `,`\n",
+            " --> line 2, column 15\n",
+            "  |\n",
+            "2 | \tkey = [1, 2] 3\n",
+            "  | \t             ^\n",
+        ));
+
+        let span = Span::new(Position::at(9, 2), Position::at(10, 0));
+        let diagnostic = Diagnostic::new("too long", span).help("shorten it").file("a.fig");
+        let source = format!("{}abcdef\nx", "\n".repeat(9));
+        assert_eq!(diagnostic.to_string(), "too long (in a.fig, at line 10, column 3)");
+        assert_eq!(diagnostic.render(&source), concat!(
+            "error: too long\n",
+            "  --> a.fig:10:3\n",
+            "   |\n",
+            "10 | abcdef\n",
+            "   |   ^^^^\n",
+            "   = help: shorten it\n",
+        ));
+    }
+
+    #[test]
+    fn adds_help() {
+        let diagnostic = Diagnostic::from(parse_error("node { key = [1, 2 }"));
+        assert_eq!(diagnostic.help, None);
+        let diagnostic = Diagnostic::from(parse_error("node { key = [1, 2]"));
+        assert_eq!(diagnostic.message, "unexpected end of file");
+        assert!(diagnostic.help.unwrap().contains("matching `}`"));
+
+        let first = Span::new(Position::at(0, 7), Position::at(0, 8));
+        let error = (ParseError::DuplicateKey("a".into(), first), Position::at(0, 14));
+        let diagnostic = Diagnostic::from(error);
+        assert_eq!(diagnostic.help.as_deref(), Some("the key was first used at line 1, column 8"));
+        assert_eq!(diagnostic.render("node { a = 1, a = 2 }").lines().nth(4),
+                   Some("  |               ^"));
+    }
+}
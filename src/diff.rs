@@ -0,0 +1,232 @@
+use std::fmt;
+
+use super::path::{join, node_path};
+use super::position::Span;
+use super::types::{Document, Node, Value, ValueSpan};
+
+/// Whether an entry was added, removed or modified between two documents
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ChangeKind {
+    /// The entry is only in the second document
+    Added,
+    /// The entry is only in the first document
+    Removed,
+    /// The entry is in both documents, but with different values (or, for a node,
+    /// different arguments)
+    Modified,
+}
+
+/// A difference between two documents, found by `diff`
+#[derive(Debug, PartialEq, Clone)]
+pub struct Change {
+    pub kind: ChangeKind,
+    /// The path to the node or value that changed, in the same form as the paths used by
+    /// `Document::get_path`
+    pub path: String,
+    /// Where the entry is in the first document, if it is there and the document was
+    /// parsed.  For a modified value, this is the span of just the value.
+    pub old_span: Option<Span>,
+    /// Where the entry is in the second document, if it is there and the document was
+    /// parsed
+    pub new_span: Option<Span>,
+}
+
+impl Change {
+    fn new(kind: ChangeKind, path: String, old_span: Option<Span>, new_span: Option<Span>) -> Self {
+        Change { kind, path, old_span, new_span }
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (kind, span) = match self.kind {
+            ChangeKind::Added => ("added", &self.new_span),
+            ChangeKind::Removed => ("removed", &self.old_span),
+            ChangeKind::Modified => ("modified", &self.new_span),
+        };
+        write!(f, "{} {}", kind, self.path)?;
+        if let Some(ref span) = *span {
+            write!(f, " (at line {}, column {})", span.start.line + 1, span.start.pos + 1)?;
+        }
+        Ok(())
+    }
+}
+
+/// Find the differences between two documents, for auditing changes to config files.
+///
+/// Nodes are compared by name, pairing up repeated nodes in order like `Document::merge`
+/// does, and dicts are compared key by key.  Any other values that are not structurally
+/// equal (see `Value::structurally_eq`), including lists, are reported as modified as a
+/// whole.  A node whose arguments are different is reported as modified, and the rest
+/// of it is still compared.  Changes are listed in the order of the first document,
+/// followed by anything only in the second; keys of dicts are in sorted order.
+///
+/// # Examples
+/// ```
+/// # use figtree::types::*;
+/// # use figtree::{diff, ChangeKind};
+/// # use std::io::Cursor;
+/// let old = Document::parse(Cursor::new("server { host = 'a', port = 80 }")).unwrap();
+/// let new = Document::parse(Cursor::new("server {\n    port = 8080\n}\nlog {}")).unwrap();
+/// let changes = diff(&old, &new);
+/// assert_eq!(changes.iter().map(|change| change.kind).collect::<Vec<_>>(),
+///     vec![ChangeKind::Removed, ChangeKind::Modified, ChangeKind::Added]);
+/// assert_eq!(changes[1].path, "server.port");
+/// assert_eq!(changes[1].to_string(), "modified server.port (at line 2, column 12)");
+/// ```
+pub fn diff(first: &Document, second: &Document) -> Vec<Change> {
+    let mut changes = Vec::new();
+    diff_nodes(&mut changes, first.children().as_slice(), second.children().as_slice(), "");
+    changes
+}
+
+/// The span of a whole `key = value` entry
+fn entry_span(key: &Span, value: &ValueSpan) -> Span {
+    Span::new(key.start.clone(), value.span.end.clone())
+}
+
+fn diff_nodes(changes: &mut Vec<Change>, nodes: &[Node], others: &[Node], path: &str) {
+    let mut names: Vec<&str> = Vec::new();
+    for node in nodes.iter().chain(others) {
+        if !names.contains(&node.name()) {
+            names.push(node.name());
+        }
+    }
+
+    for name in names {
+        let named: Vec<_> = nodes.iter().filter(|node| node.name() == name).collect();
+        let others: Vec<_> = others.iter().filter(|node| node.name() == name).collect();
+        let count = named.len().max(others.len());
+        for index in 0..count {
+            let path = node_path(path, name, index, count > 1);
+            match (named.get(index), others.get(index)) {
+                (Some(node), Some(other)) => diff_node(changes, node, other, &path),
+                (Some(node), None) =>
+                    changes.push(Change::new(ChangeKind::Removed, path, node.span().cloned(), None)),
+                (None, Some(other)) =>
+                    changes.push(Change::new(ChangeKind::Added, path, None, other.span().cloned())),
+                (None, None) => {},
+            }
+        }
+    }
+}
+
+fn diff_node(changes: &mut Vec<Change>, node: &Node, other: &Node, path: &str) {
+    let (arguments, other_arguments) = (node.arguments(), other.arguments());
+    if arguments.len() != other_arguments.len()
+        || !arguments.iter().zip(other_arguments).all(|(a, b)| a.structurally_eq(b)) {
+
+        changes.push(Change::new(ChangeKind::Modified, path.to_string(),
+            node.span().cloned(), other.span().cloned()));
+    }
+
+    for (key, value) in node.properties() {
+        let key_path = join(path, key);
+        match other.get(key) {
+            Some(other_value) => diff_value(changes, value, other_value, &key_path,
+                node.value_span(key), other.value_span(key)),
+            None => {
+                let span = node.key_span(key).zip(node.value_span(key));
+                let span = span.map(|(key, value)| entry_span(key, value));
+                changes.push(Change::new(ChangeKind::Removed, key_path, span, None));
+            },
+        }
+    }
+    for (key, _) in other.properties().filter(|&(key, _)| !node.has(key)) {
+        let span = other.key_span(key).zip(other.value_span(key));
+        let span = span.map(|(key, value)| entry_span(key, value));
+        changes.push(Change::new(ChangeKind::Added, join(path, key), None, span));
+    }
+
+    diff_nodes(changes, node.children().as_slice(), other.children().as_slice(), path);
+}
+
+fn diff_value(changes: &mut Vec<Change>, value: &Value, other: &Value, path: &str,
+    span: Option<&ValueSpan>, other_span: Option<&ValueSpan>) {
+
+    let (dict, other) = match (value, other) {
+        (Value::Dict(dict), Value::Dict(other)) => (dict, other),
+        _ => {
+            if !value.structurally_eq(other) {
+                changes.push(Change::new(ChangeKind::Modified, path.to_string(),
+                    span.map(|span| span.span.clone()), other_span.map(|span| span.span.clone())));
+            }
+            return;
+        },
+    };
+
+    let mut keys: Vec<_> = dict.keys().chain(other.keys().filter(|key| !dict.contains_key(*key)))
+        .collect();
+    keys.sort();
+    for key in keys {
+        let key_path = join(path, key);
+        let entry = span.and_then(|span| span.entries.get(key));
+        let other_entry = other_span.and_then(|span| span.entries.get(key));
+        match (dict.get(key), other.get(key)) {
+            (Some(value), Some(other_value)) => diff_value(changes, value, other_value, &key_path,
+                entry.map(|entry| &entry.1), other_entry.map(|entry| &entry.1)),
+            (Some(_), None) => changes.push(Change::new(ChangeKind::Removed, key_path,
+                entry.map(|entry| entry_span(&entry.0, &entry.1)), None)),
+            (None, Some(_)) => changes.push(Change::new(ChangeKind::Added, key_path,
+                None, other_entry.map(|entry| entry_span(&entry.0, &entry.1)))),
+            (None, None) => {},
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::figtree::Figtree;
+    use super::super::parser::ParserOptions;
+    use super::super::position::Position;
+
+    fn parse(input: &str) -> Document {
+        let options = ParserOptions::new().repeated_nodes(true).node_arguments(true);
+        Figtree::from_string(input).with_options(options).parse().unwrap()
+    }
+
+    fn changes(first: &str, second: &str) -> Vec<(ChangeKind, String)> {
+        diff(&parse(first), &parse(second)).into_iter()
+            .map(|change| (change.kind, change.path))
+            .collect()
+    }
+
+    #[test]
+    fn finds_changes() {
+        use self::ChangeKind::*;
+        let first = "a { x = 1, y = [1, 2], d = { k = 1, gone = 2, same = nan } } up { h = 'a' } old {}";
+        let second = "a { x = 1, y = [2, 1], d = { k = 2, same = nan, new = 3 }, z = 1 }
+                      up { h = 'a' } up { h = 'b' } new 1 {}";
+        assert_eq!(changes(first, second), vec![
+            (Modified, "a.y".to_string()),
+            (Removed, "a.d.gone".to_string()),
+            (Modified, "a.d.k".to_string()),
+            (Added, "a.d.new".to_string()),
+            (Added, "a.z".to_string()),
+            (Added, "up[1]".to_string()),
+            (Removed, "old".to_string()),
+            (Added, "new".to_string()),
+        ]);
+        assert_eq!(changes(first, first), vec![]);
+        assert_eq!(changes("a 1 { b 'x' { c = 1 } }", "a 2 { b 'y' { c = 2 } }"), vec![
+            (Modified, "a".to_string()),
+            (Modified, "a.b".to_string()),
+            (Modified, "a.b.c".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn records_spans() {
+        let changes = diff(&parse("a {\n  x = 1,\n  y = 2\n}"), &parse("a { x = 3 } b {}"));
+        let span = |line, start, end| Some(Span::new(Position::at(line, start), Position::at(line, end)));
+        assert_eq!(changes, vec![
+            Change::new(ChangeKind::Modified, "a.x".to_string(), span(1, 6, 7), span(0, 8, 9)),
+            Change::new(ChangeKind::Removed, "a.y".to_string(), span(2, 2, 7), None),
+            Change::new(ChangeKind::Added, "b".to_string(), None, span(0, 12, 16)),
+        ]);
+        assert_eq!(changes[1].to_string(), "removed a.y (at line 3, column 3)");
+        assert_eq!(diff(&Document::new(), &Document::builder().node(Node::builder("b")).build())[0]
+            .to_string(), "added b");
+    }
+}
@@ -0,0 +1,934 @@
+use std::borrow::Borrow;
+use std::fmt::{self, Write};
+use std::time::Duration;
+
+use super::parser::ParseEvent;
+use super::types::{Document, Node, Value};
+use super::utils::{ident_body, ident_head};
+
+/// Test if `text` can be written without quotes, as a name, key or identifier value
+fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    chars.next().is_some_and(ident_head) && chars.all(ident_body)
+        && !matches!(text, "true" | "false" | "null" | "none" | "inf" | "nan")
+}
+
+/// Write a string literal, escaping anything that can't appear in one as it is
+pub fn write_string<W: Write>(f: &mut W, string: &str) -> fmt::Result {
+    write_string_with(f, string, EscapePolicy::Minimal)
+}
+
+fn write_string_with<W: Write>(f: &mut W, string: &str, escapes: EscapePolicy) -> fmt::Result {
+    if escapes == EscapePolicy::Raw && string.contains(['"', '\\'])
+            && !string.chars().any(char::is_control) {
+        return write_raw_string(f, string);
+    }
+    f.write_char('"')?;
+    for ch in string.chars() {
+        match ch {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            ch if ch.is_control() || (escapes == EscapePolicy::Ascii && !ch.is_ascii()) =>
+                write!(f, "\\u{{{:x}}}", ch as u32)?,
+            ch => f.write_char(ch)?,
+        }
+    }
+    f.write_char('"')
+}
+
+/// Write a string as a raw string, such as `r#"say "hi""#`.  A raw string is closed by a
+/// double quote and the same number of hashes that opened it, so it's opened with one
+/// more hash than follows any double quote in the string.
+fn write_raw_string<W: Write>(f: &mut W, string: &str) -> fmt::Result {
+    let hashes = string.match_indices('"')
+        .map(|(index, _)| string[index + 1..].chars().take_while(|&ch| ch == '#').count() + 1)
+        .max()
+        .unwrap_or(0);
+    let hashes = "#".repeat(hashes);
+    write!(f, "r{}\"{}\"{}", hashes, string, hashes)
+}
+
+/// Write a node name or key, quoting it unless it is an identifier
+pub fn write_name<W: Write>(f: &mut W, name: &str) -> fmt::Result {
+    if is_identifier(name) { f.write_str(name) } else { write_string(f, name) }
+}
+
+fn write_name_with<W: Write>(f: &mut W, name: &str, options: &FormatOptions) -> fmt::Result {
+    if is_identifier(name) && (options.escapes != EscapePolicy::Ascii || name.is_ascii()) {
+        f.write_str(name)
+    } else {
+        write_string_with(f, name, options.escapes)
+    }
+}
+
+/// Write an attribute or dict key, which is always quoted if the options say so
+fn write_key<W: Write>(f: &mut W, key: &str, options: &FormatOptions) -> fmt::Result {
+    if options.quote_keys {
+        write_string_with(f, key, options.escapes)
+    } else {
+        write_name_with(f, key, options)
+    }
+}
+
+/// Write a duration in units from days down to nanoseconds, such as `1h30m`
+fn write_duration<W: Write>(f: &mut W, duration: &Duration) -> fmt::Result {
+    const UNITS: [(&str, u64); 4] = [("d", 24 * 60 * 60), ("h", 60 * 60), ("m", 60), ("s", 1)];
+    const SUBSECOND_UNITS: [(&str, u32); 3] = [("ms", 1_000_000), ("us", 1_000), ("ns", 1)];
+
+    let (mut secs, mut nanos) = (duration.as_secs(), duration.subsec_nanos());
+    if secs == 0 && nanos == 0 {
+        return f.write_str("0s");
+    }
+    for &(unit, size) in &UNITS {
+        if secs >= size {
+            write!(f, "{}{}", secs / size, unit)?;
+            secs %= size;
+        }
+    }
+    for &(unit, size) in &SUBSECOND_UNITS {
+        if nanos >= size {
+            write!(f, "{}{}", nanos / size, unit)?;
+            nanos %= size;
+        }
+    }
+    Ok(())
+}
+
+/// Where the opening brace of a node is written
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum BraceStyle {
+    /// On the same line as the node's name (the default)
+    SameLine,
+    /// On a line of its own, lined up with the node's name
+    NextLine,
+}
+
+/// What is written between a key and its value
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum KeySeparator {
+    /// `key: value` (the default)
+    Colon,
+    /// `key = value`
+    Equals,
+}
+
+/// What is written after each attribute of a node
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Terminator {
+    /// A comma (the default)
+    Comma,
+    /// A semicolon, which can only be parsed back with `ParserOptions::semicolons`
+    Semicolon,
+    /// Nothing but the newline, which can only be parsed back with
+    /// `SeparatorPolicy::Newline` or `SeparatorPolicy::Optional`
+    Newline,
+}
+
+/// How strings, and names and keys that need quoting, are escaped
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum EscapePolicy {
+    /// Only escape what can't appear in a string as it is (the default)
+    Minimal,
+    /// Also escape every character that isn't ASCII, such as `"caf\u{e9}"`, for
+    /// consumers that can't read anything else
+    Ascii,
+    /// Write strings with double quotes or backslashes in them as raw strings, such as
+    /// `r"C:\Users"`, unless they have control characters in them
+    Raw,
+}
+
+/// Options controlling how documents, nodes and values are written out.  The default
+/// options give the same output as `Display`.
+///
+/// # Examples
+/// ```
+/// # use figtree::{Figtree, FormatOptions, BraceStyle, KeySeparator};
+/// let doc = Figtree::from_string("a { b = 1 } c { d = [1, 2, 3] }").parse().unwrap();
+/// let options = FormatOptions::new()
+///     .indent('\t', 1)
+///     .braces(BraceStyle::NextLine)
+///     .key_separator(KeySeparator::Equals)
+///     .blank_lines(1)
+///     .max_width(Some(10));
+/// assert_eq!(doc.to_string_with(&options), "\
+/// a
+/// {
+/// \tb = 1,
+/// }
+///
+/// c
+/// {
+/// \td = [
+/// \t\t1,
+/// \t\t2,
+/// \t\t3,
+/// \t],
+/// }
+/// ");
+/// ```
+#[derive(Debug, PartialEq, Clone)]
+pub struct FormatOptions {
+    /// The character that nodes are indented with
+    pub indent_char: char,
+    /// How many indent characters are written for each level of nesting
+    pub indent_width: usize,
+    /// Where the opening braces of nodes are written
+    pub braces: BraceStyle,
+    /// What is written between keys and values
+    pub key_separator: KeySeparator,
+    /// What is written after each attribute of a node
+    pub terminator: Terminator,
+    /// The number of blank lines between top-level nodes
+    pub blank_lines: usize,
+    /// The widest that a line with a list on it can be before the list is split over
+    /// several lines
+    pub max_width: Option<usize>,
+    /// Whether everything is written on one line, with as little whitespace as possible
+    pub compact: bool,
+    /// How strings are escaped
+    pub escapes: EscapePolicy,
+    /// Whether keys are quoted even when they are identifiers
+    pub quote_keys: bool,
+}
+
+impl FormatOptions {
+    /// Construct the default set of options
+    pub fn new() -> Self {
+        FormatOptions {
+            indent_char: ' ',
+            indent_width: 4,
+            braces: BraceStyle::SameLine,
+            key_separator: KeySeparator::Colon,
+            terminator: Terminator::Comma,
+            blank_lines: 0,
+            max_width: None,
+            compact: false,
+            escapes: EscapePolicy::Minimal,
+            quote_keys: false,
+        }
+    }
+
+    /// Indent each level of nesting with `width` copies of `ch`.  For example, `(' ', 2)`
+    /// indents by two spaces, and `('\t', 1)` by one tab.
+    pub fn indent(mut self, ch: char, width: usize) -> Self {
+        self.indent_char = ch;
+        self.indent_width = width;
+        self
+    }
+
+    /// Set where the opening braces of nodes are written.  Empty nodes are always
+    /// written as `name {}`.
+    pub fn braces(mut self, style: BraceStyle) -> Self {
+        self.braces = style;
+        self
+    }
+
+    /// Set what is written between keys and values, in both nodes and dicts
+    pub fn key_separator(mut self, separator: KeySeparator) -> Self {
+        self.key_separator = separator;
+        self
+    }
+
+    /// Set what is written after each attribute of a node.  The items of lists and the
+    /// entries of dicts are always separated by commas.
+    pub fn terminator(mut self, terminator: Terminator) -> Self {
+        self.terminator = terminator;
+        self
+    }
+
+    /// Set the number of blank lines written between top-level nodes
+    pub fn blank_lines(mut self, lines: usize) -> Self {
+        self.blank_lines = lines;
+        self
+    }
+
+    /// Split lists over several lines, one item per line, when writing them on one line
+    /// would make the line wider than `width`.  Every character of the indentation
+    /// counts as one column.  Lists inside dicts are always written on one line.
+    pub fn max_width(mut self, width: Option<usize>) -> Self {
+        self.max_width = width;
+        self
+    }
+
+    /// Write everything on one line, with no more whitespace than is needed to parse it
+    /// back again, such as `a{b:1,c:[1,2]}d{}`.  Only the key separator is used from the
+    /// other options.
+    ///
+    /// # Examples
+    /// ```
+    /// # use figtree::{Figtree, FormatOptions};
+    /// let input = "a { b = 1, c = { d = [1, 2] } e {} } f {}";
+    /// let doc = Figtree::from_string(input).parse().unwrap();
+    /// let compact = doc.to_string_with(&FormatOptions::new().compact(true));
+    /// assert_eq!(compact, "a{b:1,c:{d:[1,2]},e{}}f{}");
+    /// assert_eq!(Figtree::from_string(compact).parse().unwrap(), doc);
+    /// ```
+    pub fn compact(mut self, enabled: bool) -> Self {
+        self.compact = enabled;
+        self
+    }
+
+    /// Set how strings, and names and keys that need quoting, are escaped
+    ///
+    /// # Examples
+    /// ```
+    /// # use figtree::{Figtree, FormatOptions, EscapePolicy};
+    /// let doc = Figtree::from_string(r"café { path = 'C:\\Users' }").parse().unwrap();
+    /// let ascii = FormatOptions::new().escapes(EscapePolicy::Ascii);
+    /// assert_eq!(doc.to_string_with(&ascii), "\"caf\\u{e9}\" {\n    path: \"C:\\\\Users\",\n}\n");
+    /// let raw = FormatOptions::new().escapes(EscapePolicy::Raw);
+    /// assert_eq!(doc.to_string_with(&raw), "café {\n    path: r\"C:\\Users\",\n}\n");
+    /// ```
+    pub fn escapes(mut self, policy: EscapePolicy) -> Self {
+        self.escapes = policy;
+        self
+    }
+
+    /// Quote every key of every node and dict, even those that could be written without
+    /// quotes.  Node names are only quoted when they need to be.
+    pub fn quote_keys(mut self, enabled: bool) -> Self {
+        self.quote_keys = enabled;
+        self
+    }
+
+    fn separator(&self) -> &'static str {
+        match (self.key_separator, self.compact) {
+            (KeySeparator::Colon, false) => ": ",
+            (KeySeparator::Equals, false) => " = ",
+            (KeySeparator::Colon, true) => ":",
+            (KeySeparator::Equals, true) => "=",
+        }
+    }
+
+    fn item_separator(&self) -> &'static str {
+        if self.compact { "," } else { ", " }
+    }
+
+    fn terminator_str(&self) -> &'static str {
+        match self.terminator {
+            Terminator::Comma => ",",
+            Terminator::Semicolon => ";",
+            Terminator::Newline => "",
+        }
+    }
+
+    fn write_indent<W: Write>(&self, f: &mut W, level: usize) -> fmt::Result {
+        for _ in 0..level * self.indent_width {
+            f.write_char(self.indent_char)?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Write a value on one line, with the default options
+pub fn write_value<W: Write>(f: &mut W, value: &Value) -> fmt::Result {
+    write_inline(f, value, &FormatOptions::new())
+}
+
+/// Write a value on one line
+fn write_inline<W: Write>(f: &mut W, value: &Value, options: &FormatOptions) -> fmt::Result {
+    match *value {
+        Value::Str(ref string) => write_string_with(f, string, options.escapes),
+        Value::Int(int) => write!(f, "{}", int),
+        Value::Float(float) if float.is_nan() => f.write_str("nan"),
+        Value::Float(float) if float.is_infinite() =>
+            f.write_str(if float > 0.0 { "inf" } else { "-inf" }),
+        // `{:?}` always includes a decimal point or exponent, so the float is read back
+        // as a float, and is precise enough that it's read back exactly
+        Value::Float(float) => write!(f, "{:?}", float),
+        Value::Bool(boolean) => write!(f, "{}", boolean),
+        Value::Ident(ref ident) if is_identifier(ident)
+                && (options.escapes != EscapePolicy::Ascii || ident.is_ascii()) =>
+            write!(f, "!{}", ident),
+        Value::Ident(ref ident) if options.escapes == EscapePolicy::Ascii => {
+            f.write_str("!`")?;
+            for ch in ident.chars() {
+                match ch {
+                    '`' => f.write_str("\\`")?,
+                    '\\' => f.write_str("\\\\")?,
+                    ch if !ch.is_ascii() => write!(f, "\\u{{{:x}}}", ch as u32)?,
+                    ch => f.write_char(ch)?,
+                }
+            }
+            f.write_char('`')
+        },
+        Value::Ident(ref ident) => write!(f, "!`{}`", ident),
+        Value::DateTime(ref datetime) => write!(f, "{}", datetime),
+        Value::Duration(ref duration) => write_duration(f, duration),
+        Value::Bytes(ref bytes) => {
+            f.write_str("b\"")?;
+            for &byte in bytes {
+                match byte {
+                    b'"' | b'\\' => write!(f, "\\x{:02x}", byte)?,
+                    0x20..=0x7e => f.write_char(byte as char)?,
+                    _ => write!(f, "\\x{:02x}", byte)?,
+                }
+            }
+            f.write_char('"')
+        },
+        Value::List(ref items) => {
+            f.write_char('[')?;
+            for (index, item) in items.iter().enumerate() {
+                if index > 0 {
+                    f.write_str(options.item_separator())?;
+                }
+                write_inline(f, item, options)?;
+            }
+            f.write_char(']')
+        },
+        Value::Dict(ref dict) => {
+            if dict.is_empty() {
+                return f.write_str("{}");
+            }
+            // dicts don't keep their order, so keys are written in sorted order
+            let mut entries: Vec<_> = dict.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            f.write_str(if options.compact { "{" } else { "{ " })?;
+            for (index, (key, value)) in entries.into_iter().enumerate() {
+                if index > 0 {
+                    f.write_str(options.item_separator())?;
+                }
+                write_key(f, key, options)?;
+                f.write_str(options.separator())?;
+                write_inline(f, value, options)?;
+            }
+            f.write_str(if options.compact { "}" } else { " }" })
+        },
+        Value::Node(ref node) => write_inline(f, &Value::Dict(node.to_dict()), options),
+        Value::Null => f.write_str("null"),
+    }
+}
+
+/// Write a value that starts at `column` of a line nested `level` deep, and is followed
+/// by `trailing` more characters, splitting lists that would make the line too wide
+fn write_value_at<W: Write>(f: &mut W, value: &Value, options: &FormatOptions, level: usize,
+                            column: usize, trailing: usize) -> fmt::Result {
+    let (items, width) = match (value, options.max_width) {
+        (Value::List(items), Some(width)) if !items.is_empty() && !options.compact =>
+            (items, width),
+        _ => return write_inline(f, value, options),
+    };
+    let mut inline = String::new();
+    write_inline(&mut inline, value, options)?;
+    if column + inline.chars().count() + trailing <= width {
+        return f.write_str(&inline);
+    }
+
+    f.write_str("[\n")?;
+    let item_column = (level + 1) * options.indent_width;
+    for item in items {
+        options.write_indent(f, level + 1)?;
+        write_value_at(f, item, options, level + 1, item_column, 1)?;
+        f.write_str(",\n")?;
+    }
+    options.write_indent(f, level)?;
+    f.write_char(']')
+}
+
+fn write_node<W: Write>(f: &mut W, node: &Node, options: &FormatOptions, level: usize)
+                        -> fmt::Result {
+    write_name_with(f, node.name(), options)?;
+    for argument in node.arguments() {
+        f.write_char(' ')?;
+        write_inline(f, argument, options)?;
+    }
+    if options.compact {
+        return write_compact_body(f, node, options);
+    }
+    if node.properties().len() == 0 && node.children().len() == 0 {
+        return f.write_str(" {}");
+    }
+
+    match options.braces {
+        BraceStyle::SameLine => f.write_str(" {\n")?,
+        BraceStyle::NextLine => {
+            f.write_char('\n')?;
+            options.write_indent(f, level)?;
+            f.write_str("{\n")?;
+        },
+    }
+    let terminator = options.terminator_str();
+    for (key, value) in node.properties() {
+        let mut start = String::new();
+        options.write_indent(&mut start, level + 1)?;
+        write_key(&mut start, key, options)?;
+        start.push_str(options.separator());
+        f.write_str(&start)?;
+        write_value_at(f, value, options, level + 1, start.chars().count(), terminator.len())?;
+        f.write_str(terminator)?;
+        f.write_char('\n')?;
+    }
+    for child in node.children() {
+        options.write_indent(f, level + 1)?;
+        write_node(f, child, options, level + 1)?;
+        f.write_char('\n')?;
+    }
+    options.write_indent(f, level)?;
+    f.write_char('}')
+}
+
+/// Write the body of a node on one line.  Commas are only needed between an attribute
+/// and whatever follows it.
+fn write_compact_body<W: Write>(f: &mut W, node: &Node, options: &FormatOptions) -> fmt::Result {
+    f.write_char('{')?;
+    let mut remaining = node.properties().len() + node.children().len();
+    for (key, value) in node.properties() {
+        write_key(f, key, options)?;
+        f.write_str(options.separator())?;
+        write_inline(f, value, options)?;
+        remaining -= 1;
+        if remaining > 0 {
+            f.write_char(',')?;
+        }
+    }
+    for child in node.children() {
+        write_node(f, child, options, 0)?;
+    }
+    f.write_char('}')
+}
+
+fn write_document<W: Write>(f: &mut W, doc: &Document, options: &FormatOptions) -> fmt::Result {
+    if options.compact {
+        return doc.children().try_for_each(|node| write_node(f, node, options, 0));
+    }
+    for (index, node) in doc.children().enumerate() {
+        if index > 0 {
+            for _ in 0..options.blank_lines {
+                f.write_char('\n')?;
+            }
+        }
+        write_node(f, node, options, 0)?;
+        f.write_char('\n')?;
+    }
+    Ok(())
+}
+
+/// Sort the attributes and subnodes of a node, all the way down, and write negative zero
+/// as zero.  Dicts are always written in sorted order already.
+fn canonicalize(node: &mut Node) {
+    fn normalize(value: &mut Value) {
+        match *value {
+            Value::Float(ref mut float) if *float == 0.0 => *float = 0.0,
+            Value::List(ref mut items) => items.iter_mut().for_each(normalize),
+            Value::Dict(ref mut dict) => dict.values_mut().for_each(normalize),
+            _ => {},
+        }
+    }
+    node.sort_properties();
+    node.sort_children();
+    node.arguments_mut().iter_mut().for_each(normalize);
+    node.properties_mut().for_each(|(_, value)| normalize(value));
+    node.children_mut().for_each(canonicalize);
+}
+
+impl Value {
+    /// Write the value out with the given options.  See `FormatOptions`.
+    pub fn to_string_with(&self, options: &FormatOptions) -> String {
+        let mut string = String::new();
+        let _ = write_value_at(&mut string, self, options, 0, 0, 0);
+        string
+    }
+}
+
+impl Node {
+    /// Write the node out with the given options.  See `FormatOptions`.
+    pub fn to_string_with(&self, options: &FormatOptions) -> String {
+        let mut string = String::new();
+        let _ = write_node(&mut string, self, options, 0);
+        string
+    }
+}
+
+impl Document {
+    /// Write the document out with the given options.  See `FormatOptions`.
+    pub fn to_string_with(&self, options: &FormatOptions) -> String {
+        let mut string = String::new();
+        let _ = write_document(&mut string, self, options);
+        string
+    }
+
+    /// Write the document out in canonical form, so that any two documents that mean the
+    /// same thing are written out in exactly the same way.  This is useful for hashing or
+    /// signing documents, where the layout they happened to be written in shouldn't
+    /// matter.
+    ///
+    /// The canonical form is the compact form (see `to_compact_string`), with attributes
+    /// and dict entries sorted by key, and nodes sorted by name.  Nodes with the same name
+    /// keep their order, since it's part of what the document means.  Numbers and strings
+    /// are always written in the same way whatever way they were read, with negative zero
+    /// written as zero.
+    ///
+    /// # Examples
+    /// ```
+    /// # use figtree::{Figtree, ParserOptions};
+    /// let first = "b { y = 0x10, x = 'caf\\u{e9}' } a { z = [1e2, -0.0] }";
+    /// let second = "a{z:[100.0, 0.0]}\n// b\nb{x:\"café\",y:16,}";
+    /// let first = Figtree::from_string(first).parse().unwrap().to_canonical_string();
+    /// let second = Figtree::from_string(second).parse().unwrap().to_canonical_string();
+    /// assert_eq!(first, "a{z:[100.0,0.0]}b{x:\"café\",y:16}");
+    /// assert_eq!(first, second);
+    /// ```
+    pub fn to_canonical_string(&self) -> String {
+        let mut canonical = self.clone();
+        canonical.sort_children();
+        canonical.children_mut().for_each(canonicalize);
+        canonical.to_compact_string()
+    }
+
+    /// Write the document out on a single line, with as little whitespace as possible.
+    /// This is the same as `to_string_with` with `FormatOptions::compact`.
+    pub fn to_compact_string(&self) -> String {
+        self.to_string_with(&FormatOptions::new().compact(true))
+    }
+}
+
+/// Values are written as they would be in a document, so that they can be parsed back
+/// again.  The keys of dicts are written in sorted order.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_value(f, self)
+    }
+}
+
+/// Nodes are written as they would be in a document, indented by four spaces for each
+/// level of nesting.  Nodes with arguments can only be parsed back with
+/// `ParserOptions::node_arguments`.
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_node(f, self, &FormatOptions::new(), 0)
+    }
+}
+
+/// Documents are written with each top-level node on its own line, so that they can be
+/// parsed back again.  Comments and the original formatting aren't kept.
+///
+/// # Examples
+/// ```
+/// # use figtree::types::*;
+/// # use std::io::Cursor;
+/// let doc = Document::parse(Cursor::new("server{host='a',ports=[80,443] tls{}}")).unwrap();
+/// assert_eq!(doc.to_string(), "\
+/// server {
+///     host: \"a\",
+///     ports: [80, 443],
+///     tls {}
+/// }
+/// ");
+/// assert_eq!(Document::parse(Cursor::new(doc.to_string())).unwrap(), doc);
+/// ```
+impl fmt::Display for Document {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_document(f, self, &FormatOptions::new())
+    }
+}
+
+/// Events are written as the name of the event, followed by its key, name or value, such
+/// as `Key port` or `Value 8080`.  Keys and names are quoted if they were written as
+/// string literals.
+impl fmt::Display for ParseEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseEvent::FileStart => f.write_str("FileStart"),
+            ParseEvent::FileEnd => f.write_str("FileEnd"),
+            ParseEvent::NodeStart(ref name, quoted) => {
+                f.write_str("NodeStart ")?;
+                if quoted { write_string(f, name) } else { f.write_str(name) }
+            },
+            ParseEvent::NodeEnd => f.write_str("NodeEnd"),
+            ParseEvent::Argument(ref value) => {
+                f.write_str("Argument ")?;
+                write_value(f, &Value::from_parsed_value(value.clone()))
+            },
+            ParseEvent::Key(ref key, quoted) => {
+                f.write_str("Key ")?;
+                if quoted { write_string(f, key) } else { f.write_str(key) }
+            },
+            ParseEvent::Value(ref value) => {
+                f.write_str("Value ")?;
+                write_value(f, &Value::from_parsed_value(value.clone()))
+            },
+            ParseEvent::ListStart => f.write_str("ListStart"),
+            ParseEvent::ListEnd => f.write_str("ListEnd"),
+            ParseEvent::DictStart => f.write_str("DictStart"),
+            ParseEvent::DictEnd => f.write_str("DictEnd"),
+        }
+    }
+}
+
+/// Write out a stream of events one per line, indented by how deeply nested each event
+/// is.  This is much easier to read than the `Debug` output of the events, such as when
+/// comparing the events of a failing test.
+///
+/// # Examples
+/// ```
+/// # use figtree::{dump_events, Parser};
+/// let events: Vec<_> = Parser::from_bytes(b"a { b = [1, 'c'] }")
+///     .map(|result| result.unwrap().0)
+///     .collect();
+/// assert_eq!(dump_events(&events), "\
+/// FileStart
+///   NodeStart a
+///     Key b
+///     ListStart
+///       Value 1
+///       Value \"c\"
+///     ListEnd
+///   NodeEnd
+/// FileEnd
+/// ");
+/// ```
+pub fn dump_events<I>(events: I) -> String
+        where I: IntoIterator, I::Item: Borrow<ParseEvent> {
+    let mut dump = String::new();
+    let mut level = 0usize;
+    for event in events {
+        let event = event.borrow();
+        if let ParseEvent::NodeEnd | ParseEvent::ListEnd | ParseEvent::DictEnd
+                | ParseEvent::FileEnd = *event {
+            level = level.saturating_sub(1);
+        }
+        let _ = writeln!(dump, "{:width$}{}", "", event, width = level * 2);
+        if let ParseEvent::FileStart | ParseEvent::NodeStart(..) | ParseEvent::ListStart
+                | ParseEvent::DictStart = *event {
+            level += 1;
+        }
+    }
+    dump
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::figtree::Figtree;
+    use super::super::parser::{Parser, ParserOptions, SeparatorPolicy};
+
+    fn parse(input: &str) -> Document {
+        let options = ParserOptions::new().repeated_nodes(true).node_arguments(true);
+        Figtree::from_string(input).with_options(options).parse().unwrap()
+    }
+
+    #[test]
+    fn writes_values() {
+        let values = [
+            (Value::new_string("a \"quoted\"\\\n\tline\u{7}"), r#""a \"quoted\"\\\n\tline\u{7}""#),
+            (Value::new_int(-12), "-12"),
+            (Value::new_float(5.0), "5.0"),
+            (Value::new_float(1e100), "1e100"),
+            (Value::new_float(f64::NEG_INFINITY), "-inf"),
+            (Value::new_bool(false), "false"),
+            (Value::new_ident("name"), "!name"),
+            (Value::new_ident("two words"), "!`two words`"),
+            (Value::new_datetime("2024-01-02T03:04:05Z".parse().unwrap()), "2024-01-02T03:04:05Z"),
+            (Value::new_duration(Duration::new(5400, 1_500_000)), "1h30m1ms500us"),
+            (Value::new_duration(Duration::new(0, 0)), "0s"),
+            (Value::new_bytes(vec![0, b'a', b'"', 0xff]), r#"b"\x00a\x22\xff""#),
+            (Value::List(vec![Value::new_int(1), Value::List(vec![])]), "[1, []]"),
+            (Value::new_null(), "null"),
+        ];
+        for &(ref value, expected) in &values {
+            assert_eq!(value.to_string(), expected);
+        }
+
+        let doc = parse("a { d = { z = 1, 'two words' = 2, true = 3, e = {} } }");
+        assert_eq!(doc["a"]["d"].to_string(), r#"{ e: {}, "true": 3, "two words": 2, z: 1 }"#);
+        assert_eq!(Value::new_node(doc["a"].clone()).to_string(),
+                   r#"{ d: { e: {}, "true": 3, "two words": 2, z: 1 } }"#);
+    }
+
+    #[test]
+    fn round_trips() {
+        let input = "
+            server 'main' 80 {
+                host = 'localhost', 'odd key' = [1, 2.5, nan, !id, null], wait = 1m30s,
+                when = 2024-01-01T00:00:00Z, data = b'\\x00\\xff', env = { PATH = '/bin' }
+                upstream { weight = 1 }
+                upstream { weight = -inf }
+                'odd node' {}
+            }
+            r {}
+        ";
+        let doc = parse(input);
+        assert!(parse(&doc.to_string()).structurally_eq(&doc), "{}", doc);
+        assert_eq!(doc["r"].to_string(), "r {}");
+        assert_eq!(doc["server"][0].to_string(), "upstream {\n    weight: 1,\n}");
+        assert_eq!(Document::new().to_string(), "");
+    }
+
+    #[test]
+    fn formats_with_options() {
+        let doc = parse("a 1 { b = [1, [2, 3]], c = { d = 1 } e { f = 'g' } } h {} i { j = [] }");
+        assert_eq!(doc.to_string_with(&FormatOptions::new()), doc.to_string());
+
+        let options = FormatOptions::new()
+            .indent(' ', 2)
+            .key_separator(KeySeparator::Equals)
+            .terminator(Terminator::Semicolon)
+            .blank_lines(2);
+        assert_eq!(doc.to_string_with(&options), "\
+a 1 {
+  b = [1, [2, 3]];
+  c = { d = 1 };
+  e {
+    f = \"g\";
+  }
+}
+
+
+h {}
+
+
+i {
+  j = [];
+}
+");
+        let semicolons = ParserOptions::new().node_arguments(true).semicolons(true);
+        let mut reparsed = Figtree::from_string(doc.to_string_with(&options)).with_options(semicolons);
+        assert!(reparsed.parse().unwrap().structurally_eq(&doc));
+
+        let options = FormatOptions::new()
+            .braces(BraceStyle::NextLine)
+            .terminator(Terminator::Newline)
+            .max_width(Some(16));
+        assert_eq!(doc["a"].to_string_with(&options), "\
+a 1
+{
+    b: [
+        1,
+        [2, 3],
+    ]
+    c: { d: 1 }
+    e
+    {
+        f: \"g\"
+    }
+}");
+        let newlines = ParserOptions::new().node_arguments(true).separators(SeparatorPolicy::Newline);
+        let mut reparsed = Figtree::from_string(doc.to_string_with(&options)).with_options(newlines);
+        assert!(reparsed.parse().unwrap().structurally_eq(&doc));
+
+        let list = Value::List(vec![Value::new_int(100), Value::new_int(200)]);
+        assert_eq!(list.to_string_with(&options.clone().max_width(Some(10))), "[100, 200]");
+        assert_eq!(list.to_string_with(&options.max_width(Some(9))), "[\n    100,\n    200,\n]");
+    }
+
+    #[test]
+    fn writes_compactly() {
+        let input = "
+            server 'main' 80 {
+                host = 'a b', ports = [80, 443], env = { PATH = '/bin', HOME = '~' }
+                upstream { weight = 1 }
+                upstream 2 {}
+                tls {}
+            }
+            r {}
+        ";
+        let doc = parse(input);
+        let compact = doc.to_compact_string();
+        assert_eq!(compact, "server \"main\" 80{host:\"a b\",ports:[80,443],env:{HOME:\"~\",PATH:\"/bin\"},\
+            upstream{weight:1}upstream 2{}tls{}}r{}");
+        assert!(parse(&compact).structurally_eq(&doc));
+
+        let options = FormatOptions::new().compact(true).key_separator(KeySeparator::Equals)
+            .blank_lines(1).max_width(Some(1));
+        assert_eq!(doc["server"][0].to_string_with(&options), "upstream{weight=1}");
+        assert_eq!(doc["server"]["ports"].to_string_with(&options), "[80,443]");
+        assert_eq!(Document::new().to_compact_string(), "");
+    }
+
+    #[test]
+    fn escapes_strings() {
+        let input = r###"
+            café 'é\t' {
+                plain = 'a b', path = r"C:\Users", quote = 'say "hi"', hash = r##"a "# b"##,
+                tab = 'a\t"b"', idents = [!élan, !`a b`], 'ünï' = { 'x y' = 1 }
+                "quoted-ʞ" {}
+            }
+        "###;
+        let doc = parse(input);
+
+        let minimal = doc.to_string_with(&FormatOptions::new().compact(true));
+        assert_eq!(minimal, concat!(r###"café "é\t"{plain:"a b",path:"C:\\Users","###,
+            r###"quote:"say \"hi\"",hash:"a \"# b""###,
+            r###",tab:"a\t\"b\"",idents:[!élan,!`a b`],ünï:{"x y":1},"quoted-ʞ"{}}"###));
+
+        let options = FormatOptions::new().compact(true).escapes(EscapePolicy::Ascii);
+        let ascii = doc.to_string_with(&options);
+        assert_eq!(ascii, concat!(r###""caf\u{e9}" "\u{e9}\t"{plain:"a b",path:"C:\\Users","###,
+            r###"quote:"say \"hi\"",hash:"a \"# b""###,
+            r###",tab:"a\t\"b\"",idents:[!`\u{e9}lan`,!`a b`],"###,
+            r###""\u{fc}n\u{ef}":{"x y":1},"quoted-\u{29e}"{}}"###));
+        assert!(ascii.is_ascii());
+        assert!(parse(&ascii).structurally_eq(&doc));
+
+        let options = FormatOptions::new().compact(true).escapes(EscapePolicy::Raw);
+        let raw = doc.to_string_with(&options);
+        assert_eq!(raw, concat!(r###"café "é\t"{plain:"a b",path:r"C:\Users","###,
+            r###"quote:r#"say "hi""#,hash:r##"a "# b"##"###,
+            r###",tab:"a\t\"b\"",idents:[!élan,!`a b`],ünï:{"x y":1},"quoted-ʞ"{}}"###));
+        assert!(parse(&raw).structurally_eq(&doc));
+
+        let options = FormatOptions::new().quote_keys(true).escapes(EscapePolicy::Raw);
+        assert_eq!(doc["café"][0].to_string_with(&options), "\"quoted-ʞ\" {}");
+        let quoted = doc.to_string_with(&options);
+        assert!(quoted.contains("\n    \"plain\": \"a b\",\n    \"path\": r\"C:\\Users\",\n"));
+        assert!(quoted.contains("\n    \"ünï\": { \"x y\": 1 },\n"));
+        assert!(parse(&quoted).structurally_eq(&doc));
+    }
+
+    #[test]
+    fn writes_canonically() {
+        let first = parse("
+            up 1 { weight = 1, host = 'a' }
+            down { x = { b = [0x1F, -0.0, 2.5e0], a = 'tab\\tand\\u0041' } }
+            up -0.0 { host = 'b' }
+        ");
+        let second = parse("down{x:{a:\"tab\tandA\",b:[31,0.0,2.5]}}up 1{host:'a',weight:1}up 0.0{host:'b'}");
+        assert!(first.structurally_eq(&second));
+        assert_eq!(first.to_canonical_string(), second.to_canonical_string());
+        assert_eq!(first.to_canonical_string(), "\
+            down{x:{a:\"tab\\tandA\",b:[31,0.0,2.5]}}up 1{host:\"a\",weight:1}up 0.0{host:\"b\"}");
+
+        let swapped = parse("up 0.0 { host = 'b' } up 1 { host = 'a', weight = 1 } down {}");
+        assert_ne!(swapped.to_canonical_string(), first.to_canonical_string());
+        assert!(parse(&first.to_canonical_string()).structurally_eq(&first));
+    }
+
+    #[test]
+    fn dumps_events() {
+        let input = "'a b' 1 { x = { \"k y\" = [] }, z = null } c {}";
+        let options = ParserOptions::new().node_arguments(true);
+        let mut parser = Parser::from_bytes(input.as_bytes());
+        parser.set_options(options);
+        let events: Vec<_> = parser.map(|result| result.unwrap()).collect();
+        assert_eq!(events[1].0.to_string(), "NodeStart \"a b\"");
+        assert_eq!(events[2].0.to_string(), "Argument 1");
+        assert_eq!(dump_events(events.iter().map(|(event, _)| event)), "\
+FileStart
+  NodeStart \"a b\"
+    Argument 1
+    Key x
+    DictStart
+      Key \"k y\"
+      ListStart
+      ListEnd
+    DictEnd
+    Key z
+    Value null
+  NodeEnd
+  NodeStart c
+  NodeEnd
+FileEnd
+");
+        assert_eq!(dump_events(vec![ParseEvent::NodeEnd, ParseEvent::Key("k".into(), false)]),
+                   "NodeEnd\nKey k\n");
+    }
+}
@@ -0,0 +1,193 @@
+use std::fmt::Write;
+
+use super::display::write_value;
+use super::types::{Document, Node, Value};
+
+/// Options controlling what `Document::to_dot_with_options` includes in each node's label
+///
+/// # Examples
+/// ```
+/// # use figtree::{DotOptions, Figtree};
+/// let doc = Figtree::from_string("server { port = 80 }").parse().unwrap();
+/// let dot = doc.to_dot_with_options(&DotOptions::new().attributes(true));
+/// assert!(dot.contains(r#"n0 [label="server\lport = 80\l"];"#));
+/// ```
+#[derive(Debug, PartialEq, Clone)]
+pub struct DotOptions {
+    /// The name of the graph
+    pub name: String,
+    /// Whether each node's arguments are written after its name
+    pub arguments: bool,
+    /// Whether each node's attributes are written below its name, one to a line
+    pub attributes: bool,
+}
+
+impl DotOptions {
+    /// Construct the default set of options, which label each node with its name and
+    /// arguments
+    pub fn new() -> Self {
+        DotOptions {
+            name: "figtree".into(),
+            arguments: true,
+            attributes: false,
+        }
+    }
+
+    /// Set the name of the graph (`figtree` by default)
+    pub fn name<S: Into<String>>(mut self, name: S) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// Write each node's arguments after its name (the default)
+    pub fn arguments(mut self, enabled: bool) -> Self {
+        self.arguments = enabled;
+        self
+    }
+
+    /// Write each node's attributes below its name, as `key = value`
+    pub fn attributes(mut self, enabled: bool) -> Self {
+        self.attributes = enabled;
+        self
+    }
+}
+
+impl Default for DotOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Escape text for a quoted DOT string
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {},
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn value_text(value: &Value) -> String {
+    let mut text = String::new();
+    let _ = write_value(&mut text, value);
+    text
+}
+
+/// Write a node's label, its subnodes and the edges to them, numbering each node in the
+/// order it's written
+fn write_node(out: &mut String, node: &Node, options: &DotOptions, next: &mut usize) -> usize {
+    let id = *next;
+    *next += 1;
+    let mut label = node.name().to_string();
+    if options.arguments {
+        for argument in node.arguments() {
+            label.push(' ');
+            label.push_str(&value_text(argument));
+        }
+    }
+    let mut label = escape(&label);
+    if options.attributes && node.iter_attrs().next().is_some() {
+        // every line is left-justified, so the attributes line up under the name
+        label.push_str("\\l");
+        for (key, value) in node.iter_attrs() {
+            label.push_str(&escape(&format!("{} = {}", key, value_text(value))));
+            label.push_str("\\l");
+        }
+    }
+    let _ = writeln!(out, "    n{} [label=\"{}\"];", id, label);
+    for child in node.children() {
+        let child_id = write_node(out, child, options, next);
+        let _ = writeln!(out, "    n{} -> n{};", id, child_id);
+    }
+    id
+}
+
+impl Document {
+    /// Write the node hierarchy of the document as a Graphviz DOT graph, with each node
+    /// labelled with its name and arguments and an edge to each of its subnodes.  The
+    /// top-level nodes are the roots of the graph.
+    ///
+    /// # Examples
+    /// ```
+    /// # use figtree::{Figtree, ParserOptions};
+    /// let doc = Figtree::from_string("server { tls { } upstream 'a' { } }")
+    ///     .with_options(ParserOptions::new().node_arguments(true)).parse().unwrap();
+    /// assert_eq!(doc.to_dot(), concat!(
+    ///     "digraph \"figtree\" {\n",
+    ///     "    node [shape=box];\n",
+    ///     "    n0 [label=\"server\"];\n",
+    ///     "    n1 [label=\"tls\"];\n",
+    ///     "    n0 -> n1;\n",
+    ///     "    n2 [label=\"upstream \\\"a\\\"\"];\n",
+    ///     "    n0 -> n2;\n",
+    ///     "}\n",
+    /// ));
+    /// ```
+    pub fn to_dot(&self) -> String {
+        self.to_dot_with_options(&DotOptions::new())
+    }
+
+    /// Write the document as a Graphviz DOT graph, labelling each node as described by
+    /// `options`
+    pub fn to_dot_with_options(&self, options: &DotOptions) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "digraph \"{}\" {{", escape(&options.name));
+        out.push_str("    node [shape=box];\n");
+        let mut next = 0;
+        for node in self.children() {
+            write_node(&mut out, node, options, &mut next);
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::figtree::Figtree;
+    use super::super::parser::ParserOptions;
+    use super::*;
+
+    fn parse(input: &str) -> Document {
+        let options = ParserOptions::new().node_arguments(true).repeated_nodes(true);
+        Figtree::from_string(input).with_options(options).parse().unwrap()
+    }
+
+    #[test]
+    fn writes_hierarchy() {
+        let doc = parse("a { b { c {} } d {} } e 1 'x\"y' {}");
+        assert_eq!(doc.to_dot(), concat!(
+            "digraph \"figtree\" {\n",
+            "    node [shape=box];\n",
+            "    n0 [label=\"a\"];\n",
+            "    n1 [label=\"b\"];\n",
+            "    n2 [label=\"c\"];\n",
+            "    n1 -> n2;\n",
+            "    n0 -> n1;\n",
+            "    n3 [label=\"d\"];\n",
+            "    n0 -> n3;\n",
+            "    n4 [label=\"e 1 \\\"x\\\\\\\"y\\\"\"];\n",
+            "}\n",
+        ));
+        assert_eq!(Document::new().to_dot(), "digraph \"figtree\" {\n    node [shape=box];\n}\n");
+    }
+
+    #[test]
+    fn writes_attributes() {
+        let doc = parse("server 'web' { port = 80, hosts = ['a', 'b'] } tls {}");
+        let options = DotOptions::new().name("config").arguments(false).attributes(true);
+        assert_eq!(doc.to_dot_with_options(&options), concat!(
+            "digraph \"config\" {\n",
+            "    node [shape=box];\n",
+            "    n0 [label=\"server\\lport = 80\\lhosts = [\\\"a\\\", \\\"b\\\"]\\l\"];\n",
+            "    n1 [label=\"tls\"];\n",
+            "}\n",
+        ));
+    }
+}
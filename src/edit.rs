@@ -0,0 +1,596 @@
+use std::fmt;
+use std::io::Cursor;
+use std::ops::Range;
+
+use super::display::write_name;
+use super::figtree::Figtree;
+use super::lexer::Lexer;
+use super::parser::{Parser, ParseEvent, ParseError, ParserOptions, SeparatorPolicy};
+use super::patch::PatchError;
+use super::path::{segments, split_last};
+use super::position::{Position, Span};
+use super::types::{Document, Value};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Kind {
+    Node,
+    /// An attribute, dict entry or list item whose value isn't a list or a dict
+    Value,
+    List,
+    Dict,
+}
+
+/// Something found in the source: a node, an attribute, a dict entry or a list item
+#[derive(Debug)]
+struct Entry {
+    kind: Kind,
+    /// The name of the node, or the key of the attribute or dict entry
+    name: Option<String>,
+    /// The character between the key and the value
+    separator: Option<char>,
+    /// Where the entry starts, at its name or key, or for a list item at its value
+    start: usize,
+    /// Where the value starts and ends.  For nodes this starts at the opening brace, and
+    /// for the document it covers the whole input.
+    value: Range<usize>,
+    /// The entries inside a node, dict or list, in the order they were written
+    entries: Vec<Entry>,
+}
+
+impl Entry {
+    fn container(kind: Kind, name: Option<String>, separator: Option<char>, start: usize) -> Self {
+        Entry { kind, name, separator, start, value: start..start, entries: Vec::new() }
+    }
+
+    fn is_named(&self, name: &str) -> bool {
+        self.name.as_ref().is_some_and(|own| own == name)
+    }
+
+    /// Find the entry at a path, in the same way as `Document::get_path`, except that the
+    /// path can also lead to a node
+    fn find(&self, path: &str) -> Option<&Entry> {
+        let mut entry = self;
+        for segment in segments(path)? {
+            let name = &*segment.name;
+            let mut indexes = segment.indexes.iter();
+            entry = match entry.kind {
+                Kind::Node => {
+                    let mut nodes = entry.entries.iter()
+                        .filter(|child| child.kind == Kind::Node && child.is_named(name));
+                    // with `DuplicateKeyPolicy::LastWins`, the last attribute is the one kept
+                    let attr = entry.entries.iter().rev()
+                        .find(|child| child.kind != Kind::Node && child.is_named(name));
+                    match attr {
+                        Some(attr) if nodes.clone().next().is_none() => attr,
+                        _ => nodes.nth(indexes.next().cloned().unwrap_or(0))?,
+                    }
+                },
+                Kind::Dict => entry.entries.iter().rev().find(|child| child.is_named(name))?,
+                Kind::Value | Kind::List => return None,
+            };
+            for &index in indexes {
+                entry = match entry.kind {
+                    Kind::List => entry.entries.get(index)?,
+                    _ => return None,
+                };
+            }
+        }
+        Some(entry)
+    }
+}
+
+/// A document that can be edited without losing its formatting.
+///
+/// Parsing a document normally throws away its comments and layout, so writing it back
+/// out again rewrites the whole file.  An editable document keeps the input as it was
+/// written instead, and each edit only rewrites the part of it that changes: replacing a
+/// value only rewrites that value, and removing an entry only removes that entry.  Every
+/// other byte, including comments, whitespace, and the choice of separators, is written
+/// out exactly as it was read.  This makes it suitable for tools that update config files
+/// that are also maintained by hand.
+///
+/// New attributes are written in the style of the entries around them, and new values
+/// are written in the same way as `Value`'s `Display` output.  The edited document can be
+/// read through `document`.
+///
+/// # Examples
+/// ```
+/// # use figtree::{EditableDocument, Value};
+/// let input = "\
+/// server {
+///     // the public port
+///     port = 80,
+///     host = 'localhost',  // for now
+/// }
+/// ";
+/// let mut doc = EditableDocument::parse(input).unwrap();
+/// doc.set_path("server.port", Value::new_int(8080)).unwrap();
+/// doc.set_path("server.tls", Value::new_bool(true)).unwrap();
+/// assert_eq!(doc.to_string(), "\
+/// server {
+///     // the public port
+///     port = 8080,
+///     host = 'localhost',  // for now
+///     tls = true,
+/// }
+/// ");
+/// assert_eq!(doc.document()["server"].get_i64("port"), Ok(8080));
+/// ```
+#[derive(Debug)]
+pub struct EditableDocument {
+    source: String,
+    options: ParserOptions,
+    document: Document,
+    root: Entry,
+}
+
+impl EditableDocument {
+    /// Parse a document for editing, with the default parser options
+    ///
+    /// # Failures
+    /// Fails in the same way as `Figtree::parse`.
+    pub fn parse(source: &str) -> Result<Self, (ParseError, Span)> {
+        Self::parse_with_options(source, ParserOptions::new())
+    }
+
+    /// Parse a document for editing with the given options.  The options are also used
+    /// to decide which separators new entries need, and to reparse the document after
+    /// each edit.
+    ///
+    /// # Failures
+    /// Fails in the same way as `Figtree::parse`.
+    pub fn parse_with_options(source: &str, mut options: ParserOptions)
+                              -> Result<Self, (ParseError, Span)> {
+        // positions are used to find entries in the source, so a tab must be one column
+        options.tab_width = 1;
+        let document = Figtree::from_string(source).with_options(options.clone()).parse()?;
+        let root = index(source, &options)?;
+        Ok(EditableDocument { source: source.to_string(), options, document, root })
+    }
+
+    /// The document as it is after the edits so far
+    pub fn document(&self) -> &Document {
+        &self.document
+    }
+
+    /// The text of the document as it is after the edits so far
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// Stop editing, and get back the text of the document
+    pub fn into_string(self) -> String {
+        self.source
+    }
+
+    /// Set the value at a path, adding an attribute or dict entry if there isn't one
+    /// there yet.  Returns the value that was replaced.  Paths are written in the same
+    /// form as for `Document::get_path`.
+    ///
+    /// # Failures
+    /// Fails with `PatchError::InvalidPath` if the path is malformed, leads to a node, or
+    /// leads to somewhere that a value can't be added, such as the end of a list.
+    pub fn set_path(&mut self, path: &str, value: Value) -> Result<Option<Value>, PatchError> {
+        let invalid = || PatchError::InvalidPath(path.to_string());
+        let old = self.document.get_path(path).cloned();
+        let (range, text) = match self.root.find(path) {
+            Some(entry) if entry.kind != Kind::Node => (entry.value.clone(), value.to_string()),
+            Some(_) => return Err(invalid()),
+            None => {
+                let (parent, last) = split_last(path).ok_or_else(invalid)?;
+                let parent = Some(parent).filter(|parent| !parent.is_empty())
+                    .and_then(|parent| self.root.find(parent))
+                    .filter(|parent| matches!(parent.kind, Kind::Node | Kind::Dict));
+                match parent {
+                    Some(parent) if last.indexes.is_empty() =>
+                        self.insertion(parent, &last.name, &value),
+                    _ => return Err(invalid()),
+                }
+            },
+        };
+        self.splice(range, &text).map_err(|_| invalid())?;
+        Ok(old)
+    }
+
+    /// Remove the attribute, node, dict entry or list item at a path, along with the
+    /// separator after it, and the rest of its line if nothing else is on it.
+    ///
+    /// # Failures
+    /// Fails with `PatchError::Missing` if there is nothing at the path.
+    pub fn remove_path(&mut self, path: &str) -> Result<(), PatchError> {
+        let range = match self.root.find(path) {
+            Some(entry) if !path.is_empty() => self.removal(entry),
+            _ => return Err(PatchError::Missing(path.to_string())),
+        };
+        self.splice(range, "").map_err(|_| PatchError::InvalidPath(path.to_string()))
+    }
+
+    /// Replace part of the source, and reparse it.  If the result can't be parsed, the
+    /// document is left as it was.
+    fn splice(&mut self, range: Range<usize>, text: &str) -> Result<(), (ParseError, Span)> {
+        let mut source = self.source.clone();
+        source.replace_range(range, text);
+        *self = Self::parse_with_options(&source, self.options.clone())?;
+        Ok(())
+    }
+
+    /// The separator written after the value of an entry, and where it ends
+    fn terminator(&self, entry: &Entry) -> Option<usize> {
+        let mut rest = &self.source[entry.value.end..];
+        loop {
+            rest = rest.trim_start_matches([' ', '\t']);
+            match rest.strip_prefix("/*").and_then(|comment| comment.find("*/").map(|end| (comment, end))) {
+                Some((comment, end)) => rest = &comment[end + 2..],
+                None => break,
+            }
+        }
+        let end = self.source.len() - rest.len();
+        matches!(rest.chars().next(), Some(',') | Some(';')).then(|| end + 1)
+    }
+
+    /// Where to insert a new entry into a node or dict, and the text to insert
+    fn insertion(&self, parent: &Entry, name: &str, value: &Value) -> (Range<usize>, String) {
+        let source = &self.source;
+        let attrs: Vec<&Entry> = parent.entries.iter().filter(|entry| entry.kind != Kind::Node)
+            .collect();
+        let mut entry = String::new();
+        let _ = write_name(&mut entry, name);
+        entry.push_str(match attrs.last().and_then(|attr| attr.separator) {
+            Some('=') => " = ",
+            _ => ": ",
+        });
+        entry.push_str(&value.to_string());
+
+        // new entries are separated in the same way as the attributes before them, and
+        // if those end in semicolons, so does the new one
+        let separator = attrs.iter().rev().find_map(|attr| self.terminator(attr))
+            .map_or(",", |end| &source[end - 1..end]);
+
+        // the entry before the new one needs a separator, if it doesn't have one already
+        let comma =
+            |last: &Entry| last.kind != Kind::Node && self.terminator(last).is_none()
+                && self.options.separators != SeparatorPolicy::Forbidden;
+        let close = parent.value.end - 1;
+        let open = parent.value.start;
+        let multiline = parent.kind == Kind::Node
+            && line_start(source, close) > open
+            && source[line_start(source, close)..close].trim().is_empty();
+
+        if !multiline {
+            let last = parent.entries.iter().max_by_key(|entry| entry.value.end);
+            let (at, mut text) = match last {
+                Some(last) if comma(last) && self.options.separators != SeparatorPolicy::Optional =>
+                    (last.value.end, separator.to_string()),
+                Some(last) => (self.terminator(last).unwrap_or(last.value.end), String::new()),
+                None => (open + 1, String::new()),
+            };
+            text.push(' ');
+            text.push_str(&entry);
+            if separator == ";" {
+                text.push(';');
+            }
+            if close == open + 1 {
+                text.push(' ');
+            }
+            return (at..at, text);
+        }
+
+        // new attributes go on their own line after the last attribute, in the same style
+        let newline = if source.contains("\r\n") { "\r\n" } else { "\n" };
+        let mut indent = format!("{}    ", &source[line_start(source, close)..close]);
+        if let Some(&last) = attrs.last() {
+            let end = self.terminator(last);
+            let terminator = end.map_or("", |end| &source[end - 1..end]);
+            let after = end.unwrap_or(last.value.end);
+            let line_end = line_end(source, after);
+            let before = &source[line_start(source, last.start)..last.start];
+            if before.trim().is_empty() {
+                indent = before.to_string();
+            }
+            let mut text = String::new();
+            if comma(last) && self.options.separators == SeparatorPolicy::Required {
+                text.push_str(separator);
+            }
+            text.push_str(&source[after..line_end]);
+            text.push_str(newline);
+            text.push_str(&indent);
+            text.push_str(&entry);
+            text.push_str(terminator);
+            return (after..line_end, text);
+        }
+
+        // without any attributes to follow, the attribute goes straight after the brace
+        if let Some(first) = parent.entries.first() {
+            let before = &source[line_start(source, first.start)..first.start];
+            if before.trim().is_empty() {
+                indent = before.to_string();
+            }
+        }
+        let terminator = match self.options.separators {
+            SeparatorPolicy::Forbidden => "",
+            _ => ",",
+        };
+        let at = line_end(source, open);
+        (at..at, format!("{}{}{}{}", newline, indent, entry, terminator))
+    }
+
+    /// The part of the source to remove to remove an entry
+    fn removal(&self, entry: &Entry) -> Range<usize> {
+        let source = &self.source;
+        let terminator = self.terminator(entry);
+        let end = terminator.unwrap_or(entry.value.end);
+
+        // remove the whole line if the entry was all there was on it, other than a comment
+        let (line, line_end) = (line_start(source, entry.start), line_end(source, end));
+        let rest = source[end..line_end].trim();
+        if source[line..entry.start].trim().is_empty()
+            && (rest.is_empty() || rest.starts_with('#') || rest.starts_with("//")) {
+            let ending = if source[line_end..].starts_with("\r\n") { 2 } else { 1 };
+            return line..(line_end + ending).min(source.len());
+        }
+
+        if terminator.is_some() {
+            let rest = &source[end..];
+            return entry.start..end + (rest.len() - rest.trim_start_matches([' ', '\t']).len());
+        }
+        // without a separator of its own, the previous entry's separator is removed
+        let before = source[..entry.start].trim_end();
+        if before.ends_with([',', ';']) { before.len() - 1..end } else { entry.start..end }
+    }
+}
+
+/// Documents are written out as they were read, with the edits made to them.
+impl fmt::Display for EditableDocument {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+/// Where the line containing `offset` starts
+fn line_start(source: &str, offset: usize) -> usize {
+    source[..offset].rfind(['\n', '\r']).map_or(0, |end| end + 1)
+}
+
+/// Where the line containing `offset` ends, before its line ending
+fn line_end(source: &str, offset: usize) -> usize {
+    source[offset..].find(['\n', '\r']).map_or(source.len(), |end| offset + end)
+}
+
+/// Find where everything in the source is, from the spans of its parse events
+fn index(source: &str, options: &ParserOptions) -> Result<Entry, (ParseError, Span)> {
+    // lines end in the same places as they do for the lexer, which skips a byte order mark
+    let bom = if source.starts_with('\u{feff}') { '\u{feff}'.len_utf8() } else { 0 };
+    let mut lines = vec![bom];
+    let mut after_cr = false;
+    for (offset, ch) in source.char_indices() {
+        match ch {
+            '\n' if after_cr => *lines.last_mut().unwrap() = offset + 1,
+            '\n' | '\r' => lines.push(offset + 1),
+            _ => {},
+        }
+        after_cr = ch == '\r';
+    }
+    let offset = |position: &Position| {
+        let start = lines.get(position.line).cloned().unwrap_or(source.len());
+        source[start..].char_indices().nth(position.pos).map_or(source.len(), |(at, _)| start + at)
+    };
+
+    let lexer = Lexer::lex(Cursor::new(source.as_bytes().to_vec()));
+    let mut stack = vec![Entry::container(Kind::Node, None, None, 0)];
+    let mut key: Option<(String, char, usize)> = None;
+    for result in Parser::with_options(lexer, options.clone()) {
+        let (event, span) = result?;
+        let (start, end) = (offset(&span.start), offset(&span.end));
+        let (name, separator, entry_start) = match key.take() {
+            Some((name, separator, key_start)) => (Some(name), Some(separator), key_start),
+            None => (None, None, start),
+        };
+        let finished = match event {
+            ParseEvent::NodeStart(name, _) => {
+                let mut node = Entry::container(Kind::Node, Some(name), None, start);
+                // the opening brace is part of the span if there are no arguments
+                node.value.start = if source[..end].ends_with('{') { end - 1 } else { end };
+                stack.push(node);
+                None
+            },
+            ParseEvent::Argument(_) => {
+                let node = stack.last_mut().expect("arguments are always inside a node");
+                node.value.start = end + source[end..].find('{').unwrap_or(0);
+                None
+            },
+            ParseEvent::Key(name, _) => {
+                let separator = source[..end].chars().next_back().unwrap_or(':');
+                key = Some((name, separator, start));
+                None
+            },
+            ParseEvent::Value(_) => Some(Entry {
+                kind: Kind::Value, name, separator, start: entry_start, value: start..end,
+                entries: Vec::new(),
+            }),
+            ParseEvent::ListStart | ParseEvent::DictStart => {
+                let kind = if event == ParseEvent::ListStart { Kind::List } else { Kind::Dict };
+                let mut container = Entry::container(kind, name, separator, entry_start);
+                container.value.start = start;
+                stack.push(container);
+                None
+            },
+            ParseEvent::NodeEnd | ParseEvent::ListEnd | ParseEvent::DictEnd => {
+                let mut container = stack.pop().expect("containers are always closed once");
+                container.value.end = end;
+                Some(container)
+            },
+            ParseEvent::FileStart | ParseEvent::FileEnd => None,
+        };
+        if let Some(entry) = finished {
+            stack.last_mut().expect("the document is never closed").entries.push(entry);
+        }
+    }
+    let mut root = stack.pop().expect("the document is never closed");
+    root.value = 0..source.len();
+    Ok(root)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str) -> EditableDocument {
+        EditableDocument::parse_with_options(input, ParserOptions::new().repeated_nodes(true))
+            .unwrap()
+    }
+
+    fn edited<F: FnOnce(&mut EditableDocument)>(input: &str, edit: F) -> String {
+        let mut doc = parse(input);
+        edit(&mut doc);
+        let options = ParserOptions::new().repeated_nodes(true);
+        assert_eq!(Figtree::from_string(doc.as_str()).with_options(options).parse().unwrap(),
+            *doc.document());
+        doc.into_string()
+    }
+
+    #[test]
+    fn preserves_formatting() {
+        let input = "# config\r\nserver   {\r\n\thost: 'a' ,  # the host\r\n\r\n\
+                     \tports = [ 80,443 ]  /* ports */ ; tls{}\r\n}";
+        let options = ParserOptions::new().semicolons(true);
+        let mut doc = EditableDocument::parse_with_options(input, options).unwrap();
+        assert_eq!(doc.to_string(), input);
+        assert_eq!(doc.document()["server"].get_list("ports").unwrap().len(), 2);
+        doc.set_path("server.host", Value::new_string("b")).unwrap();
+        assert_eq!(doc.as_str(), input.replace("'a'", "\"b\""));
+
+        let output = edited("a {\r\n    b = 1,\r\n}\r\n", |doc| {
+            doc.set_path("a.c", Value::new_int(2)).unwrap();
+        });
+        assert_eq!(output, "a {\r\n    b = 1,\r\n    c = 2,\r\n}\r\n");
+    }
+
+    #[test]
+    fn keeps_byte_order_mark() {
+        let output = edited("\u{feff}server { port = 80 }", |doc| {
+            doc.set_path("server.port", Value::new_int(1)).unwrap();
+        });
+        assert_eq!(output, "\u{feff}server { port = 1 }");
+    }
+
+    #[test]
+    fn replaces_values() {
+        let input = "a { b = 1, /* c */ c: { d = [1, 2] } } e { f = 'g' } e { f = 'h' }";
+        let output = edited(input, |doc| {
+            assert_eq!(doc.set_path("a.b", Value::new_int(2)), Ok(Some(Value::new_int(1))));
+            doc.set_path("a.c.d[1]", Value::new_string("x")).unwrap();
+            doc.set_path("e[1].f", Value::List(vec![])).unwrap();
+        });
+        assert_eq!(output, "a { b = 2, /* c */ c: { d = [1, \"x\"] } } e { f = 'g' } e { f = [] }");
+
+        let mut doc = parse(input);
+        for invalid in &["a", "a.b.c", "a.c.d[2]", "x.y", "a.c.d.e", "", "a..b", "new"] {
+            assert_eq!(doc.set_path(invalid, Value::new_null()),
+                Err(PatchError::InvalidPath(invalid.to_string())), "{}", invalid);
+        }
+        assert_eq!(doc.to_string(), input);
+    }
+
+    #[test]
+    fn adds_entries() {
+        let input = "\
+a {
+  c { }
+  b = 1
+}
+d {
+    e {}
+}
+f {} g { h = 1 } i { j {} }
+";
+        let output = edited(input, |doc| {
+            doc.set_path("a.new", Value::new_int(2)).unwrap();
+            doc.set_path("a.c.new", Value::new_int(3)).unwrap();
+            doc.set_path("d.new", Value::new_int(4)).unwrap();
+            doc.set_path("f.new", Value::new_int(5)).unwrap();
+            doc.set_path("g.new", Value::new_int(6)).unwrap();
+            doc.set_path("i.new", Value::new_int(7)).unwrap();
+            doc.set_path("g.dict", Value::Dict(Default::default())).unwrap();
+            doc.set_path("g.dict.x", Value::new_int(8)).unwrap();
+            doc.set_path("g.dict.y", Value::new_int(9)).unwrap();
+            doc.set_path("g.odd key", Value::new_null()).unwrap();
+        });
+        assert_eq!(output, "\
+a {
+  c { new: 3 }
+  b = 1,
+  new = 2
+}
+d {
+    new: 4,
+    e {}
+}
+f { new: 5 } g { h = 1, new = 6, dict = { x: 8, y: 9 }, \"odd key\" = null } i { j {} new: 7 }
+");
+    }
+
+    #[test]
+    fn adds_entries_after_semicolons() {
+        let input = "\
+a {
+    x = 1;
+    y = 2;
+}
+b { x = 1; y = 2; }
+c {
+    x = 1;
+    y = 2
+}
+";
+        let options = ParserOptions::new().semicolons(true);
+        let mut doc = EditableDocument::parse_with_options(input, options.clone()).unwrap();
+        doc.set_path("a.z", Value::new_int(3)).unwrap();
+        doc.set_path("b.z", Value::new_int(3)).unwrap();
+        doc.set_path("c.z", Value::new_int(3)).unwrap();
+        assert_eq!(doc.as_str(), "\
+a {
+    x = 1;
+    y = 2;
+    z = 3;
+}
+b { x = 1; y = 2; z = 3; }
+c {
+    x = 1;
+    y = 2;
+    z = 3
+}
+");
+        assert_eq!(Figtree::from_string(doc.as_str()).with_options(options).parse().unwrap(),
+            *doc.document());
+    }
+
+    #[test]
+    fn removes_entries() {
+        let input = "\
+a {
+    b = 1,  # one
+    c = [1, 2, 3],
+    d { e = 1 }  # d
+    d {}
+}
+f { g = 1, h = { i = 1, j = 2 } }
+";
+        let output = edited(input, |doc| {
+            doc.remove_path("a.b").unwrap();
+            doc.remove_path("a.c[1]").unwrap();
+            doc.remove_path("a.d[1]").unwrap();
+            doc.remove_path("a.d.e").unwrap();
+            doc.remove_path("f.h.j").unwrap();
+            doc.remove_path("f.g").unwrap();
+            assert_eq!(doc.remove_path("f.g"), Err(PatchError::Missing("f.g".to_string())));
+            assert_eq!(doc.remove_path(""), Err(PatchError::Missing("".to_string())));
+        });
+        assert_eq!(output, "\
+a {
+    c = [1, 3],
+    d {  }  # d
+}
+f { h = { i = 1 } }
+");
+    }
+}
@@ -0,0 +1,272 @@
+use std::collections::HashMap;
+
+use super::display::write_value;
+use super::types::{Document, Node, Value};
+
+/// How lists are flattened into environment variables
+#[derive(Debug, PartialEq, Clone)]
+pub enum EnvLists {
+    /// Each item gets its own variable, with its index as the last part of the name
+    /// (`HOSTS_0=a`, `HOSTS_1=b`).  This is the default.
+    Indexed,
+    /// The items are joined into one variable, separated by this string (`HOSTS=a,b`)
+    Joined(String),
+}
+
+/// Options controlling how `Document::flatten_env_with_options` names and writes
+/// variables
+///
+/// # Examples
+/// ```
+/// # use figtree::{EnvLists, EnvOptions, Figtree};
+/// let doc = Figtree::from_string("server { hosts = ['a', 'b'] }").parse().unwrap();
+/// let options = EnvOptions::new().separator("__").lists(EnvLists::Joined(",".into()));
+/// assert_eq!(doc.flatten_env_with_options("APP", &options),
+///            vec![("APP__SERVER__HOSTS".to_string(), "a,b".to_string())]);
+/// ```
+#[derive(Debug, PartialEq, Clone)]
+pub struct EnvOptions {
+    /// The string put between the parts of each name
+    pub separator: String,
+    /// Whether names are converted to upper case
+    pub uppercase: bool,
+    /// How lists are flattened
+    pub lists: EnvLists,
+}
+
+impl EnvOptions {
+    /// Construct the default set of options, which write upper case names separated by
+    /// `_`, with each item of a list in its own variable
+    pub fn new() -> Self {
+        EnvOptions {
+            separator: "_".into(),
+            uppercase: true,
+            lists: EnvLists::Indexed,
+        }
+    }
+
+    /// Set the string put between the parts of each name
+    pub fn separator<S: Into<String>>(mut self, separator: S) -> Self {
+        self.separator = separator.into();
+        self
+    }
+
+    /// Convert names to upper case (the default)
+    pub fn uppercase(mut self, enabled: bool) -> Self {
+        self.uppercase = enabled;
+        self
+    }
+
+    /// Set how lists are flattened
+    pub fn lists(mut self, policy: EnvLists) -> Self {
+        self.lists = policy;
+        self
+    }
+}
+
+impl Default for EnvOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The text of a variable: strings and identifiers as they are, `null` as nothing, and
+/// anything else as it would be written in a figtree document
+fn value_text(value: &Value) -> String {
+    match *value {
+        Value::Str(ref string) | Value::Ident(ref string) => string.clone(),
+        Value::Null => String::new(),
+        ref value => {
+            let mut text = String::new();
+            let _ = write_value(&mut text, value);
+            text
+        },
+    }
+}
+
+struct Flattener<'a> {
+    options: &'a EnvOptions,
+    pairs: Vec<(String, String)>,
+}
+
+impl<'a> Flattener<'a> {
+    /// The name of an entry inside the node or value named `path`, with any characters
+    /// that can't be in a variable's name replaced by `_`
+    fn name(&self, path: &str, name: &str) -> String {
+        let name: String = name.chars().map(|c| match c {
+            c if c.is_ascii_alphanumeric() && self.options.uppercase => c.to_ascii_uppercase(),
+            c if c.is_ascii_alphanumeric() => c,
+            _ => '_',
+        }).collect();
+        if path.is_empty() {
+            name
+        } else {
+            format!("{}{}{}", path, self.options.separator, name)
+        }
+    }
+
+    fn nodes(&mut self, nodes: &[Node], path: &str) {
+        let mut counts = HashMap::new();
+        for node in nodes {
+            *counts.entry(node.name()).or_insert(0) += 1;
+        }
+        let mut indices = HashMap::new();
+        for node in nodes {
+            let mut name = self.name(path, node.name());
+            if counts[node.name()] > 1 {
+                let index = indices.entry(node.name()).or_insert(0);
+                name = self.name(&name, &index.to_string());
+                *index += 1;
+            }
+            self.node(node, &name);
+        }
+    }
+
+    fn node(&mut self, node: &Node, path: &str) {
+        match node.arguments() {
+            [] => {},
+            [argument] => self.value(argument, path),
+            arguments => self.list(arguments, path),
+        }
+        for (key, value) in node.iter_attrs() {
+            let name = self.name(path, key);
+            self.value(value, &name);
+        }
+        self.nodes(node.children().as_slice(), path);
+    }
+
+    fn value(&mut self, value: &Value, path: &str) {
+        match *value {
+            Value::Dict(ref dict) => {
+                let mut entries: Vec<_> = dict.iter().collect();
+                entries.sort_by(|a, b| a.0.cmp(b.0));
+                for (key, value) in entries {
+                    let name = self.name(path, key);
+                    self.value(value, &name);
+                }
+            },
+            Value::List(ref items) => self.list(items, path),
+            Value::Node(ref node) => self.node(node, path),
+            ref value => self.pairs.push((path.to_string(), value_text(value))),
+        }
+    }
+
+    fn list(&mut self, items: &[Value], path: &str) {
+        match self.options.lists {
+            EnvLists::Indexed => for (index, item) in items.iter().enumerate() {
+                let name = self.name(path, &index.to_string());
+                self.value(item, &name);
+            },
+            EnvLists::Joined(ref separator) => {
+                let text: Vec<_> = items.iter().map(value_text).collect();
+                self.pairs.push((path.to_string(), text.join(separator)));
+            },
+        }
+    }
+}
+
+impl Document {
+    /// Flatten the document into environment variables, with the default options.  Each
+    /// attribute becomes a variable named with the path to it, starting with `prefix`
+    /// (which is used as it is, and left out if it's empty), so `cert` in `tls` in
+    /// `server` becomes `PREFIX_SERVER_TLS_CERT`.  Dicts are flattened in the same way as
+    /// nodes, and lists have a variable for each item, named with its index.
+    ///
+    /// A node's arguments are a variable named with the node's own path, and are
+    /// flattened as a list if there's more than one.  Nodes that share a name with
+    /// another node beside them have their index in the name too (`UPSTREAM_0_HOST`).
+    /// Any character in a name that isn't an ASCII letter or digit becomes `_`, so two
+    /// different paths can end up with the same name.
+    ///
+    /// Strings and identifiers are written as they are, `null` as an empty variable, and
+    /// other values as they would be written in a figtree document.
+    ///
+    /// # Examples
+    /// ```
+    /// # use figtree::{Figtree, ParserOptions};
+    /// # use std::process::Command;
+    /// let options = ParserOptions::new().node_arguments(true);
+    /// let doc = Figtree::from_string("server 8080 { tls { cert = '/etc/a.pem' } }")
+    ///     .with_options(options).parse().unwrap();
+    /// let variables = doc.flatten_env("APP");
+    /// assert_eq!(variables, vec![
+    ///     ("APP_SERVER".to_string(), "8080".to_string()),
+    ///     ("APP_SERVER_TLS_CERT".to_string(), "/etc/a.pem".to_string()),
+    /// ]);
+    /// let mut command = Command::new("server");
+    /// command.envs(variables);
+    /// ```
+    pub fn flatten_env(&self, prefix: &str) -> Vec<(String, String)> {
+        self.flatten_env_with_options(prefix, &EnvOptions::new())
+    }
+
+    /// Flatten the document into environment variables, in the way described by
+    /// `options`
+    pub fn flatten_env_with_options(&self, prefix: &str, options: &EnvOptions)
+                                    -> Vec<(String, String)> {
+        let mut flattener = Flattener { options, pairs: Vec::new() };
+        flattener.nodes(self.children().as_slice(), prefix);
+        flattener.pairs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::figtree::Figtree;
+    use super::super::parser::ParserOptions;
+    use super::*;
+
+    fn parse(input: &str) -> Document {
+        let options = ParserOptions::new().node_arguments(true).repeated_nodes(true);
+        Figtree::from_string(input).with_options(options).parse().unwrap()
+    }
+
+    fn pairs(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|&(key, value)| (key.to_string(), value.to_string())).collect()
+    }
+
+    const INPUT: &str = "
+        server 'web' 8080 {
+            'log-level' = !debug,
+            timeout = 250ms,
+            hosts = ['a', ['b', 1]],
+            env = { path = '/bin', home = null },
+            upstream { host = 'a' }
+            upstream { host = 'b' }
+        }
+    ";
+
+    #[test]
+    fn flattens_documents() {
+        assert_eq!(parse(INPUT).flatten_env(""), pairs(&[
+            ("SERVER_0", "web"),
+            ("SERVER_1", "8080"),
+            ("SERVER_LOG_LEVEL", "debug"),
+            ("SERVER_TIMEOUT", "250ms"),
+            ("SERVER_HOSTS_0", "a"),
+            ("SERVER_HOSTS_1_0", "b"),
+            ("SERVER_HOSTS_1_1", "1"),
+            ("SERVER_ENV_HOME", ""),
+            ("SERVER_ENV_PATH", "/bin"),
+            ("SERVER_UPSTREAM_0_HOST", "a"),
+            ("SERVER_UPSTREAM_1_HOST", "b"),
+        ]));
+        assert!(Document::new().flatten_env("APP").is_empty());
+    }
+
+    #[test]
+    fn uses_options() {
+        let options = EnvOptions::new().separator(".").uppercase(false)
+            .lists(EnvLists::Joined(";".into()));
+        assert_eq!(parse(INPUT).flatten_env_with_options("app", &options), pairs(&[
+            ("app.server", "web;8080"),
+            ("app.server.log_level", "debug"),
+            ("app.server.timeout", "250ms"),
+            ("app.server.hosts", "a;[\"b\", 1]"),
+            ("app.server.env.home", ""),
+            ("app.server.env.path", "/bin"),
+            ("app.server.upstream.0.host", "a"),
+            ("app.server.upstream.1.host", "b"),
+        ]));
+    }
+}
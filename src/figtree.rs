@@ -1,12 +1,21 @@
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::io::{Cursor, Error};
 use std::fs::File;
 use std::io::prelude::*;
+use std::mem;
 
-use super::parser::{Parser, ParseEvent, ParseError};
+use super::parser::{Parser, ParseEvent, ParseError, ParseResult, ParserOptions};
+use super::parser::{DuplicateKeyPolicy, ParseWarning};
 use super::lexer::Lexer;
-use super::position::Position;
+use super::position::{Origin, Position, Span};
 
 use super::types::*;
+use super::intern::Interner;
+
+#[cfg(feature = "encoding")]
+use encoding_rs::Encoding;
+#[cfg(feature = "encoding")]
+use super::decoding::DecodingReader;
 
 /// Opens, parses, and reads figtree files.
 ///
@@ -25,6 +34,14 @@ use super::types::*;
 /// ```
 pub struct Figtree {
     parser: Parser,
+    /// comments that have been read, but not attached to anything yet
+    comments: VecDeque<(String, Span)>,
+    /// the end of the most recent event
+    last_end: Position,
+    /// where the input came from, if it's known
+    origin: Option<Origin>,
+    /// the names and keys read so far, which are given to the document
+    interner: Interner,
 }
 
 impl Figtree {
@@ -44,7 +61,11 @@ impl Figtree {
     /// ```
     pub fn new<T: Read + 'static>(input: T) -> Self {
         Figtree {
-            parser: Parser::parse(Lexer::lex(input))
+            parser: Parser::parse(Lexer::lex(input)),
+            comments: VecDeque::new(),
+            last_end: Position::new(),
+            origin: None,
+            interner: Interner::new(),
         }
     }
 
@@ -57,7 +78,9 @@ impl Figtree {
     /// This function will fail under the same circumstances that `File::open` will fail,
     /// producing the same error (`std::io::Error`).
     pub fn from_filename<T>(input: T) -> Result<Figtree, Error> where T: Into<String> {
-        Ok(Figtree::new(try!(File::open(input.into()))))
+        let filename = input.into();
+        let figtree = Figtree::new(File::open(&filename)?);
+        Ok(figtree.with_origin(Origin::File(filename.into())))
     }
 
     /// Constructs a `Figtree` instance from a &str or String.
@@ -81,16 +104,84 @@ impl Figtree {
     /// let mut figgy = Figtree::from_string("input");
     /// ```
     pub fn from_string<T>(input: T) -> Figtree where T: Into<String> {
-        Figtree::new(Cursor::new(input.into().into_bytes()))
+        Figtree::new(Cursor::new(input.into().into_bytes())).with_origin(Origin::Inline)
+    }
+
+    /// Constructs a `Figtree` instance from input in an encoding other than UTF-8.
+    ///
+    /// The encoding is only used if the input doesn't start with a byte order mark -
+    /// otherwise the encoding given by the byte order mark is used instead.  Bytes that
+    /// aren't valid in the encoding are reported as a `LexError::DecodeError`.
+    ///
+    /// Requires the `encoding` feature.  The `encoding_rs` crate is re-exported to
+    /// provide the available encodings.
+    ///
+    /// # Examples
+    /// ```
+    /// # use figtree::Figtree;
+    /// # use std::io::Cursor;
+    /// use figtree::encoding_rs::WINDOWS_1252;
+    /// let input = Cursor::new(b"node { name = 'caf\xe9' }".to_vec());
+    /// let config = Figtree::with_encoding(input, WINDOWS_1252).parse().unwrap();
+    /// let name = config.get_node("node").and_then(|node| node.get_attr("name"));
+    /// assert_eq!(name.and_then(|value| value.get_str()), Some("café"));
+    /// ```
+    #[cfg(feature = "encoding")]
+    pub fn with_encoding<T: Read + 'static>(input: T, encoding: &'static Encoding) -> Self {
+        Figtree::new(DecodingReader::new(input, encoding))
+    }
+
+    /// Sets the options used when parsing the document.
+    ///
+    /// This allows different dialects of the figtree format to be parsed - see
+    /// `ParserOptions` for the available settings.
+    ///
+    /// # Examples
+    /// ```
+    /// # use figtree::{Figtree, ParserOptions, SeparatorPolicy};
+    /// let options = ParserOptions::new().separators(SeparatorPolicy::Newline);
+    /// let mut figgy = Figtree::from_string("node {
+    ///     'key': 1
+    ///     'other key': 2
+    /// }").with_options(options);
+    /// assert!(figgy.parse().is_ok());
+    /// ```
+    pub fn with_options(mut self, options: ParserOptions) -> Self {
+        self.parser.set_options(options);
+        self
+    }
+
+    /// Sets where the input came from, which is given to the parsed document (see
+    /// `Document::origin`).  This is set already by `from_filename` and `from_string`, but
+    /// not when the `Figtree` is constructed from any other reader.
+    ///
+    /// # Examples
+    /// ```
+    /// # use figtree::{Figtree, Origin};
+    /// # use std::io::Cursor;
+    /// let input = Cursor::new(b"server { port = 80 }".to_vec());
+    /// let origin = Origin::Url("https://example.com/server.fig".to_string());
+    /// let config = Figtree::new(input).with_origin(origin.clone()).parse().unwrap();
+    /// assert_eq!(config.origin(), Some(&origin));
+    /// assert_eq!(config["server"].origin(), Some(&origin));
+    /// ```
+    pub fn with_origin(mut self, origin: Origin) -> Self {
+        self.origin = Some(origin);
+        self
+    }
+
+    /// Where the input came from, if it's known
+    pub fn origin(&self) -> Option<&Origin> {
+        self.origin.as_ref()
     }
 
     /// Parse the document stored in this `Figtree` instance into a `Document`.
     ///
     /// # Failures
-    /// If a parsing error occurs, a `(ParseError, Position)` tuple is returned, where
-    /// the `ParseError` contains the kind of error that happened, and the `Position`
-    /// points to the position the lexer was in at the beginning of the last (erroring)
-    /// token.
+    /// If a parsing error occurs, a `(ParseError, Span)` tuple is returned, where the
+    /// `ParseError` contains the kind of error that happened, and the `Span` covers the
+    /// token or construct that caused it - all of an unclosed string, for example, up to
+    /// the end of the input.
     ///
     /// # Examples
     /// Parsing successfully:
@@ -108,19 +199,59 @@ impl Figtree {
     /// # use figtree::Figtree;
     /// # use figtree::ParseError;
     /// # use figtree::LexToken;
-    /// # use figtree::Position;
+    /// # use figtree::{Expected, Position, Span};
     /// let mut figgy = Figtree::from_string("invalid document");
     /// let error = figgy.parse().err().expect("parsing should have failed");
     /// assert_eq!(
     ///     error.0,
-    ///     ParseError::UnexpectedToken(LexToken::Identifier("document".to_string())));
+    ///     ParseError::UnexpectedToken(LexToken::Identifier("document".to_string()),
+    ///                                 vec![Expected::OpenBrace]));
     /// assert_eq!(
     ///     error.1,
-    ///     Position::at(0, 8));
+    ///     Span::new(Position::at(0, 8), Position::at(0, 16)));
+    ///
+    /// let error = Figtree::from_string("node { key = 'value }").parse().unwrap_err();
+    /// assert_eq!(error.1, Span::new(Position::at(0, 13), Position::at(0, 21)));
     /// ```
-    pub fn parse(&mut self) -> Result<Document, (ParseError, Position)> {
+    pub fn parse(&mut self) -> Result<Document, (ParseError, Span)> {
+        self.parse_document()
+    }
+
+    /// Take the warnings that the parser has found so far (see `Parser::take_warnings`).
+    /// Warnings are only kept if `ParserOptions::warnings` is turned on.
+    ///
+    /// # Examples
+    /// ```
+    /// # use figtree::{Figtree, ParserOptions, ParseWarning};
+    /// let mut figgy = Figtree::from_string("node { key = 'a'\n'b' }")
+    ///     .with_options(ParserOptions::new().warnings(true));
+    /// let config = figgy.parse().unwrap();
+    /// assert_eq!(config["node"]["key"], "ab".into());
+    /// assert_eq!(figgy.take_warnings()[0].0, ParseWarning::SplitString);
+    /// ```
+    pub fn take_warnings(&mut self) -> Vec<(ParseWarning, Span)> {
+        self.parser.take_warnings()
+    }
+
+    /// Get the next event, keeping track of where it ended
+    fn next_event(&mut self) -> Option<ParseResult> {
+        let result = self.parser.next();
+        if let Some(Ok((_, ref span))) = result {
+            self.last_end = span.end.clone();
+        }
+        result
+    }
+
+    /// Take the comments that start before `position`
+    fn comments_before(&mut self, position: &Position) -> Vec<(String, Span)> {
+        self.comments.extend(self.parser.take_comments());
+        let count = self.comments.iter().take_while(|comment| comment.1.start < *position).count();
+        self.comments.drain(..count).collect()
+    }
+
+    fn parse_document(&mut self) -> Result<Document, (ParseError, Span)> {
         let mut doc = Document::new();
-        match self.parser.next() {
+        match self.next_event() {
             Some(Ok((ParseEvent::FileStart, _))) => {
                 if let Some(err) = self.parse_file(&mut doc) {
                     return Err(err);
@@ -131,21 +262,40 @@ impl Figtree {
             Some(Err(error)) =>
                 return Err(error),
         }
+        if let Some(ref origin) = self.origin {
+            doc.set_origin(origin.clone());
+        }
+        mem::swap(doc.interner_mut(), &mut self.interner);
         Ok(doc)
     }
 
-    fn parse_file(&mut self, doc: &mut Document) -> Option<(ParseError, Position)> {
+    fn parse_file(&mut self, doc: &mut Document) -> Option<(ParseError, Span)> {
+        let mut block = Block::default();
         loop {
-            match self.parser.next() {
-                Some(Ok((ParseEvent::NodeStart(name), _))) => {
-                    if doc.has_node(&name) {
-                        return Some((ParseError::RepeatedNode(name), self.parser.lex_position()));
+            let next = self.next_event();
+            if let Some(Ok((_, ref span))) = next {
+                for (entry, comment) in block.sort(self.comments_before(&span.start)) {
+                    doc_comment(doc, &entry, comment);
+                }
+            }
+            match next {
+                Some(Ok((ParseEvent::NodeStart(name, _), span))) => {
+                    if doc.has_node(&name) && !self.parser.options().repeated_nodes {
+                        return Some((ParseError::RepeatedNode(name), span));
                     }
-                    if let Some(err) = self.parse_node(doc.new_node_or_get(name)) {
+                    let index = doc.node_count();
+                    let node = doc.append_node(self.interner.intern(&name), Node::new());
+                    node.comments_mut().leading = block.start();
+                    if let Some(err) = self.parse_node(node, span) {
                         return Some(err);
                     }
+                    block.end(Entry::Node(index), self.last_end.clone());
                 },
                 Some(Ok((ParseEvent::FileEnd, _))) => {
+                    let (entry, comments) = block.finish();
+                    for comment in comments {
+                        doc_comment(doc, &entry, comment);
+                    }
                     return None;
                 },
                 Some(Ok(ev)) =>
@@ -157,23 +307,65 @@ impl Figtree {
         }
     }
 
-    fn parse_node(&mut self, node: &mut Node) -> Option<(ParseError, Position)> {
+    /// Parse the contents of a node, whose `NodeStart` event covered `start`
+    fn parse_node(&mut self, node: &mut Node, start: Span) -> Option<(ParseError, Span)> {
+        let mut keys = Keys::default();
+        let mut block = Block::default();
+        block.end(Entry::Owner, start.end);
         loop {
-            match self.parser.next() {
-                Some(Ok((ParseEvent::NodeEnd, _))) => { return None; },
-                Some(Ok((ParseEvent::NodeStart(name), _))) => {
-                    if node.has_node(&name) {
-                        return Some((ParseError::RepeatedNode(name), self.parser.lex_position()));
+            let next = self.next_event();
+            if let Some(Ok((_, ref span))) = next {
+                for (entry, comment) in block.sort(self.comments_before(&span.start)) {
+                    node_comment(node, &entry, comment);
+                }
+            }
+            match next {
+                Some(Ok((ParseEvent::NodeEnd, span))) => {
+                    let (entry, comments) = block.finish();
+                    for comment in comments {
+                        node_comment(node, &entry, comment);
                     }
-                    if let Some(err) = self.parse_node(node.new_node_or_get(name)) {
+                    node.set_span(Span::new(start.start, span.end));
+                    return None;
+                },
+                Some(Ok((ParseEvent::Argument(value), _))) => {
+                    node.push_argument(Value::from_parsed_value(value));
+                },
+                Some(Ok((ParseEvent::NodeStart(name, _), span))) => {
+                    if node.has_node(&name) && !self.parser.options().repeated_nodes {
+                        return Some((ParseError::RepeatedNode(name), span));
+                    }
+                    let index = node.node_count();
+                    let child = node.append_node(self.interner.intern(&name), Node::new());
+                    child.comments_mut().leading = block.start();
+                    if let Some(err) = self.parse_node(child, span) {
                         return Some(err);
                     }
+                    block.end(Entry::Node(index), self.last_end.clone());
                 },
-                Some(Ok((ParseEvent::Key(key), _))) => {
-                    match self.parse_value() {
-                        Ok(value) => { node.insert_attr(key, value); },
+                Some(Ok((ParseEvent::Key(key, _), span))) => {
+                    let leading = block.start();
+                    let (value, value_span) = match self.parse_value() {
+                        Ok(value) => value,
+                        Err(err) => { return Some(err); }
+                    };
+                    let policy = self.parser.options().duplicate_keys;
+                    let key_span = span.clone();
+                    match keys.add(policy, &key, span, value, node.get_attr_mut(&*key)) {
+                        Ok(Some(value)) => {
+                            let symbol = self.interner.intern(&key);
+                            node.insert_attr(symbol, value);
+                            node.set_attr_span(&*key, key_span, value_span);
+                        },
+                        Ok(None) => {},
                         Err(err) => { return Some(err); }
                     }
+                    if !leading.is_empty() {
+                        if let Some(comments) = node.attr_comments_mut(&key) {
+                            comments.leading.extend(leading);
+                        }
+                    }
+                    block.end(Entry::Attr(key), self.last_end.clone());
                 }
                 Some(Ok(ev)) =>
                     unreachable!("ParseEvent {:?} occurred that cannot happen at this time.", ev),
@@ -184,14 +376,15 @@ impl Figtree {
         }
     }
 
-    fn parse_value(&mut self) -> Result<Value, (ParseError, Position)> {
-        match self.parser.next() {
-            Some(Ok((ParseEvent::Value(val), _))) =>
-                Ok(Value::from_parsed_value(val)),
-            Some(Ok((ParseEvent::ListStart, _))) =>
-                self.parse_list(),
-            Some(Ok((ParseEvent::DictStart, _))) =>
-                self.parse_dict(),
+    /// Parse a value, along with the spans of it and of anything inside it
+    fn parse_value(&mut self) -> Result<(Value, ValueSpan), (ParseError, Span)> {
+        match self.next_event() {
+            Some(Ok((ParseEvent::Value(val), span))) =>
+                Ok((Value::from_parsed_value(val), ValueSpan::new(span))),
+            Some(Ok((ParseEvent::ListStart, span))) =>
+                self.parse_list(span),
+            Some(Ok((ParseEvent::DictStart, span))) =>
+                self.parse_dict(span),
             Some(Ok(ev)) =>
                 unreachable!("ParseEvent {:?} occurred that cannot happen at this time.", ev),
             Some(Err(error)) =>
@@ -201,33 +394,45 @@ impl Figtree {
         }
     }
 
-    fn parse_list(&mut self) -> Result<Value, (ParseError, Position)> {
+    fn parse_list(&mut self, start: Span) -> Result<(Value, ValueSpan), (ParseError, Span)> {
         let mut list = List::new();
+        let mut list_span = ValueSpan::new(start);
         loop {
             if matches!(self.parser.peek(), Some(&Ok((ParseEvent::ListEnd, _)))) {
-                self.parser.next();
-                return Ok(Value::List(list));
+                self.next_event();
+                list_span.span.end = self.last_end.clone();
+                return Ok((Value::List(list), list_span));
             } else {
                 match self.parse_value() {
-                    Ok(val) => list.push(val),
+                    Ok((val, span)) => {
+                        list.push(val);
+                        list_span.items.push(span);
+                    },
                     Err(err) => { return Err(err); }
                 }
             }
         }
     }
 
-    fn parse_dict(&mut self) -> Result<Value, (ParseError, Position)> {
+    fn parse_dict(&mut self, start: Span) -> Result<(Value, ValueSpan), (ParseError, Span)> {
         let mut dict = Dict::new();
+        let mut dict_span = ValueSpan::new(start);
+        let mut keys = Keys::default();
         loop {
-            match self.parser.next() {
-                Some(Ok((ParseEvent::Key(key), _))) => {
-                    match self.parse_value() {
-                        Ok(value) => { dict.insert(key, value); },
-                        Err(err) => { return Err(err); }
+            match self.next_event() {
+                Some(Ok((ParseEvent::Key(key, _), span))) => {
+                    let (value, value_span) = self.parse_value()?;
+                    let policy = self.parser.options().duplicate_keys;
+                    let key_span = span.clone();
+                    let existing = dict.get_mut(&key);
+                    if let Some(value) = keys.add(policy, &key, span, value, existing)? {
+                        dict_span.entries.insert(key.clone(), (key_span, value_span));
+                        dict.insert(key, value);
                     }
                 },
-                Some(Ok((ParseEvent::DictEnd, _))) => {
-                    return Ok(Value::Dict(dict));
+                Some(Ok((ParseEvent::DictEnd, span))) => {
+                    dict_span.span.end = span.end;
+                    return Ok((Value::Dict(dict), dict_span));
                 },
                 Some(Ok(ev)) =>
                     unreachable!("ParseEvent {:?} occurred that cannot happen at this time.", ev),
@@ -239,6 +444,125 @@ impl Figtree {
     }
 }
 
+/// An entry of a node or document that comments can be attached to
+#[derive(Clone)]
+enum Entry {
+    /// The node or document that the entries belong to
+    Owner,
+    /// A subnode, by its position
+    Node(usize),
+    Attr(String),
+}
+
+/// Works out where the comments inside a node or document belong
+#[derive(Default)]
+struct Block {
+    /// the most recently finished entry, and where it ended
+    last: Option<(Entry, Position)>,
+    /// comments waiting to be attached to the next entry
+    leading: Vec<String>,
+}
+
+impl Block {
+    /// Sort comments into leading comments of the next entry, and trailing comments of
+    /// the last one, which are returned
+    fn sort(&mut self, comments: Vec<(String, Span)>) -> Vec<(Entry, String)> {
+        let mut trailing = Vec::new();
+        for (comment, span) in comments {
+            match self.last {
+                Some((ref entry, ref end)) if span.start < *end || span.start.line == end.line =>
+                    trailing.push((entry.clone(), comment)),
+                _ => self.leading.push(comment),
+            }
+        }
+        trailing
+    }
+
+    /// Start an entry, returning its leading comments
+    fn start(&mut self) -> Vec<String> {
+        self.last = None;
+        mem::take(&mut self.leading)
+    }
+
+    fn end(&mut self, entry: Entry, end: Position) {
+        self.last = Some((entry, end));
+    }
+
+    /// Finish the block.  Any comments left over are trailing comments of the last entry.
+    fn finish(&mut self) -> (Entry, Vec<String>) {
+        let entry = self.last.take().map_or(Entry::Owner, |last| last.0);
+        (entry, mem::take(&mut self.leading))
+    }
+}
+
+/// Attach a trailing comment to an entry of a node
+fn node_comment(node: &mut Node, entry: &Entry, comment: String) {
+    let comments = match *entry {
+        Entry::Owner => Some(node.comments_mut()),
+        Entry::Node(index) => node.children_mut().nth(index).map(Node::comments_mut),
+        Entry::Attr(ref key) => node.attr_comments_mut(key),
+    };
+    if let Some(comments) = comments {
+        comments.trailing.push(comment);
+    }
+}
+
+/// Attach a trailing comment to an entry of a document
+fn doc_comment(doc: &mut Document, entry: &Entry, comment: String) {
+    match *entry {
+        Entry::Node(index) => if let Some(node) = doc.children_mut().nth(index) {
+            node.comments_mut().trailing.push(comment);
+        },
+        _ => doc.comments_mut().push(comment),
+    }
+}
+
+/// The keys seen so far in a node or dict, for applying the `DuplicateKeyPolicy`
+#[derive(Default)]
+struct Keys {
+    spans: HashMap<String, Span>,
+    /// keys whose values have already been collected into a list
+    collected: HashSet<String>,
+}
+
+impl Keys {
+    /// Add a value for `key`, given the value already stored for it, if any.  Returns
+    /// the value that should now be stored, or `None` if the stored value has been
+    /// updated or kept in place.
+    fn add(&mut self, policy: DuplicateKeyPolicy, key: &str, span: Span, value: Value,
+        existing: Option<&mut Value>) -> Result<Option<Value>, (ParseError, Span)> {
+
+        let existing = match existing {
+            Some(existing) => existing,
+            None => {
+                if policy == DuplicateKeyPolicy::Error {
+                    self.spans.insert(key.to_string(), span);
+                }
+                return Ok(Some(value));
+            },
+        };
+
+        match policy {
+            DuplicateKeyPolicy::LastWins => Ok(Some(value)),
+            DuplicateKeyPolicy::FirstWins => Ok(None),
+            DuplicateKeyPolicy::Error => {
+                let first = self.spans[key].clone();
+                Err((ParseError::DuplicateKey(key.to_string(), first), span))
+            },
+            DuplicateKeyPolicy::CollectList => {
+                if self.collected.insert(key.to_string()) {
+                    let first = mem::replace(existing, Value::new_null());
+                    *existing = Value::List(vec![first]);
+                }
+                if let Value::List(ref mut list) = *existing {
+                    list.push(value);
+                }
+                Ok(None)
+            },
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::Figtree;
@@ -295,5 +619,286 @@ mod tests {
             dict.insert("dict".to_string(), Value::Dict(HashMap::new()));
             dict
         }));
+
+        let mut figgy = Figtree::from_string("node { 'list': [{'a': 1}, {}] }");
+        let config = figgy.parse().unwrap();
+        let node = config.get_node("node").unwrap();
+        assert_eq!(node.get_attr("list").unwrap(), &Value::List(vec![
+            Value::Dict({
+                let mut dict = Dict::new();
+                dict.insert("a".to_string(), Value::new_int(1));
+                dict
+            }),
+            Value::Dict(Dict::new())]));
+    }
+
+    #[test]
+    fn unicode_identifiers() {
+        let mut figgy = Figtree::from_string("daten { größe = 1 }");
+        let config = figgy.parse().unwrap();
+        let node = config.get_node("daten").unwrap();
+        assert_eq!(node.get_attr("größe").unwrap(), &Value::new_int(1));
+    }
+
+    #[test]
+    fn quoted_names() {
+        let mut figgy = Figtree::from_string("'my node' { \"my key\" = 1 }");
+        let config = figgy.parse().unwrap();
+        let node = config.get_node("my node").unwrap();
+        assert_eq!(node.get_attr("my key").unwrap(), &Value::new_int(1));
+    }
+
+    #[test]
+    fn datetime_values() {
+        let mut figgy = Figtree::from_string("node { at = 2024-06-01T08:30:00+02:00 }");
+        let config = figgy.parse().unwrap();
+        let datetime = config.get_node("node").unwrap().get_attr("at").unwrap().get_datetime().unwrap();
+        assert_eq!((datetime.year, datetime.month, datetime.day), (2024, 6, 1));
+        assert_eq!(datetime.offset, Some(120));
+    }
+
+    #[test]
+    #[cfg(feature = "encoding")]
+    fn decoding_errors() {
+        use encoding_rs::SHIFT_JIS;
+        use super::super::parser::ParseError;
+        use super::super::lexer::LexError;
+        use super::super::position::{Position, Span};
+        use std::io::Cursor;
+
+        let input = Cursor::new(b"node {\n  key = '\x81\x20' }".to_vec());
+        let err = Figtree::with_encoding(input, SHIFT_JIS).parse().unwrap_err();
+        assert_eq!(err, (ParseError::LexError(LexError::DecodeError("Shift_JIS")), Span::new(Position::at(1, 9), Position::at(1, 9))));
+    }
+
+    #[test]
+    fn errors_inside_lists() {
+        let mut figgy = Figtree::from_string("node { 'list': [1, 2 3] }");
+        assert!(figgy.parse().is_err());
+    }
+
+    #[test]
+    fn documents_parse_themselves() {
+        use std::io::Cursor;
+        use super::super::parser::ParseError;
+
+        let doc = Document::parse(Cursor::new("node { sub { a = [1] } } other {}")).unwrap();
+        assert_eq!(doc, Figtree::from_string("node { sub { a = [1] } } other {}").parse().unwrap());
+        assert_eq!(Document::parse(Cursor::new("node {} node {}")).unwrap_err().0,
+            ParseError::RepeatedNode("node".to_string()));
+    }
+
+    #[test]
+    fn repeated_nodes() {
+        use super::super::parser::ParserOptions;
+
+        let input = "a { x = 1 } b { n { v = 1 } m {} n { v = 2 } } a { x = 2 }";
+        let options = ParserOptions::new().repeated_nodes(true);
+        let config = Figtree::from_string(input).with_options(options).parse().unwrap();
+        let names: Vec<_> = config.children().map(Node::name).collect();
+        assert_eq!(names, vec!["a", "b", "a"]);
+        let xs: Vec<_> = config.children_named("a").map(|node| node.get_i64("x").unwrap()).collect();
+        assert_eq!(xs, vec![1, 2]);
+        let b = &config["b"];
+        assert_eq!(b.children_named("n").map(|node| &node["v"]).collect::<Vec<_>>(),
+            vec![&Value::new_int(1), &Value::new_int(2)]);
+        assert_eq!(b[1].name(), "m");
+        assert!(Figtree::from_string(input).parse().is_err());
+    }
+
+    #[test]
+    fn duplicate_keys() {
+        use super::super::parser::{ParserOptions, ParseError, DuplicateKeyPolicy};
+        use super::super::position::{Position, Span};
+
+        let input = "node { a = 1, b = 2, a = 3, d = { k = 'x', k = 'y' }, a = [4] }";
+        let parse = |policy| {
+            let options = ParserOptions::new().duplicate_keys(policy);
+            Figtree::from_string(input).with_options(options).parse()
+        };
+
+        let config = parse(DuplicateKeyPolicy::LastWins).unwrap();
+        assert_eq!(config["node"]["a"], Value::List(vec![Value::new_int(4)]));
+        assert_eq!(config["node"]["d"]["k"], Value::new_string("y"));
+        let keys: Vec<_> = config["node"].properties().map(|(key, _)| key.as_str()).collect();
+        assert_eq!(keys, vec!["a", "b", "d"]);
+
+        let config = parse(DuplicateKeyPolicy::FirstWins).unwrap();
+        assert_eq!(config["node"]["a"], Value::new_int(1));
+        assert_eq!(config["node"]["d"]["k"], Value::new_string("x"));
+
+        let config = parse(DuplicateKeyPolicy::CollectList).unwrap();
+        assert_eq!(config["node"]["a"], Value::List(vec![
+            Value::new_int(1), Value::new_int(3), Value::List(vec![Value::new_int(4)])]));
+        assert_eq!(config["node"]["b"], Value::new_int(2));
+        assert_eq!(config["node"]["d"]["k"],
+            Value::List(vec![Value::new_string("x"), Value::new_string("y")]));
+
+        let error = parse(DuplicateKeyPolicy::Error).unwrap_err();
+        let first = Span::new(Position::at(0, 7), Position::at(0, 10));
+        let repeated = Span::new(Position::at(0, 21), Position::at(0, 24));
+        assert_eq!(error, (ParseError::DuplicateKey("a".to_string(), first), repeated));
+    }
+
+    #[test]
+    fn node_arguments() {
+        use super::super::parser::ParserOptions;
+
+        let parse = |input: &str| {
+            let options = ParserOptions::new().node_arguments(true);
+            Figtree::from_string(input).with_options(options).parse().unwrap()
+        };
+        let config = parse("listen 8080 'tcp' { sub !x { a = 1 } }");
+        let listen = &config["listen"];
+        assert_eq!(listen.arguments(), &[Value::new_int(8080), Value::new_string("tcp")]);
+        assert_eq!(listen[0].arguments(), &[Value::new_ident("x")]);
+        assert_eq!(listen[0].get_i64("a"), Ok(1));
+        assert_ne!(config, parse("listen 'tcp' 8080 { sub !x { a = 1 } }"));
+        assert!(!Node::builder("n").arg(1).build().is_empty());
+    }
+
+    #[test]
+    fn attaches_comments() {
+        use super::super::parser::ParserOptions;
+
+        let input = "# the main server
+server { # trailing the node
+    // about the port
+    // (really)
+    port = 80, # the default
+    hosts = [
+        'a', # inside the list
+    ],
+    /* the tls settings */
+    tls {
+        # nothing here yet
+    } // after tls
+    # left over
+}
+# about other
+other {} # after other
+# the end";
+        let options = ParserOptions::new().comments(true);
+        let doc = Figtree::from_string(input).with_options(options).parse().unwrap();
+        let strings = |comments: &[&str]| comments.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+
+        let server = &doc["server"];
+        assert_eq!(server.comments(), &Comments {
+            leading: strings(&["# the main server"]),
+            trailing: strings(&["# trailing the node"]),
+        });
+        assert_eq!(server.attr_comments("port"), Some(&Comments {
+            leading: strings(&["// about the port", "// (really)"]),
+            trailing: strings(&["# the default"]),
+        }));
+        assert_eq!(server.attr_comments("hosts").unwrap().trailing, strings(&["# inside the list"]));
+        assert_eq!(server[0].comments(), &Comments {
+            leading: strings(&["/* the tls settings */"]),
+            trailing: strings(&["# nothing here yet", "// after tls", "# left over"]),
+        });
+        assert_eq!(doc["other"].comments(), &Comments {
+            leading: strings(&["# about other"]),
+            trailing: strings(&["# after other", "# the end"]),
+        });
+        assert!(doc.comments().is_empty());
+
+        // comments don't affect equality, and are only kept when asked for
+        let plain = Figtree::from_string(input).parse().unwrap();
+        assert_eq!(doc, plain);
+        assert!(plain["server"].comments().is_empty());
+        assert_eq!(plain["server"].attr_comments("port"), None);
+
+        let options = ParserOptions::new().comments(true);
+        let doc = Figtree::from_string("// just a comment\n").with_options(options).parse().unwrap();
+        assert_eq!(doc.comments(), &["// just a comment".to_string()]);
+    }
+
+    #[test]
+    fn records_spans() {
+        use super::super::position::{Position, Span};
+
+        let span = |start: (usize, usize), end: (usize, usize)|
+            Span::new(Position::at(start.0, start.1), Position::at(end.0, end.1));
+        let input = "server {\n  port = 80,\n  tls { d = { k = [true] } }\n}";
+        let doc = Figtree::from_string(input).parse().unwrap();
+        let server = &doc["server"];
+        assert_eq!(server.span(), Some(&span((0, 0), (3, 1))));
+        assert_eq!(server.key_span("port"), Some(&span((1, 2), (1, 8))));
+        assert_eq!(server.value_span("port"), Some(&ValueSpan::new(span((1, 9), (1, 11)))));
+
+        let tls = &server[0];
+        assert_eq!(tls.span(), Some(&span((2, 2), (2, 28))));
+        let d = tls.value_span("d").unwrap();
+        assert_eq!(d.span, span((2, 12), (2, 26)));
+        let (ref key, ref k) = d.entries["k"];
+        assert_eq!(*key, span((2, 14), (2, 17)));
+        assert_eq!(k.span, span((2, 18), (2, 24)));
+        assert_eq!(k.items, vec![ValueSpan::new(span((2, 19), (2, 23)))]);
+
+        assert_eq!(server.get_str("port").unwrap_err().span, Some(span((1, 9), (1, 11))));
+        assert_eq!(tls.get_str("missing").unwrap_err().to_string(),
+            "expected a string \"missing\" in node \"tls\", but it is missing (at line 3, column 3)");
+
+        let mut server = server.clone();
+        server.insert_attr("port", Value::new_int(443));
+        assert_eq!(server.value_span("port"), None);
+        assert_eq!(Node::named("new").span(), None);
+    }
+
+    #[test]
+    fn interns_names_and_keys() {
+        use super::super::parser::ParserOptions;
+
+        let input = "a { port = 1 // one\n b { port = 2 } } b { a {} }";
+        let options = ParserOptions::new().comments(true);
+        let mut doc = Figtree::from_string(input).with_options(options).parse().unwrap();
+        assert_eq!(doc.interner().len(), 3);
+        let port = doc.interner().get("port").unwrap();
+        let key = |node: &Node| node.properties().next().unwrap().0.clone();
+        assert!(key(&doc["a"]).ptr_eq(&port));
+        assert!(key(&doc["a"][0]).ptr_eq(&port));
+        let b = doc.iter_nodes().nth(1).unwrap().0;
+        assert!(b.ptr_eq(doc["a"].iter_nodes().next().unwrap().0));
+
+        let a = doc.get_node_mut("a").unwrap();
+        a.delete_node("b");
+        a.insert_attr("extra", Value::new_null());
+        doc.delete_node("b");
+        doc.intern_all();
+        // "b" isn't used any more, but "extra" now is
+        assert_eq!(doc.interner().len(), 3);
+        assert!(doc.interner().get("b").is_none());
+        assert!(key(&doc["a"]).ptr_eq(&doc.interner().get("port").unwrap()));
+        assert_eq!(doc["a"].attr_comments("port").unwrap().trailing, vec!["// one"]);
+        assert!(doc["a"].value_span("port").is_some());
+        assert_eq!(doc["a"].get_attr("extra"), Some(&Value::new_null()));
+    }
+
+    #[test]
+    fn records_origins() {
+        use super::super::merge::MergeStrategy;
+        use super::super::position::Origin;
+
+        let base = Origin::File("base.fig".into());
+        let local = Origin::Url("http://config/local.fig".to_string());
+        let mut config = Figtree::from_string("server { port = 80 }").with_origin(base.clone())
+            .parse().unwrap();
+        let overrides = Figtree::from_string("server { tls = 'yes' } log { path = 1 }")
+            .with_origin(local.clone()).parse().unwrap();
+        config.merge(&overrides, MergeStrategy::LaterWins).unwrap();
+        // merged nodes keep saying where they came from
+        assert_eq!(config.origin(), Some(&base));
+        assert_eq!(config["server"].origin(), Some(&base));
+        assert_eq!(config["log"].origin(), Some(&local));
+        assert_eq!(config["log"].get_str("path").unwrap_err().to_string(), "expected \"path\" in \
+            node \"log\" to be a string, found an integer (in http://config/local.fig, at line 1, \
+            column 37)");
+
+        let config = Figtree::from_string("a { b {} }").parse().unwrap();
+        assert_eq!(config["a"][0].origin(), Some(&Origin::Inline));
+        let config = Document::parse(::std::io::Cursor::new("a {}")).unwrap();
+        assert_eq!(config.origin(), None);
+        assert_eq!(config["a"].origin(), None);
+        assert_eq!(Document::new().origin(), None);
     }
 }
@@ -0,0 +1,92 @@
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+
+use super::path::split_last;
+use super::types::{Node, Value};
+
+/// A change that is about to be made to a document, which is given to the document's
+/// mutation hooks to allow or reject.  See `Document::add_mutation_hook`.
+///
+/// Paths are written in the same form as the paths of a `Patch`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Mutation<'a> {
+    /// An attribute or dict entry is being set to a value, which it may or may not have
+    /// already
+    SetValue(&'a str, &'a Value),
+    /// A node is being added, or is replacing the node at the path.  The node is given
+    /// the name at the end of the path.
+    SetNode(&'a str, &'a Node),
+    /// The entry at the path is being removed
+    Remove(&'a str),
+    /// The subnodes of the node at the path are being put in a different order.  The
+    /// path is empty for the nodes of the document itself.
+    Reorder(&'a str),
+}
+
+impl<'a> Mutation<'a> {
+    /// The path of the entry that is being changed
+    pub fn path(&self) -> &'a str {
+        match *self {
+            Mutation::SetValue(path, _) | Mutation::SetNode(path, _) | Mutation::Remove(path)
+                | Mutation::Reorder(path) => path,
+        }
+    }
+
+    /// The key or node name at the end of the path, without any index or quotes
+    pub fn name(&self) -> Cow<'a, str> {
+        split_last(self.path()).map_or(Cow::Borrowed(""), |(_, last)| last.name)
+    }
+}
+
+/// The error produced when a mutation hook rejects a change, by the `try_*` methods of a
+/// `Document` that add, remove and sort its nodes.  See `Document::add_mutation_hook`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct HookError {
+    /// The path of the entry that was being changed
+    pub path: String,
+    /// The error message of the hook that rejected the change
+    pub reason: String,
+}
+
+impl fmt::Display for HookError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "cannot change {:?}: {}", self.path, self.reason)
+    }
+}
+
+impl Error for HookError {}
+
+type Hook = dyn Fn(&Mutation) -> Result<(), String> + Send + Sync;
+
+/// The mutation hooks of a document, which are shared between its clones
+#[derive(Clone, Default)]
+pub struct MutationHooks {
+    hooks: Vec<Arc<Hook>>,
+}
+
+impl MutationHooks {
+    pub fn push(&mut self, hook: Arc<Hook>) {
+        self.hooks.push(hook);
+    }
+
+    pub fn clear(&mut self) {
+        self.hooks.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+
+    /// Ask each hook in turn, stopping at the first to reject the change
+    pub fn check(&self, mutation: &Mutation) -> Result<(), String> {
+        self.hooks.iter().try_for_each(|hook| hook(mutation))
+    }
+}
+
+impl fmt::Debug for MutationHooks {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "MutationHooks({})", self.hooks.len())
+    }
+}
@@ -0,0 +1,457 @@
+use std::error::Error;
+use std::fmt;
+use std::mem;
+
+use super::position::Position;
+use super::types::{Document, Node, Value};
+
+/// The error produced when INI can't be imported as a `Document`
+#[derive(Debug, PartialEq, Clone)]
+pub struct IniError {
+    /// What the problem is
+    pub message: String,
+    /// Where the problem is in the INI
+    pub position: Position,
+}
+
+impl IniError {
+    fn new<S: Into<String>>(message: S, line: usize, pos: usize) -> Self {
+        IniError { message: message.into(), position: Position::at(line, pos) }
+    }
+}
+
+impl fmt::Display for IniError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (line {}, column {})", self.message, self.position.line + 1,
+               self.position.pos + 1)
+    }
+}
+
+impl Error for IniError {}
+
+/// Options controlling how INI is imported into a `Document`
+///
+/// # Examples
+/// ```
+/// # use figtree::types::{Document, Value};
+/// # use figtree::IniOptions;
+/// let ini = "[server.tls]\nport = 443";
+/// let doc = Document::from_ini(ini).unwrap();
+/// assert_eq!(doc["server.tls"].get("port"), Some(&Value::new_int(443)));
+///
+/// let options = IniOptions::new().nested_sections(true).typed_values(false);
+/// let doc = Document::from_ini_with_options(ini, &options).unwrap();
+/// let tls = doc["server"].child("tls").unwrap();
+/// assert_eq!(tls.get("port"), Some(&Value::from("443")));
+/// ```
+#[derive(Debug, PartialEq, Clone)]
+pub struct IniOptions {
+    /// Whether unquoted values that look like booleans or numbers are imported as them
+    pub typed_values: bool,
+    /// Whether section names are split on `.` into nested nodes
+    pub nested_sections: bool,
+}
+
+impl IniOptions {
+    /// Construct the default set of options
+    pub fn new() -> Self {
+        IniOptions {
+            typed_values: true,
+            nested_sections: false,
+        }
+    }
+
+    /// Import unquoted values that are `true` or `false` (in any case), integers or
+    /// floats as booleans, integers and floats, rather than strings (the default)
+    pub fn typed_values(mut self, enabled: bool) -> Self {
+        self.typed_values = enabled;
+        self
+    }
+
+    /// Split section names on `.`, so `[server.tls]` is a `tls` node inside a `server`
+    /// node, rather than a node called `server.tls`
+    pub fn nested_sections(mut self, enabled: bool) -> Self {
+        self.nested_sections = enabled;
+        self
+    }
+}
+
+impl Default for IniOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A section header: the name of each node in its path, and the argument of the last one
+#[derive(Debug, PartialEq, Clone)]
+struct Section {
+    names: Vec<String>,
+    argument: Option<String>,
+}
+
+/// An entry whose value might still be continued on the lines after it
+struct Entry {
+    key: String,
+    /// the value, or `None` if the entry has no `=` or `:`
+    value: Option<String>,
+    quoted: bool,
+}
+
+/// Check if a node is the one for a section, which is a node with the same name, and the
+/// section's argument as its only argument if it has one
+fn is_section(node: &Node, name: &str, argument: Option<&str>) -> bool {
+    node.name() == name && match argument {
+        Some(argument) => node.arguments() == [Value::from(argument)],
+        None => node.arguments().is_empty(),
+    }
+}
+
+/// Find the node for a section in the document, adding it and any nodes around it that
+/// aren't there yet
+fn section_node<'a>(document: &'a mut Document, section: &Section) -> &'a mut Node {
+    let last = section.names.len() - 1;
+    let argument = |index| if index == last { section.argument.as_deref() } else { None };
+    let new_node = |index| {
+        let mut node = Node::new();
+        if let Some(argument) = argument(index) {
+            node.push_argument(Value::from(argument));
+        }
+        node
+    };
+
+    let name = &section.names[0];
+    let mut node = match document.children().position(|node| is_section(node, name, argument(0))) {
+        Some(index) => document.children_mut().nth(index).expect("the node has been found"),
+        None => document.append_node(name.as_str(), new_node(0)),
+    };
+    for (index, name) in section.names.iter().enumerate().skip(1) {
+        node = match node.children().position(|node| is_section(node, name, argument(index))) {
+            Some(position) => node.children_mut().nth(position).expect("the node has been found"),
+            None => {
+                node.append_node(name.as_str(), new_node(index));
+                node.children_mut().last().expect("the node has been added")
+            },
+        };
+    }
+    node
+}
+
+/// Add an entry to a node, with a key that's already there making a list of every value
+/// it has been given
+fn add_entry(node: &mut Node, key: &str, value: Value) {
+    match node.get_attr_mut(key) {
+        Some(&mut Value::List(ref mut items)) => items.push(value),
+        Some(existing) => {
+            let first = mem::replace(existing, Value::Null);
+            *existing = Value::List(vec![first, value]);
+        },
+        None => {
+            node.insert_attr(key, value);
+        },
+    }
+}
+
+/// Import a value, as a boolean or a number if it looks like one and `typed` is set
+fn import_value(text: String, quoted: bool, typed: bool) -> Value {
+    if quoted || !typed {
+        return Value::Str(text);
+    }
+    if text.eq_ignore_ascii_case("true") {
+        return Value::Bool(true);
+    }
+    if text.eq_ignore_ascii_case("false") {
+        return Value::Bool(false);
+    }
+    if let Ok(int) = text.parse() {
+        return Value::Int(int);
+    }
+    // Rust also parses `inf` and `nan`, which are more likely to be words
+    let numeric = text.chars().any(|c| c.is_ascii_digit())
+        && text.chars().all(|c| c.is_ascii_digit() || matches!(c, '+' | '-' | '.' | 'e' | 'E'));
+    match text.parse() {
+        Ok(float) if numeric => Value::Float(float),
+        _ => Value::Str(text),
+    }
+}
+
+/// Remove a comment from the end of an unquoted value, which starts with `;` or `#`
+/// after some whitespace
+fn strip_comment(text: &str) -> &str {
+    let mut previous = ' ';
+    for (index, c) in text.char_indices() {
+        if (c == ';' || c == '#') && previous.is_whitespace() {
+            return text[..index].trim_end();
+        }
+        previous = c;
+    }
+    text.trim_end()
+}
+
+/// Read a section header, from the line after its `[`
+fn read_section(text: &str, line: usize, options: &IniOptions) -> Result<Section, IniError> {
+    let end = text.find(']')
+        .ok_or_else(|| IniError::new("unterminated section header", line, 0))?;
+    let rest = text[end + 1..].trim_start();
+    if !rest.is_empty() && !rest.starts_with(';') && !rest.starts_with('#') {
+        // the position is in the whole line, which starts with the `[`
+        let start = text.len() - rest.len() + 1;
+        return Err(IniError::new("unexpected text after section header", line, start));
+    }
+    let header = text[..end].trim();
+    let (name, argument) = match header.find('"') {
+        Some(start) if header.ends_with('"') && start < header.len() - 1 => {
+            let argument = &header[start + 1..header.len() - 1];
+            (header[..start].trim_end(), Some(argument.to_string()))
+        },
+        Some(start) => return Err(IniError::new("unterminated section argument", line, start + 1)),
+        None => (header, None),
+    };
+    let names: Vec<String> = if options.nested_sections {
+        name.split('.').map(|name| name.trim().to_string()).collect()
+    } else {
+        vec![name.to_string()]
+    };
+    if names.iter().any(String::is_empty) {
+        return Err(IniError::new("empty section name", line, 1));
+    }
+    Ok(Section { names, argument })
+}
+
+/// Read an entry, from its line
+fn read_entry(text: &str, line: usize) -> Result<Entry, IniError> {
+    let (key, value) = match text.find(['=', ':']) {
+        Some(index) => (&text[..index], Some((&text[index + 1..], index + 1))),
+        None => (text, None),
+    };
+    let key = key.trim();
+    if key.is_empty() {
+        return Err(IniError::new("expected a key", line, 0));
+    }
+    let (value, start) = match value {
+        Some((value, start)) => (value, start + value.len() - value.trim_start().len()),
+        None => return Ok(Entry { key: key.into(), value: None, quoted: false }),
+    };
+    let value = value.trim_start();
+    match value.chars().next() {
+        Some(quote) if quote == '"' || quote == '\'' => {
+            let end = value[1..].find(quote)
+                .ok_or_else(|| IniError::new("unterminated string", line, start))?;
+            let rest = value[end + 2..].trim_start();
+            if !rest.is_empty() && !rest.starts_with(';') && !rest.starts_with('#') {
+                let position = start + value.len() - rest.len();
+                return Err(IniError::new("unexpected text after string", line, position));
+            }
+            Ok(Entry { key: key.into(), value: Some(value[1..end + 1].into()), quoted: true })
+        },
+        _ => Ok(Entry { key: key.into(), value: Some(strip_comment(value).into()), quoted: false }),
+    }
+}
+
+/// Add an entry to its section, or to `root` if it comes before the first section
+fn add(sections: &mut Document, root: &mut Node, section: &Option<Section>, entry: Entry,
+       options: &IniOptions) {
+    let value = match entry.value {
+        Some(value) => import_value(value, entry.quoted, options.typed_values),
+        None => Value::Null,
+    };
+    match *section {
+        Some(ref section) => add_entry(section_node(sections, section), &entry.key, value),
+        None => add_entry(root, &entry.key, value),
+    }
+}
+
+impl Document {
+    /// Import a document from INI, with the default options.  Each section becomes a
+    /// node, and its entries become attributes, separated from their keys with `=` or
+    /// `:`.  Entries before the first section become nodes with the value as their single
+    /// argument, in the same way as the top-level values of TOML.
+    ///
+    /// A section with a quoted name after its own (`[remote "origin"]`, as git writes
+    /// them) becomes a node with the quoted name as its argument.  A section that comes
+    /// more than once adds to the same node, and a key that comes more than once in a
+    /// section becomes a list of all of its values.
+    ///
+    /// Values can be quoted with `"` or `'`, which are removed, and unquoted values end
+    /// at a `;` or `#` after whitespace, which starts a comment.  An indented line
+    /// continues the value of the entry before it, on a new line.  An entry with no `=`
+    /// or `:` becomes a `null` attribute.
+    ///
+    /// # Failures
+    /// Fails if a section header isn't closed, a line has no key, or a quoted value isn't
+    /// closed.
+    ///
+    /// # Examples
+    /// ```
+    /// # use figtree::types::{Document, Value};
+    /// let ini = "
+    /// ; written by hand
+    /// [core]
+    /// bare = false
+    /// editor = vim -u NONE  ; no plugins
+    ///
+    /// [remote \"origin\"]
+    /// url = https://example.com/figtree.git
+    /// fetch = +refs/heads/*:refs/remotes/origin/*
+    /// ";
+    /// let doc = Document::from_ini(ini).unwrap();
+    /// assert_eq!(doc["core"].get("bare"), Some(&Value::new_bool(false)));
+    /// assert_eq!(doc["core"].get("editor"), Some(&Value::from("vim -u NONE")));
+    /// assert_eq!(doc["remote"].arguments(), &[Value::from("origin")][..]);
+    /// ```
+    pub fn from_ini(text: &str) -> Result<Document, IniError> {
+        Document::from_ini_with_options(text, &IniOptions::new())
+    }
+
+    /// Import a document from INI, in the way described by `options`
+    pub fn from_ini_with_options(text: &str, options: &IniOptions)
+                                 -> Result<Document, IniError> {
+        let mut sections = Document::new();
+        // the entries before the first section, which become top-level nodes
+        let mut root = Node::new();
+        let mut section: Option<Section> = None;
+        let mut entry: Option<Entry> = None;
+
+        for (line, text) in text.lines().enumerate() {
+            let trimmed = text.trim();
+            if trimmed.starts_with(';') || trimmed.starts_with('#') {
+                continue;
+            }
+            if text.starts_with([' ', '\t']) && !trimmed.is_empty() {
+                if let Some(Entry { value: Some(ref mut value), quoted: false, .. }) = entry {
+                    value.push('\n');
+                    value.push_str(strip_comment(trimmed));
+                    continue;
+                }
+            }
+            if let Some(entry) = entry.take() {
+                add(&mut sections, &mut root, &section, entry, options);
+            }
+            if trimmed.is_empty() {
+                continue;
+            }
+            let offset = text.len() - text.trim_start().len();
+            let read = match trimmed.strip_prefix('[') {
+                Some(header) => read_section(header, line, options).map(|header| {
+                    section_node(&mut sections, &header);
+                    section = Some(header);
+                }),
+                None => read_entry(trimmed, line).map(|read| entry = Some(read)),
+            };
+            read.map_err(|mut error| {
+                error.position.pos += offset;
+                error
+            })?;
+        }
+        if let Some(entry) = entry.take() {
+            add(&mut sections, &mut root, &section, entry, options);
+        }
+
+        let mut document = Document::new();
+        for (key, value) in root.iter_attrs() {
+            let mut node = Node::new();
+            match *value {
+                Value::List(ref items) => for item in items {
+                    node.push_argument(item.clone());
+                },
+                ref value => node.push_argument(value.clone()),
+            }
+            document.append_node(key.to_string(), node);
+        }
+        while let Some(node) = sections.detach(0) {
+            document.adopt(node);
+        }
+        Ok(document)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(ini: &str) -> String {
+        Document::from_ini(ini).unwrap_err().to_string()
+    }
+
+    const INPUT: &str = "
+name = figtree
+name = figtree-derive
+; comment
+# comment
+
+[server]
+host = 'example.com'  ; quoted
+port: 8080
+ratio = 0.5
+debug = TRUE
+version = 1e
+motd = first line
+    second line  # comment
+  third line
+flag
+path = /a;b
+path = /c
+
+[remote \"origin\"]
+url = a
+[remote \"fork\"]
+url = b
+[server]
+timeout = 5
+";
+
+    #[test]
+    fn imports_sections() {
+        let doc = Document::from_ini(INPUT).unwrap();
+        let names: Vec<_> = doc.children().map(|node| node.name()).collect();
+        assert_eq!(names, ["name", "server", "remote", "remote"]);
+        assert_eq!(doc["name"].arguments(),
+                   &[Value::from("figtree"), Value::from("figtree-derive")][..]);
+
+        let server = &doc["server"];
+        assert_eq!(server.get("host"), Some(&Value::from("example.com")));
+        assert_eq!(server.get("port"), Some(&Value::new_int(8080)));
+        assert_eq!(server.get("ratio"), Some(&Value::new_float(0.5)));
+        assert_eq!(server.get("debug"), Some(&Value::new_bool(true)));
+        assert_eq!(server.get("version"), Some(&Value::from("1e")));
+        assert_eq!(server.get("motd"), Some(&Value::from("first line\nsecond line\nthird line")));
+        assert_eq!(server.get("flag"), Some(&Value::Null));
+        assert_eq!(server.get("path"), Some(&Value::from(vec![Value::from("/a;b"),
+                                                               Value::from("/c")])));
+        assert_eq!(server.get("timeout"), Some(&Value::new_int(5)));
+
+        let remotes: Vec<_> = doc.children_named("remote")
+            .map(|node| (node.arguments()[0].clone(), node.get("url").cloned().unwrap()))
+            .collect();
+        assert_eq!(remotes, [(Value::from("origin"), Value::from("a")),
+                             (Value::from("fork"), Value::from("b"))]);
+    }
+
+    #[test]
+    fn uses_options() {
+        let ini = "[a.b]\nx = 1\n[a]\ny = true\n[a.b \"c\"]\nz = 2";
+        let options = IniOptions::new().nested_sections(true).typed_values(false);
+        let doc = Document::from_ini_with_options(ini, &options).unwrap();
+        assert_eq!(doc.children().count(), 1);
+        let a = &doc["a"];
+        assert_eq!(a.get("y"), Some(&Value::from("true")));
+        let bs: Vec<_> = a.children_named("b").collect();
+        assert_eq!(bs.len(), 2);
+        assert_eq!(bs[0].get("x"), Some(&Value::from("1")));
+        assert_eq!(bs[1].arguments(), &[Value::from("c")][..]);
+        assert_eq!(bs[1].get("z"), Some(&Value::from("2")));
+
+        let doc = Document::from_ini("[a.b]\nx = 1").unwrap();
+        assert_eq!(doc.children().next().map(Node::name), Some("a.b"));
+    }
+
+    #[test]
+    fn reports_errors() {
+        assert_eq!(error("[server\nport = 1"), "unterminated section header (line 1, column 1)");
+        assert_eq!(error("[server] x"), "unexpected text after section header (line 1, column 10)");
+        assert_eq!(error("[]"), "empty section name (line 1, column 2)");
+        assert_eq!(error("[remote \"a]"), "unterminated section argument (line 1, column 9)");
+        assert_eq!(error("\n  = 1"), "expected a key (line 2, column 3)");
+        assert_eq!(error("a = 'b"), "unterminated string (line 1, column 5)");
+        assert_eq!(error("a = 'b' c"), "unexpected text after string (line 1, column 9)");
+    }
+}
@@ -0,0 +1,198 @@
+use std::borrow::Borrow;
+use std::collections::HashSet;
+use std::fmt;
+use std::ops::Deref;
+use std::sync::Arc;
+
+/// A shared, immutable string, used for the names of nodes and the keys of attributes.
+///
+/// Cloning a symbol only copies a pointer to the string, so when a document's names and
+/// keys are interned (see `Interner`), each distinct string is only stored once however
+/// many times it is used.  Symbols dereference to `str`, and compare and hash in the
+/// same way as the strings they contain, so they can be used anywhere a `&str` can.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Symbol(Arc<str>);
+
+impl Symbol {
+    /// The string the symbol contains
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Test if two symbols share the same string, such as when they were interned by
+    /// the same `Interner`
+    pub fn ptr_eq(&self, other: &Symbol) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Borrow<str> for Symbol {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for Symbol {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&*self.0, f)
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl<'a> From<&'a str> for Symbol {
+    fn from(string: &'a str) -> Self {
+        Symbol(string.into())
+    }
+}
+
+impl<'a> From<&'a String> for Symbol {
+    fn from(string: &'a String) -> Self {
+        Symbol(string.as_str().into())
+    }
+}
+
+impl From<String> for Symbol {
+    fn from(string: String) -> Self {
+        Symbol(string.into())
+    }
+}
+
+impl<'a> From<&'a Symbol> for Symbol {
+    fn from(symbol: &'a Symbol) -> Self {
+        symbol.clone()
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        *self.0 == *other
+    }
+}
+
+impl<'a> PartialEq<&'a str> for Symbol {
+    fn eq(&self, other: &&'a str) -> bool {
+        *self.0 == **other
+    }
+}
+
+impl PartialEq<String> for Symbol {
+    fn eq(&self, other: &String) -> bool {
+        *self.0 == **other
+    }
+}
+
+/// A set of symbols, which makes sure that each distinct string only has one symbol.
+///
+/// Every `Document` has an interner, which `Figtree` uses for the names and keys of the
+/// documents it parses.  Looking a string up in the interner gives the symbol that the
+/// document uses for it, which can be kept to look the same name up again later.
+///
+/// # Examples
+/// ```
+/// # use figtree::Figtree;
+/// # use figtree::ParserOptions;
+/// let options = ParserOptions::new().repeated_nodes(true);
+/// let input = "upstream { host = 'a' } upstream { host = 'b' }";
+/// let doc = Figtree::from_string(input).with_options(options).parse().unwrap();
+/// assert_eq!(doc.interner().len(), 2);
+///
+/// let host = doc.interner().get("host").unwrap();
+/// for upstream in doc.children() {
+///     let (key, _) = upstream.properties().next().unwrap();
+///     assert!(key.ptr_eq(&host));
+///     assert!(upstream.get(&host).is_some());
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Interner {
+    symbols: HashSet<Symbol>,
+}
+
+impl Interner {
+    /// Construct an empty interner
+    pub fn new() -> Self {
+        Interner { symbols: HashSet::new() }
+    }
+
+    /// Get the symbol for a string, adding one if there isn't one yet
+    pub fn intern(&mut self, string: &str) -> Symbol {
+        if let Some(symbol) = self.symbols.get(string) {
+            return symbol.clone();
+        }
+        let symbol = Symbol::from(string);
+        self.symbols.insert(symbol.clone());
+        symbol
+    }
+
+    /// Get the symbol for the same string as `symbol`, adding `symbol` itself if there
+    /// isn't one yet.  This avoids copying the string of a symbol that isn't interned.
+    pub fn intern_symbol(&mut self, symbol: Symbol) -> Symbol {
+        if let Some(existing) = self.symbols.get(&symbol) {
+            return existing.clone();
+        }
+        self.symbols.insert(symbol.clone());
+        symbol
+    }
+
+    /// Get the symbol for a string, if there is one
+    pub fn get(&self, string: &str) -> Option<Symbol> {
+        self.symbols.get(string).cloned()
+    }
+
+    /// The number of distinct strings that have been interned
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    /// Test if nothing has been interned
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interns_strings() {
+        let mut interner = Interner::new();
+        let first = interner.intern("name");
+        let second = interner.intern(&String::from("name"));
+        assert!(first.ptr_eq(&second));
+        assert!(!first.ptr_eq(&Symbol::from("name")));
+        assert_eq!(first, Symbol::from("name"));
+        assert_eq!(interner.len(), 1);
+        assert!(interner.get("other").is_none());
+        assert!(interner.intern_symbol(Symbol::from("name")).ptr_eq(&first));
+        let other = Symbol::from("other");
+        assert!(interner.intern_symbol(other.clone()).ptr_eq(&other));
+        assert!(interner.intern("other").ptr_eq(&other));
+        assert!(other > first);
+        assert_eq!(interner.len(), 2);
+
+        assert_eq!(first, "name");
+        assert_eq!(first.as_str(), "name");
+        assert_eq!(first.len(), 4);
+        assert_eq!(format!("{} {:?}", first, first), "name \"name\"");
+    }
+}
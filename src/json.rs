@@ -0,0 +1,507 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use serde_json::{self, Map, Number};
+
+use super::parser::ParseEvent;
+use super::position::Position;
+use super::types::{Document, Node, Value};
+
+/// The key that a node's arguments are written under, the same as the `ARGUMENTS_KEY` that
+/// serde uses
+pub(crate) const ARGUMENTS_KEY: &str = "$arguments";
+
+impl Value {
+    /// Convert the value to JSON.  Strings and identifiers both become strings, and
+    /// datetimes become strings in the same format they are written in.  Durations become
+    /// objects of `secs` and `nanos`, as `std::time::Duration` is with serde, and bytes
+    /// become arrays of numbers.  Infinite and NaN floats can't be written in JSON, so
+    /// they become `null`.
+    ///
+    /// # Examples
+    /// ```
+    /// # use figtree::types::Value;
+    /// let value = Value::from(vec![Value::new_ident("a"), Value::new_float(f64::NAN)]);
+    /// assert_eq!(value.to_json_value().to_string(), r#"["a",null]"#);
+    /// ```
+    pub fn to_json_value(&self) -> serde_json::Value {
+        match *self {
+            Value::Str(ref string) | Value::Ident(ref string) => {
+                serde_json::Value::String(string.clone())
+            },
+            Value::Int(int) => serde_json::Value::Number(int.into()),
+            Value::Float(float) => Number::from_f64(float)
+                .map_or(serde_json::Value::Null, serde_json::Value::Number),
+            Value::Bool(boolean) => serde_json::Value::Bool(boolean),
+            Value::DateTime(ref datetime) => serde_json::Value::String(datetime.to_string()),
+            Value::Duration(ref duration) => {
+                let mut object = Map::new();
+                object.insert("secs".into(), duration.as_secs().into());
+                object.insert("nanos".into(), duration.subsec_nanos().into());
+                serde_json::Value::Object(object)
+            },
+            Value::Bytes(ref bytes) => {
+                serde_json::Value::Array(bytes.iter().map(|&byte| byte.into()).collect())
+            },
+            Value::Dict(ref dict) => serde_json::Value::Object(dict.iter()
+                .map(|(key, value)| (key.clone(), value.to_json_value()))
+                .collect()),
+            Value::List(ref list) => {
+                serde_json::Value::Array(list.iter().map(Value::to_json_value).collect())
+            },
+            Value::Node(ref node) => node.to_json(),
+            Value::Null => serde_json::Value::Null,
+        }
+    }
+}
+
+/// Add groups of nodes with the same name to an object, with a single node as an object
+/// and several as an array of objects, keeping the order that each name first appears in
+fn insert_nodes<'a, I>(object: &mut Map<String, serde_json::Value>, nodes: I)
+        where I: IntoIterator<Item = &'a Node> {
+    let mut groups: Vec<(&str, Vec<serde_json::Value>)> = Vec::new();
+    let mut indices = HashMap::new();
+    for node in nodes {
+        let index = *indices.entry(node.name()).or_insert_with(|| {
+            groups.push((node.name(), Vec::new()));
+            groups.len() - 1
+        });
+        groups[index].1.push(node.to_json());
+    }
+    for (name, mut nodes) in groups {
+        let value = if nodes.len() == 1 {
+            nodes.remove(0)
+        } else {
+            serde_json::Value::Array(nodes)
+        };
+        object.insert(name.into(), value);
+    }
+}
+
+impl Node {
+    /// Convert the node to a JSON object.  Its arguments, if it has any, are an array
+    /// under the key `"$arguments"`, followed by its attributes, converted with
+    /// `Value::to_json_value`, and its subnodes.  Subnodes that share a name are put
+    /// together in an array, and a subnode that's the only one with its name is an object
+    /// on its own.  This is the same shape as the node is serialized in with serde.
+    ///
+    /// The node's own name isn't included, as it belongs to whatever contains the node.
+    /// JSON can't have the same key twice, so a subnode with the same name as an attribute
+    /// replaces it.
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut object = Map::new();
+        if !self.arguments().is_empty() {
+            let arguments = self.arguments().iter().map(Value::to_json_value).collect();
+            object.insert(ARGUMENTS_KEY.into(), serde_json::Value::Array(arguments));
+        }
+        for (key, value) in self.iter_attrs() {
+            object.insert(key.to_string(), value.to_json_value());
+        }
+        insert_nodes(&mut object, self.children());
+        serde_json::Value::Object(object)
+    }
+}
+
+impl Document {
+    /// Convert the document to a JSON object of its nodes, in the same way as the subnodes
+    /// of a node (see `Node::to_json`).  Comments, spans and the difference between
+    /// strings and identifiers are lost.
+    ///
+    /// # Examples
+    /// ```
+    /// # use figtree::{Figtree, ParserOptions};
+    /// let options = ParserOptions::new().node_arguments(true).repeated_nodes(true);
+    /// let doc = Figtree::from_string("listen 80 { tls = false } listen 443 { tls = true }")
+    ///     .with_options(options).parse().unwrap();
+    /// assert_eq!(doc.to_json_string(), concat!(r#"{"listen":[{"$arguments":[80],"tls":false},"#,
+    ///                                          r#"{"$arguments":[443],"tls":true}]}"#));
+    /// ```
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut object = Map::new();
+        insert_nodes(&mut object, self.children());
+        serde_json::Value::Object(object)
+    }
+
+    /// Write the document as compact JSON text
+    pub fn to_json_string(&self) -> String {
+        self.to_json().to_string()
+    }
+
+    /// Write the document as JSON text, with each entry on its own line and indented
+    pub fn to_json_pretty(&self) -> String {
+        format!("{:#}", self.to_json())
+    }
+}
+
+/// How arrays of objects are imported from JSON
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum JsonArrays {
+    /// An array of objects becomes a run of subnodes, each named with the array's key (the
+    /// default).  This is how `to_json` writes subnodes that share a name.
+    RepeatedNodes,
+    /// Every array becomes a list, with any objects in it as dicts
+    Lists,
+}
+
+/// Options controlling how JSON is imported into a `Document`
+///
+/// # Examples
+/// ```
+/// # use figtree::types::{Document, Value};
+/// # use figtree::{JsonArrays, JsonOptions};
+/// let json = r#"{"server": {"upstreams": [{"host": "a"}, {"host": "b"}]}}"#;
+/// let doc = Document::from_json_str(json).unwrap();
+/// assert_eq!(doc["server"].children_named("upstreams").count(), 2);
+///
+/// let options = JsonOptions::new().arrays(JsonArrays::Lists);
+/// let doc = Document::from_json_str_with_options(json, &options).unwrap();
+/// assert_eq!(doc["server"].get("upstreams").and_then(Value::get_list).map(|list| list.len()),
+///            Some(2));
+/// ```
+#[derive(Debug, PartialEq, Clone)]
+pub struct JsonOptions {
+    /// How arrays of objects are imported
+    pub arrays: JsonArrays,
+    /// Whether `"$arguments"` arrays are imported as the arguments of their node
+    pub arguments: bool,
+}
+
+impl JsonOptions {
+    /// Construct the default set of options
+    pub fn new() -> Self {
+        JsonOptions {
+            arrays: JsonArrays::RepeatedNodes,
+            arguments: true,
+        }
+    }
+
+    /// Set how arrays of objects are imported
+    pub fn arrays(mut self, policy: JsonArrays) -> Self {
+        self.arrays = policy;
+        self
+    }
+
+    /// Import the `"$arguments"` key of an object as the arguments of its node, as
+    /// `to_json` writes them (the default).  Otherwise, it's an attribute like any other.
+    pub fn arguments(mut self, enabled: bool) -> Self {
+        self.arguments = enabled;
+        self
+    }
+}
+
+impl Default for JsonOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The error produced when JSON can't be imported as a `Document`
+#[derive(Debug)]
+pub enum JsonError {
+    /// The text isn't valid JSON
+    Syntax(serde_json::Error),
+    /// The JSON isn't an object, so can't be a document, with the type that it is instead
+    NotAnObject(&'static str),
+    /// The outermost object has an `"$arguments"` key, but documents can't have arguments
+    DocumentArguments,
+    /// The input couldn't be read from, or the output couldn't be written to, by a
+    /// `JsonReader` or a `JsonWriter`
+    Io(io::Error),
+    /// The text read by a `JsonReader` isn't valid JSON, or can't be read as nodes, at this
+    /// position
+    Invalid(String, Position),
+    /// The event can't come next in the document written by a `JsonWriter`.  Nothing is
+    /// written for it.
+    UnexpectedEvent(ParseEvent),
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            JsonError::Syntax(ref error) => write!(f, "invalid JSON: {}", error),
+            JsonError::NotAnObject(found) => {
+                write!(f, "expected a JSON object for the document, found {}", found)
+            },
+            JsonError::DocumentArguments => f.write_str("a document can't have arguments"),
+            JsonError::Io(ref error) => write!(f, "could not read or write JSON: {}", error),
+            JsonError::Invalid(ref message, ref position) => {
+                write!(f, "{} (line {}, column {})", message, position.line + 1, position.pos + 1)
+            },
+            JsonError::UnexpectedEvent(ref event) => write!(f, "unexpected event {:?}", event),
+        }
+    }
+}
+
+impl Error for JsonError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            JsonError::Syntax(ref error) => Some(error),
+            JsonError::Io(ref error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for JsonError {
+    fn from(error: io::Error) -> Self {
+        JsonError::Io(error)
+    }
+}
+
+/// The name of the type of a JSON value, for errors
+fn json_type(value: &serde_json::Value) -> &'static str {
+    match *value {
+        serde_json::Value::Null => "null",
+        serde_json::Value::Bool(_) => "a boolean",
+        serde_json::Value::Number(_) => "a number",
+        serde_json::Value::String(_) => "a string",
+        serde_json::Value::Array(_) => "an array",
+        serde_json::Value::Object(_) => "an object",
+    }
+}
+
+impl Value {
+    /// Import a value from JSON.  Numbers become integers if they fit in an `i64`, and
+    /// floats otherwise, arrays become lists and objects become dicts.
+    pub fn from_json_value(value: &serde_json::Value) -> Value {
+        match *value {
+            serde_json::Value::Null => Value::Null,
+            serde_json::Value::Bool(boolean) => Value::Bool(boolean),
+            serde_json::Value::Number(ref number) => match number.as_i64() {
+                Some(int) => Value::Int(int),
+                None => number.as_f64().map_or(Value::Null, Value::Float),
+            },
+            serde_json::Value::String(ref string) => Value::Str(string.clone()),
+            serde_json::Value::Array(ref items) => {
+                Value::List(items.iter().map(Value::from_json_value).collect())
+            },
+            serde_json::Value::Object(ref object) => Value::Dict(object.iter()
+                .map(|(key, value)| (key.clone(), Value::from_json_value(value)))
+                .collect()),
+        }
+    }
+}
+
+/// The objects in an entry that are imported as subnodes, or `None` if the entry is
+/// imported as a value
+fn objects<'a>(value: &'a serde_json::Value, options: &JsonOptions)
+               -> Option<Vec<&'a Map<String, serde_json::Value>>> {
+    match *value {
+        serde_json::Value::Object(ref object) => Some(vec![object]),
+        serde_json::Value::Array(ref items)
+                if options.arrays == JsonArrays::RepeatedNodes && !items.is_empty() => {
+            items.iter().map(serde_json::Value::as_object).collect()
+        },
+        _ => None,
+    }
+}
+
+/// Import an object as a node
+fn import_node(object: &Map<String, serde_json::Value>, options: &JsonOptions) -> Node {
+    let mut node = Node::new();
+    for (key, value) in object {
+        if options.arguments && key == ARGUMENTS_KEY {
+            match *value {
+                serde_json::Value::Array(ref items) => for item in items {
+                    node.push_argument(Value::from_json_value(item));
+                },
+                ref value => node.push_argument(Value::from_json_value(value)),
+            }
+            continue;
+        }
+        match objects(value, options) {
+            Some(objects) => for object in objects {
+                node.append_node(key.as_str(), import_node(object, options));
+            },
+            None => {
+                node.insert_attr(key.as_str(), Value::from_json_value(value));
+            },
+        }
+    }
+    node
+}
+
+impl Document {
+    /// Import a document from a JSON object, with the default options.  This is the
+    /// inverse of `to_json`: objects become nodes, with their `"$arguments"` as the node's
+    /// arguments, and arrays of objects become runs of nodes with the same name.  Other
+    /// values become attributes, or at the top level of the document, nodes with the
+    /// value as their single argument.
+    ///
+    /// As objects are always imported as nodes, a dict attribute, or a list of dicts,
+    /// that was written with `to_json` comes back as subnodes.
+    ///
+    /// # Failures
+    /// Fails if the value isn't an object, or has an `"$arguments"` key.
+    ///
+    /// # Examples
+    /// ```
+    /// # extern crate serde_json;
+    /// # extern crate figtree;
+    /// # use figtree::types::{Document, Value};
+    /// # fn main() {
+    /// let json = serde_json::json!({ "listen": { "$arguments": [443], "tls": true } });
+    /// let doc = Document::from_json_value(&json).unwrap();
+    /// assert_eq!(doc["listen"].arguments(), &[Value::new_int(443)][..]);
+    /// assert_eq!(doc.to_json(), json);
+    /// # }
+    /// ```
+    pub fn from_json_value(value: &serde_json::Value) -> Result<Document, JsonError> {
+        Document::from_json_value_with_options(value, &JsonOptions::new())
+    }
+
+    /// Import a document from a JSON object, in the way described by `options`
+    ///
+    /// # Failures
+    /// Fails if the value isn't an object, or has an `"$arguments"` key when arguments are
+    /// being imported.
+    pub fn from_json_value_with_options(value: &serde_json::Value, options: &JsonOptions)
+                                        -> Result<Document, JsonError> {
+        let object = value.as_object().ok_or_else(|| JsonError::NotAnObject(json_type(value)))?;
+        let mut document = Document::new();
+        for (key, value) in object {
+            if options.arguments && key == ARGUMENTS_KEY {
+                return Err(JsonError::DocumentArguments);
+            }
+            match objects(value, options) {
+                Some(objects) => for object in objects {
+                    document.append_node(key.as_str(), import_node(object, options));
+                },
+                None => {
+                    let mut node = Node::new();
+                    node.push_argument(Value::from_json_value(value));
+                    document.append_node(key.as_str(), node);
+                },
+            }
+        }
+        Ok(document)
+    }
+
+    /// Import a document from JSON text, with the default options (see
+    /// `from_json_value`)
+    ///
+    /// # Failures
+    /// Fails if the text isn't valid JSON, or can't be imported as a document.
+    pub fn from_json_str(text: &str) -> Result<Document, JsonError> {
+        Document::from_json_str_with_options(text, &JsonOptions::new())
+    }
+
+    /// Import a document from JSON text, in the way described by `options`
+    ///
+    /// # Failures
+    /// Fails if the text isn't valid JSON, or can't be imported as a document.
+    pub fn from_json_str_with_options(text: &str, options: &JsonOptions)
+                                      -> Result<Document, JsonError> {
+        let value = serde_json::from_str(text).map_err(JsonError::Syntax)?;
+        Document::from_json_value_with_options(&value, options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use serde_json::json;
+
+    use super::super::figtree::Figtree;
+    use super::super::parser::ParserOptions;
+    use super::*;
+
+    #[test]
+    fn converts_values() {
+        let value = Value::from(vec![
+            Value::from("a"),
+            Value::new_int(-1),
+            Value::new_float(1.5),
+            Value::new_float(f64::INFINITY),
+            Value::new_bool(true),
+            Value::new_duration(Duration::new(90, 5)),
+            Value::new_bytes(vec![0, 255]),
+            Value::Null,
+            Value::new_node(Node::new()),
+        ]);
+        assert_eq!(value.to_json_value().to_string(),
+                   r#"["a",-1,1.5,null,true,{"nanos":5,"secs":90},[0,255],null,{}]"#);
+    }
+
+    #[test]
+    fn converts_documents() {
+        let input = "
+            server 'web' {
+                ports = [80, 443],
+                env = { HOME = '~' }
+                upstream { host = 'a' }
+                upstream { host = 'b' }
+                tls { cert = 'c.pem' }
+            }
+        ";
+        let options = ParserOptions::new().node_arguments(true).repeated_nodes(true);
+        let doc = Figtree::from_string(input).with_options(options).parse().unwrap();
+        let json = doc.to_json();
+        assert_eq!(json["server"]["$arguments"], json!(["web"]));
+        assert_eq!(json["server"]["ports"], json!([80, 443]));
+        assert_eq!(json["server"]["env"], json!({ "HOME": "~" }));
+        assert_eq!(json["server"]["upstream"], json!([{ "host": "a" }, { "host": "b" }]));
+        assert_eq!(json["server"]["tls"], json!({ "cert": "c.pem" }));
+        assert!(doc.to_json_pretty().starts_with("{\n  \"server\": {\n"));
+        assert_eq!(Node::new().to_json().to_string(), "{}");
+    }
+
+    #[test]
+    fn imports_documents() {
+        let json = json!({
+            "server": {
+                "$arguments": ["web", 2],
+                "ports": [80, 443],
+                "ratio": 0.5,
+                "upstream": [{ "host": "a" }, { "host": "b" }],
+                "tls": { "cert": "c.pem" },
+                "mixed": [{ "a": 1 }, 2],
+                "empty": []
+            },
+            "port": 8080
+        });
+        let doc = Document::from_json_value(&json).unwrap();
+        let server = &doc["server"];
+        assert_eq!(server.arguments(), &[Value::from("web"), Value::new_int(2)][..]);
+        assert_eq!(server.get("ports"), Some(&Value::from(vec![Value::new_int(80),
+                                                               Value::new_int(443)])));
+        assert_eq!(server.get("ratio"), Some(&Value::new_float(0.5)));
+        assert_eq!(server.children_named("upstream").count(), 2);
+        assert_eq!(server.child("tls").and_then(|tls| tls.get("cert")), Some(&Value::from("c.pem")));
+        assert_eq!(server.get("mixed").and_then(Value::get_list).map(|list| list.len()), Some(2));
+        assert_eq!(server.get("empty"), Some(&Value::List(vec![])));
+        assert_eq!(doc["port"].arguments(), &[Value::new_int(8080)][..]);
+        assert_eq!(doc.to_json()["server"], json["server"]);
+        assert_eq!(doc.to_json()["port"], json!({ "$arguments": [8080] }));
+
+        let options = JsonOptions::new().arrays(JsonArrays::Lists).arguments(false);
+        let doc = Document::from_json_value_with_options(&json, &options).unwrap();
+        assert!(doc["server"].arguments().is_empty());
+        assert!(doc["server"].get("$arguments").is_some_and(Value::is_list));
+        assert!(doc["server"].get("upstream").is_some_and(Value::is_list));
+    }
+
+    #[test]
+    fn rejects_invalid_documents() {
+        let error = Document::from_json_str("[1]").unwrap_err();
+        assert_eq!(error.to_string(), "expected a JSON object for the document, found an array");
+        let error = Document::from_json_str(r#"{"$arguments": []}"#).unwrap_err();
+        assert_eq!(error.to_string(), "a document can't have arguments");
+        match Document::from_json_str("{") {
+            Err(JsonError::Syntax(_)) => {},
+            other => panic!("expected a syntax error, got {:?}", other),
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn matches_serde() {
+        let input = "a 1 { b = [1.5, !c], d { e = 2020-01-01T00:00:00Z } d {} } f { g = 1s }";
+        let options = ParserOptions::new().node_arguments(true).repeated_nodes(true);
+        let doc = Figtree::from_string(input).with_options(options).parse().unwrap();
+        assert_eq!(doc.to_json(), serde_json::to_value(&doc).unwrap());
+    }
+}
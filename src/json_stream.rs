@@ -0,0 +1,861 @@
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Read, Write};
+
+use serde_json;
+
+use super::json::{JsonArrays, JsonError, JsonOptions, ARGUMENTS_KEY};
+use super::parser::{ParseEvent, ParsedValue};
+use super::position::Position;
+use super::types::Value;
+
+/// What's being written by a `JsonWriter`, innermost last
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum WriteFrame {
+    /// The object of the document (`node` is `false`) or of a node, with the number of
+    /// entries that have been written in it
+    Object { entries: usize, node: bool },
+    /// The `"$arguments"` array of the node that the object before it is for
+    Arguments,
+    /// An attribute whose key has been written, waiting for its value
+    Attribute,
+    /// A list, with the number of items that have been written in it
+    List { items: usize },
+    /// A dict, with the number of entries that have been written in it, and whether the
+    /// key of the next entry has been written
+    Dict { entries: usize, key: bool },
+}
+
+/// Writes JSON from a stream of `ParseEvent`s, without building a `Document`, so files of
+/// any size can be converted in constant memory.  Each node is written as an object in
+/// the same shape as `Node::to_json` writes it, with its arguments in an `"$arguments"`
+/// array, followed by its attributes and subnodes in the order they come in.
+///
+/// `to_json` puts subnodes that share a name together in an array, but that would mean
+/// holding on to every node until its parent ends.  A `JsonWriter` writes each subnode
+/// under its own key instead, so nodes that share a name become repeated keys in the
+/// same object.  JSON parsers disagree about repeated keys (`serde_json` keeps the last
+/// one), but a `JsonReader` reads each of them back as a node.
+///
+/// # Examples
+/// ```
+/// # use figtree::{JsonWriter, Lexer, Parser, ParserOptions};
+/// # use std::io;
+/// let input = "listen 80 { tls = false } listen 443 { tls = true, ciphers = [!a, !b] }";
+/// let options = ParserOptions::new().node_arguments(true).repeated_nodes(true);
+/// let parser = Parser::with_options(Lexer::lex(io::Cursor::new(input)), options);
+/// let mut writer = JsonWriter::new(Vec::new());
+/// writer.write_events(parser.map(|result| result.unwrap().0)).unwrap();
+/// assert_eq!(String::from_utf8(writer.into_inner()).unwrap(), concat!(
+///     r#"{"listen":{"$arguments":[80],"tls":false},"#,
+///     r#""listen":{"$arguments":[443],"tls":true,"ciphers":["a","b"]}}"#,
+/// ));
+/// ```
+pub struct JsonWriter<W> {
+    output: W,
+    stack: Vec<WriteFrame>,
+    started: bool,
+    finished: bool,
+}
+
+impl<W: Write> JsonWriter<W> {
+    /// Construct a writer that writes each event to `output` as soon as it's given
+    pub fn new(output: W) -> Self {
+        JsonWriter {
+            output,
+            stack: Vec::new(),
+            started: false,
+            finished: false,
+        }
+    }
+
+    /// Get a reference to the output
+    pub fn get_ref(&self) -> &W {
+        &self.output
+    }
+
+    /// Take the output back from the writer
+    pub fn into_inner(self) -> W {
+        self.output
+    }
+
+    /// Write the JSON for an event.  Events have to come in the order that a `Parser`
+    /// produces them, starting with `FileStart` and ending with `FileEnd`.
+    ///
+    /// # Failures
+    /// Fails if the event can't come next in the document, or if the output can't be
+    /// written to.
+    pub fn write_event(&mut self, event: ParseEvent) -> Result<(), JsonError> {
+        if !self.started || self.finished {
+            return match event {
+                ParseEvent::FileStart if !self.started => {
+                    self.started = true;
+                    self.stack.push(WriteFrame::Object { entries: 0, node: false });
+                    self.output.write_all(b"{")?;
+                    Ok(())
+                },
+                event => Err(JsonError::UnexpectedEvent(event)),
+            };
+        }
+        // the arguments end at the first event that isn't one
+        let arguments = self.stack.last() == Some(&WriteFrame::Arguments);
+        let top = if arguments {
+            self.stack[self.stack.len() - 2]
+        } else {
+            *self.stack.last().expect("the document hasn't ended")
+        };
+        let expected = match event {
+            ParseEvent::FileStart => false,
+            ParseEvent::FileEnd => self.stack.len() == 1,
+            ParseEvent::NodeStart(..) | ParseEvent::Key(..) if arguments => true,
+            ParseEvent::NodeStart(..) => matches!(top, WriteFrame::Object { .. }),
+            ParseEvent::NodeEnd => matches!(top, WriteFrame::Object { node: true, .. }),
+            ParseEvent::Argument(_) => {
+                arguments || top == WriteFrame::Object { entries: 0, node: true }
+            },
+            ParseEvent::Key(..) => match top {
+                WriteFrame::Object { node, .. } => node,
+                WriteFrame::Dict { key, .. } => !key,
+                _ => false,
+            },
+            _ if arguments => false,
+            ParseEvent::Value(_) | ParseEvent::ListStart | ParseEvent::DictStart => match top {
+                WriteFrame::Attribute | WriteFrame::List { .. } => true,
+                WriteFrame::Dict { key, .. } => key,
+                _ => false,
+            },
+            ParseEvent::ListEnd => matches!(top, WriteFrame::List { .. }),
+            ParseEvent::DictEnd => matches!(top, WriteFrame::Dict { key: false, .. }),
+        };
+        if !expected {
+            return Err(JsonError::UnexpectedEvent(event));
+        }
+        if arguments && !matches!(event, ParseEvent::Argument(_)) {
+            self.stack.pop();
+            self.output.write_all(b"]")?;
+        }
+        match event {
+            ParseEvent::FileEnd => {
+                self.stack.pop();
+                self.finished = true;
+                self.output.write_all(b"}")?;
+                self.output.flush()?;
+            },
+            ParseEvent::NodeStart(name, _) => {
+                self.entry(&name)?;
+                self.output.write_all(b"{")?;
+                self.stack.push(WriteFrame::Object { entries: 0, node: true });
+            },
+            ParseEvent::NodeEnd => {
+                self.stack.pop();
+                self.output.write_all(b"}")?;
+            },
+            ParseEvent::Argument(value) => {
+                if arguments {
+                    self.output.write_all(b",")?;
+                } else {
+                    self.entry(ARGUMENTS_KEY)?;
+                    self.output.write_all(b"[")?;
+                    self.stack.push(WriteFrame::Arguments);
+                }
+                self.scalar(value)?;
+            },
+            ParseEvent::Key(key, _) => {
+                self.entry(&key)?;
+                if let Some(&mut WriteFrame::Dict { ref mut key, .. }) = self.stack.last_mut() {
+                    *key = true;
+                } else {
+                    self.stack.push(WriteFrame::Attribute);
+                }
+            },
+            ParseEvent::Value(value) => {
+                self.item()?;
+                self.scalar(value)?;
+                self.end_value();
+            },
+            ParseEvent::ListStart => {
+                self.item()?;
+                self.output.write_all(b"[")?;
+                self.stack.push(WriteFrame::List { items: 0 });
+            },
+            ParseEvent::DictStart => {
+                self.item()?;
+                self.output.write_all(b"{")?;
+                self.stack.push(WriteFrame::Dict { entries: 0, key: false });
+            },
+            ParseEvent::ListEnd | ParseEvent::DictEnd => {
+                let end = if event == ParseEvent::ListEnd { b"]" } else { b"}" };
+                self.stack.pop();
+                self.output.write_all(end)?;
+                self.end_value();
+            },
+            ParseEvent::FileStart => unreachable!("FileStart has been checked"),
+        }
+        Ok(())
+    }
+
+    /// Write every event from `events`, stopping at the first error
+    pub fn write_events<I>(&mut self, events: I) -> Result<(), JsonError>
+        where I: IntoIterator<Item = ParseEvent> {
+
+        events.into_iter().try_for_each(|event| self.write_event(event))
+    }
+
+    /// Write the key of the next entry in the object or dict that's being written
+    fn entry(&mut self, key: &str) -> Result<(), JsonError> {
+        match self.stack.last_mut() {
+            Some(&mut WriteFrame::Object { ref mut entries, .. })
+                    | Some(&mut WriteFrame::Dict { ref mut entries, .. }) => {
+                if *entries > 0 {
+                    self.output.write_all(b",")?;
+                }
+                *entries += 1;
+            },
+            _ => unreachable!("entries are only written in objects and dicts"),
+        }
+        serde_json::to_writer(&mut self.output, key).map_err(io::Error::from)?;
+        self.output.write_all(b":")?;
+        Ok(())
+    }
+
+    /// Write the separator before a value, if it's not the first item of a list
+    fn item(&mut self) -> Result<(), JsonError> {
+        if let Some(&mut WriteFrame::List { ref mut items }) = self.stack.last_mut() {
+            if *items > 0 {
+                self.output.write_all(b",")?;
+            }
+            *items += 1;
+        }
+        Ok(())
+    }
+
+    /// Finish the attribute or dict entry that a value has just been written for
+    fn end_value(&mut self) {
+        match self.stack.last_mut() {
+            Some(&mut WriteFrame::Attribute) => {
+                self.stack.pop();
+            },
+            Some(&mut WriteFrame::Dict { ref mut key, .. }) => *key = false,
+            _ => {},
+        }
+    }
+
+    /// Write a value in the same way as `Value::to_json_value` converts it
+    fn scalar(&mut self, value: ParsedValue) -> Result<(), JsonError> {
+        let value = Value::from_parsed_value(value).to_json_value();
+        serde_json::to_writer(&mut self.output, &value).map_err(io::Error::from)?;
+        Ok(())
+    }
+}
+
+/// What's being read by a `JsonReader`, innermost last
+#[derive(Debug, PartialEq, Clone)]
+enum ReadFrame {
+    /// The object of the whole document
+    Document,
+    /// The object of a node
+    Node,
+    /// The `"$arguments"` array of a node
+    Arguments,
+    /// An array of objects, each of which is a node with this name
+    Nodes(String),
+    List,
+    Dict,
+}
+
+/// Reads JSON as a stream of `ParseEvent`s, without building a `Document` or a
+/// `serde_json::Value`, so files of any size can be converted in constant memory.  The
+/// JSON is read in the same way as `Document::from_json_value_with_options`: objects
+/// become nodes, with their `"$arguments"` as the node's arguments, arrays of objects
+/// become runs of nodes with the same name, and other values become attributes, or at the
+/// top level of the document, nodes with the value as their single argument.  Repeated
+/// keys, like the ones a `JsonWriter` writes, each become a node or an attribute.
+///
+/// Only as much of the JSON is read as is needed for the next event, so the reader
+/// can't look ahead the way `from_json_value` does.  An array is read as nodes if its
+/// first item is an object, and it's a `JsonError::Invalid` if any of the later items
+/// aren't objects.  An `"$arguments"` key is only read as arguments if it's the first key
+/// of its object, and arguments and top-level values can't be arrays or objects, as the
+/// events can only have scalars as arguments.
+///
+/// # Examples
+/// ```
+/// # use figtree::{JsonReader, ParseEvent, ParsedValue};
+/// let json = r#"{"listen": [{"$arguments": [443], "tls": true}]}"#;
+/// let events: Result<Vec<_>, _> = JsonReader::new(json.as_bytes()).collect();
+/// assert_eq!(events.unwrap(), vec![
+///     ParseEvent::FileStart,
+///     ParseEvent::NodeStart("listen".into(), false),
+///     ParseEvent::Argument(ParsedValue::Int(443)),
+///     ParseEvent::Key("tls".into(), false),
+///     ParseEvent::Value(ParsedValue::Bool(true)),
+///     ParseEvent::NodeEnd,
+///     ParseEvent::FileEnd,
+/// ]);
+/// ```
+pub struct JsonReader<R> {
+    input: BufReader<R>,
+    options: JsonOptions,
+    line: usize,
+    pos: usize,
+    /// the objects and arrays that haven't ended yet, and whether the next item in each
+    /// is its first
+    stack: Vec<(ReadFrame, bool)>,
+    queue: VecDeque<ParseEvent>,
+    started: bool,
+    finished: bool,
+}
+
+impl<R: Read> JsonReader<R> {
+    /// Construct a reader with the default options
+    pub fn new(input: R) -> Self {
+        JsonReader::with_options(input, JsonOptions::new())
+    }
+
+    /// Construct a reader that reads JSON in the way described by `options`
+    pub fn with_options(input: R, options: JsonOptions) -> Self {
+        JsonReader {
+            input: BufReader::new(input),
+            options,
+            line: 0,
+            pos: 0,
+            stack: Vec::new(),
+            queue: VecDeque::new(),
+            started: false,
+            finished: false,
+        }
+    }
+
+    fn location(&self) -> Position {
+        Position::at(self.line, self.pos)
+    }
+
+    fn invalid<T, S: Into<String>>(&self, message: S) -> Result<T, JsonError> {
+        Err(JsonError::Invalid(message.into(), self.location()))
+    }
+
+    fn peek(&mut self) -> Result<Option<u8>, JsonError> {
+        Ok(self.input.fill_buf()?.first().cloned())
+    }
+
+    fn bump(&mut self) -> Result<Option<u8>, JsonError> {
+        let byte = self.peek()?;
+        if let Some(byte) = byte {
+            self.input.consume(1);
+            if byte == b'\n' {
+                self.line += 1;
+                self.pos = 0;
+            } else {
+                self.pos += 1;
+            }
+        }
+        Ok(byte)
+    }
+
+    /// Skip any whitespace, and peek at the byte after it
+    fn skip_whitespace(&mut self) -> Result<Option<u8>, JsonError> {
+        while let Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'\r') = self.peek()? {
+            self.bump()?;
+        }
+        self.peek()
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), JsonError> {
+        match self.skip_whitespace()? {
+            Some(found) if found == byte => {
+                self.bump()?;
+                Ok(())
+            },
+            Some(found) => {
+                self.invalid(format!("expected `{}`, found `{}`", byte as char, found as char))
+            },
+            None => self.invalid(format!("expected `{}`, found the end of the input",
+                                         byte as char)),
+        }
+    }
+
+    fn hex_escape(&mut self) -> Result<u32, JsonError> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self.bump()?.and_then(|byte| (byte as char).to_digit(16));
+            match digit {
+                Some(digit) => code = code * 16 + digit,
+                None => return self.invalid("invalid unicode escape"),
+            }
+        }
+        Ok(code)
+    }
+
+    /// Read a string, starting at its opening quote
+    fn string(&mut self) -> Result<String, JsonError> {
+        self.bump()?;
+        let mut bytes = Vec::new();
+        loop {
+            match self.bump()? {
+                None => return self.invalid("unterminated string"),
+                Some(b'"') => break,
+                Some(b'\\') => {
+                    let escaped = match self.bump()? {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => {
+                            let mut code = self.hex_escape()?;
+                            if (0xd800..0xdc00).contains(&code) {
+                                // a surrogate pair, for characters outside the BMP
+                                if self.bump()? != Some(b'\\') || self.bump()? != Some(b'u') {
+                                    return self.invalid("unpaired surrogate in string");
+                                }
+                                let low = self.hex_escape()?;
+                                if !(0xdc00..0xe000).contains(&low) {
+                                    return self.invalid("unpaired surrogate in string");
+                                }
+                                code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                            }
+                            match ::std::char::from_u32(code) {
+                                Some(c) => c,
+                                None => return self.invalid("unpaired surrogate in string"),
+                            }
+                        },
+                        _ => return self.invalid("invalid escape in string"),
+                    };
+                    let mut buffer = [0; 4];
+                    bytes.extend_from_slice(escaped.encode_utf8(&mut buffer).as_bytes());
+                },
+                Some(byte) if byte < 0x20 => {
+                    return self.invalid("control character in string");
+                },
+                Some(byte) => bytes.push(byte),
+            }
+        }
+        match String::from_utf8(bytes) {
+            Ok(string) => Ok(string),
+            Err(_) => self.invalid("string isn't valid UTF-8"),
+        }
+    }
+
+    fn literal(&mut self, word: &str, value: ParsedValue) -> Result<ParsedValue, JsonError> {
+        for &byte in word.as_bytes() {
+            if self.bump()? != Some(byte) {
+                return self.invalid(format!("invalid literal, expected `{}`", word));
+            }
+        }
+        Ok(value)
+    }
+
+    /// Read a number: an integer if it has no fraction or exponent and fits in an `i64`,
+    /// and a float otherwise
+    fn number(&mut self) -> Result<ParsedValue, JsonError> {
+        let mut text = String::new();
+        while let Some(byte) = self.peek()? {
+            match byte {
+                b'0'..=b'9' | b'-' | b'+' | b'.' | b'e' | b'E' => {
+                    text.push(byte as char);
+                    self.bump()?;
+                },
+                _ => break,
+            }
+        }
+        if !is_number(&text) {
+            return self.invalid(format!("invalid number `{}`", text));
+        }
+        if !text.contains(['.', 'e', 'E']) {
+            if let Ok(int) = text.parse() {
+                return Ok(ParsedValue::Int(int));
+            }
+        }
+        match text.parse() {
+            Ok(float) => Ok(ParsedValue::Float(float)),
+            Err(_) => self.invalid(format!("invalid number `{}`", text)),
+        }
+    }
+
+    /// Read a value that isn't an array or an object
+    fn scalar(&mut self) -> Result<ParsedValue, JsonError> {
+        match self.skip_whitespace()? {
+            Some(b'"') => self.string().map(ParsedValue::Str),
+            Some(b't') => self.literal("true", ParsedValue::Bool(true)),
+            Some(b'f') => self.literal("false", ParsedValue::Bool(false)),
+            Some(b'n') => self.literal("null", ParsedValue::Null),
+            Some(b'-') | Some(b'0'..=b'9') => self.number(),
+            Some(byte) => self.invalid(format!("expected a value, found `{}`", byte as char)),
+            None => self.invalid("expected a value, found the end of the input"),
+        }
+    }
+
+    /// Start reading a value in a list, a dict or an attribute
+    fn value(&mut self) -> Result<(), JsonError> {
+        match self.skip_whitespace()? {
+            Some(b'[') => {
+                self.bump()?;
+                self.queue.push_back(ParseEvent::ListStart);
+                self.stack.push((ReadFrame::List, true));
+            },
+            Some(b'{') => {
+                self.bump()?;
+                self.queue.push_back(ParseEvent::DictStart);
+                self.stack.push((ReadFrame::Dict, true));
+            },
+            _ => {
+                let value = self.scalar()?;
+                self.queue.push_back(ParseEvent::Value(value));
+            },
+        }
+        Ok(())
+    }
+
+    /// Start reading the value of an entry in the document or in a node
+    fn entry(&mut self, key: String, document: bool, first: bool) -> Result<(), JsonError> {
+        if self.options.arguments && key == ARGUMENTS_KEY {
+            if document {
+                return Err(JsonError::DocumentArguments);
+            }
+            if !first {
+                return self.invalid(format!("`{}` has to be the first key of its object to be \
+                                             read as arguments", ARGUMENTS_KEY));
+            }
+            match self.skip_whitespace()? {
+                Some(b'[') => {
+                    self.bump()?;
+                    self.stack.push((ReadFrame::Arguments, true));
+                },
+                Some(b'{') => return self.invalid("arguments can't be objects"),
+                _ => {
+                    let value = self.scalar()?;
+                    self.queue.push_back(ParseEvent::Argument(value));
+                },
+            }
+            return Ok(());
+        }
+        match self.skip_whitespace()? {
+            Some(b'{') => {
+                self.bump()?;
+                self.queue.push_back(ParseEvent::NodeStart(key, false));
+                self.stack.push((ReadFrame::Node, true));
+            },
+            Some(b'[') => {
+                self.bump()?;
+                let nodes = self.options.arrays == JsonArrays::RepeatedNodes
+                    && self.skip_whitespace()? == Some(b'{');
+                if nodes {
+                    self.stack.push((ReadFrame::Nodes(key), true));
+                } else if document {
+                    return self.invalid("a top-level array has to be an array of objects");
+                } else {
+                    self.queue.push_back(ParseEvent::Key(key, false));
+                    self.queue.push_back(ParseEvent::ListStart);
+                    self.stack.push((ReadFrame::List, true));
+                }
+            },
+            _ if document => {
+                let value = self.scalar()?;
+                self.queue.push_back(ParseEvent::NodeStart(key, false));
+                self.queue.push_back(ParseEvent::Argument(value));
+                self.queue.push_back(ParseEvent::NodeEnd);
+            },
+            _ => {
+                self.queue.push_back(ParseEvent::Key(key, false));
+                let value = self.scalar()?;
+                self.queue.push_back(ParseEvent::Value(value));
+            },
+        }
+        Ok(())
+    }
+
+    /// Read until there's at least one event in the queue, or the document ends
+    fn read(&mut self) -> Result<(), JsonError> {
+        if self.stack.is_empty() {
+            let found = match self.skip_whitespace()? {
+                Some(b'{') => None,
+                Some(b'[') => Some("an array"),
+                Some(b'"') => Some("a string"),
+                Some(b't') | Some(b'f') => Some("a boolean"),
+                Some(b'n') => Some("null"),
+                Some(b'-') | Some(b'0'..=b'9') => Some("a number"),
+                Some(byte) => {
+                    return self.invalid(format!("expected a value, found `{}`", byte as char));
+                },
+                None => return self.invalid("expected a value, found the end of the input"),
+            };
+            if let Some(found) = found {
+                return Err(JsonError::NotAnObject(found));
+            }
+            self.bump()?;
+            self.stack.push((ReadFrame::Document, true));
+        }
+        while self.queue.is_empty() {
+            let (frame, first) = self.stack.last().cloned().expect("the document hasn't ended");
+            let close = match frame {
+                ReadFrame::Document | ReadFrame::Node | ReadFrame::Dict => b'}',
+                _ => b']',
+            };
+            if self.skip_whitespace()? == Some(close) {
+                self.bump()?;
+                self.stack.pop();
+                match frame {
+                    ReadFrame::Document => {
+                        if self.skip_whitespace()?.is_some() {
+                            return self.invalid("trailing characters after the document");
+                        }
+                        self.queue.push_back(ParseEvent::FileEnd);
+                        self.finished = true;
+                    },
+                    ReadFrame::Node => self.queue.push_back(ParseEvent::NodeEnd),
+                    ReadFrame::List => self.queue.push_back(ParseEvent::ListEnd),
+                    ReadFrame::Dict => self.queue.push_back(ParseEvent::DictEnd),
+                    ReadFrame::Arguments | ReadFrame::Nodes(_) => {},
+                }
+                continue;
+            }
+            if !first {
+                self.expect(b',')?;
+            }
+            self.stack.last_mut().expect("the frame is still open").1 = false;
+            match frame {
+                ReadFrame::Document | ReadFrame::Node | ReadFrame::Dict => {
+                    if self.skip_whitespace()? != Some(b'"') {
+                        return self.invalid("expected a key");
+                    }
+                    let key = self.string()?;
+                    self.expect(b':')?;
+                    if frame == ReadFrame::Dict {
+                        self.queue.push_back(ParseEvent::Key(key, false));
+                        self.value()?;
+                    } else {
+                        self.entry(key, frame == ReadFrame::Document, first)?;
+                    }
+                },
+                ReadFrame::Arguments => match self.skip_whitespace()? {
+                    Some(b'[') | Some(b'{') => {
+                        return self.invalid("arguments can't be arrays or objects");
+                    },
+                    _ => {
+                        let value = self.scalar()?;
+                        self.queue.push_back(ParseEvent::Argument(value));
+                    },
+                },
+                ReadFrame::Nodes(name) => {
+                    if self.skip_whitespace()? != Some(b'{') {
+                        return self.invalid(format!("expected an object in the array of `{}` \
+                                                     nodes", name));
+                    }
+                    self.bump()?;
+                    self.queue.push_back(ParseEvent::NodeStart(name, false));
+                    self.stack.push((ReadFrame::Node, true));
+                },
+                ReadFrame::List => self.value()?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Check that text is a number in JSON's grammar, which is stricter than Rust's
+fn is_number(text: &str) -> bool {
+    let text = text.strip_prefix('-').unwrap_or(text);
+    let digits = |text: &str| {
+        text.len() - text.trim_start_matches(|c: char| c.is_ascii_digit()).len()
+    };
+    let int = digits(text);
+    if int == 0 || (int > 1 && text.starts_with('0')) {
+        return false;
+    }
+    let mut rest = &text[int..];
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let count = digits(fraction);
+        if count == 0 {
+            return false;
+        }
+        rest = &fraction[count..];
+    }
+    if let Some(exponent) = rest.strip_prefix(['e', 'E']) {
+        let exponent = exponent.strip_prefix(['+', '-']).unwrap_or(exponent);
+        let count = digits(exponent);
+        if count == 0 {
+            return false;
+        }
+        rest = &exponent[count..];
+    }
+    rest.is_empty()
+}
+
+impl<R: Read> Iterator for JsonReader<R> {
+    type Item = Result<ParseEvent, JsonError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.started {
+            self.started = true;
+            return Some(Ok(ParseEvent::FileStart));
+        }
+        if self.queue.is_empty() && !self.finished {
+            if let Err(error) = self.read() {
+                // nothing more is read after an error
+                self.finished = true;
+                self.queue.clear();
+                return Some(Err(error));
+            }
+        }
+        self.queue.pop_front().map(Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::figtree::Figtree;
+    use super::super::lexer::Lexer;
+    use super::super::parser::{Parser, ParserOptions};
+    use super::*;
+
+    fn events(input: &str) -> Vec<ParseEvent> {
+        let options = ParserOptions::new().node_arguments(true).repeated_nodes(true);
+        let lexer = Lexer::lex(io::Cursor::new(input.to_string()));
+        Parser::with_options(lexer, options).map(|result| result.unwrap().0).collect()
+    }
+
+    fn write(events: Vec<ParseEvent>) -> String {
+        let mut writer = JsonWriter::new(Vec::new());
+        writer.write_events(events).unwrap();
+        String::from_utf8(writer.into_inner()).unwrap()
+    }
+
+    fn read(json: &str) -> Result<Vec<ParseEvent>, JsonError> {
+        JsonReader::new(json.as_bytes()).collect()
+    }
+
+    fn error(json: &str) -> String {
+        read(json).unwrap_err().to_string()
+    }
+
+    const INPUT: &str = "
+        server 'web' 8080 {
+            name = 'a\\n\"b\"',
+            ratio = 0.1,
+            big = -9223372036854775807,
+            ports = [80, [443], null, true],
+            paths = ['/bin', { home = '/root' }]
+            upstream { host = 'a' }
+            upstream 'b' { weight = 2 }
+        }
+        empty {}
+    ";
+
+    #[test]
+    fn writes_events() {
+        let options = ParserOptions::new().node_arguments(true).repeated_nodes(true);
+        let input = "server 'web' { started = 2024-01-01T12:00:00Z, timeout = 250ms, mode = !fast,
+                     key = b\"\\x00\\xff\", nan = nan, tls { cert = 'a.pem' } } empty {}";
+        let doc = Figtree::from_string(input).with_options(options).parse().unwrap();
+        let json: serde_json::Value = serde_json::from_str(&write(events(input))).unwrap();
+        assert_eq!(json, doc.to_json());
+
+        assert_eq!(write(events("a { b { } } c 1 2 {}")),
+                   r#"{"a":{"b":{}},"c":{"$arguments":[1,2]}}"#);
+        assert_eq!(write(events("")), "{}");
+    }
+
+    #[test]
+    fn rejects_unexpected_events() {
+        let mut writer = JsonWriter::new(Vec::new());
+        let error = writer.write_event(ParseEvent::NodeEnd).unwrap_err();
+        assert_eq!(error.to_string(), "unexpected event NodeEnd");
+
+        writer.write_event(ParseEvent::FileStart).unwrap();
+        writer.write_event(ParseEvent::NodeStart("a".into(), false)).unwrap();
+        writer.write_event(ParseEvent::Key("b".into(), false)).unwrap();
+        assert!(matches!(writer.write_event(ParseEvent::Argument(ParsedValue::Int(1))),
+                         Err(JsonError::UnexpectedEvent(_))));
+        writer.write_event(ParseEvent::Value(ParsedValue::Int(1))).unwrap();
+        assert!(matches!(writer.write_event(ParseEvent::Argument(ParsedValue::Int(1))),
+                         Err(JsonError::UnexpectedEvent(_))));
+        assert!(matches!(writer.write_event(ParseEvent::FileEnd),
+                         Err(JsonError::UnexpectedEvent(_))));
+        assert_eq!(String::from_utf8_lossy(writer.get_ref()), r#"{"a":{"b":1"#);
+    }
+
+    #[test]
+    fn reads_events() {
+        let json = r#" { "a": 1, "b": {"$arguments": "x", "c": [{"d": null}], "e": []},
+                         "f": [{}, {"g": {"h": [1.5e0, "é😀", {"i": -0}]}}] } "#;
+        assert_eq!(read(json).unwrap(), vec![
+            ParseEvent::FileStart,
+            ParseEvent::NodeStart("a".into(), false),
+            ParseEvent::Argument(ParsedValue::Int(1)),
+            ParseEvent::NodeEnd,
+            ParseEvent::NodeStart("b".into(), false),
+            ParseEvent::Argument(ParsedValue::Str("x".into())),
+            ParseEvent::NodeStart("c".into(), false),
+            ParseEvent::Key("d".into(), false),
+            ParseEvent::Value(ParsedValue::Null),
+            ParseEvent::NodeEnd,
+            ParseEvent::Key("e".into(), false),
+            ParseEvent::ListStart,
+            ParseEvent::ListEnd,
+            ParseEvent::NodeEnd,
+            ParseEvent::NodeStart("f".into(), false),
+            ParseEvent::NodeEnd,
+            ParseEvent::NodeStart("f".into(), false),
+            ParseEvent::NodeStart("g".into(), false),
+            ParseEvent::Key("h".into(), false),
+            ParseEvent::ListStart,
+            ParseEvent::Value(ParsedValue::Float(1.5)),
+            ParseEvent::Value(ParsedValue::Str("\u{e9}\u{1f600}".into())),
+            ParseEvent::DictStart,
+            ParseEvent::Key("i".into(), false),
+            ParseEvent::Value(ParsedValue::Int(0)),
+            ParseEvent::DictEnd,
+            ParseEvent::ListEnd,
+            ParseEvent::NodeEnd,
+            ParseEvent::NodeEnd,
+            ParseEvent::FileEnd,
+        ]);
+
+        let options = JsonOptions::new().arrays(JsonArrays::Lists).arguments(false);
+        let events: Vec<_> = JsonReader::with_options(&br#"{"a": {"$arguments": [{}]}}"#[..],
+                                                      options)
+            .map(Result::unwrap).collect();
+        assert_eq!(&events[2..6], &[
+            ParseEvent::Key("$arguments".into(), false),
+            ParseEvent::ListStart,
+            ParseEvent::DictStart,
+            ParseEvent::DictEnd,
+        ]);
+    }
+
+    // objects are read as nodes, so dicts only come back in lists that don't start with one
+    #[test]
+    fn round_trips_events() {
+        let events = events(INPUT);
+        assert_eq!(read(&write(events.clone())).unwrap(), events);
+    }
+
+    #[test]
+    fn reports_errors() {
+        assert_eq!(error("[1]"), "expected a JSON object for the document, found an array");
+        assert_eq!(error(r#"{"$arguments": [1]}"#), "a document can't have arguments");
+        assert_eq!(error("{\n  \"a\": 01}"), "invalid number `01` (line 2, column 10)");
+        assert_eq!(error(r#"{"a": [1]}"#),
+                   "a top-level array has to be an array of objects (line 1, column 8)");
+        assert_eq!(error(r#"{"a": [{}, 1]}"#),
+                   "expected an object in the array of `a` nodes (line 1, column 12)");
+        assert_eq!(error(r#"{"a": {"b": 1, "$arguments": [1]}}"#),
+                   "`$arguments` has to be the first key of its object to be read as arguments \
+                    (line 1, column 29)");
+        assert_eq!(error(r#"{"a": {"$arguments": [[1]]}}"#),
+                   "arguments can't be arrays or objects (line 1, column 23)");
+        assert_eq!(error(r#"{"a": "\x"}"#), "invalid escape in string (line 1, column 10)");
+        assert_eq!(error(r#"{"a": 1 "b": 2}"#), "expected `,`, found `\"` (line 1, column 9)");
+        assert_eq!(error(r#"{"a": 1} x"#),
+                   "trailing characters after the document (line 1, column 10)");
+        assert_eq!(error(r#"{"a": tru}"#), "invalid literal, expected `true` (line 1, column 11)");
+        assert_eq!(error(r#"{"a": {"#), "expected a key (line 1, column 8)");
+
+        // nothing more is read after an error
+        let mut reader = JsonReader::new(&b"{\"a\": [1], \"b\": 2}"[..]);
+        assert!(reader.nth(1).unwrap().is_err());
+        assert!(reader.next().is_none());
+    }
+}
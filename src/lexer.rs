@@ -1,68 +1,514 @@
 use std::io::prelude::*;
 use std::io;
+use std::error::Error;
+use std::fmt;
+use std::collections::VecDeque;
 use std::char::from_u32;
+use std::ops::Range;
 use std::str::FromStr;
+use std::time::Duration;
 
-use utils::{CharReader, ident_head, ident_body};
-use position::MutablePosition;
+use utils::{CharReader, Base64Decoder, ident_head, ident_body};
+use position::{MutablePosition, Span};
+use datetime::DateTime;
 
 type LexResult = Result<LexToken, LexError>;
+/// A token or error, along with the span of the input that it came from
+pub type SpannedResult = Result<(LexToken, Span), (LexError, Span)>;
 
 /// A enum representing different kinds of lexed event
 #[derive(Debug, PartialEq, Clone)]
 pub enum LexToken {
     OpenBrace, CloseBrace,
     OpenBracket, CloseBracket,
-    Comma, Colon, Bang,
+    Comma, Colon, Semicolon, Equals, Bang,
     Identifier(String),
     StringLit(String),
     IntegerLit(i64),
     FloatLit(f64),
+    DateTimeLit(DateTime),
+    DurationLit(Duration),
+    BytesLit(Vec<u8>),
+    /// A run of whitespace, including line endings (only produced in trivia mode)
+    Whitespace(String),
+    /// A comment, including its delimiters but not the line ending that ends a single
+    /// line comment (only produced in trivia mode)
+    Comment(String),
 }
 
 /// An enum representing different kinds of lexing errors
 ///
 /// May be referenced in a `ParseError` if the parsing failed due to a lexical error
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum LexError {
     UnclosedCommentError,
     UnclosedStringError,
     UnclosedIdentError,
     NewlineInIdentifier,
     InvalidEscape(char),
+    /// A unicode escape for a value that isn't a unicode scalar value (a surrogate, or
+    /// anything above `10FFFF`)
     InvalidUnicodeEscape(u32),
+    /// A braced unicode escape (`\u{1F600}`) without hex digits, with more than six
+    /// digits, or without a closing brace
+    MalformedUnicodeEscape,
     FloatParseError(<f64 as FromStr>::Err),
     IntegerParseError(<i64 as FromStr>::Err),
+    /// A character that is not a valid digit directly follows a hexadecimal, octal,
+    /// binary, or explicitly decimal integer literal
+    InvalidDigit(char),
+    /// A `0x`, `0o`, `0b`, or `0d` prefix is not followed by any digits
+    MissingDigits,
+    /// A `_` digit separator that isn't between two digits
+    InvalidDigitSeparator,
+    /// A date and time literal that is malformed or out of range
+    InvalidDateTime,
+    /// A duration literal with an unknown or out-of-order unit, that is too large, or
+    /// that isn't made of unsigned integers (`1.5s`)
+    InvalidDuration,
+    /// A character in a byte string (`b"..."`) that is not ASCII
+    InvalidByte(char),
+    /// A character in a base64 string (`base64"..."`) that is not valid base64, or the
+    /// closing quote if the string is truncated
+    InvalidBase64(char),
     UnrecognisedCharError(char),
+    /// The input isn't valid UTF-8
+    InvalidUtf8,
+    /// The input starts with the byte order mark of an encoding other than UTF-8
+    UnsupportedEncoding(&'static str),
+    /// The input could not be read
+    IoError(io::ErrorKind),
+    /// The input isn't valid in the encoding it is being decoded from (only produced with
+    /// the `encoding` feature).  Contains the name of the encoding.
+    DecodeError(&'static str),
+    /// An identifier or string is longer than the maximum token length
+    TokenTooLong,
+    /// The input is larger than the maximum document size
+    DocumentTooLarge,
+}
+
+impl LexError {
+    /// A stable code for the kind of error, like `E0102` for an unclosed string, which
+    /// tools and tests can match on instead of the message.  Lexical errors have codes
+    /// from `E0101`, and a code is never changed or reused for a different error.
+    pub fn code(&self) -> &'static str {
+        match *self {
+            LexError::UnclosedCommentError => "E0101",
+            LexError::UnclosedStringError => "E0102",
+            LexError::UnclosedIdentError => "E0103",
+            LexError::NewlineInIdentifier => "E0104",
+            LexError::InvalidEscape(_) => "E0105",
+            LexError::InvalidUnicodeEscape(_) => "E0106",
+            LexError::MalformedUnicodeEscape => "E0107",
+            LexError::FloatParseError(_) => "E0108",
+            LexError::IntegerParseError(_) => "E0109",
+            LexError::InvalidDigit(_) => "E0110",
+            LexError::MissingDigits => "E0111",
+            LexError::InvalidDigitSeparator => "E0112",
+            LexError::InvalidDateTime => "E0113",
+            LexError::InvalidDuration => "E0114",
+            LexError::InvalidByte(_) => "E0115",
+            LexError::InvalidBase64(_) => "E0116",
+            LexError::UnrecognisedCharError(_) => "E0117",
+            LexError::InvalidUtf8 => "E0118",
+            LexError::UnsupportedEncoding(_) => "E0119",
+            LexError::IoError(_) => "E0120",
+            LexError::DecodeError(_) => "E0121",
+            LexError::TokenTooLong => "E0122",
+            LexError::DocumentTooLarge => "E0123",
+        }
+    }
+}
+
+impl fmt::Display for LexToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LexToken::OpenBrace => f.write_str("`{`"),
+            LexToken::CloseBrace => f.write_str("`}`"),
+            LexToken::OpenBracket => f.write_str("`[`"),
+            LexToken::CloseBracket => f.write_str("`]`"),
+            LexToken::Comma => f.write_str("`,`"),
+            LexToken::Colon => f.write_str("`:`"),
+            LexToken::Semicolon => f.write_str("`;`"),
+            LexToken::Equals => f.write_str("`=`"),
+            LexToken::Bang => f.write_str("`!`"),
+            LexToken::Identifier(ref ident) => write!(f, "identifier `{}`", ident),
+            LexToken::StringLit(ref string) => write!(f, "string {:?}", string),
+            LexToken::IntegerLit(int) => write!(f, "integer {}", int),
+            LexToken::FloatLit(float) => write!(f, "float {}", float),
+            LexToken::DateTimeLit(ref datetime) => write!(f, "datetime {}", datetime),
+            LexToken::DurationLit(_) => f.write_str("duration"),
+            LexToken::BytesLit(_) => f.write_str("byte string"),
+            LexToken::Whitespace(_) => f.write_str("whitespace"),
+            LexToken::Comment(_) => f.write_str("comment"),
+        }
+    }
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LexError::UnclosedCommentError => f.write_str("unclosed block comment"),
+            LexError::UnclosedStringError => f.write_str("unclosed string"),
+            LexError::UnclosedIdentError => f.write_str("unclosed identifier"),
+            LexError::NewlineInIdentifier => f.write_str("newline in identifier"),
+            LexError::InvalidEscape(c) => write!(f, "invalid escape `\\{}`", c),
+            LexError::InvalidUnicodeEscape(code) => {
+                write!(f, "unicode escape `\\u{{{:x}}}` is not a unicode scalar value", code)
+            },
+            LexError::MalformedUnicodeEscape => f.write_str("malformed unicode escape"),
+            LexError::FloatParseError(ref error) => write!(f, "invalid float: {}", error),
+            LexError::IntegerParseError(ref error) => write!(f, "invalid integer: {}", error),
+            LexError::InvalidDigit(c) => write!(f, "invalid digit {:?} in integer", c),
+            LexError::MissingDigits => f.write_str("integer prefix is not followed by any digits"),
+            LexError::InvalidDigitSeparator => {
+                f.write_str("digit separator `_` is not between two digits")
+            },
+            LexError::InvalidDateTime => f.write_str("invalid datetime"),
+            LexError::InvalidDuration => f.write_str("invalid duration"),
+            LexError::InvalidByte(c) => write!(f, "non-ASCII character {:?} in byte string", c),
+            LexError::InvalidBase64(c) => write!(f, "invalid character {:?} in base64 string", c),
+            LexError::UnrecognisedCharError(c) => write!(f, "unrecognised character {:?}", c),
+            LexError::InvalidUtf8 => f.write_str("input is not valid UTF-8"),
+            LexError::UnsupportedEncoding(encoding) => {
+                write!(f, "input is encoded as {}, which is not supported", encoding)
+            },
+            LexError::IoError(kind) => write!(f, "could not read input: {}", kind),
+            LexError::DecodeError(encoding) => write!(f, "input is not valid {}", encoding),
+            LexError::TokenTooLong => f.write_str("token is longer than the maximum token length"),
+            LexError::DocumentTooLarge => {
+                f.write_str("input is larger than the maximum document size")
+            },
+        }
+    }
+}
+
+impl Error for LexError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            LexError::FloatParseError(ref error) => Some(error),
+            LexError::IntegerParseError(ref error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// Where a `Lexer` reads its characters from.  `ReaderSource` reads them from any
+/// `Read`, and `StrSource` from a string.
+pub trait Source {
+    /// The next character of the input, or `None` at the end of the input, or once
+    /// it can't be read any further
+    fn next_char(&mut self) -> Option<char>;
+
+    /// Take the error that stopped the input from being read, if there was one.  This
+    /// is only called once every character has been lexed.
+    fn take_error(&mut self) -> Option<LexError>;
+
+    /// Set the maximum number of bytes that will be read.  Reading more than this is a
+    /// `DocumentTooLarge` error.
+    fn set_limit(&mut self, limit: Option<usize>);
+}
+
+/// Reads the characters of a `Lexer` from a reader, a line at a time
+pub struct ReaderSource(CharReader<io::BufReader<Box<dyn Read>>>);
+
+impl Source for ReaderSource {
+    fn next_char(&mut self) -> Option<char> {
+        self.0.next()
+    }
+
+    fn take_error(&mut self) -> Option<LexError> {
+        self.0.take_error()
+    }
+
+    fn set_limit(&mut self, limit: Option<usize>) {
+        self.0.set_limit(limit);
+    }
+}
+
+/// Reads the characters of a `Lexer` from a string, without copying it
+pub struct StrSource<'a> {
+    input: &'a str,
+    /// the byte offset of the next character
+    offset: usize,
+    limit: Option<usize>,
+    error: Option<LexError>,
+}
+
+impl<'a> StrSource<'a> {
+    pub fn new(input: &'a str) -> Self {
+        // the byte order mark is skipped, in the same way as by `ReaderSource`
+        let offset = if input.starts_with('\u{FEFF}') { 3 } else { 0 };
+        StrSource { input, offset, limit: None, error: None }
+    }
 }
 
-pub struct Lexer {
+impl<'a> Source for StrSource<'a> {
+    fn next_char(&mut self) -> Option<char> {
+        let next = self.input[self.offset..].chars().next()?;
+        let end = self.offset + next.len_utf8();
+        if self.limit.is_some_and(|limit| end > limit) {
+            self.offset = self.input.len();
+            self.error = Some(LexError::DocumentTooLarge);
+            return None;
+        }
+        self.offset = end;
+        Some(next)
+    }
+
+    fn take_error(&mut self) -> Option<LexError> {
+        self.error.take()
+    }
+
+    fn set_limit(&mut self, limit: Option<usize>) {
+        self.limit = limit;
+    }
+}
+
+/// A lexer over a reader of figtree input, producing a stream of tokens.
+///
+/// Lexers made with `lex` read from a reader.  `from_str` makes one that reads a string
+/// in place, and `with_source` one that reads from any other `Source`.
+///
+/// As an `Iterator`, the lexer produces the tokens themselves.  `spanned` turns it into
+/// an iterator that also produces the span of the input that each token came from.
+/// After an error, the lexer stops producing tokens.
+///
+/// Whitespace and comments are skipped, unless trivia mode is turned on with
+/// `set_trivia`.  In trivia mode they are produced as `Whitespace` and `Comment` tokens,
+/// and the spans of all the tokens cover the whole input, which makes the lexer usable
+/// for formatters and syntax highlighters.
+///
+/// # Examples
+/// ```
+/// # use figtree::{Lexer, LexToken};
+/// # use std::io::Cursor;
+/// let mut lexer = Lexer::lex(Cursor::new("key = 1 // one"));
+/// lexer.set_trivia(true);
+/// let tokens: Vec<_> = lexer.map(|token| token.unwrap()).collect();
+/// assert_eq!(tokens, vec![
+///     LexToken::Identifier("key".to_string()),
+///     LexToken::Whitespace(" ".to_string()),
+///     LexToken::Equals,
+///     LexToken::Whitespace(" ".to_string()),
+///     LexToken::IntegerLit(1),
+///     LexToken::Whitespace(" ".to_string()),
+///     LexToken::Comment("// one".to_string()),
+/// ]);
+/// ```
+#[derive(Clone)]
+pub struct Lexer<S = ReaderSource> {
     pub token_start: MutablePosition,
     pub position: MutablePosition,
-    input: CharReader<io::BufReader<Box<Read>>>,
+    /// whether a newline was skipped over before the most recently lexed token
+    pub newline_before: bool,
+    input: S,
     stored_next: Vec<char>,
     errored: bool,
-    peeked_next: Option<LexResult>,
+    peeked_next: Option<(LexResult, Span)>,
+    max_token_length: Option<usize>,
+    tab_width: usize,
+    normalize_newlines: bool,
+    /// whether the most recently popped character was a carriage return, which makes a
+    /// following line feed part of the same line ending
+    after_cr: bool,
+    /// the state before the most recently popped characters, so that they can be
+    /// returned
+    history: VecDeque<(MutablePosition, bool, usize)>,
+    /// the byte offset of the next character, counted from the first character that the
+    /// source produced
+    offset: usize,
+    /// the part of the input that the text of the token being lexed was taken from
+    /// unchanged, if it was
+    verbatim: Option<Range<usize>>,
+    /// the spans of the identifiers and strings lexed so far whose text can be sliced
+    /// out of the input, along with where to slice it, if they are being collected
+    texts: Option<VecDeque<(Span, Range<usize>)>>,
+    trivia: bool,
+    /// the text of the current trivia token, which popped characters are added to
+    recording: Option<String>,
+    /// the comments skipped so far, if they are being collected
+    comments: Option<Vec<(String, Span)>>,
 }
 
+/// The number of characters that can be returned to the lexer after being popped
+const HISTORY_LENGTH: usize = 8;
+
+/// The default size of the buffer that input is read into
+const DEFAULT_CAPACITY: usize = 8 * 1024;
+
 impl Lexer {
+    /// Construct a lexer over a reader.  Input is read into a buffer of 8KiB, so that
+    /// unbuffered readers (like a `File` or a `TcpStream`) aren't read a byte at a time.
     pub fn lex<R: Read + 'static>(reader: R) -> Self {
+        Self::with_capacity(DEFAULT_CAPACITY, reader)
+    }
+
+    /// Construct a lexer over a reader, reading input into a buffer of `capacity` bytes.
+    ///
+    /// Larger buffers mean fewer reads from the underlying reader.  Readers that already
+    /// have their own buffer can use a small capacity, to avoid buffering twice.
+    pub fn with_capacity<R: Read + 'static>(capacity: usize, reader: R) -> Self {
+        let reader: Box<dyn Read> = Box::new(reader);
+        let reader = CharReader::new(io::BufReader::with_capacity(capacity, reader));
+        Lexer::with_source(ReaderSource(reader))
+    }
+}
+
+impl<'a> Lexer<StrSource<'a>> {
+    /// Construct a lexer over a string, which reads it in place instead of copying it.
+    /// A byte order mark at the start of the string is skipped.
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(input: &'a str) -> Self {
+        Lexer::with_source(StrSource::new(input))
+    }
+}
+
+impl<S: Source> Lexer<S> {
+    /// Construct a lexer over any source of characters
+    pub fn with_source(input: S) -> Self {
         Lexer {
-            input: CharReader::new(io::BufReader::new(Box::new(reader))),
+            input,
             token_start: MutablePosition::new(),
             position: MutablePosition::new(),
+            newline_before: false,
             stored_next: Vec::new(),
             errored: false,
             peeked_next: None,
+            max_token_length: None,
+            tab_width: 1,
+            normalize_newlines: false,
+            after_cr: false,
+            history: VecDeque::with_capacity(HISTORY_LENGTH),
+            offset: 0,
+            verbatim: None,
+            texts: None,
+            trivia: false,
+            recording: None,
+            comments: None,
+        }
+    }
+
+    /// Set the width of a tab for column numbers.  A tab moves the column on to the next
+    /// multiple of `width`, so that columns match what an editor displays.  A width of
+    /// `1` (the default) counts each tab as a single column.
+    pub fn set_tab_width(&mut self, width: usize) {
+        self.tab_width = width.max(1);
+    }
+
+    /// Convert `\r\n` and lone `\r` line endings inside strings into `\n`, so that files
+    /// with Windows line endings produce the same strings as files with Unix ones.
+    /// Escaped carriage returns (`"\r"`) are left alone.
+    pub fn set_normalize_newlines(&mut self, normalize: bool) {
+        self.normalize_newlines = normalize;
+    }
+
+    /// Set the maximum length in bytes of identifiers, strings and byte strings.  Longer
+    /// tokens produce a `TokenTooLong` error.
+    pub fn set_max_token_length(&mut self, limit: Option<usize>) {
+        self.max_token_length = limit;
+    }
+
+    /// Set the maximum number of bytes that will be read from the input.  Reading more
+    /// than this produces a `DocumentTooLarge` error.
+    pub fn set_max_document_size(&mut self, limit: Option<usize>) {
+        self.input.set_limit(limit);
+    }
+
+    /// Produce whitespace and comments as `Whitespace` and `Comment` tokens, instead of
+    /// skipping over them.  Parsers always turn this off.
+    pub fn set_trivia(&mut self, trivia: bool) {
+        self.trivia = trivia;
+    }
+
+    /// Keep the text and span of each comment that is skipped, to be returned by
+    /// `take_comments`.  Comments include their delimiters (`// `, `#` or `/* */`), but
+    /// not the line ending after a line comment.
+    pub fn set_collect_comments(&mut self, collect: bool) {
+        if !collect {
+            self.comments = None;
+        } else if self.comments.is_none() {
+            self.comments = Some(Vec::new());
+        }
+    }
+
+    /// Take the comments that have been skipped since this was last called, in order.
+    /// Comments are only collected once `set_collect_comments` has been turned on.
+    pub fn take_comments(&mut self) -> Vec<(String, Span)> {
+        self.comments.as_mut().map(::std::mem::take).unwrap_or_default()
+    }
+
+    /// The source that the lexer is reading characters from
+    pub(crate) fn source_mut(&mut self) -> &mut S {
+        &mut self.input
+    }
+
+    /// Keep the span of each identifier and string whose text appears in the input
+    /// exactly as it was written, along with the byte offsets of that text, to be taken
+    /// with `next_text`.  Offsets count from the first character the source produced,
+    /// so they don't include a byte order mark.
+    pub(crate) fn set_collect_texts(&mut self, collect: bool) {
+        self.texts = if collect { Some(VecDeque::new()) } else { None };
+    }
+
+    /// Take the first collected text inside `within`, skipping any that come before it
+    pub(crate) fn next_text(&mut self, within: &Span) -> Option<(Span, Range<usize>)> {
+        let texts = self.texts.as_mut()?;
+        while texts.front().is_some_and(|(span, _)| span.start < within.start) {
+            texts.pop_front();
+        }
+        match texts.front() {
+            Some((span, _)) if span.end <= within.end => texts.pop_front(),
+            _ => None,
         }
     }
 
     pub fn peek(&mut self) -> Option<&LexResult> {
         if self.peeked_next.is_none() {
-            self.peeked_next = self.next();
+            self.peeked_next = self.next_with_span();
         }
 
-        self.peeked_next.as_ref()
+        self.peeked_next.as_ref().map(|next| &next.0)
+    }
+
+    /// Lex the next token, along with the span of the input that it covers.  For errors,
+    /// the span covers the input that was read before the error was found.
+    pub fn next_spanned(&mut self) -> Option<SpannedResult> {
+        let next = self.peeked_next.take().or_else(|| self.next_with_span());
+        next.map(|(result, span)| {
+            match result {
+                Ok(tok) => Ok((tok, span)),
+                Err(err) => Err((err, span)),
+            }
+        })
+    }
+
+    /// Turn this lexer into an iterator over tokens and their spans
+    ///
+    /// # Examples
+    /// ```
+    /// # use figtree::{Lexer, LexToken, Position, Span};
+    /// # use std::io::Cursor;
+    /// let mut tokens = Lexer::lex(Cursor::new("node {\n  'key'")).spanned();
+    /// assert_eq!(tokens.next().unwrap().unwrap(),
+    ///     (LexToken::Identifier("node".to_string()), Span::new(Position::at(0, 0), Position::at(0, 4))));
+    /// tokens.next();
+    /// assert_eq!(tokens.next().unwrap().unwrap().1, Span::new(Position::at(1, 2), Position::at(1, 7)));
+    /// ```
+    pub fn spanned(self) -> SpannedTokens<S> {
+        SpannedTokens { lexer: self }
+    }
+
+    fn next_with_span(&mut self) -> Option<(LexResult, Span)> {
+        self.next_token().map(|result| {
+            let span = Span::new(self.token_start.clone().freeze(), self.position.clone().freeze());
+            let verbatim = self.verbatim.take();
+            let text = matches!(result, Ok(LexToken::Identifier(_)) | Ok(LexToken::StringLit(_)));
+            if let (true, Some(texts), Some(range)) = (text, self.texts.as_mut(), verbatim) {
+                texts.push_back((span.clone(), range));
+            }
+            (result, span)
+        })
     }
 
     fn err(&mut self, err: LexError) -> Option<LexResult> {
@@ -70,15 +516,38 @@ impl Lexer {
         Some(Err(err))
     }
 
+    /// Report an error at a position inside the current token
+    fn err_at(&mut self, position: MutablePosition, err: LexError) -> Option<LexResult> {
+        self.token_start = position;
+        self.err(err)
+    }
+
+    /// The position of the character that was just popped
+    fn last_position(&self) -> MutablePosition {
+        self.history.back().expect("no character has been popped").0.clone()
+    }
+
     fn pop_next(&mut self) -> Option<char> {
         if let Some(next) =
             if let Some(next) = self.stored_next.pop() { Some(next) }
-            else { self.input.next() } {
+            else { self.input.next_char() } {
 
-            if next == '\n' {
-                self.position.new_line();
-            } else {
-                self.position.push(1);
+            if self.history.len() == HISTORY_LENGTH {
+                self.history.pop_front();
+            }
+            self.history.push_back((self.position.clone(), self.after_cr, self.offset));
+            self.offset += next.len_utf8();
+
+            match next {
+                // `\r\n` is a single line ending
+                '\n' if self.after_cr => {},
+                '\n' | '\r' => { self.position.new_line(); },
+                '\t' => { self.position.tab(self.tab_width); },
+                _ => { self.position.push(1); },
+            }
+            self.after_cr = next == '\r';
+            if let Some(ref mut recording) = self.recording {
+                recording.push(next);
             }
 
             Some(next)
@@ -88,11 +557,32 @@ impl Lexer {
     }
 
     fn ret_next(&mut self, returned: char) {
-        self.position.unpush(1);
+        let (position, after_cr, offset) =
+            self.history.pop_back().expect("too many characters returned");
+        self.position = position;
+        self.after_cr = after_cr;
+        self.offset = offset;
+        if let Some(ref mut recording) = self.recording {
+            recording.pop();
+        }
         self.stored_next.push(returned);
     }
 
+    /// Test if `ch`, which has just been popped, is the start of a line ending that should
+    /// be normalized to `\n`.  The rest of a `\r\n` line ending is consumed.
+    fn normalize_line_ending(&mut self, ch: char) -> bool {
+        if ch != '\r' || !self.normalize_newlines {
+            return false;
+        }
+        match self.pop_next() {
+            Some('\n') | None => {},
+            Some(next_char) => self.ret_next(next_char),
+        }
+        true
+    }
+
     fn parse_ident(&mut self) -> Option<LexResult> {
+        let start = self.offset;
         if let Some(next_char) = self.pop_next() {
             let mut ident = String::new();
             if ident_head(next_char) {
@@ -103,9 +593,7 @@ impl Lexer {
             }
 
             while let Some(next_char) = self.pop_next() {
-                if ident_body(next_char) {
-                    ident.push(next_char);
-                } else if next_char == '_' {
+                if ident_body(next_char) || next_char == '_' {
                     ident.push(next_char);
                 } else {
                     self.ret_next(next_char);
@@ -113,39 +601,55 @@ impl Lexer {
                 }
             }
 
+            self.verbatim = Some(start..self.offset);
             Some(Ok(LexToken::Identifier(ident)))
         } else {
             None
         }
     }
 
-    fn parse_int(&mut self, base: u32) -> Option<LexResult> {
+    fn parse_int(&mut self, base: u32, negative: bool) -> Option<LexResult> {
         let mut buffer = String::new();
+        if negative {
+            buffer.push('-');
+        }
 
+        // the position of a '_' that hasn't yet been followed by a digit
+        let mut separator = None;
         while let Some(next_char) = self.pop_next() {
             if next_char.is_digit(base) {
                 buffer.push(next_char);
+                separator = None;
             } else if next_char == '_' {
-                continue; // accepted but ignored
+                // separators must come between two digits
+                if separator.is_some() || buffer.is_empty() || buffer == "-" {
+                    let position = self.last_position();
+                    return self.err_at(position, LexError::InvalidDigitSeparator);
+                }
+                separator = Some(self.last_position());
+            } else if ident_body(next_char) {
+                // a digit that isn't valid for this base, or a letter directly after
+                // the number - report it at the position of the offending character
+                let position = self.last_position();
+                return self.err_at(position, LexError::InvalidDigit(next_char));
             } else {
                 self.ret_next(next_char);
                 break;
             }
         }
 
-        if buffer.len() == 0 {
-            self.ret_next(match base {
-                2 => 'b',
-                8 => 'o',
-                10 => 'd',
-                16 => 'x',
-                _ => unreachable!("parse_int called with incorrect base"),
-            });
+        if let Some(position) = separator {
+            return self.err_at(position, LexError::InvalidDigitSeparator);
+        }
 
-            return Some(Ok(LexToken::IntegerLit(0)));
+        if buffer.is_empty() || buffer == "-" {
+            return self.err(LexError::MissingDigits);
         }
 
-        Some(Ok(LexToken::IntegerLit(i64::from_str_radix(&buffer, base).unwrap())))
+        match i64::from_str_radix(&buffer, base) {
+            Ok(intgr) => Some(Ok(LexToken::IntegerLit(intgr))),
+            Err(err) => self.err(LexError::IntegerParseError(err)),
+        }
     }
 
     fn parse_exponent(&mut self) -> String {
@@ -159,7 +663,7 @@ impl Lexer {
         }
 
         while let Some(next_char) = self.pop_next() {
-            if next_char.is_digit(10) {
+            if next_char.is_ascii_digit() {
                 exponent.push(next_char)
             } else {
                 self.ret_next(next_char);
@@ -172,6 +676,7 @@ impl Lexer {
 
     fn parse_float_int(&mut self) -> Option<LexResult> {
         let mut sign = '+';
+        let mut signed = false;
         let mut is_float = false;
         let mut buffer = String::new();
         let mut exponent = String::new();
@@ -179,13 +684,44 @@ impl Lexer {
         if let Some(next_char) = self.pop_next() {
             if next_char == '+' || next_char == '-' {
                 sign = next_char;
+                signed = true;
+
+                // signed infinities and NaNs are lexed as floats, because the sign can't
+                // be part of an identifier.  A bare `inf` or `nan` is left for the
+                // parser to interpret.
+                match self.parse_ident() {
+                    Some(Ok(LexToken::Identifier(ref ident))) if ident == "inf" => {
+                        return Some(Ok(LexToken::FloatLit(
+                            if sign == '+' { f64::INFINITY } else { f64::NEG_INFINITY })));
+                    },
+                    Some(Ok(LexToken::Identifier(ref ident))) if ident == "nan" => {
+                        return Some(Ok(LexToken::FloatLit(f64::NAN)));
+                    },
+                    Some(Ok(LexToken::Identifier(ident))) => {
+                        for ch in ident.chars().rev() {
+                            self.ret_next(ch);
+                        }
+                    },
+                    _ => {},
+                }
             } else {
                 self.ret_next(next_char);
             }
         }
 
+        // whether the last character was a digit, and the position of a '_' that
+        // hasn't yet been followed by a digit
+        let mut after_digit = false;
+        let mut separator = None;
         while let Some(next_char) = self.pop_next() {
+            if next_char != '_' && !next_char.is_ascii_digit() {
+                if let Some(position) = separator {
+                    return self.err_at(position, LexError::InvalidDigitSeparator);
+                }
+            }
+
             if next_char == '.' {
+                after_digit = false;
                 if is_float {
                     // already a float - can't have two periods!
                     self.ret_next(next_char);
@@ -194,13 +730,39 @@ impl Lexer {
                     is_float = true;
                     buffer.push(next_char);
                 }
-            } else if next_char.is_digit(10) {
+            } else if next_char.is_ascii_digit() {
                 buffer.push(next_char);
+                after_digit = true;
+                separator = None;
             } else if next_char == '_' {
-                continue;  // accepted, but ignored
+                // separators must come between two digits
+                if !after_digit || separator.is_some() {
+                    let position = self.last_position();
+                    return self.err_at(position, LexError::InvalidDigitSeparator);
+                }
+                separator = Some(self.last_position());
+            } else if next_char == '-' && !signed && buffer.len() == 4 && !is_float {
+                // four digits and a dash can only be the start of a date
+                return self.parse_datetime(buffer);
+            } else if next_char.is_ascii_alphabetic() && next_char != 'e' && next_char != 'E' &&
+                !buffer.is_empty() {
+
+                // a number directly followed by a unit is a duration, which must be
+                // unsigned and made of integers
+                self.ret_next(next_char);
+                if signed || is_float {
+                    return self.invalid_duration();
+                }
+                return self.parse_duration(buffer);
             } else if next_char == 'e' || next_char == 'E' {
                 exponent = self.parse_exponent();
                 is_float = true;
+                if let Some(next_char) = self.pop_next() {
+                    self.ret_next(next_char);
+                    if next_char.is_ascii_alphabetic() {
+                        return self.invalid_duration();
+                    }
+                }
                 break;
             } else {
                 self.ret_next(next_char);
@@ -208,6 +770,10 @@ impl Lexer {
             }
         }
 
+        if let Some(position) = separator {
+            return self.err_at(position, LexError::InvalidDigitSeparator);
+        }
+
         if is_float {
             let str_float = buffer + &exponent;
             Some(str_float.parse::<f64>()
@@ -232,54 +798,191 @@ impl Lexer {
         }
     }
 
-    fn parse_numeric(&mut self) -> Option<LexResult> {
-        if let Some(next_char) = self.pop_next() {
-            if let Some(after) = self.pop_next() {
-                if next_char == '0' && after == 'd' {
-                    self.parse_int(10)
-                } else if next_char == '0' && after == 'x'  {
-                    self.parse_int(16)
-                } else if next_char == '0' && after == 'o' {
-                    self.parse_int(8)
-                } else if next_char == '0' && after == 'b' {
-                    self.parse_int(2)
+    fn parse_datetime(&mut self, year: String) -> Option<LexResult> {
+        let mut buffer = year;
+        buffer.push('-');
+
+        while let Some(next_char) = self.pop_next() {
+            if next_char.is_ascii_digit() ||
+                matches!(next_char, '-' | ':' | '.' | '+' | 'T' | 't' | 'Z' | 'z') {
+
+                buffer.push(next_char);
+            } else {
+                self.ret_next(next_char);
+                break;
+            }
+        }
+
+        match buffer.parse() {
+            Ok(datetime) => Some(Ok(LexToken::DateTimeLit(datetime))),
+            Err(_) => self.err(LexError::InvalidDateTime),
+        }
+    }
+
+    /// Skip the rest of a bad duration literal, so that the error covers all of it
+    fn invalid_duration(&mut self) -> Option<LexResult> {
+        while let Some(next_char) = self.pop_next() {
+            if !next_char.is_ascii_alphanumeric() && next_char != '.' && next_char != '_' {
+                self.ret_next(next_char);
+                break;
+            }
+        }
+        self.err(LexError::InvalidDuration)
+    }
+
+    /// Parse a duration (`1h30m`), after the digits of the first component
+    fn parse_duration(&mut self, digits: String) -> Option<LexResult> {
+        let mut digits = digits;
+        let mut total = Duration::new(0, 0);
+        // units must get smaller from left to right
+        let mut last_rank = None;
+
+        loop {
+            let mut unit = String::new();
+            while let Some(next_char) = self.pop_next() {
+                if next_char.is_ascii_alphabetic() {
+                    unit.push(next_char);
                 } else {
-                    self.ret_next(after);      // put back the two characters we popped
-                    self.ret_next(next_char);  // off, but in the right order
-                    self.parse_float_int()
+                    self.ret_next(next_char);
+                    break;
                 }
-            } else {
-                Some(Ok(LexToken::IntegerLit(next_char.to_digit(10).unwrap() as i64)))
             }
+
+            let amount = match digits.parse::<u64>() {
+                Ok(amount) => amount,
+                Err(_) => { return self.invalid_duration(); },
+            };
+            let (rank, component) = match &*unit {
+                "ns" => (0, Some(Duration::from_nanos(amount))),
+                "us" => (1, Some(Duration::from_micros(amount))),
+                "ms" => (2, Some(Duration::from_millis(amount))),
+                "s" => (3, Some(Duration::from_secs(amount))),
+                "m" => (4, amount.checked_mul(60).map(Duration::from_secs)),
+                "h" => (5, amount.checked_mul(60 * 60).map(Duration::from_secs)),
+                "d" => (6, amount.checked_mul(24 * 60 * 60).map(Duration::from_secs)),
+                _ => { return self.invalid_duration(); },
+            };
+            if last_rank.is_some_and(|last_rank| rank >= last_rank) {
+                return self.invalid_duration();
+            }
+            last_rank = Some(rank);
+
+            match component.and_then(|component| total.checked_add(component)) {
+                Some(sum) => total = sum,
+                None => { return self.invalid_duration(); },
+            }
+
+            digits = String::new();
+            while let Some(next_char) = self.pop_next() {
+                if next_char.is_ascii_digit() {
+                    digits.push(next_char);
+                } else {
+                    self.ret_next(next_char);
+                    break;
+                }
+            }
+            if digits.is_empty() {
+                break;
+            }
+        }
+
+        Some(Ok(LexToken::DurationLit(total)))
+    }
+
+    fn parse_numeric(&mut self) -> Option<LexResult> {
+        let sign = match self.pop_next() {
+            Some(sign @ '+') | Some(sign @ '-') => Some(sign),
+            Some(next_char) => { self.ret_next(next_char); None },
+            None => { return None; },
+        };
+
+        let base = match self.pop_next() {
+            Some('0') => {
+                let base = match self.pop_next() {
+                    Some('d') => Some(10),
+                    Some('x') => Some(16),
+                    Some('o') => Some(8),
+                    Some('b') => Some(2),
+                    Some(after) => { self.ret_next(after); None },
+                    None => None,
+                };
+                if base.is_none() {
+                    self.ret_next('0');
+                }
+                base
+            },
+            Some(next_char) => { self.ret_next(next_char); None },
+            None => None,
+        };
+
+        if let Some(base) = base {
+            self.parse_int(base, sign == Some('-'))
         } else {
-            None
+            if let Some(sign) = sign {
+                self.ret_next(sign);
+            }
+            self.parse_float_int()
         }
     }
 
     fn parse_unicode(&mut self) -> Result<char, LexError> {
-        let mut uvalue = 0;
-        for _ in 0..4 {
-            if let Some(ch) = self.pop_next() {
-                uvalue = match ch {
-                    '0'...'9' =>
-                        uvalue * 16 + ((ch as u16) - ('0' as u16)),
-                    'a'...'f' =>
-                        uvalue * 16 + (10 + (ch as u16) - ('a' as u16)),
-                    'A'...'F' =>
-                        uvalue * 16 + (10 + (ch as u16) - ('A' as u16)),
-                    _ => {
-                        return Err(LexError::InvalidUnicodeEscape(uvalue as u32));
-                    },
-                };
-            } else {
-                return Err(LexError::UnclosedStringError);
+        // either exactly four hex digits (`é`), or one to six in braces (`\u{1F600}`)
+        let braced = match self.pop_next() {
+            Some('{') => true,
+            Some(ch) => { self.ret_next(ch); false },
+            None => { return Err(LexError::UnclosedStringError); },
+        };
+
+        let mut uvalue: u32 = 0;
+        let mut digits = 0;
+        loop {
+            let ch = match self.pop_next() {
+                Some(ch) => ch,
+                None => { return Err(LexError::UnclosedStringError); },
+            };
+
+            if braced && ch == '}' {
+                if digits == 0 {
+                    return Err(LexError::MalformedUnicodeEscape);
+                }
+                break;
+            }
+
+            match ch.to_digit(16) {
+                Some(digit) if !braced || digits < 6 => {
+                    uvalue = uvalue * 16 + digit;
+                    digits += 1;
+                },
+                _ if braced => { return Err(LexError::MalformedUnicodeEscape); },
+                _ => { return Err(LexError::InvalidUnicodeEscape(uvalue)); },
+            }
+
+            if !braced && digits == 4 {
+                break;
             }
         }
 
-        if let Some(next_char) = from_u32(uvalue as u32) {
+        if let Some(next_char) = from_u32(uvalue) {
             Ok(next_char)
         } else {
-            Err(LexError::InvalidUnicodeEscape(uvalue as u32))
+            Err(LexError::InvalidUnicodeEscape(uvalue))
+        }
+    }
+
+    fn parse_escape(&mut self) -> Result<char, LexError> {
+        match self.pop_next() {
+            Some('/') => Ok('/'),
+            Some('n') => Ok('\n'),
+            Some('r') => Ok('\r'),
+            Some('t') => Ok('\t'),
+            Some('\\') => Ok('\\'),
+            Some('\"') => Ok('\"'),
+            Some('\'') => Ok('\''),
+            Some('b') => Ok('\x08'),
+            Some('f') => Ok('\x0c'),
+            Some('u') => self.parse_unicode(),
+            Some(c) => Err(LexError::InvalidEscape(c)),
+            None => Err(LexError::UnclosedStringError),
         }
     }
 
@@ -293,53 +996,172 @@ impl Lexer {
             None => { return None; },
         };
 
+        // two quotes are either an empty string or the start of a triple-quoted string
+        match self.pop_next() {
+            Some(next_char) if next_char == quote_char => {
+                match self.pop_next() {
+                    Some(next_char) if next_char == quote_char => {
+                        return self.parse_multiline_string(quote_char);
+                    },
+                    Some(next_char) => { self.ret_next(next_char); },
+                    None => {},
+                }
+                self.verbatim = Some(self.offset - 1..self.offset - 1);
+                return Some(Ok(LexToken::StringLit(buffer)));
+            },
+            Some(next_char) => { self.ret_next(next_char); },
+            None => {},
+        }
+
+        // whether the string is exactly the text between the quotes
+        let start = self.offset;
+        let mut verbatim = true;
         while let Some(next_char) = self.pop_next() {
             if next_char == '\\' {
                 // escape next character
-                match self.pop_next() {
-                    Some('/') => { buffer.push('/'); },
-                    Some('n') => { buffer.push('\n'); },
-                    Some('r') => { buffer.push('\r'); },
-                    Some('t') => { buffer.push('\t'); },
-                    Some('\\') => { buffer.push('\\'); },
-                    Some('\"') => { buffer.push('\"'); },
-                    Some('\'') => { buffer.push('\''); },
-                    Some('b') => { buffer.push('\x08'); },
-                    Some('f') => { buffer.push('\x0c'); },
-                    Some('u') => {
-                        match self.parse_unicode() {
-                            Ok(next_char) => { buffer.push(next_char); },
-                            Err(err) => { return self.err(err); }
-                        }
-                    },
-                    Some(c) => {
-                        return self.err(LexError::InvalidEscape(c));
-                    },
-                    None => {
-                        return self.err(LexError::UnclosedStringError);
-                    },
+                verbatim = false;
+                let position = self.last_position();
+                match self.parse_escape() {
+                    Ok(next_char) => { buffer.push(next_char); },
+                    Err(err) => { return self.err_at(position, err); },
                 }
             } else if next_char == quote_char {
                 quote_closed = true;
                 break;
+            } else if self.normalize_line_ending(next_char) {
+                verbatim = false;
+                buffer.push('\n');
             } else {
                 buffer.push(next_char);
             }
         }
 
-        if quote_closed {
-            Some(Ok(LexToken::StringLit(buffer)))
-        } else {
-            self.err(LexError::UnclosedStringError)
+        if quote_closed {
+            if verbatim {
+                self.verbatim = Some(start..self.offset - 1);
+            }
+            Some(Ok(LexToken::StringLit(buffer)))
+        } else {
+            self.err(LexError::UnclosedStringError)
+        }
+    }
+
+    /// Parse the body of a triple-quoted string, after the opening quotes.  Characters
+    /// are stored alongside whether they came from an escape sequence, so that escaped
+    /// whitespace is never stripped as indentation.
+    fn parse_multiline_string(&mut self, quote_char: char) -> Option<LexResult> {
+        let mut buffer = Vec::new();
+        let mut close_quote_length = 0;
+
+        while let Some(next_char) = self.pop_next() {
+            if next_char == quote_char {
+                close_quote_length += 1;
+                if close_quote_length == 3 {
+                    return Some(Ok(LexToken::StringLit(dedent(&buffer))));
+                }
+                continue;
+            }
+
+            for _ in 0..close_quote_length {
+                buffer.push((quote_char, false));
+            }
+            close_quote_length = 0;
+
+            if next_char == '\\' {
+                let position = self.last_position();
+                match self.parse_escape() {
+                    Ok(next_char) => { buffer.push((next_char, true)); },
+                    Err(err) => { return self.err_at(position, err); },
+                }
+            } else if self.normalize_line_ending(next_char) {
+                buffer.push(('\n', false));
+            } else {
+                buffer.push((next_char, false));
+            }
+        }
+
+        self.err(LexError::UnclosedStringError)
+    }
+
+    /// Parse a byte string (`b"..."`) or a base64 string (`base64"..."`), after the
+    /// prefix.  Errors are reported at the position of the offending character.
+    fn parse_bytes(&mut self, base64: bool) -> Option<LexResult> {
+        let quote_char = match self.pop_next() {
+            Some('\'') => '\'',
+            Some('\"') => '\"',
+            Some(ch) => unreachable!("{:?} should not be a quote char", ch),
+            None => { return None; },
+        };
+
+        let mut bytes = Vec::new();
+        let mut decoder = Base64Decoder::new();
+        while let Some(next_char) = self.pop_next() {
+            if next_char == quote_char {
+                if !base64 {
+                    return Some(Ok(LexToken::BytesLit(bytes)));
+                }
+                return match decoder.finish() {
+                    Some(bytes) => Some(Ok(LexToken::BytesLit(bytes))),
+                    None => {
+                        let position = self.last_position();
+                        self.err_at(position, LexError::InvalidBase64(next_char))
+                    },
+                };
+            }
+
+            if base64 {
+                // whitespace is allowed so that long payloads can be wrapped
+                if !next_char.is_whitespace() && !decoder.push(next_char) {
+                    let position = self.last_position();
+                    return self.err_at(position, LexError::InvalidBase64(next_char));
+                }
+            } else if next_char == '\\' {
+                let position = self.last_position();
+                match self.parse_byte_escape() {
+                    Ok(byte) => bytes.push(byte),
+                    Err(err) => { return self.err_at(position, err); },
+                }
+            } else if next_char.is_ascii() {
+                bytes.push(next_char as u8);
+            } else {
+                let position = self.last_position();
+                return self.err_at(position, LexError::InvalidByte(next_char));
+            }
+        }
+
+        self.err(LexError::UnclosedStringError)
+    }
+
+    fn parse_byte_escape(&mut self) -> Result<u8, LexError> {
+        match self.pop_next() {
+            Some('x') => {
+                let mut value = 0;
+                for _ in 0..2 {
+                    match self.pop_next() {
+                        Some(ch) if ch.is_ascii_hexdigit() =>
+                            value = value * 16 + ch.to_digit(16).unwrap() as u8,
+                        Some(ch) => { return Err(LexError::InvalidEscape(ch)); },
+                        None => { return Err(LexError::UnclosedStringError); },
+                    }
+                }
+                Ok(value)
+            },
+            Some('0') => Ok(0),
+            Some('u') => Err(LexError::InvalidEscape('u')),
+            Some(ch) => {
+                // every other escape is the same as in ordinary strings
+                self.ret_next(ch);
+                self.parse_escape().map(|ch| ch as u8)
+            },
+            None => Err(LexError::UnclosedStringError),
         }
     }
 
     fn parse_raw_string(&mut self) -> Option<LexResult> {
         let mut buffer = String::new();
-        let mut quote_closed = false;
         let mut quote_length = 1;
         assert!(self.pop_next() == Some('r')); // otherwise something wrong has happened
-        let quote_char = match self.pop_next() {
+        let mut quote_char = match self.pop_next() {
             Some('/') => '/',
             Some('|') => '|',
             Some('#') => '#',
@@ -360,34 +1182,71 @@ impl Lexer {
             }
         }
 
+        // Rust-style raw strings (`r#"..."#`) are closed by a double quote followed by
+        // the same number of hashes that opened them
+        let mut hashes = 0;
+        if quote_char == '#' {
+            match self.pop_next() {
+                Some('"') => {
+                    hashes = quote_length;
+                    quote_char = '"';
+                    quote_length = 1;
+                },
+                Some(next_char) => self.ret_next(next_char),
+                None => {},
+            }
+        }
+
+        let start = self.offset;
+        let mut verbatim = true;
         while let Some(next_char) = self.pop_next() {
-            if next_char == quote_char {
-                let mut close_quote_length = 1;
-                while let Some(next_char) = self.pop_next() {
-                    if next_char == quote_char {
-                        close_quote_length += 1;
-                        if close_quote_length == quote_length {
-                            quote_closed = true;
-                            break;
-                        }
-                    } else {
-                        for _ in 0..(close_quote_length) {
-                            buffer.push(quote_char);
-                        }
-                        buffer.push(next_char);
-                        break;
-                    }
+            if next_char != quote_char {
+                if self.normalize_line_ending(next_char) {
+                    verbatim = false;
+                    buffer.push('\n');
+                } else {
+                    buffer.push(next_char);
                 }
+                continue;
+            }
 
-                if quote_closed {
-                    break;
+            let mut close_quote_length = 1;
+            while close_quote_length < quote_length {
+                match self.pop_next() {
+                    Some(next_char) if next_char == quote_char => close_quote_length += 1,
+                    Some(next_char) => { self.ret_next(next_char); break; },
+                    None => break,
                 }
-            } else {
-                buffer.push(next_char);
+            }
+
+            let mut close_hashes = 0;
+            if close_quote_length == quote_length {
+                while close_hashes < hashes {
+                    match self.pop_next() {
+                        Some('#') => close_hashes += 1,
+                        Some(next_char) => { self.ret_next(next_char); break; },
+                        None => break,
+                    }
+                }
+                if close_hashes == hashes {
+                    // the delimiters are all a single byte long
+                    if verbatim {
+                        self.verbatim = Some(start..self.offset - quote_length - hashes);
+                    }
+                    return Some(Ok(LexToken::StringLit(buffer)));
+                }
+            }
+
+            // not a closing delimiter, so it was part of the string
+            for _ in 0..close_quote_length {
+                buffer.push(quote_char);
+            }
+            for _ in 0..close_hashes {
+                buffer.push('#');
             }
         }
 
-        Some(Ok(LexToken::StringLit(buffer)))
+        self.err(LexError::UnclosedStringError)
     }
 
     fn parse_ident_escaped(&mut self) -> Option<LexResult> {
@@ -399,9 +1258,12 @@ impl Lexer {
             None => { return None; },
         };
 
+        let start = self.offset;
+        let mut verbatim = true;
         while let Some(next_char) = self.pop_next() {
             if next_char == '\\' {
                 // escape next character
+                verbatim = false;
                 match self.pop_next() {
                     Some('`') => { buffer.push('`'); },
                     Some('/') => { buffer.push('/'); },
@@ -424,7 +1286,7 @@ impl Lexer {
                         return self.err(LexError::UnclosedIdentError);
                     },
                 }
-            } else if next_char == '\n' {
+            } else if next_char == '\n' || next_char == '\r' {
                 return self.err(LexError::NewlineInIdentifier)
             } else if next_char == '`' {
                 quote_closed = true;
@@ -435,15 +1297,41 @@ impl Lexer {
         }
 
         if quote_closed {
+            if verbatim {
+                self.verbatim = Some(start..self.offset - 1);
+            }
             Some(Ok(LexToken::Identifier(buffer)))
         } else {
             self.err(LexError::UnclosedIdentError)
         }
     }
 
+    /// Skip the rest of a comment that started at `start` with `prefix`, keeping it if
+    /// comments are being collected
+    fn skip_comment(&mut self, start: MutablePosition, prefix: &str, multiline: bool)
+        -> Option<LexError> {
+
+        if self.comments.is_some() {
+            self.recording = Some(prefix.to_string());
+        }
+        let error = if multiline {
+            self.remove_multiline_comment()
+        } else {
+            self.remove_line_comment()
+        };
+        if let (Some(text), Some(comments)) = (self.recording.take(), self.comments.as_mut()) {
+            if error.is_none() {
+                comments.push((text, Span::new(start.freeze(), self.position.clone().freeze())));
+            }
+        }
+        error
+    }
+
     fn remove_line_comment(&mut self) -> Option<LexError> {
         while let Some(ch) = self.pop_next() {
             if ch == '\r' || ch == '\n' {
+                // the line ending is whitespace, not part of the comment
+                self.ret_next(ch);
                 break;
             }
         }
@@ -484,66 +1372,172 @@ impl Lexer {
     }
 }
 
-impl Iterator for Lexer {
+/// The length of a token, as limited by `Lexer::set_max_token_length`
+fn token_length(tok: &LexToken) -> usize {
+    match *tok {
+        LexToken::Identifier(ref string) | LexToken::StringLit(ref string) => string.len(),
+        LexToken::BytesLit(ref bytes) => bytes.len(),
+        _ => 0,
+    }
+}
+
+impl<S: Source> Iterator for Lexer<S> {
     type Item = Result<LexToken, LexError>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.peeked_next.is_some() {
-            return self.peeked_next.take();
+        let next = self.peeked_next.take().or_else(|| self.next_with_span());
+        next.map(|(result, _)| result)
+    }
+}
+
+/// An iterator over tokens and their spans, created by `Lexer::spanned`
+pub struct SpannedTokens<S = ReaderSource> {
+    lexer: Lexer<S>,
+}
+
+impl<S: Source> Iterator for SpannedTokens<S> {
+    type Item = SpannedResult;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.lexer.next_spanned()
+    }
+}
+
+impl<S: Source> Lexer<S> {
+    fn next_token(&mut self) -> Option<LexResult> {
+        let result = match (self.lex_next(), self.max_token_length) {
+            (Some(Ok(tok)), Some(limit)) if token_length(&tok) > limit =>
+                self.err(LexError::TokenTooLong),
+            (result, _) => result,
+        };
+        if !self.stored_next.is_empty() {
+            return result;
+        }
+
+        // if the input couldn't be read, then whatever was lexed is probably incomplete
+        match self.input.take_error() {
+            Some(err) => {
+                self.token_start = self.position.clone();
+                self.err(err)
+            },
+            None => result,
+        }
+    }
+
+    /// Lex a whitespace or comment token, if one comes next
+    fn lex_trivia(&mut self) -> Option<LexResult> {
+        self.newline_before = false;
+        self.token_start = self.position.clone();
+        self.recording = Some(String::new());
+        let result = match self.pop_next() {
+            Some(ch) if ch.is_whitespace() => {
+                while let Some(ch) = self.pop_next() {
+                    if !ch.is_whitespace() {
+                        self.ret_next(ch);
+                        break;
+                    }
+                }
+                Some(Ok(()))
+            },
+            Some('#') => Some(self.remove_line_comment().map_or(Ok(()), Err)),
+            Some('/') => match self.pop_next() {
+                Some('/') => Some(self.remove_line_comment().map_or(Ok(()), Err)),
+                Some('*') => Some(self.remove_multiline_comment().map_or(Ok(()), Err)),
+                Some(ch) => {
+                    self.ret_next(ch);
+                    self.ret_next('/');
+                    None
+                },
+                None => {
+                    self.ret_next('/');
+                    None
+                },
+            },
+            Some(ch) => {
+                self.ret_next(ch);
+                None
+            },
+            None => None,
+        };
+
+        let text = self.recording.take().unwrap_or_default();
+        match result {
+            Some(Ok(())) if text.chars().next().is_some_and(char::is_whitespace) =>
+                Some(Ok(LexToken::Whitespace(text))),
+            Some(Ok(())) => Some(Ok(LexToken::Comment(text))),
+            Some(Err(err)) => self.err(err),
+            None => None,
+        }
+    }
+
+    fn lex_next(&mut self) -> Option<LexResult> {
+        if self.trivia {
+            if let Some(result) = self.lex_trivia() {
+                return Some(result);
+            }
         }
 
+
         // remove comments & whitespace (ignorables)
+        let start_line = self.position.line;
         // loop continuously until told to break
-        loop {
-            // take first character, test if it's either whitespace or '/'
-            if let Some(ch) = self.pop_next() {
-                if ch.is_whitespace() {
-                    continue;
-                } else if ch == '/' {
-                    match self.pop_next() {
-                        Some('/') => {
-                            // single line comment ("// hello")
-                            // remove, and continue cycle to find next ignorable
-                            if let Some(err) = self.remove_line_comment() {
-                                return self.err(err);
-                            }
-                            continue;
-                        },
-                        Some('*') => {
-                            // multiline comment ("/* hello */")
-                            // remove and continue cycle
-                            if let Some(err) = self.remove_multiline_comment() {
-                                return self.err(err);
-                            }
-                            continue;
-                        },
-                        Some(ch) => {
-                            // neither single nor multiline comment
-                            // nor is it whitespace (failed that check earlier)
-                            // return both popped characters and break out of loop
-                            self.ret_next(ch);
-                            self.ret_next('/');
-                            break;
-                        },
-                        None => {
-                            // Best place to deal with unexpected EOFs is in the main
-                            // lexer body, so break here and let it be dealt with
-                            // there.
-                            break;
+        // take first character, test if it's either whitespace or '/'
+        // (None -> Pass to main lexer body, that knows how to deal with it best)
+        while let Some(ch) = self.pop_next() {
+            if ch.is_whitespace() {
+                continue;
+            } else if ch == '#' {
+                // shell-style single line comment ("# hello")
+                let comment_start = self.last_position();
+                if let Some(err) = self.skip_comment(comment_start, "#", false) {
+                    return self.err(err);
+                }
+                continue;
+            } else if ch == '/' {
+                let comment_start = self.last_position();
+                match self.pop_next() {
+                    Some('/') => {
+                        // single line comment ("// hello")
+                        // remove, and continue cycle to find next ignorable
+                        if let Some(err) = self.skip_comment(comment_start, "//", false) {
+                            return self.err(err);
+                        }
+                        continue;
+                    },
+                    Some('*') => {
+                        // multiline comment ("/* hello */")
+                        // remove and continue cycle
+                        if let Some(err) = self.skip_comment(comment_start.clone(), "/*", true) {
+                            // point at the start of the comment, not the previous token
+                            self.token_start = comment_start;
+                            return self.err(err);
                         }
+                        continue;
+                    },
+                    Some(ch) => {
+                        // neither single nor multiline comment
+                        // nor is it whitespace (failed that check earlier)
+                        // return both popped characters and break out of loop
+                        self.ret_next(ch);
+                        self.ret_next('/');
+                        break;
+                    },
+                    None => {
+                        // Best place to deal with unexpected EOFs is in the main
+                        // lexer body, so break here and let it be dealt with
+                        // there.
+                        break;
                     }
-                } else {
-                    // not a whitespace, not a comment
-                    // return popped character and break out of loop
-                    self.ret_next(ch);
-                    break;
                 }
             } else {
-                // None -> Pass to main lexer body, that knows how to deal with it best
+                // not a whitespace, not a comment
+                // return popped character and break out of loop
+                self.ret_next(ch);
                 break;
             }
         }
 
+        self.newline_before = self.position.line != start_line;
         self.token_start = self.position.clone();
 
         if let Some(next_char) = self.pop_next() {
@@ -555,13 +1549,8 @@ impl Iterator for Lexer {
                             self.ret_next(next_char);
                             return self.parse_raw_string();
                         },
-                        _ => {
-                            self.ret_next(after);
-                            self.ret_next(next_char);
-                        }
+                        _ => self.ret_next(after),
                     }
-                } else {
-                    self.ret_next(next_char);
                 }
             }
             if next_char == '{' {
@@ -585,15 +1574,34 @@ impl Iterator for Lexer {
             if next_char == ':' {
                 return Some(Ok(LexToken::Colon));
             }
+            if next_char == ';' {
+                return Some(Ok(LexToken::Semicolon));
+            }
+            if next_char == '=' {
+                return Some(Ok(LexToken::Equals));
+            }
             if next_char == '`' {
                 self.ret_next(next_char);
                 return self.parse_ident_escaped();
             }
             if ident_head(next_char) {
                 self.ret_next(next_char);
-                return self.parse_ident();
+                let ident = self.parse_ident();
+
+                // `b` and `base64` directly followed by a quote are byte strings
+                if let Some(Ok(LexToken::Identifier(ref name))) = ident {
+                    if name == "b" || name == "base64" {
+                        if let Some(after) = self.pop_next() {
+                            self.ret_next(after);
+                            if after == '"' || after == '\'' {
+                                return self.parse_bytes(name == "base64");
+                            }
+                        }
+                    }
+                }
+                return ident;
             }
-            if next_char.is_digit(10) || ['+', '-', '.'].contains(&next_char) {
+            if next_char.is_ascii_digit() || ['+', '-', '.'].contains(&next_char) {
                 self.ret_next(next_char);
                 return self.parse_numeric();
             }
@@ -609,10 +1617,67 @@ impl Iterator for Lexer {
     }
 }
 
+/// Strip the common leading whitespace from the lines of a multi-line string.  A
+/// newline directly after the opening quotes, and a final line containing only the
+/// indentation of the closing quotes, are both removed.
+fn dedent(chars: &[(char, bool)]) -> String {
+    let is_indent = |&(ch, escaped): &(char, bool)| !escaped && (ch == ' ' || ch == '\t');
+    let is_blank = |line: &[(char, bool)]| line.iter().all(is_indent);
+
+    let mut lines = split_lines(chars);
+    if lines.len() > 1 {
+        if is_blank(lines[0].0) {
+            lines.remove(0);
+        }
+        if is_blank(lines[lines.len() - 1].0) {
+            lines.pop();
+        }
+    }
+
+    let indent = lines.iter()
+        .filter(|&&(line, _)| !is_blank(line))
+        .map(|&(line, _)| line.iter().take_while(|c| is_indent(c)).count())
+        .min()
+        .unwrap_or(0);
+
+    let mut buffer = String::new();
+    for (i, &(line, _)) in lines.iter().enumerate() {
+        // lines keep the line ending they were written with
+        if i > 0 {
+            buffer.extend(lines[i - 1].1.iter().map(|&(ch, _)| ch));
+        }
+        buffer.extend(line.iter().skip(indent).map(|&(ch, _)| ch));
+    }
+    buffer
+}
+
+/// A line of a triple-quoted string, and the line ending after it
+type Line<'a> = (&'a [(char, bool)], &'a [(char, bool)]);
+
+/// Split characters into lines, each paired with the (unescaped) line ending after it.
+/// The line ending of the last line is always empty.
+fn split_lines(chars: &[(char, bool)]) -> Vec<Line<'_>> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
+    while i < chars.len() {
+        let end = match chars[i] {
+            ('\r', false) if chars.get(i + 1) == Some(&('\n', false)) => i + 2,
+            ('\r', false) | ('\n', false) => i + 1,
+            _ => { i += 1; continue; },
+        };
+        lines.push((&chars[start..i], &chars[i..end]));
+        start = end;
+        i = end;
+    }
+    lines.push((&chars[start..], &chars[chars.len()..]));
+    lines
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use super::super::position::MutablePosition;
+    use super::super::position::{MutablePosition, Position};
     use std::io::Cursor;
 
     #[test]
@@ -667,6 +1732,15 @@ mod tests {
         assert_eq!(lexer.token_start, MutablePosition::at(0, 38));
         assert_eq!(lexer.position, MutablePosition::at(0, 51));
         assert!(lexer.next().is_none());
+
+        let mut lexer = Lexer::lex(Cursor::new("key = 1".as_bytes()));
+        assert_eq!(lexer.next().unwrap().unwrap(),
+            LexToken::Identifier("key".to_string()));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::Equals);
+        assert_eq!(lexer.token_start, MutablePosition::at(0, 4));
+        assert_eq!(lexer.position, MutablePosition::at(0, 5));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::IntegerLit(1));
+        assert!(lexer.next().is_none());
     }
 
     #[test]
@@ -685,6 +1759,19 @@ mod tests {
         assert_eq!(lexer.next().unwrap().unwrap(), LexToken::IntegerLit(1));
         assert!(lexer.next().is_none());
 
+        let mut lexer = Lexer::lex(Cursor::new("
+            1 # hello
+            # world
+            2 #".as_bytes()));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::IntegerLit(1));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::IntegerLit(2));
+        assert!(lexer.next().is_none());
+
+        // raw strings can still use '#' as a quote character
+        let mut lexer = Lexer::lex(Cursor::new("r##hello## # comment".as_bytes()));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::StringLit("hello".to_string()));
+        assert!(lexer.next().is_none());
+
         let mut lexer = Lexer::lex(Cursor::new("
             1 /*
             hello
@@ -706,17 +1793,70 @@ mod tests {
         let mut lexer = Lexer::lex(Cursor::new("1 /*".as_bytes()));
         assert_eq!(lexer.next().unwrap().unwrap(), LexToken::IntegerLit(1));
         assert_eq!(lexer.next().unwrap().unwrap_err(), LexError::UnclosedCommentError);
+        assert_eq!(lexer.token_start, MutablePosition::at(0, 2));
+        assert!(lexer.next().is_none());
+
+        let mut lexer = Lexer::lex(Cursor::new("1\n  /* /* */\n\n".as_bytes()));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::IntegerLit(1));
+        assert_eq!(lexer.next().unwrap().unwrap_err(), LexError::UnclosedCommentError);
+        assert_eq!(lexer.token_start, MutablePosition::at(1, 2));
+    }
+
+    #[test]
+    fn positions_after_comments() {
+        let mut lexer = Lexer::lex(Cursor::new("/* a\n /* nested\n ** */ b */ x".as_bytes()));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::Identifier("x".to_string()));
+        assert_eq!(lexer.token_start, MutablePosition::at(2, 12));
+        assert_eq!(lexer.position, MutablePosition::at(2, 13));
+
+        let mut lexer = Lexer::lex(Cursor::new("/* * / */ x /**/ y /* *\n/ */ z".as_bytes()));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::Identifier("x".to_string()));
+        assert_eq!(lexer.token_start, MutablePosition::at(0, 10));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::Identifier("y".to_string()));
+        assert_eq!(lexer.token_start, MutablePosition::at(0, 17));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::Identifier("z".to_string()));
+        assert_eq!(lexer.token_start, MutablePosition::at(1, 5));
         assert!(lexer.next().is_none());
     }
 
+    #[test]
+    fn input_errors() {
+        let mut lexer = Lexer::lex(Cursor::new(&b"\xEF\xBB\xBFnode {}"[..]));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::Identifier("node".to_string()));
+        assert_eq!(lexer.token_start, MutablePosition::at(0, 0));
+
+        let mut lexer = Lexer::lex(Cursor::new(&b"\xFF\xFEn\x00"[..]));
+        assert_eq!(lexer.next().unwrap().unwrap_err(), LexError::UnsupportedEncoding("UTF-16LE"));
+        assert_eq!(lexer.token_start, MutablePosition::at(0, 0));
+
+        let mut lexer = Lexer::lex(Cursor::new(&b"node {\n  1 \xFF }"[..]));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::Identifier("node".to_string()));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::OpenBrace);
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::IntegerLit(1));
+        assert_eq!(lexer.next().unwrap().unwrap_err(), LexError::InvalidUtf8);
+        assert_eq!(lexer.token_start, MutablePosition::at(1, 4));
+        assert!(lexer.next().is_none());
+    }
+
+    #[test]
+    fn token_length_limit() {
+        let mut lexer = Lexer::lex(Cursor::new("abc 'abcd' b'ab' 12345678".as_bytes()));
+        lexer.set_max_token_length(Some(3));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::Identifier("abc".to_string()));
+        assert_eq!(lexer.next().unwrap().unwrap_err(), LexError::TokenTooLong);
+        assert_eq!(lexer.token_start, MutablePosition::at(0, 4));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::BytesLit(b"ab".to_vec()));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::IntegerLit(12345678));
+    }
+
     #[test]
     fn parse_unrecognised_char() {
         let mut lexer = Lexer::lex(Cursor::new("&".as_bytes()));
         match lexer.next() {
             None => panic!("Should return some"),
-            Some(Ok(tok)) => panic!(format!("Should return err, returned Ok({:?})", tok)),
+            Some(Ok(tok)) => panic!("Should return err, returned Ok({:?})", tok),
             Some(Err(LexError::UnrecognisedCharError(c))) => assert_eq!(c, '&'),
-            Some(Err(err)) => panic!(format!("Should return char error, returned {:?}", err)),
+            Some(Err(err)) => panic!("Should return char error, returned {:?}", err),
         }
     }
 
@@ -741,9 +1881,17 @@ mod tests {
         assert_eq!(lexer.parse_ident().unwrap().unwrap(),
             LexToken::Identifier("th15_1s_an_1d3n7".to_string()));
 
-        let mut lexer = Lexer::lex(Cursor::new("🐶".as_bytes())); // heart
+        let mut lexer = Lexer::lex(Cursor::new("größe".as_bytes()));
         assert_eq!(lexer.parse_ident().unwrap().unwrap(),
-            LexToken::Identifier("🐶".to_string()));
+            LexToken::Identifier("größe".to_string()));
+
+        // emoji are not XID characters
+        #[cfg(not(feature = "unicode-xid"))]
+        {
+            let mut lexer = Lexer::lex(Cursor::new("🐶".as_bytes())); // heart
+            assert_eq!(lexer.parse_ident().unwrap().unwrap(),
+                LexToken::Identifier("🐶".to_string()));
+        }
 
         let mut lexer = Lexer::lex(Cursor::new("`ident`".as_bytes()));
         assert_eq!(lexer.parse_ident_escaped().unwrap().unwrap(),
@@ -768,7 +1916,7 @@ mod tests {
         let mut lexer = Lexer::lex(Cursor::new("`string".as_bytes()));
         match lexer.parse_ident_escaped().unwrap() {
             Ok(_) => panic!("should raise error"),
-            Err(LexError::UnclosedIdentError) => assert!(true),
+            Err(LexError::UnclosedIdentError) => {},
             Err(_) => panic!("wrong error raised"),
         }
     }
@@ -800,13 +1948,61 @@ mod tests {
             LexToken::IntegerLit(8));
 
         let mut lexer = Lexer::lex(Cursor::new("0d10e5".as_bytes()));
-        assert_eq!(lexer.parse_numeric().unwrap().unwrap(),
-            LexToken::IntegerLit(10));
+        assert_eq!(lexer.parse_numeric().unwrap().unwrap_err(),
+            LexError::InvalidDigit('e'));
 
         let mut lexer = Lexer::lex(Cursor::new("10e5".as_bytes()));
         assert_eq!(lexer.parse_numeric().unwrap().unwrap(),
             LexToken::FloatLit(10e5));
 
+        let mut lexer = Lexer::lex(Cursor::new("1.5e-3 2E+2 -inf +inf inf".as_bytes()));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::FloatLit(1.5e-3));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::FloatLit(2e2));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::FloatLit(f64::NEG_INFINITY));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::FloatLit(f64::INFINITY));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::Identifier("inf".to_string()));
+        assert!(lexer.next().is_none());
+
+        let mut lexer = Lexer::lex(Cursor::new("-nan +nan nan".as_bytes()));
+        assert!(matches!(lexer.next(), Some(Ok(LexToken::FloatLit(flt))) if flt.is_nan()));
+        assert!(matches!(lexer.next(), Some(Ok(LexToken::FloatLit(flt))) if flt.is_nan()));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::Identifier("nan".to_string()));
+        assert!(lexer.next().is_none());
+
+        let mut lexer = Lexer::lex(Cursor::new("2024-01-01T12:00:00Z, 1999-12-31t23:59:59.5 2024".as_bytes()));
+        assert_eq!(lexer.next().unwrap().unwrap(),
+            LexToken::DateTimeLit("2024-01-01T12:00:00Z".parse().unwrap()));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::Comma);
+        assert_eq!(lexer.next().unwrap().unwrap(),
+            LexToken::DateTimeLit("1999-12-31T23:59:59.5".parse().unwrap()));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::IntegerLit(2024));
+        assert!(lexer.next().is_none());
+
+        let mut lexer = Lexer::lex(Cursor::new("\n  2024-02-30T00:00:00Z".as_bytes()));
+        assert_eq!(lexer.next().unwrap().unwrap_err(), LexError::InvalidDateTime);
+        assert_eq!(lexer.token_start, MutablePosition::at(1, 2));
+
+        let mut lexer = Lexer::lex(Cursor::new("30s 5m 1h30m 1d2h3m4s5ms6us7ns 1_000ms".as_bytes()));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::DurationLit(Duration::from_secs(30)));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::DurationLit(Duration::from_secs(300)));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::DurationLit(Duration::from_secs(5400)));
+        assert_eq!(lexer.next().unwrap().unwrap(),
+            LexToken::DurationLit(Duration::new(93784, 5_006_007)));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::DurationLit(Duration::from_secs(1)));
+        assert!(lexer.next().is_none());
+
+        for input in &["30min", "30m1h", "1s1s", "99999999999999999999d", "213503982334602d",
+            "1.5s", "1e3s", "1E-3s", "1w", "-5s", "+5s", "1h1.5m"] {
+
+            let mut lexer = Lexer::lex(Cursor::new(input.as_bytes())).spanned();
+            let end = Position::at(0, input.len());
+            assert_eq!(lexer.next().unwrap(),
+                Err((LexError::InvalidDuration, Span::new(Position::at(0, 0), end))), "{:?}", input);
+        }
+
+        let mut lexer = Lexer::lex(Cursor::new("-info".as_bytes()));
+        assert!(matches!(lexer.next().unwrap(), Err(LexError::IntegerParseError(_))));
+
         let mut lexer = Lexer::lex(Cursor::new("10.5".as_bytes()));
         assert_eq!(lexer.parse_numeric().unwrap().unwrap(),
             LexToken::FloatLit(10.5));
@@ -816,12 +2012,50 @@ mod tests {
             LexToken::IntegerLit(105));
 
         let mut lexer = Lexer::lex(Cursor::new("1_0__5___".as_bytes()));
-        assert_eq!(lexer.parse_numeric().unwrap().unwrap(),
-            LexToken::IntegerLit(105));
+        assert_eq!(lexer.parse_numeric().unwrap().unwrap_err(),
+            LexError::InvalidDigitSeparator);
+        assert_eq!(lexer.token_start, MutablePosition::at(0, 4));
+
+        let mut lexer = Lexer::lex(Cursor::new("1_000_000 0xDEAD_BEEF 0b1_0 -1_0.2_5e10 1_0e-5".as_bytes()));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::IntegerLit(1000000));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::IntegerLit(0xDEADBEEF));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::IntegerLit(2));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::FloatLit(-10.25e10));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::FloatLit(10e-5));
+        assert!(lexer.next().is_none());
+
+        for &(input, pos) in &[("0x_FF", 2), ("1__0", 2), ("1_.5", 1), ("1._5", 2), ("1_", 1), ("0b1_ ", 3)] {
+            let mut lexer = Lexer::lex(Cursor::new(input.as_bytes()));
+            assert_eq!(lexer.next().unwrap().unwrap_err(), LexError::InvalidDigitSeparator);
+            assert_eq!(lexer.token_start, MutablePosition::at(0, pos));
+        }
 
         let mut lexer = Lexer::lex(Cursor::new("0x".as_bytes()));
-        assert_eq!(lexer.parse_numeric().unwrap().unwrap(),
-            LexToken::IntegerLit(0));
+        assert_eq!(lexer.parse_numeric().unwrap().unwrap_err(),
+            LexError::MissingDigits);
+
+        let mut lexer = Lexer::lex(Cursor::new("0xFF 0o755 0b1010 -0x10 +0b1 0x7fffffffffffffff".as_bytes()));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::IntegerLit(255));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::IntegerLit(0o755));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::IntegerLit(10));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::IntegerLit(-16));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::IntegerLit(1));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::IntegerLit(i64::MAX));
+        assert!(lexer.next().is_none());
+
+        let mut lexer = Lexer::lex(Cursor::new("[0b1012]".as_bytes()));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::OpenBracket);
+        assert_eq!(lexer.next().unwrap().unwrap_err(), LexError::InvalidDigit('2'));
+        assert_eq!(lexer.token_start, MutablePosition::at(0, 6));
+
+        let mut lexer = Lexer::lex(Cursor::new("0o78".as_bytes()));
+        assert_eq!(lexer.parse_numeric().unwrap().unwrap_err(), LexError::InvalidDigit('8'));
+
+        let mut lexer = Lexer::lex(Cursor::new("0xFFG".as_bytes()));
+        assert_eq!(lexer.parse_numeric().unwrap().unwrap_err(), LexError::InvalidDigit('G'));
+
+        let mut lexer = Lexer::lex(Cursor::new("0x1_0000_0000_0000_0000".as_bytes()));
+        assert!(matches!(lexer.parse_numeric().unwrap(), Err(LexError::IntegerParseError(_))));
     }
 
     #[test]
@@ -846,6 +2080,25 @@ mod tests {
         assert_eq!(lexer.parse_string().unwrap().unwrap(),
             LexToken::StringLit("stAring".to_string()));
 
+        let mut lexer = Lexer::lex(Cursor::new("'\\u{1F600} \\u{e9}\\u{0}\\u{10FFFF}'".as_bytes()));
+        assert_eq!(lexer.parse_string().unwrap().unwrap(),
+            LexToken::StringLit("\u{1F600} \u{e9}\u{0}\u{10FFFF}".to_string()));
+
+        for &(input, ref err) in &[
+            ("'ab\\u{110000}'", LexError::InvalidUnicodeEscape(0x110000)),
+            ("'ab\\u{D800}'", LexError::InvalidUnicodeEscape(0xD800)),
+            ("'ab\\uD800'", LexError::InvalidUnicodeEscape(0xD800)),
+            ("'ab\\u{}'", LexError::MalformedUnicodeEscape),
+            ("'ab\\u{1234567}'", LexError::MalformedUnicodeEscape),
+            ("'ab\\u{12G}'", LexError::MalformedUnicodeEscape),
+            ("'ab\\u{12'", LexError::MalformedUnicodeEscape),
+            ("'ab\\q'", LexError::InvalidEscape('q')),
+        ] {
+            let mut lexer = Lexer::lex(Cursor::new(input.as_bytes()));
+            assert_eq!(&lexer.next().unwrap().unwrap_err(), err, "{:?}", input);
+            assert_eq!(lexer.token_start, MutablePosition::at(0, 3), "{:?}", input);
+        }
+
         let mut lexer = Lexer::lex(Cursor::new("'stri\\\\ng'".as_bytes()));
         assert_eq!(lexer.parse_string().unwrap().unwrap(),
             LexToken::StringLit("stri\\ng".to_string()));
@@ -854,14 +2107,77 @@ mod tests {
         assert_eq!(lexer.parse_string().unwrap().unwrap(),
             LexToken::StringLit("str'ing".to_string()));
 
+        let mut lexer = Lexer::lex(Cursor::new("'' \"\"".as_bytes()));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::StringLit("".to_string()));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::StringLit("".to_string()));
+        assert!(lexer.next().is_none());
+
         let mut lexer = Lexer::lex(Cursor::new("'string".as_bytes()));
         match lexer.parse_string().unwrap() {
             Ok(_) => panic!("should raise error"),
-            Err(LexError::UnclosedStringError) => assert!(true),
+            Err(LexError::UnclosedStringError) => {},
             Err(_) => panic!("wrong error raised"),
         }
     }
 
+    #[test]
+    fn parse_multiline_string() {
+        let mut lexer = Lexer::lex(Cursor::new("\"\"\"
+            first line
+              indented line
+
+            last line
+            \"\"\"".as_bytes()));
+        assert_eq!(lexer.next().unwrap().unwrap(),
+            LexToken::StringLit("first line\n  indented line\n\nlast line".to_string()));
+        assert!(lexer.next().is_none());
+
+        // escapes are processed, but escaped whitespace is not treated as indentation
+        let mut lexer = Lexer::lex(Cursor::new("'''
+              \\t tab
+            quote: '' \\''' '''".as_bytes()));
+        assert_eq!(lexer.next().unwrap().unwrap(),
+            LexToken::StringLit("  \t tab\nquote: '' ''' ".to_string()));
+        assert!(lexer.next().is_none());
+
+        let mut lexer = Lexer::lex(Cursor::new("'''one line''' '''\n  unclosed ''".as_bytes()));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::StringLit("one line".to_string()));
+        assert_eq!(lexer.next().unwrap().unwrap_err(), LexError::UnclosedStringError);
+        assert!(lexer.next().is_none());
+    }
+
+    #[test]
+    fn parse_bytes() {
+        let mut lexer = Lexer::lex(Cursor::new("b'abc' b\"\\x00\\xFF\\n\\\"\" base64\"aGVs\n  bG8=\" base64''".as_bytes()));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::BytesLit(b"abc".to_vec()));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::BytesLit(vec![0, 255, b'\n', b'"']));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::BytesLit(b"hello".to_vec()));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::BytesLit(vec![]));
+        assert!(lexer.next().is_none());
+
+        // `b` and `base64` are still identifiers when not followed by a quote
+        let mut lexer = Lexer::lex(Cursor::new("b base64 'str'".as_bytes()));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::Identifier("b".to_string()));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::Identifier("base64".to_string()));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::StringLit("str".to_string()));
+
+        let mut lexer = Lexer::lex(Cursor::new("b'caf\u{e9}'".as_bytes()));
+        assert_eq!(lexer.next().unwrap().unwrap_err(), LexError::InvalidByte('\u{e9}'));
+        assert_eq!(lexer.token_start, MutablePosition::at(0, 5));
+
+        let mut lexer = Lexer::lex(Cursor::new("b'ab\\xZZ'".as_bytes()));
+        assert_eq!(lexer.next().unwrap().unwrap_err(), LexError::InvalidEscape('Z'));
+        assert_eq!(lexer.token_start, MutablePosition::at(0, 4));
+
+        let mut lexer = Lexer::lex(Cursor::new("base64'aGV*bG8='".as_bytes()));
+        assert_eq!(lexer.next().unwrap().unwrap_err(), LexError::InvalidBase64('*'));
+        assert_eq!(lexer.token_start, MutablePosition::at(0, 10));
+
+        let mut lexer = Lexer::lex(Cursor::new("base64'aGVsb'".as_bytes()));
+        assert_eq!(lexer.next().unwrap().unwrap_err(), LexError::InvalidBase64('\''));
+        assert_eq!(lexer.token_start, MutablePosition::at(0, 12));
+    }
+
     #[test]
     fn parse_raw_string() {
         let mut lexer = Lexer::lex(Cursor::new("r/hello/".as_bytes()));
@@ -875,5 +2191,188 @@ mod tests {
         let mut lexer = Lexer::lex(Cursor::new("r////hel///lo////".as_bytes()));
         assert_eq!(lexer.parse_raw_string().unwrap().unwrap(),
             LexToken::StringLit("hel///lo".to_string()));
+
+        let mut lexer = Lexer::lex(Cursor::new(r"r/C:\Users\/ r'\d+'".as_bytes()));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::StringLit(r"C:\Users\".to_string()));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::StringLit(r"\d+".to_string()));
+        assert!(lexer.next().is_none());
+
+        let mut lexer = Lexer::lex(Cursor::new(r###"r#"say "hi""# r##"a "# b"## r#""#"###.as_bytes()));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::StringLit(r#"say "hi""#.to_string()));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::StringLit(r##"a "# b"##.to_string()));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::StringLit("".to_string()));
+        assert!(lexer.next().is_none());
+
+        let mut lexer = Lexer::lex(Cursor::new("1\n  r#\"unclosed\" ".as_bytes()));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::IntegerLit(1));
+        assert_eq!(lexer.next().unwrap().unwrap_err(), LexError::UnclosedStringError);
+        assert_eq!(lexer.token_start, MutablePosition::at(1, 2));
+        assert!(lexer.next().is_none());
+
+        // identifiers can start with `r` too
+        let tokens: Vec<_> = Lexer::lex(Cursor::new("remove { r = rx }".as_bytes()))
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(tokens, vec![
+            LexToken::Identifier("remove".to_string()), LexToken::OpenBrace,
+            LexToken::Identifier("r".to_string()), LexToken::Equals,
+            LexToken::Identifier("rx".to_string()), LexToken::CloseBrace,
+        ]);
+    }
+
+    #[test]
+    fn token_spans() {
+        let lexer = Lexer::lex(Cursor::new("node {\n  \"key\": [1.5,\n'a\nb'] } &".as_bytes()));
+        let spans: Vec<_> = lexer.spanned().map(|result| match result {
+            Ok((_, span)) => span,
+            Err((_, span)) => span,
+        }).collect();
+        let span = |start: (usize, usize), end: (usize, usize)|
+            Span::new(Position::at(start.0, start.1), Position::at(end.0, end.1));
+        assert_eq!(spans, vec![
+            span((0, 0), (0, 4)), span((0, 5), (0, 6)),
+            span((1, 2), (1, 7)), span((1, 7), (1, 8)), span((1, 9), (1, 10)),
+            span((1, 10), (1, 13)), span((1, 13), (1, 14)),
+            span((2, 0), (3, 2)), span((3, 2), (3, 3)), span((3, 4), (3, 5)),
+            span((3, 6), (3, 7)),
+        ]);
+
+        // peeking doesn't lose the span
+        let mut lexer = Lexer::lex(Cursor::new("a  bc".as_bytes()));
+        lexer.next();
+        lexer.peek();
+        assert_eq!(lexer.next_spanned().unwrap().unwrap().1, span((0, 3), (0, 5)));
+    }
+
+    #[test]
+    fn line_endings() {
+        // `\r\n` and lone `\r` are both a single line ending
+        let mut lexer = Lexer::lex(Cursor::new("a\r\nb\rc\n\r\nd // comment\r\ne".as_bytes()));
+        for (ident, line) in &[("a", 0), ("b", 1), ("c", 2), ("d", 4), ("e", 5)] {
+            assert_eq!(lexer.next().unwrap().unwrap(), LexToken::Identifier(ident.to_string()));
+            assert_eq!(lexer.token_start, MutablePosition::at(*line, 0));
+        }
+
+        let input = "'a\r\nb\rc\\r' r'd\r\ne' '''\r\n    f\r\n      g\r\n    '''";
+        let mut lexer = Lexer::lex(Cursor::new(input.as_bytes()));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::StringLit("a\r\nb\rc\r".to_string()));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::StringLit("d\r\ne".to_string()));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::StringLit("f\r\n  g".to_string()));
+
+        // escaped carriage returns are not normalized
+        let mut lexer = Lexer::lex(Cursor::new(input.as_bytes()));
+        lexer.set_normalize_newlines(true);
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::StringLit("a\nb\nc\r".to_string()));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::StringLit("d\ne".to_string()));
+        assert_eq!(lexer.next().unwrap().unwrap(), LexToken::StringLit("f\n  g".to_string()));
+        assert_eq!(lexer.position, MutablePosition::at(6, 7));
+    }
+
+    /// Counts the number of times it has been read from
+    struct CountingReader {
+        input: Cursor<Vec<u8>>,
+        reads: ::std::rc::Rc<::std::cell::Cell<usize>>,
+    }
+
+    impl Read for CountingReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.reads.set(self.reads.get() + 1);
+            self.input.read(buf)
+        }
+    }
+
+    #[test]
+    fn buffered_input() {
+        let input = "node { key = 'value' }\n".repeat(40).into_bytes();
+        let lex = |capacity| {
+            let reads = ::std::rc::Rc::new(::std::cell::Cell::new(0));
+            let reader = CountingReader { input: Cursor::new(input.clone()), reads: reads.clone() };
+            assert_eq!(Lexer::with_capacity(capacity, reader).count(), 40 * 6);
+            reads.get()
+        };
+
+        // 920 bytes of input are read in a few large chunks, rather than byte by byte
+        assert!(lex(8 * 1024) <= 3);
+        let reads = lex(100);
+        assert!((10..=12).contains(&reads), "{} reads", reads);
+    }
+
+    #[test]
+    fn lexes_strings_in_place() {
+        let input = "\u{FEFF}ключ { a = 'é\\t', r#\"raw\"# = `b c`, d: [1.5, 30s] } # end\n";
+        let from_reader: Vec<_> = Lexer::lex(Cursor::new(input)).spanned().collect();
+        let from_str: Vec<_> = Lexer::from_str(input).spanned().collect();
+        assert_eq!(from_str, from_reader);
+
+        // the texts of identifiers and strings are found without the byte order mark, and
+        // only when they are written out as they are
+        let mut lexer = Lexer::from_str(input);
+        lexer.set_collect_texts(true);
+        lexer.by_ref().count();
+        let all = Span::new(Position::new(), Position::at(1, 0));
+        let texts: Vec<_> = ::std::iter::from_fn(|| lexer.next_text(&all))
+            .map(|(_, range)| &input[3 + range.start..3 + range.end])
+            .collect();
+        assert_eq!(texts, vec!["ключ", "a", "raw", "b c", "d"]);
+
+        let mut lexer = Lexer::from_str("node { a = 1 }");
+        lexer.set_max_document_size(Some(10));
+        assert_eq!(lexer.last(), Some(Err(LexError::DocumentTooLarge)));
+    }
+
+    #[test]
+    fn trivia_tokens() {
+        let input = "# top\r\nnode /* a /* nested */ comment */{\n\tkey = 'x' // end\n}\n";
+        let mut lexer = Lexer::lex(Cursor::new(input.as_bytes()));
+        lexer.set_trivia(true);
+        let tokens: Vec<_> = lexer.map(|token| token.unwrap()).collect();
+        assert_eq!(tokens, vec![
+            LexToken::Comment("# top".to_string()),
+            LexToken::Whitespace("\r\n".to_string()),
+            LexToken::Identifier("node".to_string()),
+            LexToken::Whitespace(" ".to_string()),
+            LexToken::Comment("/* a /* nested */ comment */".to_string()),
+            LexToken::OpenBrace,
+            LexToken::Whitespace("\n\t".to_string()),
+            LexToken::Identifier("key".to_string()),
+            LexToken::Whitespace(" ".to_string()),
+            LexToken::Equals,
+            LexToken::Whitespace(" ".to_string()),
+            LexToken::StringLit("x".to_string()),
+            LexToken::Whitespace(" ".to_string()),
+            LexToken::Comment("// end".to_string()),
+            LexToken::Whitespace("\n".to_string()),
+            LexToken::CloseBrace,
+            LexToken::Whitespace("\n".to_string()),
+        ]);
+
+        // the spans cover the whole input, with no gaps
+        let mut lexer = Lexer::lex(Cursor::new("a 'b' 1/* c".as_bytes()));
+        lexer.set_trivia(true);
+        let results: Vec<_> = lexer.spanned().collect();
+        let span = |start: usize, end: usize| Span::new(Position::at(0, start), Position::at(0, end));
+        assert_eq!(results, vec![
+            Ok((LexToken::Identifier("a".to_string()), span(0, 1))),
+            Ok((LexToken::Whitespace(" ".to_string()), span(1, 2))),
+            Ok((LexToken::StringLit("b".to_string()), span(2, 5))),
+            Ok((LexToken::Whitespace(" ".to_string()), span(5, 6))),
+            Ok((LexToken::IntegerLit(1), span(6, 7))),
+            Err((LexError::UnclosedCommentError, span(7, 11))),
+        ]);
+    }
+
+    #[test]
+    fn collects_comments() {
+        let mut lexer = Lexer::lex(Cursor::new("a # one\r\n/* two\n */ b // three"));
+        assert_eq!(lexer.next(), Some(Ok(LexToken::Identifier("a".to_string()))));
+        lexer.set_collect_comments(true);
+        let tokens: Vec<_> = lexer.by_ref().collect();
+        assert_eq!(tokens, vec![Ok(LexToken::Identifier("b".to_string()))]);
+        assert_eq!(lexer.take_comments(), vec![
+            ("# one".to_string(), Span::new(Position::at(0, 2), Position::at(0, 7))),
+            ("/* two\n */".to_string(), Span::new(Position::at(1, 0), Position::at(2, 3))),
+            ("// three".to_string(), Span::new(Position::at(2, 6), Position::at(2, 14))),
+        ]);
+        assert!(lexer.take_comments().is_empty());
     }
 }
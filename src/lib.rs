@@ -33,21 +33,162 @@
 
 #[macro_use]
 extern crate matches;
+#[cfg(feature = "unicode-xid")]
+extern crate unicode_xid;
+#[cfg(feature = "encoding")]
+pub extern crate encoding_rs;
+#[cfg(feature = "arena")]
+pub extern crate bumpalo;
+#[cfg(feature = "async")]
+pub extern crate tokio;
+#[cfg(feature = "serde")]
+#[macro_use]
+pub extern crate serde;
+#[cfg(feature = "serde_json")]
+pub extern crate serde_json;
+#[cfg(feature = "derive")]
+extern crate figtree_derive;
 
 mod utils;
+#[cfg(feature = "encoding")]
+mod decoding;
 
 mod position;
-pub use position::Position;
+pub use position::{Position, Span, Origin};
+
+mod datetime;
+pub use datetime::{DateTime, DateTimeError};
 
 mod lexer;
+pub use lexer::{Lexer, SpannedTokens, SpannedResult, Source, ReaderSource, StrSource};
 pub use lexer::LexToken;
 pub use lexer::LexError;
 
 mod parser;
-pub use parser::ParseError;
+pub use parser::{Parser, ParseEvent, ParsedValue, ParseResult, CollectedResults, WithDepth};
+pub use parser::{ParseError, Expected, ParseWarning, ParserOptions, SeparatorPolicy, NonFinitePolicy};
+pub use parser::DuplicateKeyPolicy;
+
+mod map;
+
+mod intern;
+pub use intern::{Symbol, Interner};
 
 pub mod types;
 pub use types::*;
 
+mod builder;
+pub use builder::{DocumentBuilder, NodeBuilder};
+
+mod merge;
+pub use merge::{MergeStrategy, MergeConflict, MergeError};
+
+mod path;
+
+mod convert;
+pub use convert::{ConvertError, ToValue};
+
+mod node_convert;
+pub use node_convert::{FromNode, ToNode, FromNodeError, NodeItem};
+#[cfg(feature = "derive")]
+pub use figtree_derive::{FromNode, ToNode};
+
+mod diff;
+pub use diff::{diff, Change, ChangeKind};
+
+mod env;
+pub use env::{EnvOptions, EnvLists};
+
+mod patch;
+pub use patch::{Patch, PatchOp, PatchEntry, PatchError};
+
+mod edit;
+pub use edit::EditableDocument;
+
+mod hooks;
+pub use hooks::{Mutation, HookError};
+
+pub mod visit;
+pub use visit::{Visit, VisitMut};
+
+mod traverse;
+pub use traverse::{Descendant, Descendants, BreadthFirst, Find, FilterValues};
+
+mod display;
+pub use display::{FormatOptions, BraceStyle, KeySeparator, Terminator, EscapePolicy};
+pub use display::dump_events;
+
+mod writer;
+pub use writer::{Writer, WriteError, FlushPolicy};
+
+#[cfg(feature = "async")]
+mod async_writer;
+#[cfg(feature = "async")]
+pub use async_writer::{AsyncWriter, WriteEvent};
+
+#[cfg(feature = "serde")]
+mod de;
+#[cfg(feature = "serde")]
+pub use de::{from_str, from_reader, Deserializer, DeserializeError, ARGUMENTS_KEY};
+#[cfg(feature = "serde")]
+mod ser;
+#[cfg(feature = "serde")]
+pub use ser::{to_string, to_writer, Serializer, SerializeError};
+#[cfg(feature = "serde")]
+mod spanned;
+#[cfg(feature = "serde")]
+pub use spanned::Spanned;
+#[cfg(feature = "serde")]
+mod serde_impls;
+#[cfg(feature = "serde_json")]
+mod json;
+#[cfg(feature = "serde_json")]
+pub use json::{JsonOptions, JsonArrays, JsonError};
+#[cfg(feature = "serde_json")]
+mod json_stream;
+#[cfg(feature = "serde_json")]
+pub use json_stream::{JsonReader, JsonWriter};
+
+#[cfg(feature = "yaml")]
+mod yaml;
+#[cfg(feature = "yaml")]
+pub use yaml::YamlError;
+#[cfg(feature = "toml")]
+mod toml;
+#[cfg(feature = "toml")]
+pub use toml::TomlError;
+#[cfg(feature = "xml")]
+mod xml;
+#[cfg(feature = "xml")]
+pub use xml::{XmlError, XmlReader, XmlWriter};
+#[cfg(feature = "cbor")]
+mod cbor;
+#[cfg(feature = "cbor")]
+pub use cbor::{CborError, CborReader, CborWriter};
+#[cfg(feature = "dot")]
+mod dot;
+#[cfg(feature = "dot")]
+pub use dot::DotOptions;
+#[cfg(feature = "ini")]
+mod ini;
+#[cfg(feature = "ini")]
+pub use ini::{IniError, IniOptions};
+#[cfg(feature = "diagnostics")]
+mod diagnostic;
+#[cfg(feature = "diagnostics")]
+pub use diagnostic::Diagnostic;
+
+mod shared;
+pub use shared::SharedDocument;
+
+#[cfg(feature = "arena")]
+pub mod arena;
+
+mod borrowed;
+pub use borrowed::{StrParser, BorrowedEvent, BorrowedResult};
+
+mod push;
+pub use push::PushParser;
+
 mod figtree;
 pub use figtree::Figtree;
@@ -0,0 +1,141 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::slice;
+
+use super::intern::Symbol;
+
+/// A map from symbols to values that remembers the order its entries were inserted in.
+///
+/// The entries are stored in a `Vec`, in order, with a hash table from each key to its
+/// position, so lookups don't need to scan through the entries.  Removing an entry has to
+/// shift the entries after it along, so is linear in the size of the map.
+#[derive(Clone, Default)]
+pub struct OrderedMap<V> {
+    entries: Vec<(Symbol, V)>,
+    index: HashMap<Symbol, usize>,
+}
+
+impl<V> OrderedMap<V> {
+    pub fn new() -> Self {
+        OrderedMap { entries: Vec::new(), index: HashMap::new() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&V> {
+        self.index.get(key).map(|&index| &self.entries[index].1)
+    }
+
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut V> {
+        match self.index.get(key) {
+            Some(&index) => Some(&mut self.entries[index].1),
+            None => None,
+        }
+    }
+
+    /// Insert a value, returning the value that it replaced.  A replacement keeps the
+    /// old value's place in the order.
+    pub fn insert(&mut self, key: Symbol, value: V) -> Option<V> {
+        if let Some(old) = self.get_mut(&key) {
+            return Some(::std::mem::replace(old, value));
+        }
+        self.index.insert(key.clone(), self.entries.len());
+        self.entries.push((key, value));
+        None
+    }
+
+    /// Get the symbol that a key is stored as
+    pub fn key(&self, key: &str) -> Option<&Symbol> {
+        self.index.get_key_value(key).map(|(key, _)| key)
+    }
+
+    /// Remove a value, keeping the remaining entries in order
+    pub fn remove(&mut self, key: &str) -> Option<V> {
+        let index = self.index.remove(key)?;
+        let (_, value) = self.entries.remove(index);
+        for entry in &self.entries[index..] {
+            if let Some(position) = self.index.get_mut(&entry.0) {
+                *position -= 1;
+            }
+        }
+        Some(value)
+    }
+
+    /// Iterate over the (key, value) entries in order
+    pub fn iter(&self) -> slice::Iter<'_, (Symbol, V)> {
+        self.entries.iter()
+    }
+
+    /// Sort the entries, keeping entries that compare equal in the same order
+    pub fn sort_by<F>(&mut self, compare: F)
+        where F: FnMut(&(Symbol, V), &(Symbol, V)) -> Ordering {
+
+        self.entries.sort_by(compare);
+        for (position, entry) in self.entries.iter().enumerate() {
+            if let Some(index) = self.index.get_mut(&entry.0) {
+                *index = position;
+            }
+        }
+    }
+
+    /// Replace each key with the symbol that `replace` gives for it, which must be for the
+    /// same string
+    pub fn replace_keys<F>(&mut self, mut replace: F) where F: FnMut(&Symbol) -> Symbol {
+        self.index.clear();
+        for (position, entry) in self.entries.iter_mut().enumerate() {
+            entry.0 = replace(&entry.0);
+            self.index.insert(entry.0.clone(), position);
+        }
+    }
+
+    /// Iterate mutably over the entries in order.  Changing a key would break the index,
+    /// so callers must only change the values.
+    pub fn iter_mut(&mut self) -> slice::IterMut<'_, (Symbol, V)> {
+        self.entries.iter_mut()
+    }
+}
+
+impl<V> fmt::Debug for OrderedMap<V> where V: fmt::Debug {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.entries.iter().map(|(key, value)| (key, value))).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(map: &OrderedMap<i32>) -> Vec<&str> {
+        map.iter().map(|entry| &*entry.0).collect()
+    }
+
+    #[test]
+    fn keeps_insertion_order() {
+        let mut map = OrderedMap::new();
+        for (value, key) in ["c", "a", "d", "b"].iter().enumerate() {
+            assert_eq!(map.insert((*key).into(), value as i32), None);
+        }
+        assert_eq!(map.insert("a".into(), 10), Some(1));
+        assert_eq!(keys(&map), vec!["c", "a", "d", "b"]);
+        assert_eq!(map.get("a"), Some(&10));
+        assert_eq!(map.len(), 4);
+
+        assert_eq!(map.remove("a"), Some(10));
+        assert_eq!(map.remove("a"), None);
+        assert_eq!(keys(&map), vec!["c", "d", "b"]);
+        assert_eq!((map.get("c"), map.get("d"), map.get("b")), (Some(&0), Some(&2), Some(&3)));
+
+        *map.get_mut("b").unwrap() += 1;
+        map.insert("a".into(), 5);
+        assert_eq!(keys(&map), vec!["c", "d", "b", "a"]);
+        assert_eq!(map.get("b"), Some(&4));
+        assert_eq!(format!("{:?}", map), r#"{"c": 0, "d": 2, "b": 4, "a": 5}"#);
+    }
+}
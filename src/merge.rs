@@ -0,0 +1,272 @@
+use std::error::Error;
+use std::fmt;
+
+use super::hooks::HookError;
+use super::patch::Patch;
+use super::path::{join, node_path};
+use super::types::{Dict, Document, Node, Value};
+
+/// What `Document::merge` does when both documents have a value for the same key
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum MergeStrategy {
+    /// The value from the document being merged in replaces the existing one
+    LaterWins,
+    /// The existing value is kept
+    FirstWins,
+    /// Different values for the same key are a `MergeConflict` error
+    Error,
+}
+
+/// The error produced by `Document::merge` with `MergeStrategy::Error`, when the two
+/// documents have different values for the same key.  Contains the path to the key,
+/// such as `server.tls.port` (or `upstream[1].host` for a repeated node).
+#[derive(Debug, PartialEq, Clone)]
+pub struct MergeConflict(pub String);
+
+impl fmt::Display for MergeConflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "conflicting values for {}", self.0)
+    }
+}
+
+impl Error for MergeConflict {}
+
+/// The error produced by `Document::merge`
+#[derive(Debug, PartialEq, Clone)]
+pub enum MergeError {
+    /// With `MergeStrategy::Error`, the two documents have different values for the same
+    /// key
+    Conflict(MergeConflict),
+    /// A mutation hook rejected one of the changes that the merge makes.  See
+    /// `Document::add_mutation_hook`.
+    Rejected(HookError),
+}
+
+impl fmt::Display for MergeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MergeError::Conflict(ref conflict) => fmt::Display::fmt(conflict, f),
+            MergeError::Rejected(ref err) => fmt::Display::fmt(err, f),
+        }
+    }
+}
+
+impl Error for MergeError {}
+
+impl From<MergeConflict> for MergeError {
+    fn from(conflict: MergeConflict) -> Self {
+        MergeError::Conflict(conflict)
+    }
+}
+
+impl From<HookError> for MergeError {
+    fn from(err: HookError) -> Self {
+        MergeError::Rejected(err)
+    }
+}
+
+impl Document {
+    /// Merge another document into this one, for layering configuration files on top of
+    /// each other.
+    ///
+    /// Nodes with the same name are merged together recursively, as are dict values with
+    /// the same key.  When nodes are repeated, the first node with a name is merged with
+    /// the first node of that name in the other document, the second with the second,
+    /// and so on.  Nodes that are only in the other document are added to the end.
+    /// Other values for the same key (including lists), and nodes with different
+    /// arguments, are resolved by `strategy`.
+    ///
+    /// # Failures
+    /// With `MergeStrategy::Error`, fails if the documents have different values for
+    /// the same key.  Also fails if one of the document's mutation hooks rejects one of
+    /// the changes that the merge makes, which are the operations of the `Patch` between
+    /// the document before and after (see `add_mutation_hook`).  Either way, the document
+    /// is left unchanged.
+    ///
+    /// # Examples
+    /// ```
+    /// # use figtree::types::*;
+    /// # use figtree::MergeStrategy;
+    /// # use std::io::Cursor;
+    /// let mut config = Document::parse(Cursor::new("server { host = 'a', port = 80 }")).unwrap();
+    /// let local = Document::parse(Cursor::new("server { port = 8080 } log {}")).unwrap();
+    /// config.merge(&local, MergeStrategy::LaterWins).unwrap();
+    /// assert_eq!(config["server"]["port"], Value::new_int(8080));
+    /// assert_eq!(config["server"]["host"], Value::new_string("a"));
+    /// assert!(config.has_node("log"));
+    /// ```
+    pub fn merge(&mut self, other: &Document, strategy: MergeStrategy) -> Result<(), MergeError> {
+        let mut merged = self.unhooked();
+        merge_nodes(merged.children_mut().collect(), other.children(), strategy, "")?;
+        for node in unmatched(self.children(), other.children()) {
+            merged.append_node(node.name().to_string(), node.clone());
+        }
+        if self.has_hooks() {
+            for op in &Patch::between(self, &merged).ops {
+                self.allow(&op.mutation())?;
+            }
+        }
+        self.replace_contents(merged);
+        Ok(())
+    }
+}
+
+/// Pair up the nodes in `others` with nodes of the same name in `nodes`, in order, and
+/// merge each pair
+fn merge_nodes<'a, I>(mut nodes: Vec<&mut Node>, others: I, strategy: MergeStrategy, path: &str)
+    -> Result<(), MergeConflict> where I: Iterator<Item = &'a Node> + Clone {
+
+    for (position, other) in others.clone().enumerate() {
+        let name = other.name();
+        let index = others.clone().take(position).filter(|node| node.name() == name).count();
+        let repeated = others.clone().filter(|node| node.name() == name).count() > 1;
+        let node = nodes.iter_mut().filter(|node| node.name() == name).nth(index);
+        if let Some(node) = node {
+            merge_node(node, other, strategy, &node_path(path, name, index, repeated))?;
+        }
+    }
+    Ok(())
+}
+
+/// The nodes in `others` that don't have a node to be merged with in `nodes`
+fn unmatched<'a, I>(nodes: I, others: I) -> Vec<&'a Node>
+    where I: Iterator<Item = &'a Node> + Clone {
+
+    let mut seen = Vec::new();
+    let mut unmatched = Vec::new();
+    for other in others {
+        let index = seen.iter().filter(|&&name| name == other.name()).count();
+        if nodes.clone().filter(|node| node.name() == other.name()).nth(index).is_none() {
+            unmatched.push(other);
+        }
+        seen.push(other.name());
+    }
+    unmatched
+}
+
+fn merge_node(node: &mut Node, other: &Node, strategy: MergeStrategy, path: &str)
+    -> Result<(), MergeConflict> {
+
+    // arguments are resolved as a whole, in the same way as a list
+    if node.arguments().is_empty() {
+        *node.arguments_mut() = other.arguments().to_vec();
+    } else if !other.arguments().is_empty() && node.arguments() != other.arguments() {
+        match strategy {
+            MergeStrategy::LaterWins => *node.arguments_mut() = other.arguments().to_vec(),
+            MergeStrategy::FirstWins => {},
+            MergeStrategy::Error => return Err(MergeConflict(path.to_string())),
+        }
+    }
+
+    for (key, value) in other.properties() {
+        let key_path = join(path, key);
+        match node.get_attr_mut(&**key) {
+            Some(existing) => merge_value(existing, value, strategy, &key_path)?,
+            None => { node.insert_attr(&**key, value.clone()); },
+        }
+    }
+
+    let added: Vec<_> = unmatched(node.children(), other.children()).into_iter().cloned().collect();
+    merge_nodes(node.children_mut().collect(), other.children(), strategy, path)?;
+    for child in added {
+        node.append_node(child.name().to_string(), child);
+    }
+    Ok(())
+}
+
+fn merge_value(value: &mut Value, other: &Value, strategy: MergeStrategy, path: &str)
+    -> Result<(), MergeConflict> {
+
+    if let (Value::Dict(dict), Value::Dict(other)) = (&mut *value, other) {
+        return merge_dict(dict, other, strategy, path);
+    }
+    if value == other {
+        return Ok(());
+    }
+    match strategy {
+        MergeStrategy::LaterWins => *value = other.clone(),
+        MergeStrategy::FirstWins => {},
+        MergeStrategy::Error => return Err(MergeConflict(path.to_string())),
+    }
+    Ok(())
+}
+
+fn merge_dict(dict: &mut Dict, other: &Dict, strategy: MergeStrategy, path: &str)
+    -> Result<(), MergeConflict> {
+
+    for (key, value) in other {
+        let key_path = join(path, key);
+        match dict.get_mut(key) {
+            Some(existing) => merge_value(existing, value, strategy, &key_path)?,
+            None => { dict.insert(key.clone(), value.clone()); },
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::figtree::Figtree;
+    use super::super::parser::ParserOptions;
+
+    fn parse(input: &str) -> Document {
+        let options = ParserOptions::new().repeated_nodes(true).node_arguments(true);
+        Figtree::from_string(input).with_options(options).parse().unwrap()
+    }
+
+    fn merged(first: &str, second: &str, strategy: MergeStrategy) -> Result<Document, MergeError> {
+        let mut doc = parse(first);
+        doc.merge(&parse(second), strategy).map(|_| doc)
+    }
+
+    #[test]
+    fn merges_recursively() {
+        let first = "server { port = 80, tls { cert = 'a' } env = { a = 1, b = { c = 2 } } }";
+        let second = "server { port = 81, tls { key = 'b' } env = { b = { d = 3 } }, list = [] } log {}";
+        assert_eq!(merged(first, second, MergeStrategy::LaterWins).unwrap(), parse(
+            "server { port = 81, tls { cert = 'a', key = 'b' } env = { a = 1, b = { c = 2, d = 3 } }, list = [] }
+             log {}"));
+        assert_eq!(merged(first, second, MergeStrategy::FirstWins).unwrap(), parse(
+            "server { port = 80, tls { cert = 'a', key = 'b' } env = { a = 1, b = { c = 2, d = 3 } }, list = [] }
+             log {}"));
+    }
+
+    #[test]
+    fn merges_repeated_nodes_in_order() {
+        let first = "up { host = 'a' } up { host = 'b' }";
+        let second = "up { port = 1 } up { port = 2 } up { port = 3 }";
+        assert_eq!(merged(first, second, MergeStrategy::LaterWins).unwrap(), parse(
+            "up { host = 'a', port = 1 } up { host = 'b', port = 2 } up { port = 3 }"));
+    }
+
+    #[test]
+    fn reports_conflicts() {
+        let first = "a { b { c = 1, same = 2 } } up { x = 1 } up { x = 2 }";
+        assert_eq!(merged(first, "a { b { c = 2 } }", MergeStrategy::Error),
+            Err(MergeConflict("a.b.c".to_string()).into()));
+        assert_eq!(merged(first, "up {} up { x = 3 }", MergeStrategy::Error).unwrap_err().to_string(),
+            "conflicting values for up[1].x");
+        assert!(merged(first, "a { b { same = 2, d = { e = 1 } } }", MergeStrategy::Error).is_ok());
+
+        // the document isn't changed by a failed merge
+        let mut doc = parse(first);
+        assert!(doc.merge(&parse("new {} a { b { c = 3 } }"), MergeStrategy::Error).is_err());
+        assert_eq!(doc, parse(first));
+    }
+
+    #[test]
+    fn merges_arguments() {
+        let doc = merged("listen 80 {} a {} b 1 {}", "listen 8080 {} a 'x' {} b 1 {}",
+            MergeStrategy::LaterWins).unwrap();
+        assert_eq!(doc["listen"].arguments(), &[Value::new_int(8080)]);
+        assert_eq!(doc["a"].arguments(), &[Value::new_string("x")]);
+        let doc = merged("listen 80 {}", "listen {}", MergeStrategy::LaterWins).unwrap();
+        assert_eq!(doc["listen"].arguments(), &[Value::new_int(80)]);
+        let doc = merged("listen 80 {}", "listen 8080 {}", MergeStrategy::FirstWins).unwrap();
+        assert_eq!(doc["listen"].arguments(), &[Value::new_int(80)]);
+        assert_eq!(merged("s { listen 80 {} }", "s { listen 8080 {} }", MergeStrategy::Error),
+            Err(MergeConflict("s.listen".to_string()).into()));
+        assert!(merged("b 1 {}", "b 1 {}", MergeStrategy::Error).is_ok());
+    }
+}
@@ -0,0 +1,219 @@
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+
+use super::convert::ConvertError;
+use super::position::Span;
+use super::types::{Node, Value};
+
+/// Types that can be built from a node.  This can be derived with `#[derive(FromNode)]`
+/// from the `figtree-derive` crate (re-exported with the `derive` feature), which reads
+/// each field of a struct from an attribute, argument or subnode of the node.
+///
+/// # Examples
+/// ```
+/// # use figtree::{Figtree, FromNode, FromNodeError, ParserOptions};
+/// # use figtree::types::*;
+/// #[derive(Debug)]
+/// struct Listen {
+///     port: u16,
+///     tls: bool,
+/// }
+///
+/// impl FromNode for Listen {
+///     fn from_node(node: &Node) -> Result<Self, FromNodeError> {
+///         Ok(Listen {
+///             port: node.require_argument(0)?,
+///             tls: node.convert_attr("tls")?.unwrap_or(false),
+///         })
+///     }
+/// }
+///
+/// let options = ParserOptions::new().node_arguments(true);
+/// let doc = Figtree::from_string("a 443 { tls = true } b {}").with_options(options)
+///     .parse().unwrap();
+/// let listen = Listen::from_node(&doc["a"]).unwrap();
+/// assert_eq!((listen.port, listen.tls), (443, true));
+/// assert_eq!(Listen::from_node(&doc["b"]).unwrap_err().to_string(),
+///            "node \"b\" is missing argument 1 (at line 1, column 22)");
+/// ```
+#[allow(clippy::result_large_err)]
+pub trait FromNode: Sized {
+    /// Build a value from a node
+    ///
+    /// # Failures
+    /// Fails if something the type needs is missing from the node, or can't be converted.
+    fn from_node(node: &Node) -> Result<Self, FromNodeError>;
+}
+
+/// Types that can be written as a node.  This is the inverse of `FromNode`, and can be
+/// derived in the same way, with `#[derive(ToNode)]`.
+pub trait ToNode {
+    /// Build a node from the value.  The node's name is left empty, as it belongs to
+    /// whatever the node is put into.
+    fn to_node(&self) -> Node;
+}
+
+impl FromNode for Node {
+    fn from_node(node: &Node) -> Result<Self, FromNodeError> {
+        Ok(node.clone())
+    }
+}
+
+impl ToNode for Node {
+    fn to_node(&self) -> Node {
+        self.clone()
+    }
+}
+
+/// The part of a node that a `FromNodeError` is about
+#[derive(Debug, PartialEq, Clone)]
+pub enum NodeItem {
+    /// The attribute with this key
+    Attr(String),
+    /// The argument at this index
+    Argument(usize),
+    /// The subnode with this name
+    Child(String),
+}
+
+/// Arguments are counted from one, as they are read
+impl fmt::Display for NodeItem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            NodeItem::Attr(ref key) => write!(f, "attribute {:?}", key),
+            NodeItem::Argument(index) => write!(f, "argument {}", index + 1),
+            NodeItem::Child(ref name) => write!(f, "node {:?}", name),
+        }
+    }
+}
+
+/// The error produced when a type can't be built from a node with `FromNode`
+#[derive(Debug, PartialEq, Clone)]
+pub struct FromNodeError {
+    /// The name of the node that the problem is in
+    pub node: String,
+    /// What in the node the problem is with
+    pub item: NodeItem,
+    /// Why the item couldn't be converted, or `None` if it's missing
+    pub error: Option<ConvertError>,
+    /// Where the problem is in the source: the value of an attribute, or the node itself
+    /// for anything else.  `None` for nodes that weren't parsed.
+    pub span: Option<Span>,
+}
+
+impl FromNodeError {
+    /// Construct an error for something that's missing from `node`
+    pub fn missing(node: &Node, item: NodeItem) -> Self {
+        FromNodeError { node: node.name().into(), item, error: None, span: node.span().cloned() }
+    }
+
+    /// Construct an error for something in `node` that can't be converted
+    pub fn convert(node: &Node, item: NodeItem, error: ConvertError) -> Self {
+        let span = match item {
+            NodeItem::Attr(ref key) => node.value_span(key).map(|spans| &spans.span),
+            _ => None,
+        };
+        let span = span.or(node.span()).cloned();
+        FromNodeError { node: node.name().into(), item, error: Some(error), span }
+    }
+}
+
+impl fmt::Display for FromNodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.error {
+            Some(ref error) => write!(f, "{} in node {:?}: {}", self.item, self.node, error)?,
+            None => write!(f, "node {:?} is missing {}", self.node, self.item)?,
+        }
+        if let Some(ref span) = self.span {
+            write!(f, " (at line {}, column {})", span.start.line + 1, span.start.pos + 1)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for FromNodeError {}
+
+/// Typed access to the parts of a node, for implementing `FromNode`.  Like `AttrError`s,
+/// `FromNodeError`s are only made when a conversion fails, so they aren't boxed.
+#[allow(clippy::result_large_err)]
+impl Node {
+    /// Get an attribute converted to `T`, or `None` if the node doesn't have it
+    ///
+    /// # Failures
+    /// Fails if the attribute can't be converted.
+    pub fn convert_attr<'a, T>(&'a self, key: &str) -> Result<Option<T>, FromNodeError>
+            where T: TryFrom<&'a Value, Error = ConvertError> {
+        self.get(key).map(T::try_from).transpose()
+            .map_err(|error| FromNodeError::convert(self, NodeItem::Attr(key.into()), error))
+    }
+
+    /// Get an attribute converted to `T`
+    ///
+    /// # Failures
+    /// Fails if the attribute is missing or can't be converted.
+    pub fn require_attr<'a, T>(&'a self, key: &str) -> Result<T, FromNodeError>
+            where T: TryFrom<&'a Value, Error = ConvertError> {
+        self.convert_attr(key)?
+            .ok_or_else(|| FromNodeError::missing(self, NodeItem::Attr(key.into())))
+    }
+
+    /// Get the argument at `index` converted to `T`, or `None` if there aren't that many
+    ///
+    /// # Failures
+    /// Fails if the argument can't be converted.
+    pub fn convert_argument<'a, T>(&'a self, index: usize) -> Result<Option<T>, FromNodeError>
+            where T: TryFrom<&'a Value, Error = ConvertError> {
+        self.arguments().get(index).map(T::try_from).transpose()
+            .map_err(|error| FromNodeError::convert(self, NodeItem::Argument(index), error))
+    }
+
+    /// Get the argument at `index` converted to `T`
+    ///
+    /// # Failures
+    /// Fails if there aren't that many arguments, or the argument can't be converted.
+    pub fn require_argument<'a, T>(&'a self, index: usize) -> Result<T, FromNodeError>
+            where T: TryFrom<&'a Value, Error = ConvertError> {
+        self.convert_argument(index)?
+            .ok_or_else(|| FromNodeError::missing(self, NodeItem::Argument(index)))
+    }
+
+    /// Get the arguments from `index` onwards, each converted to `T`
+    ///
+    /// # Failures
+    /// Fails with the first argument that can't be converted.
+    pub fn convert_arguments<'a, T>(&'a self, index: usize) -> Result<Vec<T>, FromNodeError>
+            where T: TryFrom<&'a Value, Error = ConvertError> {
+        let arguments = self.arguments().get(index..).unwrap_or(&[]);
+        arguments.iter().enumerate().map(|(offset, argument)| {
+            let item = NodeItem::Argument(index + offset);
+            T::try_from(argument).map_err(|error| FromNodeError::convert(self, item, error))
+        }).collect()
+    }
+
+    /// Get the first subnode with the given name, converted with `FromNode`, or `None` if
+    /// there isn't one
+    ///
+    /// # Failures
+    /// Fails if the subnode can't be converted.
+    pub fn convert_child<T: FromNode>(&self, name: &str) -> Result<Option<T>, FromNodeError> {
+        self.child(name).map(T::from_node).transpose()
+    }
+
+    /// Get the first subnode with the given name, converted with `FromNode`
+    ///
+    /// # Failures
+    /// Fails if there isn't a subnode with the name, or it can't be converted.
+    pub fn require_child<T: FromNode>(&self, name: &str) -> Result<T, FromNodeError> {
+        self.convert_child(name)?
+            .ok_or_else(|| FromNodeError::missing(self, NodeItem::Child(name.into())))
+    }
+
+    /// Get all of the subnodes with the given name, each converted with `FromNode`
+    ///
+    /// # Failures
+    /// Fails with the first subnode that can't be converted.
+    pub fn convert_children<T: FromNode>(&self, name: &str) -> Result<Vec<T>, FromNodeError> {
+        self.children_named(name).map(T::from_node).collect()
+    }
+}
@@ -1,5 +1,16 @@
-use super::lexer::{Lexer, LexToken, LexError};
-use super::position::Position;
+use super::lexer::{Lexer, LexToken, LexError, ReaderSource, Source};
+use super::position::{Position, Span};
+use super::datetime::DateTime;
+use super::borrowed::StrParser;
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::{Cursor, Read};
+use std::mem;
+use std::path::Path;
+use std::time::Duration;
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum ParsedValue {
@@ -8,6 +19,9 @@ pub enum ParsedValue {
     Float(f64),
     Bool(bool),
     Ident(String),
+    DateTime(DateTime),
+    Duration(Duration),
+    Bytes(Vec<u8>),
     Null,
 }
 
@@ -15,9 +29,16 @@ pub enum ParsedValue {
 pub enum ParseEvent {
     FileStart,
     FileEnd,
-    NodeStart(String),
+    /// The start of a node.  The flag is `true` if the name was written as a string
+    /// literal (`"node name" {}`) rather than as an identifier.
+    NodeStart(String, bool),
     NodeEnd,
-    Key(String),
+    /// A positional argument of the node that has just started (`listen 8080 { }`).
+    /// Arguments come straight after the `NodeStart`, before any of the node's entries.
+    Argument(ParsedValue),
+    /// A key.  The flag is `true` if the key was written as a string literal
+    /// (`"key": 1`) rather than as an identifier (`key = 1`).
+    Key(String, bool),
     Value(ParsedValue),
     ListStart,
     ListEnd,
@@ -26,56 +47,828 @@ pub enum ParseEvent {
 }
 
 /// An enum representing an error that occurs during parsing.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum ParseError {
     LexError(LexError),
     UnexpectedEndOfFile,
-    UnexpectedToken(LexToken),
+    /// A token that can't come where it was found, along with what could have come there
+    /// instead
+    UnexpectedToken(LexToken, Vec<Expected>),
     RepeatedNode(String),
+    /// An infinite or NaN float was found while `NonFinitePolicy::Error` is in use
+    NonFiniteFloat,
+    /// The document produced more events than `ParserOptions::max_events` allows
+    TooManyEvents,
+    /// A node or dict contained the same key twice while `DuplicateKeyPolicy::Error` is
+    /// in use.  The span is where the key first appeared; the error itself is reported
+    /// where it was repeated.
+    DuplicateKey(String, Span),
+}
+
+impl ParseError {
+    /// A stable code for the kind of error, like `E0001` for an unexpected token, which
+    /// tools and tests can match on instead of the message.  A lexical error has the code
+    /// of its `LexError`, and a code is never changed or reused for a different error.
+    ///
+    /// # Examples
+    /// ```
+    /// # use figtree::Figtree;
+    /// let (error, _) = Figtree::from_string("node { key = }").parse().unwrap_err();
+    /// assert_eq!(error.code(), "E0001");
+    /// let (error, _) = Figtree::from_string("node { key = 'value }").parse().unwrap_err();
+    /// assert_eq!(error.code(), "E0102");
+    /// ```
+    pub fn code(&self) -> &'static str {
+        match *self {
+            ParseError::LexError(ref error) => error.code(),
+            ParseError::UnexpectedToken(..) => "E0001",
+            ParseError::UnexpectedEndOfFile => "E0002",
+            ParseError::RepeatedNode(_) => "E0003",
+            ParseError::NonFiniteFloat => "E0004",
+            ParseError::TooManyEvents => "E0005",
+            ParseError::DuplicateKey(..) => "E0006",
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseError::LexError(ref error) => write!(f, "invalid token: {}", error),
+            ParseError::UnexpectedEndOfFile => f.write_str("unexpected end of file"),
+            ParseError::UnexpectedToken(ref token, ref expected) => {
+                write!(f, "unexpected {}", token)?;
+                for (index, item) in expected.iter().enumerate() {
+                    let joiner = match index {
+                        0 => ", expected ",
+                        index if index + 1 == expected.len() => " or ",
+                        _ => ", ",
+                    };
+                    write!(f, "{}{}", joiner, item)?;
+                }
+                Ok(())
+            },
+            ParseError::RepeatedNode(ref name) => write!(f, "node {:?} is repeated", name),
+            ParseError::NonFiniteFloat => f.write_str("infinite and NaN floats are not allowed"),
+            ParseError::TooManyEvents => {
+                f.write_str("document has more events than the maximum allowed")
+            },
+            ParseError::DuplicateKey(ref key, ref first) => {
+                write!(f, "duplicate key {:?} (first used at {})", key, first.start)
+            },
+        }
+    }
+}
+
+impl Error for ParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            ParseError::LexError(ref error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// Something that the parser would have accepted in place of an unexpected token
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Expected {
+    /// `{`, opening a node
+    OpenBrace,
+    /// `}`, closing a node or dict
+    CloseBrace,
+    /// `]`, closing a list
+    CloseBracket,
+    /// `:` between a key and its value
+    Colon,
+    /// `=` between a key and its value
+    Equals,
+    /// `,` between two entries
+    Comma,
+    /// `;` after a property or node, when `ParserOptions::semicolons` is turned on
+    Semicolon,
+    /// A line break between two entries, when `SeparatorPolicy::Newline` is in use
+    Newline,
+    /// The name of a node
+    NodeName,
+    /// The key of a property or dict entry
+    Key,
+    /// Any value
+    Value,
+    /// A node argument, when `ParserOptions::node_arguments` is turned on
+    Argument,
+    /// An identifier after `!`
+    Identifier,
+}
+
+impl fmt::Display for Expected {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Expected::OpenBrace => "`{`",
+            Expected::CloseBrace => "`}`",
+            Expected::CloseBracket => "`]`",
+            Expected::Colon => "`:`",
+            Expected::Equals => "`=`",
+            Expected::Comma => "`,`",
+            Expected::Semicolon => "`;`",
+            Expected::Newline => "a new line",
+            Expected::NodeName => "a node name",
+            Expected::Key => "a key",
+            Expected::Value => "a value",
+            Expected::Argument => "an argument",
+            Expected::Identifier => "an identifier",
+        })
+    }
+}
+
+/// Something suspicious in a document that doesn't stop it from being parsed, which the
+/// parser keeps when `ParserOptions::warnings` is turned on (see `Parser::take_warnings`).
+#[derive(Debug, PartialEq, Clone)]
+pub enum ParseWarning {
+    /// A node or dict contained the same key more than once.  The span is where the key
+    /// first appeared; the warning itself is reported where it was repeated.  Whichever
+    /// value is kept depends on the `DuplicateKeyPolicy`.
+    DuplicateKey(String, Span),
+    /// A string on a new line was joined onto the string before it, which usually means
+    /// that a comma is missing between them
+    SplitString,
+}
+
+impl ParseWarning {
+    /// A stable code for the kind of warning, like `W0001` for a duplicate key, in the
+    /// same way as `ParseError::code`
+    pub fn code(&self) -> &'static str {
+        match *self {
+            ParseWarning::DuplicateKey(..) => "W0001",
+            ParseWarning::SplitString => "W0002",
+        }
+    }
+}
+
+impl fmt::Display for ParseWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseWarning::DuplicateKey(ref key, ref first) => {
+                write!(f, "duplicate key {:?} (first used at {})", key, first.start)
+            },
+            ParseWarning::SplitString => {
+                f.write_str("string on a new line is joined onto the previous string")
+            },
+        }
+    }
+}
+
+/// How entries in nodes, dicts, and lists must be separated from each other.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SeparatorPolicy {
+    /// Entries must be separated by commas (the default).  Trailing commas are allowed.
+    Required,
+    /// Commas between entries may be left out.
+    Optional,
+    /// Commas are not allowed at all.
+    Forbidden,
+    /// Either a comma or a newline separates two entries.
+    Newline,
+}
+
+/// How non-finite floats are treated.  These can be written as `inf`, `-inf`, `+inf`
+/// or `nan`, or come from literals too large to be represented (`1e999`).
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum NonFinitePolicy {
+    /// Non-finite floats are parsed as floats (the default).
+    Allow,
+    /// Non-finite floats are an error.
+    Error,
+    /// Non-finite floats are parsed as identifier values (`!inf`, `!-inf`, or `!nan`).
+    Identifier,
+}
+
+/// What happens when a node or dict contains the same key more than once
+/// (`node { a = 1, a = 2 }`).
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum DuplicateKeyPolicy {
+    /// The later value replaces the earlier one (the default).
+    LastWins,
+    /// The later value is ignored.
+    FirstWins,
+    /// A repeated key is a `ParseError::DuplicateKey` error.
+    Error,
+    /// The values are collected into a list, in the order they were written
+    /// (`a = [1, 2]`).  A key that only appears once keeps its value as it is.
+    CollectList,
+}
+
+/// Options controlling which dialect of figtree a parser accepts.
+///
+/// # Examples
+/// ```
+/// # use figtree::{Figtree, ParserOptions, SeparatorPolicy};
+/// let options = ParserOptions::new().separators(SeparatorPolicy::Optional);
+/// let mut figgy = Figtree::from_string("node { 'a': 1 'b': 2 }").with_options(options);
+/// assert!(figgy.parse().is_ok());
+/// ```
+#[derive(Debug, PartialEq, Clone)]
+pub struct ParserOptions {
+    /// How entries must be separated from each other
+    pub separators: SeparatorPolicy,
+    /// Whether properties and nodes can be terminated with semicolons
+    pub semicolons: bool,
+    /// How infinite and NaN floats are treated
+    pub non_finite: NonFinitePolicy,
+    /// Whether the parser carries on after an error
+    pub recover: bool,
+    /// The maximum length in bytes of identifiers, strings and byte strings
+    pub max_token_length: Option<usize>,
+    /// The maximum number of events that can be produced
+    pub max_events: Option<usize>,
+    /// The maximum size of the input in bytes
+    pub max_document_size: Option<usize>,
+    /// The width of a tab, for column numbers
+    pub tab_width: usize,
+    /// Whether line endings in strings are converted to `\n`
+    pub normalize_newlines: bool,
+    /// Whether a document can contain several nodes with the same name
+    pub repeated_nodes: bool,
+    /// What happens when a node or dict contains the same key more than once
+    pub duplicate_keys: DuplicateKeyPolicy,
+    /// Whether node names can be followed by positional arguments
+    pub node_arguments: bool,
+    /// Whether comments are kept, and attached to the nodes and attributes of a `Document`
+    pub comments: bool,
+    /// Whether warnings are kept, to be read with `Parser::take_warnings`
+    pub warnings: bool,
+}
+
+impl ParserOptions {
+    /// Construct the default set of options
+    pub fn new() -> Self {
+        ParserOptions {
+            separators: SeparatorPolicy::Required,
+            semicolons: false,
+            non_finite: NonFinitePolicy::Allow,
+            recover: false,
+            max_token_length: None,
+            max_events: None,
+            max_document_size: None,
+            tab_width: 1,
+            normalize_newlines: false,
+            repeated_nodes: false,
+            duplicate_keys: DuplicateKeyPolicy::LastWins,
+            node_arguments: false,
+            comments: false,
+            warnings: false,
+        }
+    }
+
+    /// Set the separator policy
+    pub fn separators(mut self, policy: SeparatorPolicy) -> Self {
+        self.separators = policy;
+        self
+    }
+
+    /// Allow properties and nodes to be terminated with semicolons (`node { a = 1; };`).
+    ///
+    /// A semicolon is treated as a separator in the same way as a comma.
+    pub fn semicolons(mut self, allowed: bool) -> Self {
+        self.semicolons = allowed;
+        self
+    }
+
+    /// Set how infinite and NaN floats are treated
+    pub fn non_finite(mut self, policy: NonFinitePolicy) -> Self {
+        self.non_finite = policy;
+        self
+    }
+
+    /// Carry on parsing after an error, so that more than one error can be reported.
+    ///
+    /// After an error, the parser skips ahead to the next `}` or `]` that closes one of
+    /// the open nodes, dicts, or lists, or to the next identifier at the top level of the
+    /// file, and continues from there.  End events are produced for everything that was
+    /// left open, so the events stay balanced.  Lexing errors in the skipped input are
+    /// not reported.
+    pub fn recover(mut self, enabled: bool) -> Self {
+        self.recover = enabled;
+        self
+    }
+
+    /// Limit the length of identifiers, strings and byte strings, in bytes.  Longer
+    /// tokens are a `LexError::TokenTooLong` error.
+    pub fn max_token_length(mut self, limit: usize) -> Self {
+        self.max_token_length = Some(limit);
+        self
+    }
+
+    /// Limit the number of events that are produced.  Producing more is a
+    /// `ParseError::TooManyEvents` error, which ends parsing even in recovery mode.
+    pub fn max_events(mut self, limit: usize) -> Self {
+        self.max_events = Some(limit);
+        self
+    }
+
+    /// Limit the size of the input, in bytes.  Input beyond this is not read, and is a
+    /// `LexError::DocumentTooLarge` error.
+    pub fn max_document_size(mut self, limit: usize) -> Self {
+        self.max_document_size = Some(limit);
+        self
+    }
+
+    /// Set the width of a tab, so that column numbers match what an editor displays.  A
+    /// tab moves on to the next column that is a multiple of `width`.  The default width
+    /// of `1` counts each tab as a single column.
+    pub fn tab_width(mut self, width: usize) -> Self {
+        self.tab_width = width;
+        self
+    }
+
+    /// Convert `\r\n` and `\r` line endings in strings to `\n`, so that files written
+    /// with Windows line endings produce the same values as files with Unix ones
+    pub fn normalize_newlines(mut self, normalize: bool) -> Self {
+        self.normalize_newlines = normalize;
+        self
+    }
+
+    /// Allow a document, or a node, to contain more than one node with the same name
+    /// (`upstream { } upstream { }`).  Otherwise, repeated nodes are a
+    /// `ParseError::RepeatedNode` error when a `Document` is built.
+    ///
+    /// This doesn't affect the stream of parse events, which can always contain repeated
+    /// nodes.
+    pub fn repeated_nodes(mut self, allowed: bool) -> Self {
+        self.repeated_nodes = allowed;
+        self
+    }
+
+    /// Set what happens when a node or dict contains the same key more than once.  This
+    /// is applied when a `Document` is built, so doesn't affect the stream of parse
+    /// events.
+    pub fn duplicate_keys(mut self, policy: DuplicateKeyPolicy) -> Self {
+        self.duplicate_keys = policy;
+        self
+    }
+
+    /// Allow node names to be followed by positional arguments before the opening brace
+    /// (`listen 8080 'tcp' { }`), which are produced as `ParseEvent::Argument` events.
+    ///
+    /// Arguments are separated by whitespace, and can be any value other than a list or
+    /// a dict.
+    pub fn node_arguments(mut self, allowed: bool) -> Self {
+        self.node_arguments = allowed;
+        self
+    }
+
+    /// Keep the comments in the input, so that they can be read with
+    /// `Parser::take_comments`.  When a `Document` is built, each comment is attached to
+    /// the nearest node or attribute (see `Comments`).
+    pub fn comments(mut self, enabled: bool) -> Self {
+        self.comments = enabled;
+        self
+    }
+
+    /// Keep warnings about things in the input that are allowed but probably mistakes,
+    /// so that they can be read with `Parser::take_warnings` (see `ParseWarning`).
+    pub fn warnings(mut self, enabled: bool) -> Self {
+        self.warnings = enabled;
+        self
+    }
+}
+
+impl Default for ParserOptions {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum ParseContext {
     Basefile,
     Node(bool),
+    /// The arguments of a node, before its opening brace.  The node's own context is
+    /// underneath this one.
+    Arguments,
     Value,
     List(bool),
     Dict(bool),
 }
 
 type ContextStack = Vec<ParseContext>;
-pub type ParseResult = Result<(ParseEvent, Position), (ParseError, Position)>;
+/// An event or an error, along with the span of the input that it came from
+pub type ParseResult = Result<(ParseEvent, Span), (ParseError, Span)>;
+/// Every event and every error from a document, as returned by `Parser::parse_collecting`
+pub type CollectedResults = (Vec<(ParseEvent, Span)>, Vec<(ParseError, Span)>);
 
-pub struct Parser {
+/// Turns a stream of tokens into a stream of parse events.
+///
+/// # Examples
+/// ```
+/// # use figtree::{Lexer, Parser, ParserOptions};
+/// # use std::io::Cursor;
+/// let lexer = Lexer::lex(Cursor::new("a { x = } b { y = ] }"));
+/// let options = ParserOptions::new().recover(true);
+/// let errors = Parser::with_options(lexer, options).filter(|result| result.is_err());
+/// assert_eq!(errors.count(), 2);
+/// ```
+#[derive(Clone)]
+pub struct Parser<S = ReaderSource> {
     context: ContextStack,
     ended: bool,
-    lexer: Lexer,
-    peeked_result: Option<ParseResult>,
+    lexer: Lexer<S>,
+    /// Results that have been peeked at, but not yet returned
+    peeked: VecDeque<ParseResult>,
+    options: ParserOptions,
+    /// Whether the parser is skipping ahead after an error
+    recovering: bool,
+    /// A token that caused an error, which may be where parsing resumes
+    resync_token: Option<LexToken>,
+    /// Events produced while resynchronizing, waiting to be returned
+    queued: VecDeque<ParseResult>,
+    /// The number of events produced so far
+    event_count: usize,
+    /// The span of the most recently consumed token
+    token_span: Span,
+    /// Where the event being parsed started, if it covers more than one token
+    event_start: Option<Position>,
+    /// The number of nodes, dicts and lists opened by the events returned so far
+    depth: usize,
+    /// Warnings that haven't been taken yet
+    warnings: Vec<(ParseWarning, Span)>,
+    /// The keys seen so far in each open node and dict, for warning about duplicates
+    keys: Vec<HashMap<String, Span>>,
 }
 
-impl Parser {
-    pub fn parse(lexer: Lexer) -> Self {
-        Parser {
+impl<S: Source> Parser<S> {
+    pub fn parse(lexer: Lexer<S>) -> Self {
+        Self::with_options(lexer, ParserOptions::new())
+    }
+
+    pub fn with_options(lexer: Lexer<S>, options: ParserOptions) -> Self {
+        let mut parser = Parser {
             context: ContextStack::new(),
             ended: false,
-            lexer: lexer,
-            peeked_result: None,
+            lexer,
+            peeked: VecDeque::new(),
+            options: ParserOptions::new(),
+            recovering: false,
+            resync_token: None,
+            queued: VecDeque::new(),
+            event_count: 0,
+            token_span: Span::new(Position::new(), Position::new()),
+            event_start: None,
+            depth: 0,
+            warnings: Vec::new(),
+            keys: Vec::new(),
+        };
+        parser.set_options(options);
+        parser
+    }
+}
+
+impl Parser {
+    /// Parse the whole of `reader` in recovery mode, returning every event and every
+    /// error that was found.
+    ///
+    /// # Examples
+    /// ```
+    /// # use figtree::{Parser, ParseError, Position, Span};
+    /// # use std::io::Cursor;
+    /// let (events, errors) = Parser::parse_collecting(Cursor::new("a { x = } b { y = 1 2 }"));
+    /// assert_eq!(events.len(), 9);
+    /// assert_eq!(errors.len(), 2);
+    /// assert_eq!(errors[1].1, Span::new(Position::at(0, 20), Position::at(0, 21)));
+    /// ```
+    pub fn parse_collecting<R: Read + 'static>(reader: R) -> CollectedResults {
+        let options = ParserOptions::new().recover(true);
+        let mut events = Vec::new();
+        let mut errors = Vec::new();
+        for result in Self::with_options(Lexer::lex(reader), options) {
+            match result {
+                Ok(event) => events.push(event),
+                Err(error) => errors.push(error),
+            }
         }
+        (events, errors)
+    }
+
+    /// Construct a parser over any reader, with the default options
+    pub fn from_reader<R: Read + 'static>(reader: R) -> Self {
+        Self::parse(Lexer::lex(reader))
+    }
+
+    /// Construct a parser over the contents of a file
+    ///
+    /// # Failures
+    /// Returns an error if the file could not be opened.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self::from_reader(File::open(path)?))
+    }
+
+    /// Construct a parser over a slice of bytes.  The bytes are copied, so the parser
+    /// doesn't borrow from them.
+    ///
+    /// # Examples
+    /// ```
+    /// # use figtree::{Parser, ParseEvent};
+    /// let mut parser = Parser::from_bytes(b"node {}");
+    /// assert_eq!(parser.nth(1).unwrap().unwrap().0, ParseEvent::NodeStart("node".to_string(), false));
+    /// ```
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        Self::from_reader(Cursor::new(bytes.to_vec()))
+    }
+
+    /// Parse a string, producing events that borrow their names and strings from it
+    /// wherever possible.  See `StrParser` for the details.
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(input: &str) -> StrParser<'_> {
+        StrParser::new(input, ParserOptions::new())
+    }
+}
+
+impl<S: Source> Parser<S> {
+
+    pub fn set_options(&mut self, options: ParserOptions) {
+        self.lexer.set_max_token_length(options.max_token_length);
+        self.lexer.set_max_document_size(options.max_document_size);
+        self.lexer.set_tab_width(options.tab_width);
+        self.lexer.set_normalize_newlines(options.normalize_newlines);
+        self.lexer.set_trivia(false);
+        self.lexer.set_collect_comments(options.comments);
+        self.options = options;
     }
 
+    /// Take the comments that have been read since this was last called, along with
+    /// their spans.  Comments are only kept if `ParserOptions::comments` is turned on.
+    ///
+    /// The input is read ahead of the events that have been returned, so this can
+    /// include comments that come after the most recent event.
+    ///
+    /// # Examples
+    /// ```
+    /// # use figtree::{Lexer, Parser, ParserOptions};
+    /// # use std::io::Cursor;
+    /// let lexer = Lexer::lex(Cursor::new("node { # a comment\n}"));
+    /// let mut parser = Parser::with_options(lexer, ParserOptions::new().comments(true));
+    /// parser.by_ref().count();
+    /// let comments = parser.take_comments();
+    /// assert_eq!(comments[0].0, "# a comment");
+    /// ```
+    pub fn take_comments(&mut self) -> Vec<(String, Span)> {
+        self.lexer.take_comments()
+    }
+
+    /// Take the warnings that have been found since this was last called, along with
+    /// their spans.  Warnings are only kept if `ParserOptions::warnings` is turned on, and
+    /// never stop the parse, so a linter can report them alongside any errors.
+    ///
+    /// Like comments, this can include warnings from after the most recent event.
+    ///
+    /// # Examples
+    /// ```
+    /// # use figtree::{Lexer, Parser, ParserOptions, ParseWarning};
+    /// # use std::io::Cursor;
+    /// let lexer = Lexer::lex(Cursor::new("node { a = 1, b = 'x'\n'y', a = 2 }"));
+    /// let mut parser = Parser::with_options(lexer, ParserOptions::new().warnings(true));
+    /// assert!(parser.by_ref().all(|result| result.is_ok()));
+    /// let warnings: Vec<_> = parser.take_warnings().into_iter().map(|w| w.0).collect();
+    /// assert_eq!(warnings[0], ParseWarning::SplitString);
+    /// assert!(matches!(warnings[1], ParseWarning::DuplicateKey(ref key, _) if key == "a"));
+    /// ```
+    pub fn take_warnings(&mut self) -> Vec<(ParseWarning, Span)> {
+        mem::take(&mut self.warnings)
+    }
+
+    /// The options that the parser is using
+    pub fn options(&self) -> &ParserOptions {
+        &self.options
+    }
+
+    /// Look at the next result without consuming it
     pub fn peek(&mut self) -> Option<&ParseResult> {
-        if self.peeked_result.is_some() {
-            self.peeked_result.as_ref()
-        } else {
-            self.peeked_result = self.next();
-            self.peeked_result.as_ref()
+        self.peek_n(0)
+    }
+
+    /// Look at the result `n` places ahead without consuming anything, so that
+    /// `peek_n(0)` is the same as `peek()`.  Returns `None` if parsing ends before then.
+    ///
+    /// # Examples
+    /// ```
+    /// # use figtree::{Lexer, Parser, ParseEvent};
+    /// # use std::io::Cursor;
+    /// let mut parser = Parser::parse(Lexer::lex(Cursor::new("node { }")));
+    /// assert_eq!(parser.peek_n(2).unwrap().as_ref().unwrap().0, ParseEvent::NodeEnd);
+    /// assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::FileStart);
+    /// assert!(parser.peek_n(3).is_none());
+    /// ```
+    pub fn peek_n(&mut self, n: usize) -> Option<&ParseResult> {
+        while self.peeked.len() <= n {
+            match self.produce() {
+                Some(result) => self.peeked.push_back(result),
+                None => break,
+            }
+        }
+        self.peeked.get(n)
+    }
+
+    /// Skip the rest of the innermost open node, dict, or list, including its end event.
+    /// Called straight after a `NodeStart`, this skips the whole of that node.
+    ///
+    /// The skipped input is only lexed, not parsed, so no events are built for it, and
+    /// mistakes in it (other than lexing errors and unbalanced brackets) aren't reported.
+    /// Does nothing if no node, dict, or list is open.
+    ///
+    /// # Failures
+    /// Returns the error if the input ends, or can't be lexed, before the end of the
+    /// subtree.  This ends parsing, unless the parser is in recovery mode.
+    ///
+    /// # Examples
+    /// ```
+    /// # use figtree::{Parser, ParseEvent};
+    /// let mut parser = Parser::from_bytes(b"unknown { a = [1, {}] sub {} } known {}");
+    /// assert_eq!(parser.nth(1).unwrap().unwrap().0, ParseEvent::NodeStart("unknown".to_string(), false));
+    /// parser.skip_subtree().unwrap();
+    /// assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("known".to_string(), false));
+    /// ```
+    pub fn skip_subtree(&mut self) -> Result<(), (ParseError, Span)> {
+        // events that have already been produced are skipped first
+        let mut depth = 0usize;
+        while let Some(result) = self.peeked.pop_front().or_else(|| self.queued.pop_front()) {
+            match result {
+                Ok((ParseEvent::NodeStart(..), _)) | Ok((ParseEvent::ListStart, _)) |
+                Ok((ParseEvent::DictStart, _)) => depth += 1,
+                Ok((ParseEvent::NodeEnd, _)) | Ok((ParseEvent::ListEnd, _)) |
+                Ok((ParseEvent::DictEnd, _)) => match depth.checked_sub(1) {
+                    Some(remaining) => depth = remaining,
+                    None => {
+                        self.depth = self.depth.saturating_sub(1);
+                        return Ok(());
+                    },
+                },
+                Ok(_) => {},
+                Err(err) => return Err(err),
+            }
         }
+        if self.ended || self.recovering {
+            return Ok(());
+        }
+
+        let is_container = |ctx: &ParseContext| matches!(*ctx,
+            ParseContext::Node(_) | ParseContext::List(_) | ParseContext::Dict(_));
+        if !self.context.iter().any(is_container) {
+            return Ok(());
+        }
+
+        // the rest of the node's arguments are skipped along with its opening brace
+        if self.context.last() == Some(&ParseContext::Arguments) {
+            self.context.pop();
+            loop {
+                match self.next_token() {
+                    Some(Ok(LexToken::OpenBrace)) => break,
+                    Some(Ok(_)) => {},
+                    Some(Err(err)) => return self.skip_error(ParseError::LexError(err)),
+                    None => return self.skip_error(ParseError::UnexpectedEndOfFile),
+                }
+            }
+        }
+
+        let mut remaining = depth + 1;
+        let mut nested = 0usize;
+        while remaining > 0 {
+            match self.next_token() {
+                Some(Ok(LexToken::OpenBrace)) | Some(Ok(LexToken::OpenBracket)) => nested += 1,
+                Some(Ok(LexToken::CloseBrace)) | Some(Ok(LexToken::CloseBracket)) if nested > 0 =>
+                    nested -= 1,
+                Some(Ok(closer @ LexToken::CloseBrace)) | Some(Ok(closer @ LexToken::CloseBracket)) => {
+                    let index = self.context.iter().rposition(is_container).unwrap_or(0);
+                    // as with the end events, nodes are only followed by a separator if
+                    // semicolons are allowed
+                    let separated = match (&self.context[index], &closer) {
+                        (&ParseContext::Node(_), &LexToken::CloseBrace) => self.options.semicolons,
+                        (&ParseContext::Dict(_), &LexToken::CloseBrace) |
+                        (&ParseContext::List(_), &LexToken::CloseBracket) => true,
+                        (&ParseContext::List(_), _) => {
+                            let expected = vec![Expected::CloseBracket];
+                            return self.skip_error(ParseError::UnexpectedToken(closer, expected));
+                        },
+                        _ => {
+                            let expected = vec![Expected::CloseBrace];
+                            return self.skip_error(ParseError::UnexpectedToken(closer, expected));
+                        },
+                    };
+                    self.context.truncate(index);
+                    remaining -= 1;
+                    if remaining == 0 && separated {
+                        self.consume_separator();
+                    }
+                },
+                Some(Ok(_)) => {},
+                Some(Err(err)) => return self.skip_error(ParseError::LexError(err)),
+                None => return self.skip_error(ParseError::UnexpectedEndOfFile),
+            }
+        }
+        self.depth = self.depth.saturating_sub(1);
+        Ok(())
+    }
+
+    /// The number of nodes, dicts and lists that are open, as of the events returned so
+    /// far.  Events that have only been peeked at don't count.
+    ///
+    /// # Examples
+    /// ```
+    /// # use figtree::Parser;
+    /// let mut parser = Parser::from_bytes(b"node { list = [] }");
+    /// parser.nth(3);
+    /// assert_eq!(parser.depth(), 2);
+    /// ```
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Turn the parser into an iterator that also produces the depth of each event.
+    ///
+    /// Top level nodes are at depth `0`, and the entries inside them at depth `1`.  End
+    /// events are at the same depth as the start events that they match, and errors are
+    /// at the depth of wherever they were found.
+    ///
+    /// # Examples
+    /// ```
+    /// # use figtree::{Parser, ParseEvent};
+    /// let depths: Vec<_> = Parser::from_bytes(b"node { key = [1] }").with_depth()
+    ///     .map(|(result, depth)| (result.unwrap().0, depth))
+    ///     .collect();
+    /// assert_eq!(depths[2], (ParseEvent::Key("key".to_string(), false), 1));
+    /// assert_eq!(depths[4], (ParseEvent::Value(figtree::ParsedValue::Int(1)), 2));
+    /// assert_eq!(depths[5], (ParseEvent::ListEnd, 1));
+    /// ```
+    pub fn with_depth(self) -> WithDepth<S> {
+        WithDepth { parser: self }
+    }
+
+    fn skip_error(&mut self, error: ParseError) -> Result<(), (ParseError, Span)> {
+        match self.yield_error(error) {
+            Some(Err(err)) => Err(err),
+            _ => unreachable!(),
+        }
+    }
+
+    /// The lexer that the parser is reading tokens from
+    pub(crate) fn lexer_mut(&mut self) -> &mut Lexer<S> {
+        &mut self.lexer
     }
 
     pub fn lex_position(&self) -> Position {
         self.lexer.token_start.clone().freeze()
     }
 
+    /// Consume the next token, keeping track of its span
+    fn next_token(&mut self) -> Option<Result<LexToken, LexError>> {
+        match self.lexer.next_spanned() {
+            Some(Ok((tok, span))) => {
+                self.token_span = span;
+                Some(Ok(tok))
+            },
+            Some(Err((err, span))) => {
+                self.token_span = span;
+                Some(Err(err))
+            },
+            None => {
+                let end = self.lexer.position.clone().freeze();
+                self.token_span = Span::new(end.clone(), end);
+                None
+            },
+        }
+    }
+
+    /// Mark the token just consumed as the start of a multi-token event
+    fn start_event(&mut self) {
+        self.event_start = Some(self.token_span.start.clone());
+    }
+
+    fn warn(&mut self, warning: ParseWarning, span: Span) {
+        if self.options.warnings {
+            self.warnings.push((warning, span));
+        }
+    }
+
+    /// Keep track of the keys in each node and dict, to warn about any that are repeated
+    fn check_keys(&mut self, event: &ParseEvent, span: &Span) {
+        match *event {
+            ParseEvent::NodeStart(..) | ParseEvent::DictStart => self.keys.push(HashMap::new()),
+            ParseEvent::NodeEnd | ParseEvent::DictEnd => { self.keys.pop(); },
+            ParseEvent::Key(ref key, _) => {
+                let first = match self.keys.last_mut() {
+                    Some(keys) => match keys.get(key) {
+                        Some(first) => first.clone(),
+                        None => {
+                            keys.insert(key.clone(), span.clone());
+                            return;
+                        },
+                    },
+                    None => return,
+                };
+                self.warn(ParseWarning::DuplicateKey(key.clone(), first), span.clone());
+            },
+            _ => {},
+        }
+    }
+
     fn lex_error(&mut self, error: LexError) -> Option<ParseResult> {
         self.yield_error(ParseError::LexError(error))
     }
@@ -84,6 +877,7 @@ impl Parser {
         match self.context.last() {
             None => false,
             Some(&ParseContext::Basefile) => false,
+            Some(&ParseContext::Arguments) => false,
             Some(&ParseContext::Value) => false,
             Some(&ParseContext::Node(has_comma)) => has_comma,
             Some(&ParseContext::List(has_comma)) => has_comma,
@@ -100,79 +894,220 @@ impl Parser {
             Some(ctx) => Some(ctx),
         };
 
-        if pushable.is_some() {
-            self.context.push(pushable.unwrap());
+        if let Some(pushable) = pushable {
+            self.context.push(pushable);
         }
     }
 
-    fn parse_context_file(&mut self) -> Option<ParseResult> {
-        let next = self.lexer.next();
-        if let Some(Ok(LexToken::Identifier(ident))) = next {
-            let next = self.lexer.next();
-            match next {
-                Some(Ok(LexToken::OpenBrace)) => {
-                    self.context.push(ParseContext::Node(true));
-                    self.yield_state(ParseEvent::NodeStart(ident))
+    /// Test if the entry about to be parsed has been separated from the previous one.
+    fn is_separated(&mut self) -> bool {
+        match self.options.separators {
+            SeparatorPolicy::Required => self.has_comma(),
+            SeparatorPolicy::Optional | SeparatorPolicy::Forbidden => true,
+            SeparatorPolicy::Newline => self.has_comma() || self.lexer.newline_before,
+        }
+    }
+
+    /// What could have separated the entry just parsed from the one before it, in a
+    /// container closed by `closer`
+    fn separators(&self, closer: Expected) -> Vec<Expected> {
+        let mut expected = vec![closer, Expected::Comma];
+        if self.options.semicolons && closer != Expected::CloseBracket {
+            expected.push(Expected::Semicolon);
+        }
+        if self.options.separators == SeparatorPolicy::Newline {
+            expected.push(Expected::Newline);
+        }
+        expected
+    }
+
+    /// What could have followed the name of a node
+    fn after_node_name(&self, key: bool) -> Vec<Expected> {
+        let mut expected = vec![Expected::OpenBrace];
+        if self.options.node_arguments {
+            expected.push(Expected::Argument);
+        }
+        if key {
+            expected.extend([Expected::Colon, Expected::Equals]);
+        }
+        expected
+    }
+
+    /// What could have been parsed as the value the parser is looking for
+    fn value_expected(&self) -> Vec<Expected> {
+        match self.context.last() {
+            Some(&ParseContext::Arguments) => vec![Expected::OpenBrace, Expected::Argument],
+            Some(&ParseContext::List(_)) => vec![Expected::CloseBracket, Expected::Value],
+            _ => vec![Expected::Value],
+        }
+    }
+
+    fn consume_separator(&mut self) {
+        // arguments are only separated by whitespace
+        if self.context.last() == Some(&ParseContext::Arguments) {
+            return;
+        }
+        let consume = match self.lexer.peek() {
+            // if commas are forbidden, leave the comma to be reported as an unexpected token
+            Some(&Ok(LexToken::Comma)) =>
+                self.options.separators != SeparatorPolicy::Forbidden,
+            // semicolons terminate properties and nodes, but not list items
+            Some(&Ok(LexToken::Semicolon)) =>
+                self.options.semicolons && !matches!(self.context.last(), Some(&ParseContext::List(_))),
+            _ => false,
+        };
+
+        if consume {
+            self.set_comma(true);
+            self.lexer.next();
+        }
+    }
+
+    fn name_token(name: String, quoted: bool) -> LexToken {
+        if quoted { LexToken::StringLit(name) } else { LexToken::Identifier(name) }
+    }
+
+    fn parse_key_separator(&mut self, key: String, quoted: bool) -> Option<ParseResult> {
+        // keys can be separated from their values by either ':' or '='
+        match self.next_token() {
+            Some(Ok(LexToken::Colon)) | Some(Ok(LexToken::Equals)) => {
+                self.context.push(ParseContext::Value);
+                self.yield_state(ParseEvent::Key(key, quoted))
+            },
+            Some(Ok(tok)) => {
+                let expected = vec![Expected::Colon, Expected::Equals];
+                self.yield_error(ParseError::UnexpectedToken(tok, expected))
+            },
+            Some(Err(err)) =>
+                self.lex_error(err),
+            None =>
+                self.yield_error(ParseError::UnexpectedEndOfFile),
+        }
+    }
+
+    /// Test if the next token starts a node argument
+    fn at_argument(&mut self) -> bool {
+        if !self.options.node_arguments {
+            return false;
+        }
+        match self.lexer.peek() {
+            Some(&Ok(LexToken::StringLit(_))) | Some(&Ok(LexToken::IntegerLit(_))) |
+            Some(&Ok(LexToken::FloatLit(_))) | Some(&Ok(LexToken::DateTimeLit(_))) |
+            Some(&Ok(LexToken::DurationLit(_))) | Some(&Ok(LexToken::BytesLit(_))) |
+            Some(&Ok(LexToken::Bang)) => true,
+            Some(&Ok(LexToken::Identifier(ref ident))) =>
+                matches!(&**ident, "true" | "false" | "null" | "none" | "inf" | "nan"),
+            _ => false,
+        }
+    }
+
+    /// Start a node whose name is followed by arguments.  Its span only covers the name.
+    fn start_arguments(&mut self, name: String, quoted: bool) -> Option<ParseResult> {
+        self.context.push(ParseContext::Node(true));
+        self.context.push(ParseContext::Arguments);
+        self.yield_state(ParseEvent::NodeStart(name, quoted))
+    }
+
+    /// Parse a name inside a node, which may either start a subnode or a key.
+    fn parse_node_entry(&mut self, name: String, quoted: bool) -> Option<ParseResult> {
+        // whether this is separated from the previous entry can only be tested before
+        // moving on to the next token
+        let separated = self.is_separated();
+        let name_span = self.token_span.clone();
+        self.start_event();
+        if self.at_argument() {
+            self.set_comma(true);
+            return self.start_arguments(name, quoted);
+        }
+        match self.next_token() {
+            Some(Ok(LexToken::OpenBrace)) => {
+                self.set_comma(true);
+                self.context.push(ParseContext::Node(true));
+                self.yield_state(ParseEvent::NodeStart(name, quoted))
+            },
+            Some(Ok(LexToken::Equals)) | Some(Ok(LexToken::Colon)) => {
+                if !separated {
+                    let expected = self.separators(Expected::CloseBrace);
+                    return self.fail(
+                        ParseError::UnexpectedToken(Self::name_token(name, quoted), expected),
+                        name_span);
                 }
-                Some(Ok(tok)) =>
-                    self.yield_error(ParseError::UnexpectedToken(tok)),
-                Some(Err(err)) =>
-                    self.yield_error(ParseError::LexError(err)),
-                None =>
-                    self.yield_error(ParseError::UnexpectedEndOfFile),
+                self.set_comma(false);
+                self.context.push(ParseContext::Value);
+                self.yield_state(ParseEvent::Key(name, quoted))
+            },
+            Some(Ok(tok)) => {
+                let expected = self.after_node_name(true);
+                self.yield_error(ParseError::UnexpectedToken(tok, expected))
+            },
+            Some(Err(err)) =>
+                self.lex_error(err),
+            None =>
+                self.yield_error(ParseError::UnexpectedEndOfFile),
+        }
+    }
+
+    fn parse_context_file(&mut self) -> Option<ParseResult> {
+        let next = match self.resync_token.take() {
+            Some(tok) => Some(Ok(tok)),
+            None => self.next_token(),
+        };
+        let (name, quoted) = match next {
+            Some(Ok(LexToken::Identifier(ident))) => (ident, false),
+            Some(Ok(LexToken::StringLit(string))) => (string, true),
+            Some(Ok(tok)) =>
+                return self.yield_error(ParseError::UnexpectedToken(tok, vec![Expected::NodeName])),
+            Some(Err(err)) =>
+                return self.lex_error(err),
+            None => {
+                self.ended = true;
+                return self.yield_state(ParseEvent::FileEnd);
+            },
+        };
+
+        self.start_event();
+        if self.at_argument() {
+            return self.start_arguments(name, quoted);
+        }
+        match self.next_token() {
+            Some(Ok(LexToken::OpenBrace)) => {
+                self.context.push(ParseContext::Node(true));
+                self.yield_state(ParseEvent::NodeStart(name, quoted))
             }
-        } else if let Some(Ok(tok)) = next {
-            self.yield_error(ParseError::UnexpectedToken(tok))
-        } else if let Some(Err(next)) = next {
-            self.lex_error(next)
-        } else {
-            self.ended = true;
-            self.yield_state(ParseEvent::FileEnd)
+            Some(Ok(tok)) => {
+                let expected = self.after_node_name(false);
+                self.yield_error(ParseError::UnexpectedToken(tok, expected))
+            },
+            Some(Err(err)) =>
+                self.yield_error(ParseError::LexError(err)),
+            None =>
+                self.yield_error(ParseError::UnexpectedEndOfFile),
         }
     }
 
     fn parse_context_node(&mut self) -> Option<ParseResult> {
-        let next = self.lexer.next();
+        let next = self.next_token();
         match next {
             Some(Ok(LexToken::CloseBrace)) => {
                 self.context.pop();
+                if self.options.semicolons {
+                    self.consume_separator();
+                }
                 self.yield_state(ParseEvent::NodeEnd)
             },
-            Some(Ok(LexToken::Identifier(ident))) => {
-                self.set_comma(true);
-                match self.lexer.next() {
-                    Some(Ok(LexToken::OpenBrace)) => {
-                        self.context.push(ParseContext::Node(true));
-                        self.yield_state(ParseEvent::NodeStart(ident))
-                    },
-                    Some(Ok(tok)) =>
-                        self.yield_error(ParseError::UnexpectedToken(tok)),
-                    Some(Err(err)) =>
-                        self.lex_error(err),
-                    None =>
-                        self.yield_error(ParseError::UnexpectedEndOfFile),
-                }
+            Some(Ok(LexToken::Identifier(name))) => {
+                self.parse_node_entry(name, false)
             },
-            Some(Ok(LexToken::StringLit(key))) => {
-                if !self.has_comma() {
-                    return self.yield_error(ParseError::UnexpectedToken(LexToken::StringLit(key)));
-                }
-                self.set_comma(false);
-                match self.lexer.next() {
-                    Some(Ok(LexToken::Colon)) => {
-                        self.context.push(ParseContext::Value);
-                        self.yield_state(ParseEvent::Key(key))
-                    },
-                    Some(Ok(tok)) =>
-                        self.yield_error(ParseError::UnexpectedToken(tok)),
-                    Some(Err(err)) =>
-                        self.lex_error(err),
-                    None =>
-                        self.yield_error(ParseError::UnexpectedEndOfFile),
-                }
+            Some(Ok(LexToken::StringLit(name))) => {
+                self.parse_node_entry(name, true)
             },
             Some(Ok(tok)) => {
-                self.yield_error(ParseError::UnexpectedToken(tok))
+                let expected = if self.is_separated() {
+                    vec![Expected::CloseBrace, Expected::NodeName, Expected::Key]
+                } else {
+                    self.separators(Expected::CloseBrace)
+                };
+                self.yield_error(ParseError::UnexpectedToken(tok, expected))
             },
             Some(Err(err)) => {
                 self.yield_error(ParseError::LexError(err))
@@ -183,24 +1118,66 @@ impl Parser {
         }
     }
 
+    fn parse_context_arguments(&mut self) -> Option<ParseResult> {
+        match self.lexer.peek() {
+            Some(&Ok(LexToken::OpenBrace)) => {
+                self.next_token();
+                self.context.pop();
+                self.parse_context_node()
+            },
+            // lists and dicts can't be arguments, and a brace opens the node instead
+            Some(&Ok(LexToken::OpenBracket)) => {
+                let tok = self.next_token().unwrap().unwrap();
+                let expected = self.value_expected();
+                self.yield_error(ParseError::UnexpectedToken(tok, expected))
+            },
+            _ => {
+                self.context.push(ParseContext::Value);
+                match self.parse_context_value() {
+                    Some(Ok((ParseEvent::Value(value), span))) =>
+                        Some(Ok((ParseEvent::Argument(value), span))),
+                    result => result,
+                }
+            },
+        }
+    }
+
+    fn parse_float(&mut self, flt: f64) -> Option<ParseResult> {
+        if flt.is_finite() {
+            return self.yield_state(ParseEvent::Value(ParsedValue::Float(flt)));
+        }
+
+        match self.options.non_finite {
+            NonFinitePolicy::Allow =>
+                self.yield_state(ParseEvent::Value(ParsedValue::Float(flt))),
+            NonFinitePolicy::Error =>
+                self.yield_error(ParseError::NonFiniteFloat),
+            NonFinitePolicy::Identifier => {
+                let ident = if flt.is_nan() { "nan" } else if flt > 0.0 { "inf" } else { "-inf" };
+                self.yield_state(ParseEvent::Value(ParsedValue::Ident(ident.to_string())))
+            },
+        }
+    }
+
     fn parse_context_value(&mut self) -> Option<ParseResult> {
         self.context.pop();
-       let response = match self.lexer.next() {
+        let response = match self.next_token() {
             None => self.yield_error(ParseError::UnexpectedEndOfFile),
             Some(Err(err)) => self.yield_error(ParseError::LexError(err)),
             Some(Ok(LexToken::StringLit(string))) => {
                 let mut val_string = String::new();
                 val_string.push_str(&string);
-                loop {
-                    // I think this hack is necessary
-                    match self.lexer.peek() {
-                        Some(&Ok(LexToken::StringLit(_))) => {},
-                        _ => { break; }
-                    }
-
-                    match self.lexer.next().unwrap().unwrap() {
+                self.start_event();
+                // I think this hack is necessary
+                while matches!(self.lexer.peek(), Some(&Ok(LexToken::StringLit(_)))) {
+                    let split = self.lexer.newline_before;
+                    match self.next_token().unwrap().unwrap() {
                         LexToken::StringLit(s) => {
                             val_string.push_str(&s);
+                            if split {
+                                let span = self.token_span.clone();
+                                self.warn(ParseWarning::SplitString, span);
+                            }
                         },
                         _ => unreachable!(),
                     }
@@ -211,7 +1188,16 @@ impl Parser {
                 self.yield_state(ParseEvent::Value(ParsedValue::Int(integer)))
             }
             Some(Ok(LexToken::FloatLit(flt))) => {
-                self.yield_state(ParseEvent::Value(ParsedValue::Float(flt)))
+                self.parse_float(flt)
+            }
+            Some(Ok(LexToken::DateTimeLit(datetime))) => {
+                self.yield_state(ParseEvent::Value(ParsedValue::DateTime(datetime)))
+            }
+            Some(Ok(LexToken::DurationLit(duration))) => {
+                self.yield_state(ParseEvent::Value(ParsedValue::Duration(duration)))
+            }
+            Some(Ok(LexToken::BytesLit(bytes))) => {
+                self.yield_state(ParseEvent::Value(ParsedValue::Bytes(bytes)))
             }
             Some(Ok(LexToken::Identifier(ident))) => {
                 match &*ident {
@@ -219,84 +1205,104 @@ impl Parser {
                         self.yield_state(ParseEvent::Value(ParsedValue::Bool(true))),
                     "false" =>
                         self.yield_state(ParseEvent::Value(ParsedValue::Bool(false))),
-                    "null" =>
+                    "null" | "none" =>
                         self.yield_state(ParseEvent::Value(ParsedValue::Null)),
-                    _ =>
-                        self.yield_error(ParseError::UnexpectedToken(LexToken::Identifier(ident))),
+                    "inf" =>
+                        self.parse_float(f64::INFINITY),
+                    "nan" =>
+                        self.parse_float(f64::NAN),
+                    _ => {
+                        let expected = self.value_expected();
+                        let token = LexToken::Identifier(ident);
+                        self.yield_error(ParseError::UnexpectedToken(token, expected))
+                    },
                 }
             },
             Some(Ok(LexToken::Bang)) => {
-                match self.lexer.next() {
+                self.start_event();
+                match self.next_token() {
                     Some(Ok(LexToken::Identifier(s))) => {
                         self.yield_state(ParseEvent::Value(ParsedValue::Ident(s)))
                     },
-                    Some(Ok(tok)) => self.yield_error(ParseError::UnexpectedToken(tok)),
+                    Some(Ok(tok)) => {
+                        let expected = vec![Expected::Identifier];
+                        self.yield_error(ParseError::UnexpectedToken(tok, expected))
+                    },
                     Some(Err(err)) => self.lex_error(err),
                     None => self.yield_error(ParseError::UnexpectedEndOfFile),
                 }
             },
             Some(Ok(LexToken::OpenBracket)) => {
+                // container values are only complete once they have been closed, so the
+                // comma (if any) is consumed by the matching ListEnd/DictEnd
                 self.context.push(ParseContext::List(true));
-                self.yield_state(ParseEvent::ListStart)
+                return self.yield_state(ParseEvent::ListStart);
             },
             Some(Ok(LexToken::OpenBrace)) => {
                 self.context.push(ParseContext::Dict(true));
-                self.yield_state(ParseEvent::DictStart)
+                return self.yield_state(ParseEvent::DictStart);
+            },
+            Some(Ok(tok)) => {
+                let expected = self.value_expected();
+                self.yield_error(ParseError::UnexpectedToken(tok, expected))
             },
-            Some(Ok(tok)) => self.yield_error(ParseError::UnexpectedToken(tok)),
         };
 
-        if matches!(self.lexer.peek(), Some(&Ok(LexToken::Comma))) {
-            self.set_comma(true);
-            self.lexer.next();
-        }
-
+        self.consume_separator();
         response
     }
 
     fn parse_context_list(&mut self) -> Option<ParseResult> {
         if matches!(self.lexer.peek(), Some(&Ok(LexToken::CloseBracket))) {
-            self.lexer.next(); // consume close-bracket
+            self.next_token(); // consume close-bracket
             self.context.pop();
-            if matches!(self.lexer.peek(), Some(&Ok(LexToken::Comma))) {
-                self.set_comma(true);
-                self.lexer.next();
-            }
+            self.consume_separator();
             self.yield_state(ParseEvent::ListEnd)
+        } else if matches!(self.lexer.peek(), Some(&Ok(_))) && !self.is_separated() {
+            // list items must be separated by commas
+            let tok = self.next_token().unwrap().unwrap();
+            let expected = self.separators(Expected::CloseBracket);
+            self.yield_error(ParseError::UnexpectedToken(tok, expected))
         } else {
             // This isn't a close-bracket, so push a value context
             // and parse the next token(s) as a value.
+            self.set_comma(false);
             self.context.push(ParseContext::Value);
             self.parse_context_value()
         }
     }
 
     fn parse_context_dict(&mut self) -> Option<ParseResult> {
-        match self.lexer.next() {
+        match self.next_token() {
             Some(Ok(LexToken::CloseBrace)) => {
                 self.context.pop();
+                self.consume_separator();
                 self.yield_state(ParseEvent::DictEnd)
             },
             Some(Ok(LexToken::StringLit(key))) => {
-                if !self.has_comma() {
-                    return self.yield_error(ParseError::UnexpectedToken(LexToken::StringLit(key)));
+                if !self.is_separated() {
+                    let expected = self.separators(Expected::CloseBrace);
+                    let token = LexToken::StringLit(key);
+                    return self.yield_error(ParseError::UnexpectedToken(token, expected));
                 }
                 self.set_comma(false);
-                match self.lexer.next() {
-                    Some(Ok(LexToken::Colon)) => {
-                        self.context.push(ParseContext::Value);
-                        self.yield_state(ParseEvent::Key(key))
-                    },
-                    Some(Ok(tok)) =>
-                        self.yield_error(ParseError::UnexpectedToken(tok)),
-                    Some(Err(err)) =>
-                        self.lex_error(err),
-                    None =>
-                        self.yield_error(ParseError::UnexpectedEndOfFile),
+                self.start_event();
+                self.parse_key_separator(key, true)
+            },
+            Some(Ok(LexToken::Identifier(key))) => {
+                // dicts cannot contain nodes, so a bare identifier is always a key
+                if !self.is_separated() {
+                    let expected = self.separators(Expected::CloseBrace);
+                    let token = LexToken::Identifier(key);
+                    return self.yield_error(ParseError::UnexpectedToken(token, expected));
                 }
+                self.set_comma(false);
+                self.start_event();
+                self.parse_key_separator(key, false)
             },
             Some(Ok(tok)) => {
-                self.yield_error(ParseError::UnexpectedToken(tok))
+                let expected = vec![Expected::CloseBrace, Expected::Key];
+                self.yield_error(ParseError::UnexpectedToken(tok, expected))
             },
             Some(Err(err)) => {
                 self.yield_error(ParseError::LexError(err))
@@ -308,21 +1314,177 @@ impl Parser {
     }
 
     fn yield_state(&mut self, state: ParseEvent) -> Option<ParseResult> {
-        Some(Ok((state, self.lex_position())))
+        let span = match self.event_start.take() {
+            Some(start) => Span::new(start, self.token_span.end.clone()),
+            None => self.token_span.clone(),
+        };
+        Some(Ok((state, span)))
     }
 
     fn yield_error(&mut self, error: ParseError) -> Option<ParseResult> {
-        self.ended = true;
-        Some(Err((error, self.lex_position())))
+        // the token that caused the error may be the one that parsing can resume from
+        if let ParseError::UnexpectedToken(ref tok, _) = error {
+            if self.options.recover {
+                self.resync_token = Some(tok.clone());
+            }
+        }
+        let span = self.token_span.clone();
+        self.fail(error, span)
+    }
+
+    fn fail(&mut self, error: ParseError, span: Span) -> Option<ParseResult> {
+        self.event_start = None;
+        if self.options.recover {
+            self.recovering = true;
+        } else {
+            self.ended = true;
+        }
+        Some(Err((error, span)))
+    }
+
+    /// Pop contexts until one that is closed by `closer` has been popped, queueing the
+    /// end events for each of them.  Returns `false` (and leaves the stack alone) if
+    /// nothing open is closed by `closer`.
+    fn unwind_to(&mut self, closer: &LexToken) -> bool {
+        let closes = |ctx: &ParseContext| match *ctx {
+            ParseContext::Node(_) | ParseContext::Dict(_) => closer == &LexToken::CloseBrace,
+            ParseContext::List(_) => closer == &LexToken::CloseBracket,
+            _ => false,
+        };
+        if !self.context.iter().any(&closes) {
+            return false;
+        }
+
+        while let Some(ctx) = self.context.pop() {
+            let closed = closes(&ctx);
+            let event = match ctx {
+                ParseContext::Node(_) => ParseEvent::NodeEnd,
+                ParseContext::Dict(_) => ParseEvent::DictEnd,
+                ParseContext::List(_) => ParseEvent::ListEnd,
+                _ => continue,
+            };
+            self.queued.push_back(Ok((event, self.token_span.clone())));
+            if closed {
+                break;
+            }
+        }
+
+        let is_node = matches!(self.queued.back(), Some(&Ok((ParseEvent::NodeEnd, _))));
+        if !is_node || self.options.semicolons {
+            self.consume_separator();
+        }
+        true
+    }
+
+    /// Skip ahead after an error to somewhere that parsing can continue from.
+    fn resynchronize(&mut self) -> Option<ParseResult> {
+        self.recovering = false;
+        let mut depth = 0usize;
+        loop {
+            let next = match self.resync_token.take() {
+                Some(tok) => Some(Ok(tok)),
+                None => self.next_token(),
+            };
+            match next {
+                Some(Ok(LexToken::OpenBrace)) | Some(Ok(LexToken::OpenBracket)) => depth += 1,
+                Some(Ok(LexToken::CloseBrace)) | Some(Ok(LexToken::CloseBracket)) if depth > 0 =>
+                    depth -= 1,
+                Some(Ok(closer @ LexToken::CloseBrace)) | Some(Ok(closer @ LexToken::CloseBracket)) => {
+                    if self.unwind_to(&closer) {
+                        return self.next_result();
+                    }
+                },
+                Some(Ok(tok @ LexToken::Identifier(_))) | Some(Ok(tok @ LexToken::StringLit(_))) => {
+                    if depth == 0 && self.context.last() == Some(&ParseContext::Basefile) {
+                        self.resync_token = Some(tok);
+                        return self.next_result();
+                    }
+                },
+                Some(_) => {},
+                None => {
+                    // close everything that was left open, and end the file
+                    while let Some(ctx) = self.context.pop() {
+                        let event = match ctx {
+                            ParseContext::Basefile => ParseEvent::FileEnd,
+                            ParseContext::Node(_) => ParseEvent::NodeEnd,
+                            ParseContext::Dict(_) => ParseEvent::DictEnd,
+                            ParseContext::List(_) => ParseEvent::ListEnd,
+                            ParseContext::Arguments | ParseContext::Value => continue,
+                        };
+                        self.queued.push_back(Ok((event, self.token_span.clone())));
+                    }
+                    self.ended = true;
+                    return self.next_result();
+                },
+            }
+        }
     }
 }
 
-impl Iterator for Parser {
+impl<S: Source> Iterator for Parser<S> {
     type Item = ParseResult;
 
     fn next(&mut self) -> Option<Self::Item> {
+        let result = match self.peeked.pop_front() {
+            Some(result) => Some(result),
+            None => self.produce(),
+        };
+        match result {
+            Some(Ok((ParseEvent::NodeStart(..), _))) | Some(Ok((ParseEvent::ListStart, _))) |
+            Some(Ok((ParseEvent::DictStart, _))) => self.depth += 1,
+            Some(Ok((ParseEvent::NodeEnd, _))) | Some(Ok((ParseEvent::ListEnd, _))) |
+            Some(Ok((ParseEvent::DictEnd, _))) => self.depth = self.depth.saturating_sub(1),
+            _ => {},
+        }
+        result
+    }
+}
+
+/// An iterator over parse results and the depth of each of them, created by
+/// `Parser::with_depth`
+pub struct WithDepth<S = ReaderSource> {
+    parser: Parser<S>,
+}
+
+impl<S> WithDepth<S> {
+    /// The parser that is producing the results
+    pub fn parser(&mut self) -> &mut Parser<S> {
+        &mut self.parser
+    }
+}
+
+impl<S: Source> Iterator for WithDepth<S> {
+    type Item = (ParseResult, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let before = self.parser.depth;
+        let result = self.parser.next()?;
+        Some((result, before.min(self.parser.depth)))
+    }
+}
+
+impl<S: Source> Parser<S> {
+    /// Produce the next result, enforcing the event limit
+    fn produce(&mut self) -> Option<ParseResult> {
+        let result = self.next_result();
+        if let Some(Ok((ref event, ref span))) = result {
+            if self.options.warnings {
+                self.check_keys(event, span);
+            }
+            self.event_count += 1;
+            if self.options.max_events.is_some_and(|limit| self.event_count > limit) {
+                self.ended = true;
+                self.queued.clear();
+                return Some(Err((ParseError::TooManyEvents, span.clone())));
+            }
+        }
+        result
+    }
+
+    fn next_result(&mut self) -> Option<ParseResult> {
+        if !self.queued.is_empty() { return self.queued.pop_front(); }
         if self.ended { return None; }
-        if self.peeked_result.is_some() { return self.peeked_result.take(); }
+        if self.recovering { return self.resynchronize(); }
 
         let current_state = self.context.pop();
         match current_state {
@@ -338,6 +1500,10 @@ impl Iterator for Parser {
                 self.context.push(current_state.unwrap());
                 self.parse_context_node()
             },
+            Some(ParseContext::Arguments) => {
+                self.context.push(current_state.unwrap());
+                self.parse_context_arguments()
+            },
             Some(ParseContext::Value) => {
                 self.context.push(current_state.unwrap());
                 self.parse_context_value()
@@ -358,8 +1524,13 @@ impl Iterator for Parser {
 mod tests {
     use super::*;
     use super::super::lexer::{Lexer, LexToken};
+    use super::super::position::Position;
     use std::io::Cursor;
 
+    fn span(start: (usize, usize), end: (usize, usize)) -> Span {
+        Span::new(Position::at(start.0, start.1), Position::at(end.0, end.1))
+    }
+
     #[test]
     fn handle_empty_file() {
         let file = Cursor::new("".as_bytes());
@@ -374,7 +1545,7 @@ mod tests {
         let file = Cursor::new("node { }".as_bytes());
         let mut parser = Parser::parse(Lexer::lex(file));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::FileStart);
-        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("node".to_string()));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("node".to_string(), false));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeEnd);
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::FileEnd);
         assert!(parser.next().is_none());
@@ -385,8 +1556,8 @@ mod tests {
         let file = Cursor::new("node { subnode {} }".as_bytes());
         let mut parser = Parser::parse(Lexer::lex(file));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::FileStart);
-        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("node".to_string()));
-        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("subnode".to_string()));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("node".to_string(), false));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("subnode".to_string(), false));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeEnd);
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeEnd);
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::FileEnd);
@@ -395,11 +1566,11 @@ mod tests {
         let file = Cursor::new("node { subnode { sub { sub { sub {} } } } }".as_bytes());
         let mut parser = Parser::parse(Lexer::lex(file));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::FileStart);
-        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("node".to_string()));
-        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("subnode".to_string()));
-        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("sub".to_string()));
-        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("sub".to_string()));
-        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("sub".to_string()));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("node".to_string(), false));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("subnode".to_string(), false));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("sub".to_string(), false));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("sub".to_string(), false));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("sub".to_string(), false));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeEnd);
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeEnd);
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeEnd);
@@ -413,8 +1584,8 @@ mod tests {
         let file = Cursor::new("node { 'key': 'value' }".as_bytes());
         let mut parser = Parser::parse(Lexer::lex(file));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::FileStart);
-        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("node".to_string()));
-        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("key".to_string()));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("node".to_string(), false));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("key".to_string(), true));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Value(ParsedValue::Str("value".to_string())));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeEnd);
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::FileEnd);
@@ -423,8 +1594,8 @@ mod tests {
         let file = Cursor::new("node { 'key': 3 }".as_bytes());
         let mut parser = Parser::parse(Lexer::lex(file));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::FileStart);
-        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("node".to_string()));
-        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("key".to_string()));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("node".to_string(), false));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("key".to_string(), true));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Value(ParsedValue::Int(3)));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeEnd);
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::FileEnd);
@@ -433,8 +1604,8 @@ mod tests {
         let file = Cursor::new("node { 'key': 3.5 }".as_bytes());
         let mut parser = Parser::parse(Lexer::lex(file));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::FileStart);
-        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("node".to_string()));
-        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("key".to_string()));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("node".to_string(), false));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("key".to_string(), true));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Value(ParsedValue::Float(3.5)));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeEnd);
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::FileEnd);
@@ -443,8 +1614,8 @@ mod tests {
         let file = Cursor::new("node { 'key': true }".as_bytes());
         let mut parser = Parser::parse(Lexer::lex(file));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::FileStart);
-        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("node".to_string()));
-        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("key".to_string()));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("node".to_string(), false));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("key".to_string(), true));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Value(ParsedValue::Bool(true)));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeEnd);
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::FileEnd);
@@ -453,8 +1624,8 @@ mod tests {
         let file = Cursor::new("node { 'key': false }".as_bytes());
         let mut parser = Parser::parse(Lexer::lex(file));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::FileStart);
-        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("node".to_string()));
-        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("key".to_string()));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("node".to_string(), false));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("key".to_string(), true));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Value(ParsedValue::Bool(false)));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeEnd);
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::FileEnd);
@@ -463,8 +1634,8 @@ mod tests {
         let file = Cursor::new("node { 'key': !my_ident }".as_bytes());
         let mut parser = Parser::parse(Lexer::lex(file));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::FileStart);
-        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("node".to_string()));
-        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("key".to_string()));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("node".to_string(), false));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("key".to_string(), true));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Value(ParsedValue::Ident("my_ident".to_string())));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeEnd);
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::FileEnd);
@@ -473,12 +1644,80 @@ mod tests {
         let file = Cursor::new("node { 'key': null }".as_bytes());
         let mut parser = Parser::parse(Lexer::lex(file));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::FileStart);
-        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("node".to_string()));
-        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("key".to_string()));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("node".to_string(), false));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("key".to_string(), true));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Value(ParsedValue::Null));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeEnd);
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::FileEnd);
+        assert!(parser.next().is_none());
+
+        let file = Cursor::new("node { 'key': none }".as_bytes());
+        let mut parser = Parser::parse(Lexer::lex(file));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::FileStart);
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("node".to_string(), false));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("key".to_string(), true));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Value(ParsedValue::Null));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeEnd);
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::FileEnd);
         assert!(parser.next().is_none());
+
+        // any other bare identifier is not a value
+        let file = Cursor::new("node { 'key': maybe }".as_bytes());
+        let mut parser = Parser::parse(Lexer::lex(file));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::FileStart);
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("node".to_string(), false));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("key".to_string(), true));
+        assert_eq!(parser.next().unwrap().unwrap_err().0, ParseError::UnexpectedToken(LexToken::Identifier("maybe".to_string()), vec![Expected::Value]));
+        assert!(parser.next().is_none());
+    }
+
+    #[test]
+    fn handle_identifier_keys() {
+        let file = Cursor::new("node { name = 'foo', 'other' = 2, last: true }".as_bytes());
+        let mut parser = Parser::parse(Lexer::lex(file));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::FileStart);
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("node".to_string(), false));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("name".to_string(), false));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Value(ParsedValue::Str("foo".to_string())));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("other".to_string(), true));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Value(ParsedValue::Int(2)));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("last".to_string(), false));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Value(ParsedValue::Bool(true)));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeEnd);
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::FileEnd);
+        assert!(parser.next().is_none());
+
+        // identifier keys still need separating commas
+        let file = Cursor::new("node { a = 1 b = 2 }".as_bytes());
+        let mut parser = Parser::parse(Lexer::lex(file));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::FileStart);
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("node".to_string(), false));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("a".to_string(), false));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Value(ParsedValue::Int(1)));
+        assert_eq!(parser.next().unwrap().unwrap_err().0, ParseError::UnexpectedToken(LexToken::Identifier("b".to_string()), vec![Expected::CloseBrace, Expected::Comma]));
+        assert!(parser.next().is_none());
+    }
+
+    #[test]
+    fn handle_quoted_names() {
+        let file = Cursor::new("'weird node' { \"my key\" = 1, \"sub node\" { } } other {}".as_bytes());
+        let mut parser = Parser::parse(Lexer::lex(file));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::FileStart);
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("weird node".to_string(), true));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("my key".to_string(), true));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Value(ParsedValue::Int(1)));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("sub node".to_string(), true));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeEnd);
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeEnd);
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("other".to_string(), false));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeEnd);
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::FileEnd);
+        assert!(parser.next().is_none());
+
+        // a missing separator is reported at the key, not at the token after it
+        let file = Cursor::new("node { 'a': 1 'b': 2 }".as_bytes());
+        let err = Parser::parse(Lexer::lex(file)).last().unwrap().unwrap_err();
+        assert_eq!(err, (ParseError::UnexpectedToken(LexToken::StringLit("b".to_string()), vec![Expected::CloseBrace, Expected::Comma]), span((0, 14), (0, 17))));
     }
 
     #[test]
@@ -486,8 +1725,8 @@ mod tests {
         let file = Cursor::new("node { 'key': 'value 1' 'value 2' }".as_bytes());
         let mut parser = Parser::parse(Lexer::lex(file));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::FileStart);
-        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("node".to_string()));
-        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("key".to_string()));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("node".to_string(), false));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("key".to_string(), true));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Value(ParsedValue::Str("value 1value 2".to_string())));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeEnd);
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::FileEnd);
@@ -500,10 +1739,10 @@ mod tests {
         let file = Cursor::new("node { 'key1': true, 'key2': 'val' }".as_bytes());
         let mut parser = Parser::parse(Lexer::lex(file));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::FileStart);
-        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("node".to_string()));
-        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("key1".to_string()));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("node".to_string(), false));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("key1".to_string(), true));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Value(ParsedValue::Bool(true)));
-        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("key2".to_string()));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("key2".to_string(), true));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Value(ParsedValue::Str("val".to_string())));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeEnd);
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::FileEnd);
@@ -513,28 +1752,194 @@ mod tests {
         let file = Cursor::new("node { 'key1': true 'key2': 'val' }".as_bytes());
         let mut parser = Parser::parse(Lexer::lex(file));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::FileStart);
-        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("node".to_string()));
-        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("key1".to_string()));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("node".to_string(), false));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("key1".to_string(), true));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Value(ParsedValue::Bool(true)));
-        assert_eq!(parser.next().unwrap().unwrap_err().0, ParseError::UnexpectedToken(LexToken::StringLit("key2".to_string())));
+        assert_eq!(parser.next().unwrap().unwrap_err().0, ParseError::UnexpectedToken(LexToken::StringLit("key2".to_string()), vec![Expected::CloseBrace, Expected::Comma]));
         assert!(parser.next().is_none());
         let file = Cursor::new("node { 'key1': 'true' 'key2': 'val' }".as_bytes());
         let mut parser = Parser::parse(Lexer::lex(file));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::FileStart);
-        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("node".to_string()));
-        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("key1".to_string()));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("node".to_string(), false));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("key1".to_string(), true));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Value(ParsedValue::Str("truekey2".to_string())));
-        assert_eq!(parser.next().unwrap().unwrap_err().0, ParseError::UnexpectedToken(LexToken::Colon));
+        assert_eq!(parser.next().unwrap().unwrap_err().0, ParseError::UnexpectedToken(LexToken::Colon, vec![Expected::CloseBrace, Expected::Comma]));
         assert!(parser.next().is_none());
     }
 
+    fn parse_with_separators(input: &'static str, policy: SeparatorPolicy) -> Vec<ParseResult> {
+        let options = ParserOptions::new().separators(policy);
+        Parser::with_options(Lexer::lex(Cursor::new(input.as_bytes())), options).collect()
+    }
+
+    #[test]
+    fn separator_policies() {
+        let input = "node { 'a': 1, 'b': [1, 2], 'c': 3 }";
+        assert!(parse_with_separators(input, SeparatorPolicy::Required).iter().all(|r| r.is_ok()));
+        assert!(parse_with_separators(input, SeparatorPolicy::Optional).iter().all(|r| r.is_ok()));
+        assert!(parse_with_separators(input, SeparatorPolicy::Newline).iter().all(|r| r.is_ok()));
+        assert_eq!(
+            parse_with_separators(input, SeparatorPolicy::Forbidden).pop().unwrap().unwrap_err().0,
+            ParseError::UnexpectedToken(LexToken::Comma, vec![Expected::CloseBrace, Expected::NodeName, Expected::Key]));
+
+        let input = "node { 'a': 1 'b': [1 2] 'c': 3 }";
+        assert_eq!(
+            parse_with_separators(input, SeparatorPolicy::Required).pop().unwrap().unwrap_err().0,
+            ParseError::UnexpectedToken(LexToken::StringLit("b".to_string()), vec![Expected::CloseBrace, Expected::Comma]));
+        assert!(parse_with_separators(input, SeparatorPolicy::Optional).iter().all(|r| r.is_ok()));
+        assert!(parse_with_separators(input, SeparatorPolicy::Forbidden).iter().all(|r| r.is_ok()));
+        assert_eq!(
+            parse_with_separators(input, SeparatorPolicy::Newline).pop().unwrap().unwrap_err().0,
+            ParseError::UnexpectedToken(LexToken::StringLit("b".to_string()), vec![Expected::CloseBrace, Expected::Comma, Expected::Newline]));
+
+        let input = "node {
+            'a': 1
+            'b': [
+                1
+                2, 3
+            ]
+        }";
+        let events = parse_with_separators(input, SeparatorPolicy::Newline);
+        assert!(events.iter().all(|r| r.is_ok()));
+        assert_eq!(events.len(), 12);
+        assert_eq!(
+            parse_with_separators(input, SeparatorPolicy::Required).pop().unwrap().unwrap_err().0,
+            ParseError::UnexpectedToken(LexToken::StringLit("b".to_string()), vec![Expected::CloseBrace, Expected::Comma]));
+
+        let input = "node {
+            a = 1
+            b = 2
+        }";
+        assert!(parse_with_separators(input, SeparatorPolicy::Newline).iter().all(|r| r.is_ok()));
+    }
+
+    #[test]
+    fn semicolon_terminators() {
+        let input = "node { a = 1; 'b': [1, 2]; sub { c = {'d': 3;}; }; } other {};";
+        let options = ParserOptions::new().semicolons(true);
+        let events: Vec<_> = Parser::with_options(Lexer::lex(Cursor::new(input.as_bytes())), options)
+            .map(|r| r.unwrap().0)
+            .collect();
+        assert_eq!(events, vec![
+            ParseEvent::FileStart,
+            ParseEvent::NodeStart("node".to_string(), false),
+            ParseEvent::Key("a".to_string(), false),
+            ParseEvent::Value(ParsedValue::Int(1)),
+            ParseEvent::Key("b".to_string(), true),
+            ParseEvent::ListStart,
+            ParseEvent::Value(ParsedValue::Int(1)),
+            ParseEvent::Value(ParsedValue::Int(2)),
+            ParseEvent::ListEnd,
+            ParseEvent::NodeStart("sub".to_string(), false),
+            ParseEvent::Key("c".to_string(), false),
+            ParseEvent::DictStart,
+            ParseEvent::Key("d".to_string(), true),
+            ParseEvent::Value(ParsedValue::Int(3)),
+            ParseEvent::DictEnd,
+            ParseEvent::NodeEnd,
+            ParseEvent::NodeEnd,
+            ParseEvent::NodeStart("other".to_string(), false),
+            ParseEvent::NodeEnd,
+            ParseEvent::FileEnd,
+        ]);
+
+        // semicolons can't separate list items
+        let input = "node { a = [1; 2] }";
+        let options = ParserOptions::new().semicolons(true);
+        let result = Parser::with_options(Lexer::lex(Cursor::new(input.as_bytes())), options).last();
+        assert_eq!(result.unwrap().unwrap_err().0, ParseError::UnexpectedToken(LexToken::Semicolon, vec![Expected::CloseBracket, Expected::Comma]));
+
+        // and are not allowed unless enabled
+        let input = "node { a = 1; }";
+        let result = Parser::parse(Lexer::lex(Cursor::new(input.as_bytes()))).last();
+        assert_eq!(result.unwrap().unwrap_err().0, ParseError::UnexpectedToken(LexToken::Semicolon, vec![Expected::CloseBrace, Expected::Comma]));
+    }
+
+    #[test]
+    fn handle_datetime_values() {
+        let values = parse_values("node { at = 2024-01-01T12:00:00Z }", ParserOptions::new());
+        assert_eq!(values, vec![Ok((
+            ParseEvent::Value(ParsedValue::DateTime("2024-01-01T12:00:00Z".parse().unwrap())),
+            span((0, 12), (0, 32))))]);
+
+        let values = parse_values("node { at = 2024-01-01T25:00:00Z }", ParserOptions::new());
+        assert_eq!(values, vec![Err((
+            ParseError::LexError(LexError::InvalidDateTime),
+            span((0, 12), (0, 32))))]);
+    }
+
+    #[test]
+    fn handle_duration_values() {
+        let values = parse_values("node { timeout = 1h30m, retry = [500ms, 2s] }", ParserOptions::new());
+        let values: Vec<_> = values.into_iter().map(|r| r.unwrap().0).collect();
+        assert_eq!(values, vec![
+            ParseEvent::Value(ParsedValue::Duration(Duration::from_secs(5400))),
+            ParseEvent::Value(ParsedValue::Duration(Duration::from_millis(500))),
+            ParseEvent::Value(ParsedValue::Duration(Duration::from_secs(2))),
+        ]);
+
+        for input in &["1.5s", "1e3s", "1w"] {
+            let source = format!("node {{ timeout = {} }}", input);
+            let values: Vec<_> = Parser::with_options(
+                Lexer::lex(Cursor::new(source.into_bytes())), ParserOptions::new()).collect();
+            assert_eq!(values.last().unwrap(), &Err((
+                ParseError::LexError(LexError::InvalidDuration),
+                span((0, 17), (0, 17 + input.len())))), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn handle_bytes_values() {
+        let values = parse_values("node { key = base64'3q2+7w==', tag = b'\\x01ab' }", ParserOptions::new());
+        let values: Vec<_> = values.into_iter().map(|r| r.unwrap().0).collect();
+        assert_eq!(values, vec![
+            ParseEvent::Value(ParsedValue::Bytes(vec![0xde, 0xad, 0xbe, 0xef])),
+            ParseEvent::Value(ParsedValue::Bytes(vec![1, b'a', b'b'])),
+        ]);
+    }
+
+    fn parse_values(input: &'static str, options: ParserOptions) -> Vec<ParseResult> {
+        Parser::with_options(Lexer::lex(Cursor::new(input.as_bytes())), options)
+            .filter(|r| matches!(r, Ok((ParseEvent::Value(_), _)) | Err(_)))
+            .collect()
+    }
+
+    #[test]
+    fn non_finite_floats() {
+        let input = "node { a = [1.5e-3, inf, -inf, +inf, nan, 1e999, -nan] }";
+
+        let values = parse_values(input, ParserOptions::new());
+        assert_eq!(values.len(), 7);
+        assert_eq!(values[0].as_ref().unwrap().0, ParseEvent::Value(ParsedValue::Float(1.5e-3)));
+        assert_eq!(values[1].as_ref().unwrap().0, ParseEvent::Value(ParsedValue::Float(f64::INFINITY)));
+        assert_eq!(values[2].as_ref().unwrap().0, ParseEvent::Value(ParsedValue::Float(f64::NEG_INFINITY)));
+        assert_eq!(values[3].as_ref().unwrap().0, ParseEvent::Value(ParsedValue::Float(f64::INFINITY)));
+        assert!(matches!(values[4], Ok((ParseEvent::Value(ParsedValue::Float(flt)), _)) if flt.is_nan()));
+        assert_eq!(values[5].as_ref().unwrap().0, ParseEvent::Value(ParsedValue::Float(f64::INFINITY)));
+        assert!(matches!(values[6], Ok((ParseEvent::Value(ParsedValue::Float(flt)), _)) if flt.is_nan()));
+
+        let values = parse_values(input, ParserOptions::new().non_finite(NonFinitePolicy::Identifier));
+        let idents: Vec<_> = values.into_iter().skip(1).map(|r| r.unwrap().0).collect();
+        assert_eq!(idents, vec!["inf", "-inf", "inf", "nan", "inf", "nan"].into_iter()
+            .map(|s| ParseEvent::Value(ParsedValue::Ident(s.to_string())))
+            .collect::<Vec<_>>());
+
+        let values = parse_values(input, ParserOptions::new().non_finite(NonFinitePolicy::Error));
+        assert_eq!(values.len(), 2);
+        assert_eq!(values[1], Err((ParseError::NonFiniteFloat, span((0, 20), (0, 23)))));
+
+        // the policy only applies to values, not to keys or node names
+        let values = parse_values("inf { nan = 1.0 }", ParserOptions::new().non_finite(NonFinitePolicy::Error));
+        assert_eq!(values, vec![Ok((ParseEvent::Value(ParsedValue::Float(1.0)), span((0, 12), (0, 15))))]);
+    }
+
     #[test]
     fn handle_list_values() {
         let file = Cursor::new("node { 'key': ['val1', 2, 3.4, false, !ident] }".as_bytes());
         let mut parser = Parser::parse(Lexer::lex(file));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::FileStart);
-        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("node".to_string()));
-        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("key".to_string()));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("node".to_string(), false));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("key".to_string(), true));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::ListStart);
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Value(ParsedValue::Str("val1".to_string())));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Value(ParsedValue::Int(2)));
@@ -552,8 +1957,8 @@ mod tests {
         let file = Cursor::new("node { 'key': ['lista', ['listb', []]] }".as_bytes());
         let mut parser = Parser::parse(Lexer::lex(file));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::FileStart);
-        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("node".to_string()));
-        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("key".to_string()));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("node".to_string(), false));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("key".to_string(), true));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::ListStart);
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Value(ParsedValue::Str("lista".to_string())));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::ListStart);
@@ -567,18 +1972,88 @@ mod tests {
         assert!(parser.next().is_none());
     }
 
+    #[test]
+    fn handle_lists_of_containers() {
+        let file = Cursor::new("node { 'key': [{'a': 1}, {'b': [2]}], 'after': [] }".as_bytes());
+        let mut parser = Parser::parse(Lexer::lex(file));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::FileStart);
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("node".to_string(), false));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("key".to_string(), true));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::ListStart);
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::DictStart);
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("a".to_string(), true));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Value(ParsedValue::Int(1)));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::DictEnd);
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::DictStart);
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("b".to_string(), true));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::ListStart);
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Value(ParsedValue::Int(2)));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::ListEnd);
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::DictEnd);
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::ListEnd);
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("after".to_string(), true));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::ListStart);
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::ListEnd);
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeEnd);
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::FileEnd);
+        assert!(parser.next().is_none());
+    }
+
+    #[test]
+    fn requires_comma_between_list_items() {
+        let file = Cursor::new("node { 'key': [1 2] }".as_bytes());
+        let mut parser = Parser::parse(Lexer::lex(file));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::FileStart);
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("node".to_string(), false));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("key".to_string(), true));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::ListStart);
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Value(ParsedValue::Int(1)));
+        assert_eq!(parser.next().unwrap().unwrap_err().0, ParseError::UnexpectedToken(LexToken::IntegerLit(2), vec![Expected::CloseBracket, Expected::Comma]));
+        assert!(parser.next().is_none());
+
+        let file = Cursor::new("node { 'key': [, 1] }".as_bytes());
+        let mut parser = Parser::parse(Lexer::lex(file));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::FileStart);
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("node".to_string(), false));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("key".to_string(), true));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::ListStart);
+        assert_eq!(parser.next().unwrap().unwrap_err().0, ParseError::UnexpectedToken(LexToken::Comma, vec![Expected::CloseBracket, Expected::Value]));
+        assert!(parser.next().is_none());
+    }
+
+    #[test]
+    fn peeked_errors_are_not_lost() {
+        let file = Cursor::new("node { 'key' }".as_bytes());
+        let mut parser = Parser::parse(Lexer::lex(file));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::FileStart);
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("node".to_string(), false));
+        assert!(parser.peek().unwrap().is_err());
+        assert_eq!(parser.next().unwrap().unwrap_err().0, ParseError::UnexpectedToken(LexToken::CloseBrace, vec![Expected::OpenBrace, Expected::Colon, Expected::Equals]));
+        assert!(parser.next().is_none());
+    }
+
+    #[test]
+    fn error_positions_after_comments() {
+        let file = Cursor::new("/* a long\n comment /* with\n nesting */\n */ node { 'key' 3 }".as_bytes());
+        let mut parser = Parser::parse(Lexer::lex(file));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::FileStart);
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("node".to_string(), false));
+        assert_eq!(parser.next().unwrap().unwrap_err(),
+            (ParseError::UnexpectedToken(LexToken::IntegerLit(3), vec![Expected::OpenBrace, Expected::Colon, Expected::Equals]), span((3, 17), (3, 18))));
+    }
+
     #[test]
     fn trailing_commas() {
         let file = Cursor::new("node { 'key': [1, 2,], subnode {} }".as_bytes());
         let mut parser = Parser::parse(Lexer::lex(file));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::FileStart);
-        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("node".to_string()));
-        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("key".to_string()));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("node".to_string(), false));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("key".to_string(), true));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::ListStart);
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Value(ParsedValue::Int(1)));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Value(ParsedValue::Int(2)));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::ListEnd);
-        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("subnode".to_string()));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("subnode".to_string(), false));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeEnd);
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeEnd);
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::FileEnd);
@@ -587,8 +2062,8 @@ mod tests {
         let file = Cursor::new("node { , }".as_bytes());
         let mut parser = Parser::parse(Lexer::lex(file));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::FileStart);
-        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("node".to_string()));
-        assert_eq!(parser.next().unwrap().unwrap_err().0, ParseError::UnexpectedToken(LexToken::Comma));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("node".to_string(), false));
+        assert_eq!(parser.next().unwrap().unwrap_err().0, ParseError::UnexpectedToken(LexToken::Comma, vec![Expected::CloseBrace, Expected::NodeName, Expected::Key]));
         assert!(parser.next().is_none());
     }
 
@@ -597,12 +2072,12 @@ mod tests {
         let file = Cursor::new("node { 'key': {'1': 2, '3': 4} }".as_bytes());
         let mut parser = Parser::parse(Lexer::lex(file));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::FileStart);
-        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("node".to_string()));
-        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("key".to_string()));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("node".to_string(), false));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("key".to_string(), true));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::DictStart);
-        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("1".to_string()));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("1".to_string(), true));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Value(ParsedValue::Int(2)));
-        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("3".to_string()));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("3".to_string(), true));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Value(ParsedValue::Int(4)));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::DictEnd);
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeEnd);
@@ -610,17 +2085,45 @@ mod tests {
         assert!(parser.next().is_none());
     }
 
+    #[test]
+    fn handle_inline_object_values() {
+        let file = Cursor::new("node { server = { host = 'x', port = 1 } }".as_bytes());
+        let mut parser = Parser::parse(Lexer::lex(file));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::FileStart);
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("node".to_string(), false));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("server".to_string(), false));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::DictStart);
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("host".to_string(), false));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Value(ParsedValue::Str("x".to_string())));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("port".to_string(), false));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Value(ParsedValue::Int(1)));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::DictEnd);
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeEnd);
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::FileEnd);
+        assert!(parser.next().is_none());
+
+        // named nodes are not allowed inside anonymous objects
+        let file = Cursor::new("node { 'key': { sub {} } }".as_bytes());
+        let mut parser = Parser::parse(Lexer::lex(file));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::FileStart);
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("node".to_string(), false));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("key".to_string(), true));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::DictStart);
+        assert_eq!(parser.next().unwrap().unwrap_err().0, ParseError::UnexpectedToken(LexToken::OpenBrace, vec![Expected::Colon, Expected::Equals]));
+        assert!(parser.next().is_none());
+    }
+
     #[test]
     fn handle_nested_dicts() {
         let file = Cursor::new("node { 'key': {'1': {'b': {} } } }".as_bytes());
         let mut parser = Parser::parse(Lexer::lex(file));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::FileStart);
-        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("node".to_string()));
-        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("key".to_string()));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("node".to_string(), false));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("key".to_string(), true));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::DictStart);
-        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("1".to_string()));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("1".to_string(), true));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::DictStart);
-        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("b".to_string()));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::Key("b".to_string(), true));
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::DictStart);
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::DictEnd);
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::DictEnd);
@@ -629,4 +2132,402 @@ mod tests {
         assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::FileEnd);
         assert!(parser.next().is_none());
     }
+
+    fn parse_events_with_spans(input: &'static str, options: ParserOptions) -> Vec<ParseResult> {
+        Parser::with_options(Lexer::lex(Cursor::new(input.as_bytes())), options).collect()
+    }
+
+    fn parse_events(input: &'static str, options: ParserOptions) -> Vec<Result<ParseEvent, ParseError>> {
+        Parser::with_options(Lexer::lex(Cursor::new(input.as_bytes())), options)
+            .map(|r| r.map(|(event, _)| event).map_err(|(err, _)| err))
+            .collect()
+    }
+
+    #[test]
+    fn recover_from_errors() {
+        let options = ParserOptions::new().recover(true);
+        let node = |name: &str| Ok(ParseEvent::NodeStart(name.to_string(), false));
+        let key = |name: &str| Ok(ParseEvent::Key(name.to_string(), false));
+
+        // skip to the end of the node, then carry on with the next one
+        let events = parse_events("a { x = 1 2, y = 3 } b { z = }\nc { }", options.clone());
+        assert_eq!(events, vec![
+            Ok(ParseEvent::FileStart),
+            node("a"), key("x"), Ok(ParseEvent::Value(ParsedValue::Int(1))),
+            Err(ParseError::UnexpectedToken(LexToken::IntegerLit(2), vec![Expected::CloseBrace, Expected::Comma])),
+            Ok(ParseEvent::NodeEnd),
+            node("b"), key("z"),
+            Err(ParseError::UnexpectedToken(LexToken::CloseBrace, vec![Expected::Value])),
+            Ok(ParseEvent::NodeEnd),
+            node("c"), Ok(ParseEvent::NodeEnd),
+            Ok(ParseEvent::FileEnd),
+        ]);
+
+        // containers left open are closed, and nested braces are skipped over
+        let events = parse_events("a { x = [1 { y = 2 } ], w = 1 }", options.clone());
+        assert_eq!(events, vec![
+            Ok(ParseEvent::FileStart),
+            node("a"), key("x"), Ok(ParseEvent::ListStart), Ok(ParseEvent::Value(ParsedValue::Int(1))),
+            Err(ParseError::UnexpectedToken(LexToken::OpenBrace, vec![Expected::CloseBracket, Expected::Comma])),
+            Ok(ParseEvent::ListEnd),
+            key("w"), Ok(ParseEvent::Value(ParsedValue::Int(1))),
+            Ok(ParseEvent::NodeEnd),
+            Ok(ParseEvent::FileEnd),
+        ]);
+
+        // errors at the top level skip to the next identifier
+        let events = parse_events("12 a b { } 'c' { ", options.clone());
+        assert_eq!(events, vec![
+            Ok(ParseEvent::FileStart),
+            Err(ParseError::UnexpectedToken(LexToken::IntegerLit(12), vec![Expected::NodeName])),
+            Err(ParseError::UnexpectedToken(LexToken::Identifier("b".to_string()), vec![Expected::OpenBrace])),
+            node("b"), Ok(ParseEvent::NodeEnd),
+            Ok(ParseEvent::NodeStart("c".to_string(), true)),
+            Err(ParseError::UnexpectedEndOfFile),
+            Ok(ParseEvent::NodeEnd),
+            Ok(ParseEvent::FileEnd),
+        ]);
+
+        // without recovery, parsing stops at the first error
+        let events = parse_events("a { x = } b { }", ParserOptions::new());
+        assert_eq!(events.last(), Some(&Err(ParseError::UnexpectedToken(LexToken::CloseBrace, vec![Expected::Value]))));
+    }
+
+    #[test]
+    fn collect_all_errors() {
+        let (events, errors) = Parser::parse_collecting(Cursor::new("a { x = ] }\n'b' { y = [1 2] }"));
+        assert_eq!(events.iter().map(|e| &e.0).collect::<Vec<_>>(), vec![
+            &ParseEvent::FileStart,
+            &ParseEvent::NodeStart("a".to_string(), false),
+            &ParseEvent::Key("x".to_string(), false),
+            &ParseEvent::NodeEnd,
+            &ParseEvent::NodeStart("b".to_string(), true),
+            &ParseEvent::Key("y".to_string(), false),
+            &ParseEvent::ListStart,
+            &ParseEvent::Value(ParsedValue::Int(1)),
+            &ParseEvent::ListEnd,
+            &ParseEvent::NodeEnd,
+            &ParseEvent::FileEnd,
+        ]);
+        assert_eq!(errors, vec![
+            (ParseError::UnexpectedToken(LexToken::CloseBracket, vec![Expected::Value]), span((0, 8), (0, 9))),
+            (ParseError::UnexpectedToken(LexToken::IntegerLit(2), vec![Expected::CloseBracket, Expected::Comma]), span((1, 13), (1, 14))),
+        ]);
+
+        let (events, errors) = Parser::parse_collecting(Cursor::new("a { }"));
+        assert_eq!(events.len(), 4);
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn resource_limits() {
+        let options = ParserOptions::new().max_events(4).recover(true);
+        let events = parse_events("a { b { } } c { }", options);
+        assert_eq!(events, vec![
+            Ok(ParseEvent::FileStart),
+            Ok(ParseEvent::NodeStart("a".to_string(), false)),
+            Ok(ParseEvent::NodeStart("b".to_string(), false)),
+            Ok(ParseEvent::NodeEnd),
+            Err(ParseError::TooManyEvents),
+        ]);
+
+        let events = parse_events("'long node' { }", ParserOptions::new().max_token_length(4));
+        assert_eq!(events.last(), Some(&Err(ParseError::LexError(LexError::TokenTooLong))));
+
+        let options = ParserOptions::new().max_document_size(10);
+        assert_eq!(parse_events("node { a = 1 }", options.clone()).last(),
+            Some(&Err(ParseError::LexError(LexError::DocumentTooLarge))));
+        assert_eq!(parse_events("node {}", options).last(), Some(&Ok(ParseEvent::FileEnd)));
+    }
+
+    #[test]
+    fn peek_ahead() {
+        let mut parser = Parser::parse(Lexer::lex(Cursor::new("node { a = } b".as_bytes())));
+        assert_eq!(parser.peek_n(1).unwrap().as_ref().unwrap().0,
+            ParseEvent::NodeStart("node".to_string(), false));
+        assert_eq!(parser.peek_n(3).unwrap().as_ref().unwrap_err().0,
+            ParseError::UnexpectedToken(LexToken::CloseBrace, vec![Expected::Value]));
+        assert!(parser.peek_n(4).is_none());
+        assert_eq!(parser.peek().unwrap().as_ref().unwrap().0, ParseEvent::FileStart);
+
+        let events: Vec<_> = parser.collect();
+        assert_eq!(events.len(), 4);
+        assert_eq!(events[2].as_ref().unwrap().0, ParseEvent::Key("a".to_string(), false));
+    }
+
+    #[test]
+    fn event_spans() {
+        let file = Cursor::new("node {\n  'key': 'a' 'b',\n  list = [1, !x],\n  sub {}\n}".as_bytes());
+        let spans: Vec<_> = Parser::parse(Lexer::lex(file)).map(|r| r.unwrap()).collect();
+        assert_eq!(spans, vec![
+            (ParseEvent::FileStart, span((0, 0), (0, 0))),
+            (ParseEvent::NodeStart("node".to_string(), false), span((0, 0), (0, 6))),
+            (ParseEvent::Key("key".to_string(), true), span((1, 2), (1, 8))),
+            (ParseEvent::Value(ParsedValue::Str("ab".to_string())), span((1, 9), (1, 16))),
+            (ParseEvent::Key("list".to_string(), false), span((2, 2), (2, 8))),
+            (ParseEvent::ListStart, span((2, 9), (2, 10))),
+            (ParseEvent::Value(ParsedValue::Int(1)), span((2, 10), (2, 11))),
+            (ParseEvent::Value(ParsedValue::Ident("x".to_string())), span((2, 13), (2, 15))),
+            (ParseEvent::ListEnd, span((2, 15), (2, 16))),
+            (ParseEvent::NodeStart("sub".to_string(), false), span((3, 2), (3, 7))),
+            (ParseEvent::NodeEnd, span((3, 7), (3, 8))),
+            (ParseEvent::NodeEnd, span((4, 0), (4, 1))),
+            (ParseEvent::FileEnd, span((4, 1), (4, 1))),
+        ]);
+    }
+
+    #[test]
+    fn tab_width() {
+        let input = "node {\n\tkey = [\t1,\t2 3] }";
+        let error = |options| parse_events_with_spans(input, options).pop().unwrap().unwrap_err();
+        assert_eq!(error(ParserOptions::new()).1, span((1, 14), (1, 15)));
+        assert_eq!(error(ParserOptions::new().tab_width(4)).1, span((1, 18), (1, 19)));
+        assert_eq!(error(ParserOptions::new().tab_width(8)).1, span((1, 26), (1, 27)));
+    }
+
+    #[test]
+    fn windows_line_endings() {
+        let unix = "node {\n  text = '''\n    a\n    b\n  ''',\n  list = [1,\n    2 3]\n}";
+        let windows = Cursor::new(unix.replace('\n', "\r\n").into_bytes());
+        let options = ParserOptions::new().normalize_newlines(true);
+        let events: Vec<_> = Parser::with_options(Lexer::lex(windows), options.clone()).collect();
+        assert_eq!(events, parse_events_with_spans(unix, options));
+    }
+
+    #[test]
+    fn convenience_constructors() {
+        let events: Vec<_> = Parser::from_bytes(b"node { a = 1 }").map(|r| r.unwrap().0).collect();
+        let expected: Vec<_> = Parser::parse(Lexer::lex(Cursor::new("node { a = 1 }")))
+            .map(|r| r.unwrap().0).collect();
+        assert_eq!(events, expected);
+
+        let events: Vec<_> = Parser::from_reader(Cursor::new("node { a = 1 }")).map(|r| r.unwrap().0).collect();
+        assert_eq!(events, expected);
+
+        assert!(Parser::from_path("tests/resources/does-not-exist.ft").is_err());
+    }
+
+    #[test]
+    fn skip_subtree() {
+        let input = "a { x = [1, { y: 2 }], b { } } c { z = [1, 2, 3], w = 4 } d {}";
+        let rest = |parser: Parser| -> Vec<_> { parser.map(|r| r.unwrap().0).collect() };
+
+        // skip a whole node
+        let mut parser = Parser::parse(Lexer::lex(Cursor::new(input.as_bytes())));
+        parser.nth(1);
+        assert_eq!(parser.skip_subtree(), Ok(()));
+        assert_eq!(parser.next().unwrap().unwrap(),
+            (ParseEvent::NodeStart("c".to_string(), false), span((0, 31), (0, 34))));
+
+        // skip the rest of a list, then carry on in the node around it
+        let mut parser = Parser::parse(Lexer::lex(Cursor::new(input.as_bytes())));
+        parser.nth(16);
+        assert_eq!(parser.skip_subtree(), Ok(()));
+        assert_eq!(rest(parser), vec![
+            ParseEvent::Key("w".to_string(), false),
+            ParseEvent::Value(ParsedValue::Int(4)),
+            ParseEvent::NodeEnd,
+            ParseEvent::NodeStart("d".to_string(), false),
+            ParseEvent::NodeEnd,
+            ParseEvent::FileEnd,
+        ]);
+
+        // events that have been peeked at are skipped too
+        let mut parser = Parser::parse(Lexer::lex(Cursor::new(input.as_bytes())));
+        parser.nth(1);
+        parser.peek_n(4);
+        assert_eq!(parser.skip_subtree(), Ok(()));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("c".to_string(), false));
+
+        // nothing is skipped outside of a node
+        let mut parser = Parser::parse(Lexer::lex(Cursor::new(input.as_bytes())));
+        parser.next();
+        assert_eq!(parser.skip_subtree(), Ok(()));
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("a".to_string(), false));
+
+        // the subtree must be closed
+        let mut parser = Parser::parse(Lexer::lex(Cursor::new("a { b = 1 ]".as_bytes())));
+        parser.nth(1);
+        assert_eq!(parser.skip_subtree(),
+            Err((ParseError::UnexpectedToken(LexToken::CloseBracket, vec![Expected::CloseBrace]), span((0, 10), (0, 11)))));
+        assert!(parser.next().is_none());
+        let mut parser = Parser::parse(Lexer::lex(Cursor::new("a { b { }".as_bytes())));
+        parser.nth(1);
+        assert_eq!(parser.skip_subtree().unwrap_err().0, ParseError::UnexpectedEndOfFile);
+    }
+
+    #[test]
+    fn depths() {
+        let input = "a { b { c = { d: [1] } } } e { x = ] }";
+        let parser = Parser::with_options(Lexer::lex(Cursor::new(input.as_bytes())),
+            ParserOptions::new().recover(true));
+        let depths: Vec<_> = parser.with_depth().map(|(_, depth)| depth).collect();
+        assert_eq!(depths, vec![0, 0, 1, 2, 2, 3, 3, 4, 3, 2, 1, 0, 0, 1, 1, 0, 0]);
+
+        // peeking doesn't change the depth, but skipping does
+        let mut parser = Parser::parse(Lexer::lex(Cursor::new(input.as_bytes())));
+        parser.nth(2);
+        assert_eq!(parser.depth(), 2);
+        parser.peek_n(3);
+        assert_eq!(parser.depth(), 2);
+        parser.skip_subtree().unwrap();
+        assert_eq!(parser.depth(), 1);
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeEnd);
+        assert_eq!(parser.depth(), 0);
+    }
+
+    #[test]
+    fn node_arguments() {
+        let input = "listen 8080 'x' 'y' !ident true {\n  a = 1\n  sub null { }\n}\n\
+                     plain { } end -1.5 2s { }";
+        let options = ParserOptions::new().node_arguments(true);
+        let events: Vec<_> = Parser::with_options(Lexer::lex(Cursor::new(input)), options.clone())
+            .map(|result| result.unwrap())
+            .collect();
+        assert_eq!(events.iter().map(|event| event.0.clone()).collect::<Vec<_>>(), vec![
+            ParseEvent::FileStart,
+            ParseEvent::NodeStart("listen".to_string(), false),
+            ParseEvent::Argument(ParsedValue::Int(8080)),
+            ParseEvent::Argument(ParsedValue::Str("xy".to_string())),
+            ParseEvent::Argument(ParsedValue::Ident("ident".to_string())),
+            ParseEvent::Argument(ParsedValue::Bool(true)),
+            ParseEvent::Key("a".to_string(), false),
+            ParseEvent::Value(ParsedValue::Int(1)),
+            ParseEvent::NodeStart("sub".to_string(), false),
+            ParseEvent::Argument(ParsedValue::Null),
+            ParseEvent::NodeEnd,
+            ParseEvent::NodeEnd,
+            ParseEvent::NodeStart("plain".to_string(), false),
+            ParseEvent::NodeEnd,
+            ParseEvent::NodeStart("end".to_string(), false),
+            ParseEvent::Argument(ParsedValue::Float(-1.5)),
+            ParseEvent::Argument(ParsedValue::Duration(::std::time::Duration::from_secs(2))),
+            ParseEvent::NodeEnd,
+            ParseEvent::FileEnd,
+        ]);
+        // a node with arguments starts at its name
+        assert_eq!(events[1].1, span((0, 0), (0, 6)));
+        assert_eq!(events[2].1, span((0, 7), (0, 11)));
+
+        for input in &["node 1, 2 {}", "node [1] {}", "node 1 2", "node { a 1 = 2 }"] {
+            let lexer = Lexer::lex(Cursor::new(*input));
+            let results: Vec<_> = Parser::with_options(lexer, options.clone()).collect();
+            assert!(results.last().unwrap().is_err(), "{:?} should fail", input);
+        }
+        let results: Vec<_> = Parser::parse(Lexer::lex(Cursor::new("node 1 {}"))).collect();
+        assert_eq!(results[1],
+            Err((ParseError::UnexpectedToken(LexToken::IntegerLit(1), vec![Expected::OpenBrace]), span((0, 5), (0, 6)))));
+    }
+
+    #[test]
+    fn skip_subtree_with_arguments() {
+        let options = ParserOptions::new().node_arguments(true);
+        let lexer = Lexer::lex(Cursor::new("a 1 2 { b = { } } c {}"));
+        let mut parser = Parser::with_options(lexer, options.clone());
+        assert_eq!(parser.nth(1).unwrap().unwrap().0, ParseEvent::NodeStart("a".to_string(), false));
+        parser.skip_subtree().unwrap();
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("c".to_string(), false));
+
+        let mut parser = Parser::with_options(Lexer::lex(Cursor::new("a 1 2 { } c {}")), options);
+        assert_eq!(parser.nth(2).unwrap().unwrap().0, ParseEvent::Argument(ParsedValue::Int(1)));
+        parser.skip_subtree().unwrap();
+        assert_eq!(parser.next().unwrap().unwrap().0, ParseEvent::NodeStart("c".to_string(), false));
+        assert_eq!(parser.depth(), 1);
+    }
+
+    #[test]
+    fn display_errors() {
+        let error = |input: &str| {
+            let results: Vec<_> = Parser::parse(Lexer::lex(Cursor::new(input.to_string())))
+                .collect();
+            results.into_iter().find_map(Result::err).unwrap().0
+        };
+        assert_eq!(error("node {").to_string(), "unexpected end of file");
+        assert_eq!(error("node 1 {}").to_string(), "unexpected integer 1, expected `{`");
+        assert_eq!(error("node { a: } }").to_string(), "unexpected `}`, expected a value");
+        assert_eq!(error("node { a }").to_string(), "unexpected `}`, expected `{`, `:` or `=`");
+        assert_eq!(error("node { a = 1 b = 2 }").to_string(),
+                   "unexpected identifier `b`, expected `}` or `,`");
+        let unexpected = ParseError::UnexpectedToken(LexToken::Comma, vec![]);
+        assert_eq!(unexpected.to_string(), "unexpected `,`");
+        assert_eq!(error("node { a: \"\\q\" }").to_string(), "invalid token: invalid escape `\\q`");
+        let duplicate = ParseError::DuplicateKey("a".into(), span((1, 2), (1, 3)));
+        assert_eq!(duplicate.to_string(), "duplicate key \"a\" (first used at line 2, column 3)");
+
+        let error = error("node { a: 0x }");
+        let source = error.source().unwrap();
+        assert_eq!(source.to_string(), "integer prefix is not followed by any digits");
+        assert!(source.source().is_none());
+        let parse_error = "x".parse::<i64>().unwrap_err();
+        let error = ParseError::LexError(LexError::IntegerParseError(parse_error));
+        assert_eq!(error.source().and_then(Error::source).unwrap().to_string(),
+                   "invalid digit found in string");
+    }
+
+    #[test]
+    fn error_codes() {
+        let lex_errors = [
+            LexError::UnclosedCommentError, LexError::UnclosedStringError,
+            LexError::UnclosedIdentError, LexError::NewlineInIdentifier,
+            LexError::InvalidEscape('q'), LexError::InvalidUnicodeEscape(0xd800),
+            LexError::MalformedUnicodeEscape,
+            LexError::FloatParseError("x".parse::<f64>().unwrap_err()),
+            LexError::IntegerParseError("x".parse::<i64>().unwrap_err()),
+            LexError::InvalidDigit('g'), LexError::MissingDigits, LexError::InvalidDigitSeparator,
+            LexError::InvalidDateTime, LexError::InvalidDuration, LexError::InvalidByte('é'),
+            LexError::InvalidBase64('!'), LexError::UnrecognisedCharError('$'),
+            LexError::InvalidUtf8, LexError::UnsupportedEncoding("UTF-16LE"),
+            LexError::IoError(io::ErrorKind::Other), LexError::DecodeError("Shift_JIS"),
+            LexError::TokenTooLong, LexError::DocumentTooLarge,
+        ];
+        let mut codes: Vec<_> = lex_errors.iter().map(LexError::code).collect();
+        assert_eq!(codes[0], "E0101");
+        assert_eq!(codes[22], "E0123");
+        let parse_errors = [
+            ParseError::UnexpectedToken(LexToken::Comma, vec![]), ParseError::UnexpectedEndOfFile,
+            ParseError::RepeatedNode("a".into()), ParseError::NonFiniteFloat,
+            ParseError::TooManyEvents, ParseError::DuplicateKey("a".into(), span((0, 0), (0, 1))),
+        ];
+        codes.extend(parse_errors.iter().map(ParseError::code));
+        assert_eq!(codes[23], "E0001");
+        let count = codes.len();
+        codes.sort();
+        codes.dedup();
+        assert_eq!(codes.len(), count);
+
+        let error = ParseError::LexError(LexError::InvalidDuration);
+        assert_eq!(error.code(), "E0114");
+    }
+
+    #[test]
+    fn collects_warnings() {
+        let input = "a { x = 1, y = none, d = { x = 1, x = [none] }, x = 'p'\n  'q' 'r', \
+                     b { x = 2 } }";
+        let options = ParserOptions::new().warnings(true);
+        let mut parser = Parser::with_options(Lexer::lex(Cursor::new(input)), options.clone());
+        assert!(parser.by_ref().all(|result| result.is_ok()));
+        assert_eq!(parser.take_warnings(), vec![
+            (ParseWarning::DuplicateKey("x".into(), span((0, 27), (0, 30))),
+             span((0, 34), (0, 37))),
+            (ParseWarning::DuplicateKey("x".into(), span((0, 4), (0, 7))),
+             span((0, 48), (0, 51))),
+            (ParseWarning::SplitString, span((1, 2), (1, 5))),
+        ]);
+        assert!(parser.take_warnings().is_empty());
+        assert_eq!(ParseWarning::SplitString.code(), "W0002");
+        assert_eq!(ParseWarning::SplitString.to_string(),
+                   "string on a new line is joined onto the previous string");
+
+        // warnings are only kept when they've been asked for
+        let mut parser = Parser::parse(Lexer::lex(Cursor::new(input)));
+        assert_eq!(parser.by_ref().count(), 23);
+        assert!(parser.take_warnings().is_empty());
+
+        // and don't stop the parse alongside errors
+        let options = options.recover(true);
+        let lexer = Lexer::lex(Cursor::new("a { x = 1, x = 2, y = }"));
+        let mut parser = Parser::with_options(lexer, options);
+        assert_eq!(parser.by_ref().filter(Result::is_err).count(), 1);
+        assert_eq!(parser.take_warnings().len(), 1);
+    }
 }
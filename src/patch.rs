@@ -0,0 +1,641 @@
+use std::error::Error;
+use std::fmt;
+
+use super::diff::{diff, ChangeKind};
+use super::hooks::Mutation;
+use super::path::{node_at, node_at_mut, split_last};
+use super::types::{Dict, Document, Node, Value};
+
+/// Something that a patch adds to a document, or replaces an entry with
+#[derive(Debug, PartialEq, Clone)]
+pub enum PatchEntry {
+    Value(Value),
+    Node(Box<Node>),
+}
+
+/// A single change in a `Patch`.  Paths are written in the same form as the paths used
+/// by `Document::get_path`, except that they can also lead to nodes.  A path can't end
+/// in a list index: lists are changed as a whole.
+#[derive(Debug, PartialEq, Clone)]
+pub enum PatchOp {
+    /// Add an entry at a path.  A value can only be added where there isn't one yet.  A
+    /// node is added after any other nodes with the same name, and is given the name at
+    /// the end of the path - an index there must be the number of nodes with that name.
+    Add(String, PatchEntry),
+    /// Remove the entry at a path
+    Remove(String),
+    /// Replace the entry at a path.  A replacement node is given the name of the node it
+    /// replaces.
+    Replace(String, PatchEntry),
+}
+
+impl PatchOp {
+    /// The path that this operation changes
+    pub fn path(&self) -> &str {
+        match *self {
+            PatchOp::Add(ref path, _) | PatchOp::Remove(ref path) | PatchOp::Replace(ref path, _) =>
+                path,
+        }
+    }
+
+    /// The change that this operation makes, to ask a document's mutation hooks about
+    pub(crate) fn mutation(&self) -> Mutation<'_> {
+        match *self {
+            PatchOp::Add(ref path, ref entry) | PatchOp::Replace(ref path, ref entry) =>
+                match *entry {
+                    PatchEntry::Value(ref value) => Mutation::SetValue(path, value),
+                    PatchEntry::Node(ref node) => Mutation::SetNode(path, node),
+                },
+            PatchOp::Remove(ref path) => Mutation::Remove(path),
+        }
+    }
+}
+
+/// A list of changes that can be applied to a document with `Document::apply_patch`, so
+/// that only the changes to a config file need to be sent around.
+///
+/// A patch can be turned into a document with `to_document`, and read back with
+/// `from_document`, so it can be stored and sent as a figtree file.  Each operation is
+/// a node called `add`, `remove` or `replace`, with a `path` attribute.  The entry being
+/// added is either a `value` attribute, or the only subnode:
+///
+/// ```text
+/// replace { path = 'server.port', value = 8080 }
+/// add { path = 'upstream[1]', upstream { host = 'b' } }
+/// remove { path = 'server.debug' }
+/// ```
+///
+/// Patch documents usually repeat node names, so they must be parsed with
+/// `ParserOptions::repeated_nodes`.
+///
+/// # Examples
+/// ```
+/// # use figtree::types::*;
+/// # use figtree::Patch;
+/// # use std::io::Cursor;
+/// let old = Document::parse(Cursor::new("server { host = 'a', port = 80 }")).unwrap();
+/// let new = Document::parse(Cursor::new("server { port = 8080 } log {}")).unwrap();
+/// let patch = Patch::between(&old, &new);
+///
+/// let mut doc = old.clone();
+/// doc.apply_patch(&patch).unwrap();
+/// assert_eq!(doc, new);
+/// assert_eq!(Patch::from_document(&patch.to_document()), Ok(patch));
+/// ```
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Patch {
+    pub ops: Vec<PatchOp>,
+}
+
+impl Patch {
+    /// Construct an empty patch
+    pub fn new() -> Self {
+        Patch { ops: Vec::new() }
+    }
+
+    /// Construct the patch that turns `first` into `second`, from the changes found by
+    /// `diff`.  A node with different arguments is replaced as a whole.
+    pub fn between(first: &Document, second: &Document) -> Self {
+        let mut ops = Vec::new();
+        let mut removals = Vec::new();
+        // the last node to be replaced, whose changed contents are already in the patch
+        let mut replaced: Option<String> = None;
+        for change in diff(first, second) {
+            if let Some(ref replaced) = replaced {
+                if change.path.starts_with(replaced)
+                    && change.path[replaced.len()..].starts_with('.') {
+                    continue;
+                }
+            }
+            match change.kind {
+                ChangeKind::Added => if let Some(entry) = entry_at(second, &change.path) {
+                    ops.push(PatchOp::Add(change.path, entry));
+                },
+                ChangeKind::Removed => removals.push(PatchOp::Remove(change.path)),
+                ChangeKind::Modified => if let Some(entry) = entry_at(second, &change.path) {
+                    if let PatchEntry::Node(_) = entry {
+                        replaced = Some(change.path.clone());
+                    }
+                    ops.push(PatchOp::Replace(change.path, entry));
+                },
+            }
+        }
+        // later repeated nodes are removed first, so the indexes of earlier ones are
+        // still right
+        ops.extend(removals.into_iter().rev());
+        Patch { ops }
+    }
+
+    /// Add an operation to the end of the patch
+    pub fn push(&mut self, op: PatchOp) -> &mut Self {
+        self.ops.push(op);
+        self
+    }
+
+    /// Write the patch as a document.  See `Patch` for the format.
+    pub fn to_document(&self) -> Document {
+        let mut document = Document::new();
+        for op in &self.ops {
+            let (name, entry) = match *op {
+                PatchOp::Add(_, ref entry) => ("add", Some(entry)),
+                PatchOp::Remove(_) => ("remove", None),
+                PatchOp::Replace(_, ref entry) => ("replace", Some(entry)),
+            };
+            let node = document.append_node(name, Node::new());
+            node.insert_attr("path", Value::new_string(op.path()));
+            match entry {
+                Some(PatchEntry::Value(value)) => { node.insert_attr("value", value.clone()); },
+                Some(PatchEntry::Node(child)) => { node.adopt((**child).clone()); },
+                None => {},
+            }
+        }
+        document
+    }
+
+    /// Read a patch from a document written in the format described for `Patch`.
+    ///
+    /// # Failures
+    /// Fails with `PatchError::InvalidOperation` if a node in the document isn't an
+    /// operation, or is missing its path or entry.
+    pub fn from_document(document: &Document) -> Result<Self, PatchError> {
+        let ops = document.children().enumerate().map(|(index, node)| {
+            let invalid = || PatchError::InvalidOperation(index);
+            let path = node.get("path").and_then(Value::get_str).ok_or_else(invalid)?.to_string();
+            let entry = match (node.get("value"), node.children().as_slice()) {
+                (Some(value), []) => Some(PatchEntry::Value(value.clone())),
+                (None, [child]) => Some(PatchEntry::Node(Box::new(child.clone()))),
+                _ => None,
+            };
+            match (node.name(), entry) {
+                ("add", Some(entry)) => Ok(PatchOp::Add(path, entry)),
+                ("remove", _) => Ok(PatchOp::Remove(path)),
+                ("replace", Some(entry)) => Ok(PatchOp::Replace(path, entry)),
+                _ => Err(invalid()),
+            }
+        });
+        Ok(Patch { ops: ops.collect::<Result<_, _>>()? })
+    }
+}
+
+/// The error produced when a patch can't be applied or read.  Contains the path of the
+/// operation that failed, or for `InvalidOperation` the index of the node that isn't an
+/// operation.
+#[derive(Debug, PartialEq, Clone)]
+pub enum PatchError {
+    /// The path is malformed, or leads somewhere the entry can't go
+    InvalidPath(String),
+    /// There is nothing at the path to remove or replace
+    Missing(String),
+    /// There is already a value at the path being added to
+    Exists(String),
+    /// A node in a patch document isn't a valid operation
+    InvalidOperation(usize),
+    /// A mutation hook rejected the change, with the given message.  See
+    /// `Document::add_mutation_hook`.
+    Rejected(String, String),
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PatchError::InvalidPath(ref path) => write!(f, "invalid path {}", path),
+            PatchError::Missing(ref path) => write!(f, "nothing to change at {}", path),
+            PatchError::Exists(ref path) => write!(f, "there is already a value at {}", path),
+            PatchError::InvalidOperation(index) => write!(f, "node {} is not a patch operation", index),
+            PatchError::Rejected(ref path, ref reason) =>
+                write!(f, "cannot change {}: {}", path, reason),
+        }
+    }
+}
+
+impl Error for PatchError {}
+
+impl Document {
+    /// Apply the operations of a patch to the document, in order.
+    ///
+    /// # Failures
+    /// Fails if an operation can't be applied, or if one of the document's mutation hooks
+    /// rejects it (see `add_mutation_hook`), in which case the document is left
+    /// unchanged.
+    pub fn apply_patch(&mut self, patch: &Patch) -> Result<(), PatchError> {
+        // the changes are made to a copy without the hooks, as they've already been asked
+        let mut patched = self.unhooked();
+        for op in &patch.ops {
+            self.check_mutation(&op.mutation())
+                .map_err(|reason| PatchError::Rejected(op.path().to_string(), reason))?;
+            apply(&mut patched, op)?;
+        }
+        self.replace_contents(patched);
+        Ok(())
+    }
+
+    /// Set the value at a path, adding it if there isn't one there yet.  Returns the
+    /// value that was replaced.  Paths are written in the same form as for `get_path`,
+    /// except that they can't end in a list index (see `PatchOp`).
+    ///
+    /// # Failures
+    /// Fails if the path is invalid, or if a mutation hook rejects the change.  See
+    /// `apply_patch`.
+    pub fn set_path(&mut self, path: &str, value: Value) -> Result<Option<Value>, PatchError> {
+        let old = self.get_path(path).cloned();
+        let op = match old {
+            Some(_) => PatchOp::Replace(path.to_string(), PatchEntry::Value(value)),
+            None => PatchOp::Add(path.to_string(), PatchEntry::Value(value)),
+        };
+        self.apply_patch(&Patch { ops: vec![op] }).map(|_| old)
+    }
+
+    /// Remove the value or node at a path.
+    ///
+    /// # Failures
+    /// Fails if there is nothing at the path, or if a mutation hook rejects the change.
+    /// See `apply_patch`.
+    pub fn remove_path(&mut self, path: &str) -> Result<(), PatchError> {
+        self.apply_patch(&Patch { ops: vec![PatchOp::Remove(path.to_string())] })
+    }
+}
+
+/// The value or node at a path
+fn entry_at(document: &Document, path: &str) -> Option<PatchEntry> {
+    match document.get_path(path) {
+        Some(value) => Some(PatchEntry::Value(value.clone())),
+        None => node_at(document.children().as_slice(), path)
+            .map(|node| PatchEntry::Node(Box::new(node.clone()))),
+    }
+}
+
+/// `node`, named `name`
+fn renamed(name: &str, node: &Node) -> Node {
+    let mut parent = Node::new();
+    parent.append_node(name, node.clone());
+    parent.detach(0).unwrap()
+}
+
+/// The subnodes of a document or node
+trait Subnodes {
+    fn nodes(&mut self) -> &mut [Node];
+    fn append(&mut self, name: &str, node: Node);
+    fn remove(&mut self, index: usize);
+}
+
+impl Subnodes for Document {
+    fn nodes(&mut self) -> &mut [Node] {
+        self.children_mut().into_slice()
+    }
+
+    fn append(&mut self, name: &str, node: Node) {
+        self.append_node(name, node);
+    }
+
+    fn remove(&mut self, index: usize) {
+        self.detach(index);
+    }
+}
+
+impl Subnodes for Node {
+    fn nodes(&mut self) -> &mut [Node] {
+        self.children_mut().into_slice()
+    }
+
+    fn append(&mut self, name: &str, node: Node) {
+        self.append_node(name, node);
+    }
+
+    fn remove(&mut self, index: usize) {
+        self.detach(index);
+    }
+}
+
+/// Where the last part of a path is
+enum Parent<'a> {
+    Document(&'a mut Document),
+    Node(&'a mut Node),
+    Dict(&'a mut Dict),
+}
+
+fn apply(document: &mut Document, op: &PatchOp) -> Result<(), PatchError> {
+    let path = op.path();
+    let invalid = || PatchError::InvalidPath(path.to_string());
+    let (parent, last) = split_last(path).ok_or_else(invalid)?;
+    if last.indexes.len() > 1 {
+        return Err(invalid());
+    }
+    let (name, index) = (&*last.name, last.indexes.first().cloned());
+
+    let parent = if parent.is_empty() {
+        Parent::Document(document)
+    } else if node_at(document.children().as_slice(), parent).is_some() {
+        Parent::Node(node_at_mut(document.children_mut().into_slice(), parent).unwrap())
+    } else {
+        let value = document.get_path_mut(parent).ok_or_else(|| PatchError::Missing(path.to_string()))?;
+        Parent::Dict(value.get_dict_mut().ok_or_else(invalid)?)
+    };
+
+    match parent {
+        Parent::Document(document) => apply_to_nodes(document, op, name, index),
+        Parent::Node(node) => {
+            let is_value = match *op {
+                PatchOp::Add(_, ref entry) | PatchOp::Replace(_, ref entry) =>
+                    matches!(*entry, PatchEntry::Value(_)),
+                PatchOp::Remove(_) => node.has(name) && node.children_named(name).next().is_none(),
+            };
+            if !is_value {
+                return apply_to_nodes(node, op, name, index);
+            }
+            if index.is_some() {
+                return Err(invalid());
+            }
+            match *op {
+                PatchOp::Add(_, PatchEntry::Value(ref value)) if !node.has(name) =>
+                    { node.insert_attr(name, value.clone()); },
+                PatchOp::Add(..) => return Err(PatchError::Exists(path.to_string())),
+                PatchOp::Remove(_) => { node.delete_attr(name); },
+                PatchOp::Replace(_, PatchEntry::Value(ref value)) if node.has(name) =>
+                    { node.insert_attr(name, value.clone()); },
+                PatchOp::Replace(..) => return Err(PatchError::Missing(path.to_string())),
+            }
+            Ok(())
+        },
+        Parent::Dict(dict) => {
+            if index.is_some() {
+                return Err(invalid());
+            }
+            match *op {
+                PatchOp::Add(_, PatchEntry::Value(_)) if dict.contains_key(name) =>
+                    Err(PatchError::Exists(path.to_string())),
+                PatchOp::Add(_, PatchEntry::Value(ref value)) => {
+                    dict.insert(name.to_string(), value.clone());
+                    Ok(())
+                },
+                PatchOp::Remove(_) => dict.remove(name).map(|_| ())
+                    .ok_or_else(|| PatchError::Missing(path.to_string())),
+                PatchOp::Replace(_, PatchEntry::Value(ref value)) => match dict.get_mut(name) {
+                    Some(existing) => {
+                        *existing = value.clone();
+                        Ok(())
+                    },
+                    None => Err(PatchError::Missing(path.to_string())),
+                },
+                _ => Err(invalid()),
+            }
+        },
+    }
+}
+
+/// Apply an operation to the `index`th subnode called `name`
+fn apply_to_nodes<N>(parent: &mut N, op: &PatchOp, name: &str, index: Option<usize>)
+    -> Result<(), PatchError> where N: Subnodes {
+
+    let path = op.path();
+    let count = parent.nodes().iter().filter(|node| node.name() == name).count();
+    let position = parent.nodes().iter()
+        .enumerate()
+        .filter(|&(_, node)| node.name() == name)
+        .nth(index.unwrap_or(0))
+        .map(|(position, _)| position);
+
+    match (op, position) {
+        (&PatchOp::Add(_, PatchEntry::Node(ref node)), _) if index.is_none_or(|index| index == count) =>
+            parent.append(name, (**node).clone()),
+        (&PatchOp::Remove(_), Some(position)) => parent.remove(position),
+        (&PatchOp::Replace(_, PatchEntry::Node(ref node)), Some(position)) =>
+            parent.nodes()[position] = renamed(name, node),
+        (&PatchOp::Remove(_), None) | (&PatchOp::Replace(_, PatchEntry::Node(_)), None) =>
+            return Err(PatchError::Missing(path.to_string())),
+        _ => return Err(PatchError::InvalidPath(path.to_string())),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::figtree::Figtree;
+    use super::super::parser::ParserOptions;
+
+    fn parse(input: &str) -> Document {
+        let options = ParserOptions::new().repeated_nodes(true).node_arguments(true);
+        Figtree::from_string(input).with_options(options).parse().unwrap()
+    }
+
+    fn patched(input: &str, ops: Vec<PatchOp>) -> Result<Document, PatchError> {
+        let mut doc = parse(input);
+        doc.apply_patch(&Patch { ops }).map(|_| doc)
+    }
+
+    fn value(input: i64) -> PatchEntry {
+        PatchEntry::Value(Value::new_int(input))
+    }
+
+    fn node(name: &str) -> PatchEntry {
+        PatchEntry::Node(Box::new(Node::builder(name).prop("n", 1).build()))
+    }
+
+    #[test]
+    fn applies_operations() {
+        let input = "a { x = 1, d = { k = 1 } sub {} } up { h = 1 } up { h = 2 }";
+        let doc = patched(input, vec![
+            PatchOp::Replace("a.x".to_string(), value(2)),
+            PatchOp::Add("a.y".to_string(), value(3)),
+            PatchOp::Add("a.d.j".to_string(), value(4)),
+            PatchOp::Remove("a.d.k".to_string()),
+            PatchOp::Replace("a.sub".to_string(), node("other")),
+            PatchOp::Add("up[2]".to_string(), node("ignored")),
+            PatchOp::Remove("up".to_string()),
+            PatchOp::Add("new".to_string(), node("new")),
+        ]).unwrap();
+        assert_eq!(doc, parse("a { x = 2, y = 3, d = { j = 4 } sub { n = 1 } }
+                               up { h = 2 } up { n = 1 } new { n = 1 }"));
+        assert_eq!(doc["a"][0].name(), "sub");
+        assert_eq!(doc.children().map(Node::name).collect::<Vec<_>>(), vec!["a", "up", "up", "new"]);
+    }
+
+    #[test]
+    fn reports_errors() {
+        let input = "a { x = 1, d = { k = 1 }, l = [{ k = 1 }] } up {}";
+        let error = |op| patched(input, vec![PatchOp::Remove("a.x".to_string()), op]).unwrap_err();
+        assert_eq!(error(PatchOp::Add("a.d.k".to_string(), value(1))),
+            PatchError::Exists("a.d.k".to_string()));
+        assert_eq!(error(PatchOp::Remove("a.x".to_string())), PatchError::Missing("a.x".to_string()));
+        assert_eq!(error(PatchOp::Replace("b".to_string(), node("b"))),
+            PatchError::Missing("b".to_string()));
+        assert_eq!(error(PatchOp::Remove("a.nope.k".to_string())),
+            PatchError::Missing("a.nope.k".to_string()));
+        for path in &["a.l[0]", "a.d.k[0]", "x", "a..x", "up[2]", "a.x[0][0]"] {
+            let op = match *path {
+                "up[2]" => PatchOp::Add(path.to_string(), node("up")),
+                "x" => PatchOp::Add(path.to_string(), value(1)),
+                _ => PatchOp::Remove(path.to_string()),
+            };
+            assert_eq!(error(op), PatchError::InvalidPath(path.to_string()), "{}", path);
+        }
+        assert_eq!(error(PatchOp::Remove("".to_string())).to_string(), "invalid path ");
+    }
+
+    #[test]
+    fn patches_between_documents() {
+        let pairs = [
+            ("a { x = 1, d = { k = 1, j = 2 } } up {} up {} up {}", "a { x = 2, d = { j = 3 }, y = [] } up {}"),
+            ("a 1 { b { c = 1 } } old {}", "a 2 { b { c = 2 } d {} } up {} up { n = 1 }"),
+            ("", "a { b { c = 1 } }"),
+            ("server { tls { 'a.b' = 1, 'c[0]' = 2, d = { 'e]' = 3 } } }",
+             "server { tls { 'c[0]' = 4, d = { 'e]' = 5, \"it's\" = 6 } } }"),
+        ];
+        for &(first, second) in &pairs {
+            for &(first, second) in &[(first, second), (second, first)] {
+                let patch = Patch::between(&parse(first), &parse(second));
+                let mut doc = parse(first);
+                doc.apply_patch(&patch).unwrap();
+                assert!(doc.structurally_eq(&parse(second)), "{} -> {}", first, second);
+                assert_eq!(doc.children().map(Node::name).collect::<Vec<_>>(),
+                    parse(second).children().map(Node::name).collect::<Vec<_>>());
+            }
+        }
+
+        // names that are part of the syntax of paths are quoted
+        let patch = Patch::between(&parse(pairs[3].0), &parse(pairs[3].1));
+        assert!(patch.ops.contains(&PatchOp::Remove("server.tls.'a.b'".to_string())));
+        assert!(patch.ops.contains(&PatchOp::Replace("server.tls.d.'e]'".to_string(),
+                                                     PatchEntry::Value(Value::new_int(5)))));
+    }
+
+    #[test]
+    fn reads_and_writes_documents() {
+        let written = "replace { path = 'a.x', value = 2 }
+                       add { path = 'up[1]', up 'arg' { h = 1 } }
+                       remove { path = 'a.y' }";
+        let patch = Patch::from_document(&parse(written)).unwrap();
+        assert_eq!(patch.ops, vec![
+            PatchOp::Replace("a.x".to_string(), value(2)),
+            PatchOp::Add("up[1]".to_string(),
+                PatchEntry::Node(Box::new(Node::builder("up").arg("arg").prop("h", 1).build()))),
+            PatchOp::Remove("a.y".to_string()),
+        ]);
+        assert_eq!(patch.to_document(), parse(written));
+        assert_eq!(Patch::from_document(&patch.to_document()), Ok(patch));
+
+        for &(input, index) in &[("add { path = 'a' }", 0), ("remove {} ", 0),
+                                 ("remove { path = 'a' } add { path = 'a', value = 1, b {} }", 1),
+                                 ("move { path = 'a', value = 1 }", 0)] {
+            assert_eq!(Patch::from_document(&parse(input)), Err(PatchError::InvalidOperation(index)));
+        }
+    }
+    #[test]
+    fn consults_mutation_hooks() {
+        let mut doc = parse("server { port = 80 } up {} up {}");
+        doc.add_mutation_hook(|mutation: &Mutation| {
+            if mutation.name().starts_with('_') {
+                return Err("reserved".to_string());
+            }
+            match *mutation {
+                Mutation::SetNode(_, node) if node.has("port") => Err("no ports here".to_string()),
+                Mutation::Remove("server") => Err("the server stays".to_string()),
+                _ => Ok(()),
+            }
+        });
+        let rejected = |path: &str, reason: &str|
+            PatchError::Rejected(path.to_string(), reason.to_string());
+
+        assert_eq!(doc.set_path("server.port", Value::new_int(81)), Ok(Some(Value::new_int(80))));
+        assert_eq!(doc.set_path("server.tls", Value::new_bool(true)), Ok(None));
+        assert_eq!(doc.set_path("server._hidden", Value::new_null()),
+            Err(rejected("server._hidden", "reserved")));
+        assert_eq!(doc.remove_path("server"), Err(rejected("server", "the server stays")));
+        assert_eq!(doc.remove_path("up[1]"), Ok(()));
+        // nothing is changed when any operation is rejected
+        let before = doc.clone();
+        let patch = Patch { ops: vec![
+            PatchOp::Remove("server.tls".to_string()),
+            PatchOp::Add("up[1]".to_string(), PatchEntry::Node(Box::new(Node::builder("up")
+                .prop("port", 1).build()))),
+            PatchOp::Add("_up".to_string(), PatchEntry::Node(Box::default())),
+        ] };
+        assert_eq!(doc.apply_patch(&patch), Err(rejected("up[1]", "no ports here")));
+        assert_eq!(doc, before);
+        assert_eq!(doc.remove_path("server.port[0]"),
+            Err(PatchError::InvalidPath("server.port[0]".to_string())));
+
+        assert_eq!(doc.check_mutation(&Mutation::Remove("a._b[2]")), Err("reserved".to_string()));
+        let mut copy = doc.clone();
+        assert!(copy.remove_path("server").is_err());
+        copy.clear_mutation_hooks();
+        assert_eq!(copy.remove_path("server"), Ok(()));
+        assert!(doc.has_node("server"));
+        assert_eq!(PatchError::Rejected("a.b".to_string(), "reserved".to_string()).to_string(),
+            "cannot change a.b: reserved");
+    }
+
+    #[test]
+    fn mutators_consult_hooks() {
+        use std::sync::{Arc, Mutex};
+        use super::super::hooks::HookError;
+        use super::super::merge::{MergeError, MergeStrategy};
+
+        let mut doc = parse("server { port = 80 } up {} up {}");
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let log = seen.clone();
+        doc.add_mutation_hook(move |mutation: &Mutation| {
+            log.lock().unwrap().push(mutation.path().to_string());
+            match *mutation {
+                Mutation::SetNode(path, _) | Mutation::Remove(path) if path.starts_with('_') =>
+                    Err("reserved".to_string()),
+                _ => Ok(()),
+            }
+        });
+        let changes = |doc: &mut Document, change: &dyn Fn(&mut Document) -> Result<(), HookError>| {
+            seen.lock().unwrap().clear();
+            let rejected = change(doc).is_err();
+            (seen.lock().unwrap().clone(), rejected)
+        };
+        let ok = |paths: &[&str]| (paths.iter().map(|path| path.to_string()).collect(), false);
+        let rejected = |paths: &[&str]| (paths.iter().map(|path| path.to_string()).collect(), true);
+
+        assert_eq!(changes(&mut doc, &|doc| doc.try_new_node_or_get("server").map(drop)), ok(&[]));
+        assert_eq!(changes(&mut doc, &|doc| doc.try_new_node_or_get("log").map(drop)), ok(&["log"]));
+        assert_eq!(changes(&mut doc, &|doc| doc.try_insert_node("log", Node::new()).map(drop)),
+                   ok(&["log"]));
+        assert_eq!(changes(&mut doc, &|doc| doc.try_append_node("up", Node::new()).map(drop)),
+                   ok(&["up[2]"]));
+        assert_eq!(changes(&mut doc, &|doc| doc.try_adopt(Node::named("tls")).map(drop)),
+                   ok(&["tls"]));
+        assert_eq!(changes(&mut doc, &|doc| doc.try_detach(2).map(drop)), ok(&["up[1]"]));
+        assert_eq!(changes(&mut doc, &|doc| doc.try_delete_node("tls").map(drop)), ok(&["tls"]));
+        assert_eq!(changes(&mut doc, &|doc| doc.try_delete_node("tls").map(drop)), ok(&[]));
+        assert_eq!(changes(&mut doc, &|doc| doc.try_sort_children()), ok(&[""]));
+        assert_eq!(changes(&mut doc, &|doc| { doc.sort_children(); Ok(()) }), ok(&[""]));
+
+        let before = doc.clone();
+        assert_eq!(changes(&mut doc, &|doc| doc.try_new_node_or_get("_a").map(drop)),
+                   rejected(&["_a"]));
+        assert_eq!(changes(&mut doc, &|doc| doc.try_append_node("_a", Node::new()).map(drop)),
+                   rejected(&["_a"]));
+        assert_eq!(doc.try_adopt(Node::named("_b")).unwrap_err(),
+                   HookError { path: "_b".to_string(), reason: "reserved".to_string() });
+        let other = parse("server { port = 81 } _a {}");
+        seen.lock().unwrap().clear();
+        assert_eq!(doc.merge(&other, MergeStrategy::LaterWins), Err(MergeError::Rejected(
+            HookError { path: "_a".to_string(), reason: "reserved".to_string() })));
+        assert_eq!(*seen.lock().unwrap(), vec!["server.port", "_a"]);
+        assert_eq!(doc, before);
+        assert_eq!(HookError { path: "_a".to_string(), reason: "reserved".to_string() }.to_string(),
+                   "cannot change \"_a\": reserved");
+
+        // changes made through references to the nodes aren't seen by the hooks
+        seen.lock().unwrap().clear();
+        let server = doc.get_node_mut("server").unwrap();
+        server.insert_node("_hidden", Node::new());
+        server.insert_attr("_key", Value::new_int(1));
+        assert!(seen.lock().unwrap().is_empty());
+        assert!(doc["server"].has_node("_hidden") && doc["server"].has("_key"));
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot change \"_a\": reserved")]
+    fn mutators_panic_when_rejected() {
+        let mut doc = Document::new();
+        doc.add_mutation_hook(|mutation: &Mutation| {
+            if mutation.name().starts_with('_') { Err("reserved".to_string()) } else { Ok(()) }
+        });
+        doc.append_node("_a", Node::new());
+    }
+}
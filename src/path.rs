@@ -0,0 +1,319 @@
+use std::borrow::Cow;
+
+use super::types::{Document, Node, Value};
+
+/// A name as it is written in a path: in quotes, if it is empty or has characters that
+/// are part of the syntax of paths
+pub fn quote(name: &str) -> Cow<'_, str> {
+    if !name.is_empty() && !name.contains(['.', '[', ']', '\'']) {
+        return Cow::Borrowed(name);
+    }
+    let mut quoted = String::with_capacity(name.len() + 2);
+    quoted.push('\'');
+    for c in name.chars() {
+        if c == '\'' || c == '\\' {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('\'');
+    Cow::Owned(quoted)
+}
+
+/// The path of an entry inside the node or dict at `path`
+pub fn join(path: &str, name: &str) -> String {
+    if path.is_empty() { quote(name).into_owned() } else { format!("{}.{}", path, quote(name)) }
+}
+
+/// The path of the `index`th node called `name` at `path`, including the index if the
+/// node is repeated
+pub fn node_path(path: &str, name: &str, index: usize, repeated: bool) -> String {
+    if repeated { format!("{}[{}]", join(path, name), index) } else { join(path, name) }
+}
+
+/// A segment of a dotted path: a name, followed by any number of indexes
+pub struct Segment<'a> {
+    pub name: Cow<'a, str>,
+    pub indexes: Vec<usize>,
+}
+
+/// Split a path like `upstream[2].host` into its segments, or `None` if it is malformed.
+/// Names with `.`, `[`, `]` or `'` in them are written in single quotes, with a
+/// backslash before any `'` or `\` (see `quote`).
+pub fn segments(path: &str) -> Option<Vec<Segment<'_>>> {
+    Some(parse(path)?.into_iter().map(|(_, segment)| segment).collect())
+}
+
+/// Split a path into the path of the node or dict that it's in, and its last segment
+pub fn split_last(path: &str) -> Option<(&str, Segment<'_>)> {
+    let (start, last) = parse(path)?.pop()?;
+    Some((&path[..start.saturating_sub(1)], last))
+}
+
+/// The segments of a path, with where each one starts
+fn parse(path: &str) -> Option<Vec<(usize, Segment<'_>)>> {
+    let mut segments = Vec::new();
+    let mut rest = path;
+    loop {
+        let start = path.len() - rest.len();
+        let (name, after) = name(rest)?;
+        rest = after;
+        let mut indexes = Vec::new();
+        while let Some(after) = rest.strip_prefix('[') {
+            let end = after.find(']')?;
+            indexes.push(after[..end].parse().ok()?);
+            rest = &after[end + 1..];
+        }
+        segments.push((start, Segment { name, indexes }));
+        match rest.strip_prefix('.') {
+            Some(after) => rest = after,
+            None if rest.is_empty() => return Some(segments),
+            None => return None,
+        }
+    }
+}
+
+/// The name at the start of a segment, and the rest of the path after it
+fn name(path: &str) -> Option<(Cow<'_, str>, &str)> {
+    let quoted = match path.strip_prefix('\'') {
+        Some(quoted) => quoted,
+        None => {
+            let end = path.find(['.', '[', ']', '\'']).unwrap_or(path.len());
+            return if end == 0 { None } else { Some((Cow::Borrowed(&path[..end]), &path[end..])) };
+        },
+    };
+    let mut name = String::new();
+    let mut chars = quoted.char_indices();
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '\'' => return Some((Cow::Owned(name), &quoted[index + 1..])),
+            '\\' => name.push(chars.next()?.1),
+            c => name.push(c),
+        }
+    }
+    None
+}
+
+/// Where a path has got to: either still in the nodes, or inside a value
+enum Location<'a> {
+    Node(&'a Node),
+    Value(&'a Value),
+}
+
+enum LocationMut<'a> {
+    Node(&'a mut Node),
+    Value(&'a mut Value),
+}
+
+/// Follow `segments` from a node to the value they lead to
+fn follow<'a>(mut location: Location<'a>, segments: &[Segment]) -> Option<&'a Value> {
+    for segment in segments {
+        let mut indexes = segment.indexes.iter();
+        location = match location {
+            Location::Node(node) => match node.get(&segment.name) {
+                Some(value) if node.children_named(&segment.name).next().is_none() =>
+                    Location::Value(value),
+                _ => Location::Node(node.children_named(&segment.name)
+                    .nth(indexes.next().cloned().unwrap_or(0))?),
+            },
+            Location::Value(value) => Location::Value(value.get_dict()?.get(&*segment.name)?),
+        };
+        for &index in indexes {
+            location = match location {
+                Location::Value(value) => Location::Value(value.get_list()?.get(index)?),
+                Location::Node(_) => return None,
+            };
+        }
+    }
+    match location {
+        Location::Value(value) => Some(value),
+        Location::Node(_) => None,
+    }
+}
+
+fn follow_mut<'a>(mut location: LocationMut<'a>, segments: &[Segment]) -> Option<&'a mut Value> {
+    for segment in segments {
+        let mut indexes = segment.indexes.iter();
+        location = match location {
+            LocationMut::Node(node) => {
+                let name = &*segment.name;
+                if node.has(name) && node.children_named(name).next().is_none() {
+                    LocationMut::Value(node.get_attr_mut(name)?)
+                } else {
+                    let index = indexes.next().cloned().unwrap_or(0);
+                    LocationMut::Node(node.children_mut()
+                        .filter(|child| child.name() == name)
+                        .nth(index)?)
+                }
+            },
+            LocationMut::Value(value) =>
+                LocationMut::Value(value.get_dict_mut()?.get_mut(&*segment.name)?),
+        };
+        for &index in indexes {
+            location = match location {
+                LocationMut::Value(value) => LocationMut::Value(value.get_list_mut()?.get_mut(index)?),
+                LocationMut::Node(_) => return None,
+            };
+        }
+    }
+    match location {
+        LocationMut::Value(value) => Some(value),
+        LocationMut::Node(_) => None,
+    }
+}
+
+impl Node {
+    /// Look up a value by a dotted path, relative to this node.  See `Document::get_path`.
+    pub fn get_path(&self, path: &str) -> Option<&Value> {
+        follow(Location::Node(self), &segments(path)?)
+    }
+
+    /// Look up a value by a dotted path, relative to this node, and get a mutable
+    /// reference to it.  See `Document::get_path`.
+    pub fn get_path_mut(&mut self, path: &str) -> Option<&mut Value> {
+        follow_mut(LocationMut::Node(self), &segments(path)?)
+    }
+}
+
+impl Document {
+    /// Look up a value by a dotted path, such as `server.tls.cert`.
+    ///
+    /// Each part of the path is the name of a node, and the last part is the key of an
+    /// attribute.  Parts after an attribute look up keys in dicts.  An index picks out
+    /// one of several nodes with the same name (`upstream[1].host` is the `host` of the
+    /// second `upstream` node), or an item in a list (`hosts[0]`).  Returns `None` if
+    /// there is nothing at the path, if the path leads to a node rather than a value,
+    /// or if the path is malformed.
+    ///
+    /// Names that contain `.`, `[`, `]` or `'` are written in single quotes, with a
+    /// backslash before any `'` or backslash in them (`server.'a.b'`).
+    ///
+    /// # Examples
+    /// ```
+    /// # use figtree::{Figtree, ParserOptions, Value};
+    /// let input = "server { tls { cert = 'a.pem' } }
+    ///              upstream { hosts = ['x'] } upstream { hosts = ['y', 'z'] }";
+    /// let options = ParserOptions::new().repeated_nodes(true);
+    /// let doc = Figtree::from_string(input).with_options(options).parse().unwrap();
+    /// assert_eq!(doc.get_path("server.tls.cert"), Some(&Value::new_string("a.pem")));
+    /// assert_eq!(doc.get_path("upstream[1].hosts[1]"), Some(&Value::new_string("z")));
+    /// assert_eq!(doc.get_path("server.tls"), None);
+    ///
+    /// let doc = Figtree::from_string("server { tls { 'a.b' = 1 } }").parse().unwrap();
+    /// assert_eq!(doc.get_path("server.tls.'a.b'"), Some(&Value::new_int(1)));
+    /// ```
+    pub fn get_path(&self, path: &str) -> Option<&Value> {
+        let segments = segments(path)?;
+        let (first, rest) = segments.split_first()?;
+        let mut indexes = first.indexes.iter();
+        let node = self.children_named(&first.name).nth(indexes.next().cloned().unwrap_or(0))?;
+        if indexes.next().is_some() {
+            return None;
+        }
+        follow(Location::Node(node), rest)
+    }
+
+    /// Look up a value by a dotted path, and get a mutable reference to it.  See
+    /// `Document::get_path` for the syntax of paths.
+    pub fn get_path_mut(&mut self, path: &str) -> Option<&mut Value> {
+        let segments = segments(path)?;
+        let (first, rest) = segments.split_first()?;
+        if first.indexes.len() > 1 {
+            return None;
+        }
+        let index = first.indexes.first().cloned().unwrap_or(0);
+        let node = self.children_mut().filter(|node| node.name() == &*first.name).nth(index)?;
+        follow_mut(LocationMut::Node(node), rest)
+    }
+}
+
+/// Find the node at a path made up only of node names, each with an optional index
+pub fn node_at<'a>(nodes: &'a [Node], path: &str) -> Option<&'a Node> {
+    find(nodes, &segments(path)?)
+}
+
+pub fn node_at_mut<'a>(nodes: &'a mut [Node], path: &str) -> Option<&'a mut Node> {
+    find_mut(nodes, &segments(path)?)
+}
+
+fn find<'a>(nodes: &'a [Node], segments: &[Segment]) -> Option<&'a Node> {
+    let (first, rest) = segments.split_first()?;
+    if first.indexes.len() > 1 {
+        return None;
+    }
+    let index = first.indexes.first().cloned().unwrap_or(0);
+    let node = nodes.iter().filter(|node| node.name() == &*first.name).nth(index)?;
+    if rest.is_empty() { Some(node) } else { find(node.children().as_slice(), rest) }
+}
+
+fn find_mut<'a>(nodes: &'a mut [Node], segments: &[Segment]) -> Option<&'a mut Node> {
+    let (first, rest) = segments.split_first()?;
+    if first.indexes.len() > 1 {
+        return None;
+    }
+    let index = first.indexes.first().cloned().unwrap_or(0);
+    let node = nodes.iter_mut().filter(|node| node.name() == &*first.name).nth(index)?;
+    if rest.is_empty() { Some(node) } else { find_mut(node.children_mut().into_slice(), rest) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::figtree::Figtree;
+    use super::super::parser::ParserOptions;
+    use super::super::types::*;
+
+    fn parse(input: &str) -> Document {
+        let options = ParserOptions::new().repeated_nodes(true);
+        Figtree::from_string(input).with_options(options).parse().unwrap()
+    }
+
+    #[test]
+    fn looks_up_paths() {
+        let doc = parse("a { b { c = 1 } env = { x = { y = [[1, 2], [3]] } } }
+                         up { host = 'p' } up { host = 'q' }");
+        assert_eq!(doc.get_path("a.b.c"), Some(&Value::new_int(1)));
+        assert_eq!(doc.get_path("a.env.x.y[0][1]"), Some(&Value::new_int(2)));
+        assert_eq!(doc.get_path("a.env.x.y[1]"), Some(&Value::List(vec![Value::new_int(3)])));
+        assert_eq!(doc.get_path("up.host"), Some(&Value::new_string("p")));
+        assert_eq!(doc.get_path("up[1].host"), Some(&Value::new_string("q")));
+        assert_eq!(doc["a"].get_path("b.c"), Some(&Value::new_int(1)));
+
+        for missing in &["a.b", "a.b.d", "up[2].host", "a.env.x.y[2]", "a.b.c.d", "a.b[0][0].c",
+                         "", "a..b", "a.b[x].c", "a.b[0", "a.b[0]x.c", "a.env[0]"] {
+            assert_eq!(doc.get_path(missing), None, "{}", missing);
+        }
+    }
+
+    #[test]
+    fn quotes_names() {
+        let doc = parse("a { 'b.c' = 1, 'd[0]' = { \"it's\" = 2, 'back\\\\slash' = 3 } }");
+        assert_eq!(doc.get_path("a.'b.c'"), Some(&Value::new_int(1)));
+        assert_eq!(doc.get_path("a.'d[0]'.'it\\'s'"), Some(&Value::new_int(2)));
+        assert_eq!(doc.get_path("a.'d[0]'.'back\\\\slash'"), Some(&Value::new_int(3)));
+        assert_eq!(doc.get_path("a.b.c"), None);
+
+        assert_eq!(super::join("a", "b.c"), "a.'b.c'");
+        assert_eq!(super::join("", "it's"), "'it\\'s'");
+        assert_eq!(super::join("a", "back\\slash"), "a.back\\slash");
+        assert_eq!(super::node_path("a", "]", 1, true), "a.']'[1]");
+        for name in &["b.c", "d[0]", "it's", "'", "\\'", "", "x"] {
+            let path = super::node_path("a", name, 2, true);
+            let segments = super::segments(&path).unwrap();
+            assert_eq!(segments[1].name, *name);
+            assert_eq!(segments[1].indexes, vec![2]);
+        }
+        for malformed in &["a.'b", "a.'b'c", "a.b]", "a.'b\\'"] {
+            assert!(super::segments(malformed).is_none(), "{}", malformed);
+        }
+    }
+
+    #[test]
+    fn changes_values_at_paths() {
+        let mut doc = parse("up { host = 'p', ports = [1] } up { host = 'q' }");
+        *doc.get_path_mut("up[1].host").unwrap() = Value::new_string("r");
+        *doc.get_path_mut("up.ports[0]").unwrap() = Value::new_int(2);
+        assert_eq!(doc, parse("up { host = 'p', ports = [2] } up { host = 'r' }"));
+        assert!(doc.get_path_mut("up[2].host").is_none());
+        assert!(doc["up"].clone().get_path_mut("ports[0]").is_some());
+    }
+}
@@ -1,5 +1,9 @@
-/// Represents a position in the file
-#[derive(Debug, PartialEq)]
+use std::fmt;
+use std::path::PathBuf;
+
+/// Represents a position in the file.  Positions are ordered by line, then by position
+/// in the line.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
 pub struct Position {
     /// line of the file
     pub line: usize,
@@ -15,7 +19,58 @@ impl Position {
 
     /// Construct a new position at an arbitrary line and position
     pub fn at(line: usize, pos: usize) -> Self {
-        Position { line: line, pos: pos }
+        Position { line, pos }
+    }
+}
+
+impl Default for Position {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Positions are displayed counting from one, as `line 1, column 1`
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}", self.line + 1, self.pos + 1)
+    }
+}
+
+/// Represents a range of the file, from `start` up to (but not including) `end`
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Span {
+    /// the position of the first character in the range
+    pub start: Position,
+    /// the position just after the last character in the range
+    pub end: Position,
+}
+
+impl Span {
+    /// Construct a new span between two positions
+    pub fn new(start: Position, end: Position) -> Self {
+        Span { start, end }
+    }
+}
+
+/// Where a document came from, so that messages about it can say which file (or other
+/// source) they are about.  This matters once documents are built up from several files.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Origin {
+    /// A file on disk
+    File(PathBuf),
+    /// A document fetched from a remote location
+    Url(String),
+    /// A document written in the program itself, such as one parsed from a string
+    Inline,
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Origin::File(ref path) => write!(f, "{}", path.display()),
+            Origin::Url(ref url) => f.write_str(url),
+            Origin::Inline => f.write_str("inline input"),
+        }
     }
 }
 
@@ -31,7 +86,7 @@ impl MutablePosition {
         Self::at(0, 0)
     }
     pub fn at(line: usize, pos: usize) -> Self {
-        MutablePosition { line: line, pos: pos, line_lengths: Vec::new() }
+        MutablePosition { line, pos, line_lengths: Vec::new() }
     }
     pub fn new_line(&mut self) -> &mut Self {
         self.line_lengths.push(self.pos);
@@ -44,6 +99,13 @@ impl MutablePosition {
         self
     }
 
+    /// Move forward over a tab, to the next column that is a multiple of `width`
+    pub fn tab(&mut self, width: usize) -> &mut Self {
+        let width = width.max(1);
+        self.pos += width - self.pos % width;
+        self
+    }
+
     pub fn unpush(&mut self, amt: usize) -> &mut Self {
         if amt <= self.pos {
             self.pos -= amt;
@@ -82,10 +144,6 @@ impl PartialEq for MutablePosition {
     fn eq(&self, other: &MutablePosition) -> bool {
         self.pos == other.pos && self.line == other.line
     }
-
-    fn ne(&self, other: &MutablePosition) -> bool {
-        self.pos != other.pos || self.line != other.line
-    }
 }
 
 #[cfg(test)]
@@ -160,6 +218,22 @@ mod tests {
         assert_eq!(pos.pos, 10);
     }
 
+    #[test]
+    fn tab_stops() {
+        let mut pos = MutablePosition::new();
+        pos.tab(4);
+        assert_eq!(pos.pos, 4);
+        pos.push(1).tab(4);
+        assert_eq!(pos.pos, 8);
+        pos.push(3).tab(4);
+        assert_eq!(pos.pos, 12);
+
+        pos.tab(1);
+        assert_eq!(pos.pos, 13);
+        pos.tab(0);
+        assert_eq!(pos.pos, 14);
+    }
+
     #[test]
     fn equality() {
         let mut pos = MutablePosition::new();
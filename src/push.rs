@@ -0,0 +1,284 @@
+use std::mem;
+use std::str;
+use std::sync::Arc;
+
+use super::lexer::{Lexer, LexError, Source};
+use super::parser::{Parser, ParserOptions, ParseResult};
+
+/// The input that has been fed to a `PushParser`, read by its lexer
+#[derive(Clone)]
+struct ChunkSource {
+    /// the input fed so far, from the first character that hadn't been read when it was
+    /// last fed.  It is shared with the parser's checkpoints, so it isn't copied for each
+    /// of them.
+    text: Arc<str>,
+    /// the byte offset of the next character in `text`
+    offset: usize,
+    /// whether the end of the input has been fed
+    finished: bool,
+    /// whether the lexer has asked for a character that hasn't been fed yet
+    starved: bool,
+    /// the error that ended the input, once the text before it has been read
+    error: Option<LexError>,
+}
+
+impl ChunkSource {
+    fn push(&mut self, text: &str) {
+        let mut rest = String::with_capacity(self.text.len() - self.offset + text.len());
+        rest.push_str(&self.text[self.offset..]);
+        rest.push_str(text);
+        self.text = Arc::from(rest);
+        self.offset = 0;
+    }
+}
+
+impl Source for ChunkSource {
+    fn next_char(&mut self) -> Option<char> {
+        match self.text[self.offset..].chars().next() {
+            Some(next) => {
+                self.offset += next.len_utf8();
+                Some(next)
+            },
+            None => {
+                self.starved = !self.finished && self.error.is_none();
+                None
+            },
+        }
+    }
+
+    fn take_error(&mut self) -> Option<LexError> {
+        if self.offset == self.text.len() { self.error.take() } else { None }
+    }
+
+    // the limit is applied by `PushParser::decode`, as the input is fed
+    fn set_limit(&mut self, _: Option<usize>) {}
+}
+
+/// A parser that is given its input a chunk at a time, for sources that can't be wrapped
+/// in a blocking `Read`.
+///
+/// Each call to `feed` parses as far as the input fed so far allows, so a result is
+/// produced as soon as all of the input that it depends on has been fed.  If the input
+/// runs out partway through a result, the parser goes back to the state it was in
+/// before that result, and parses it again once there is more input.  Chunks don't need
+/// to split the input anywhere in particular - even a character split across two chunks
+/// is fine.
+///
+/// Comments and warnings can't be taken from a push parser, so they are never kept, even
+/// if `ParserOptions::comments` or `ParserOptions::warnings` are turned on.
+///
+/// # Examples
+/// ```
+/// # use figtree::{PushParser, ParseEvent};
+/// let mut parser = PushParser::new();
+/// let mut events = parser.feed(b"node {\n  key = ");
+/// events.extend(parser.feed(b"1\n}"));
+/// events.extend(parser.finish());
+///
+/// let events: Vec<_> = events.into_iter().map(|result| result.unwrap().0).collect();
+/// assert_eq!(events.len(), 6);
+/// assert_eq!(events[1], ParseEvent::NodeStart("node".to_string(), false));
+/// assert_eq!(events[5], ParseEvent::FileEnd);
+/// ```
+pub struct PushParser {
+    parser: Parser<ChunkSource>,
+    /// bytes at the end of the input fed so far that aren't a whole character yet, or
+    /// all of the input, until there is enough of it to tell its encoding
+    partial: Vec<u8>,
+    /// the number of bytes fed so far, and the most that will be read
+    fed: usize,
+    limit: Option<usize>,
+    /// whether the encoding of the input has been checked
+    started: bool,
+    done: bool,
+}
+
+impl PushParser {
+    /// Construct a push parser with the default options
+    pub fn new() -> Self {
+        Self::with_options(ParserOptions::new())
+    }
+
+    /// Construct a push parser with a set of options
+    pub fn with_options(options: ParserOptions) -> Self {
+        let source = ChunkSource {
+            text: Arc::from(""),
+            offset: 0,
+            finished: false,
+            starved: false,
+            error: None,
+        };
+        let limit = options.max_document_size;
+        let options = ParserOptions { comments: false, warnings: false, ..options };
+        PushParser {
+            parser: Parser::with_options(Lexer::with_source(source), options),
+            partial: Vec::new(),
+            fed: 0,
+            limit,
+            started: false,
+            done: false,
+        }
+    }
+
+    /// Feed the next chunk of input to the parser, returning any results that it made
+    /// available.
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<ParseResult> {
+        if self.done {
+            return Vec::new();
+        }
+        self.decode(chunk, false);
+        self.parse()
+    }
+
+    /// Mark the end of the input, returning all of the remaining results.
+    pub fn finish(mut self) -> Vec<ParseResult> {
+        if self.done {
+            return Vec::new();
+        }
+        self.decode(&[], true);
+        self.parse()
+    }
+
+    /// Add a chunk to the input, in the same way as a `Lexer` reads it from a reader:
+    /// a byte order mark is skipped, other encodings and invalid UTF-8 are errors, and
+    /// nothing past the maximum document size is read.
+    fn decode(&mut self, chunk: &[u8], last: bool) {
+        let source = self.parser.lexer_mut().source_mut();
+        source.finished = last;
+        if source.error.is_some() {
+            return;
+        }
+
+        let fed = self.fed;
+        let allowed = self.limit.map_or(chunk.len(), |limit| chunk.len().min(limit - fed));
+        self.fed += allowed;
+        self.partial.extend_from_slice(&chunk[..allowed]);
+        let mut error = if allowed < chunk.len() { Some(LexError::DocumentTooLarge) } else { None };
+
+        let mut bytes = &self.partial[..];
+        if !self.started {
+            // the encoding can be told from the first four bytes
+            if bytes.len() < 4 && !last && error.is_none() {
+                return;
+            }
+            self.started = true;
+            // UTF-32 has to be tested first, as its little-endian BOM starts with the
+            // UTF-16 little-endian BOM
+            for &(bom, encoding) in &[
+                (&b"\x00\x00\xFE\xFF"[..], "UTF-32BE"), (&b"\xFF\xFE\x00\x00"[..], "UTF-32LE"),
+                (&b"\xFE\xFF"[..], "UTF-16BE"), (&b"\xFF\xFE"[..], "UTF-16LE"),
+            ] {
+                if bytes.starts_with(bom) {
+                    source.error = Some(LexError::UnsupportedEncoding(encoding));
+                    return;
+                }
+            }
+            if bytes.starts_with(b"\xEF\xBB\xBF") {
+                bytes = &bytes[3..];
+            }
+        }
+
+        let (text, rest) = match str::from_utf8(bytes) {
+            Ok(text) => (text, &[][..]),
+            Err(err) => {
+                // a character cut off by the end of a chunk is finished by the next one,
+                // unless there isn't going to be one
+                if err.error_len().is_some() || (last && error.is_none()) {
+                    error = Some(LexError::InvalidUtf8);
+                }
+                let (valid, rest) = bytes.split_at(err.valid_up_to());
+                (str::from_utf8(valid).unwrap(), rest)
+            },
+        };
+        source.push(text);
+        source.error = error;
+        self.partial = rest.to_vec();
+    }
+
+    /// Parse as many results as the input fed so far allows
+    fn parse(&mut self) -> Vec<ParseResult> {
+        let mut results = Vec::new();
+        while !self.done {
+            let checkpoint = self.parser.clone();
+            let result = self.parser.next();
+            if mem::take(&mut self.parser.lexer_mut().source_mut().starved) {
+                self.parser = checkpoint;
+                break;
+            }
+            match result {
+                Some(result) => results.push(result),
+                None => self.done = true,
+            }
+        }
+        results
+    }
+}
+
+impl Default for PushParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::parser::{ParseEvent, ParsedValue};
+    use std::io::Cursor;
+
+    #[test]
+    fn results_are_produced_as_input_is_fed() {
+        let mut parser = PushParser::new();
+        let events = |results: Vec<ParseResult>| -> Vec<_> {
+            results.into_iter().map(|r| r.unwrap().0).collect()
+        };
+        assert_eq!(events(parser.feed(b"node {\n  ke")), vec![
+            ParseEvent::FileStart,
+            ParseEvent::NodeStart("node".to_string(), false),
+        ]);
+        assert_eq!(events(parser.feed(b"y = 'a")), vec![ParseEvent::Key("key".to_string(), false)]);
+        // the string could still go on to be concatenated with another one
+        assert!(parser.feed(b"b'\n").is_empty());
+        assert_eq!(events(parser.feed(b"}")), vec![
+            ParseEvent::Value(ParsedValue::Str("ab".to_string())),
+            ParseEvent::NodeEnd,
+        ]);
+        assert_eq!(events(parser.finish()), vec![ParseEvent::FileEnd]);
+    }
+
+    #[test]
+    fn matches_pull_parser() {
+        let inputs: &[(&[u8], usize)] = &[
+            ("node { 'é' = [1, 2.5, \"§\"]\n  sub { x = !y }\n}\nnode2 {}".as_bytes(), 100),
+            (b"\xEF\xBB\xBFnode { a = r#'raw'# }", 100),
+            (b"node { a = 'x\xFF' }", 100),
+            (b"node { a = '\xC3", 100),
+            (b"\xFF\xFE\x00n", 100),
+            (b"node { a = 1 } node { a = 2 }", 24),
+            (b"node { a = '\xC3\xA9' }", 13),
+        ];
+        for &(input, limit) in inputs {
+            let options = ParserOptions::new().max_document_size(limit);
+            let lexer = Lexer::lex(Cursor::new(input.to_vec()));
+            let expected: Vec<_> = Parser::with_options(lexer, options.clone()).collect();
+
+            // feed a byte at a time, splitting multi-byte characters
+            let mut parser = PushParser::with_options(options);
+            let mut results = Vec::new();
+            for &byte in input {
+                results.extend(parser.feed(&[byte]));
+            }
+            results.extend(parser.finish());
+            assert_eq!(results, expected, "{:?}", String::from_utf8_lossy(input));
+        }
+    }
+
+    #[test]
+    fn errors_end_parsing() {
+        let mut parser = PushParser::new();
+        let results = parser.feed(b"node { 1 }\n");
+        assert!(results.last().unwrap().is_err());
+        assert!(parser.feed(b"more {}\n").is_empty());
+        assert!(parser.finish().is_empty());
+    }
+}
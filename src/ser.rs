@@ -0,0 +1,851 @@
+use std::error::Error;
+use std::fmt;
+use std::io::Write;
+
+use serde::ser::{self, Impossible, Serialize};
+
+use super::de::ARGUMENTS_KEY;
+use super::parser::{ParseEvent, ParsedValue};
+use super::writer::{Writer, WriteError};
+
+/// The error produced when a value can't be serialized as a document
+#[derive(Debug)]
+pub enum SerializeError {
+    /// The document couldn't be written
+    Write(WriteError),
+    /// The value doesn't have the shape of a document, such as a string at the top level,
+    /// or a map used as a key
+    Message(String),
+}
+
+impl fmt::Display for SerializeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SerializeError::Write(ref error) => error.fmt(f),
+            SerializeError::Message(ref message) => f.write_str(message),
+        }
+    }
+}
+
+impl Error for SerializeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            SerializeError::Write(ref error) => Some(error),
+            SerializeError::Message(_) => None,
+        }
+    }
+}
+
+impl ser::Error for SerializeError {
+    fn custom<T: fmt::Display>(message: T) -> Self {
+        SerializeError::Message(message.to_string())
+    }
+}
+
+impl From<WriteError> for SerializeError {
+    fn from(error: WriteError) -> Self {
+        SerializeError::Write(error)
+    }
+}
+
+type Result<T> = ::std::result::Result<T, SerializeError>;
+
+/// Serialize `value` as a document, and write it to `output`
+///
+/// # Failures
+/// Fails if `value` doesn't have the shape of a document, or the output can't be
+/// written to.
+pub fn to_writer<W: Write, T: Serialize + ?Sized>(output: W, value: &T) -> Result<W> {
+    let mut serializer = Serializer::new(output);
+    value.serialize(&mut serializer)?;
+    serializer.into_inner()
+}
+
+/// Serialize `value` as a document in a string
+///
+/// # Examples
+/// ```
+/// # extern crate serde;
+/// # use serde::Serialize;
+/// #[derive(Serialize)]
+/// struct Server {
+///     host: &'static str,
+///     ports: Vec<u16>,
+///     upstream: Vec<Upstream>,
+/// }
+///
+/// #[derive(Serialize)]
+/// struct Upstream {
+///     weight: u8,
+/// }
+///
+/// let mut servers = std::collections::BTreeMap::new();
+/// servers.insert("server", Server {
+///     host: "example.com",
+///     ports: vec![80, 443],
+///     upstream: vec![Upstream { weight: 1 }, Upstream { weight: 2 }],
+/// });
+/// assert_eq!(figtree::to_string(&servers).unwrap(), "\
+/// server {
+///     host: \"example.com\",
+///     ports: [80, 443],
+///     upstream {
+///         weight: 1,
+///     }
+///     upstream {
+///         weight: 2,
+///     }
+/// }
+/// ");
+/// ```
+///
+/// # Failures
+/// Fails if `value` doesn't have the shape of a document.
+pub fn to_string<T: Serialize + ?Sized>(value: &T) -> Result<String> {
+    let output = to_writer(Vec::new(), value)?;
+    Ok(String::from_utf8(output).expect("documents are always written as UTF-8"))
+}
+
+/// Where the serializer is in the document
+#[derive(Debug)]
+enum Frame {
+    /// The top level, where every entry must be a node
+    Document,
+    /// The entries of a node, where maps are written as subnodes, and sequences of maps
+    /// as runs of nodes with the same name
+    Node,
+    /// A dict, where maps are written as dicts
+    Dict,
+    List,
+    /// A sequence in a node whose first item hasn't been seen yet, so it isn't known
+    /// whether it's a list or a run of nodes
+    Undecided(String),
+    /// A run of nodes with the same name
+    Run(String),
+    /// The arguments of a node
+    Arguments,
+}
+
+/// Serializes Rust types as documents, writing them to a `Writer` as they go.  This is
+/// the inverse of `Deserializer`, so a value written with it is read back the same way,
+/// and the two can be used with `serde_transcode` to convert between figtree and other
+/// formats without building a `Document` in between.
+///
+/// Only maps and structs can be serialized as documents.  Each entry of the map is written
+/// as a top-level node, and each entry of a node as:
+///
+/// - a subnode, if its value is a map or struct, or a run of subnodes with the same name,
+///   if its value is a sequence of maps or structs
+/// - the arguments of the node, if its key is `ARGUMENTS_KEY`
+/// - an attribute, otherwise.
+///
+/// Maps in attributes are written as dicts, unit variants of enums as identifiers
+/// (`!variant`), and other variants as maps with a single entry.
+pub struct Serializer<W: Write> {
+    writer: Writer<W>,
+    stack: Vec<Frame>,
+    /// the key of the node entry whose value is being serialized
+    key: Option<String>,
+}
+
+impl<W: Write> Serializer<W> {
+    /// Construct a serializer that writes to `output`
+    pub fn new(output: W) -> Self {
+        Self::from_writer(Writer::new(output))
+    }
+
+    /// Construct a serializer that writes events to a `Writer`, such as one with a
+    /// particular `FlushPolicy`
+    pub fn from_writer(writer: Writer<W>) -> Self {
+        Serializer { writer, stack: Vec::new(), key: None }
+    }
+
+    /// Stop writing, and get back the output
+    ///
+    /// # Failures
+    /// Fails if the output can't be flushed.
+    pub fn into_inner(self) -> Result<W> {
+        Ok(self.writer.into_inner()?)
+    }
+
+    fn write(&mut self, event: ParseEvent) -> Result<()> {
+        Ok(self.writer.write_event(event)?)
+    }
+
+    /// Decide what a sequence in a node is, now that its first item has been seen
+    fn decide(&mut self, map: bool) -> Result<()> {
+        if let Some(&Frame::Undecided(_)) = self.stack.last() {
+            if let Some(Frame::Undecided(name)) = self.stack.pop() {
+                if map {
+                    self.stack.push(Frame::Run(name));
+                } else {
+                    self.write(ParseEvent::Key(name, false))?;
+                    self.write(ParseEvent::ListStart)?;
+                    self.stack.push(Frame::List);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn key(&mut self) -> Result<String> {
+        self.key.take().ok_or_else(|| ser::Error::custom("a value was written before its key"))
+    }
+
+    fn scalar(&mut self, value: ParsedValue) -> Result<()> {
+        self.decide(false)?;
+        match self.stack.last() {
+            Some(&Frame::Node) => {
+                let key = self.key()?;
+                self.write(ParseEvent::Key(key, false))?;
+                self.write(ParseEvent::Value(value))
+            },
+            Some(&Frame::Dict) | Some(&Frame::List) => self.write(ParseEvent::Value(value)),
+            Some(&Frame::Arguments) => self.write(ParseEvent::Argument(value)),
+            Some(Frame::Run(name)) => Err(mixed(name)),
+            _ => Err(ser::Error::custom("only nodes can be written at the top level")),
+        }
+    }
+
+    fn start_map(&mut self) -> Result<()> {
+        self.decide(true)?;
+        let frame = match self.stack.last() {
+            None => {
+                self.write(ParseEvent::FileStart)?;
+                Frame::Document
+            },
+            Some(&Frame::Document) | Some(&Frame::Node) => {
+                let key = self.key()?;
+                if key == ARGUMENTS_KEY {
+                    return Err(ser::Error::custom("the arguments of a node must be a sequence"));
+                }
+                self.write(ParseEvent::NodeStart(key, false))?;
+                Frame::Node
+            },
+            Some(Frame::Run(name)) => {
+                let name = name.clone();
+                self.write(ParseEvent::NodeStart(name, false))?;
+                Frame::Node
+            },
+            Some(&Frame::Dict) | Some(&Frame::List) => {
+                self.write(ParseEvent::DictStart)?;
+                Frame::Dict
+            },
+            _ => return Err(ser::Error::custom("the arguments of a node must be values")),
+        };
+        self.stack.push(frame);
+        Ok(())
+    }
+
+    fn end_map(&mut self) -> Result<()> {
+        match self.stack.pop() {
+            Some(Frame::Document) => {
+                self.write(ParseEvent::FileEnd)?;
+                Ok(self.writer.flush()?)
+            },
+            Some(Frame::Node) => self.write(ParseEvent::NodeEnd),
+            Some(Frame::Dict) => self.write(ParseEvent::DictEnd),
+            frame => Err(ser::Error::custom(format_args!("unexpected end of map in {:?}", frame))),
+        }
+    }
+
+    fn map_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<()> {
+        let key = key.serialize(KeySerializer)?;
+        if let Some(&Frame::Dict) = self.stack.last() {
+            self.write(ParseEvent::Key(key, false))
+        } else {
+            self.key = Some(key);
+            Ok(())
+        }
+    }
+
+    fn start_seq(&mut self) -> Result<()> {
+        self.decide(false)?;
+        let frame = match self.stack.last() {
+            Some(&Frame::Document) => Frame::Run(self.key()?),
+            Some(&Frame::Node) => match self.key()? {
+                ref key if key == ARGUMENTS_KEY => Frame::Arguments,
+                key => Frame::Undecided(key),
+            },
+            Some(&Frame::Dict) | Some(&Frame::List) => {
+                self.write(ParseEvent::ListStart)?;
+                Frame::List
+            },
+            Some(&Frame::Arguments) =>
+                return Err(ser::Error::custom("the arguments of a node must be values")),
+            Some(Frame::Run(name)) => return Err(mixed(name)),
+            _ => return Err(ser::Error::custom("only nodes can be written at the top level")),
+        };
+        self.stack.push(frame);
+        Ok(())
+    }
+
+    fn end_seq(&mut self) -> Result<()> {
+        match self.stack.pop() {
+            Some(Frame::List) => self.write(ParseEvent::ListEnd),
+            Some(Frame::Undecided(name)) => {
+                self.write(ParseEvent::Key(name, false))?;
+                self.write(ParseEvent::ListStart)?;
+                self.write(ParseEvent::ListEnd)
+            },
+            Some(Frame::Run(_)) | Some(Frame::Arguments) => Ok(()),
+            frame => Err(ser::Error::custom(format_args!("unexpected end of sequence in {:?}",
+                                                         frame))),
+        }
+    }
+}
+
+impl<W: Write> ser::Serializer for &mut Serializer<W> {
+    type Ok = ();
+    type Error = SerializeError;
+    type SerializeSeq = Self;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Self;
+    type SerializeTupleVariant = Self;
+    type SerializeMap = Self;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Self;
+
+    fn serialize_bool(self, value: bool) -> Result<()> {
+        self.scalar(ParsedValue::Bool(value))
+    }
+
+    fn serialize_i8(self, value: i8) -> Result<()> {
+        self.serialize_i64(value.into())
+    }
+
+    fn serialize_i16(self, value: i16) -> Result<()> {
+        self.serialize_i64(value.into())
+    }
+
+    fn serialize_i32(self, value: i32) -> Result<()> {
+        self.serialize_i64(value.into())
+    }
+
+    fn serialize_i64(self, value: i64) -> Result<()> {
+        self.scalar(ParsedValue::Int(value))
+    }
+
+    fn serialize_u8(self, value: u8) -> Result<()> {
+        self.serialize_i64(value.into())
+    }
+
+    fn serialize_u16(self, value: u16) -> Result<()> {
+        self.serialize_i64(value.into())
+    }
+
+    fn serialize_u32(self, value: u32) -> Result<()> {
+        self.serialize_i64(value.into())
+    }
+
+    fn serialize_u64(self, value: u64) -> Result<()> {
+        if value > i64::MAX as u64 {
+            return Err(ser::Error::custom(format_args!("integer {} is out of range", value)));
+        }
+        self.serialize_i64(value as i64)
+    }
+
+    fn serialize_f32(self, value: f32) -> Result<()> {
+        self.serialize_f64(value.into())
+    }
+
+    fn serialize_f64(self, value: f64) -> Result<()> {
+        self.scalar(ParsedValue::Float(value))
+    }
+
+    fn serialize_char(self, value: char) -> Result<()> {
+        self.scalar(ParsedValue::Str(value.to_string()))
+    }
+
+    fn serialize_str(self, value: &str) -> Result<()> {
+        self.scalar(ParsedValue::Str(value.to_string()))
+    }
+
+    fn serialize_bytes(self, value: &[u8]) -> Result<()> {
+        self.scalar(ParsedValue::Bytes(value.to_vec()))
+    }
+
+    fn serialize_none(self) -> Result<()> {
+        self.scalar(ParsedValue::Null)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<()> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<()> {
+        self.scalar(ParsedValue::Null)
+    }
+
+    fn serialize_unit_struct(self, _: &'static str) -> Result<()> {
+        self.scalar(ParsedValue::Null)
+    }
+
+    fn serialize_unit_variant(self, _: &'static str, _: u32, variant: &'static str)
+                              -> Result<()> {
+        self.scalar(ParsedValue::Ident(variant.to_string()))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _: &'static str, value: &T)
+                                                       -> Result<()> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(self, _: &'static str, _: u32, variant: &'static str,
+                                    value: &T) -> Result<()> where T: Serialize + ?Sized {
+        self.start_map()?;
+        self.map_key(variant)?;
+        value.serialize(&mut *self)?;
+        self.end_map()
+    }
+
+    fn serialize_seq(self, _: Option<usize>) -> Result<Self> {
+        self.start_seq()?;
+        Ok(self)
+    }
+
+    fn serialize_tuple(self, _: usize) -> Result<Self> {
+        self.start_seq()?;
+        Ok(self)
+    }
+
+    fn serialize_tuple_struct(self, _: &'static str, _: usize) -> Result<Self> {
+        self.start_seq()?;
+        Ok(self)
+    }
+
+    fn serialize_tuple_variant(self, _: &'static str, _: u32, variant: &'static str, _: usize)
+                               -> Result<Self> {
+        self.start_map()?;
+        self.map_key(variant)?;
+        self.start_seq()?;
+        Ok(self)
+    }
+
+    fn serialize_map(self, _: Option<usize>) -> Result<Self> {
+        self.start_map()?;
+        Ok(self)
+    }
+
+    fn serialize_struct(self, _: &'static str, _: usize) -> Result<Self> {
+        self.start_map()?;
+        Ok(self)
+    }
+
+    fn serialize_struct_variant(self, _: &'static str, _: u32, variant: &'static str,
+                                _: usize) -> Result<Self> {
+        self.start_map()?;
+        self.map_key(variant)?;
+        self.start_map()?;
+        Ok(self)
+    }
+}
+
+impl<W: Write> ser::SerializeSeq for &mut Serializer<W> {
+    type Ok = ();
+    type Error = SerializeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        self.end_seq()
+    }
+}
+
+impl<W: Write> ser::SerializeTuple for &mut Serializer<W> {
+    type Ok = ();
+    type Error = SerializeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        self.end_seq()
+    }
+}
+
+impl<W: Write> ser::SerializeTupleStruct for &mut Serializer<W> {
+    type Ok = ();
+    type Error = SerializeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        self.end_seq()
+    }
+}
+
+impl<W: Write> ser::SerializeTupleVariant for &mut Serializer<W> {
+    type Ok = ();
+    type Error = SerializeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        self.end_seq()?;
+        self.end_map()
+    }
+}
+
+impl<W: Write> ser::SerializeMap for &mut Serializer<W> {
+    type Ok = ();
+    type Error = SerializeError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<()> {
+        self.map_key(key)
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        self.end_map()
+    }
+}
+
+impl<W: Write> ser::SerializeStruct for &mut Serializer<W> {
+    type Ok = ();
+    type Error = SerializeError;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
+            where T: Serialize + ?Sized {
+        self.map_key(key)?;
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        self.end_map()
+    }
+}
+
+impl<W: Write> ser::SerializeStructVariant for &mut Serializer<W> {
+    type Ok = ();
+    type Error = SerializeError;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
+            where T: Serialize + ?Sized {
+        self.map_key(key)?;
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        self.end_map()?;
+        self.end_map()
+    }
+}
+
+fn mixed(name: &str) -> SerializeError {
+    ser::Error::custom(format_args!("the items of `{}` must all be maps, or all be values", name))
+}
+
+/// Serializes the keys of maps, which can be strings, characters or integers
+struct KeySerializer;
+
+fn key_error() -> SerializeError {
+    ser::Error::custom("keys must be strings, characters or integers")
+}
+
+impl ser::Serializer for KeySerializer {
+    type Ok = String;
+    type Error = SerializeError;
+    type SerializeSeq = Impossible<String, SerializeError>;
+    type SerializeTuple = Impossible<String, SerializeError>;
+    type SerializeTupleStruct = Impossible<String, SerializeError>;
+    type SerializeTupleVariant = Impossible<String, SerializeError>;
+    type SerializeMap = Impossible<String, SerializeError>;
+    type SerializeStruct = Impossible<String, SerializeError>;
+    type SerializeStructVariant = Impossible<String, SerializeError>;
+
+    fn serialize_str(self, value: &str) -> Result<String> {
+        Ok(value.to_string())
+    }
+
+    fn serialize_char(self, value: char) -> Result<String> {
+        Ok(value.to_string())
+    }
+
+    fn serialize_i8(self, value: i8) -> Result<String> {
+        Ok(value.to_string())
+    }
+
+    fn serialize_i16(self, value: i16) -> Result<String> {
+        Ok(value.to_string())
+    }
+
+    fn serialize_i32(self, value: i32) -> Result<String> {
+        Ok(value.to_string())
+    }
+
+    fn serialize_i64(self, value: i64) -> Result<String> {
+        Ok(value.to_string())
+    }
+
+    fn serialize_u8(self, value: u8) -> Result<String> {
+        Ok(value.to_string())
+    }
+
+    fn serialize_u16(self, value: u16) -> Result<String> {
+        Ok(value.to_string())
+    }
+
+    fn serialize_u32(self, value: u32) -> Result<String> {
+        Ok(value.to_string())
+    }
+
+    fn serialize_u64(self, value: u64) -> Result<String> {
+        Ok(value.to_string())
+    }
+
+    fn serialize_unit_variant(self, _: &'static str, _: u32, variant: &'static str)
+                              -> Result<String> {
+        Ok(variant.to_string())
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _: &'static str, value: &T)
+                                                       -> Result<String> {
+        value.serialize(self)
+    }
+
+    fn serialize_bool(self, _: bool) -> Result<String> {
+        Err(key_error())
+    }
+
+    fn serialize_f32(self, _: f32) -> Result<String> {
+        Err(key_error())
+    }
+
+    fn serialize_f64(self, _: f64) -> Result<String> {
+        Err(key_error())
+    }
+
+    fn serialize_bytes(self, _: &[u8]) -> Result<String> {
+        Err(key_error())
+    }
+
+    fn serialize_none(self) -> Result<String> {
+        Err(key_error())
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, _: &T) -> Result<String> {
+        Err(key_error())
+    }
+
+    fn serialize_unit(self) -> Result<String> {
+        Err(key_error())
+    }
+
+    fn serialize_unit_struct(self, _: &'static str) -> Result<String> {
+        Err(key_error())
+    }
+
+    fn serialize_newtype_variant<T>(self, _: &'static str, _: u32, _: &'static str, _: &T)
+                                    -> Result<String> where T: Serialize + ?Sized {
+        Err(key_error())
+    }
+
+    fn serialize_seq(self, _: Option<usize>) -> Result<Self::SerializeSeq> {
+        Err(key_error())
+    }
+
+    fn serialize_tuple(self, _: usize) -> Result<Self::SerializeTuple> {
+        Err(key_error())
+    }
+
+    fn serialize_tuple_struct(self, _: &'static str, _: usize)
+                              -> Result<Self::SerializeTupleStruct> {
+        Err(key_error())
+    }
+
+    fn serialize_tuple_variant(self, _: &'static str, _: u32, _: &'static str, _: usize)
+                               -> Result<Self::SerializeTupleVariant> {
+        Err(key_error())
+    }
+
+    fn serialize_map(self, _: Option<usize>) -> Result<Self::SerializeMap> {
+        Err(key_error())
+    }
+
+    fn serialize_struct(self, _: &'static str, _: usize) -> Result<Self::SerializeStruct> {
+        Err(key_error())
+    }
+
+    fn serialize_struct_variant(self, _: &'static str, _: u32, _: &'static str, _: usize)
+                                -> Result<Self::SerializeStructVariant> {
+        Err(key_error())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate serde_json;
+    extern crate serde_transcode;
+
+    use super::*;
+    use super::super::de::Deserializer;
+    use serde::Deserialize;
+    use super::super::parser::ParserOptions;
+    use std::collections::BTreeMap;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Listener {
+        #[serde(rename = "$arguments")]
+        address: (String, u16),
+        mode: Mode,
+        fallback: Mode,
+        limits: BTreeMap<String, Option<u32>>,
+        backend: Vec<Backend>,
+        tags: Vec<char>,
+        empty: Vec<Backend>,
+        tls: Option<Tls>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Backend {
+        host: String,
+        weight: f64,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Tls {
+        cert: String,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    #[serde(rename_all = "lowercase")]
+    enum Mode {
+        Fast,
+        Custom(String),
+        Weighted { weights: Vec<u8> },
+    }
+
+    #[test]
+    fn serializes_structs() {
+        let listener = Listener {
+            address: ("localhost".into(), 80),
+            mode: Mode::Fast,
+            fallback: Mode::Weighted { weights: vec![1, 2] },
+            limits: vec![("a".to_string(), Some(1)), ("b c".to_string(), None)]
+                .into_iter().collect(),
+            backend: vec![
+                Backend { host: "a".into(), weight: 1.0 },
+                Backend { host: "b".into(), weight: 0.5 },
+            ],
+            tags: vec!['x', 'y'],
+            empty: vec![],
+            tls: None,
+        };
+        let mut document = BTreeMap::new();
+        document.insert("listen", listener);
+        let output = to_string(&document).unwrap();
+        assert_eq!(output, "\
+listen \"localhost\" 80 {
+    mode: !fast,
+    fallback {
+        weighted {
+            weights: [1, 2],
+        }
+    }
+    limits {
+        a: 1,
+        \"b c\": null,
+    }
+    backend {
+        host: \"a\",
+        weight: 1.0,
+    }
+    backend {
+        host: \"b\",
+        weight: 0.5,
+    }
+    tags: [\"x\", \"y\"],
+    empty: [],
+    tls: null,
+}
+");
+        let options = ParserOptions::new().node_arguments(true);
+        let mut deserializer = Deserializer::from_str_with_options(&output, options);
+        let read = BTreeMap::<&str, Listener>::deserialize(&mut deserializer).unwrap();
+        assert_eq!(read, document);
+
+        let listener = read.into_iter().next().unwrap().1;
+        let mut document = BTreeMap::new();
+        document.insert("l", Listener { mode: Mode::Custom("x".into()), ..listener });
+        let output = String::from_utf8(to_writer(Vec::new(), &document).unwrap()).unwrap();
+        assert!(output.contains("    mode {\n        custom: \"x\",\n    }\n"));
+        assert_eq!(to_string(&BTreeMap::<&str, ()>::new()).unwrap(), "");
+    }
+
+    #[test]
+    fn rejects_values_that_are_not_documents() {
+        let error = to_string(&1).unwrap_err();
+        assert_eq!(error.to_string(), "only nodes can be written at the top level");
+        let error = to_string(&vec![("a", 1)].into_iter().collect::<BTreeMap<_, _>>()).unwrap_err();
+        assert_eq!(error.to_string(), "only nodes can be written at the top level");
+
+        let mixed = serde_json::json!({ "a": { "b": [{}, 1] } });
+        assert_eq!(to_string(&mixed).unwrap_err().to_string(),
+                   "the items of `b` must all be maps, or all be values");
+        let key = vec![(vec![1], 1)].into_iter().collect::<BTreeMap<_, _>>();
+        let key = vec![("a", key)].into_iter().collect::<BTreeMap<_, _>>();
+        let error = to_string(&key).unwrap_err();
+        assert_eq!(error.to_string(), "keys must be strings, characters or integers");
+        #[derive(Serialize)]
+        struct Late {
+            b: u8,
+            #[serde(rename = "$arguments")]
+            arguments: (u8,),
+        }
+        let late = vec![("a", Late { b: 1, arguments: (2,) })].into_iter().collect::<BTreeMap<_, _>>();
+        let error = to_string(&late).unwrap_err();
+        assert!(matches!(error, SerializeError::Write(WriteError::UnexpectedEvent(_))));
+    }
+
+    #[test]
+    fn transcodes() {
+        let input = "
+            server 'a' {
+                ports = [80, 443], env = { HOME = '~' }
+                upstream { weight = 1 }
+                upstream { weight = 2 }
+                workers 4 {}
+            }
+            client {}
+        ";
+        let options = ParserOptions::new().node_arguments(true);
+        let mut deserializer = Deserializer::from_str_with_options(input, options);
+        let mut json = serde_json::Serializer::new(Vec::new());
+        serde_transcode::transcode(&mut deserializer, &mut json).unwrap();
+        let json = String::from_utf8(json.into_inner()).unwrap();
+        assert_eq!(json, concat!(r#"{"server":{"$arguments":["a"],"ports":[80,443],"env":{"HOME":"~"},"#,
+                                 r#""upstream":[{"weight":1},{"weight":2}],"workers":4},"client":{}}"#));
+
+        let mut json = serde_json::Deserializer::from_str(&json);
+        let mut serializer = Serializer::new(Vec::new());
+        serde_transcode::transcode(&mut json, &mut serializer).unwrap();
+        let output = String::from_utf8(serializer.into_inner().unwrap()).unwrap();
+        assert_eq!(output, "\
+server \"a\" {
+    ports: [80, 443],
+    env {
+        HOME: \"~\",
+    }
+    upstream {
+        weight: 1,
+    }
+    upstream {
+        weight: 2,
+    }
+    workers: 4,
+}
+client {}
+");
+    }
+}
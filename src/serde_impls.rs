@@ -0,0 +1,498 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::de::{self, Visitor};
+use serde::ser::{self, SerializeMap};
+
+use super::de::ARGUMENTS_KEY;
+use super::types::{Dict, Document, Node, Value};
+
+/// Values are serialized as the closest thing to them in the serde data model, so they
+/// can be written in other formats, and read back with `Deserialize`.  Identifiers and
+/// strings are both serialized as strings, and datetimes as strings in the same format
+/// they are written in; durations are serialized in the same way as `std::time::Duration`.
+impl ser::Serialize for Value {
+    fn serialize<S: ser::Serializer>(&self, serializer: S)
+                                     -> ::std::result::Result<S::Ok, S::Error> {
+        match *self {
+            Value::Str(ref string) | Value::Ident(ref string) => serializer.serialize_str(string),
+            Value::Int(int) => serializer.serialize_i64(int),
+            Value::Float(float) => serializer.serialize_f64(float),
+            Value::Bool(boolean) => serializer.serialize_bool(boolean),
+            Value::DateTime(ref datetime) => serializer.collect_str(datetime),
+            Value::Duration(ref duration) => ser::Serialize::serialize(duration, serializer),
+            Value::Bytes(ref bytes) => serializer.serialize_bytes(bytes),
+            Value::Dict(ref dict) => serializer.collect_map(dict),
+            Value::List(ref list) => serializer.collect_seq(list),
+            Value::Node(ref node) => ser::Serialize::serialize(&**node, serializer),
+            Value::Null => serializer.serialize_unit(),
+        }
+    }
+}
+
+/// Values can be deserialized from any self-describing format, and from documents
+/// without knowing their shape in advance.  Sequences are read as lists and maps as
+/// dicts, so a whole document is read as a dict of its nodes, and each node as a dict
+/// (see `Deserializer`).  Identifiers and datetimes are read as strings, as that's what
+/// they are to serde, durations as dicts of `secs` and `nanos`, and bytes from
+/// documents as lists of numbers, as most formats have no bytes.
+///
+/// # Examples
+/// ```
+/// # use figtree::types::Value;
+/// let doc: Value = figtree::from_str("a { b = [1, 'c'] } d {} d {}").unwrap();
+/// assert_eq!(doc["a"]["b"], Value::from(vec![Value::from(1), Value::from("c")]));
+/// assert_eq!(doc["d"].get_list().map(|nodes| nodes.len()), Some(2));
+/// ```
+impl<'de> de::Deserialize<'de> for Value {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D)
+                                             -> ::std::result::Result<Self, D::Error> {
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
+struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("any value")
+    }
+
+    fn visit_bool<E: de::Error>(self, value: bool) -> ::std::result::Result<Value, E> {
+        Ok(Value::Bool(value))
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> ::std::result::Result<Value, E> {
+        Ok(Value::Int(value))
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> ::std::result::Result<Value, E> {
+        if value > i64::MAX as u64 {
+            return Err(E::invalid_value(de::Unexpected::Unsigned(value), &"a 64-bit integer"));
+        }
+        Ok(Value::Int(value as i64))
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> ::std::result::Result<Value, E> {
+        Ok(Value::Float(value))
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> ::std::result::Result<Value, E> {
+        Ok(Value::Str(value.to_string()))
+    }
+
+    fn visit_string<E: de::Error>(self, value: String) -> ::std::result::Result<Value, E> {
+        Ok(Value::Str(value))
+    }
+
+    fn visit_bytes<E: de::Error>(self, value: &[u8]) -> ::std::result::Result<Value, E> {
+        Ok(Value::Bytes(value.to_vec()))
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, value: Vec<u8>) -> ::std::result::Result<Value, E> {
+        Ok(Value::Bytes(value))
+    }
+
+    fn visit_none<E: de::Error>(self) -> ::std::result::Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_unit<E: de::Error>(self) -> ::std::result::Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_some<D: de::Deserializer<'de>>(self, deserializer: D)
+                                           -> ::std::result::Result<Value, D::Error> {
+        de::Deserialize::deserialize(deserializer)
+    }
+
+    fn visit_newtype_struct<D: de::Deserializer<'de>>(self, deserializer: D)
+                                                     -> ::std::result::Result<Value, D::Error> {
+        de::Deserialize::deserialize(deserializer)
+    }
+
+    fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A)
+                                       -> ::std::result::Result<Value, A::Error> {
+        let mut list = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(item) = seq.next_element()? {
+            list.push(item);
+        }
+        Ok(Value::List(list))
+    }
+
+    fn visit_map<A: de::MapAccess<'de>>(self, mut map: A)
+                                       -> ::std::result::Result<Value, A::Error> {
+        let mut dict = Dict::with_capacity(map.size_hint().unwrap_or(0));
+        while let Some((key, value)) = map.next_entry()? {
+            dict.insert(key, value);
+        }
+        Ok(Value::Dict(dict))
+    }
+}
+
+
+/// Group nodes by their names, in the order that each name first appears in
+fn group_nodes<'a, I>(nodes: I) -> Vec<(&'a str, Vec<&'a Node>)>
+        where I: IntoIterator<Item = &'a Node> {
+    let mut groups: Vec<(&str, Vec<&Node>)> = Vec::new();
+    let mut indices = HashMap::new();
+    for node in nodes {
+        let index = *indices.entry(node.name()).or_insert_with(|| {
+            groups.push((node.name(), Vec::new()));
+            groups.len() - 1
+        });
+        groups[index].1.push(node);
+    }
+    groups
+}
+
+/// Serialize groups of nodes with the same name as entries of a map, with a single node
+/// as a map and several as a sequence of maps
+fn serialize_groups<M: SerializeMap>(map: &mut M, groups: Vec<(&str, Vec<&Node>)>)
+                                     -> ::std::result::Result<(), M::Error> {
+    for (name, nodes) in groups {
+        match nodes.as_slice() {
+            [node] => map.serialize_entry(name, node)?,
+            nodes => map.serialize_entry(name, nodes)?,
+        }
+    }
+    Ok(())
+}
+
+/// Nodes are serialized in the same way as the `Deserializer` reads them: as maps of
+/// their attributes and subnodes, with any arguments under `ARGUMENTS_KEY`.  Subnodes that
+/// share a name are serialized together, as a sequence, under the first place that name
+/// appears.  The node's own name isn't serialized, as it belongs to whatever contains the
+/// node.
+///
+/// When a node is deserialized, maps are read as subnodes, sequences of maps as runs of
+/// subnodes, and anything else as an attribute.  This means that a dict attribute, or a
+/// list of dicts, comes back as subnodes instead.
+///
+/// # Examples
+/// ```
+/// # extern crate serde_json;
+/// # use figtree::types::{Node, Value};
+/// let mut node = Node::new();
+/// node.push_argument(Value::new_int(8080));
+/// node.insert_attr("host", Value::from("example.com"));
+/// node.append_node("upstream", Node::new());
+/// node.append_node("upstream", Node::new());
+/// let json = serde_json::to_string(&node).unwrap();
+/// assert_eq!(json, r#"{"$arguments":[8080],"host":"example.com","upstream":[{},{}]}"#);
+/// assert_eq!(serde_json::from_str::<Node>(&json).unwrap(), node);
+/// ```
+impl ser::Serialize for Node {
+    fn serialize<S: ser::Serializer>(&self, serializer: S)
+                                     -> ::std::result::Result<S::Ok, S::Error> {
+        let groups = group_nodes(self.children());
+        let arguments = if self.arguments().is_empty() { 0 } else { 1 };
+        let len = arguments + self.iter_attrs().len() + groups.len();
+        let mut map = serializer.serialize_map(Some(len))?;
+        if !self.arguments().is_empty() {
+            map.serialize_entry(ARGUMENTS_KEY, self.arguments())?;
+        }
+        for (key, value) in self.iter_attrs() {
+            map.serialize_entry(key.as_str(), value)?;
+        }
+        serialize_groups(&mut map, groups)?;
+        map.end()
+    }
+}
+
+/// Documents are serialized as maps of their nodes, in the same way as the subnodes of a
+/// `Node`.  When a document is deserialized, anything that isn't a map or a sequence of
+/// maps is read as a node with that as its single argument, as the `Deserializer` reads
+/// a node like `port 8080 {}` as its argument.
+impl ser::Serialize for Document {
+    fn serialize<S: ser::Serializer>(&self, serializer: S)
+                                     -> ::std::result::Result<S::Ok, S::Error> {
+        let groups = group_nodes(self.children());
+        let mut map = serializer.serialize_map(Some(groups.len()))?;
+        serialize_groups(&mut map, groups)?;
+        map.end()
+    }
+}
+
+/// The value of an entry of a node or document, before it's known which it is
+enum Entry {
+    Node(Box<Node>),
+    Run(Vec<Node>),
+    Value(Value),
+}
+
+impl Entry {
+    fn into_value(self) -> Value {
+        match self {
+            Entry::Node(node) => node_value(*node),
+            Entry::Run(nodes) => Value::List(nodes.into_iter().map(node_value).collect()),
+            Entry::Value(value) => value,
+        }
+    }
+}
+
+/// Turn a node that was read from a list back into a dict
+fn node_value(node: Node) -> Value {
+    Value::Dict(node.to_dict())
+}
+
+impl<'de> de::Deserialize<'de> for Entry {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D)
+                                             -> ::std::result::Result<Self, D::Error> {
+        deserializer.deserialize_any(EntryVisitor)
+    }
+}
+
+struct EntryVisitor;
+
+macro_rules! visit_value {
+    ($($method:ident($ty:ty))*) => {
+        $(
+            fn $method<E: de::Error>(self, value: $ty) -> ::std::result::Result<Entry, E> {
+                ValueVisitor.$method(value).map(Entry::Value)
+            }
+        )*
+    };
+}
+
+impl<'de> Visitor<'de> for EntryVisitor {
+    type Value = Entry;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a node or a value")
+    }
+
+    visit_value! {
+        visit_bool(bool) visit_i64(i64) visit_u64(u64) visit_f64(f64) visit_str(&str)
+        visit_string(String) visit_bytes(&[u8]) visit_byte_buf(Vec<u8>)
+    }
+
+    fn visit_none<E: de::Error>(self) -> ::std::result::Result<Entry, E> {
+        Ok(Entry::Value(Value::Null))
+    }
+
+    fn visit_unit<E: de::Error>(self) -> ::std::result::Result<Entry, E> {
+        Ok(Entry::Value(Value::Null))
+    }
+
+    fn visit_some<D: de::Deserializer<'de>>(self, deserializer: D)
+                                           -> ::std::result::Result<Entry, D::Error> {
+        de::Deserialize::deserialize(deserializer)
+    }
+
+    fn visit_newtype_struct<D: de::Deserializer<'de>>(self, deserializer: D)
+                                                     -> ::std::result::Result<Entry, D::Error> {
+        de::Deserialize::deserialize(deserializer)
+    }
+
+    /// A run of nodes if every item is a map, and a list otherwise
+    fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A)
+                                       -> ::std::result::Result<Entry, A::Error> {
+        let mut entries = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(entry) = seq.next_element()? {
+            entries.push(entry);
+        }
+        if entries.is_empty() || entries.iter().any(|entry| !matches!(entry, Entry::Node(_))) {
+            return Ok(Entry::Value(Value::List(entries.into_iter().map(Entry::into_value)
+                                                      .collect())));
+        }
+        let nodes = entries.into_iter().filter_map(|entry| match entry {
+            Entry::Node(node) => Some(*node),
+            _ => None,
+        });
+        Ok(Entry::Run(nodes.collect()))
+    }
+
+    fn visit_map<A: de::MapAccess<'de>>(self, map: A)
+                                       -> ::std::result::Result<Entry, A::Error> {
+        NodeVisitor.visit_map(map).map(|node| Entry::Node(Box::new(node)))
+    }
+}
+
+impl<'de> de::Deserialize<'de> for Node {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D)
+                                             -> ::std::result::Result<Self, D::Error> {
+        deserializer.deserialize_map(NodeVisitor)
+    }
+}
+
+struct NodeVisitor;
+
+impl<'de> Visitor<'de> for NodeVisitor {
+    type Value = Node;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a node")
+    }
+
+    fn visit_map<A: de::MapAccess<'de>>(self, mut map: A)
+                                       -> ::std::result::Result<Node, A::Error> {
+        let mut node = Node::new();
+        while let Some(key) = map.next_key::<String>()? {
+            if key == ARGUMENTS_KEY {
+                let arguments: Vec<Value> = map.next_value()?;
+                node.arguments_mut().extend(arguments);
+                continue;
+            }
+            match map.next_value()? {
+                Entry::Node(child) => {
+                    node.append_node(key, *child);
+                },
+                Entry::Run(children) => for child in children {
+                    node.append_node(key.as_str(), child);
+                },
+                Entry::Value(value) => {
+                    node.insert_attr(key, value);
+                },
+            }
+        }
+        Ok(node)
+    }
+}
+
+impl<'de> de::Deserialize<'de> for Document {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D)
+                                             -> ::std::result::Result<Self, D::Error> {
+        deserializer.deserialize_map(DocumentVisitor)
+    }
+}
+
+struct DocumentVisitor;
+
+impl<'de> Visitor<'de> for DocumentVisitor {
+    type Value = Document;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a document")
+    }
+
+    fn visit_map<A: de::MapAccess<'de>>(self, mut map: A)
+                                       -> ::std::result::Result<Document, A::Error> {
+        let mut document = Document::new();
+        while let Some(name) = map.next_key::<String>()? {
+            if name == ARGUMENTS_KEY {
+                return Err(de::Error::custom("a document can't have arguments"));
+            }
+            match map.next_value()? {
+                Entry::Node(node) => {
+                    document.append_node(name, *node);
+                },
+                Entry::Run(nodes) => for node in nodes {
+                    document.append_node(name.as_str(), node);
+                },
+                Entry::Value(value) => {
+                    let mut node = Node::new();
+                    node.push_argument(value);
+                    document.append_node(name, node);
+                },
+            }
+        }
+        Ok(document)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate serde_json;
+
+    use super::*;
+    use serde::Deserialize;
+    use std::time::Duration;
+
+    #[test]
+    fn round_trips_documents() {
+        let input = "
+            server {
+                host: 'example.com',
+                ports: [80, 443],
+                timeout: 1s,
+                mode: !fast,
+                upstream { weight: 1 }
+                upstream { weight: 2 }
+                tls { cert: 'a.pem' }
+            }
+            empty {}
+        ";
+        let options = ::ParserOptions::new().repeated_nodes(true);
+        let document = ::Figtree::from_string(input).with_options(options).parse().unwrap();
+        let json = serde_json::to_value(&document).unwrap();
+        assert_eq!(json, serde_json::json!({
+            "server": {
+                "host": "example.com",
+                "ports": [80, 443],
+                "timeout": { "secs": 1, "nanos": 0 },
+                "mode": "fast",
+                "upstream": [{ "weight": 1 }, { "weight": 2 }],
+                "tls": { "cert": "a.pem" },
+            },
+            "empty": {},
+        }));
+
+        let back: Document = serde_json::from_value(json).unwrap();
+        let server = back.get_node("server").unwrap();
+        assert_eq!(server.get_str("host"), Ok("example.com"));
+        assert_eq!(server.children_named("upstream").count(), 2);
+        assert_eq!(server.child("tls").and_then(|tls| tls.get("cert")), Some(&"a.pem".into()));
+        // identifiers and durations can't be told apart from strings and dicts any more
+        assert_eq!(server.get("mode"), Some(&"fast".into()));
+        assert!(server.get_dict("timeout").is_err());
+        assert_eq!(server.child("timeout").and_then(|timeout| timeout.get("secs")),
+                   Some(&Value::new_int(1)));
+
+        // through figtree itself, nodes and values keep their places
+        let figtree = ::to_string(&document).unwrap();
+        let back: Document = ::from_str(&figtree).unwrap();
+        assert_eq!(back.get_node("empty"), document.get_node("empty"));
+        let original = document.get_node("server").unwrap();
+        let server = back.get_node("server").unwrap();
+        assert_eq!(server.get("ports"), original.get("ports"));
+        assert_eq!(server.children_named("upstream").collect::<Vec<_>>(),
+                   original.children_named("upstream").collect::<Vec<_>>());
+        let attrs: Vec<_> = server.iter_attrs().map(|(key, _)| key.to_string()).collect();
+        assert_eq!(attrs, vec!["host", "ports", "mode"]);
+    }
+
+    #[test]
+    fn deserializes_embedded_nodes() {
+        #[derive(Deserialize)]
+        struct Config {
+            name: String,
+            plugins: Node,
+            extra: Value,
+        }
+
+        let input = "
+            config {
+                name = 'x',
+                extra = [1, { a = null }],
+                plugins {
+                    cache { size = 10 }
+                    log 'debug' {}
+                }
+            }
+        ";
+        let options = ::ParserOptions::new().node_arguments(true);
+        let mut deserializer = ::Deserializer::from_str_with_options(input, options);
+        let config = HashMap::<String, Config>::deserialize(&mut deserializer).unwrap();
+        let config = &config["config"];
+        assert_eq!(config.name, "x");
+        let mut dict = Dict::new();
+        dict.insert("a".into(), Value::Null);
+        assert_eq!(config.extra, Value::List(vec![1.into(), dict.into()]));
+        let cache = config.plugins.child("cache").unwrap();
+        assert_eq!(cache.get("size"), Some(&Value::new_int(10)));
+        // a node with nothing but an argument is read as that argument
+        assert_eq!(config.plugins.get("log"), Some(&"debug".into()));
+
+        let document: Document = serde_json::from_str(r#"{"port": 8080, "a": [{}, 1]}"#).unwrap();
+        assert_eq!(document.get_node("port").map(Node::arguments), Some(&[8080.into()][..]));
+        assert_eq!(document.get_node("a").map(Node::arguments),
+                   Some(&[Value::List(vec![Dict::new().into(), 1.into()])][..]));
+        let error = serde_json::from_str::<Document>(r#"{"$arguments": [1]}"#).unwrap_err();
+        assert!(error.to_string().starts_with("a document can't have arguments"));
+        assert_eq!(serde_json::to_string(&Value::new_duration(Duration::from_millis(1500)))
+                       .unwrap(), r#"{"secs":1,"nanos":500000000}"#);
+    }
+}
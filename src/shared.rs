@@ -0,0 +1,94 @@
+use std::ops::Deref;
+use std::sync::Arc;
+
+use super::types::Document;
+
+/// A read-only document that can be shared between threads.  Created by
+/// `Document::freeze`.
+///
+/// Cloning a shared document only copies a pointer to it, so each worker thread can
+/// have its own handle to the same config.  It dereferences to a `Document`, so all of
+/// the usual lookups work on it directly.
+///
+/// # Examples
+/// ```
+/// # use figtree::types::*;
+/// # use std::io::Cursor;
+/// # use std::thread;
+/// let config = Document::parse(Cursor::new("server { port = 80 }")).unwrap().freeze();
+/// let workers: Vec<_> = (0..4).map(|_| {
+///     let config = config.clone();
+///     thread::spawn(move || config["server"].get_i64("port").unwrap())
+/// }).collect();
+/// for worker in workers {
+///     assert_eq!(worker.join().unwrap(), 80);
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct SharedDocument {
+    document: Arc<Document>,
+}
+
+impl SharedDocument {
+    /// Get a mutable copy of the document.  The document is only copied if there are
+    /// other handles to it.
+    pub fn thaw(self) -> Document {
+        Arc::try_unwrap(self.document).unwrap_or_else(|document| (*document).clone())
+    }
+
+    /// Test if two handles share the same document
+    pub fn ptr_eq(&self, other: &SharedDocument) -> bool {
+        Arc::ptr_eq(&self.document, &other.document)
+    }
+}
+
+impl Deref for SharedDocument {
+    type Target = Document;
+
+    fn deref(&self) -> &Document {
+        &self.document
+    }
+}
+
+impl From<Document> for SharedDocument {
+    fn from(document: Document) -> Self {
+        SharedDocument { document: Arc::new(document) }
+    }
+}
+
+impl Document {
+    /// Turn the document into a `SharedDocument`, which can't be changed, but which can
+    /// be cheaply cloned and shared between threads.
+    pub fn freeze(self) -> SharedDocument {
+        SharedDocument::from(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use std::thread;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn shares_between_threads() {
+        assert_send_sync::<SharedDocument>();
+
+        let doc = Document::parse(Cursor::new("a { b = [1, 2] } c {}")).unwrap();
+        let shared = doc.clone().freeze();
+        let copy = shared.clone();
+        assert!(copy.ptr_eq(&shared));
+        let lengths = thread::spawn(move || copy["a"].get_list("b").unwrap().len()).join();
+        assert_eq!(lengths.unwrap(), 2);
+
+        assert_eq!(*shared, doc);
+        assert!(shared.has_node("c"));
+        let other = shared.clone();
+        let mut thawed = shared.thaw();
+        thawed.delete_node("c");
+        assert!(other.has_node("c"));
+        assert_eq!(other.thaw(), doc);
+    }
+}
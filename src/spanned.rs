@@ -0,0 +1,192 @@
+use std::fmt;
+
+use serde::de::{self, Visitor};
+use serde::ser;
+
+use super::position::{Position, Span};
+
+/// The name of the struct that a `Spanned` is deserialized as, which the `Deserializer`
+/// looks out for
+pub const NAME: &str = "$__figtree_private_Spanned";
+pub const START_LINE: &str = "$__figtree_private_start_line";
+pub const START_POS: &str = "$__figtree_private_start_pos";
+pub const END_LINE: &str = "$__figtree_private_end_line";
+pub const END_POS: &str = "$__figtree_private_end_pos";
+pub const VALUE: &str = "$__figtree_private_value";
+pub const FIELDS: &[&str] = &[START_LINE, START_POS, END_LINE, END_POS, VALUE];
+
+/// A value deserialized along with the span of the document it came from, so that
+/// problems found with the value after it's been deserialized can still point to where
+/// it was written.
+///
+/// The span of an attribute's value, or an item in a list or dict, covers the value
+/// itself; the span of a node covers the whole of the node, from its name to its closing
+/// brace (or the whole run of nodes, if it's deserialized as a sequence).  Spans are only
+/// known to the figtree `Deserializer`, so deserializing a `Spanned` with any other
+/// deserializer fails.  Serializing one serializes the value on its own.
+///
+/// # Examples
+/// ```
+/// # extern crate serde;
+/// # use serde::Deserialize;
+/// # use figtree::{Position, Spanned};
+/// #[derive(Deserialize)]
+/// struct Config {
+///     server: Server,
+/// }
+///
+/// #[derive(Deserialize)]
+/// struct Server {
+///     port: Spanned<u16>,
+/// }
+///
+/// let config: Config = figtree::from_str("server { port = 80 }").unwrap();
+/// let port = &config.server.port;
+/// assert_eq!(port.span().start, Position::at(0, 16));
+/// if *port.get_ref() < 1024 {
+///     let line = port.span().start.line + 1;
+///     let message = format!("port {} on line {} is reserved", port.get_ref(), line);
+///     assert_eq!(message, "port 80 on line 1 is reserved");
+/// }
+/// ```
+#[derive(Debug, PartialEq, Clone)]
+pub struct Spanned<T> {
+    span: Span,
+    value: T,
+}
+
+impl<T> Spanned<T> {
+    /// Construct a value with a span
+    pub fn new(value: T, span: Span) -> Self {
+        Spanned { span, value }
+    }
+
+    /// The span of the document that the value came from
+    pub fn span(&self) -> &Span {
+        &self.span
+    }
+
+    /// Get a reference to the value
+    pub fn get_ref(&self) -> &T {
+        &self.value
+    }
+
+    /// Get a mutable reference to the value
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+
+    /// Take the value, dropping its span
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+impl<'de, T: de::Deserialize<'de>> de::Deserialize<'de> for Spanned<T> {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D)
+                                             -> ::std::result::Result<Self, D::Error> {
+        deserializer.deserialize_struct(NAME, FIELDS, SpannedVisitor(Default::default()))
+    }
+}
+
+struct SpannedVisitor<T>(::std::marker::PhantomData<T>);
+
+impl<'de, T: de::Deserialize<'de>> Visitor<'de> for SpannedVisitor<T> {
+    type Value = Spanned<T>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a value with a span, from a figtree document")
+    }
+
+    fn visit_map<A: de::MapAccess<'de>>(self, mut map: A)
+                                       -> ::std::result::Result<Spanned<T>, A::Error> {
+        let (mut start, mut end) = (Position::new(), Position::new());
+        let mut value = None;
+        while let Some(key) = map.next_key::<&str>()? {
+            match key {
+                START_LINE => start.line = map.next_value()?,
+                START_POS => start.pos = map.next_value()?,
+                END_LINE => end.line = map.next_value()?,
+                END_POS => end.pos = map.next_value()?,
+                VALUE => value = Some(map.next_value()?),
+                _ => return Err(de::Error::custom("a span can only be read from figtree")),
+            }
+        }
+        match value {
+            Some(value) => Ok(Spanned::new(value, Span::new(start, end))),
+            None => Err(de::Error::custom("a span can only be read from figtree")),
+        }
+    }
+}
+
+impl<T: ser::Serialize> ser::Serialize for Spanned<T> {
+    fn serialize<S: ser::Serializer>(&self, serializer: S)
+                                     -> ::std::result::Result<S::Ok, S::Error> {
+        self.value.serialize(serializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate serde_json;
+
+    use super::*;
+    use super::super::types::Value;
+
+    #[derive(Debug, Deserialize)]
+    struct Document {
+        a: Spanned<A>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct A {
+        b: Spanned<Vec<Spanned<Value>>>,
+        c: Spanned<Vec<Spanned<C>>>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct C {
+        #[serde(default)]
+        d: Option<Spanned<i64>>,
+    }
+
+    fn span(start: (usize, usize), end: (usize, usize)) -> Span {
+        Span::new(Position::at(start.0, start.1), Position::at(end.0, end.1))
+    }
+
+    #[test]
+    fn deserializes_spans() {
+        let input = concat!(
+            "a {\n",
+            "    b = [1, 'two']\n",
+            "    c { d = 3 }\n",
+            "    c {}\n",
+            "}\n",
+        );
+        let document: Spanned<Document> = ::from_str(input).unwrap();
+        assert_eq!(document.span(), &span((0, 0), (5, 0)));
+        let a = &document.get_ref().a;
+        assert_eq!(a.span(), &span((0, 0), (4, 1)));
+
+        let b = &a.get_ref().b;
+        assert_eq!(b.span(), &span((1, 8), (1, 18)));
+        let items: Vec<_> = b.get_ref().iter().map(|item| item.span().clone()).collect();
+        assert_eq!(items, vec![span((1, 9), (1, 10)), span((1, 12), (1, 17))]);
+        assert_eq!(b.get_ref()[1].get_ref(), &Value::from("two"));
+
+        let c = &a.get_ref().c;
+        assert_eq!(c.span(), &span((2, 4), (3, 8)));
+        let nodes: Vec<_> = c.get_ref().iter().map(|node| node.span().clone()).collect();
+        assert_eq!(nodes, vec![span((2, 4), (2, 15)), span((3, 4), (3, 8))]);
+        let d = c.get_ref()[0].get_ref().d.clone().unwrap();
+        assert_eq!(d, Spanned::new(3, span((2, 12), (2, 13))));
+        assert_eq!(c.get_ref()[1].get_ref().d, None);
+    }
+
+    #[test]
+    fn needs_figtree_for_spans() {
+        let error = serde_json::from_str::<Spanned<i64>>("1").unwrap_err();
+        assert!(error.to_string().contains("a value with a span"));
+        assert_eq!(serde_json::to_string(&Spanned::new(1, span((0, 0), (0, 1)))).unwrap(), "1");
+    }
+}
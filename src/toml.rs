@@ -0,0 +1,1074 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fmt::Write;
+
+use super::position::Position;
+use super::types::{Dict, Document, Node, Value};
+
+/// The key that a node's arguments are written under, the same as the `ARGUMENTS_KEY` that
+/// serde uses
+const ARGUMENTS_KEY: &str = "$arguments";
+
+/// The error produced when TOML can't be imported as a `Document`, or a document can't be
+/// written as TOML
+#[derive(Debug, PartialEq, Clone)]
+pub struct TomlError {
+    /// What the problem is
+    pub message: String,
+    /// Where the problem is in the TOML, or `None` if the problem isn't with the syntax of
+    /// the TOML
+    pub position: Option<Position>,
+}
+
+impl TomlError {
+    fn new<S: Into<String>>(message: S, position: Position) -> Self {
+        TomlError { message: message.into(), position: Some(position) }
+    }
+}
+
+impl fmt::Display for TomlError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)?;
+        if let Some(ref position) = self.position {
+            write!(f, " (line {}, column {})", position.line + 1, position.pos + 1)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for TomlError {}
+
+/// How a table was first defined, which decides how it can be added to later
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Origin {
+    /// Only as part of the header of another table (`a` in `[a.b]`), so it can still be
+    /// given a header of its own
+    Implicit,
+    /// With a header (`[a]`)
+    Header,
+    /// With a dotted key (`a.b = 1`)
+    Dotted,
+}
+
+/// A table, as it's read
+#[derive(Debug)]
+struct Table {
+    origin: Origin,
+    entries: Vec<(String, Item)>,
+}
+
+/// An entry of a table.  Inline tables and arrays are values, and only tables and
+/// arrays of tables with headers (or dotted keys) are kept apart.
+#[derive(Debug)]
+enum Item {
+    Value(Value),
+    Table(Table),
+    Tables(Vec<Table>),
+}
+
+impl Table {
+    fn new(origin: Origin) -> Self {
+        Table { origin, entries: Vec::new() }
+    }
+
+    fn position(&self, key: &str) -> Option<usize> {
+        self.entries.iter().position(|entry| entry.0 == key)
+    }
+}
+
+/// Find the table that `key` leads to, creating it if it doesn't exist.  Dotted keys can't
+/// add to tables that have headers, or to arrays of tables, but headers lead to the last
+/// table of an array of tables.
+fn subtable<'a>(table: &'a mut Table, key: &str, dotted: bool) -> Result<&'a mut Table, String> {
+    let index = match table.position(key) {
+        Some(index) => index,
+        None => {
+            let origin = if dotted { Origin::Dotted } else { Origin::Implicit };
+            table.entries.push((key.to_string(), Item::Table(Table::new(origin))));
+            table.entries.len() - 1
+        },
+    };
+    match table.entries[index].1 {
+        Item::Table(ref mut table) if dotted && table.origin == Origin::Header => {
+            Err(format!("table `{}` is already defined", key))
+        },
+        Item::Table(ref mut table) => Ok(table),
+        Item::Tables(_) if dotted => Err(format!("`{}` is already an array of tables", key)),
+        Item::Tables(ref mut tables) => {
+            Ok(tables.last_mut().expect("arrays of tables aren't empty"))
+        },
+        Item::Value(_) => Err(format!("`{}` is already a value", key)),
+    }
+}
+
+/// Define the table, or add a table to the array of tables, that a header leads to
+fn define(root: &mut Table, path: &[String], array: bool) -> Result<(), String> {
+    let (last, parents) = path.split_last().expect("keys aren't empty");
+    let mut table = root;
+    for key in parents {
+        table = subtable(table, key, false)?;
+    }
+    let index = match table.position(last) {
+        Some(index) => index,
+        None => {
+            let item = if array {
+                Item::Tables(vec![Table::new(Origin::Header)])
+            } else {
+                Item::Table(Table::new(Origin::Header))
+            };
+            table.entries.push((last.clone(), item));
+            return Ok(());
+        },
+    };
+    match (&mut table.entries[index].1, array) {
+        (&mut Item::Table(ref mut table), false) if table.origin == Origin::Implicit => {
+            table.origin = Origin::Header;
+            Ok(())
+        },
+        (&mut Item::Tables(ref mut tables), true) => {
+            tables.push(Table::new(Origin::Header));
+            Ok(())
+        },
+        (&mut Item::Table(_), _) => Err(format!("table `{}` is already defined", path.join("."))),
+        (&mut Item::Tables(_), _) => {
+            Err(format!("`{}` is already an array of tables", path.join(".")))
+        },
+        (&mut Item::Value(_), _) => Err(format!("`{}` is already a value", path.join("."))),
+    }
+}
+
+/// Add a value to a table, under a key that might be dotted
+fn insert(table: &mut Table, keys: &[String], value: Value) -> Result<(), String> {
+    let (last, parents) = keys.split_last().expect("keys aren't empty");
+    let mut table = table;
+    for key in parents {
+        table = subtable(table, key, true)?;
+    }
+    if table.position(last).is_some() {
+        return Err(format!("duplicate key `{}`", last));
+    }
+    table.entries.push((last.clone(), Item::Value(value)));
+    Ok(())
+}
+
+/// Add a value to an inline table, under a key that might be dotted
+fn insert_dict(dict: &mut Dict, keys: &[String], value: Value) -> Result<(), String> {
+    let (last, parents) = keys.split_last().expect("keys aren't empty");
+    let mut dict = dict;
+    for key in parents {
+        dict = match *dict.entry(key.clone()).or_insert_with(|| Value::Dict(Dict::new())) {
+            Value::Dict(ref mut dict) => dict,
+            _ => return Err(format!("`{}` is already a value", key)),
+        };
+    }
+    if dict.contains_key(last) {
+        return Err(format!("duplicate key `{}`", last));
+    }
+    dict.insert(last.clone(), value);
+    Ok(())
+}
+
+/// Check if text is a full date (`1979-05-27`)
+fn is_date(text: &str) -> bool {
+    let bytes = text.as_bytes();
+    bytes.len() == 10 && bytes.iter().enumerate().all(|(index, &byte)| match index {
+        4 | 7 => byte == b'-',
+        _ => byte.is_ascii_digit(),
+    })
+}
+
+/// Check if text starts with a time (`07:32:00`)
+fn is_time(text: &str) -> bool {
+    let bytes = text.as_bytes();
+    bytes.len() >= 8 && bytes[..8].iter().enumerate().all(|(index, &byte)| match index {
+        2 | 5 => byte == b':',
+        _ => byte.is_ascii_digit(),
+    })
+}
+
+/// Remove the underscores from digits, checking that each one is between two digits
+fn digits(text: &str, radix: u32) -> Option<String> {
+    let chars: Vec<char> = text.chars().collect();
+    let valid = !chars.is_empty() && chars.iter().enumerate().all(|(index, &c)| match c {
+        '_' => index > 0 && chars[index - 1].is_digit(radix)
+            && chars.get(index + 1).is_some_and(|c| c.is_digit(radix)),
+        c => c.is_digit(radix),
+    });
+    if valid { Some(text.replace('_', "")) } else { None }
+}
+
+/// Check that a decimal number doesn't have leading zeros
+fn decimal(text: &str) -> Option<String> {
+    let digits = digits(text, 10)?;
+    if digits.len() > 1 && digits.starts_with('0') { None } else { Some(digits) }
+}
+
+/// Parse a value that isn't a string, array or inline table
+fn parse_scalar(token: &str) -> Option<Value> {
+    match token {
+        "true" => return Some(Value::Bool(true)),
+        "false" => return Some(Value::Bool(false)),
+        "inf" | "+inf" => return Some(Value::Float(f64::INFINITY)),
+        "-inf" => return Some(Value::Float(f64::NEG_INFINITY)),
+        "nan" | "+nan" | "-nan" => return Some(Value::Float(f64::NAN)),
+        _ => {},
+    }
+    if token.len() >= 10 && is_date(&token[..10]) {
+        if token.len() == 10 {
+            return Some(Value::Str(token.to_string()));
+        }
+        let datetime = format!("{}T{}", &token[..10], &token[11..]);
+        return datetime.parse().ok().map(Value::DateTime);
+    }
+    if is_time(token) {
+        return Some(Value::Str(token.to_string()));
+    }
+
+    for &(prefix, radix) in &[("0x", 16), ("0o", 8), ("0b", 2)] {
+        if let Some(rest) = token.strip_prefix(prefix) {
+            return i64::from_str_radix(&digits(rest, radix)?, radix).ok().map(Value::Int);
+        }
+    }
+    let (sign, rest) = match token.chars().next() {
+        Some(sign @ '+') | Some(sign @ '-') => (Some(sign), &token[1..]),
+        _ => (None, token),
+    };
+    let sign = if sign == Some('-') { "-" } else { "" };
+    if !rest.contains(['.', 'e', 'E']) {
+        return format!("{}{}", sign, decimal(rest)?).parse().ok().map(Value::Int);
+    }
+    let (mantissa, exponent) = match rest.find(['e', 'E']) {
+        Some(index) => (&rest[..index], Some(&rest[index + 1..])),
+        None => (rest, None),
+    };
+    let (whole, fraction) = match mantissa.find('.') {
+        Some(index) => (&mantissa[..index], Some(&mantissa[index + 1..])),
+        None => (mantissa, None),
+    };
+    let mut float = format!("{}{}", sign, decimal(whole)?);
+    if let Some(fraction) = fraction {
+        float.push('.');
+        float.push_str(&digits(fraction, 10)?);
+    }
+    if let Some(exponent) = exponent {
+        let (sign, exponent) = match exponent.chars().next() {
+            Some(sign @ '+') | Some(sign @ '-') => (sign.to_string(), &exponent[1..]),
+            _ => (String::new(), exponent),
+        };
+        float.push('e');
+        float.push_str(&sign);
+        float.push_str(&digits(exponent, 10)?);
+    }
+    float.parse().ok().map(Value::Float)
+}
+
+/// Reads TOML 1.0 into tables
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+    line_start: usize,
+}
+
+impl Parser {
+    fn new(input: &str) -> Self {
+        let chars = input.replace("\r\n", "\n").chars().collect();
+        Parser { chars, pos: 0, line: 0, line_start: 0 }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.peek_at(0)
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).cloned()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+            self.line_start = self.pos;
+        }
+        Some(c)
+    }
+
+    fn bump_if(&mut self, c: char) -> Option<char> {
+        if self.peek() == Some(c) { self.bump() } else { None }
+    }
+
+    /// Check if the next characters are `text`, and skip them if they are
+    fn bump_str(&mut self, text: &str) -> bool {
+        let matches = text.chars().enumerate().all(|(offset, c)| self.peek_at(offset) == Some(c));
+        if matches {
+            for _ in text.chars() {
+                self.bump();
+            }
+        }
+        matches
+    }
+
+    fn position(&self) -> Position {
+        Position::at(self.line, self.pos - self.line_start)
+    }
+
+    fn error<T, S: Into<String>>(&self, message: S) -> Result<T, TomlError> {
+        Err(TomlError::new(message, self.position()))
+    }
+
+    fn unexpected<T>(&self) -> Result<T, TomlError> {
+        match self.peek() {
+            Some('\n') => self.error("unexpected end of line"),
+            Some(c) => self.error(format!("unexpected `{}`", c.escape_default())),
+            None => self.error("unexpected end of input"),
+        }
+    }
+
+    fn skip_inline_space(&mut self) {
+        while matches!(self.peek(), Some(' ') | Some('\t')) {
+            self.bump();
+        }
+    }
+
+    /// Skip whitespace, comments and line breaks
+    fn skip_blank_lines(&mut self) {
+        loop {
+            self.skip_inline_space();
+            if self.peek() == Some('#') {
+                while !matches!(self.peek(), None | Some('\n')) {
+                    self.bump();
+                }
+            }
+            if self.bump_if('\n').is_none() {
+                return;
+            }
+        }
+    }
+
+    /// Check that nothing but a comment follows on the line
+    fn end_line(&mut self) -> Result<(), TomlError> {
+        self.skip_inline_space();
+        match self.peek() {
+            None | Some('\n') | Some('#') => Ok(()),
+            _ => self.unexpected(),
+        }
+    }
+
+    fn document(&mut self) -> Result<Table, TomlError> {
+        let mut root = Table::new(Origin::Header);
+        let mut current = Vec::new();
+        loop {
+            self.skip_blank_lines();
+            let start = self.position();
+            match self.peek() {
+                None => return Ok(root),
+                Some('[') => {
+                    self.bump();
+                    let array = self.bump_if('[').is_some();
+                    self.skip_inline_space();
+                    let path = self.key()?;
+                    if !self.bump_str(if array { "]]" } else { "]" }) {
+                        return self.error(if array { "expected `]]`" } else { "expected `]`" });
+                    }
+                    define(&mut root, &path, array)
+                        .map_err(|message| TomlError::new(message, start))?;
+                    current = path;
+                },
+                Some(_) => {
+                    let keys = self.key()?;
+                    if self.bump_if('=').is_none() {
+                        return self.error("expected `=` after the key");
+                    }
+                    self.skip_inline_space();
+                    let value = self.value()?;
+                    let mut table = &mut root;
+                    for key in &current {
+                        table = subtable(table, key, false).expect("headers have been checked");
+                    }
+                    insert(table, &keys, value).map_err(|message| TomlError::new(message, start))?;
+                },
+            }
+            self.end_line()?;
+        }
+    }
+
+    /// Read a key, which can be dotted, along with any whitespace after it
+    fn key(&mut self) -> Result<Vec<String>, TomlError> {
+        let mut keys = Vec::new();
+        loop {
+            keys.push(self.simple_key()?);
+            self.skip_inline_space();
+            if self.bump_if('.').is_none() {
+                return Ok(keys);
+            }
+            self.skip_inline_space();
+        }
+    }
+
+    fn simple_key(&mut self) -> Result<String, TomlError> {
+        match self.peek() {
+            Some('"') => self.basic_string(),
+            Some('\'') => self.literal_string(),
+            _ => {
+                let mut key = String::new();
+                while let Some(c) = self.peek() {
+                    if !(c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+                        break;
+                    }
+                    key.push(c);
+                    self.bump();
+                }
+                if key.is_empty() { self.error("expected a key") } else { Ok(key) }
+            },
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, TomlError> {
+        let start = self.position();
+        match self.peek() {
+            Some('"') => self.basic_string().map(Value::Str),
+            Some('\'') => self.literal_string().map(Value::Str),
+            Some('[') => self.array(),
+            Some('{') => self.inline_table(),
+            Some(c) if c.is_ascii_alphanumeric() || "+-.".contains(c) => {
+                let token = self.token();
+                parse_scalar(&token)
+                    .ok_or_else(|| TomlError::new(format!("invalid value `{}`", token), start))
+            },
+            _ => self.error("expected a value"),
+        }
+    }
+
+    /// Read the text of a value that isn't a string, array or inline table.  A space can
+    /// separate the date and time of a datetime.
+    fn token(&mut self) -> String {
+        let mut token = String::new();
+        while let Some(c) = self.peek() {
+            let date_space = c == ' ' && is_date(&token)
+                && self.peek_at(1).is_some_and(|c| c.is_ascii_digit());
+            if !(c.is_ascii_alphanumeric() || "+-.:_".contains(c) || date_space) {
+                break;
+            }
+            token.push(c);
+            self.bump();
+        }
+        token
+    }
+
+    /// Read a basic string (`"..."`), or a multi-line one (`"""..."""`)
+    fn basic_string(&mut self) -> Result<String, TomlError> {
+        let start = self.position();
+        let multiline = self.bump_str("\"\"\"");
+        if multiline {
+            self.bump_if('\n');
+        } else {
+            self.bump();
+        }
+        let mut text = String::new();
+        loop {
+            let escape = self.position();
+            match self.bump() {
+                None => return Err(TomlError::new("unclosed string", start)),
+                Some('\n') if !multiline => return Err(TomlError::new("unclosed string", start)),
+                Some('"') if !multiline => return Ok(text),
+                Some('"') if self.closes_multiline('"', &mut text) => return Ok(text),
+                Some('\\') => match self.bump() {
+                    Some('b') => text.push('\x08'),
+                    Some('t') => text.push('\t'),
+                    Some('n') => text.push('\n'),
+                    Some('f') => text.push('\x0c'),
+                    Some('r') => text.push('\r'),
+                    Some('e') => text.push('\x1b'),
+                    Some('"') => text.push('"'),
+                    Some('\\') => text.push('\\'),
+                    Some('u') => text.push(self.hex(4, escape)?),
+                    Some('U') => text.push(self.hex(8, escape)?),
+                    Some(c) if multiline && (c == '\n' || c == ' ' || c == '\t') => {
+                        // a backslash at the end of a line trims the whitespace after it
+                        self.skip_inline_space();
+                        if c != '\n' && self.bump_if('\n').is_none() {
+                            return Err(TomlError::new("invalid escape", escape));
+                        }
+                        while matches!(self.peek(), Some(' ') | Some('\t') | Some('\n')) {
+                            self.bump();
+                        }
+                    },
+                    _ => return Err(TomlError::new("invalid escape", escape)),
+                },
+                Some(c) => text.push(c),
+            }
+        }
+    }
+
+    /// Read a literal string (`'...'`), or a multi-line one (`'''...'''`)
+    fn literal_string(&mut self) -> Result<String, TomlError> {
+        let start = self.position();
+        let multiline = self.bump_str("'''");
+        if multiline {
+            self.bump_if('\n');
+        } else {
+            self.bump();
+        }
+        let mut text = String::new();
+        loop {
+            match self.bump() {
+                None => return Err(TomlError::new("unclosed string", start)),
+                Some('\n') if !multiline => return Err(TomlError::new("unclosed string", start)),
+                Some('\'') if !multiline => return Ok(text),
+                Some('\'') if self.closes_multiline('\'', &mut text) => return Ok(text),
+                Some(c) => text.push(c),
+            }
+        }
+    }
+
+    /// Check if a quote in a multi-line string, which has just been read, closes it.  Up
+    /// to two more quotes can come before the closing ones, which are added to the string
+    /// if it does.
+    fn closes_multiline(&mut self, quote: char, text: &mut String) -> bool {
+        let mut quotes = 1;
+        while quotes < 5 && self.peek_at(quotes - 1) == Some(quote) {
+            quotes += 1;
+        }
+        if quotes < 3 {
+            return false;
+        }
+        for _ in 1..quotes {
+            self.bump();
+        }
+        text.extend((3..quotes).map(|_| quote));
+        true
+    }
+
+    fn hex(&mut self, digits: usize, escape: Position) -> Result<char, TomlError> {
+        let mut code = 0;
+        for _ in 0..digits {
+            match self.peek().and_then(|c| c.to_digit(16)) {
+                Some(digit) => code = code * 16 + digit,
+                None => return Err(TomlError::new("invalid escape", escape)),
+            }
+            self.bump();
+        }
+        ::std::char::from_u32(code).ok_or_else(|| TomlError::new("invalid escape", escape))
+    }
+
+    fn array(&mut self) -> Result<Value, TomlError> {
+        let start = self.position();
+        self.bump();
+        let mut items = Vec::new();
+        loop {
+            self.skip_blank_lines();
+            match self.peek() {
+                None => return Err(TomlError::new("unclosed array", start)),
+                Some(']') => break,
+                _ => {},
+            }
+            items.push(self.value()?);
+            self.skip_blank_lines();
+            match self.peek() {
+                Some(',') => {
+                    self.bump();
+                },
+                Some(']') => {},
+                None => return Err(TomlError::new("unclosed array", start)),
+                Some(_) => return self.unexpected(),
+            }
+        }
+        self.bump();
+        Ok(Value::List(items))
+    }
+
+    fn inline_table(&mut self) -> Result<Value, TomlError> {
+        let start = self.position();
+        self.bump();
+        let mut dict = Dict::new();
+        loop {
+            self.skip_inline_space();
+            match self.peek() {
+                None | Some('\n') => return Err(TomlError::new("unclosed inline table", start)),
+                Some('}') if dict.is_empty() => break,
+                _ => {},
+            }
+            let key_start = self.position();
+            let keys = self.key()?;
+            if self.bump_if('=').is_none() {
+                return self.error("expected `=` after the key");
+            }
+            self.skip_inline_space();
+            let value = self.value()?;
+            insert_dict(&mut dict, &keys, value)
+                .map_err(|message| TomlError::new(message, key_start))?;
+            self.skip_inline_space();
+            match self.peek() {
+                Some(',') => {
+                    self.bump();
+                },
+                Some('}') => break,
+                None | Some('\n') => return Err(TomlError::new("unclosed inline table", start)),
+                Some(_) => return self.unexpected(),
+            }
+        }
+        self.bump();
+        Ok(Value::Dict(dict))
+    }
+}
+
+/// Import a table as a node
+fn import_node(table: &Table) -> Node {
+    let mut node = Node::new();
+    for (key, item) in &table.entries {
+        match *item {
+            Item::Value(Value::List(ref items)) if key == ARGUMENTS_KEY => {
+                for item in items {
+                    node.push_argument(item.clone());
+                }
+            },
+            Item::Value(ref value) if key == ARGUMENTS_KEY => node.push_argument(value.clone()),
+            Item::Value(ref value) => {
+                node.insert_attr(key.as_str(), value.clone());
+            },
+            Item::Table(ref table) => {
+                node.append_node(key.as_str(), import_node(table));
+            },
+            Item::Tables(ref tables) => for table in tables {
+                node.append_node(key.as_str(), import_node(table));
+            },
+        }
+    }
+    node
+}
+
+/// Group nodes by their names, in the order that each name first appears in
+fn group_nodes<'a, I>(nodes: I) -> Vec<(&'a str, Vec<&'a Node>)>
+        where I: IntoIterator<Item = &'a Node> {
+    let mut groups: Vec<(&str, Vec<&Node>)> = Vec::new();
+    let mut indices = HashMap::new();
+    for node in nodes {
+        let index = *indices.entry(node.name()).or_insert_with(|| {
+            groups.push((node.name(), Vec::new()));
+            groups.len() - 1
+        });
+        groups[index].1.push(node);
+    }
+    groups
+}
+
+fn write_string(out: &mut String, string: &str) {
+    out.push('"');
+    for c in string.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            '\x08' => out.push_str("\\b"),
+            '\x0c' => out.push_str("\\f"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            },
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Write a key, quoting it unless it's a bare key
+fn write_key(out: &mut String, key: &str) {
+    if !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        out.push_str(key);
+    } else {
+        write_string(out, key);
+    }
+}
+
+/// Write a value, on a single line.  `path` is the key that the value is under, for
+/// errors.
+fn write_value(out: &mut String, value: &Value, path: &str) -> Result<(), TomlError> {
+    match *value {
+        Value::Str(ref string) | Value::Ident(ref string) => write_string(out, string),
+        Value::Float(float) if float.is_nan() => out.push_str("nan"),
+        Value::Float(float) if float.is_infinite() => {
+            out.push_str(if float > 0.0 { "inf" } else { "-inf" });
+        },
+        Value::Float(float) => {
+            let _ = write!(out, "{:?}", float);
+        },
+        Value::Int(int) => {
+            let _ = write!(out, "{}", int);
+        },
+        Value::Bool(boolean) => out.push_str(if boolean { "true" } else { "false" }),
+        Value::DateTime(ref datetime) => {
+            let _ = write!(out, "{}", datetime);
+        },
+        Value::Duration(ref duration) => {
+            let _ = write!(out, "{{ secs = {}, nanos = {} }}", duration.as_secs(),
+                           duration.subsec_nanos());
+        },
+        Value::Bytes(ref bytes) => {
+            let bytes: Vec<_> = bytes.iter().map(|byte| byte.to_string()).collect();
+            let _ = write!(out, "[{}]", bytes.join(", "));
+        },
+        Value::List(ref items) => write_list(out, items, path)?,
+        Value::Dict(ref dict) => {
+            if dict.is_empty() {
+                out.push_str("{}");
+                return Ok(());
+            }
+            // dicts don't keep their order, so keys are written in sorted order
+            let mut entries: Vec<_> = dict.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            out.push_str("{ ");
+            for (index, (key, value)) in entries.into_iter().enumerate() {
+                if index > 0 {
+                    out.push_str(", ");
+                }
+                write_key(out, key);
+                out.push_str(" = ");
+                write_value(out, value, &format!("{}.{}", path, key))?;
+            }
+            out.push_str(" }");
+        },
+        Value::Node(ref node) => write_value(out, &Value::Dict(node.to_dict()), path)?,
+        Value::Null => {
+            let message = format!("`{}` is null, which TOML can't represent", path);
+            return Err(TomlError { message, position: None });
+        },
+    }
+    Ok(())
+}
+
+fn write_list(out: &mut String, items: &[Value], path: &str) -> Result<(), TomlError> {
+    out.push('[');
+    for (index, item) in items.iter().enumerate() {
+        if index > 0 {
+            out.push_str(", ");
+        }
+        write_value(out, item, path)?;
+    }
+    out.push(']');
+    Ok(())
+}
+
+/// Write a node as a table, or as one of an array of tables, followed by its subnodes
+fn write_table(out: &mut String, path: &[&str], node: &Node, array: bool)
+               -> Result<(), TomlError> {
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    out.push_str(if array { "[[" } else { "[" });
+    for (index, key) in path.iter().enumerate() {
+        if index > 0 {
+            out.push('.');
+        }
+        write_key(out, key);
+    }
+    out.push_str(if array { "]]\n" } else { "]\n" });
+
+    let groups = group_nodes(node.children());
+    if !node.arguments().is_empty() {
+        write_key(out, ARGUMENTS_KEY);
+        out.push_str(" = ");
+        write_list(out, node.arguments(), &format!("{}.{}", path.join("."), ARGUMENTS_KEY))?;
+        out.push('\n');
+    }
+    for (key, value) in node.iter_attrs() {
+        // a subnode with the same name as an attribute replaces it, as it does in JSON
+        if groups.iter().any(|group| group.0 == key.as_str()) {
+            continue;
+        }
+        write_key(out, key);
+        out.push_str(" = ");
+        write_value(out, value, &format!("{}.{}", path.join("."), key))?;
+        out.push('\n');
+    }
+    for (name, nodes) in groups {
+        let mut path = path.to_vec();
+        path.push(name);
+        let array = nodes.len() > 1;
+        for node in nodes {
+            write_table(out, &path, node, array)?;
+        }
+    }
+    Ok(())
+}
+
+impl Document {
+    /// Import a document from TOML.  Tables become nodes, with their `"$arguments"` as the
+    /// node's arguments, and arrays of tables become runs of nodes with the same name.
+    /// Inline tables and arrays are values, so they become dict and list attributes.  Other
+    /// values at the top level of the document become nodes with the value as their
+    /// single argument.
+    ///
+    /// Datetimes are imported as datetimes, but local dates and local times on their own
+    /// are imported as strings.
+    ///
+    /// # Failures
+    /// Fails if the TOML is invalid, or has a `"$arguments"` key at the top level.
+    ///
+    /// # Examples
+    /// ```
+    /// # use figtree::types::{Document, Value};
+    /// let toml = r#"
+    /// [package]
+    /// name = "figtree"
+    /// authors = ["Jonathan"]
+    ///
+    /// [[bin]]
+    /// name = "a"
+    ///
+    /// [[bin]]
+    /// name = "b"
+    /// "#;
+    /// let doc = Document::from_toml(toml).unwrap();
+    /// assert_eq!(doc["package"].get("name"), Some(&Value::from("figtree")));
+    /// assert_eq!(doc.children_named("bin").count(), 2);
+    /// ```
+    pub fn from_toml(text: &str) -> Result<Document, TomlError> {
+        let root = Parser::new(text).document()?;
+        let mut document = Document::new();
+        for (key, item) in &root.entries {
+            match *item {
+                Item::Value(_) if key == ARGUMENTS_KEY => {
+                    let message = "a document can't have arguments".into();
+                    return Err(TomlError { message, position: None });
+                },
+                Item::Value(ref value) => {
+                    let mut node = Node::new();
+                    node.push_argument(value.clone());
+                    document.append_node(key.as_str(), node);
+                },
+                Item::Table(ref table) => {
+                    document.append_node(key.as_str(), import_node(table));
+                },
+                Item::Tables(ref tables) => for table in tables {
+                    document.append_node(key.as_str(), import_node(table));
+                },
+            }
+        }
+        Ok(document)
+    }
+
+    /// Write the document as TOML.  Each node is written as a table, with its arguments
+    /// under `"$arguments"`, and nodes that share a name as an array of tables.  Dicts are
+    /// written as inline tables, durations in the same way as `to_json` writes them, and
+    /// bytes as arrays of numbers.  Comments are lost.
+    ///
+    /// # Failures
+    /// Fails if the document has a null value anywhere, as TOML doesn't have them.
+    ///
+    /// # Examples
+    /// ```
+    /// # use figtree::{Figtree, ParserOptions};
+    /// let options = ParserOptions::new().node_arguments(true).repeated_nodes(true);
+    /// let doc = Figtree::from_string("listen 80 { tls = false } listen 443 { tls = true }")
+    ///     .with_options(options).parse().unwrap();
+    /// assert_eq!(doc.to_toml().unwrap(), concat!(
+    ///     "[[listen]]\n",
+    ///     "\"$arguments\" = [80]\n",
+    ///     "tls = false\n",
+    ///     "\n",
+    ///     "[[listen]]\n",
+    ///     "\"$arguments\" = [443]\n",
+    ///     "tls = true\n",
+    /// ));
+    /// ```
+    pub fn to_toml(&self) -> Result<String, TomlError> {
+        let mut out = String::new();
+        for (name, nodes) in group_nodes(self.children()) {
+            let array = nodes.len() > 1;
+            for node in nodes {
+                write_table(&mut out, &[name], node, array)?;
+            }
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::super::figtree::Figtree;
+    use super::super::parser::ParserOptions;
+    use super::*;
+
+    fn error(input: &str) -> String {
+        Document::from_toml(input).unwrap_err().to_string()
+    }
+
+    fn value(input: &str) -> Value {
+        let doc = Document::from_toml(&format!("a = {}", input)).unwrap();
+        doc["a"].arguments()[0].clone()
+    }
+
+    #[test]
+    fn parses_values() {
+        assert_eq!(value("1_000"), Value::Int(1000));
+        assert_eq!(value("-17"), Value::Int(-17));
+        assert_eq!(value("0xdead_beef"), Value::Int(0xdead_beef));
+        assert_eq!(value("0o755"), Value::Int(0o755));
+        assert_eq!(value("0b1101"), Value::Int(0b1101));
+        assert_eq!(value("-9223372036854775808"), Value::Int(i64::MIN));
+        assert_eq!(value("6.626e-34"), Value::Float(6.626e-34));
+        assert_eq!(value("+1_000.5"), Value::Float(1000.5));
+        assert_eq!(value("5E+2"), Value::Float(500.0));
+        assert_eq!(value("-inf"), Value::Float(f64::NEG_INFINITY));
+        assert!(value("nan").get_float().is_some_and(f64::is_nan));
+        assert_eq!(value("true"), Value::Bool(true));
+        assert_eq!(value("1979-05-27T07:32:00Z"), value("1979-05-27 07:32:00Z"));
+        assert!(value("1979-05-27T00:32:00.999999-07:00").is_datetime());
+        assert_eq!(value("1979-05-27"), Value::from("1979-05-27"));
+        assert_eq!(value("07:32:00"), Value::from("07:32:00"));
+        assert_eq!(value(r#""tab\t \u00e9 \"q\"""#), Value::from("tab\t \u{e9} \"q\""));
+        assert_eq!(value(r"'C:\path'"), Value::from(r"C:\path"));
+        assert_eq!(value("\"\"\"\nline one\nline \\\n    two\"\"\"\""),
+                   Value::from("line one\nline two\""));
+        assert_eq!(value("'''\nraw ''\\n'''"), Value::from("raw ''\\n"));
+        assert_eq!(value("[ 1, [2, 'three'], ]"), Value::List(vec![
+            Value::Int(1),
+            Value::List(vec![Value::Int(2), Value::from("three")]),
+        ]));
+        let mut dict = Dict::new();
+        dict.insert("b".into(), Value::Int(1));
+        let mut outer = Dict::new();
+        outer.insert("a".into(), Value::Dict(dict));
+        outer.insert("c".into(), Value::Dict(Dict::new()));
+        assert_eq!(value("{ a.b = 1, c = {} }"), Value::Dict(outer));
+    }
+
+    #[test]
+    fn parses_tables() {
+        let input = r#"
+            # a comment
+            title = "example"
+
+            [server]
+            host = "a"  # trailing comment
+            ports = [
+                80,
+                443,  # the secure one
+            ]
+            limits.max = 10
+            "quoted key" = 'literal \n'
+
+            [server.tls]
+            cert = "c.pem"
+
+            [[server.upstream]]
+            host = "b"
+
+            [[server.upstream]]
+            host = "c"
+            [server.upstream.health]
+            path = "/"
+
+            [empty]
+        "#;
+        let doc = Document::from_toml(input).unwrap();
+        assert_eq!(doc["title"].arguments(), &[Value::from("example")][..]);
+        let server = &doc["server"];
+        assert_eq!(server.get("host"), Some(&Value::from("a")));
+        assert_eq!(server.get("ports"), Some(&Value::List(vec![Value::Int(80), Value::Int(443)])));
+        assert_eq!(server.child("limits").and_then(|limits| limits.get("max")),
+                   Some(&Value::Int(10)));
+        assert_eq!(server.get("quoted key"), Some(&Value::from("literal \\n")));
+        assert_eq!(server.child("tls").and_then(|tls| tls.get("cert")), Some(&Value::from("c.pem")));
+        let upstreams: Vec<_> = server.children_named("upstream").collect();
+        assert_eq!(upstreams.len(), 2);
+        assert!(upstreams[0].child("health").is_none());
+        assert!(upstreams[1].child("health").is_some());
+        assert!(doc["empty"].is_empty());
+    }
+
+    #[test]
+    fn reports_errors() {
+        assert_eq!(error("a = 1\na = 2"), "duplicate key `a` (line 2, column 1)");
+        assert_eq!(error("[a]\n[a]"), "table `a` is already defined (line 2, column 1)");
+        assert_eq!(error("a = 1\n[a]"), "`a` is already a value (line 2, column 1)");
+        assert_eq!(error("[[a]]\n[a]"), "`a` is already an array of tables (line 2, column 1)");
+        assert_eq!(error("[a]\nb.c = 1\n[a.b]"),
+                   "table `a.b` is already defined (line 3, column 1)");
+        assert_eq!(error("[a.b]\n[a]\nb.c = 1"), "table `b` is already defined (line 3, column 1)");
+        assert_eq!(error("a = 01"), "invalid value `01` (line 1, column 5)");
+        assert_eq!(error("a = 1__0"), "invalid value `1__0` (line 1, column 5)");
+        assert_eq!(error("a = \"b"), "unclosed string (line 1, column 5)");
+        assert_eq!(error("a = [1 2]"), "unexpected `2` (line 1, column 8)");
+        assert_eq!(error("a = { b = 1, b = 2 }"), "duplicate key `b` (line 1, column 14)");
+        assert_eq!(error("a = 1 b = 2"), "unexpected `b` (line 1, column 7)");
+        assert_eq!(error("a"), "expected `=` after the key (line 1, column 2)");
+        assert_eq!(error("\"$arguments\" = 1"), "a document can't have arguments");
+    }
+
+    #[test]
+    fn writes_documents() {
+        let input = "
+            server 'web' {
+                ports = [80, 443],
+                name = 'a \"b\"',
+                ratio = 1.0,
+                timeout = 5s,
+                key = b\"\\x00\\xff\",
+                env = { HOME = '~', 'my var' = 1 }
+                upstream { host = 'a' }
+                upstream { host = 'b', tls { cert = 'c' } }
+                tls {}
+            }
+        ";
+        let options = ParserOptions::new().node_arguments(true).repeated_nodes(true);
+        let doc = Figtree::from_string(input).with_options(options).parse().unwrap();
+        assert_eq!(doc.to_toml().unwrap(), concat!(
+            "[server]\n",
+            "\"$arguments\" = [\"web\"]\n",
+            "ports = [80, 443]\n",
+            "name = \"a \\\"b\\\"\"\n",
+            "ratio = 1.0\n",
+            "timeout = { secs = 5, nanos = 0 }\n",
+            "key = [0, 255]\n",
+            "env = { HOME = \"~\", \"my var\" = 1 }\n",
+            "\n",
+            "[[server.upstream]]\n",
+            "host = \"a\"\n",
+            "\n",
+            "[[server.upstream]]\n",
+            "host = \"b\"\n",
+            "\n",
+            "[server.upstream.tls]\n",
+            "cert = \"c\"\n",
+            "\n",
+            "[server.tls]\n",
+        ));
+        assert_eq!(Document::new().to_toml().unwrap(), "");
+
+        let doc = Figtree::from_string("a { b = [1, null] }").parse().unwrap();
+        assert_eq!(doc.to_toml().unwrap_err().to_string(),
+                   "`a.b` is null, which TOML can't represent");
+    }
+
+    #[test]
+    fn round_trips_documents() {
+        let input = "
+            a 1 'two' {
+                b = ['x', \"multi\\nline\", true, 2024-01-01T00:00:00.5+01:00, -inf],
+                c = -1.5e-7,
+                d = { e = [1, { f = 'g' }] }
+                h { i = 1 }
+                h { i = 2, j { k = [] } }
+            }
+            l {}
+        ";
+        let options = ParserOptions::new().node_arguments(true).repeated_nodes(true);
+        let doc = Figtree::from_string(input).with_options(options).parse().unwrap();
+        let toml = doc.to_toml().unwrap();
+        assert_eq!(Document::from_toml(&toml).unwrap(), doc, "{}", toml);
+
+        let mut node = Node::new();
+        node.insert_attr("d", Value::new_duration(Duration::new(1, 5)));
+        let mut durations = Document::new();
+        durations.append_node("a", node);
+        let imported = Document::from_toml(&durations.to_toml().unwrap()).unwrap();
+        let d = imported["a"].get("d").and_then(Value::get_dict).unwrap();
+        assert_eq!(d.get("nanos"), Some(&Value::new_int(5)));
+    }
+}
@@ -0,0 +1,317 @@
+use std::collections::VecDeque;
+
+use super::path::{join, node_path};
+use super::types::{Document, Node, Value};
+
+/// A node found by `Document::descendants` or `Document::breadth_first`
+#[derive(Debug, Clone)]
+pub struct Descendant<'a> {
+    pub node: &'a Node,
+    /// The path to the node, in the same form as the paths used by `Document::get_path`
+    pub path: String,
+    /// How deep the node is: 0 for nodes at the top level, 1 for their subnodes, and
+    /// so on
+    pub depth: usize,
+}
+
+/// The subnodes of a node (or document) at `path`, in order
+fn children<'a>(nodes: &'a [Node], path: &str, depth: usize) -> Vec<Descendant<'a>> {
+    nodes.iter().enumerate().map(|(position, node)| {
+        let named = |other: &&Node| other.name() == node.name();
+        let index = nodes[..position].iter().filter(named).count();
+        let repeated = nodes.iter().filter(named).count() > 1;
+        Descendant { node, path: node_path(path, node.name(), index, repeated), depth }
+    }).collect()
+}
+
+/// A depth-first iterator over every node in a document, created by
+/// `Document::descendants`
+pub struct Descendants<'a> {
+    stack: Vec<Descendant<'a>>,
+}
+
+impl<'a> Descendants<'a> {
+    fn new(nodes: &'a [Node]) -> Self {
+        let mut stack = children(nodes, "", 0);
+        stack.reverse();
+        Descendants { stack }
+    }
+}
+
+impl<'a> Iterator for Descendants<'a> {
+    type Item = Descendant<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let next = self.stack.pop()?;
+        let children = children(next.node.children().as_slice(), &next.path, next.depth + 1);
+        self.stack.extend(children.into_iter().rev());
+        Some(next)
+    }
+}
+
+/// A breadth-first iterator over every node in a document, created by
+/// `Document::breadth_first`
+pub struct BreadthFirst<'a> {
+    queue: VecDeque<Descendant<'a>>,
+}
+
+impl<'a> BreadthFirst<'a> {
+    fn new(nodes: &'a [Node]) -> Self {
+        BreadthFirst { queue: children(nodes, "", 0).into() }
+    }
+}
+
+impl<'a> Iterator for BreadthFirst<'a> {
+    type Item = Descendant<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let next = self.queue.pop_front()?;
+        self.queue.extend(children(next.node.children().as_slice(), &next.path, next.depth + 1));
+        Some(next)
+    }
+}
+
+/// An iterator over the nodes in a document that match a predicate, created by
+/// `Document::find`
+pub struct Find<'a, F> {
+    descendants: Descendants<'a>,
+    predicate: F,
+}
+
+impl<'a, F> Iterator for Find<'a, F> where F: FnMut(&Node) -> bool {
+    type Item = Descendant<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let predicate = &mut self.predicate;
+        self.descendants.find(|found| predicate(found.node))
+    }
+}
+
+/// An iterator over the attributes (and dict entries) in a document that match a
+/// predicate, created by `Document::filter_values`.  Produces the path to each value
+/// along with the value.
+pub struct FilterValues<'a, F> {
+    descendants: Descendants<'a>,
+    /// values still to be looked at, with their paths and keys, in reverse order
+    pending: Vec<(String, Option<&'a str>, &'a Value)>,
+    predicate: F,
+}
+
+impl<'a, F> FilterValues<'a, F> {
+    fn new(descendants: Descendants<'a>, pending: Vec<(String, Option<&'a str>, &'a Value)>,
+        predicate: F) -> Self {
+
+        FilterValues { descendants, pending, predicate }
+    }
+}
+
+/// The attributes of a node at `path`, in reverse order
+fn properties<'a>(node: &'a Node, path: &str) -> Vec<(String, Option<&'a str>, &'a Value)> {
+    node.properties().rev()
+        .map(|(key, value)| (join(path, key), Some(key.as_str()), value))
+        .collect()
+}
+
+impl<'a, F> Iterator for FilterValues<'a, F> where F: FnMut(&str, &Value) -> bool {
+    type Item = (String, &'a Value);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (path, key, value) = match self.pending.pop() {
+                Some(pending) => pending,
+                None => {
+                    let found = self.descendants.next()?;
+                    self.pending = properties(found.node, &found.path);
+                    continue;
+                },
+            };
+
+            // the entries of dicts are looked at too, including dicts inside lists
+            match *value {
+                Value::Dict(ref dict) => {
+                    let mut entries: Vec<_> = dict.iter().collect();
+                    entries.sort_by(|a, b| b.0.cmp(a.0));
+                    self.pending.extend(entries.into_iter()
+                        .map(|(key, value)| (join(&path, key), Some(&**key), value)));
+                },
+                Value::List(ref items) => {
+                    self.pending.extend(items.iter().enumerate().rev()
+                        .map(|(index, item)| (format!("{}[{}]", path, index), None, item)));
+                },
+                _ => {},
+            }
+            if let Some(key) = key {
+                if (self.predicate)(key, value) {
+                    return Some((path, value));
+                }
+            }
+        }
+    }
+}
+
+impl Node {
+    /// Iterate depth-first over every node inside this one.  The paths are relative to
+    /// this node.  See `Document::descendants`.
+    pub fn descendants(&self) -> Descendants<'_> {
+        Descendants::new(self.children().as_slice())
+    }
+
+    /// Iterate breadth-first over every node inside this one.  The paths are relative
+    /// to this node.  See `Document::breadth_first`.
+    pub fn breadth_first(&self) -> BreadthFirst<'_> {
+        BreadthFirst::new(self.children().as_slice())
+    }
+
+    /// Find the nodes inside this one for which `predicate` returns `true`.  See
+    /// `Document::find`.
+    pub fn find<F>(&self, predicate: F) -> Find<'_, F> where F: FnMut(&Node) -> bool {
+        Find { descendants: self.descendants(), predicate }
+    }
+
+    /// Find the values in this node or the nodes inside it for which `predicate` returns
+    /// `true`.  See `Document::filter_values`.
+    pub fn filter_values<F>(&self, predicate: F) -> FilterValues<'_, F>
+        where F: FnMut(&str, &Value) -> bool {
+
+        FilterValues::new(self.descendants(), properties(self, ""), predicate)
+    }
+}
+
+impl Document {
+    /// Iterate over every node in the document, depth-first: each node comes before its
+    /// subnodes, which come before the node's next sibling.  Nodes are produced along
+    /// with their paths and depths.
+    ///
+    /// # Examples
+    /// ```
+    /// # use figtree::types::*;
+    /// # use std::io::Cursor;
+    /// let doc = Document::parse(Cursor::new(
+    ///     "db { password = 'a' } cache { redis { password = 'b' } }")).unwrap();
+    /// let paths: Vec<_> = doc.descendants()
+    ///     .filter(|found| found.node.has("password"))
+    ///     .map(|found| found.path)
+    ///     .collect();
+    /// assert_eq!(paths, vec!["db", "cache.redis"]);
+    /// ```
+    pub fn descendants(&self) -> Descendants<'_> {
+        Descendants::new(self.children().as_slice())
+    }
+
+    /// Iterate over every node in the document, breadth-first: all the nodes at one
+    /// depth come before any of the nodes below them.  Nodes are produced along with
+    /// their paths and depths.
+    pub fn breadth_first(&self) -> BreadthFirst<'_> {
+        BreadthFirst::new(self.children().as_slice())
+    }
+
+    /// Find the nodes in the document for which `predicate` returns `true`, in the same
+    /// order as `descendants`.
+    ///
+    /// # Examples
+    /// ```
+    /// # use figtree::types::*;
+    /// # use std::io::Cursor;
+    /// let doc = Document::parse(Cursor::new("a { tls { port = 1 } } b { c { tls {} } }"))
+    ///     .unwrap();
+    /// let paths: Vec<_> = doc.find(|node| node.name() == "tls")
+    ///     .map(|found| found.path)
+    ///     .collect();
+    /// assert_eq!(paths, vec!["a.tls", "b.c.tls"]);
+    /// ```
+    pub fn find<F>(&self, predicate: F) -> Find<'_, F> where F: FnMut(&Node) -> bool {
+        Find { descendants: self.descendants(), predicate }
+    }
+
+    /// Find the attributes in the document for which `predicate` returns `true` when
+    /// given their keys and values, along with their paths.  The entries of dict values
+    /// are looked at too, in order of their keys, including dicts inside lists.
+    ///
+    /// # Examples
+    /// ```
+    /// # use figtree::types::*;
+    /// # use std::io::Cursor;
+    /// let doc = Document::parse(Cursor::new(
+    ///     "db { password = 'a' } cache { servers = [{ password = 'b' }] }")).unwrap();
+    /// let paths: Vec<_> = doc.filter_values(|key, _| key == "password")
+    ///     .map(|(path, _)| path)
+    ///     .collect();
+    /// assert_eq!(paths, vec!["db.password", "cache.servers[0].password"]);
+    /// ```
+    pub fn filter_values<F>(&self, predicate: F) -> FilterValues<'_, F>
+        where F: FnMut(&str, &Value) -> bool {
+
+        FilterValues::new(self.descendants(), Vec::new(), predicate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::figtree::Figtree;
+    use super::super::parser::ParserOptions;
+    use super::super::path::node_at;
+    use std::ptr;
+
+    fn parse(input: &str) -> Document {
+        let options = ParserOptions::new().repeated_nodes(true);
+        Figtree::from_string(input).with_options(options).parse().unwrap()
+    }
+
+    #[test]
+    fn traverses_in_order() {
+        let doc = parse("a { b { c {} } up {} up { d {} } } e { f {} }");
+        let found = |iter: &mut dyn Iterator<Item = Descendant>| iter
+            .map(|found| (found.path, found.depth))
+            .collect::<Vec<_>>();
+        let expected = |paths: &[(&str, usize)]| paths.iter()
+            .map(|&(path, depth)| (path.to_string(), depth))
+            .collect::<Vec<_>>();
+
+        assert_eq!(found(&mut doc.descendants()), expected(&[
+            ("a", 0), ("a.b", 1), ("a.b.c", 2), ("a.up[0]", 1), ("a.up[1]", 1), ("a.up[1].d", 2),
+            ("e", 0), ("e.f", 1),
+        ]));
+        assert_eq!(found(&mut doc.breadth_first()), expected(&[
+            ("a", 0), ("e", 0), ("a.b", 1), ("a.up[0]", 1), ("a.up[1]", 1), ("e.f", 1),
+            ("a.b.c", 2), ("a.up[1].d", 2),
+        ]));
+        assert_eq!(found(&mut doc["a"].descendants()), expected(&[
+            ("b", 0), ("b.c", 1), ("up[0]", 0), ("up[1]", 0), ("up[1].d", 1),
+        ]));
+        assert_eq!(doc["a"].breadth_first().count(), 5);
+        // the paths lead back to the nodes
+        assert!(doc.descendants().all(|found| {
+            node_at(doc.children().as_slice(), &found.path).is_some_and(|node| ptr::eq(node, found.node))
+        }));
+        assert_eq!(Document::new().descendants().count(), 0);
+    }
+
+    #[test]
+    fn queries() {
+        let doc = parse("a { x = 1, d = { k = 2, j = { k = 3 } }, l = [{ k = 4 }, 5] b { k = 6 } }
+                         c { k = 7 }");
+        let values: Vec<_> = doc.filter_values(|_, value| value.is_int())
+            .map(|(path, value)| (path, value.get_int().unwrap()))
+            .collect();
+        assert_eq!(values, vec![
+            ("a.x".to_string(), 1), ("a.d.j.k".to_string(), 3), ("a.d.k".to_string(), 2),
+            ("a.l[0].k".to_string(), 4), ("a.b.k".to_string(), 6), ("c.k".to_string(), 7),
+        ]);
+        let keys: Vec<_> = doc["a"].filter_values(|key, _| key == "k")
+            .map(|(path, _)| path)
+            .collect();
+        assert_eq!(keys, vec!["d.j.k", "d.k", "l[0].k", "b.k"]);
+        for (path, value) in doc.filter_values(|_, _| true) {
+            assert_eq!(doc.get_path(&path), Some(value), "{}", path);
+        }
+
+        let mut seen = 0;
+        let found: Vec<_> = doc.find(|node| { seen += 1; node.has("k") })
+            .map(|found| found.path)
+            .collect();
+        assert_eq!(found, vec!["a.b", "c"]);
+        assert_eq!(seen, 3);
+        assert_eq!(doc["a"].find(|_| true).count(), 1);
+    }
+}
@@ -1,6 +1,7 @@
 //! A collection of types that define a figtree document.
 //!
-//! These types are re-exported in the main module because there aren't too many of
+//! A `Document` can be parsed from any reader with `Document::parse`, or built up from
+//! scratch.  These types are re-exported in the main module because there aren't too many of
 //! them, and because they're useful when testing equality or building figtree documents
 //! from scratch.  The `types` module is also made available to allow explicit namespaced
 //! imports of these types.
@@ -16,9 +17,30 @@
 //!     Value::new_int(4032));
 //! ```
 
+use std::cell::OnceCell;
+use std::cmp::Ordering;
 use std::collections::HashMap;
-use std::collections::hash_map::Iter;
-use super::parser::ParsedValue;
+use std::slice;
+use std::error::Error;
+use std::fmt;
+use std::io::Read;
+use std::mem;
+use std::ptr;
+use std::ops::Index;
+use std::sync::Arc;
+use super::parser::{ParsedValue, ParseError};
+use super::position::{Origin, Span};
+use super::datetime::DateTime;
+use super::figtree::Figtree;
+use super::builder::{DocumentBuilder, NodeBuilder};
+use super::map::OrderedMap;
+use super::intern::{Interner, Symbol};
+use super::hooks::{HookError, Mutation, MutationHooks};
+use super::path;
+use std::time::Duration;
+
+/// The key that `Node::to_dict` puts a node's arguments under
+const ARGUMENTS_KEY: &str = "$arguments";
 
 /// A type to represent a figtree dict
 ///
@@ -41,15 +63,33 @@ pub type List = Vec<Value>;
 /// let value = Value::new_string("hello!");
 /// assert!(value.get_str() == Some("hello!"));
 /// assert!(value.get_int() == None);
-#[derive(Debug, PartialEq)]
+/// ```
+///
+/// Values can also be converted from the Rust types that they contain:
+///
+/// ```
+/// # use figtree::types::Value;
+/// assert_eq!(Value::from(5), Value::new_int(5));
+/// assert_eq!(Value::from(vec![Value::from("a")]).type_name(), "list");
+/// assert!(Value::from(true).is_bool());
+/// ```
+///
+/// A node value holds a whole node.  Parsing never produces them, but they let a node be
+/// passed around wherever a value can be, such as by converters and serde.  Formats that
+/// have no way to write a node as a value write it as a dict (see `Node::to_dict`).
+#[derive(Debug, PartialEq, Clone)]
 pub enum Value {
     Str(String),
     Int(i64),
     Float(f64),
     Bool(bool),
     Ident(String),
+    DateTime(DateTime),
+    Duration(Duration),
+    Bytes(Vec<u8>),
     Dict(Dict),
     List(List),
+    Node(Box<Node>),
     Null,
 }
 
@@ -79,6 +119,26 @@ impl Value {
         Value::Bool(s)
     }
 
+    /// Construct a new date and time `Value`.
+    pub fn new_datetime(s: DateTime) -> Self {
+        Value::DateTime(s)
+    }
+
+    /// Construct a new duration `Value`.
+    pub fn new_duration(s: Duration) -> Self {
+        Value::Duration(s)
+    }
+
+    /// Construct a new bytes `Value`.
+    pub fn new_bytes<B>(s: B) -> Self where B: Into<Vec<u8>> {
+        Value::Bytes(s.into())
+    }
+
+    /// Construct a new node `Value`.
+    pub fn new_node<N>(node: N) -> Self where N: Into<Node> {
+        Value::Node(Box::new(node.into()))
+    }
+
     /// Construct a new null `Value`.
     pub fn new_null() -> Self {
         Value::Null
@@ -91,6 +151,9 @@ impl Value {
             ParsedValue::Bool(b) => Self::new_bool(b),
             ParsedValue::Int(i) => Self::new_int(i),
             ParsedValue::Ident(i) => Self::new_ident(i),
+            ParsedValue::DateTime(d) => Self::new_datetime(d),
+            ParsedValue::Duration(d) => Self::new_duration(d),
+            ParsedValue::Bytes(b) => Self::new_bytes(b),
             ParsedValue::Null => Self::new_null(),
         }
     }
@@ -98,7 +161,7 @@ impl Value {
     /// Extract the contained value if it is a string.
     pub fn get_str(&self) -> Option<&str> {
         match *self {
-            Value::Str(ref s) => Some(&s),
+            Value::Str(ref s) => Some(s),
             _ => None
         }
     }
@@ -130,7 +193,31 @@ impl Value {
     /// Extract the contained (&str) value if it is an identifier
     pub fn get_ident(&self) -> Option<&str> {
         match *self {
-            Value::Ident(ref s) => Some(&s),
+            Value::Ident(ref s) => Some(s),
+            _ => None
+        }
+    }
+
+    /// Extract the contained value if it is a date and time
+    pub fn get_datetime(&self) -> Option<DateTime> {
+        match *self {
+            Value::DateTime(s) => Some(s),
+            _ => None
+        }
+    }
+
+    /// Extract the contained value if it is a duration
+    pub fn get_duration(&self) -> Option<Duration> {
+        match *self {
+            Value::Duration(s) => Some(s),
+            _ => None
+        }
+    }
+
+    /// Extract the contained value as a slice if it is a bytes value
+    pub fn get_bytes(&self) -> Option<&[u8]> {
+        match *self {
+            Value::Bytes(ref s) => Some(s),
             _ => None
         }
     }
@@ -146,17 +233,312 @@ impl Value {
     /// Extract the contained value as a slice if it is a list
     pub fn get_list(&self) -> Option<&[Value]> {
         match *self {
-            Value::List(ref s) => Some(&s),
+            Value::List(ref s) => Some(s),
+            _ => None
+        }
+    }
+
+    /// Extract the contained value if it is a node
+    pub fn get_node(&self) -> Option<&Node> {
+        match *self {
+            Value::Node(ref s) => Some(s),
             _ => None
         }
     }
 
+    /// Test if this value is a string
+    pub fn is_str(&self) -> bool {
+        matches!(*self, Value::Str(_))
+    }
+
+    /// Test if this value is an integer
+    pub fn is_int(&self) -> bool {
+        matches!(*self, Value::Int(_))
+    }
+
+    /// Test if this value is a float
+    pub fn is_float(&self) -> bool {
+        matches!(*self, Value::Float(_))
+    }
+
+    /// Test if this value is a boolean
+    pub fn is_bool(&self) -> bool {
+        matches!(*self, Value::Bool(_))
+    }
+
+    /// Test if this value is an identifier
+    pub fn is_ident(&self) -> bool {
+        matches!(*self, Value::Ident(_))
+    }
+
+    /// Test if this value is a date and time
+    pub fn is_datetime(&self) -> bool {
+        matches!(*self, Value::DateTime(_))
+    }
+
+    /// Test if this value is a duration
+    pub fn is_duration(&self) -> bool {
+        matches!(*self, Value::Duration(_))
+    }
+
+    /// Test if this value is a bytes value
+    pub fn is_bytes(&self) -> bool {
+        matches!(*self, Value::Bytes(_))
+    }
+
+    /// Test if this value is a dict
+    pub fn is_dict(&self) -> bool {
+        matches!(*self, Value::Dict(_))
+    }
+
+    /// Test if this value is a list
+    pub fn is_list(&self) -> bool {
+        matches!(*self, Value::List(_))
+    }
+
+    /// Test if this value is a node
+    pub fn is_node(&self) -> bool {
+        matches!(*self, Value::Node(_))
+    }
+
+    /// Test if this value is null
     pub fn is_null(&self) -> bool {
+        matches!(*self, Value::Null)
+    }
+
+    /// The name of the kind of value this is (`"string"`, `"integer"`, `"list"`, etc.),
+    /// for use in error messages.
+    pub fn type_name(&self) -> &'static str {
         match *self {
-            Value::Null => true,
-            _ => false,
+            Value::Str(_) => "string",
+            Value::Int(_) => "integer",
+            Value::Float(_) => "float",
+            Value::Bool(_) => "boolean",
+            Value::Ident(_) => "identifier",
+            Value::DateTime(_) => "date and time",
+            Value::Duration(_) => "duration",
+            Value::Bytes(_) => "bytes",
+            Value::Dict(_) => "dict",
+            Value::List(_) => "list",
+            Value::Node(_) => "node",
+            Value::Null => "null",
+        }
+    }
+
+    /// Test if two values mean the same thing.  This is the same as `==`, except that
+    /// NaN floats are equal to each other.
+    pub fn structurally_eq(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Float(a), Value::Float(b)) => a == b || (a.is_nan() && b.is_nan()),
+            (Value::List(a), Value::List(b)) =>
+                a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.structurally_eq(b)),
+            (Value::Dict(a), Value::Dict(b)) => a.len() == b.len() && a.iter().all(|(key, a)| {
+                b.get(key).is_some_and(|b| a.structurally_eq(b))
+            }),
+            (Value::Node(a), Value::Node(b)) => a.structurally_eq(b),
+            (a, b) => a == b,
         }
     }
+
+    /// Extract a mutable reference to the contained value if it is a dict
+    pub fn get_dict_mut(&mut self) -> Option<&mut Dict> {
+        match *self {
+            Value::Dict(ref mut s) => Some(s),
+            _ => None
+        }
+    }
+
+    /// Extract a mutable reference to the contained value if it is a list
+    pub fn get_list_mut(&mut self) -> Option<&mut List> {
+        match *self {
+            Value::List(ref mut s) => Some(s),
+            _ => None
+        }
+    }
+
+    /// Extract a mutable reference to the contained value if it is a node
+    pub fn get_node_mut(&mut self) -> Option<&mut Node> {
+        match *self {
+            Value::Node(ref mut s) => Some(s),
+            _ => None
+        }
+    }
+}
+
+/// ` at "path"`, for the end of a panic message, if the path isn't empty
+fn at(path: &str) -> String {
+    if path.is_empty() { String::new() } else { format!(" at {:?}", path) }
+}
+
+fn value_key<'a>(value: &'a Value, path: &str, key: &str) -> &'a Value {
+    match *value {
+        Value::Dict(ref dict) => match dict.get(key) {
+            Some(value) => value,
+            None => panic!("no key {:?} in dict{}", key, at(path)),
+        },
+        Value::Node(ref node) => match node.get(key) {
+            Some(value) => value,
+            None => panic!("no attribute {:?} in node {:?}{}", key, node.name(), at(path)),
+        },
+        ref value =>
+            panic!("cannot look up key {:?} in {} value{}", key, value.type_name(), at(path)),
+    }
+}
+
+fn value_item<'a>(value: &'a Value, path: &str, index: usize) -> &'a Value {
+    match *value {
+        Value::List(ref list) => match list.get(index) {
+            Some(item) => item,
+            None => panic!("index {} out of range for list of length {}{}",
+                           index, list.len(), at(path)),
+        },
+        ref value =>
+            panic!("cannot look up index {} in {} value{}", index, value.type_name(), at(path)),
+    }
+}
+
+/// Look up a key in a dict value, or an attribute in a node value.
+///
+/// # Panics
+/// Panics if the value isn't a dict or a node, or doesn't contain the key.
+impl<'a> Index<&'a str> for Value {
+    type Output = Value;
+
+    fn index(&self, key: &'a str) -> &Value {
+        value_key(self, "", key)
+    }
+}
+
+/// Look up an item in a list value.
+///
+/// # Panics
+/// Panics if the value isn't a list, or the index is out of range.
+impl Index<usize> for Value {
+    type Output = Value;
+
+    fn index(&self, index: usize) -> &Value {
+        value_item(self, "", index)
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::Str(s)
+    }
+}
+
+impl<'a> From<&'a str> for Value {
+    fn from(s: &'a str) -> Self {
+        Value::Str(s.to_string())
+    }
+}
+
+impl From<i64> for Value {
+    fn from(s: i64) -> Self {
+        Value::Int(s)
+    }
+}
+
+impl From<i32> for Value {
+    fn from(s: i32) -> Self {
+        Value::Int(s.into())
+    }
+}
+
+impl From<f64> for Value {
+    fn from(s: f64) -> Self {
+        Value::Float(s)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(s: bool) -> Self {
+        Value::Bool(s)
+    }
+}
+
+impl From<DateTime> for Value {
+    fn from(s: DateTime) -> Self {
+        Value::DateTime(s)
+    }
+}
+
+impl From<Duration> for Value {
+    fn from(s: Duration) -> Self {
+        Value::Duration(s)
+    }
+}
+
+impl From<List> for Value {
+    fn from(s: List) -> Self {
+        Value::List(s)
+    }
+}
+
+impl From<Dict> for Value {
+    fn from(s: Dict) -> Self {
+        Value::Dict(s)
+    }
+}
+
+impl From<Node> for Value {
+    fn from(s: Node) -> Self {
+        Value::new_node(s)
+    }
+}
+
+/// The comments attached to a node or an attribute, when a document is parsed with
+/// `ParserOptions::comments` turned on.  Comments include their delimiters, so
+/// `# units: seconds` is kept as it was written.
+///
+/// Comments on the lines before an entry are its leading comments.  A comment that
+/// starts on the line where an entry ends (`port = 80 # the default`) is a trailing
+/// comment of that entry, as is a comment on the same line as the opening brace of a
+/// node.  Comments at the end of a node, after its last entry, are trailing comments of
+/// that entry, or of the node itself if it is empty.
+///
+/// # Examples
+/// ```
+/// # use figtree::{Figtree, ParserOptions};
+/// let input = "server {\n  # units: seconds\n  timeout = 30 // at most\n}";
+/// let options = ParserOptions::new().comments(true);
+/// let doc = Figtree::from_string(input).with_options(options).parse().unwrap();
+/// let comments = doc["server"].attr_comments("timeout").unwrap();
+/// assert_eq!(comments.leading, vec!["# units: seconds"]);
+/// assert_eq!(comments.trailing, vec!["// at most"]);
+/// ```
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Comments {
+    /// The comments on the lines before the entry, in order
+    pub leading: Vec<String>,
+    /// The comments after the entry, in order
+    pub trailing: Vec<String>,
+}
+
+impl Comments {
+    /// Test if there are no comments
+    pub fn is_empty(&self) -> bool {
+        self.leading.is_empty() && self.trailing.is_empty()
+    }
+}
+
+/// Where a value was in the input of a parsed document, along with the values inside
+/// it.  See `Node::value_span`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ValueSpan {
+    /// The span of the whole value, including any brackets or braces around it
+    pub span: Span,
+    /// The items of a list value, in order
+    pub items: Vec<ValueSpan>,
+    /// The keys and values of a dict value
+    pub entries: HashMap<String, (Span, ValueSpan)>,
+}
+
+impl ValueSpan {
+    /// Construct the span of a value that has no values inside it
+    pub fn new(span: Span) -> Self {
+        ValueSpan { span, items: Vec::new(), entries: HashMap::new() }
+    }
 }
 
 /// A struct representing an individual node in a parsed document
@@ -197,28 +579,99 @@ impl Value {
 /// node.insert_attr("key", Value::new_int(5));
 /// assert!(node.attr_count() == 1);
 /// ```
-#[derive(Debug, PartialEq)]
+///
+/// Looking up settings:
+///
+/// ```
+/// # use figtree::types::*;
+/// # use std::io::Cursor;
+/// let doc = Document::parse(Cursor::new("server { tls { port = 443 } }")).unwrap();
+/// let tls = doc.child("server").and_then(|server| server.child("tls")).unwrap();
+/// assert_eq!(tls.name(), "tls");
+/// assert_eq!(tls.get("port"), Some(&Value::new_int(443)));
+/// ```
+///
+/// Subnodes and attributes are kept in the order they were added in, which for a parsed
+/// document is the order they were written in.  A node can have several subnodes with
+/// the same name (see `append_node`), in which case methods that look up a single node
+/// by name find the first one.  Attributes are also indexed by key, so looking one up
+/// is quick however many attributes the node has.
+///
+/// Nodes are equal if they have the same arguments in the same order, and the same
+/// subnodes and attributes in any order - except that subnodes with the same name must
+/// be in the same order as each other.  A node's name belongs to the node or document
+/// that contains it, so it isn't compared, and nor are comments or spans.
+#[derive(Debug, Clone)]
 pub struct Node {
-    subnodes: HashMap<String, Node>,
-    attributes: HashMap<String, Value>,
+    name: Symbol,
+    arguments: Vec<Value>,
+    subnodes: Vec<Node>,
+    attributes: OrderedMap<Value>,
+    comments: Comments,
+    /// the comments of any attributes that have them
+    attr_comments: HashMap<Symbol, Comments>,
+    span: Option<Span>,
+    /// the spans of the keys and values of attributes, if they were parsed
+    attr_spans: HashMap<Symbol, (Span, ValueSpan)>,
+    /// shared between all of the nodes from the same document
+    origin: Option<Arc<Origin>>,
 }
 
 impl Node {
     /// Construct a new, empty node
     pub fn new() -> Self {
         Node {
-            subnodes: HashMap::new(),
-            attributes: HashMap::new(),
+            name: Symbol::from(""),
+            arguments: Vec::new(),
+            subnodes: Vec::new(),
+            attributes: OrderedMap::new(),
+            comments: Comments::default(),
+            attr_comments: HashMap::new(),
+            span: None,
+            attr_spans: HashMap::new(),
+            origin: None,
         }
     }
 
+    /// Construct a new, empty node with a name
+    pub fn named<S>(name: S) -> Self where S: Into<Symbol> {
+        Node { name: name.into(), ..Node::new() }
+    }
+
+    /// Construct a builder for a node with the given name
+    pub fn builder<S>(name: S) -> NodeBuilder where S: Into<Symbol> {
+        NodeBuilder::new(name)
+    }
+
+    /// The name of this node.  This is empty for nodes constructed with `Node::new` until
+    /// they are inserted into a document or another node.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The positional arguments given after this node's name (`listen 8080 { }`), in
+    /// order
+    pub fn arguments(&self) -> &[Value] {
+        &self.arguments
+    }
+
+    /// Get a mutable reference to the positional arguments of this node
+    pub fn arguments_mut(&mut self) -> &mut Vec<Value> {
+        &mut self.arguments
+    }
+
+    /// Add a positional argument to the end of this node's arguments
+    pub fn push_argument(&mut self, value: Value) {
+        self.arguments.push(value);
+    }
+
     /// Construct a new node and automatically insert it as a subnode.
     ///
     /// Returns a mutable reference to the new node.  If there is a subnode already
     /// present with the given name, this method will not insert a new node and instead
     /// just return the old node.
-    pub fn new_node_or_get<S>(&mut self, name: S) -> &mut Self where S: Into<String> {
-        self.subnodes.entry(name.into()).or_insert(Self::new())
+    pub fn new_node_or_get<S>(&mut self, name: S) -> &mut Self where S: Into<Symbol> {
+        new_node_or_get(&mut self.subnodes, name.into())
     }
 
     /// Inserts a node into this node as a subnode.
@@ -226,104 +679,925 @@ impl Node {
     /// If there is already a node with the given name, replace it and return the
     /// old node.
     pub fn insert_node<S>(&mut self, name: S, node: Node) -> Option<Node>
-        where S: Into<String> {
+        where S: Into<Symbol> {
+
+        insert_node(&mut self.subnodes, name.into(), node)
+    }
+
+    /// Add a subnode to the end of this node, even if there is already a subnode with the
+    /// same name.
+    ///
+    /// Returns a mutable reference to the added node.
+    pub fn append_node<S>(&mut self, name: S, node: Node) -> &mut Self where S: Into<Symbol> {
+        append_node(&mut self.subnodes, name.into(), node)
+    }
+
+    /// Remove a subnode from this node.
+    ///
+    /// Returns the deleted node.
+    pub fn delete_node<S>(&mut self, name: S) -> Option<Node> where S: AsRef<str> {
+        delete_node(&mut self.subnodes, name.as_ref())
+    }
+
+    /// Remove the subnode at `index`, returning it as an owned subtree that can be
+    /// adopted by another node or document.  The subnode keeps its name, along with its
+    /// contents and comments.
+    ///
+    /// Nodes don't know which node they belong to, so a subtree is detached through its
+    /// parent.  Returns `None` if there is no subnode at `index`.
+    ///
+    /// # Examples
+    /// Moving a block from one section to another:
+    ///
+    /// ```
+    /// # use figtree::types::*;
+    /// # use std::io::Cursor;
+    /// let mut doc = Document::parse(Cursor::new("old { tls { port = 443 } } new {}")).unwrap();
+    /// let tls = doc.get_node_mut("old").unwrap().detach(0).unwrap();
+    /// doc.get_node_mut("new").unwrap().adopt(tls);
+    /// assert!(doc["old"].is_empty());
+    /// assert_eq!(doc["new"][0].name(), "tls");
+    /// ```
+    pub fn detach(&mut self, index: usize) -> Option<Node> {
+        detach(&mut self.subnodes, index)
+    }
+
+    /// Add a subtree to the end of this node's subnodes, keeping its own name.
+    ///
+    /// Returns a mutable reference to the adopted node.
+    pub fn adopt(&mut self, child: Node) -> &mut Node {
+        self.subnodes.push(child);
+        self.subnodes.last_mut().unwrap()
+    }
+
+    /// Get a reference to the specified subnode
+    pub fn get_node<S>(&self, name: S) -> Option<&Self> where S: AsRef<str> {
+        self.child(name.as_ref())
+    }
+
+    /// Get a mutable reference to the specified subnode
+    pub fn get_node_mut<S>(&mut self, name: S) -> Option<&mut Self>
+        where S: AsRef<str> {
+
+        let name = name.as_ref();
+        self.subnodes.iter_mut().find(|node| *node.name == *name)
+    }
+
+    /// Get an iterable of (&name, &node) pairs
+    pub fn iter_nodes(&self) -> Subnodes<'_> {
+        Subnodes { nodes: self.subnodes.iter() }
+    }
+
+    /// Get an iterable of the subnodes of this node
+    pub fn children(&self) -> slice::Iter<'_, Node> {
+        self.subnodes.iter()
+    }
+
+    /// Get an iterable of mutable references to the subnodes of this node
+    pub fn children_mut(&mut self) -> slice::IterMut<'_, Node> {
+        self.subnodes.iter_mut()
+    }
+
+    /// Get an iterable of all of the subnodes with the given name, in order.
+    ///
+    /// # Examples
+    /// ```
+    /// # use figtree::{Figtree, ParserOptions};
+    /// let options = ParserOptions::new().repeated_nodes(true);
+    /// let input = "proxy { upstream { host = 'a' } upstream { host = 'b' } }";
+    /// let doc = Figtree::from_string(input).with_options(options).parse().unwrap();
+    /// let hosts: Vec<_> = doc["proxy"].children_named("upstream")
+    ///     .map(|upstream| upstream.get_str("host").unwrap())
+    ///     .collect();
+    /// assert_eq!(hosts, vec!["a", "b"]);
+    /// ```
+    pub fn children_named<'a, 'b>(&'a self, name: &'b str) -> ChildrenNamed<'a, 'b> {
+        ChildrenNamed { nodes: self.subnodes.iter(), name }
+    }
+
+    /// Get a reference to the subnode with the given name
+    pub fn child(&self, name: &str) -> Option<&Node> {
+        self.subnodes.iter().find(|node| *node.name == *name)
+    }
+
+    /// Get an iterable of (&key, &value) pairs for the attributes of this node, in order
+    pub fn properties(&self) -> Properties<'_> {
+        Properties { attributes: self.attributes.iter() }
+    }
+
+    /// Get an iterable of (&key, &mut value) pairs for the attributes of this node, in
+    /// order
+    pub fn properties_mut(&mut self) -> PropertiesMut<'_> {
+        PropertiesMut { attributes: self.attributes.iter_mut() }
+    }
+
+    /// Sort the attributes of this node by their keys.  See `sort_properties_by`.
+    pub fn sort_properties(&mut self) {
+        self.attributes.sort_by(|a, b| a.0.cmp(&b.0));
+    }
+
+    /// Sort the attributes of this node with a comparison function, which is given the
+    /// (key, value) pairs of the attributes to compare.  The sort is stable, so
+    /// attributes that compare equal keep their order.  The subnodes aren't sorted.
+    ///
+    /// # Examples
+    /// ```
+    /// # use figtree::types::*;
+    /// # use std::io::Cursor;
+    /// let mut doc = Document::parse(Cursor::new("a { x = 3, y = 1, z = 2 }")).unwrap();
+    /// let node = doc.get_node_mut("a").unwrap();
+    /// node.sort_properties_by(|(_, a), (_, b)| a.get_int().cmp(&b.get_int()));
+    /// let keys: Vec<_> = doc["a"].properties().map(|(key, _)| key.as_str()).collect();
+    /// assert_eq!(keys, vec!["y", "z", "x"]);
+    /// ```
+    pub fn sort_properties_by<F>(&mut self, mut compare: F)
+        where F: FnMut((&str, &Value), (&str, &Value)) -> Ordering {
+
+        self.attributes.sort_by(|a, b| compare((a.0.as_str(), &a.1), (b.0.as_str(), &b.1)));
+    }
+
+    /// Sort the subnodes of this node by their names.  See `sort_children_by`.
+    pub fn sort_children(&mut self) {
+        self.subnodes.sort_by(|a, b| a.name.cmp(&b.name));
+    }
+
+    /// Sort the subnodes of this node with a comparison function.  The sort is stable, so
+    /// nodes that compare equal keep their order - in particular, repeated nodes stay in
+    /// the same order as each other when sorting by name.  The nodes inside the subnodes
+    /// aren't sorted.
+    pub fn sort_children_by<F>(&mut self, compare: F) where F: FnMut(&Node, &Node) -> Ordering {
+        self.subnodes.sort_by(compare);
+    }
+
+    /// Get a reference to the value of the attribute with the given key
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.attributes.get(key)
+    }
+
+    /// Test if this node has an attribute with the given key
+    pub fn has(&self, key: &str) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Returns the number of entries (both subnodes and attributes) in this node.
+    pub fn len(&self) -> usize {
+        self.subnodes.len() + self.attributes.len()
+    }
+
+}
+
+// `AttrError`s are only made when a lookup fails, so they aren't boxed to keep them small
+#[allow(clippy::result_large_err)]
+impl Node {
+    /// Get an attribute, converted with `convert`, or an `AttrError` describing why it
+    /// couldn't be
+    fn get_typed<'a, T, F>(&'a self, key: &str, expected: &'static str, convert: F)
+        -> Result<T, AttrError> where F: FnOnce(&'a Value) -> Option<T> {
+
+        let value = self.get(key);
+        value.and_then(convert).ok_or_else(|| AttrError {
+            node: self.name.to_string(),
+            key: key.to_string(),
+            expected,
+            found: value.map(Value::type_name),
+            // a missing attribute is reported at the node that it's missing from
+            span: self.value_span(key).map(|spans| &spans.span).or(self.span()).cloned(),
+            origin: self.origin.clone(),
+        })
+    }
+
+    /// Get the value of a string attribute.
+    ///
+    /// # Failures
+    /// Fails if the attribute is missing or isn't a string.
+    ///
+    /// # Examples
+    /// ```
+    /// # use figtree::types::*;
+    /// # use std::io::Cursor;
+    /// let doc = Document::parse(Cursor::new("server { host = 'localhost', port = '80' }")).unwrap();
+    /// assert_eq!(doc["server"].get_str("host"), Ok("localhost"));
+    /// let error = doc["server"].get_i64("port").unwrap_err();
+    /// assert_eq!(error.to_string(),
+    ///     "expected \"port\" in node \"server\" to be an integer, found a string (at line 1, column 37)");
+    /// ```
+    pub fn get_str(&self, key: &str) -> Result<&str, AttrError> {
+        self.get_typed(key, "string", Value::get_str)
+    }
+
+    /// Get the value of an integer attribute.
+    ///
+    /// # Failures
+    /// Fails if the attribute is missing or isn't an integer.
+    pub fn get_i64(&self, key: &str) -> Result<i64, AttrError> {
+        self.get_typed(key, "integer", Value::get_int)
+    }
+
+    /// Get the value of a float attribute.  Integers are converted to floats.
+    ///
+    /// # Failures
+    /// Fails if the attribute is missing or isn't a number.
+    pub fn get_f64(&self, key: &str) -> Result<f64, AttrError> {
+        self.get_typed(key, "float", |value| match *value {
+            Value::Int(int) => Some(int as f64),
+            ref value => value.get_float(),
+        })
+    }
+
+    /// Get the value of a boolean attribute.
+    ///
+    /// # Failures
+    /// Fails if the attribute is missing or isn't a boolean.
+    pub fn get_bool(&self, key: &str) -> Result<bool, AttrError> {
+        self.get_typed(key, "boolean", Value::get_bool)
+    }
+
+    /// Get the value of an identifier attribute.
+    ///
+    /// # Failures
+    /// Fails if the attribute is missing or isn't an identifier.
+    pub fn get_identifier(&self, key: &str) -> Result<&str, AttrError> {
+        self.get_typed(key, "identifier", Value::get_ident)
+    }
+
+    /// Get the value of a list attribute.
+    ///
+    /// # Failures
+    /// Fails if the attribute is missing or isn't a list.
+    pub fn get_list(&self, key: &str) -> Result<&[Value], AttrError> {
+        self.get_typed(key, "list", Value::get_list)
+    }
+
+    /// Get the value of a dict attribute.
+    ///
+    /// # Failures
+    /// Fails if the attribute is missing or isn't a dict.
+    pub fn get_dict(&self, key: &str) -> Result<&Dict, AttrError> {
+        self.get_typed(key, "dict", Value::get_dict)
+    }
+}
+
+impl Node {
+
+    /// Insert a new value into this node.
+    ///
+    /// If there is already a value with the given name, replace it and return the old
+    /// value.  The replacement keeps the old value's place in the node.
+    pub fn insert_attr<S>(&mut self, name: S, value: Value) -> Option<Value>
+        where S: Into<Symbol> {
+
+        let name = name.into();
+        self.attr_spans.remove(&name);
+        self.attributes.insert(name, value)
+    }
+
+
+    /// Remove an attribute from this node.
+    ///
+    /// Returns the deleted value.
+    pub fn delete_attr<S>(&mut self, name: S) -> Option<Value> where S: AsRef<str> {
+        let name = name.as_ref();
+        self.attr_comments.remove(name);
+        self.attr_spans.remove(name);
+        self.attributes.remove(name)
+    }
+
+    /// Where this node is in the input, from the start of its name to its closing brace.
+    /// Only nodes that were parsed have a span.
+    pub fn span(&self) -> Option<&Span> {
+        self.span.as_ref()
+    }
+
+    /// Set where this node is in the input
+    pub fn set_span(&mut self, span: Span) {
+        self.span = Some(span);
+    }
+
+    /// Where this node came from.  Nodes keep their origin when they are moved into
+    /// another document (by `Document::merge`, for example), so it may be different to
+    /// the origin of the document they are in.
+    pub fn origin(&self) -> Option<&Origin> {
+        self.origin.as_deref()
+    }
+
+    /// Set where this node and all of the nodes inside it came from
+    pub fn set_origin(&mut self, origin: Origin) {
+        self.share_origin(&Arc::new(origin));
+    }
+
+    fn share_origin(&mut self, origin: &Arc<Origin>) {
+        self.origin = Some(origin.clone());
+        for child in &mut self.subnodes {
+            child.share_origin(origin);
+        }
+    }
+
+    /// Replace the names and keys in this node and the nodes inside it with the
+    /// interner's symbols for them.  See `Document::intern_all`.
+    fn intern_all(&mut self, interner: &mut Interner) {
+        self.name = interner.intern_symbol(self.name.clone());
+        self.attributes.replace_keys(|key| interner.intern_symbol(key.clone()));
+        self.attr_comments = self.attr_comments.drain()
+            .map(|(key, comments)| (interner.intern_symbol(key), comments))
+            .collect();
+        self.attr_spans = self.attr_spans.drain()
+            .map(|(key, spans)| (interner.intern_symbol(key), spans))
+            .collect();
+        for child in &mut self.subnodes {
+            child.intern_all(interner);
+        }
+    }
+
+    /// Where the key of an attribute is in the input, including the `=` or `:` after it
+    pub fn key_span(&self, key: &str) -> Option<&Span> {
+        self.attr_spans.get(key).map(|spans| &spans.0)
+    }
+
+    /// Where the value of an attribute is in the input, along with any values inside it.
+    /// Replacing the value with `insert_attr` removes its span.
+    ///
+    /// # Examples
+    /// ```
+    /// # use figtree::types::*;
+    /// # use figtree::Position;
+    /// # use std::io::Cursor;
+    /// let doc = Document::parse(Cursor::new("server {\n  ports = [80, 'x']\n}")).unwrap();
+    /// let ports = doc["server"].value_span("ports").unwrap();
+    /// assert_eq!(ports.span.start, Position::at(1, 10));
+    /// assert_eq!(ports.items[1].span.start, Position::at(1, 15));
+    /// ```
+    pub fn value_span(&self, key: &str) -> Option<&ValueSpan> {
+        self.attr_spans.get(key).map(|spans| &spans.1)
+    }
+
+    /// Set where the key and value of an attribute are in the input.  Does nothing if
+    /// there is no attribute with the given key.
+    pub fn set_attr_span<S>(&mut self, key: S, key_span: Span, value_span: ValueSpan)
+        where S: AsRef<str> {
+
+        if let Some(key) = self.attributes.key(key.as_ref()) {
+            self.attr_spans.insert(key.clone(), (key_span, value_span));
+        }
+    }
+
+    /// The comments attached to this node
+    pub fn comments(&self) -> &Comments {
+        &self.comments
+    }
+
+    /// Get a mutable reference to the comments attached to this node
+    pub fn comments_mut(&mut self) -> &mut Comments {
+        &mut self.comments
+    }
+
+    /// The comments attached to an attribute, if it has any
+    pub fn attr_comments(&self, key: &str) -> Option<&Comments> {
+        self.attr_comments.get(key).filter(|comments| !comments.is_empty())
+    }
+
+    /// Get a mutable reference to the comments attached to an attribute, or `None` if
+    /// there is no attribute with the given key
+    pub fn attr_comments_mut(&mut self, key: &str) -> Option<&mut Comments> {
+        let key = self.attributes.key(key)?;
+        Some(self.attr_comments.entry(key.clone()).or_default())
+    }
+
+    /// Get a reference to the specified attribute value
+    pub fn get_attr<S>(&self, name: S) -> Option<&Value> where S: AsRef<str> {
+        self.get(name.as_ref())
+    }
+
+    /// Get a mutable reference to the specified attribute value
+    pub fn get_attr_mut<S>(&mut self, name: S) -> Option<&mut Value>
+        where S: AsRef<str> {
+
+        self.attributes.get_mut(name.as_ref())
+    }
+
+    /// Get an iterable of (&name, &attribute) pairs
+    pub fn iter_attrs(&self) -> Properties<'_> {
+        self.properties()
+    }
+
+    /// Test if this node has no arguments, subnodes or attributes
+    pub fn is_empty(&self) -> bool {
+        self.arguments.is_empty() && self.subnodes.is_empty() && self.attributes.is_empty()
+    }
+
+    /// Test if this node has a subnode with the given name.
+    pub fn has_node(&self, name: &str) -> bool {
+        self.child(name).is_some()
+    }
+
+    /// Test if this node has any subnodes at all.
+    pub fn has_nodes(&self) -> bool {
+        !self.subnodes.is_empty()
+    }
+
+    /// Returns the number of subnodes.
+    pub fn node_count(&self) -> usize {
+        self.subnodes.len()
+    }
+
+    /// Test if this node had an attribute with the given key.
+    pub fn has_attr(&self, name: &str) -> bool {
+        self.has(name)
+    }
+
+    /// Test if this node has any attributes at all.
+    pub fn has_attrs(&self) -> bool {
+        !self.attributes.is_empty()
+    }
+
+    /// Returns the number of attributes.
+    pub fn attr_count(&self) -> usize {
+        self.attributes.len()
+    }
+
+    /// Convert the node to a dict, in the same shape that it's serialized in with serde:
+    /// its arguments, if it has any, as a list under the key `"$arguments"`, its
+    /// attributes, and its subnodes.  A subnode that's the only one with its name is a
+    /// dict of its own, and subnodes that share a name are a list of dicts.  A subnode
+    /// with the same name as an attribute replaces it.
+    ///
+    /// # Examples
+    /// ```
+    /// # use figtree::types::*;
+    /// # use std::io::Cursor;
+    /// let doc = Document::parse(Cursor::new("server { port = 80 tls { } }")).unwrap();
+    /// let dict = doc["server"].to_dict();
+    /// assert_eq!(dict["port"], Value::new_int(80));
+    /// assert_eq!(dict["tls"], Value::Dict(Dict::new()));
+    /// ```
+    pub fn to_dict(&self) -> Dict {
+        let mut dict = Dict::new();
+        if !self.arguments.is_empty() {
+            dict.insert(ARGUMENTS_KEY.into(), Value::List(self.arguments.clone()));
+        }
+        for (key, value) in self.iter_attrs() {
+            dict.insert(key.to_string(), value.clone());
+        }
+        let mut groups: Vec<(&str, Vec<Value>)> = Vec::new();
+        for child in &self.subnodes {
+            let value = Value::Dict(child.to_dict());
+            match groups.iter_mut().find(|group| group.0 == child.name()) {
+                Some(group) => group.1.push(value),
+                None => groups.push((child.name(), vec![value])),
+            }
+        }
+        for (name, mut nodes) in groups {
+            let value = if nodes.len() == 1 { nodes.remove(0) } else { Value::List(nodes) };
+            dict.insert(name.into(), value);
+        }
+        dict
+    }
+}
+
+impl Default for Node {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Node {
+    /// Test if two nodes mean the same thing.  See `Document::structurally_eq`.
+    pub fn structurally_eq(&self, other: &Node) -> bool {
+        self.arguments.len() == other.arguments.len()
+            && self.arguments.iter().zip(&other.arguments).all(|(a, b)| a.structurally_eq(b))
+            && self.attributes.len() == other.attributes.len()
+            && self.attributes.iter().all(|(key, value)| {
+                other.get(key).is_some_and(|other| value.structurally_eq(other))
+            })
+            && same_nodes_by(&self.subnodes, &other.subnodes, Node::structurally_eq)
+    }
+}
+
+impl PartialEq for Node {
+    fn eq(&self, other: &Node) -> bool {
+        self.arguments == other.arguments
+            && self.attributes.len() == other.attributes.len()
+            && self.attributes.iter().all(|(key, value)| other.get(key) == Some(value))
+            && same_nodes(&self.subnodes, &other.subnodes)
+    }
+}
+
+/// Look up a subnode by its position.
+///
+/// # Panics
+/// Panics if the node has no subnode at that position.
+impl Index<usize> for Node {
+    type Output = Node;
+
+    fn index(&self, index: usize) -> &Node {
+        node_child(self, self.name(), index)
+    }
+}
+
+/// Look up an attribute of the node.  Subnodes can be looked up by name through an
+/// `Indexed`.
+///
+/// # Panics
+/// Panics if the node doesn't have the attribute.
+///
+/// # Examples
+/// ```
+/// # use figtree::types::*;
+/// # use std::io::Cursor;
+/// let doc = Document::parse(Cursor::new("server { hosts = ['a', 'b'] }")).unwrap();
+/// assert_eq!(doc["server"]["hosts"][1], Value::new_string("b"));
+/// ```
+impl<'a> Index<&'a str> for Node {
+    type Output = Value;
+
+    fn index(&self, key: &'a str) -> &Value {
+        match self.get(key) {
+            Some(value) => value,
+            None => panic!("no attribute {:?} in node {:?}", key, self.name()),
+        }
+    }
+}
+
+fn node_child<'a>(node: &'a Node, path: &str, index: usize) -> &'a Node {
+    match node.subnodes.get(index) {
+        Some(child) => child,
+        None => panic!("index {} out of range for node {:?} with {} subnodes",
+                       index, path, node.subnodes.len()),
+    }
+}
+
+/// What an `Indexed` was found to be
+#[derive(Debug, Clone, Copy)]
+enum Target<'a> {
+    Document(&'a Document),
+    Node(&'a Node),
+    Value(&'a Value),
+}
+
+impl<'a> Target<'a> {
+    fn is(self, other: Target) -> bool {
+        match (self, other) {
+            (Target::Document(a), Target::Document(b)) => ptr::eq(a, b),
+            (Target::Node(a), Target::Node(b)) => ptr::eq(a, b),
+            (Target::Value(a), Target::Value(b)) => ptr::eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+/// A document, node, or value that can be looked up in with `[]`, and that remembers the
+/// path it was found at, so that a lookup that fails partway along a chain like
+/// `doc["server"]["tls"]["cert"]` can say where it was looking.
+///
+/// Looking up a name finds a node in a document, a subnode or else an attribute in a
+/// node (as with `Document::get_path`), and a key in a dict or node value.  Looking up a
+/// position finds a node or subnode, or an item in a list value.
+///
+/// # Panics
+/// Looking up something that isn't there panics, naming the path that was looked up.
+///
+/// # Examples
+/// ```
+/// # use figtree::types::*;
+/// # use std::io::Cursor;
+/// let input = "server { tls { cert = 'a.pem' } hosts = ['a', 'b'] }";
+/// let doc = Document::parse(Cursor::new(input)).unwrap();
+/// let doc = Indexed::from(&doc);
+/// assert_eq!(doc["server"]["tls"]["cert"], Value::new_string("a.pem"));
+/// assert_eq!(doc["server"]["hosts"][1].as_value(), Some(&Value::new_string("b")));
+/// assert_eq!(doc["server"]["tls"].as_node().map(Node::name), Some("tls"));
+/// assert_eq!(doc["server"]["tls"].path(), "server.tls");
+/// ```
+pub struct Indexed<'a> {
+    target: Target<'a>,
+    path: String,
+    /// the first of the entries that have been looked up in this one, which are kept so
+    /// that they can be handed out by reference
+    first: OnceCell<Box<Indexed<'a>>>,
+    /// the entry looked up in the same parent after this one
+    next: OnceCell<Box<Indexed<'a>>>,
+}
+
+impl<'a> Indexed<'a> {
+    fn new(target: Target<'a>, path: String) -> Self {
+        Indexed { target, path, first: OnceCell::new(), next: OnceCell::new() }
+    }
+
+    /// The entry for `target`, which was found in this one at `path`
+    fn found(&self, target: Target<'a>, path: String) -> &Indexed<'a> {
+        let mut slot = &self.first;
+        while let Some(entry) = slot.get() {
+            if entry.target.is(target) {
+                return entry;
+            }
+            slot = &entry.next;
+        }
+        slot.get_or_init(|| Box::new(Indexed::new(target, path)))
+    }
+
+    /// What this is looked up in as: node values are looked up in as nodes
+    fn container(&self) -> Target<'a> {
+        match self.target {
+            Target::Value(Value::Node(node)) => Target::Node(node),
+            target => target,
+        }
+    }
+
+    /// The path of names and positions that this was found at
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// The node, if this is a node or a subnode, or a node value
+    pub fn as_node(&self) -> Option<&'a Node> {
+        match self.target {
+            Target::Document(_) => None,
+            Target::Node(node) => Some(node),
+            Target::Value(value) => value.get_node(),
+        }
+    }
+
+    /// The value, if this is a value or the value of an attribute
+    pub fn as_value(&self) -> Option<&'a Value> {
+        match self.target {
+            Target::Value(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl<'a> From<&'a Document> for Indexed<'a> {
+    fn from(doc: &'a Document) -> Self {
+        Indexed::new(Target::Document(doc), String::new())
+    }
+}
+
+impl<'a> From<&'a Node> for Indexed<'a> {
+    fn from(node: &'a Node) -> Self {
+        Indexed::new(Target::Node(node), node.name().to_string())
+    }
+}
+
+impl<'a> From<&'a Value> for Indexed<'a> {
+    fn from(value: &'a Value) -> Self {
+        Indexed::new(Target::Value(value), String::new())
+    }
+}
+
+/// Look up a node, a subnode or an attribute, or a key of a dict or node value.
+///
+/// # Panics
+/// Panics if there's nothing with the name.
+impl<'a, 'k> Index<&'k str> for Indexed<'a> {
+    type Output = Indexed<'a>;
+
+    fn index(&self, name: &'k str) -> &Indexed<'a> {
+        let found = match self.container() {
+            Target::Document(doc) => match doc.child(name) {
+                Some(node) => Target::Node(node),
+                None => panic!("no node {:?} in document", name),
+            },
+            Target::Node(node) => match (node.child(name), node.get(name)) {
+                (Some(child), _) => Target::Node(child),
+                (None, Some(value)) => Target::Value(value),
+                (None, None) => panic!("no attribute or subnode {:?} in {:?}", name, self.path),
+            },
+            Target::Value(value) => Target::Value(value_key(value, &self.path, name)),
+        };
+        self.found(found, path::join(&self.path, name))
+    }
+}
+
+/// Look up a node or a subnode by its position, or an item of a list value.
+///
+/// # Panics
+/// Panics if there's nothing at the position.
+impl<'a> Index<usize> for Indexed<'a> {
+    type Output = Indexed<'a>;
+
+    fn index(&self, index: usize) -> &Indexed<'a> {
+        let (found, path) = match self.container() {
+            Target::Document(doc) => {
+                let node = doc_node(doc, index);
+                (Target::Node(node), path::join(&self.path, node.name()))
+            },
+            Target::Node(node) => {
+                let child = node_child(node, &self.path, index);
+                (Target::Node(child), path::join(&self.path, child.name()))
+            },
+            Target::Value(value) => (Target::Value(value_item(value, &self.path, index)),
+                                     format!("{}[{}]", self.path, index)),
+        };
+        self.found(found, path)
+    }
+}
+
+impl<'a> PartialEq<Value> for Indexed<'a> {
+    fn eq(&self, other: &Value) -> bool {
+        self.as_value() == Some(other)
+    }
+}
+
+impl<'a> fmt::Debug for Indexed<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Indexed").field("path", &self.path).field("target", &self.target).finish()
+    }
+}
+
+/// Entries are written in the same way as the document, node, or value that they are.
+impl<'a> fmt::Display for Indexed<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.target {
+            Target::Document(doc) => fmt::Display::fmt(doc, f),
+            Target::Node(node) => fmt::Display::fmt(node, f),
+            Target::Value(value) => fmt::Display::fmt(value, f),
+        }
+    }
+}
+
+/// The error produced by the typed attribute getters (`Node::get_str` and so on) when an
+/// attribute is missing or has the wrong type.
+#[derive(Debug, PartialEq, Clone)]
+pub struct AttrError {
+    /// The name of the node that the attribute was looked up in
+    pub node: String,
+    /// The key of the attribute
+    pub key: String,
+    /// The type of value that was expected (`"string"`, `"integer"`, etc.)
+    pub expected: &'static str,
+    /// The type of value that was found, or `None` if the attribute is missing
+    pub found: Option<&'static str>,
+    /// Where the attribute's value is in the source, or where the node is if the
+    /// attribute is missing.  `None` for nodes that weren't parsed.
+    pub span: Option<Span>,
+    /// Where the node came from, if it is known.  This is left out of the message if it
+    /// is `Origin::Inline`.
+    pub origin: Option<Arc<Origin>>,
+}
 
-        self.subnodes.insert(name.into(), node)
-    }
+/// "a" or "an", as appropriate for a type name
+fn article(type_name: &str) -> &'static str {
+    if type_name.starts_with(|ch| "aeiou".contains(ch)) { "an" } else { "a" }
+}
 
-    /// Remove a subnode from this node.
-    ///
-    /// Returns the deleted node.
-    pub fn delete_node<S>(&mut self, name: S) -> Option<Node> where S: Into<String> {
-        self.subnodes.remove(&name.into())
+impl fmt::Display for AttrError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.found {
+            Some(found) => write!(f, "expected {:?} in node {:?} to be {} {}, found {} {}",
+                self.key, self.node, article(self.expected), self.expected, article(found), found)?,
+            None => write!(f, "expected {} {} {:?} in node {:?}, but it is missing",
+                article(self.expected), self.expected, self.key, self.node)?,
+        }
+        // saying that inline input is inline doesn't tell anyone anything
+        let origin = self.origin.as_deref().filter(|origin| **origin != Origin::Inline);
+        match (origin, self.span.as_ref()) {
+            (Some(origin), Some(span)) => write!(f, " (in {}, at line {}, column {})",
+                origin, span.start.line + 1, span.start.pos + 1)?,
+            (Some(origin), None) => write!(f, " (in {})", origin)?,
+            (None, Some(span)) =>
+                write!(f, " (at line {}, column {})", span.start.line + 1, span.start.pos + 1)?,
+            (None, None) => {},
+        }
+        Ok(())
     }
+}
 
-    /// Get a reference to the specified subnode
-    pub fn get_node<S>(&self, name: S) -> Option<&Self> where S: Into<String> {
-        self.subnodes.get(&name.into())
-    }
+impl Error for AttrError {}
 
-    /// Get a mutable reference to the specified subnode
-    pub fn get_node_mut<S>(&mut self, name: S) -> Option<&mut Self>
-        where S: Into<String> {
+/// An iterator over the (&name, &node) pairs of a node or document's subnodes
+pub struct Subnodes<'a> {
+    nodes: slice::Iter<'a, Node>,
+}
+
+impl<'a> Iterator for Subnodes<'a> {
+    type Item = (&'a Symbol, &'a Node);
 
-        self.subnodes.get_mut(&name.into())
+    fn next(&mut self) -> Option<Self::Item> {
+        self.nodes.next().map(|node| (&node.name, node))
     }
 
-    /// Get an iterable of (&name, &node) pairs
-    pub fn iter_nodes(&self) -> Iter<String, Node> {
-        self.subnodes.iter()
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.nodes.size_hint()
     }
+}
 
-    /// Insert a new value into this node.
-    ///
-    /// If there is already a value with the given name, replace it and return the old
-    /// value.
-    pub fn insert_attr<S>(&mut self, name: S, value: Value) -> Option<Value>
-        where S: Into<String> {
+impl<'a> ExactSizeIterator for Subnodes<'a> {}
 
-        self.attributes.insert(name.into(), value)
-    }
+/// An iterator over the subnodes of a node or document that have a particular name
+pub struct ChildrenNamed<'a, 'b> {
+    nodes: slice::Iter<'a, Node>,
+    name: &'b str,
+}
 
+impl<'a, 'b> Iterator for ChildrenNamed<'a, 'b> {
+    type Item = &'a Node;
 
-    /// Remove an attribute from this node.
-    ///
-    /// Returns the deleted value.
-    pub fn delete_attr<S>(&mut self, name: S) -> Option<Value> where S: Into<String> {
-        self.attributes.remove(&name.into())
+    fn next(&mut self) -> Option<Self::Item> {
+        let name = self.name;
+        self.nodes.find(|node| *node.name == *name)
     }
+}
 
-    /// Get a reference to the specified attribute value
-    pub fn get_attr<S>(&self, name: S) -> Option<&Value> where S: Into<String> {
-        self.attributes.get(&name.into())
-    }
+/// An iterator over the (&key, &value) pairs of a node's attributes
+pub struct Properties<'a> {
+    attributes: slice::Iter<'a, (Symbol, Value)>,
+}
 
-    /// Get a mutable reference to the specified attribute value
-    pub fn get_attr_mut<S>(&mut self, name: S) -> Option<&mut Value>
-        where S: Into<String> {
+impl<'a> Iterator for Properties<'a> {
+    type Item = (&'a Symbol, &'a Value);
 
-        self.attributes.get_mut(&name.into())
+    fn next(&mut self) -> Option<Self::Item> {
+        self.attributes.next().map(|(key, value)| (key, value))
     }
 
-    /// Get an iterable of (&name, &attribute) pairs
-    pub fn iter_attrs(&self) -> Iter<String, Value> {
-        self.attributes.iter()
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.attributes.size_hint()
     }
+}
 
-    /// Test if this node has no subnodes or attributes
-    pub fn is_empty(&self) -> bool {
-        self.subnodes.is_empty() && self.attributes.is_empty()
+impl<'a> DoubleEndedIterator for Properties<'a> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.attributes.next_back().map(|(key, value)| (key, value))
     }
+}
 
-    /// Test if this node has a subnode with the given name.
-    pub fn has_node(&self, name: &String) -> bool {
-        self.subnodes.contains_key(name)
-    }
+impl<'a> ExactSizeIterator for Properties<'a> {}
 
-    /// Test if this node has any subnodes at all.
-    pub fn has_nodes(&self) -> bool {
-        !self.subnodes.is_empty()
+/// An iterator over the (&key, &mut value) pairs of a node's attributes
+pub struct PropertiesMut<'a> {
+    attributes: slice::IterMut<'a, (Symbol, Value)>,
+}
+
+impl<'a> Iterator for PropertiesMut<'a> {
+    type Item = (&'a Symbol, &'a mut Value);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.attributes.next().map(|&mut (ref key, ref mut value)| (key, value))
     }
 
-    /// Returns the number of subnodes.
-    pub fn node_count(&self) -> usize {
-        self.subnodes.len()
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.attributes.size_hint()
     }
+}
 
-    /// Test if this node had an attribute with the given key.
-    pub fn has_attr(&self, name: &String) -> bool {
-        self.attributes.contains_key(name)
+impl<'a> ExactSizeIterator for PropertiesMut<'a> {}
+
+/// Insert a new node with the given name into `nodes`, unless there is one already
+fn new_node_or_get(nodes: &mut Vec<Node>, name: Symbol) -> &mut Node {
+    match nodes.iter().position(|node| node.name == name) {
+        Some(index) => &mut nodes[index],
+        None => append_node(nodes, name, Node::new()),
     }
+}
 
-    /// Test if this node has any attributes at all.
-    pub fn has_attrs(&self) -> bool {
-        !self.attributes.is_empty()
+/// Insert a node into `nodes`, replacing the first node with the same name
+fn insert_node(nodes: &mut Vec<Node>, name: Symbol, mut node: Node) -> Option<Node> {
+    match nodes.iter_mut().find(|old| old.name == name) {
+        Some(old) => {
+            node.name = name;
+            Some(::std::mem::replace(old, node))
+        },
+        None => {
+            append_node(nodes, name, node);
+            None
+        },
     }
+}
 
-    /// Returns the number of attributes.
-    pub fn attr_count(&self) -> usize {
-        self.attributes.len()
+/// Add a node to the end of `nodes`
+fn append_node(nodes: &mut Vec<Node>, name: Symbol, mut node: Node) -> &mut Node {
+    node.name = name;
+    nodes.push(node);
+    nodes.last_mut().unwrap()
+}
+
+/// Remove the node at `index` from `nodes`, if there is one
+fn detach(nodes: &mut Vec<Node>, index: usize) -> Option<Node> {
+    if index < nodes.len() { Some(nodes.remove(index)) } else { None }
+}
+
+/// Remove the first node with the given name from `nodes`
+fn delete_node(nodes: &mut Vec<Node>, name: &str) -> Option<Node> {
+    let index = nodes.iter().position(|node| *node.name == *name)?;
+    Some(nodes.remove(index))
+}
+
+/// Test if two lists of nodes have the same nodes, allowing nodes with different names
+/// to be in different orders
+fn same_nodes(nodes: &[Node], others: &[Node]) -> bool {
+    same_nodes_by(nodes, others, |node, other| node == other)
+}
+
+/// `same_nodes`, comparing nodes with `eq`
+fn same_nodes_by<F>(nodes: &[Node], others: &[Node], eq: F) -> bool
+    where F: Fn(&Node, &Node) -> bool {
+
+    fn named<'a>(nodes: &'a [Node], name: &'a str) -> impl Iterator<Item = &'a Node> + 'a {
+        nodes.iter().filter(move |node| *node.name == *name)
     }
+    nodes.len() == others.len() && nodes.iter().all(|node| {
+        named(nodes, &node.name).count() == named(others, &node.name).count()
+            && named(nodes, &node.name).zip(named(others, &node.name)).all(|(a, b)| eq(a, b))
+    })
 }
 
 /// A struct representing a parsed figtree document.
@@ -350,17 +1624,206 @@ impl Node {
 ///     // node can be modified here
 /// }
 /// ```
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone)]
 pub struct Document {
-    nodes: HashMap<String, Node>,
+    nodes: Vec<Node>,
+    comments: Vec<String>,
+    origin: Option<Arc<Origin>>,
+    interner: Interner,
+    hooks: MutationHooks,
 }
 
 impl Document {
     /// Construct a new, empty document
     pub fn new() -> Self {
         Document {
-            nodes: HashMap::new(),
+            nodes: Vec::new(),
+            comments: Vec::new(),
+            origin: None,
+            interner: Interner::new(),
+            hooks: MutationHooks::default(),
+        }
+    }
+
+    /// The interner used for the names and keys in this document.  See `Interner`.
+    ///
+    /// Names given to the document's own methods (such as `Document::append_node`) are
+    /// interned with it, as are all the names and keys in a parsed document.  Nodes
+    /// don't know which document they are in, so `Node` methods can't intern the names
+    /// they are given - intern them first, or call `intern_all` after making changes.
+    pub fn interner(&self) -> &Interner {
+        &self.interner
+    }
+
+    /// Get a mutable reference to the interner used for the names and keys in this
+    /// document
+    pub fn interner_mut(&mut self) -> &mut Interner {
+        &mut self.interner
+    }
+
+    /// Intern every name and key in the document with a new interner, so that each
+    /// distinct string is only stored once.  Strings that are no longer used anywhere
+    /// in the document are dropped from the interner.
+    ///
+    /// # Examples
+    /// ```
+    /// # use figtree::types::*;
+    /// let mut doc = Document::new();
+    /// for name in &["a", "b"] {
+    ///     doc.new_node_or_get(*name).insert_attr("port", Value::new_int(80));
+    /// }
+    /// let ports = |doc: &Document| {
+    ///     let a = doc["a"].properties().next().unwrap().0.clone();
+    ///     let b = doc["b"].properties().next().unwrap().0.clone();
+    ///     a.ptr_eq(&b)
+    /// };
+    /// assert!(!ports(&doc));
+    /// doc.intern_all();
+    /// assert!(ports(&doc));
+    /// assert_eq!(doc.interner().len(), 3);
+    /// ```
+    pub fn intern_all(&mut self) {
+        let mut interner = Interner::new();
+        for node in &mut self.nodes {
+            node.intern_all(&mut interner);
+        }
+        self.interner = interner;
+    }
+
+    /// Where the document came from: the file it was read from, for example.  This is
+    /// set by `Figtree` when it knows, and can be set with `set_origin` otherwise.
+    pub fn origin(&self) -> Option<&Origin> {
+        self.origin.as_deref()
+    }
+
+    /// Set where the document came from.  This is also set as the origin of every node
+    /// in the document, so that errors from looking up their attributes say where they
+    /// came from.
+    ///
+    /// # Examples
+    /// ```
+    /// # use figtree::types::*;
+    /// # use figtree::Origin;
+    /// # use std::io::Cursor;
+    /// let mut doc = Document::parse(Cursor::new("server { port = 'http' }")).unwrap();
+    /// doc.set_origin(Origin::File("config.fig".into()));
+    /// assert_eq!(doc["server"].get_i64("port").unwrap_err().to_string(),
+    ///     "expected \"port\" in node \"server\" to be an integer, found a string \
+    ///      (in config.fig, at line 1, column 17)");
+    /// ```
+    pub fn set_origin(&mut self, origin: Origin) {
+        let origin = Arc::new(origin);
+        for node in &mut self.nodes {
+            node.share_origin(&origin);
         }
+        self.origin = Some(origin);
+    }
+
+    /// Register a hook that is asked before each change made to the document, so that
+    /// invariants (such as reserved key names) can be kept without checking every change
+    /// by hand.  The hook rejects a change by returning an error message, in which case
+    /// `set_path`, `remove_path` and `apply_patch` fail with `PatchError::Rejected`,
+    /// `merge` fails with `MergeError::Rejected`, and the `try_*` methods that add,
+    /// remove and sort the document's nodes fail with a `HookError`, all leaving the
+    /// document unchanged.  The methods that those `try_*` methods stand in for panic
+    /// instead.  Hooks are asked in the order they were added, and are kept by clones of
+    /// the document.
+    ///
+    /// Hooks can't see changes made through references to the document's nodes (from
+    /// `get_node_mut`, for example), or by the methods that take those references.  Use
+    /// `check_mutation` to ask the hooks about such changes first.
+    ///
+    /// # Examples
+    /// ```
+    /// # use figtree::types::*;
+    /// # use figtree::{Mutation, PatchError};
+    /// # use std::io::Cursor;
+    /// let mut doc = Document::parse(Cursor::new("server { port = 80 }")).unwrap();
+    /// doc.add_mutation_hook(|mutation: &Mutation| match *mutation {
+    ///     Mutation::SetValue(_, value) if mutation.name() == "port" && !value.is_int() =>
+    ///         Err("ports must be integers".to_string()),
+    ///     _ => Ok(()),
+    /// });
+    /// assert!(doc.set_path("server.port", Value::new_int(8080)).is_ok());
+    /// assert_eq!(doc.set_path("server.port", Value::new_string("http")),
+    ///     Err(PatchError::Rejected("server.port".to_string(), "ports must be integers".to_string())));
+    /// assert_eq!(doc["server"]["port"], Value::new_int(8080));
+    /// ```
+    pub fn add_mutation_hook<F>(&mut self, hook: F)
+        where F: Fn(&Mutation) -> Result<(), String> + Send + Sync + 'static {
+
+        self.hooks.push(Arc::new(hook));
+    }
+
+    /// Remove all of the document's mutation hooks
+    pub fn clear_mutation_hooks(&mut self) {
+        self.hooks.clear();
+    }
+
+    /// Ask the document's mutation hooks whether a change is allowed, returning the error
+    /// message of the first hook to reject it
+    pub fn check_mutation(&self, mutation: &Mutation) -> Result<(), String> {
+        self.hooks.check(mutation)
+    }
+
+    /// Ask the mutation hooks about a change, giving the error for a rejected change
+    pub(crate) fn allow(&self, mutation: &Mutation) -> Result<(), HookError> {
+        self.check_mutation(mutation)
+            .map_err(|reason| HookError { path: mutation.path().to_string(), reason })
+    }
+
+    pub(crate) fn has_hooks(&self) -> bool {
+        !self.hooks.is_empty()
+    }
+
+    /// A copy of the document without its mutation hooks, to make changes to that have
+    /// already been checked
+    pub(crate) fn unhooked(&self) -> Document {
+        Document { hooks: MutationHooks::default(), ..self.clone() }
+    }
+
+    /// Replace the contents of the document with another's, keeping its own mutation
+    /// hooks
+    pub(crate) fn replace_contents(&mut self, other: Document) {
+        let hooks = mem::take(&mut self.hooks);
+        *self = Document { hooks, ..other };
+    }
+
+    /// The comments in the document that aren't attached to a node, because the
+    /// document doesn't have any nodes.  See `Comments`.
+    pub fn comments(&self) -> &[String] {
+        &self.comments
+    }
+
+    /// Get a mutable reference to the comments that aren't attached to a node
+    pub fn comments_mut(&mut self) -> &mut Vec<String> {
+        &mut self.comments
+    }
+
+    /// Construct a builder for a document
+    pub fn builder() -> DocumentBuilder {
+        DocumentBuilder::new()
+    }
+
+    /// Parse a document from a reader.
+    ///
+    /// This is a shortcut for `Figtree::new(reader).parse()` - use a `Figtree` directly to
+    /// parse with a different set of options.
+    ///
+    /// # Failures
+    /// Fails if the document can't be parsed, returning the error and the span of the
+    /// input that it occurred in.
+    ///
+    /// # Examples
+    /// ```
+    /// # use figtree::types::*;
+    /// # use std::io::Cursor;
+    /// let doc = Document::parse(Cursor::new("server { port = 8080 }")).unwrap();
+    /// let port = doc.get_node("server").and_then(|node| node.get_attr("port"));
+    /// assert_eq!(port, Some(&Value::new_int(8080)));
+    /// ```
+    pub fn parse<R: Read + 'static>(reader: R) -> Result<Document, (ParseError, Span)> {
+        Figtree::new(reader).parse()
     }
 
     /// Construct a new node and insert it into the document.
@@ -368,25 +1831,151 @@ impl Document {
     /// Returns a mutable reference to the new node.  If there is a node already
     /// present with the given name, this method will not insert a new node and instead
     /// just return the old node.
-    pub fn new_node_or_get<S>(&mut self, name: S) -> &mut Node where S: Into<String> {
-        self.nodes.entry(name.into()).or_insert(Node::new())
+    ///
+    /// # Panics
+    /// Panics if a mutation hook rejects the new node.  See `try_new_node_or_get`.
+    pub fn new_node_or_get<S>(&mut self, name: S) -> &mut Node where S: Into<Symbol> {
+        allowed(self.try_new_node_or_get(name))
+    }
+
+    /// Construct a new node and insert it into the document, as `new_node_or_get` does.
+    ///
+    /// # Failures
+    /// Fails if a mutation hook rejects the new node.
+    pub fn try_new_node_or_get<S>(&mut self, name: S) -> Result<&mut Node, HookError>
+        where S: Into<Symbol> {
+
+        let name = self.interner.intern_symbol(name.into());
+        if !self.hooks.is_empty() && !self.has_node(&name) {
+            self.allow(&Mutation::SetNode(&name, &Node::new()))?;
+        }
+        Ok(new_node_or_get(&mut self.nodes, name))
     }
 
     /// Inserts a node into the document.
     ///
     /// If there is already a node with the given name, replace it and return the
     /// old node.
+    ///
+    /// # Panics
+    /// Panics if a mutation hook rejects the node.  See `try_insert_node`.
     pub fn insert_node<S>(&mut self, name: S, node: Node) -> Option<Node>
-        where S: Into<String> {
+        where S: Into<Symbol> {
+
+        allowed(self.try_insert_node(name, node))
+    }
 
-        self.nodes.insert(name.into(), node)
+    /// Inserts a node into the document, as `insert_node` does.
+    ///
+    /// # Failures
+    /// Fails if a mutation hook rejects the node.
+    pub fn try_insert_node<S>(&mut self, name: S, node: Node) -> Result<Option<Node>, HookError>
+        where S: Into<Symbol> {
+
+        let name = self.interner.intern_symbol(name.into());
+        if !self.hooks.is_empty() {
+            self.allow(&Mutation::SetNode(&name, &node))?;
+        }
+        Ok(insert_node(&mut self.nodes, name, node))
     }
 
     /// Remove a node from the document.
     ///
     /// Returns the deleted node, if it exists.
-    pub fn delete_node<S>(&mut self, name: S) -> Option<Node> where S: Into<String> {
-        self.nodes.remove(&name.into())
+    ///
+    /// # Panics
+    /// Panics if a mutation hook rejects the removal.  See `try_delete_node`.
+    pub fn delete_node<S>(&mut self, name: S) -> Option<Node> where S: AsRef<str> {
+        allowed(self.try_delete_node(name))
+    }
+
+    /// Remove a node from the document, as `delete_node` does.
+    ///
+    /// # Failures
+    /// Fails if a mutation hook rejects the removal.
+    pub fn try_delete_node<S>(&mut self, name: S) -> Result<Option<Node>, HookError>
+        where S: AsRef<str> {
+
+        let name = name.as_ref();
+        if !self.hooks.is_empty() && self.has_node(name) {
+            self.allow(&Mutation::Remove(name))?;
+        }
+        Ok(delete_node(&mut self.nodes, name))
+    }
+
+    /// Remove the node at `index`, returning it as an owned subtree.  See `Node::detach`.
+    ///
+    /// # Panics
+    /// Panics if a mutation hook rejects the removal.  See `try_detach`.
+    pub fn detach(&mut self, index: usize) -> Option<Node> {
+        allowed(self.try_detach(index))
+    }
+
+    /// Remove the node at `index`, as `detach` does.
+    ///
+    /// # Failures
+    /// Fails if a mutation hook rejects the removal.
+    pub fn try_detach(&mut self, index: usize) -> Result<Option<Node>, HookError> {
+        if !self.hooks.is_empty() && index < self.nodes.len() {
+            let name = self.nodes[index].name();
+            let before = self.nodes[..index].iter().filter(|node| node.name() == name).count();
+            let repeated = self.children_named(name).nth(1).is_some();
+            self.allow(&Mutation::Remove(&path::node_path("", name, before, repeated)))?;
+        }
+        Ok(detach(&mut self.nodes, index))
+    }
+
+    /// Add a subtree to the end of the document, keeping its own name.
+    ///
+    /// Returns a mutable reference to the adopted node.
+    ///
+    /// # Panics
+    /// Panics if a mutation hook rejects the node.  See `try_adopt`.
+    pub fn adopt(&mut self, node: Node) -> &mut Node {
+        allowed(self.try_adopt(node))
+    }
+
+    /// Add a subtree to the end of the document, as `adopt` does.
+    ///
+    /// # Failures
+    /// Fails if a mutation hook rejects the node.
+    pub fn try_adopt(&mut self, node: Node) -> Result<&mut Node, HookError> {
+        if !self.hooks.is_empty() {
+            self.allow(&Mutation::SetNode(&self.appended_path(node.name()), &node))?;
+        }
+        self.nodes.push(node);
+        Ok(self.nodes.last_mut().unwrap())
+    }
+
+    /// Add a node to the end of the document, even if there is already a node with the
+    /// same name.
+    ///
+    /// Returns a mutable reference to the added node.
+    ///
+    /// # Panics
+    /// Panics if a mutation hook rejects the node.  See `try_append_node`.
+    pub fn append_node<S>(&mut self, name: S, node: Node) -> &mut Node where S: Into<Symbol> {
+        allowed(self.try_append_node(name, node))
+    }
+
+    /// Add a node to the end of the document, as `append_node` does.
+    ///
+    /// # Failures
+    /// Fails if a mutation hook rejects the node.
+    pub fn try_append_node<S>(&mut self, name: S, node: Node) -> Result<&mut Node, HookError>
+        where S: Into<Symbol> {
+
+        let name = self.interner.intern_symbol(name.into());
+        if !self.hooks.is_empty() {
+            self.allow(&Mutation::SetNode(&self.appended_path(&name), &node))?;
+        }
+        Ok(append_node(&mut self.nodes, name, node))
+    }
+
+    /// The path that a node called `name` has when it's added to the end of the document
+    fn appended_path(&self, name: &str) -> String {
+        let count = self.children_named(name).count();
+        path::node_path("", name, count, count > 0)
     }
 
     /// Get a reference to a specified node
@@ -394,8 +1983,8 @@ impl Document {
     /// Essentially a thin wrapper around the `Document.nodes` mapping, but it allows for
     /// &str arguments, and allows users to do common operations without having to know
     /// about the internal structure of the node.
-    pub fn get_node<S>(&self, name: S) -> Option<&Node> where S: Into<String> {
-        self.nodes.get(&name.into())
+    pub fn get_node<S>(&self, name: S) -> Option<&Node> where S: AsRef<str> {
+        self.child(name.as_ref())
     }
 
     /// Get a mutable reference to a specified node
@@ -403,23 +1992,100 @@ impl Document {
     /// Essentially a thin wrapper around the `Document.nodes` mapping, but it allows for
     /// &str arguments, and allows users to do common operations without having to know
     /// about the internal structure of the node.
-    pub fn get_node_mut<S>(&mut self, name: S) -> Option<&mut Node> where S: Into<String> {
-        self.nodes.get_mut(&name.into())
+    pub fn get_node_mut<S>(&mut self, name: S) -> Option<&mut Node> where S: AsRef<str> {
+        let name = name.as_ref();
+        self.nodes.iter_mut().find(|node| *node.name == *name)
     }
 
     /// Get an iterable of (&name, &node) pairs
-    pub fn iter_nodes(&self) -> Iter<String, Node> {
+    pub fn iter_nodes(&self) -> Subnodes<'_> {
+        Subnodes { nodes: self.nodes.iter() }
+    }
+
+    /// Get an iterable of the nodes in the document
+    pub fn children(&self) -> slice::Iter<'_, Node> {
         self.nodes.iter()
     }
 
+    /// Get an iterable of mutable references to the nodes in the document
+    pub fn children_mut(&mut self) -> slice::IterMut<'_, Node> {
+        self.nodes.iter_mut()
+    }
+
+    /// Get an iterable of all of the nodes with the given name, in order
+    pub fn children_named<'a, 'b>(&'a self, name: &'b str) -> ChildrenNamed<'a, 'b> {
+        ChildrenNamed { nodes: self.nodes.iter(), name }
+    }
+
+    /// Get a reference to the node with the given name
+    pub fn child(&self, name: &str) -> Option<&Node> {
+        self.nodes.iter().find(|node| node.name == name)
+    }
+
+    /// Sort the nodes of the document by their names.  See `Node::sort_children_by`.
+    ///
+    /// # Examples
+    /// Sorting every node and attribute in a document:
+    ///
+    /// ```
+    /// # use figtree::types::*;
+    /// # use std::io::Cursor;
+    /// fn tidy(node: &mut Node) {
+    ///     node.sort_properties();
+    ///     node.sort_children();
+    ///     node.children_mut().for_each(tidy);
+    /// }
+    ///
+    /// let mut doc = Document::parse(Cursor::new("b { y = 1, x = 2 } a { d {} c {} }")).unwrap();
+    /// doc.sort_children();
+    /// doc.children_mut().for_each(tidy);
+    /// assert_eq!(doc.to_string(), "a {\n    c {}\n    d {}\n}\nb {\n    x: 2,\n    y: 1,\n}\n");
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if a mutation hook rejects the sort.  See `try_sort_children`.
+    pub fn sort_children(&mut self) {
+        allowed(self.try_sort_children())
+    }
+
+    /// Sort the nodes of the document by their names, as `sort_children` does.
+    ///
+    /// # Failures
+    /// Fails if a mutation hook rejects the sort.
+    pub fn try_sort_children(&mut self) -> Result<(), HookError> {
+        self.try_sort_children_by(|a, b| a.name.cmp(&b.name))
+    }
+
+    /// Sort the nodes of the document with a comparison function.  See
+    /// `Node::sort_children_by`.
+    ///
+    /// # Panics
+    /// Panics if a mutation hook rejects the sort.  See `try_sort_children_by`.
+    pub fn sort_children_by<F>(&mut self, compare: F) where F: FnMut(&Node, &Node) -> Ordering {
+        allowed(self.try_sort_children_by(compare))
+    }
+
+    /// Sort the nodes of the document with a comparison function, as `sort_children_by`
+    /// does.
+    ///
+    /// # Failures
+    /// Fails if a mutation hook rejects the sort.
+    pub fn try_sort_children_by<F>(&mut self, compare: F) -> Result<(), HookError>
+        where F: FnMut(&Node, &Node) -> Ordering {
+
+        self.allow(&Mutation::Reorder(""))?;
+        self.nodes.sort_by(compare);
+        Ok(())
+    }
+
     /// Test if the document is empty - if it has no nodes.
     pub fn is_empty(&self) -> bool {
         self.nodes.is_empty()
     }
 
     /// Test if the document has a given node.
-    pub fn has_node(&self, name: &String) -> bool {
-        self.nodes.contains_key(name)
+    pub fn has_node(&self, name: &str) -> bool {
+        self.child(name).is_some()
     }
 
     /// Test if the document has any nodes.
@@ -433,9 +2099,91 @@ impl Document {
     }
 }
 
+impl Default for Document {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Document {
+    /// Test if two documents mean the same thing, however they were formatted.
+    ///
+    /// This is the same as `==`, which already ignores whitespace, comments, separators,
+    /// and the order of attributes and of differently-named nodes - except that NaN
+    /// floats are equal to each other, so a document with a `nan` in it is structurally
+    /// equal to itself.
+    ///
+    /// # Examples
+    /// ```
+    /// # use figtree::types::*;
+    /// # use std::io::Cursor;
+    /// let first = Document::parse(Cursor::new("a { x = nan, y = [1, 2] }")).unwrap();
+    /// let second = Document::parse(Cursor::new("// the same
+    ///     a {
+    ///         y = [1, 2,],
+    ///         x = nan,
+    ///     }")).unwrap();
+    /// assert!(first.structurally_eq(&second));
+    /// assert!(first != second);
+    /// ```
+    pub fn structurally_eq(&self, other: &Document) -> bool {
+        same_nodes_by(&self.nodes, &other.nodes, Node::structurally_eq)
+    }
+}
+
+impl PartialEq for Document {
+    fn eq(&self, other: &Document) -> bool {
+        same_nodes(&self.nodes, &other.nodes)
+    }
+}
+
+/// Look up a node in the document.
+///
+/// # Panics
+/// Panics if the document doesn't have the node.
+impl<'a> Index<&'a str> for Document {
+    type Output = Node;
+
+    fn index(&self, name: &'a str) -> &Node {
+        match self.child(name) {
+            Some(node) => node,
+            None => panic!("no node {:?} in document", name),
+        }
+    }
+}
+
+/// Look up a node in the document by its position.
+///
+/// # Panics
+/// Panics if the document has no node at that position.
+impl Index<usize> for Document {
+    type Output = Node;
+
+    fn index(&self, index: usize) -> &Node {
+        doc_node(self, index)
+    }
+}
+
+/// The result of a change that a mutation hook allowed, for the methods that panic if
+/// the change was rejected
+fn allowed<T>(result: Result<T, HookError>) -> T {
+    result.unwrap_or_else(|err| panic!("{}", err))
+}
+
+fn doc_node(doc: &Document, index: usize) -> &Node {
+    match doc.nodes.get(index) {
+        Some(node) => node,
+        None => panic!("index {} out of range for document with {} nodes",
+                       index, doc.nodes.len()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use super::super::position::Position;
+    use std::io::Cursor;
+    use std::panic::{self, AssertUnwindSafe};
 
     #[test]
     fn type_creations() {
@@ -505,7 +2253,7 @@ mod tests {
         assert_eq!(iterable.len(), 1);
         assert_eq!(
             iterable.next(),
-            Some((&("secondary_subnode".to_string()), &Node::new())));
+            Some((&Symbol::from("secondary_subnode"), &Node::new())));
         assert_eq!(iterable.next(), None);
     }
 
@@ -543,7 +2291,7 @@ mod tests {
         assert_eq!(iterable.len(), 1);
         assert_eq!(
             iterable.next(),
-            Some((&("key".to_string()), &Value::new_int(7))));
+            Some((&Symbol::from("key"), &Value::new_int(7))));
         assert_eq!(iterable.next(), None);
     }
 
@@ -563,7 +2311,343 @@ mod tests {
         assert_eq!(iterable.len(), 1);
         assert_eq!(
             iterable.next(),
-            Some((&("subnode".to_string()), &Node::new())));
+            Some((&Symbol::from("subnode"), &Node::new())));
         assert_eq!(iterable.next(), None);
     }
+
+    #[test]
+    fn node_accessors() {
+        let mut doc = Document::new();
+        {
+            let node = doc.new_node_or_get("server");
+            node.insert_attr("port", Value::new_int(80));
+            node.insert_attr("host", Value::new_string("localhost"));
+            node.new_node_or_get("tls");
+        }
+        let mut replaced = Node::new();
+        replaced.insert_attr("level", Value::new_string("debug"));
+        doc.insert_node("logging", replaced);
+
+        let server = doc.child("server").unwrap();
+        assert_eq!(server.name(), "server");
+        assert_eq!(server.len(), 3);
+        assert_eq!(server.get("port"), Some(&Value::new_int(80)));
+        assert!(server.has("host"));
+        assert!(!server.has("tls"));
+        assert_eq!(server.child("tls").map(Node::name), Some("tls"));
+        assert_eq!(server.children().map(Node::name).collect::<Vec<_>>(), vec!["tls"]);
+        assert_eq!(doc.child("logging").unwrap().name(), "logging");
+        assert!(doc.child("missing").is_none());
+
+        let mut names: Vec<_> = doc.children().map(Node::name).collect();
+        names.sort();
+        assert_eq!(names, vec!["logging", "server"]);
+
+        // names aren't compared
+        assert_eq!(server.child("tls"), Some(&Node::new()));
+    }
+
+    #[test]
+    fn value_kinds() {
+        let values = vec![
+            Value::from("s"), Value::from(1), Value::from(1.5), Value::from(false),
+            Value::new_ident("i"), Value::from(Duration::from_secs(1)), Value::new_bytes(vec![0]),
+            Value::from(Dict::new()), Value::from(vec![Value::new_null()]), Value::new_null(),
+            Value::from(Node::new()),
+        ];
+        let kinds: Vec<_> = values.iter().map(|value| (
+            value.type_name(),
+            [value.is_str(), value.is_int(), value.is_float(), value.is_bool(), value.is_ident(),
+             value.is_datetime(), value.is_duration(), value.is_bytes(), value.is_dict(),
+             value.is_list(), value.is_null(), value.is_node()].iter().filter(|&&is| is).count(),
+        )).collect();
+        assert_eq!(kinds, vec![
+            ("string", 1), ("integer", 1), ("float", 1), ("boolean", 1), ("identifier", 1),
+            ("duration", 1), ("bytes", 1), ("dict", 1), ("list", 1), ("null", 1), ("node", 1),
+        ]);
+
+        let mut list = Value::from(vec![Value::from(1)]);
+        list.get_list_mut().unwrap().push(Value::from(2i64));
+        assert_eq!(list, Value::List(vec![Value::new_int(1), Value::new_int(2)]));
+        assert!(Value::from(String::from("x")).get_dict_mut().is_none());
+    }
+
+    #[test]
+    fn node_values() {
+        let mut node = Node::named("server");
+        node.push_argument(Value::from(1));
+        node.insert_attr("host", Value::from("a"));
+        node.append_node("upstream", Node::new());
+        node.append_node("upstream", Node::new());
+        node.append_node("tls", Node::new());
+
+        let mut value = Value::new_node(node.clone());
+        assert_eq!(value.get_node(), Some(&node));
+        assert!(Value::from(1).get_node().is_none());
+        value.get_node_mut().unwrap().insert_attr("port", Value::from(80));
+        assert_eq!(value.get_node().unwrap().get("port"), Some(&Value::new_int(80)));
+        assert!(value.structurally_eq(&value.clone()));
+
+        let dict = node.to_dict();
+        assert_eq!(dict.len(), 4);
+        assert_eq!(dict["$arguments"], Value::from(vec![Value::from(1)]));
+        assert_eq!(dict["host"], Value::from("a"));
+        assert_eq!(dict["upstream"], Value::from(vec![Value::from(Dict::new()), Value::from(Dict::new())]));
+        assert_eq!(dict["tls"], Value::from(Dict::new()));
+    }
+
+    #[test]
+    fn indexing() {
+        let mut doc = Document::new();
+        doc.new_node_or_get("server").insert_attr("ports", Value::from(vec![Value::from(80)]));
+        assert_eq!(doc["server"]["ports"][0], Value::new_int(80));
+
+        let mut dict = Dict::new();
+        dict.insert("key".to_string(), Value::from("value"));
+        assert_eq!(Value::from(dict)["key"], Value::new_string("value"));
+    }
+
+    #[test]
+    #[should_panic(expected = "no attribute \"port\" in node \"server\"")]
+    fn indexing_missing_attribute() {
+        let mut doc = Document::new();
+        doc.new_node_or_get("server");
+        let _ = &doc["server"]["port"];
+    }
+
+    #[test]
+    fn indexing_subnodes() {
+        let doc = Document::parse(Cursor::new("server {
+            tls { cert = 'a.pem', opts = { min = 1.2 } }
+            upstream { hosts = ['a'] }
+            upstream = 1
+        }")).unwrap();
+        let doc = Indexed::from(&doc);
+        assert_eq!(doc["server"]["tls"]["cert"], Value::new_string("a.pem"));
+        assert_eq!(doc["server"]["tls"]["opts"]["min"], Value::new_float(1.2));
+        assert_eq!(doc["server"]["upstream"]["hosts"][0].as_value().and_then(Value::get_str),
+                   Some("a"));
+        assert_eq!(doc["server"][0]["cert"].to_string(), "\"a.pem\"");
+        assert_eq!(doc["server"]["tls"].as_node(), doc["server"].as_node().unwrap().child("tls"));
+        assert_eq!(doc["server"]["tls"]["cert"].as_node(), None);
+        assert_eq!(doc[0]["tls"]["opts"]["min"].path(), "server.tls.opts.min");
+        assert_eq!(doc["server"]["upstream"]["hosts"][0].path(), "server.upstream.hosts[0]");
+
+        let value = Value::new_node(doc["server"].as_node().unwrap().clone());
+        assert_eq!(Indexed::from(&value)["tls"]["cert"], Value::new_string("a.pem"));
+    }
+
+    #[test]
+    fn indexing_interleaved() {
+        let doc = Document::parse(Cursor::new("
+            server { tls { cert = 'a.pem' } }
+            client { tls { cert = 'b.pem' } }
+        ")).unwrap();
+        let doc = Indexed::from(&doc);
+        let server = &doc["server"]["tls"];
+        let client = &doc["client"]["tls"];
+        assert_eq!(server["cert"].path(), "server.tls.cert");
+        assert_eq!(client["cert"].path(), "client.tls.cert");
+        assert_eq!(server["cert"], Value::new_string("a.pem"));
+
+        let missing = |entry: &Indexed| {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| entry["key"].path().len()));
+            *result.unwrap_err().downcast::<String>().unwrap()
+        };
+        assert_eq!(missing(server), "no attribute or subnode \"key\" in \"server.tls\"");
+        assert_eq!(missing(client), "no attribute or subnode \"key\" in \"client.tls\"");
+    }
+
+    #[test]
+    #[should_panic(expected = "no attribute or subnode \"key\" in \"server.tls\"")]
+    fn indexing_missing_nested() {
+        let doc = Document::parse(Cursor::new("server { tls { cert = 'a.pem' } }")).unwrap();
+        let _ = &Indexed::from(&doc)["server"]["tls"]["key"];
+    }
+
+    #[test]
+    #[should_panic(expected = "no key \"max\" in dict at \"server.tls.opts\"")]
+    fn indexing_missing_dict_key() {
+        let doc = Document::parse(Cursor::new("server { tls { opts = { min = 1 } } }")).unwrap();
+        let _ = &Indexed::from(&doc)["server"]["tls"]["opts"]["max"];
+    }
+
+    #[test]
+    #[should_panic(expected = "index 1 out of range for list of length 1")]
+    fn indexing_out_of_range() {
+        let _ = &Value::from(vec![Value::from(1)])[1];
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot look up key \"a\" in integer value")]
+    fn indexing_wrong_type() {
+        let _ = &Value::from(1)["a"];
+    }
+
+    #[test]
+    fn typed_getters() {
+        let mut node = Node::new();
+        node.insert_attr("host", Value::from("localhost"));
+        node.insert_attr("port", Value::from(80));
+        node.insert_attr("debug", Value::from(true));
+        node.insert_attr("ratio", Value::from(0.5));
+        node.insert_attr("mode", Value::new_ident("fast"));
+        node.insert_attr("tags", Value::from(vec![Value::from("a")]));
+        node.insert_attr("env", Value::from(Dict::new()));
+        let mut doc = Document::new();
+        doc.insert_node("server", node);
+        let node = &doc["server"];
+
+        assert_eq!(node.get_str("host"), Ok("localhost"));
+        assert_eq!(node.get_i64("port"), Ok(80));
+        assert_eq!(node.get_bool("debug"), Ok(true));
+        assert_eq!(node.get_f64("ratio"), Ok(0.5));
+        assert_eq!(node.get_f64("port"), Ok(80.0));
+        assert_eq!(node.get_identifier("mode"), Ok("fast"));
+        assert_eq!(node.get_list("tags").map(<[Value]>::len), Ok(1));
+        assert!(node.get_dict("env").unwrap().is_empty());
+
+        let error = node.get_bool("port").unwrap_err();
+        assert_eq!(error, AttrError {
+            node: "server".to_string(),
+            key: "port".to_string(),
+            expected: "boolean",
+            found: Some("integer"),
+            span: None,
+            origin: None,
+        });
+        assert_eq!(error.to_string(), "expected \"port\" in node \"server\" to be a boolean, found an integer");
+
+        let error = AttrError { span: Some(Span::new(Position::at(2, 4), Position::at(2, 6))), ..error };
+        assert_eq!(error.to_string(),
+            "expected \"port\" in node \"server\" to be a boolean, found an integer (at line 3, column 5)");
+        assert_eq!(node.get_i64("missing").unwrap_err().to_string(),
+            "expected an integer \"missing\" in node \"server\", but it is missing");
+
+        let error = AttrError { origin: Some(Arc::new(Origin::Url("http://a/b.fig".into()))), ..error };
+        assert_eq!(error.to_string(), "expected \"port\" in node \"server\" to be a boolean, \
+            found an integer (in http://a/b.fig, at line 3, column 5)");
+        let error = AttrError { span: None, ..error };
+        assert!(error.to_string().ends_with("found an integer (in http://a/b.fig)"));
+    }
+
+    #[test]
+    fn document_order() {
+        let mut node = Node::new();
+        for key in &["z", "a", "m"] {
+            node.insert_attr(*key, Value::new_string(*key));
+        }
+        assert_eq!(node.insert_attr("a", Value::new_int(1)), Some(Value::new_string("a")));
+        let keys: Vec<_> = node.properties().map(|(key, _)| key.as_str()).collect();
+        assert_eq!(keys, vec!["z", "a", "m"]);
+        assert_eq!(node.delete_attr("z"), Some(Value::new_string("z")));
+        assert_eq!(node.properties().next(), Some((&Symbol::from("a"), &Value::new_int(1))));
+
+        node.append_node("up", Node::new()).insert_attr("n", Value::new_int(1));
+        node.new_node_or_get("other");
+        node.append_node("up", Node::new()).insert_attr("n", Value::new_int(2));
+        let ns: Vec<_> = node.children_named("up").map(|up| up.get_i64("n").unwrap()).collect();
+        assert_eq!(ns, vec![1, 2]);
+        assert_eq!(node.get_i64("n").ok(), None);
+        assert_eq!(node.child("up").unwrap().get_i64("n"), Ok(1));
+        assert_eq!(node[1].name(), "other");
+        assert_eq!(node.delete_node("up").unwrap().get_i64("n"), Ok(1));
+        assert_eq!(node.child("up").unwrap().get_i64("n"), Ok(2));
+
+        // attributes and differently-named nodes can be in any order, but nodes with the
+        // same name can't
+        let mut first = Node::new();
+        first.insert_attr("a", Value::new_int(1));
+        first.insert_attr("b", Value::new_int(2));
+        first.new_node_or_get("x");
+        first.append_node("y", Node::new()).insert_attr("n", Value::new_int(1));
+        first.append_node("y", Node::new());
+        let mut second = Node::new();
+        second.append_node("y", Node::new()).insert_attr("n", Value::new_int(1));
+        second.insert_attr("b", Value::new_int(2));
+        second.new_node_or_get("x");
+        second.insert_attr("a", Value::new_int(1));
+        second.append_node("y", Node::new());
+        assert_eq!(first, second);
+        second.delete_node("y");
+        second.append_node("y", Node::new()).insert_attr("n", Value::new_int(1));
+        assert!(first != second);
+    }
+
+    #[test]
+    fn sorting() {
+        let options = ::ParserOptions::new().repeated_nodes(true);
+        let input = "b { z = 1, y = 2, x = 1 } a { up { n = 1 } c {} up { n = 2 } }";
+        let mut doc = Figtree::from_string(input).with_options(options).parse().unwrap();
+        let names = |nodes: slice::Iter<Node>|
+            nodes.map(|node| node.name.clone()).collect::<Vec<_>>();
+        let keys = |node: &Node| node.properties().map(|(key, _)| key.clone()).collect::<Vec<_>>();
+
+        doc.sort_children();
+        assert_eq!(names(doc.children()), vec!["a", "b"]);
+        let a = doc.get_node_mut("a").unwrap();
+        a.sort_children();
+        assert_eq!(names(a.children()), vec!["c", "up", "up"]);
+        assert_eq!(a[1].get_i64("n"), Ok(1));
+        a.sort_children_by(|x, y| y.get_i64("n").ok().cmp(&x.get_i64("n").ok()));
+        assert_eq!(a[0].get_i64("n"), Ok(2));
+        assert_eq!(a[2].name(), "c");
+
+        let b = doc.get_node_mut("b").unwrap();
+        b.sort_properties_by(|(_, x), (_, y)| x.get_int().cmp(&y.get_int()));
+        assert_eq!(keys(b), vec!["z", "x", "y"]);
+        b.sort_properties();
+        assert_eq!(keys(b), vec!["x", "y", "z"]);
+        // lookups still find the right values once they have moved
+        assert_eq!(b.get_i64("z"), Ok(1));
+        assert_eq!(b.get_i64("y"), Ok(2));
+        doc.sort_children_by(|x, y| y.name().cmp(x.name()));
+        assert_eq!(names(doc.children()), vec!["b", "a"]);
+    }
+
+    #[test]
+    fn detach_and_adopt() {
+        let mut doc = Document::builder()
+            .node(Node::builder("a").child(Node::builder("x").prop("n", 1)).child(Node::builder("y")))
+            .node(Node::builder("b"))
+            .build();
+
+        let x = doc.get_node_mut("a").unwrap().detach(0).unwrap();
+        assert_eq!(x.name(), "x");
+        assert_eq!(x.get_i64("n"), Ok(1));
+        assert!(doc.get_node_mut("a").unwrap().detach(1).is_none());
+        doc.get_node_mut("b").unwrap().adopt(x).insert_attr("moved", Value::new_bool(true));
+        assert_eq!(doc["a"].children().map(Node::name).collect::<Vec<_>>(), vec!["y"]);
+        assert_eq!(doc["b"].child("x").unwrap().get_bool("moved"), Ok(true));
+
+        let b = doc.detach(1).unwrap();
+        assert!(doc.detach(1).is_none());
+        doc.adopt(b).delete_node("x");
+        assert_eq!(doc.children().map(Node::name).collect::<Vec<_>>(), vec!["a", "b"]);
+        assert!(doc["b"].is_empty());
+    }
+
+    #[test]
+    fn structural_equality() {
+        use super::super::{Figtree, ParserOptions};
+        let parse = |input: String| {
+            let options = ParserOptions::new().repeated_nodes(true);
+            Figtree::from_string(input).with_options(options).parse().unwrap()
+        };
+        let around_b = |first: &str, second: &str| parse(format!("{} b {{}} {}", first, second));
+        let first = "a { x = nan, l = [1, nan], d = { k: nan } }";
+        let doc = around_b(first, "a { y = 1 }");
+        let same = parse("/* reformatted */ b { }\n\
+            a {\n    d: {'k': nan,},\n    l = [1, nan,],\n    x: nan,\n}\n\
+            a { // second\n    y: 1\n}".to_string());
+        assert!(doc.structurally_eq(&same));
+        assert!(doc.structurally_eq(&doc));
+        assert!(doc != doc);
+
+        let reordered = "a { x = nan, l = [nan, 1], d = { k: nan } }";
+        assert!(!doc.structurally_eq(&around_b(reordered, "a { y = 1 }")));
+        assert!(!doc.structurally_eq(&around_b("a { y = 1 }", first)));
+        assert!(!doc.structurally_eq(&around_b(first, "")));
+        assert!(!around_b("a { x = 1.0 }", "").structurally_eq(&around_b("a { x = 1 }", "")));
+    }
 }
@@ -1,65 +1,262 @@
 use std::io::prelude::*;
+use std::io;
 use std::collections::VecDeque;
+use lexer::LexError;
 
+#[cfg(feature = "encoding")]
+use decoding::DecodeError;
+
+#[cfg(feature = "unicode-xid")]
+use unicode_xid::UnicodeXID;
+
+/// Test if a character can start an identifier, using the Unicode `XID_Start` property.
+#[cfg(feature = "unicode-xid")]
+pub fn ident_head(c: char) -> bool {
+    c == '_' || UnicodeXID::is_xid_start(c)
+}
+
+/// Test if a character can continue an identifier, using the Unicode `XID_Continue`
+/// property.
+#[cfg(feature = "unicode-xid")]
+pub fn ident_body(c: char) -> bool {
+    UnicodeXID::is_xid_continue(c)
+}
+
+#[cfg(not(feature = "unicode-xid"))]
 pub fn ident_head(c: char) -> bool {
     // TODO: This is ugly.  This should be done *waaaaay* better.
-    match c {
-        'a'...'z' => true,
-        'A'...'Z' => true,
-        '_' => true,
-        '\u{00A8}' | '\u{00AA}' | '\u{00AD}' | '\u{00AF}' => true,
-        '\u{00B2}'...'\u{00B5}' | '\u{00B7}'...'\u{00BA}' => true,
-        '\u{00BC}'...'\u{00BE}' | '\u{00C0}'...'\u{00D6}' => true,
-        '\u{00D8}'...'\u{00F6}' | '\u{00F8}'...'\u{00FF}' => true,
-        '\u{0100}'...'\u{02FF}' | '\u{0370}'...'\u{167F}' => true,
-        '\u{1681}'...'\u{180D}' | '\u{180F}'...'\u{1DBF}' => true,
-        '\u{1E00}'...'\u{1FFF}' | '\u{200B}'...'\u{200D}' => true,
-        '\u{202A}'...'\u{202E}' | '\u{203F}'...'\u{2040}' => true,
-        '\u{2054}' | '\u{2060}'...'\u{206F}' => true,
-        '\u{2070}'...'\u{20CF}' | '\u{2100}'...'\u{218F}' => true,
-        '\u{2460}'...'\u{24FF}' | '\u{2776}'...'\u{2793}' => true,
-        '\u{2C00}'...'\u{2DFF}' | '\u{2E80}'...'\u{2FFF}' => true,
-        '\u{3004}'...'\u{3007}' | '\u{3021}'...'\u{302F}' => true,
-        '\u{3031}'...'\u{303F}' | '\u{3040}'...'\u{D7FF}' => true,
-        '\u{F900}'...'\u{FD3D}' | '\u{FD40}'...'\u{FDCF}' => true,
-        '\u{FDF0}'...'\u{FE1F}' | '\u{FE30}'...'\u{FE45}' => true,
-        '\u{FE47}'...'\u{FFFD}' => true,
-        '\u{10000}'...'\u{1FFFD}' | '\u{20000}'...'\u{2FFFD}' => true,
-        '\u{30000}'...'\u{3FFFD}' | '\u{40000}'...'\u{4FFFD}' => true,
-        '\u{50000}'...'\u{5FFFD}' | '\u{60000}'...'\u{6FFFD}' => true,
-        '\u{70000}'...'\u{7FFFD}' | '\u{80000}'...'\u{8FFFD}' => true,
-        '\u{90000}'...'\u{9FFFD}' | '\u{A0000}'...'\u{AFFFD}' => true,
-        '\u{B0000}'...'\u{BFFFD}' | '\u{C0000}'...'\u{CFFFD}' => true,
-        '\u{D0000}'...'\u{DFFFD}' | '\u{E0000}'...'\u{EFFFD}' => true,
-        _ => false
-    }
+    matches!(c,
+        'a'..='z' |
+        'A'..='Z' |
+        '_' |
+        '\u{00A8}' | '\u{00AA}' | '\u{00AD}' | '\u{00AF}' |
+        '\u{00B2}'..='\u{00B5}' | '\u{00B7}'..='\u{00BA}' |
+        '\u{00BC}'..='\u{00BE}' | '\u{00C0}'..='\u{00D6}' |
+        '\u{00D8}'..='\u{00F6}' | '\u{00F8}'..='\u{00FF}' |
+        '\u{0100}'..='\u{02FF}' | '\u{0370}'..='\u{167F}' |
+        '\u{1681}'..='\u{180D}' | '\u{180F}'..='\u{1DBF}' |
+        '\u{1E00}'..='\u{1FFF}' | '\u{200B}'..='\u{200D}' |
+        '\u{202A}'..='\u{202E}' | '\u{203F}'..='\u{2040}' |
+        '\u{2054}' | '\u{2060}'..='\u{206F}' |
+        '\u{2070}'..='\u{20CF}' | '\u{2100}'..='\u{218F}' |
+        '\u{2460}'..='\u{24FF}' | '\u{2776}'..='\u{2793}' |
+        '\u{2C00}'..='\u{2DFF}' | '\u{2E80}'..='\u{2FFF}' |
+        '\u{3004}'..='\u{3007}' | '\u{3021}'..='\u{302F}' |
+        '\u{3031}'..='\u{303F}' | '\u{3040}'..='\u{D7FF}' |
+        '\u{F900}'..='\u{FD3D}' | '\u{FD40}'..='\u{FDCF}' |
+        '\u{FDF0}'..='\u{FE1F}' | '\u{FE30}'..='\u{FE45}' |
+        '\u{FE47}'..='\u{FFFD}' |
+        '\u{10000}'..='\u{1FFFD}' | '\u{20000}'..='\u{2FFFD}' |
+        '\u{30000}'..='\u{3FFFD}' | '\u{40000}'..='\u{4FFFD}' |
+        '\u{50000}'..='\u{5FFFD}' | '\u{60000}'..='\u{6FFFD}' |
+        '\u{70000}'..='\u{7FFFD}' | '\u{80000}'..='\u{8FFFD}' |
+        '\u{90000}'..='\u{9FFFD}' | '\u{A0000}'..='\u{AFFFD}' |
+        '\u{B0000}'..='\u{BFFFD}' | '\u{C0000}'..='\u{CFFFD}' |
+        '\u{D0000}'..='\u{DFFFD}' | '\u{E0000}'..='\u{EFFFD}')
 }
 
+#[cfg(not(feature = "unicode-xid"))]
 pub fn ident_body(c: char) -> bool {
     if !ident_head(c) {
-        match c {
-            '0'...'9' => true,
-            '\u{0300}'...'\u{036F}' | '\u{1DC0}'...'\u{1DFF}' => true,
-            '\u{20D0}'...'\u{20FF}' | '\u{FE20}'...'\u{FE2F}' => true,
-            _ => false
-        }
+        matches!(c,
+            '0'..='9' |
+            '\u{0300}'..='\u{036F}' | '\u{1DC0}'..='\u{1DFF}' |
+            '\u{20D0}'..='\u{20FF}' | '\u{FE20}'..='\u{FE2F}')
     } else {
         true
     }
 }
 
+/// Incrementally decodes standard base64 (`A-Z`, `a-z`, `0-9`, `+`, `/`, with optional
+/// `=` padding), so that the position of an invalid character can be reported.
+pub struct Base64Decoder {
+    bytes: Vec<u8>,
+    buffer: u32,
+    bits: u32,
+    sextets: usize,
+    padding: usize,
+}
+
+impl Base64Decoder {
+    pub fn new() -> Self {
+        Base64Decoder { bytes: Vec::new(), buffer: 0, bits: 0, sextets: 0, padding: 0 }
+    }
+
+    /// Add a character to the decoder.  Returns `false` if the character is not valid
+    /// at this point in the input.
+    pub fn push(&mut self, c: char) -> bool {
+        let value = match c {
+            'A'..='Z' => c as u32 - 'A' as u32,
+            'a'..='z' => c as u32 - 'a' as u32 + 26,
+            '0'..='9' => c as u32 - '0' as u32 + 52,
+            '+' => 62,
+            '/' => 63,
+            '=' => {
+                // padding can only fill out the last group of four characters
+                self.padding += 1;
+                return self.padding <= 2 && self.sextets % 4 >= 2 &&
+                    (self.sextets + self.padding) % 4 != 1;
+            },
+            _ => return false,
+        };
+
+        if self.padding > 0 {
+            return false;
+        }
+
+        self.buffer = (self.buffer << 6) | value;
+        self.bits += 6;
+        self.sextets += 1;
+        if self.bits >= 8 {
+            self.bits -= 8;
+            self.bytes.push((self.buffer >> self.bits) as u8);
+            self.buffer &= (1 << self.bits) - 1;
+        }
+        true
+    }
+
+    /// Finish decoding, returning `None` if the input was truncated.
+    pub fn finish(self) -> Option<Vec<u8>> {
+        let complete = if self.padding > 0 {
+            (self.sextets + self.padding).is_multiple_of(4)
+        } else {
+            self.sextets % 4 != 1
+        };
+
+        if complete { Some(self.bytes) } else { None }
+    }
+}
+
+/// Encode bytes as standard base64, with `=` padding
+#[cfg(feature = "yaml")]
+pub fn encode_base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let buffer = chunk.iter().enumerate()
+            .fold(0, |buffer, (index, &byte)| buffer | u32::from(byte) << (16 - 8 * index));
+        for index in 0..4 {
+            if index <= chunk.len() {
+                encoded.push(ALPHABET[(buffer >> (18 - 6 * index) & 63) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Reads characters from UTF-8 input, one line at a time.
+///
+/// A UTF-8 byte order mark at the start of the input is skipped.  If the input can't be
+/// read - because it has a UTF-16 or UTF-32 byte order mark, isn't valid UTF-8, or
+/// because of an IO error - iteration ends, and the error can be retrieved using
+/// `take_error`.
 pub struct CharReader<R: BufRead> {
     reader: R,
     buffer: VecDeque<char>,
+    started: bool,
+    failed: bool,
+    error: Option<LexError>,
+    /// the number of bytes read so far
+    bytes_read: usize,
+    limit: Option<usize>,
 }
 
 impl<R: BufRead> CharReader<R> {
     pub fn new(reader: R) -> Self {
         CharReader {
-            reader: reader,
+            reader,
             buffer: VecDeque::new(),
+            started: false,
+            failed: false,
+            error: None,
+            bytes_read: 0,
+            limit: None,
         }
     }
+
+    /// Set the maximum number of bytes that will be read from the input.  Once this has
+    /// been reached, iteration ends with a `DocumentTooLarge` error.
+    pub fn set_limit(&mut self, limit: Option<usize>) {
+        self.limit = limit;
+    }
+
+    /// Take the error that ended iteration, if there is one and it hasn't already been
+    /// taken
+    pub fn take_error(&mut self) -> Option<LexError> {
+        if self.buffer.is_empty() { self.error.take() } else { None }
+    }
+
+    fn fail(&mut self, error: LexError) {
+        self.failed = true;
+        self.error = Some(error);
+    }
+
+    fn read_line(&mut self) -> bool {
+        let mut bytes = Vec::new();
+        // one byte more than the limit allows is read, to find out if it has been exceeded
+        let allowed = self.limit.map_or(u64::MAX, |limit| (limit - self.bytes_read) as u64 + 1);
+        // anything read before an error is still used, so that the error is reported at
+        // the right position
+        let error = match (&mut self.reader).take(allowed).read_until(b'\n', &mut bytes) {
+            Ok(0) => { return false; },
+            Ok(_) => None,
+            Err(err) => Some(input_error(err)),
+        };
+        if let Some(error) = error {
+            self.fail(error);
+        }
+        if bytes.len() as u64 == allowed {
+            bytes.pop();
+            self.fail(LexError::DocumentTooLarge);
+        }
+        self.bytes_read += bytes.len();
+
+        let mut line: &[u8] = &bytes;
+        if !self.started {
+            self.started = true;
+            // UTF-32 has to be tested first, as its little-endian BOM starts with the
+            // UTF-16 little-endian BOM
+            for &(bom, encoding) in &[
+                (&b"\x00\x00\xFE\xFF"[..], "UTF-32BE"), (&b"\xFF\xFE\x00\x00"[..], "UTF-32LE"),
+                (&b"\xFE\xFF"[..], "UTF-16BE"), (&b"\xFF\xFE"[..], "UTF-16LE"),
+            ] {
+                if line.starts_with(bom) {
+                    self.fail(LexError::UnsupportedEncoding(encoding));
+                    return true;
+                }
+            }
+            if line.starts_with(b"\xEF\xBB\xBF") {
+                line = &line[3..];
+            }
+        }
+
+        match ::std::str::from_utf8(line) {
+            Ok(text) => self.buffer.extend(text.chars()),
+            Err(err) => {
+                // the valid part of the line is still read, so that the error is
+                // reported at the position of the invalid bytes
+                let valid = ::std::str::from_utf8(&line[..err.valid_up_to()]).unwrap();
+                self.buffer.extend(valid.chars());
+                // a character cut off by another error isn't reported separately
+                if err.error_len().is_some() || !self.failed {
+                    self.fail(LexError::InvalidUtf8);
+                }
+            },
+        }
+        true
+    }
+}
+
+fn input_error(err: io::Error) -> LexError {
+    #[cfg(feature = "encoding")]
+    {
+        if let Some(err) = err.get_ref().and_then(|err| err.downcast_ref::<DecodeError>()) {
+            return LexError::DecodeError(err.0);
+        }
+    }
+    LexError::IoError(err.kind())
 }
 
 impl<R: BufRead> Iterator for CharReader<R> {
@@ -67,12 +264,9 @@ impl<R: BufRead> Iterator for CharReader<R> {
 
     fn next(&mut self) -> Option<Self::Item> {
         while self.buffer.is_empty() {
-            let mut str_buffer = String::new();
-            match self.reader.read_line(&mut str_buffer) {
-                Ok(n) => if n == 0 { return None; },
-                Err(_) => { return None; },
+            if self.failed || !self.read_line() {
+                return None;
             }
-            self.buffer = str_buffer.chars().collect();
         }
 
         self.buffer.pop_front()
@@ -81,9 +275,70 @@ impl<R: BufRead> Iterator for CharReader<R> {
 
 #[cfg(test)]
 mod tests {
-    use super::CharReader;
+    use super::{CharReader, Base64Decoder, ident_head, ident_body};
+    #[cfg(feature = "yaml")]
+    use super::encode_base64;
+    use lexer::LexError;
     use std::io::{Cursor, empty};
 
+    #[test]
+    fn identifier_characters() {
+        assert!(ident_head('a'));
+        assert!(ident_head('_'));
+        assert!(ident_head('ö'));
+        assert!(ident_head('ß'));
+        assert!(!ident_head('1'));
+        assert!(!ident_head('-'));
+        assert!(ident_body('1'));
+        assert!(ident_body('_'));
+        assert!(ident_body('\u{0301}')); // combining acute accent
+        assert!(!ident_body(' '));
+        assert!(!ident_body('{'));
+    }
+
+    #[test]
+    #[cfg(feature = "unicode-xid")]
+    fn xid_identifier_characters() {
+        assert!(ident_head('λ'));
+        assert!(!ident_head('\u{0301}'));
+        assert!(!ident_head('🐶'));
+        assert!(ident_body('\u{0301}'));
+        assert!(!ident_body('🐶'));
+    }
+
+    fn decode_base64(input: &str) -> Option<Vec<u8>> {
+        let mut decoder = Base64Decoder::new();
+        for c in input.chars() {
+            if !decoder.push(c) {
+                return None;
+            }
+        }
+        decoder.finish()
+    }
+
+    #[test]
+    fn base64_decoding() {
+        assert_eq!(decode_base64(""), Some(vec![]));
+        assert_eq!(decode_base64("aGVsbG8="), Some(b"hello".to_vec()));
+        assert_eq!(decode_base64("aGVsbG8"), Some(b"hello".to_vec()));
+        assert_eq!(decode_base64("aGVsbA=="), Some(b"hell".to_vec()));
+        assert_eq!(decode_base64("+/+/"), Some(vec![0xfb, 0xff, 0xbf]));
+        assert_eq!(decode_base64("aGVsbA="), None);
+        assert_eq!(decode_base64("aGVsb"), None);
+        assert_eq!(decode_base64("aG=Vs"), None);
+        assert_eq!(decode_base64("a==="), None);
+        assert_eq!(decode_base64("aGV-"), None);
+    }
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn base64_encoding() {
+        assert_eq!(encode_base64(b""), "");
+        assert_eq!(encode_base64(b"hello"), "aGVsbG8=");
+        assert_eq!(encode_base64(b"hell"), "aGVsbA==");
+        assert_eq!(encode_base64(&[0xfb, 0xff, 0xbf]), "+/+/");
+    }
+
     #[test]
     fn iteration() {
         let mut reader = CharReader::new(Cursor::new("text".as_bytes()));
@@ -94,6 +349,58 @@ mod tests {
         assert_eq!(reader.next(), None);
     }
 
+    #[test]
+    fn byte_order_marks() {
+        let mut reader = CharReader::new(Cursor::new(&b"\xEF\xBB\xBFa\xEF\xBB\xBF"[..]));
+        assert_eq!(reader.next(), Some('a'));
+        assert_eq!(reader.next(), Some('\u{FEFF}'));
+        assert_eq!(reader.next(), None);
+        assert_eq!(reader.take_error(), None);
+
+        for &(input, encoding) in &[
+            (&b"\xFF\xFEa\x00"[..], "UTF-16LE"), (&b"\xFE\xFF\x00a"[..], "UTF-16BE"),
+            (&b"\xFF\xFE\x00\x00a\x00\x00\x00"[..], "UTF-32LE"),
+            (&b"\x00\x00\xFE\xFF\x00\x00\x00a"[..], "UTF-32BE"),
+        ] {
+            let mut reader = CharReader::new(Cursor::new(input));
+            assert_eq!(reader.next(), None);
+            assert_eq!(reader.take_error(), Some(LexError::UnsupportedEncoding(encoding)));
+        }
+    }
+
+    #[test]
+    fn invalid_utf8() {
+        let mut reader = CharReader::new(Cursor::new(&b"a\nbc\xFFd\ne"[..]));
+        assert_eq!(reader.next(), Some('a'));
+        assert_eq!(reader.next(), Some('\n'));
+        assert_eq!(reader.next(), Some('b'));
+        assert_eq!(reader.take_error(), None);
+        assert_eq!(reader.next(), Some('c'));
+        assert_eq!(reader.next(), None);
+        assert_eq!(reader.take_error(), Some(LexError::InvalidUtf8));
+        assert_eq!(reader.next(), None);
+        assert_eq!(reader.take_error(), None);
+    }
+
+    #[test]
+    fn size_limit() {
+        let mut reader = CharReader::new(Cursor::new("ab\ncd".as_bytes()));
+        reader.set_limit(Some(4));
+        assert_eq!(reader.by_ref().collect::<String>(), "ab\nc");
+        assert_eq!(reader.take_error(), Some(LexError::DocumentTooLarge));
+
+        let mut reader = CharReader::new(Cursor::new("ab\ncd".as_bytes()));
+        reader.set_limit(Some(5));
+        assert_eq!(reader.by_ref().collect::<String>(), "ab\ncd");
+        assert_eq!(reader.take_error(), None);
+
+        // a character cut off by the limit isn't an encoding error
+        let mut reader = CharReader::new(Cursor::new("aé".as_bytes()));
+        reader.set_limit(Some(2));
+        assert_eq!(reader.by_ref().collect::<String>(), "a");
+        assert_eq!(reader.take_error(), Some(LexError::DocumentTooLarge));
+    }
+
     #[test]
     fn empty_iteration() {
         let mut reader = CharReader::new(empty());
@@ -0,0 +1,242 @@
+//! Traversal of documents with visitors.
+//!
+//! A `Visit` implementation looks at every node and value of a document, overriding just
+//! the methods for the parts it is interested in.  Each method's default implementation
+//! calls the function of the same name in this module, which visits everything inside
+//! that part of the document in order.  An overriding method can call that function
+//! itself to carry on into the contents, or not call it to skip them.
+//!
+//! The parts of a node are visited in order: first its arguments (with `visit_value`),
+//! then its attributes (with `visit_property`), then its subnodes (with `visit_node`).
+//! The items of list values and the entries of dict values are visited too, so entries
+//! of dicts are also passed to `visit_property`, in order of their keys.
+//!
+//! # Examples
+//! Finding every password, wherever it is:
+//!
+//! ```
+//! # use figtree::types::*;
+//! # use figtree::visit::{self, Visit};
+//! # use std::io::Cursor;
+//! struct Passwords<'a>(Vec<&'a str>);
+//!
+//! impl<'a> Visit<'a> for Passwords<'a> {
+//!     fn visit_property(&mut self, key: &'a str, value: &'a Value) {
+//!         if key == "password" {
+//!             self.0.extend(value.get_str());
+//!         }
+//!         visit::visit_property(self, key, value);
+//!     }
+//! }
+//!
+//! let doc = Document::parse(Cursor::new(
+//!     "db { password = 'a' } cache { servers = [{ password = 'b' }] }")).unwrap();
+//! let mut passwords = Passwords(Vec::new());
+//! passwords.visit_document(&doc);
+//! assert_eq!(passwords.0, vec!["a", "b"]);
+//! ```
+
+use super::types::{Document, Node, Value};
+
+/// A pass over the parts of a document.  See the module documentation.
+///
+/// The lifetime is that of the document, so a visitor can keep references to the parts
+/// it visits.
+pub trait Visit<'a> {
+    fn visit_document(&mut self, document: &'a Document) {
+        visit_document(self, document)
+    }
+
+    fn visit_node(&mut self, node: &'a Node) {
+        visit_node(self, node)
+    }
+
+    /// Visit a node attribute, or an entry of a dict
+    fn visit_property(&mut self, key: &'a str, value: &'a Value) {
+        visit_property(self, key, value)
+    }
+
+    fn visit_value(&mut self, value: &'a Value) {
+        visit_value(self, value)
+    }
+}
+
+/// Visit each of a document's nodes
+pub fn visit_document<'a, V>(visitor: &mut V, document: &'a Document) where V: Visit<'a> + ?Sized {
+    for node in document.children() {
+        visitor.visit_node(node);
+    }
+}
+
+/// Visit a node's arguments, properties and subnodes
+pub fn visit_node<'a, V>(visitor: &mut V, node: &'a Node) where V: Visit<'a> + ?Sized {
+    for argument in node.arguments() {
+        visitor.visit_value(argument);
+    }
+    for (key, value) in node.properties() {
+        visitor.visit_property(key, value);
+    }
+    for child in node.children() {
+        visitor.visit_node(child);
+    }
+}
+
+/// Visit the value of a property
+pub fn visit_property<'a, V>(visitor: &mut V, _key: &'a str, value: &'a Value)
+    where V: Visit<'a> + ?Sized {
+
+    visitor.visit_value(value)
+}
+
+/// Visit the items of a list, the entries of a dict, or a node value.  Other values
+/// have nothing inside to visit.
+pub fn visit_value<'a, V>(visitor: &mut V, value: &'a Value) where V: Visit<'a> + ?Sized {
+    match *value {
+        Value::List(ref items) => for item in items {
+            visitor.visit_value(item);
+        },
+        Value::Dict(ref dict) => {
+            let mut entries: Vec<_> = dict.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            for (key, value) in entries {
+                visitor.visit_property(key, value);
+            }
+        },
+        Value::Node(ref node) => visitor.visit_node(node),
+        _ => {},
+    }
+}
+
+/// A pass over the parts of a document that can change them.  This works in the same
+/// way as `Visit`, except that the default methods call the `_mut` functions in this
+/// module.  Keys can't be changed, as they identify the values.
+pub trait VisitMut {
+    fn visit_document_mut(&mut self, document: &mut Document) {
+        visit_document_mut(self, document)
+    }
+
+    fn visit_node_mut(&mut self, node: &mut Node) {
+        visit_node_mut(self, node)
+    }
+
+    /// Visit a node attribute, or an entry of a dict
+    fn visit_property_mut(&mut self, key: &str, value: &mut Value) {
+        visit_property_mut(self, key, value)
+    }
+
+    fn visit_value_mut(&mut self, value: &mut Value) {
+        visit_value_mut(self, value)
+    }
+}
+
+/// Visit each of a document's nodes
+pub fn visit_document_mut<V>(visitor: &mut V, document: &mut Document) where V: VisitMut + ?Sized {
+    for node in document.children_mut() {
+        visitor.visit_node_mut(node);
+    }
+}
+
+/// Visit a node's arguments, properties and subnodes
+pub fn visit_node_mut<V>(visitor: &mut V, node: &mut Node) where V: VisitMut + ?Sized {
+    for argument in node.arguments_mut() {
+        visitor.visit_value_mut(argument);
+    }
+    for (key, value) in node.properties_mut() {
+        visitor.visit_property_mut(key, value);
+    }
+    for child in node.children_mut() {
+        visitor.visit_node_mut(child);
+    }
+}
+
+/// Visit the value of a property
+pub fn visit_property_mut<V>(visitor: &mut V, _key: &str, value: &mut Value)
+    where V: VisitMut + ?Sized {
+
+    visitor.visit_value_mut(value)
+}
+
+/// Visit the items of a list, the entries of a dict, or a node value
+pub fn visit_value_mut<V>(visitor: &mut V, value: &mut Value) where V: VisitMut + ?Sized {
+    match *value {
+        Value::List(ref mut items) => for item in items {
+            visitor.visit_value_mut(item);
+        },
+        Value::Dict(ref mut dict) => {
+            let mut entries: Vec<_> = dict.iter_mut().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            for (key, value) in entries {
+                visitor.visit_property_mut(key, value);
+            }
+        },
+        Value::Node(ref mut node) => visitor.visit_node_mut(node),
+        _ => {},
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::figtree::Figtree;
+    use super::super::parser::ParserOptions;
+
+    fn parse(input: &str) -> Document {
+        let options = ParserOptions::new().node_arguments(true);
+        Figtree::from_string(input).with_options(options).parse().unwrap()
+    }
+
+    /// Records what is visited, in order
+    struct Trace(Vec<String>);
+
+    impl<'a> Visit<'a> for Trace {
+        fn visit_node(&mut self, node: &'a Node) {
+            self.0.push(format!("node {}", node.name()));
+            if node.name() != "skipped" {
+                visit_node(self, node);
+            }
+        }
+
+        fn visit_property(&mut self, key: &'a str, value: &'a Value) {
+            self.0.push(format!("property {}", key));
+            visit_property(self, key, value);
+        }
+
+        fn visit_value(&mut self, value: &'a Value) {
+            self.0.push(format!("value {}", value.type_name()));
+            visit_value(self, value);
+        }
+    }
+
+    #[test]
+    fn visits_in_order() {
+        let doc = parse("a 1 { x = [2, { k = 3, j = 4 }], b { y = !z } skipped { w = 5 } } c {}");
+        let mut trace = Trace(Vec::new());
+        trace.visit_document(&doc);
+        assert_eq!(trace.0, vec![
+            "node a", "value integer",
+            "property x", "value list", "value integer", "value dict",
+            "property j", "value integer", "property k", "value integer",
+            "node b", "property y", "value identifier",
+            "node skipped",
+            "node c",
+        ]);
+    }
+
+    struct Doubler;
+
+    impl VisitMut for Doubler {
+        fn visit_value_mut(&mut self, value: &mut Value) {
+            if let Value::Int(ref mut int) = *value {
+                *int *= 2;
+            }
+            visit_value_mut(self, value);
+        }
+    }
+
+    #[test]
+    fn changes_values() {
+        let mut doc = parse("a 1 { x = [2, { k = 3 }], b { y = 4, s = 'x' } }");
+        Doubler.visit_document_mut(&mut doc);
+        assert_eq!(doc, parse("a 2 { x = [4, { k = 6 }], b { y = 8, s = 'x' } }"));
+    }
+}
@@ -0,0 +1,683 @@
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Write};
+
+use super::display::{write_name, write_string, write_value};
+use super::parser::ParseEvent;
+use super::types::Value;
+
+/// The error produced by `Writer::write_event`
+#[derive(Debug)]
+pub enum WriteError {
+    /// The output couldn't be written to
+    Io(io::Error),
+    /// The event can't come next in a document, such as a `Key` outside of a node or a
+    /// `NodeEnd` without a `NodeStart`.  Nothing is written for it.
+    UnexpectedEvent(ParseEvent),
+    /// The comment can't go where it was written, such as in the middle of a value, or as
+    /// a trailing comment with no line to go at the end of.  Nothing is written for it.
+    UnexpectedComment(String),
+}
+
+impl fmt::Display for WriteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WriteError::Io(ref error) => write!(f, "could not write document: {}", error),
+            WriteError::UnexpectedEvent(ref event) => write!(f, "unexpected event {:?}", event),
+            WriteError::UnexpectedComment(ref comment) =>
+                write!(f, "unexpected comment {:?}", comment),
+        }
+    }
+}
+
+impl Error for WriteError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            WriteError::Io(ref error) => Some(error),
+            WriteError::UnexpectedEvent(_) | WriteError::UnexpectedComment(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for WriteError {
+    fn from(error: io::Error) -> Self {
+        WriteError::Io(error)
+    }
+}
+
+/// When a `Writer` flushes its output
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum FlushPolicy {
+    /// When the document ends, or when `Writer::flush` is called (the default)
+    End,
+    /// After each top-level node, so that readers at the other end of a pipe or socket
+    /// get each node as soon as it's complete
+    EachNode,
+    /// After every event
+    EachEvent,
+}
+
+/// What the writer is in the middle of
+enum Frame {
+    /// A node, and whether its opening brace has been written yet - it isn't written
+    /// until it's known that the node isn't empty
+    Node { open: bool },
+    /// An attribute of a node, waiting for its value
+    Attr,
+    List { items: usize },
+    Dict { entries: usize },
+    /// The key of a dict entry, waiting for its value
+    Entry,
+}
+
+/// Writes a document from a stream of `ParseEvent`s, without building a `Document`.
+///
+/// This is the inverse of `Parser`: the events of a document can be passed straight from
+/// a parser to a writer, and parsing the output gives the same events again.  In between,
+/// events can be filtered or rewritten, so large documents can be transformed a piece at
+/// a time.  The output is laid out in the same way as `Document`'s `Display` output.
+///
+/// Nothing is kept once it has been written, so documents of any size can be written
+/// from a stream of events.  By default each event is written to the output straight
+/// away; `with_capacity` collects the output into a buffer instead, which is written out
+/// when it fills up or when the output is flushed (see `FlushPolicy`).
+///
+/// Comments can be written between the events, either on their own line before the next
+/// node or attribute (`write_comment`), or at the end of the line of the attribute or node
+/// that has just been written (`write_trailing_comment`).
+///
+/// # Examples
+/// Replacing every password in a document:
+///
+/// ```
+/// # use figtree::{Parser, ParseEvent, ParsedValue, Writer};
+/// let input = "db { user = 'admin', password = 'hunter2' }";
+/// let mut writer = Writer::new(Vec::new());
+/// let mut password = false;
+/// for result in Parser::from_bytes(input.as_bytes()) {
+///     let event = match result.unwrap().0 {
+///         ParseEvent::Value(_) if password => ParseEvent::Value(ParsedValue::Str("***".into())),
+///         event => event,
+///     };
+///     password = event == ParseEvent::Key("password".to_string(), false);
+///     writer.write_event(event).unwrap();
+/// }
+/// let output = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+/// assert_eq!(output, "db {\n    user: \"admin\",\n    password: \"***\",\n}\n");
+/// ```
+pub struct Writer<W> {
+    output: W,
+    /// output that hasn't been written yet, which is written once it reaches `capacity`
+    buffer: Vec<u8>,
+    capacity: usize,
+    flush: FlushPolicy,
+    layout: Layout,
+}
+
+/// Lays out the text for each event, keeping track of where in the document it is.  This
+/// is shared by `Writer` and `AsyncWriter`.
+#[derive(Default)]
+pub struct Layout {
+    stack: Vec<Frame>,
+    started: bool,
+    finished: bool,
+    /// whether the last event was the end of a node
+    node_ended: bool,
+    /// whether the line for the last attribute or node hasn't been ended yet, so that a
+    /// trailing comment can still be written on it
+    line_open: bool,
+}
+
+impl<W: Write> Writer<W> {
+    /// Construct a writer that writes each event to `output` as soon as it's given
+    pub fn new(output: W) -> Self {
+        Self::with_capacity(0, output)
+    }
+
+    /// Construct a writer that collects up to `capacity` bytes of output before writing
+    /// them to `output`.  This saves writing each event on its own to unbuffered outputs,
+    /// like a `File` or a `TcpStream`.
+    pub fn with_capacity(capacity: usize, output: W) -> Self {
+        Writer {
+            output,
+            buffer: Vec::with_capacity(capacity),
+            capacity,
+            flush: FlushPolicy::End,
+            layout: Layout::default(),
+        }
+    }
+
+    /// Set when the output is flushed
+    pub fn flush_policy(mut self, policy: FlushPolicy) -> Self {
+        self.flush = policy;
+        self
+    }
+
+    /// Get a reference to the output being written to.  Anything in the buffer hasn't
+    /// been written to it yet.
+    pub fn get_ref(&self) -> &W {
+        &self.output
+    }
+
+    /// The output that has been collected, but not yet written
+    pub fn buffer(&self) -> &[u8] {
+        &self.buffer
+    }
+
+    /// Write anything in the buffer to the output, and flush the output
+    ///
+    /// # Failures
+    /// Fails if the output can't be written to.
+    pub fn flush(&mut self) -> Result<(), WriteError> {
+        let end = self.layout.finish_line();
+        self.buffer.extend_from_slice(end.as_bytes());
+        self.write_buffer()?;
+        self.output.flush()?;
+        Ok(())
+    }
+
+    /// Stop writing, and get back the output, once anything in the buffer has been
+    /// written to it.
+    ///
+    /// # Failures
+    /// Fails if the buffer can't be written to the output.
+    pub fn into_inner(mut self) -> Result<W, WriteError> {
+        self.write_buffer()?;
+        Ok(self.output)
+    }
+
+    fn write_buffer(&mut self) -> io::Result<()> {
+        if !self.buffer.is_empty() {
+            self.output.write_all(&self.buffer)?;
+            self.buffer.clear();
+        }
+        Ok(())
+    }
+
+    /// Write the next event of the document, flushing the output if the writer's
+    /// `FlushPolicy` says to.  The output is always flushed when `FileEnd` is written.
+    ///
+    /// # Failures
+    /// Fails if the event can't come next in the document, or if the output can't be
+    /// written to.
+    pub fn write_event(&mut self, event: ParseEvent) -> Result<(), WriteError> {
+        let text = self.layout.text(event)?;
+        self.write_text(&text)
+    }
+
+    /// Write a comment on its own line, before the next node or attribute (or the end of
+    /// the node).  Each line of the comment is written as a `//` comment.
+    ///
+    /// # Examples
+    /// ```
+    /// # use figtree::{Figtree, Parser, Writer};
+    /// let mut writer = Writer::new(Vec::new());
+    /// let mut events = Parser::from_bytes(b"server { port = 80 }").map(|result| result.unwrap().0);
+    /// writer.write_event(events.next().unwrap()).unwrap();
+    /// writer.write_comment("generated by deploy.sh\ndo not edit").unwrap();
+    /// writer.write_events(events.by_ref().take(3)).unwrap();
+    /// writer.write_trailing_comment("the public port").unwrap();
+    /// writer.write_events(events).unwrap();
+    ///
+    /// let output = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+    /// assert_eq!(output, "\
+    /// // generated by deploy.sh
+    /// // do not edit
+    /// server {
+    ///     port: 80, // the public port
+    /// }
+    /// ");
+    /// assert!(Figtree::from_string(output).parse().is_ok());
+    /// ```
+    ///
+    /// # Failures
+    /// Fails if a comment can't go here, because a value is being written, or if the
+    /// output can't be written to.
+    pub fn write_comment(&mut self, comment: &str) -> Result<(), WriteError> {
+        let text = self.layout.comment(comment)?;
+        self.write_text(&text)
+    }
+
+    /// Write a comment at the end of the line of the attribute or node that has just
+    /// been written.
+    ///
+    /// # Failures
+    /// Fails if the comment has more than one line, if nothing has just been written that
+    /// the comment can trail, or if the line has already been ended by flushing the output
+    /// (see `FlushPolicy`).  Also fails if the output can't be written to.
+    pub fn write_trailing_comment(&mut self, comment: &str) -> Result<(), WriteError> {
+        let text = self.layout.trailing_comment(comment)?;
+        self.write_text(&text)
+    }
+
+    fn write_text(&mut self, text: &str) -> Result<(), WriteError> {
+        if self.buffer.len() + text.len() > self.capacity {
+            self.write_buffer()?;
+        }
+        if text.len() > self.capacity {
+            self.output.write_all(text.as_bytes())?;
+        } else {
+            self.buffer.extend_from_slice(text.as_bytes());
+        }
+
+        if self.layout.should_flush(self.flush) {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Write every event from `events`, stopping at the first error
+    pub fn write_events<I>(&mut self, events: I) -> Result<(), WriteError>
+        where I: IntoIterator<Item = ParseEvent> {
+
+        events.into_iter().try_for_each(|event| self.write_event(event))
+    }
+}
+
+impl Layout {
+    /// The text for an event, or an error if the event can't come next
+    pub fn text(&mut self, event: ParseEvent) -> Result<String, WriteError> {
+        let line_open = self.line_open;
+        let mut text = self.finish_line().to_string();
+        if !self.write_to(&mut text, &event) {
+            self.line_open = line_open;
+            return Err(WriteError::UnexpectedEvent(event));
+        }
+        self.node_ended = event == ParseEvent::NodeEnd;
+        Ok(text)
+    }
+
+    /// The text for a comment on its own line, or an error if a comment can't go here
+    pub fn comment(&mut self, comment: &str) -> Result<String, WriteError> {
+        if self.finished || !matches!(self.stack.last(), None | Some(&Frame::Node { .. })) {
+            return Err(WriteError::UnexpectedComment(comment.to_string()));
+        }
+        let mut text = self.finish_line().to_string();
+        self.open_node(&mut text);
+        let indent = self.indent();
+        // a lone `\r` would end the comment early, so it's treated as a line ending too
+        for line in comment.replace("\r\n", "\n").split(['\n', '\r']) {
+            text.push_str(&indent);
+            text.push_str("//");
+            if !line.is_empty() {
+                text.push(' ');
+                text.push_str(line);
+            }
+            text.push('\n');
+        }
+        Ok(text)
+    }
+
+    /// The text for a trailing comment, or an error if there's no line for it to trail
+    pub fn trailing_comment(&mut self, comment: &str) -> Result<String, WriteError> {
+        if !self.line_open || comment.contains(['\n', '\r']) {
+            return Err(WriteError::UnexpectedComment(comment.to_string()));
+        }
+        Ok(format!(" // {}", comment))
+    }
+
+    /// End the line of the last attribute or node, if it hasn't been already
+    pub fn finish_line(&mut self) -> &'static str {
+        if self.line_open {
+            self.line_open = false;
+            "\n"
+        } else {
+            ""
+        }
+    }
+
+    /// Test if the output should be flushed after the last event.  It always is at the
+    /// end of the document.
+    pub fn should_flush(&self, policy: FlushPolicy) -> bool {
+        match policy {
+            _ if self.finished => true,
+            FlushPolicy::End => false,
+            FlushPolicy::EachNode => self.node_ended && self.stack.is_empty(),
+            FlushPolicy::EachEvent => true,
+        }
+    }
+
+    /// The indentation for an entry of the innermost node
+    fn indent(&self) -> String {
+        let depth = self.stack.iter().filter(|frame| matches!(**frame, Frame::Node { .. })).count();
+        " ".repeat(depth * 4)
+    }
+
+    /// Write the text for an event to `text`, returning `false` (and leaving the writer
+    /// as it was) if the event can't come next.  The formatting can't fail, because
+    /// it's written to a string.
+    fn write_to(&mut self, text: &mut String, event: &ParseEvent) -> bool {
+        if !self.started || self.finished {
+            self.started = *event == ParseEvent::FileStart;
+            return self.started;
+        }
+        match *event {
+            ParseEvent::FileStart => false,
+            ParseEvent::FileEnd => {
+                self.finished = self.stack.is_empty();
+                self.finished
+            },
+            ParseEvent::NodeStart(ref name, quoted) => {
+                if !self.open_node(text) {
+                    return false;
+                }
+                text.push_str(&self.indent());
+                write_key(text, name, quoted);
+                self.stack.push(Frame::Node { open: false });
+                true
+            },
+            ParseEvent::Argument(ref value) => match self.stack.last() {
+                Some(&Frame::Node { open: false }) => {
+                    text.push(' ');
+                    let _ = write_value(text, &Value::from_parsed_value(value.clone()));
+                    true
+                },
+                _ => false,
+            },
+            ParseEvent::NodeEnd => match self.stack.last() {
+                Some(&Frame::Node { open }) => {
+                    self.stack.pop();
+                    if open {
+                        text.push_str(&self.indent());
+                        text.push('}');
+                    } else {
+                        text.push_str(" {}");
+                    }
+                    self.line_open = true;
+                    true
+                },
+                _ => false,
+            },
+            ParseEvent::Key(ref key, quoted) => match self.stack.last_mut() {
+                Some(&mut Frame::Dict { ref mut entries }) => {
+                    text.push_str(if *entries == 0 { " " } else { ", " });
+                    *entries += 1;
+                    write_key(text, key, quoted);
+                    text.push_str(": ");
+                    self.stack.push(Frame::Entry);
+                    true
+                },
+                Some(&mut Frame::Node { .. }) => {
+                    self.open_node(text);
+                    text.push_str(&self.indent());
+                    write_key(text, key, quoted);
+                    text.push_str(": ");
+                    self.stack.push(Frame::Attr);
+                    true
+                },
+                _ => false,
+            },
+            ParseEvent::Value(ref value) => {
+                if !self.start_value(text) {
+                    return false;
+                }
+                let _ = write_value(text, &Value::from_parsed_value(value.clone()));
+                self.end_value(text);
+                true
+            },
+            ParseEvent::ListStart | ParseEvent::DictStart => {
+                if !self.start_value(text) {
+                    return false;
+                }
+                if *event == ParseEvent::ListStart {
+                    text.push('[');
+                    self.stack.push(Frame::List { items: 0 });
+                } else {
+                    text.push('{');
+                    self.stack.push(Frame::Dict { entries: 0 });
+                }
+                true
+            },
+            ParseEvent::ListEnd => match self.stack.last() {
+                Some(&Frame::List { .. }) => {
+                    self.stack.pop();
+                    text.push(']');
+                    self.end_value(text);
+                    true
+                },
+                _ => false,
+            },
+            ParseEvent::DictEnd => match self.stack.last() {
+                Some(&Frame::Dict { entries }) => {
+                    self.stack.pop();
+                    text.push_str(if entries == 0 { "}" } else { " }" });
+                    self.end_value(text);
+                    true
+                },
+                _ => false,
+            },
+        }
+    }
+
+    /// Get ready to write an entry of the innermost node (or of the document), writing
+    /// the node's opening brace if it hasn't been yet
+    fn open_node(&mut self, text: &mut String) -> bool {
+        match self.stack.last_mut() {
+            None => true,
+            Some(&mut Frame::Node { ref mut open }) => {
+                if !*open {
+                    text.push_str(" {\n");
+                    *open = true;
+                }
+                true
+            },
+            Some(_) => false,
+        }
+    }
+
+    /// Get ready to write a value, returning `false` if a value can't come next
+    fn start_value(&mut self, text: &mut String) -> bool {
+        match self.stack.last_mut() {
+            Some(&mut Frame::Attr) | Some(&mut Frame::Entry) => true,
+            Some(&mut Frame::List { ref mut items }) => {
+                if *items > 0 {
+                    text.push_str(", ");
+                }
+                *items += 1;
+                true
+            },
+            _ => false,
+        }
+    }
+
+    /// Finish off the attribute or dict entry that a value belongs to
+    fn end_value(&mut self, text: &mut String) {
+        match self.stack.last() {
+            Some(&Frame::Attr) => {
+                self.stack.pop();
+                text.push(',');
+                self.line_open = true;
+            },
+            Some(&Frame::Entry) => { self.stack.pop(); },
+            _ => {},
+        }
+    }
+}
+
+/// Write a node name or key, as a string if it was written as one
+fn write_key(text: &mut String, key: &str, quoted: bool) {
+    let _ = if quoted { write_string(text, key) } else { write_name(text, key) };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::parser::{Parser, ParsedValue, ParserOptions};
+    use super::super::lexer::Lexer;
+    use super::super::figtree::Figtree;
+    use std::io::Cursor;
+
+    fn events(input: &str) -> Vec<ParseEvent> {
+        let options = ParserOptions::new().repeated_nodes(true).node_arguments(true);
+        let lexer = Lexer::lex(Cursor::new(input.to_string().into_bytes()));
+        Parser::with_options(lexer, options).map(|result| result.unwrap().0).collect()
+    }
+
+    fn written(events: Vec<ParseEvent>) -> String {
+        let mut writer = Writer::new(Vec::new());
+        writer.write_events(events).unwrap();
+        String::from_utf8(writer.into_inner().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn inverts_parser() {
+        let input = "
+            server 'main' 80 {
+                host = 'localhost', 'odd key' = [1, [2.5, -inf], { a = [] }], \"true\": !id,
+                empty = {}, dict = { x = { y = 1 }, 'z z' = null }, when = 2024-01-01T00:00:00Z
+                upstream { weight = 1 }
+                upstream 2 {}
+                wait = 1m30s, data = b'\\x00\\xff'
+                'odd node' { \"quoted\" = 1 }
+            }
+            r {}
+        ";
+        let output = written(events(input));
+        assert_eq!(events(&output), events(input), "{}", output);
+        assert_eq!(written(events("a { b { c = [1, 2] } } d 1 {}")),
+            "a {\n    b {\n        c: [1, 2],\n    }\n}\nd 1 {}\n");
+        assert_eq!(written(events("")), "");
+    }
+
+    /// An output that records each write and flush
+    #[derive(Default)]
+    struct Recorder {
+        writes: Vec<usize>,
+        flushes: usize,
+        written: Vec<u8>,
+    }
+
+    impl Write for Recorder {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+            self.writes.push(bytes.len());
+            self.written.extend_from_slice(bytes);
+            Ok(bytes.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.flushes += 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn buffers_and_flushes() {
+        let input = "a { b = 1 } c { d = [1, 2] } e {}";
+        let expected = written(events(input));
+
+        let mut writer = Writer::new(Recorder::default());
+        writer.write_events(events(input)).unwrap();
+        let recorder = writer.into_inner().unwrap();
+        assert_eq!(recorder.writes.len(), events(input).len() - 1);
+        assert_eq!((recorder.flushes, recorder.written), (1, expected.clone().into_bytes()));
+
+        let mut writer = Writer::with_capacity(16, Recorder::default());
+        writer.write_events(events(input).into_iter().take(4)).unwrap();
+        assert!(writer.get_ref().writes.is_empty());
+        assert_eq!(writer.buffer(), b"a {\n    b: 1,");
+        writer.flush().unwrap();
+        assert_eq!(writer.get_ref().writes, vec![14]);
+        assert!(writer.buffer().is_empty());
+        writer.write_events(events(input).into_iter().skip(4)).unwrap();
+        let recorder = writer.into_inner().unwrap();
+        assert!(recorder.writes.iter().all(|&length| length <= 16));
+        assert_eq!((recorder.flushes, recorder.written), (2, expected.clone().into_bytes()));
+
+        let mut writer = Writer::with_capacity(1024, Recorder::default())
+            .flush_policy(FlushPolicy::EachNode);
+        writer.write_events(events(input)).unwrap();
+        let recorder = writer.into_inner().unwrap();
+        assert_eq!((recorder.writes.len(), recorder.flushes), (3, 4));
+        assert_eq!(recorder.written, expected.into_bytes());
+
+        let mut writer = Writer::new(Recorder::default()).flush_policy(FlushPolicy::EachEvent);
+        writer.write_events(events(input)).unwrap();
+        assert_eq!(writer.get_ref().flushes, events(input).len());
+
+        // large documents go through a small buffer without being collected
+        let mut writer = Writer::with_capacity(64, Recorder::default());
+        writer.write_event(ParseEvent::FileStart).unwrap();
+        for _ in 0..1000 {
+            writer.write_events(events("node { key = 'value' }").into_iter().skip(1).take(4))
+                .unwrap();
+            assert!(writer.buffer().len() <= 64);
+        }
+        writer.write_event(ParseEvent::FileEnd).unwrap();
+        assert_eq!(writer.into_inner().unwrap().written.len(), 1000 * 27);
+    }
+
+    #[test]
+    fn writes_comments() {
+        let mut writer = Writer::new(Vec::new());
+        let mut input = events("a 1 { b = [1, 2] c {} } d {}").into_iter();
+        writer.write_comment("header").unwrap();
+        writer.write_events(input.by_ref().take(3)).unwrap();
+        writer.write_comment("inside a").unwrap();
+        writer.write_events(input.by_ref().take(2)).unwrap();
+        let unexpected = WriteError::UnexpectedComment("in a list".to_string());
+        let error = writer.write_comment("in a list").unwrap_err();
+        assert_eq!(error.to_string(), unexpected.to_string());
+        writer.write_events(input.by_ref().take(3)).unwrap();
+        writer.write_trailing_comment("after b").unwrap();
+        writer.write_trailing_comment("and more").unwrap();
+        writer.write_comment("two\r\nlines\rand a lone\n\nblank").unwrap();
+        writer.write_events(input.by_ref().take(2)).unwrap();
+        writer.write_trailing_comment("after c").unwrap();
+        assert!(writer.write_trailing_comment("two\nlines").is_err());
+        writer.write_events(input.by_ref().take(1)).unwrap();
+        writer.write_comment("between").unwrap();
+        assert!(writer.write_trailing_comment("nothing to trail").is_err());
+        writer.write_events(input).unwrap();
+        assert!(writer.write_comment("after the end").is_err());
+
+        let output = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert_eq!(output, "\
+// header
+a 1 {
+    // inside a
+    b: [1, 2], // after b // and more
+    // two
+    // lines
+    // and a lone
+    //
+    // blank
+    c {} // after c
+}
+// between
+d {}
+");
+        let options = ParserOptions::new().node_arguments(true);
+        assert!(Figtree::from_string(output).with_options(options).parse().is_ok());
+
+        let mut writer = Writer::new(Vec::new()).flush_policy(FlushPolicy::EachEvent);
+        writer.write_events(events("a { b = 1 }").into_iter().take(4)).unwrap();
+        assert!(writer.write_trailing_comment("flushed").is_err());
+    }
+
+    #[test]
+    fn rejects_unexpected_events() {
+        let unexpected = |events: Vec<ParseEvent>| {
+            let mut writer = Writer::new(Vec::new());
+            let (last, rest) = events.split_last().unwrap();
+            writer.write_events(rest.to_vec()).unwrap();
+            match writer.write_event(last.clone()) {
+                Err(WriteError::UnexpectedEvent(ref event)) => event == last,
+                _ => false,
+            }
+        };
+        let start = |name: &str| ParseEvent::NodeStart(name.to_string(), false);
+        let key = ParseEvent::Key("k".to_string(), false);
+        let value = ParseEvent::Value(ParsedValue::Int(1));
+
+        assert!(unexpected(vec![start("a")]));
+        assert!(unexpected(vec![ParseEvent::FileStart, key.clone()]));
+        assert!(unexpected(vec![ParseEvent::FileStart, ParseEvent::NodeEnd]));
+        assert!(unexpected(vec![ParseEvent::FileStart, start("a"), value.clone()]));
+        assert!(unexpected(vec![ParseEvent::FileStart, start("a"), key.clone(), start("b")]));
+        assert!(unexpected(vec![ParseEvent::FileStart, start("a"), key.clone(), ParseEvent::ListStart, ParseEvent::DictEnd]));
+        assert!(unexpected(vec![ParseEvent::FileStart, start("a"), key.clone(), value.clone(), ParseEvent::Argument(ParsedValue::Null)]));
+        assert!(unexpected(vec![ParseEvent::FileStart, start("a"), ParseEvent::FileEnd]));
+        assert!(unexpected(vec![ParseEvent::FileStart, ParseEvent::FileEnd, start("a")]));
+        assert_eq!(WriteError::UnexpectedEvent(ParseEvent::NodeEnd).to_string(), "unexpected event NodeEnd");
+    }
+}
@@ -0,0 +1,1035 @@
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+
+use super::display::write_value;
+use super::parser::{ParseEvent, ParsedValue};
+use super::position::Position;
+use super::types::{Dict, Document, Node, Value};
+
+/// The error produced when XML can't be read as nodes, or nodes can't be written as XML
+#[derive(Debug)]
+pub enum XmlError {
+    /// The input couldn't be read from, or the output couldn't be written to
+    Io(io::Error),
+    /// The XML isn't well-formed, or has text that can't be read as arguments, at this
+    /// position
+    Syntax(String, Position),
+    /// A node's name or an attribute's key isn't a valid XML name
+    InvalidName(String),
+    /// The document has this many nodes, but XML has exactly one root element
+    Roots(usize),
+    /// The event can't come next in the document, or can't be written as XML, like an
+    /// attribute after a subnode (XML attributes have to come before any elements).
+    /// Nothing is written for it.
+    UnexpectedEvent(ParseEvent),
+}
+
+impl fmt::Display for XmlError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            XmlError::Io(ref error) => write!(f, "could not read or write XML: {}", error),
+            XmlError::Syntax(ref message, ref position) => {
+                write!(f, "{} (line {}, column {})", message, position.line + 1, position.pos + 1)
+            },
+            XmlError::InvalidName(ref name) => write!(f, "{:?} isn't a valid XML name", name),
+            XmlError::Roots(count) => {
+                write!(f, "XML has exactly one root element, but the document has {} nodes", count)
+            },
+            XmlError::UnexpectedEvent(ref event) => write!(f, "unexpected event {:?}", event),
+        }
+    }
+}
+
+impl Error for XmlError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            XmlError::Io(ref error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for XmlError {
+    fn from(error: io::Error) -> Self {
+        XmlError::Io(error)
+    }
+}
+
+/// Check if text is a valid XML name, for elements and attributes
+fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_' || c == ':')
+        && chars.all(|c| c.is_alphanumeric() || matches!(c, '_' | ':' | '-' | '.'))
+}
+
+/// The text that a value is written as: strings and identifiers as they are, and
+/// anything else as it would be written in a figtree document
+fn value_text(value: &Value) -> String {
+    match *value {
+        Value::Str(ref string) | Value::Ident(ref string) => string.clone(),
+        ref value => {
+            let mut text = String::new();
+            let _ = write_value(&mut text, value);
+            text
+        },
+    }
+}
+
+/// Escape text for the content of an element, or for an attribute's value
+fn escape(text: &str, attribute: bool) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '\r' => escaped.push_str("&#13;"),
+            '"' if attribute => escaped.push_str("&quot;"),
+            // attribute values have their whitespace turned into spaces when they're read
+            '\n' if attribute => escaped.push_str("&#10;"),
+            '\t' if attribute => escaped.push_str("&#9;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// An element that a `XmlWriter` is in the middle of
+struct Element {
+    name: String,
+    /// whether the start tag is still open, so attributes can be added to it
+    open: bool,
+    /// the arguments of the node, which are written as the element's text once the
+    /// start tag is closed
+    text: Vec<String>,
+}
+
+/// Writes XML from a stream of `ParseEvent`s, without building a `Document`, in the same
+/// way as `Writer` writes figtree documents.  Each node is written as an element, with
+/// its attributes as XML attributes and its subnodes as child elements.
+///
+/// XML has no arguments, so a node's arguments are written as the element's text,
+/// separated by spaces.  Values that aren't strings are written as they would be in a
+/// figtree document, so lists are written as `[1, 2]`, and the type of each value is
+/// lost: an `XmlReader` reads every attribute and argument back as a string.
+///
+/// Attributes have to be written in an element's start tag, so an attribute that comes
+/// after one of a node's subnodes is an `XmlError::UnexpectedEvent`.  Each top-level node
+/// becomes a root element, so a document with more than one node needs to be wrapped in
+/// another element with `root` to be valid XML.
+///
+/// # Examples
+/// ```
+/// # use figtree::{Parser, XmlWriter};
+/// let input = "server { port = 80, tls { cert = 'a.pem' } }";
+/// let mut writer = XmlWriter::new(Vec::new()).root("config");
+/// writer.write_events(Parser::from_bytes(input.as_bytes()).map(|result| result.unwrap().0))
+///     .unwrap();
+/// assert_eq!(String::from_utf8(writer.into_inner()).unwrap(), concat!(
+///     "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+///     "<config>\n",
+///     "    <server port=\"80\">\n",
+///     "        <tls cert=\"a.pem\"/>\n",
+///     "    </server>\n",
+///     "</config>\n",
+/// ));
+/// ```
+pub struct XmlWriter<W> {
+    output: W,
+    root: Option<String>,
+    stack: Vec<Element>,
+    started: bool,
+    finished: bool,
+    /// the key of the attribute whose value is being written
+    key: Option<String>,
+    /// the lists and dicts of the attribute's value that haven't ended yet, along with
+    /// the key of each dict entry that's waiting for its value
+    values: Vec<(Value, Option<String>)>,
+}
+
+impl<W: Write> XmlWriter<W> {
+    /// Construct a writer that writes each event to `output` as soon as it's given
+    pub fn new(output: W) -> Self {
+        XmlWriter {
+            output,
+            root: None,
+            stack: Vec::new(),
+            started: false,
+            finished: false,
+            key: None,
+            values: Vec::new(),
+        }
+    }
+
+    /// Wrap the nodes of the document in a root element with this name
+    pub fn root<S: Into<String>>(mut self, name: S) -> Self {
+        self.root = Some(name.into());
+        self
+    }
+
+    /// Get a reference to the output
+    pub fn get_ref(&self) -> &W {
+        &self.output
+    }
+
+    /// Take the output back from the writer
+    pub fn into_inner(self) -> W {
+        self.output
+    }
+
+    /// Write the XML for an event.  Events have to come in the order that a `Parser`
+    /// produces them, starting with `FileStart` and ending with `FileEnd`.
+    ///
+    /// # Failures
+    /// Fails if the event can't come next in the document, if a node's name or an
+    /// attribute's key isn't a valid XML name, or if the output can't be written to.
+    pub fn write_event(&mut self, event: ParseEvent) -> Result<(), XmlError> {
+        if !self.started || self.finished {
+            return match event {
+                ParseEvent::FileStart if !self.started => self.begin(),
+                event => Err(XmlError::UnexpectedEvent(event)),
+            };
+        }
+        let expected = match event {
+            ParseEvent::FileStart => false,
+            ParseEvent::FileEnd => self.stack.is_empty() && self.key.is_none(),
+            ParseEvent::NodeStart(..) => self.key.is_none(),
+            ParseEvent::NodeEnd => self.key.is_none() && !self.stack.is_empty(),
+            ParseEvent::Argument(_) => {
+                self.key.is_none() && self.stack.last().is_some_and(|element| element.open)
+            },
+            ParseEvent::Key(..) => match self.values.last() {
+                Some(&(Value::Dict(_), None)) => true,
+                Some(_) => false,
+                None => self.key.is_none() && self.stack.last().is_some_and(|element| element.open),
+            },
+            ParseEvent::Value(_) | ParseEvent::ListStart | ParseEvent::DictStart => {
+                match self.values.last() {
+                    Some(&(Value::Dict(_), ref key)) => key.is_some(),
+                    Some(_) => true,
+                    None => self.key.is_some(),
+                }
+            },
+            ParseEvent::ListEnd => matches!(self.values.last(), Some(&(Value::List(_), _))),
+            ParseEvent::DictEnd => matches!(self.values.last(), Some(&(Value::Dict(_), None))),
+        };
+        if !expected {
+            return Err(XmlError::UnexpectedEvent(event));
+        }
+        match event {
+            ParseEvent::FileEnd => self.finish(),
+            ParseEvent::NodeStart(name, _) => self.start(name),
+            ParseEvent::NodeEnd => self.end(),
+            ParseEvent::Argument(value) => {
+                let value = Value::from_parsed_value(value);
+                self.argument(&value);
+                Ok(())
+            },
+            ParseEvent::Key(key, _) => {
+                match self.values.last_mut() {
+                    Some(&mut (_, ref mut entry)) => *entry = Some(key),
+                    None if is_name(&key) => self.key = Some(key),
+                    None => return Err(XmlError::InvalidName(key)),
+                }
+                Ok(())
+            },
+            ParseEvent::Value(value) => self.value(Value::from_parsed_value(value)),
+            ParseEvent::ListStart => {
+                self.values.push((Value::List(Vec::new()), None));
+                Ok(())
+            },
+            ParseEvent::DictStart => {
+                self.values.push((Value::Dict(Dict::new()), None));
+                Ok(())
+            },
+            ParseEvent::ListEnd | ParseEvent::DictEnd => {
+                let (value, _) = self.values.pop().expect("the value has been checked");
+                self.value(value)
+            },
+            ParseEvent::FileStart => unreachable!("FileStart has been checked"),
+        }
+    }
+
+    /// Write every event from `events`, stopping at the first error
+    pub fn write_events<I>(&mut self, events: I) -> Result<(), XmlError>
+        where I: IntoIterator<Item = ParseEvent> {
+
+        events.into_iter().try_for_each(|event| self.write_event(event))
+    }
+
+    fn indent(&self) -> String {
+        let depth = self.stack.len() + self.root.is_some() as usize;
+        "    ".repeat(depth)
+    }
+
+    fn begin(&mut self) -> Result<(), XmlError> {
+        if let Some(ref root) = self.root {
+            if !is_name(root) {
+                return Err(XmlError::InvalidName(root.clone()));
+            }
+        }
+        self.output.write_all(b"<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n")?;
+        if let Some(ref root) = self.root {
+            writeln!(self.output, "<{}>", root)?;
+        }
+        self.started = true;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), XmlError> {
+        if let Some(ref root) = self.root {
+            writeln!(self.output, "</{}>", root)?;
+        }
+        self.finished = true;
+        self.output.flush()?;
+        Ok(())
+    }
+
+    /// Write the end of an element's start tag, and its text
+    fn close_tag(&mut self) -> Result<(), XmlError> {
+        let element = self.stack.last_mut().expect("there's an element to close");
+        element.open = false;
+        let text = escape(&element.text.join(" "), false);
+        write!(self.output, ">{}", text)?;
+        Ok(())
+    }
+
+    fn start(&mut self, name: String) -> Result<(), XmlError> {
+        if !is_name(&name) {
+            return Err(XmlError::InvalidName(name));
+        }
+        if self.stack.last().is_some_and(|element| element.open) {
+            self.close_tag()?;
+            self.output.write_all(b"\n")?;
+        }
+        write!(self.output, "{}<{}", self.indent(), name)?;
+        self.stack.push(Element { name, open: true, text: Vec::new() });
+        Ok(())
+    }
+
+    fn end(&mut self) -> Result<(), XmlError> {
+        let (open, empty) = match self.stack.last() {
+            Some(element) => (element.open, element.text.is_empty()),
+            None => unreachable!("there's an element to end"),
+        };
+        if open && empty {
+            self.output.write_all(b"/>\n")?;
+            self.stack.pop();
+            return Ok(());
+        }
+        if open {
+            self.close_tag()?;
+        }
+        let element = self.stack.pop().expect("there's an element to end");
+        if !open {
+            self.output.write_all(self.indent().as_bytes())?;
+        }
+        writeln!(self.output, "</{}>", element.name)?;
+        Ok(())
+    }
+
+    fn argument(&mut self, value: &Value) {
+        let element = self.stack.last_mut().expect("there's an element for the argument");
+        element.text.push(value_text(value));
+    }
+
+    fn attr(&mut self, key: &str, value: &Value) -> Result<(), XmlError> {
+        if !is_name(key) {
+            return Err(XmlError::InvalidName(key.into()));
+        }
+        write!(self.output, " {}=\"{}\"", key, escape(&value_text(value), true))?;
+        Ok(())
+    }
+
+    /// Add a finished value to the list or dict it's in, or write it as an attribute
+    fn value(&mut self, value: Value) -> Result<(), XmlError> {
+        match self.values.last_mut() {
+            Some(&mut (Value::List(ref mut items), _)) => items.push(value),
+            Some(&mut (Value::Dict(ref mut dict), ref mut key)) => {
+                dict.insert(key.take().expect("the key has been checked"), value);
+            },
+            Some(_) => unreachable!("only lists and dicts are open"),
+            None => {
+                let key = self.key.take().expect("the key has been checked");
+                self.attr(&key, &value)?;
+            },
+        }
+        Ok(())
+    }
+
+    /// Write a node and everything in it
+    fn write_node(&mut self, name: &str, node: &Node) -> Result<(), XmlError> {
+        self.start(name.into())?;
+        for (key, value) in node.iter_attrs() {
+            self.attr(key, value)?;
+        }
+        for argument in node.arguments() {
+            self.argument(argument);
+        }
+        for child in node.children() {
+            self.write_node(child.name(), child)?;
+        }
+        self.end()
+    }
+}
+
+/// An element that a `XmlReader` is in the middle of
+struct OpenElement {
+    name: String,
+    /// whether the element has had a child element yet
+    children: bool,
+    /// the element's attributes, which are held back until its text has been read, as
+    /// arguments have to come first in a node
+    attributes: Vec<(String, String)>,
+}
+
+/// Reads XML as a stream of `ParseEvent`s, without building a `Document`.  Each element
+/// becomes a node, its attributes become string attributes, and its child elements become
+/// subnodes.  The events can be passed to a `Writer` to convert large XML files to figtree
+/// a piece at a time, or to anything else that takes parser events.
+///
+/// Text in an element becomes a string argument of the node, with the whitespace around
+/// it trimmed, but only before the element's first child: arguments come before anything
+/// else in a node, so text after a child element is an `XmlError::Syntax`.  Comments, the
+/// XML declaration, processing instructions and the doctype are skipped, and element
+/// names keep any namespace prefix that they have.
+///
+/// Several root elements are read as several top-level nodes, so the output of an
+/// `XmlWriter` without a `root` can be read back.  The input is read a line at a time.
+///
+/// # Examples
+/// ```
+/// # use figtree::{ParseEvent, ParsedValue, XmlReader};
+/// let events: Result<Vec<_>, _> = XmlReader::new("<a b='1'>text</a>".as_bytes()).collect();
+/// assert_eq!(events.unwrap(), vec![
+///     ParseEvent::FileStart,
+///     ParseEvent::NodeStart("a".into(), false),
+///     ParseEvent::Argument(ParsedValue::Str("text".into())),
+///     ParseEvent::Key("b".into(), false),
+///     ParseEvent::Value(ParsedValue::Str("1".into())),
+///     ParseEvent::NodeEnd,
+///     ParseEvent::FileEnd,
+/// ]);
+/// ```
+pub struct XmlReader<R> {
+    input: BufReader<R>,
+    /// the characters that have been read from the input, from the start of the line that
+    /// was read last
+    chars: Vec<char>,
+    index: usize,
+    eof: bool,
+    line: usize,
+    pos: usize,
+    /// the elements that haven't ended yet
+    stack: Vec<OpenElement>,
+    queue: VecDeque<ParseEvent>,
+    started: bool,
+    finished: bool,
+}
+
+impl<R: Read> XmlReader<R> {
+    pub fn new(input: R) -> Self {
+        XmlReader {
+            input: BufReader::new(input),
+            chars: Vec::new(),
+            index: 0,
+            eof: false,
+            line: 0,
+            pos: 0,
+            stack: Vec::new(),
+            queue: VecDeque::new(),
+            started: false,
+            finished: false,
+        }
+    }
+
+    /// Read lines from the input until there are `count` characters that haven't been
+    /// read yet, or the input ends
+    fn fill(&mut self, count: usize) -> Result<(), XmlError> {
+        while self.chars.len() - self.index < count && !self.eof {
+            self.chars.drain(..self.index);
+            self.index = 0;
+            let mut line = String::new();
+            if self.input.read_line(&mut line)? == 0 {
+                self.eof = true;
+            }
+            self.chars.extend(line.chars().filter(|&c| c != '\r'));
+        }
+        Ok(())
+    }
+
+    fn peek(&mut self) -> Result<Option<char>, XmlError> {
+        self.fill(1)?;
+        Ok(self.chars.get(self.index).cloned())
+    }
+
+    fn bump(&mut self) -> Result<Option<char>, XmlError> {
+        let c = self.peek()?;
+        if let Some(c) = c {
+            self.index += 1;
+            if c == '\n' {
+                self.line += 1;
+                self.pos = 0;
+            } else {
+                self.pos += 1;
+            }
+        }
+        Ok(c)
+    }
+
+    fn starts_with(&mut self, text: &str) -> Result<bool, XmlError> {
+        let count = text.chars().count();
+        self.fill(count)?;
+        Ok(self.chars[self.index..].iter().take(count).cloned().eq(text.chars()))
+    }
+
+    /// Check if the next characters are `text`, and skip them if they are
+    fn bump_str(&mut self, text: &str) -> Result<bool, XmlError> {
+        let matches = self.starts_with(text)?;
+        if matches {
+            for _ in text.chars() {
+                self.bump()?;
+            }
+        }
+        Ok(matches)
+    }
+
+    fn location(&self) -> Position {
+        Position::at(self.line, self.pos)
+    }
+
+    fn error<S: Into<String>>(&self, message: S) -> XmlError {
+        XmlError::Syntax(message.into(), self.location())
+    }
+
+    fn skip_whitespace(&mut self) -> Result<(), XmlError> {
+        while self.peek()?.is_some_and(char::is_whitespace) {
+            self.bump()?;
+        }
+        Ok(())
+    }
+
+    /// Skip everything up to and including `end`
+    fn skip_past(&mut self, end: &str, message: &str) -> Result<(), XmlError> {
+        let start = self.location();
+        while !self.bump_str(end)? {
+            if self.bump()?.is_none() {
+                return Err(XmlError::Syntax(message.into(), start));
+            }
+        }
+        Ok(())
+    }
+
+    /// Skip a doctype, which can have a list of declarations in brackets
+    fn skip_doctype(&mut self) -> Result<(), XmlError> {
+        let start = self.location();
+        let mut depth = 0;
+        loop {
+            match self.bump()? {
+                Some('[') => depth += 1,
+                Some(']') => depth -= 1,
+                Some('>') if depth == 0 => return Ok(()),
+                Some(_) => {},
+                None => return Err(XmlError::Syntax("unclosed doctype".into(), start)),
+            }
+        }
+    }
+
+    fn name(&mut self) -> Result<String, XmlError> {
+        let start = self.location();
+        let mut name = String::new();
+        while let Some(c) = self.peek()? {
+            if c.is_whitespace() || matches!(c, '/' | '>' | '=' | '<' | '"' | '\'') {
+                break;
+            }
+            name.push(c);
+            self.bump()?;
+        }
+        if is_name(&name) {
+            Ok(name)
+        } else if name.is_empty() {
+            Err(XmlError::Syntax("expected a name".into(), start))
+        } else {
+            Err(XmlError::Syntax(format!("invalid name `{}`", name), start))
+        }
+    }
+
+    /// Read an entity or character reference, after its `&`
+    fn entity(&mut self) -> Result<char, XmlError> {
+        let start = self.location();
+        let mut name = String::new();
+        loop {
+            match self.bump()? {
+                Some(';') => break,
+                Some(c) if c.is_alphanumeric() || c == '#' => name.push(c),
+                _ => return Err(XmlError::Syntax("unclosed entity".into(), start)),
+            }
+        }
+        let c = match name.as_str() {
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "amp" => Some('&'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => match name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok().and_then(::std::char::from_u32),
+                None => name.strip_prefix('#')
+                    .and_then(|decimal| decimal.parse().ok())
+                    .and_then(::std::char::from_u32),
+            },
+        };
+        c.ok_or_else(|| XmlError::Syntax(format!("unknown entity `&{};`", name), start))
+    }
+
+    /// Read text and CDATA sections up to the next tag
+    fn text(&mut self) -> Result<String, XmlError> {
+        let mut text = String::new();
+        loop {
+            if self.starts_with("<![CDATA[")? {
+                let start = self.location();
+                self.bump_str("<![CDATA[")?;
+                while !self.bump_str("]]>")? {
+                    match self.bump()? {
+                        Some(c) => text.push(c),
+                        None => return Err(XmlError::Syntax("unclosed CDATA section".into(), start)),
+                    }
+                }
+                continue;
+            }
+            match self.peek()? {
+                None | Some('<') => return Ok(text),
+                Some('&') => {
+                    self.bump()?;
+                    text.push(self.entity()?);
+                },
+                Some(c) => {
+                    text.push(c);
+                    self.bump()?;
+                },
+            }
+        }
+    }
+
+    fn attribute_value(&mut self) -> Result<String, XmlError> {
+        let quote = match self.peek()? {
+            Some(c @ '"') | Some(c @ '\'') => c,
+            _ => return Err(self.error("expected a quoted value")),
+        };
+        let start = self.location();
+        self.bump()?;
+        let mut value = String::new();
+        loop {
+            match self.bump()? {
+                Some(c) if c == quote => return Ok(value),
+                Some('&') => value.push(self.entity()?),
+                Some('<') => return Err(self.error("unexpected `<` in an attribute value")),
+                // whitespace in attribute values is turned into spaces, as the XML spec says
+                Some(c) if c.is_whitespace() && c.is_ascii() => value.push(' '),
+                Some(c) => value.push(c),
+                None => return Err(XmlError::Syntax("unclosed attribute value".into(), start)),
+            }
+        }
+    }
+
+    fn start_tag(&mut self) -> Result<(), XmlError> {
+        let name = self.name()?;
+        let mut attributes: Vec<(String, String)> = Vec::new();
+        let empty = loop {
+            self.skip_whitespace()?;
+            if self.bump_str("/>")? {
+                break true;
+            }
+            if self.bump_str(">")? {
+                break false;
+            }
+            let start = self.location();
+            let key = self.name()?;
+            self.skip_whitespace()?;
+            if !self.bump_str("=")? {
+                return Err(self.error("expected `=` after the attribute name"));
+            }
+            self.skip_whitespace()?;
+            let value = self.attribute_value()?;
+            if attributes.iter().any(|attribute| attribute.0 == key) {
+                return Err(XmlError::Syntax(format!("duplicate attribute `{}`", key), start));
+            }
+            attributes.push((key, value));
+        };
+
+        if let Some(parent) = self.stack.last_mut() {
+            parent.children = true;
+        }
+        self.flush_attributes();
+        if empty {
+            self.queue.push_back(ParseEvent::NodeStart(name, false));
+            self.push_attributes(attributes);
+            self.queue.push_back(ParseEvent::NodeEnd);
+        } else {
+            self.queue.push_back(ParseEvent::NodeStart(name.clone(), false));
+            self.stack.push(OpenElement { name, children: false, attributes });
+        }
+        Ok(())
+    }
+
+    fn push_attributes(&mut self, attributes: Vec<(String, String)>) {
+        for (key, value) in attributes {
+            self.queue.push_back(ParseEvent::Key(key, false));
+            self.queue.push_back(ParseEvent::Value(ParsedValue::Str(value)));
+        }
+    }
+
+    /// Write out the attributes of the innermost element, if they haven't been yet
+    fn flush_attributes(&mut self) {
+        if let Some(element) = self.stack.last_mut() {
+            let attributes = ::std::mem::take(&mut element.attributes);
+            self.push_attributes(attributes);
+        }
+    }
+
+    fn end_tag(&mut self) -> Result<(), XmlError> {
+        let start = self.location();
+        let name = self.name()?;
+        self.skip_whitespace()?;
+        if !self.bump_str(">")? {
+            return Err(self.error("expected `>`"));
+        }
+        self.flush_attributes();
+        match self.stack.pop() {
+            Some(ref open) if open.name == name => {
+                self.queue.push_back(ParseEvent::NodeEnd);
+                Ok(())
+            },
+            Some(open) => Err(XmlError::Syntax(format!("expected `</{}>`", open.name), start)),
+            None => Err(XmlError::Syntax(format!("unexpected `</{}>`", name), start)),
+        }
+    }
+
+    /// Read until there's at least one event in the queue
+    fn read(&mut self) -> Result<(), XmlError> {
+        while self.queue.is_empty() {
+            self.skip_whitespace()?;
+            if self.peek()?.is_none() {
+                if let Some(element) = self.stack.last() {
+                    return Err(self.error(format!("unclosed element `<{}>`", element.name)));
+                }
+                self.queue.push_back(ParseEvent::FileEnd);
+                self.finished = true;
+            } else if self.peek()? != Some('<') || self.starts_with("<![CDATA[")? {
+                let start = self.location();
+                let text = self.text()?;
+                let text = text.trim_end();
+                match self.stack.last() {
+                    None => return Err(XmlError::Syntax("text outside of an element".into(), start)),
+                    Some(element) if element.children => {
+                        let message = "text after a child element can't be read as an argument";
+                        return Err(XmlError::Syntax(message.into(), start));
+                    },
+                    Some(_) if text.is_empty() => {},
+                    Some(_) => {
+                        self.queue.push_back(ParseEvent::Argument(ParsedValue::Str(text.into())));
+                    },
+                }
+            } else if self.bump_str("<!--")? {
+                self.skip_past("-->", "unclosed comment")?;
+            } else if self.bump_str("<?")? {
+                self.skip_past("?>", "unclosed processing instruction")?;
+            } else if self.bump_str("<!")? {
+                self.skip_doctype()?;
+            } else if self.bump_str("</")? {
+                self.end_tag()?;
+            } else {
+                self.bump()?;
+                self.start_tag()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Iterator for XmlReader<R> {
+    type Item = Result<ParseEvent, XmlError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.started {
+            self.started = true;
+            return Some(Ok(ParseEvent::FileStart));
+        }
+        if self.queue.is_empty() && !self.finished {
+            if let Err(error) = self.read() {
+                // nothing more is read after an error
+                self.finished = true;
+                self.queue.clear();
+                return Some(Err(error));
+            }
+        }
+        self.queue.pop_front().map(Ok)
+    }
+}
+
+impl Document {
+    /// Import a document from XML, as it's read by an `XmlReader`.  The root element becomes
+    /// the document's only node, its child elements become subnodes, and its attributes
+    /// and text become string attributes and arguments.
+    ///
+    /// # Failures
+    /// Fails if the XML isn't well-formed, or has text after a child element.
+    ///
+    /// # Examples
+    /// ```
+    /// # use figtree::types::{Document, Value};
+    /// let xml = r#"<?xml version="1.0"?>
+    /// <project name="figtree">
+    ///     <dependency>serde</dependency>
+    ///     <dependency optional="true">tokio</dependency>
+    /// </project>"#;
+    /// let doc = Document::from_xml(xml).unwrap();
+    /// let project = &doc["project"];
+    /// assert_eq!(project.get("name"), Some(&Value::from("figtree")));
+    /// let dependencies: Vec<_> = project.children_named("dependency").collect();
+    /// assert_eq!(dependencies[1].arguments(), &[Value::from("tokio")][..]);
+    /// assert_eq!(dependencies[1].get("optional"), Some(&Value::from("true")));
+    /// ```
+    pub fn from_xml(text: &str) -> Result<Document, XmlError> {
+        let mut document = Document::new();
+        let mut stack: Vec<(String, Node)> = Vec::new();
+        let mut key = None;
+        for event in XmlReader::new(text.as_bytes()) {
+            match event? {
+                ParseEvent::NodeStart(name, _) => stack.push((name, Node::new())),
+                ParseEvent::Argument(value) => {
+                    if let Some(&mut (_, ref mut node)) = stack.last_mut() {
+                        node.push_argument(Value::from_parsed_value(value));
+                    }
+                },
+                ParseEvent::Key(name, _) => key = Some(name),
+                ParseEvent::Value(value) => {
+                    if let Some(&mut (_, ref mut node)) = stack.last_mut() {
+                        let key = key.take().unwrap_or_default();
+                        node.insert_attr(key, Value::from_parsed_value(value));
+                    }
+                },
+                ParseEvent::NodeEnd => if let Some((name, node)) = stack.pop() {
+                    match stack.last_mut() {
+                        Some(&mut (_, ref mut parent)) => parent.append_node(name, node),
+                        None => document.append_node(name, node),
+                    };
+                },
+                _ => {},
+            }
+        }
+        Ok(document)
+    }
+
+    /// Write the document as XML, with its only node as the root element.  See `XmlWriter`
+    /// for how nodes are written.
+    ///
+    /// # Failures
+    /// Fails if the document doesn't have exactly one node (see `to_xml_with_root` for
+    /// documents with more), or if a name or key isn't a valid XML name.
+    ///
+    /// # Examples
+    /// ```
+    /// # use figtree::Figtree;
+    /// let doc = Figtree::from_string("server { host = 'a & b', tls {} }").parse().unwrap();
+    /// assert_eq!(doc.to_xml().unwrap(), concat!(
+    ///     "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+    ///     "<server host=\"a &amp; b\">\n",
+    ///     "    <tls/>\n",
+    ///     "</server>\n",
+    /// ));
+    /// ```
+    pub fn to_xml(&self) -> Result<String, XmlError> {
+        let count = self.children().count();
+        if count != 1 {
+            return Err(XmlError::Roots(count));
+        }
+        self.write_xml(XmlWriter::new(Vec::new()))
+    }
+
+    /// Write the document as XML, with its nodes inside a root element with the given name
+    ///
+    /// # Failures
+    /// Fails if a name or key isn't a valid XML name.
+    pub fn to_xml_with_root(&self, root: &str) -> Result<String, XmlError> {
+        self.write_xml(XmlWriter::new(Vec::new()).root(root))
+    }
+
+    fn write_xml(&self, mut writer: XmlWriter<Vec<u8>>) -> Result<String, XmlError> {
+        writer.write_event(ParseEvent::FileStart)?;
+        for node in self.children() {
+            writer.write_node(node.name(), node)?;
+        }
+        writer.write_event(ParseEvent::FileEnd)?;
+        Ok(String::from_utf8(writer.into_inner()).expect("the XML is written from strings"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::figtree::Figtree;
+    use super::super::parser::{Parser, ParserOptions};
+    use super::super::writer::Writer;
+    use super::*;
+
+    fn events(input: &str) -> Result<Vec<ParseEvent>, XmlError> {
+        XmlReader::new(input.as_bytes()).collect()
+    }
+
+    fn error(input: &str) -> String {
+        events(input).unwrap_err().to_string()
+    }
+
+    fn write(input: &str) -> Result<String, XmlError> {
+        let mut writer = XmlWriter::new(Vec::new());
+        let options = ParserOptions::new().node_arguments(true).repeated_nodes(true);
+        let lexer = super::super::lexer::Lexer::lex(io::Cursor::new(input.to_string()));
+        writer.write_events(Parser::with_options(lexer, options).map(|result| result.unwrap().0))?;
+        Ok(String::from_utf8(writer.into_inner()).unwrap())
+    }
+
+    fn string(text: &str) -> ParsedValue {
+        ParsedValue::Str(text.into())
+    }
+
+    #[test]
+    fn reads_events() {
+        let input = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <!DOCTYPE config [ <!ELEMENT config ANY> ]>
+            <!-- a comment -->
+            <config xmlns:x="urn:x">
+                text &lt;here&gt; <![CDATA[<raw> & ]]> &#x263A;
+                <x:item key = 'a &amp; b' multi="one
+                    two&#10;three" ></x:item>
+                <empty/>
+            </config>
+        "#;
+        assert_eq!(events(input).unwrap(), vec![
+            ParseEvent::FileStart,
+            ParseEvent::NodeStart("config".into(), false),
+            ParseEvent::Argument(string("text <here> <raw> &  \u{263a}")),
+            ParseEvent::Key("xmlns:x".into(), false),
+            ParseEvent::Value(string("urn:x")),
+            ParseEvent::NodeStart("x:item".into(), false),
+            ParseEvent::Key("key".into(), false),
+            ParseEvent::Value(string("a & b")),
+            ParseEvent::Key("multi".into(), false),
+            ParseEvent::Value(string("one                     two\nthree")),
+            ParseEvent::NodeEnd,
+            ParseEvent::NodeStart("empty".into(), false),
+            ParseEvent::NodeEnd,
+            ParseEvent::NodeEnd,
+            ParseEvent::FileEnd,
+        ]);
+        assert_eq!(events("").unwrap(), vec![ParseEvent::FileStart, ParseEvent::FileEnd]);
+        assert_eq!(events("<a/><b/>").unwrap().len(), 6);
+    }
+
+    #[test]
+    fn reports_errors() {
+        assert_eq!(error("<a>"), "unclosed element `<a>` (line 1, column 4)");
+        assert_eq!(error("<a></b>"), "expected `</a>` (line 1, column 6)");
+        assert_eq!(error("</a>"), "unexpected `</a>` (line 1, column 3)");
+        assert_eq!(error("<a b='1' b='2'/>"), "duplicate attribute `b` (line 1, column 10)");
+        assert_eq!(error("<a b=1/>"), "expected a quoted value (line 1, column 6)");
+        assert_eq!(error("<a>&nbsp;</a>"), "unknown entity `&nbsp;` (line 1, column 5)");
+        assert_eq!(error("<1a/>"), "invalid name `1a` (line 1, column 2)");
+        assert_eq!(error("text"), "text outside of an element (line 1, column 1)");
+        assert_eq!(error("<a>\n  <b/>\n  text\n</a>"),
+                   "text after a child element can't be read as an argument (line 3, column 3)");
+        assert_eq!(error("<a><!-- </a>"), "unclosed comment (line 1, column 8)");
+
+        let mut reader = XmlReader::new("<a><b></a><c/>".as_bytes());
+        assert_eq!(reader.by_ref().filter(Result::is_err).count(), 1);
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn writes_events() {
+        let input = "
+            server 'web' 8080 {
+                name = 'a \"quoted\" <name>',
+                ports = [80, 443],
+                env = { home = '/root' },
+                timeout = 5s,
+                upstream { host = 'a' }
+                upstream 'b' {}
+            }
+        ";
+        assert_eq!(write(input).unwrap(), concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<server name=\"a &quot;quoted&quot; &lt;name&gt;\" ports=\"[80, 443]\" ",
+            "env=\"{ home: &quot;/root&quot; }\" timeout=\"5s\">web 8080\n",
+            "    <upstream host=\"a\"/>\n",
+            "    <upstream>b</upstream>\n",
+            "</server>\n",
+        ));
+
+        let error = write("a { b {} c = 1 }").unwrap_err();
+        assert_eq!(error.to_string(), "unexpected event Key(\"c\", false)");
+        let error = write("'my node' {}").unwrap_err();
+        assert_eq!(error.to_string(), "\"my node\" isn't a valid XML name");
+
+        let mut writer = XmlWriter::new(Vec::new());
+        assert!(writer.write_event(ParseEvent::NodeEnd).is_err());
+        writer.write_event(ParseEvent::FileStart).unwrap();
+        assert!(writer.write_event(ParseEvent::NodeEnd).is_err());
+        assert!(writer.write_event(ParseEvent::Value(string("a"))).is_err());
+        assert!(writer.write_event(ParseEvent::FileEnd).is_ok());
+        assert!(writer.write_event(ParseEvent::FileStart).is_err());
+    }
+
+    #[test]
+    fn converts_xml_to_figtree() {
+        let input = "<config><server port='80'>web</server><server port='81'/></config>";
+        let mut writer = Writer::new(Vec::new());
+        for event in XmlReader::new(input.as_bytes()) {
+            writer.write_event(event.unwrap()).unwrap();
+        }
+        let output = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        let options = ParserOptions::new().node_arguments(true).repeated_nodes(true);
+        let doc = Figtree::from_string(output).with_options(options).parse().unwrap();
+        assert_eq!(doc, Document::from_xml(input).unwrap());
+    }
+
+    #[test]
+    fn converts_documents() {
+        let input = "
+            project 'figtree' {
+                version = '1.0'
+                dependency 'serde' { optional = 'true' }
+                dependency 'tokio' {}
+                authors { author 'Jonathan' {} }
+            }
+        ";
+        let options = ParserOptions::new().node_arguments(true).repeated_nodes(true);
+        let doc = Figtree::from_string(input).with_options(options).parse().unwrap();
+        let xml = doc.to_xml().unwrap();
+        assert_eq!(xml, concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<project version=\"1.0\">figtree\n",
+            "    <dependency optional=\"true\">serde</dependency>\n",
+            "    <dependency>tokio</dependency>\n",
+            "    <authors>\n",
+            "        <author>Jonathan</author>\n",
+            "    </authors>\n",
+            "</project>\n",
+        ));
+        assert_eq!(Document::from_xml(&xml).unwrap(), doc);
+
+        let doc = Figtree::from_string("a {} b { c = 1 }").parse().unwrap();
+        assert_eq!(doc.to_xml().unwrap_err().to_string(),
+                   "XML has exactly one root element, but the document has 2 nodes");
+        let xml = doc.to_xml_with_root("root").unwrap();
+        assert_eq!(xml, concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<root>\n",
+            "    <a/>\n",
+            "    <b c=\"1\"/>\n",
+            "</root>\n",
+        ));
+        let imported = Document::from_xml(&xml).unwrap();
+        assert_eq!(imported["root"].child("b").and_then(|b| b.get("c")), Some(&Value::from("1")));
+    }
+}
@@ -0,0 +1,1381 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fmt::Write;
+
+use super::datetime::DateTime;
+use super::position::Position;
+use super::types::{Document, Node, Value};
+use super::utils::{encode_base64, Base64Decoder};
+
+/// The key that a node's arguments are written under, the same as the `ARGUMENTS_KEY` that
+/// serde uses
+const ARGUMENTS_KEY: &str = "$arguments";
+
+/// A YAML node, as it's read or about to be written
+#[derive(Debug, Clone)]
+enum Yaml {
+    /// A plain scalar that hasn't been resolved into a value yet, as its tag can change
+    /// what it's resolved as
+    Plain(String),
+    Scalar(Value),
+    Seq(Vec<Yaml>),
+    Map(Vec<(String, Yaml)>),
+}
+
+/// The error produced when YAML can't be imported as a `Document`
+#[derive(Debug, PartialEq, Clone)]
+pub struct YamlError {
+    /// What the problem is
+    pub message: String,
+    /// Where the problem is, or `None` if the YAML is valid but can't be imported as a
+    /// document
+    pub position: Option<Position>,
+}
+
+impl YamlError {
+    fn new<S: Into<String>>(message: S, position: Position) -> Self {
+        YamlError { message: message.into(), position: Some(position) }
+    }
+}
+
+impl fmt::Display for YamlError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)?;
+        if let Some(ref position) = self.position {
+            write!(f, " (line {}, column {})", position.line + 1, position.pos + 1)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for YamlError {}
+
+/// Resolve a plain scalar into a value, with the YAML 1.2 core schema.  Timestamps are
+/// also resolved as datetimes, as in YAML 1.1, if they are in the format figtree uses.
+fn resolve(text: &str) -> Value {
+    match text {
+        "" | "~" | "null" | "Null" | "NULL" => return Value::Null,
+        "true" | "True" | "TRUE" => return Value::Bool(true),
+        "false" | "False" | "FALSE" => return Value::Bool(false),
+        ".inf" | ".Inf" | ".INF" | "+.inf" | "+.Inf" | "+.INF" => {
+            return Value::Float(f64::INFINITY)
+        },
+        "-.inf" | "-.Inf" | "-.INF" => return Value::Float(f64::NEG_INFINITY),
+        ".nan" | ".NaN" | ".NAN" => return Value::Float(f64::NAN),
+        _ => {},
+    }
+    if let Some(int) = resolve_int(text) {
+        return Value::Int(int);
+    }
+    if is_float(text) {
+        if let Ok(float) = text.parse() {
+            return Value::Float(float);
+        }
+    }
+    if text.starts_with(|c: char| c.is_ascii_digit()) {
+        if let Ok(datetime) = text.parse::<DateTime>() {
+            return Value::DateTime(datetime);
+        }
+    }
+    Value::Str(text.to_string())
+}
+
+fn resolve_int(text: &str) -> Option<i64> {
+    let (negative, digits) = match text.as_bytes().first() {
+        Some(b'-') => (true, &text[1..]),
+        Some(b'+') => (false, &text[1..]),
+        _ => (false, text),
+    };
+    let (digits, radix) = if let Some(hex) = digits.strip_prefix("0x") {
+        (hex, 16)
+    } else if let Some(octal) = digits.strip_prefix("0o") {
+        (octal, 8)
+    } else {
+        (digits, 10)
+    };
+    if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+        return None;
+    }
+    let int = i64::from_str_radix(digits, radix).ok()?;
+    Some(if negative { -int } else { int })
+}
+
+/// Check if a plain scalar matches the core schema's pattern for floats
+fn is_float(text: &str) -> bool {
+    let text = text.trim_start_matches(['-', '+']);
+    let (mantissa, exponent) = match text.find(['e', 'E']) {
+        Some(index) => (&text[..index], Some(&text[index + 1..])),
+        None => (text, None),
+    };
+    let (whole, fraction) = match mantissa.find('.') {
+        Some(index) => (&mantissa[..index], Some(&mantissa[index + 1..])),
+        None => (mantissa, None),
+    };
+    let digits = |digits: &str| digits.chars().all(|c| c.is_ascii_digit());
+    let mantissa = match fraction {
+        Some(fraction) => {
+            digits(whole) && digits(fraction) && !(whole.is_empty() && fraction.is_empty())
+        },
+        None => !whole.is_empty() && digits(whole),
+    };
+    let exponent = exponent.is_none_or(|exponent| {
+        let exponent = exponent.trim_start_matches(['-', '+']);
+        !exponent.is_empty() && digits(exponent)
+    });
+    mantissa && exponent
+}
+
+/// The name of the kind of a YAML node, for errors
+fn kind(yaml: &Yaml) -> &'static str {
+    match *yaml {
+        Yaml::Plain(_) | Yaml::Scalar(_) => "a scalar",
+        Yaml::Seq(_) => "a sequence",
+        Yaml::Map(_) => "a mapping",
+    }
+}
+
+/// A position in the input that can be gone back to
+#[derive(Clone, Copy)]
+struct Mark {
+    pos: usize,
+    line: usize,
+    line_start: usize,
+}
+
+/// Reads a single YAML document, with block and flow collections, plain, quoted and block
+/// scalars, anchors, aliases and merge keys, and the `!!str` and `!!binary` tags.
+/// Complex keys and multi-line plain scalars aren't supported.
+struct Parser {
+    chars: Vec<char>,
+    mark: Mark,
+    anchors: HashMap<String, Yaml>,
+}
+
+impl Parser {
+    fn new(input: &str) -> Self {
+        Parser {
+            chars: input.replace("\r\n", "\n").chars().collect(),
+            mark: Mark { pos: 0, line: 0, line_start: 0 },
+            anchors: HashMap::new(),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.peek_at(0)
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.mark.pos + offset).cloned()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.mark.pos += 1;
+        if c == '\n' {
+            self.mark.line += 1;
+            self.mark.line_start = self.mark.pos;
+        }
+        Some(c)
+    }
+
+    fn column(&self) -> usize {
+        self.mark.pos - self.mark.line_start
+    }
+
+    fn position(&self) -> Position {
+        Position::at(self.mark.line, self.column())
+    }
+
+    fn error<T, S: Into<String>>(&self, message: S) -> Result<T, YamlError> {
+        Err(YamlError::new(message, self.position()))
+    }
+
+    fn unexpected<T>(&self) -> Result<T, YamlError> {
+        match self.peek() {
+            Some(c) => self.error(format!("unexpected `{}`", c.escape_default())),
+            None => self.error("unexpected end of input"),
+        }
+    }
+
+    /// Check if the character at `offset` separates an indicator from what follows it
+    fn separated(&self, offset: usize) -> bool {
+        matches!(self.peek_at(offset), None | Some(' ') | Some('\t') | Some('\n'))
+    }
+
+    /// Check if a sequence item starts here
+    fn at_item(&self) -> bool {
+        self.peek() == Some('-') && self.separated(1)
+    }
+
+    /// Check if a `---` or `...` marker starts here
+    fn at_marker(&self) -> bool {
+        self.column() == 0 && ['-', '.'].iter().any(|&c| {
+            (0..3).all(|offset| self.peek_at(offset) == Some(c)) && self.separated(3)
+        })
+    }
+
+    /// Check if a block collection indented by `indent` has ended
+    fn at_block_end(&self, indent: usize) -> bool {
+        self.peek().is_none() || self.column() < indent || self.at_marker()
+    }
+
+    fn at_line_end(&self) -> bool {
+        matches!(self.peek(), None | Some('\n') | Some('#'))
+    }
+
+    fn skip_inline_space(&mut self) {
+        while matches!(self.peek(), Some(' ') | Some('\t')) {
+            self.bump();
+        }
+    }
+
+    fn skip_comment(&mut self) {
+        if self.peek() == Some('#') {
+            while !matches!(self.peek(), None | Some('\n')) {
+                self.bump();
+            }
+        }
+    }
+
+    /// Skip whitespace, comments and line breaks, up to the next thing on a line
+    fn skip_blank_lines(&mut self) {
+        loop {
+            self.skip_inline_space();
+            self.skip_comment();
+            if self.bump_if('\n').is_none() {
+                return;
+            }
+        }
+    }
+
+    fn bump_if(&mut self, c: char) -> Option<char> {
+        if self.peek() == Some(c) { self.bump() } else { None }
+    }
+
+    /// Check that nothing but a comment follows on the line
+    fn end_line(&mut self) -> Result<(), YamlError> {
+        self.skip_inline_space();
+        if self.at_line_end() { Ok(()) } else { self.unexpected() }
+    }
+
+    fn document(&mut self) -> Result<Yaml, YamlError> {
+        self.skip_blank_lines();
+        while self.peek() == Some('%') && self.column() == 0 {
+            while !matches!(self.peek(), None | Some('\n')) {
+                self.bump();
+            }
+            self.skip_blank_lines();
+        }
+        if self.at_marker() && self.peek() == Some('-') {
+            self.mark.pos += 3;
+        }
+        let value = self.value(None, false, true)?;
+        self.skip_blank_lines();
+        if self.at_marker() && self.peek() == Some('.') {
+            self.mark.pos += 3;
+            self.end_line()?;
+            self.skip_blank_lines();
+        }
+        match self.peek() {
+            None => Ok(value),
+            Some(_) if self.at_marker() => self.error("only a single document can be imported"),
+            Some(_) => self.unexpected(),
+        }
+    }
+
+    /// Read the value after a `:` or `-` indicator, or at the start of the document, along
+    /// with its anchor and tag.  `parent` is the indentation of the mapping or sequence it
+    /// is in, and `compact` says whether a sequence or mapping can start on the same line.
+    fn value(&mut self, parent: Option<usize>, in_map: bool, compact: bool)
+             -> Result<Yaml, YamlError> {
+        self.skip_inline_space();
+        let start = self.position();
+        let (mut anchor, mut tag) = (None, None);
+        loop {
+            match self.peek() {
+                Some('&') => {
+                    self.bump();
+                    anchor = Some(self.name());
+                },
+                Some('!') => tag = Some(self.name()),
+                _ => break,
+            }
+            self.skip_inline_space();
+        }
+        let value = if self.peek() == Some('*') {
+            let value = self.alias()?;
+            self.end_line()?;
+            value
+        } else if self.at_line_end() {
+            self.block(parent, in_map)?
+        } else {
+            self.block_at(parent, compact)?
+        };
+        let value = match tag {
+            Some(tag) => apply_tag(&tag, value).map_err(|message| YamlError::new(message, start))?,
+            None => match value {
+                Yaml::Plain(text) => Yaml::Scalar(resolve(&text)),
+                value => value,
+            },
+        };
+        if let Some(anchor) = anchor {
+            self.anchors.insert(anchor, value.clone());
+        }
+        Ok(value)
+    }
+
+    /// Read the name of an anchor, alias or tag
+    fn name(&mut self) -> String {
+        let mut name = String::new();
+        while let Some(c) = self.peek() {
+            if c.is_whitespace() || ",[]{}".contains(c) {
+                break;
+            }
+            name.push(c);
+            self.bump();
+        }
+        name
+    }
+
+    fn alias(&mut self) -> Result<Yaml, YamlError> {
+        let start = self.position();
+        self.bump();
+        let name = self.name();
+        match self.anchors.get(&name) {
+            Some(value) => Ok(value.clone()),
+            None => Err(YamlError::new(format!("unknown alias `{}`", name), start)),
+        }
+    }
+
+    /// Read a value that starts on a later line than its key or `-` indicator, if it is
+    /// indented further than `parent`.  A sequence can be indented as far as the key of a
+    /// mapping it's the value of.
+    fn block(&mut self, parent: Option<usize>, in_map: bool) -> Result<Yaml, YamlError> {
+        self.skip_blank_lines();
+        if self.peek().is_none() || self.at_marker() {
+            return Ok(Yaml::Scalar(Value::Null));
+        }
+        let indent = self.column();
+        match parent {
+            Some(parent) if indent < parent || (indent == parent && !in_map) => {
+                Ok(Yaml::Scalar(Value::Null))
+            },
+            Some(parent) if indent == parent => {
+                if self.at_item() { self.sequence(indent) } else { Ok(Yaml::Scalar(Value::Null)) }
+            },
+            _ => self.block_at(parent, true),
+        }
+    }
+
+    /// Read a value that starts here
+    fn block_at(&mut self, parent: Option<usize>, compact: bool) -> Result<Yaml, YamlError> {
+        let indent = self.column();
+        match self.peek() {
+            Some('-') if self.separated(1) => {
+                if compact { self.sequence(indent) } else { self.unexpected() }
+            },
+            Some('?') if self.separated(1) => self.error("complex keys aren't supported"),
+            Some('[') | Some('{') => {
+                let value = self.flow()?;
+                self.end_line()?;
+                Ok(value)
+            },
+            Some('|') | Some('>') => self.block_scalar(parent),
+            _ => {
+                let start = self.position();
+                let (text, quoted) = self.scalar()?;
+                self.skip_inline_space();
+                if self.peek() == Some(':') && self.separated(1) {
+                    if !compact {
+                        return Err(YamlError::new("a mapping can't start on the same line as a key",
+                                                  start));
+                    }
+                    return self.mapping(indent, text, start);
+                }
+                self.end_line()?;
+                Ok(if quoted { Yaml::Scalar(Value::Str(text)) } else { Yaml::Plain(text) })
+            },
+        }
+    }
+
+    /// Read a block mapping indented by `indent`, whose first key has just been read
+    fn mapping(&mut self, indent: usize, mut key: String, mut start: Position)
+               -> Result<Yaml, YamlError> {
+        let mut entries: Vec<(String, Yaml)> = Vec::new();
+        let mut merges = Vec::new();
+        loop {
+            self.bump();
+            let value = self.value(Some(indent), true, false)?;
+            if key == "<<" {
+                merges.push((value, start));
+            } else if entries.iter().any(|entry| entry.0 == key) {
+                return Err(YamlError::new(format!("duplicate key `{}`", key), start));
+            } else {
+                entries.push((key, value));
+            }
+
+            self.skip_blank_lines();
+            if self.at_block_end(indent) {
+                break;
+            }
+            if self.column() > indent {
+                return self.error("unexpected indentation");
+            }
+            start = self.position();
+            match self.peek() {
+                Some('-') if self.separated(1) => return self.error("expected a key"),
+                Some('?') if self.separated(1) => return self.error("complex keys aren't supported"),
+                _ => key = self.scalar()?.0,
+            }
+            self.skip_inline_space();
+            if self.peek() != Some(':') || !self.separated(1) {
+                return self.error("expected `:` after the key");
+            }
+        }
+        for (merge, start) in merges {
+            merge_into(&mut entries, merge).map_err(|message| YamlError::new(message, start))?;
+        }
+        Ok(Yaml::Map(entries))
+    }
+
+    /// Read a block sequence indented by `indent`, starting at its first `-`
+    fn sequence(&mut self, indent: usize) -> Result<Yaml, YamlError> {
+        let mut items = Vec::new();
+        loop {
+            self.bump();
+            items.push(self.value(Some(indent), false, true)?);
+            self.skip_blank_lines();
+            if self.at_block_end(indent) {
+                break;
+            }
+            if self.column() > indent {
+                return self.error("unexpected indentation");
+            }
+            if !self.at_item() {
+                break;
+            }
+        }
+        Ok(Yaml::Seq(items))
+    }
+
+    /// Read a quoted or plain scalar, returning its text and whether it was quoted
+    fn scalar(&mut self) -> Result<(String, bool), YamlError> {
+        match self.peek() {
+            Some('"') => self.double_quoted().map(|text| (text, true)),
+            Some('\'') => self.single_quoted().map(|text| (text, true)),
+            _ => self.plain(false).map(|text| (text, false)),
+        }
+    }
+
+    /// Read a plain scalar, which ends at the end of the line, a `: ` or a comment, or in
+    /// a flow collection, at any of `,[]{}`
+    fn plain(&mut self, flow: bool) -> Result<String, YamlError> {
+        match self.peek() {
+            Some(c) if "#,[]{}&*!|>'\"%@`".contains(c) => return self.unexpected(),
+            Some('-') | Some('?') | Some(':') if self.separated(1) => return self.unexpected(),
+            None | Some('\n') => return self.unexpected(),
+            _ => {},
+        }
+        let mut text = String::new();
+        while let Some(c) = self.peek() {
+            match c {
+                '\n' => break,
+                ':' if self.separated(1) => break,
+                ':' if flow && self.peek_at(1).is_some_and(|c| ",[]{}".contains(c)) => break,
+                '#' if text.ends_with([' ', '\t']) => break,
+                ',' | '[' | ']' | '{' | '}' if flow => break,
+                _ => text.push(c),
+            }
+            self.bump();
+        }
+        Ok(text.trim_end().to_string())
+    }
+
+    fn single_quoted(&mut self) -> Result<String, YamlError> {
+        let start = self.position();
+        self.bump();
+        let mut text = String::new();
+        loop {
+            match self.bump() {
+                None => return Err(YamlError::new("unclosed string", start)),
+                Some('\'') if self.peek() == Some('\'') => {
+                    self.bump();
+                    text.push('\'');
+                },
+                Some('\'') => return Ok(text),
+                Some('\n') => self.fold(&mut text),
+                Some(c) => text.push(c),
+            }
+        }
+    }
+
+    fn double_quoted(&mut self) -> Result<String, YamlError> {
+        let start = self.position();
+        self.bump();
+        let mut text = String::new();
+        loop {
+            let escape = self.position();
+            let c = match self.bump() {
+                None => return Err(YamlError::new("unclosed string", start)),
+                Some('"') => return Ok(text),
+                Some('\n') => {
+                    self.fold(&mut text);
+                    continue;
+                },
+                Some('\\') => match self.bump() {
+                    Some('0') => '\0',
+                    Some('a') => '\x07',
+                    Some('b') => '\x08',
+                    Some('t') | Some('\t') => '\t',
+                    Some('n') => '\n',
+                    Some('v') => '\x0b',
+                    Some('f') => '\x0c',
+                    Some('r') => '\r',
+                    Some('e') => '\x1b',
+                    Some(' ') => ' ',
+                    Some('"') => '"',
+                    Some('/') => '/',
+                    Some('\\') => '\\',
+                    Some('N') => '\u{85}',
+                    Some('_') => '\u{a0}',
+                    Some('L') => '\u{2028}',
+                    Some('P') => '\u{2029}',
+                    Some('x') => self.hex(2, escape)?,
+                    Some('u') => self.hex(4, escape)?,
+                    Some('U') => self.hex(8, escape)?,
+                    Some('\n') => {
+                        self.skip_inline_space();
+                        continue;
+                    },
+                    _ => return Err(YamlError::new("invalid escape", escape)),
+                },
+                Some(c) => c,
+            };
+            text.push(c);
+        }
+    }
+
+    fn hex(&mut self, digits: usize, escape: Position) -> Result<char, YamlError> {
+        let mut code = 0;
+        for _ in 0..digits {
+            match self.peek().and_then(|c| c.to_digit(16)) {
+                Some(digit) => code = code * 16 + digit,
+                None => return Err(YamlError::new("invalid escape", escape)),
+            }
+            self.bump();
+        }
+        ::std::char::from_u32(code).ok_or_else(|| YamlError::new("invalid escape", escape))
+    }
+
+    /// Fold a line break in a quoted scalar.  Whitespace around the break is dropped, and
+    /// the break becomes a space, or if it's followed by empty lines, a line break for each
+    /// of them.
+    fn fold(&mut self, text: &mut String) {
+        let len = text.trim_end_matches([' ', '\t']).len();
+        text.truncate(len);
+        let mut breaks = 0;
+        loop {
+            self.skip_inline_space();
+            if self.bump_if('\n').is_none() {
+                break;
+            }
+            breaks += 1;
+        }
+        if breaks == 0 {
+            text.push(' ');
+        }
+        for _ in 0..breaks {
+            text.push('\n');
+        }
+    }
+
+    /// Read a literal (`|`) or folded (`>`) block scalar, whose lines are indented
+    /// further than `parent`
+    fn block_scalar(&mut self, parent: Option<usize>) -> Result<Yaml, YamlError> {
+        let folded = self.bump() == Some('>');
+        // `Some(false)` strips the final line break, and `Some(true)` keeps trailing ones
+        let mut chomp = None;
+        let mut indent = None;
+        loop {
+            match self.peek() {
+                Some('-') if chomp.is_none() => chomp = Some(false),
+                Some('+') if chomp.is_none() => chomp = Some(true),
+                Some(c @ '1'..='9') if indent.is_none() => {
+                    indent = Some(parent.unwrap_or(0) + c as usize - '0' as usize);
+                },
+                _ => break,
+            }
+            self.bump();
+        }
+        self.end_line()?;
+        self.skip_comment();
+        self.bump_if('\n');
+
+        let mut lines = Vec::new();
+        loop {
+            let mark = self.mark;
+            let mut spaces = 0;
+            while self.peek() == Some(' ') && indent.is_none_or(|indent| spaces < indent) {
+                self.bump();
+                spaces += 1;
+            }
+            match self.peek() {
+                None => break,
+                Some('\n') => {
+                    self.bump();
+                    lines.push(String::new());
+                    continue;
+                },
+                Some(_) => {},
+            }
+            let ends = match indent {
+                Some(indent) => spaces < indent,
+                None => parent.is_some_and(|parent| spaces <= parent),
+            };
+            if ends || (spaces == 0 && self.at_marker()) {
+                self.mark = mark;
+                break;
+            }
+            indent = Some(spaces);
+            let mut line = String::new();
+            while let Some(c) = self.peek() {
+                if c == '\n' {
+                    break;
+                }
+                line.push(c);
+                self.bump();
+            }
+            lines.push(line);
+            self.bump_if('\n');
+        }
+
+        let trailing = lines.iter().rev().take_while(|line| line.is_empty()).count();
+        let body = &lines[..lines.len() - trailing];
+        let mut text = if folded { fold_lines(body) } else { body.join("\n") };
+        if chomp != Some(false) && !body.is_empty() {
+            text.push('\n');
+        }
+        if chomp == Some(true) {
+            text.extend((0..trailing).map(|_| '\n'));
+        }
+        Ok(Yaml::Scalar(Value::Str(text)))
+    }
+
+    /// Read a flow sequence (`[a, b]`) or mapping (`{a: 1}`), which can span several lines
+    fn flow(&mut self) -> Result<Yaml, YamlError> {
+        let start = self.position();
+        let close = if self.bump() == Some('[') { ']' } else { '}' };
+        let mut items = Vec::new();
+        let mut entries: Vec<(String, Yaml)> = Vec::new();
+        loop {
+            self.skip_blank_lines();
+            match self.peek() {
+                None => return Err(YamlError::new("unclosed flow collection", start)),
+                Some(c) if c == close => break,
+                _ => {},
+            }
+            if close == ']' {
+                items.push(self.flow_node()?);
+            } else {
+                let key_start = self.position();
+                let key = self.flow_scalar()?.0;
+                self.skip_blank_lines();
+                let value = if self.bump_if(':').is_some() {
+                    self.skip_blank_lines();
+                    match self.peek() {
+                        Some(',') | Some('}') => Yaml::Scalar(Value::Null),
+                        _ => self.flow_node()?,
+                    }
+                } else {
+                    Yaml::Scalar(Value::Null)
+                };
+                if entries.iter().any(|entry| entry.0 == key) {
+                    return Err(YamlError::new(format!("duplicate key `{}`", key), key_start));
+                }
+                entries.push((key, value));
+            }
+            self.skip_blank_lines();
+            match self.peek() {
+                Some(',') => {
+                    self.bump();
+                },
+                Some(c) if c == close => {},
+                None => return Err(YamlError::new("unclosed flow collection", start)),
+                Some(_) => return self.unexpected(),
+            }
+        }
+        self.bump();
+        Ok(if close == ']' { Yaml::Seq(items) } else { Yaml::Map(entries) })
+    }
+
+    fn flow_node(&mut self) -> Result<Yaml, YamlError> {
+        match self.peek() {
+            Some('[') | Some('{') => self.flow(),
+            Some('*') => self.alias(),
+            _ => Ok(match self.flow_scalar()? {
+                (text, true) => Yaml::Scalar(Value::Str(text)),
+                (text, false) => Yaml::Scalar(resolve(&text)),
+            }),
+        }
+    }
+
+    fn flow_scalar(&mut self) -> Result<(String, bool), YamlError> {
+        match self.peek() {
+            Some('"') => self.double_quoted().map(|text| (text, true)),
+            Some('\'') => self.single_quoted().map(|text| (text, true)),
+            _ => self.plain(true).map(|text| (text, false)),
+        }
+    }
+}
+
+/// Apply a tag to a value that's just been read
+fn apply_tag(tag: &str, value: Yaml) -> Result<Yaml, String> {
+    let text = match value {
+        Yaml::Plain(ref text) | Yaml::Scalar(Value::Str(ref text)) => Some(text.clone()),
+        _ => None,
+    };
+    match (tag, text) {
+        ("!!str", Some(text)) => Ok(Yaml::Scalar(Value::Str(text))),
+        ("!!binary", Some(text)) => {
+            let mut decoder = Base64Decoder::new();
+            for c in text.chars().filter(|c| !c.is_whitespace()) {
+                if !decoder.push(c) {
+                    return Err(format!("invalid base64 character `{}`", c.escape_default()));
+                }
+            }
+            decoder.finish().map(|bytes| Yaml::Scalar(Value::Bytes(bytes)))
+                .ok_or_else(|| "truncated base64".to_string())
+        },
+        ("!!map", _) | ("!!seq", _) | ("!!int", _) | ("!!float", _) | ("!!bool", _)
+                | ("!!null", _) => {
+            let value = match value {
+                Yaml::Plain(text) => Yaml::Scalar(resolve(&text)),
+                value => value,
+            };
+            let matches = matches!((tag, &value),
+                ("!!map", &Yaml::Map(_)) | ("!!seq", &Yaml::Seq(_))
+                    | ("!!int", &Yaml::Scalar(Value::Int(_)))
+                    | ("!!float", &Yaml::Scalar(Value::Float(_)))
+                    | ("!!bool", &Yaml::Scalar(Value::Bool(_)))
+                    | ("!!null", &Yaml::Scalar(Value::Null)));
+            if matches { Ok(value) } else { Err(format!("value doesn't match its tag `{}`", tag)) }
+        },
+        _ => Err(format!("unsupported tag `{}`", tag)),
+    }
+}
+
+/// Merge the mappings of a `<<` key into a mapping, without replacing any of the keys it
+/// already has
+fn merge_into(entries: &mut Vec<(String, Yaml)>, merge: Yaml) -> Result<(), String> {
+    let mappings = match merge {
+        Yaml::Map(mapping) => vec![mapping],
+        Yaml::Seq(items) => {
+            let mut mappings = Vec::new();
+            for item in items {
+                match item {
+                    Yaml::Map(mapping) => mappings.push(mapping),
+                    item => return Err(format!("can't merge {} into a mapping", kind(&item))),
+                }
+            }
+            mappings
+        },
+        merge => return Err(format!("can't merge {} into a mapping", kind(&merge))),
+    };
+    for (key, value) in mappings.into_iter().flatten() {
+        if !entries.iter().any(|entry| entry.0 == key) {
+            entries.push((key, value));
+        }
+    }
+    Ok(())
+}
+
+/// Join the lines of a folded block scalar with spaces.  An empty line becomes a line break
+/// instead, and more indented lines are kept on lines of their own.
+fn fold_lines(lines: &[String]) -> String {
+    let normal = |line: &str| !line.is_empty() && !line.starts_with([' ', '\t']);
+    let mut text = String::new();
+    for (index, line) in lines.iter().enumerate() {
+        if index > 0 {
+            let previous = &lines[index - 1];
+            if normal(previous) && normal(line) {
+                text.push(' ');
+            } else if !(normal(previous) && line.is_empty()) {
+                text.push('\n');
+            }
+        }
+        text.push_str(line);
+    }
+    text
+}
+
+/// Convert YAML to a value, with mappings as dicts and sequences as lists
+fn yaml_value(yaml: &Yaml) -> Value {
+    match *yaml {
+        Yaml::Plain(ref text) => resolve(text),
+        Yaml::Scalar(ref value) => value.clone(),
+        Yaml::Seq(ref items) => Value::List(items.iter().map(yaml_value).collect()),
+        Yaml::Map(ref entries) => Value::Dict(entries.iter()
+            .map(|(key, value)| (key.clone(), yaml_value(value)))
+            .collect()),
+    }
+}
+
+/// The mappings in an entry that are imported as subnodes, or `None` if the entry is
+/// imported as a value
+fn mappings(yaml: &Yaml) -> Option<Vec<&[(String, Yaml)]>> {
+    match *yaml {
+        Yaml::Map(ref entries) => Some(vec![entries]),
+        Yaml::Seq(ref items) if !items.is_empty() => items.iter().map(|item| match *item {
+            Yaml::Map(ref entries) => Some(&entries[..]),
+            _ => None,
+        }).collect(),
+        _ => None,
+    }
+}
+
+/// Import a mapping as a node
+fn import_node(entries: &[(String, Yaml)]) -> Node {
+    let mut node = Node::new();
+    for (key, value) in entries {
+        if key == ARGUMENTS_KEY {
+            match *value {
+                Yaml::Seq(ref items) => for item in items {
+                    node.push_argument(yaml_value(item));
+                },
+                ref value => node.push_argument(yaml_value(value)),
+            }
+            continue;
+        }
+        match mappings(value) {
+            Some(mappings) => for mapping in mappings {
+                node.append_node(key.as_str(), import_node(mapping));
+            },
+            None => {
+                node.insert_attr(key.as_str(), yaml_value(value));
+            },
+        }
+    }
+    node
+}
+
+/// Convert a value to YAML.  Dicts are written with their keys in sorted order, as they
+/// don't keep their order, and durations in the same way as `to_json` writes them.
+fn value_yaml(value: &Value) -> Yaml {
+    match *value {
+        Value::List(ref items) => Yaml::Seq(items.iter().map(value_yaml).collect()),
+        Value::Dict(ref dict) => {
+            let mut entries: Vec<_> = dict.iter()
+                .map(|(key, value)| (key.clone(), value_yaml(value)))
+                .collect();
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            Yaml::Map(entries)
+        },
+        Value::Duration(ref duration) => Yaml::Map(vec![
+            ("secs".into(), Yaml::Scalar(Value::Int(duration.as_secs() as i64))),
+            ("nanos".into(), Yaml::Scalar(Value::Int(i64::from(duration.subsec_nanos())))),
+        ]),
+        Value::Node(ref node) => value_yaml(&Value::Dict(node.to_dict())),
+        ref value => Yaml::Scalar(value.clone()),
+    }
+}
+
+/// Add an entry to a mapping, replacing any entry that already has its key
+fn insert(entries: &mut Vec<(String, Yaml)>, key: &str, value: Yaml) {
+    match entries.iter_mut().find(|entry| entry.0 == key) {
+        Some(entry) => entry.1 = value,
+        None => entries.push((key.to_string(), value)),
+    }
+}
+
+/// Add groups of nodes with the same name to a mapping, with a single node as a mapping
+/// and several as a sequence of mappings, keeping the order that each name first appears in
+fn insert_nodes<'a, I>(entries: &mut Vec<(String, Yaml)>, nodes: I)
+        where I: IntoIterator<Item = &'a Node> {
+    let mut groups: Vec<(&str, Vec<Yaml>)> = Vec::new();
+    let mut indices = HashMap::new();
+    for node in nodes {
+        let index = *indices.entry(node.name()).or_insert_with(|| {
+            groups.push((node.name(), Vec::new()));
+            groups.len() - 1
+        });
+        groups[index].1.push(node_yaml(node));
+    }
+    for (name, mut nodes) in groups {
+        let value = if nodes.len() == 1 { nodes.remove(0) } else { Yaml::Seq(nodes) };
+        insert(entries, name, value);
+    }
+}
+
+fn node_yaml(node: &Node) -> Yaml {
+    let mut entries = Vec::new();
+    if !node.arguments().is_empty() {
+        let arguments = node.arguments().iter().map(value_yaml).collect();
+        entries.push((ARGUMENTS_KEY.to_string(), Yaml::Seq(arguments)));
+    }
+    for (key, value) in node.iter_attrs() {
+        insert(&mut entries, key.as_str(), value_yaml(value));
+    }
+    insert_nodes(&mut entries, node.children());
+    Yaml::Map(entries)
+}
+
+/// Check if a string can be written as a plain scalar, without being read back as
+/// something else
+fn is_plain(string: &str) -> bool {
+    let first = match string.chars().next() {
+        Some(first) => first,
+        None => return false,
+    };
+    !"-?:,[]{}#&*!|>'\"%@`".contains(first) && !first.is_whitespace()
+        && !string.ends_with(char::is_whitespace) && !string.ends_with(':')
+        && !string.contains(": ") && !string.contains(" #")
+        && !string.chars().any(|c| c.is_control() || ",[]{}".contains(c))
+        && matches!(resolve(string), Value::Str(_))
+}
+
+fn write_string(out: &mut String, string: &str) {
+    if is_plain(string) {
+        out.push_str(string);
+        return;
+    }
+    out.push('"');
+    for c in string.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            c if c.is_control() && (c as u32) < 0x100 => {
+                let _ = write!(out, "\\x{:02x}", c as u32);
+            },
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            },
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Write a scalar, or an empty collection, on the current line
+fn write_inline(out: &mut String, yaml: &Yaml) {
+    let value = match *yaml {
+        Yaml::Plain(ref text) => return out.push_str(text),
+        Yaml::Scalar(ref value) => value,
+        Yaml::Seq(_) => return out.push_str("[]"),
+        Yaml::Map(_) => return out.push_str("{}"),
+    };
+    match *value {
+        Value::Str(ref string) | Value::Ident(ref string) => write_string(out, string),
+        Value::Float(float) if float.is_nan() => out.push_str(".nan"),
+        Value::Float(float) if float.is_infinite() => {
+            out.push_str(if float > 0.0 { ".inf" } else { "-.inf" });
+        },
+        Value::Float(float) => {
+            let _ = write!(out, "{:?}", float);
+        },
+        Value::Bytes(ref bytes) => {
+            out.push_str("!!binary ");
+            out.push_str(&encode_base64(bytes));
+        },
+        Value::Null => out.push_str("null"),
+        ref value => {
+            let _ = write!(out, "{}", value);
+        },
+    }
+}
+
+/// Check if YAML is written on lines of its own, rather than inline
+fn is_block(yaml: &Yaml) -> bool {
+    match *yaml {
+        Yaml::Seq(ref items) => !items.is_empty(),
+        Yaml::Map(ref entries) => !entries.is_empty(),
+        _ => false,
+    }
+}
+
+/// Write YAML indented by `indent`.  If `inline` is set, the first line is written on the
+/// current line, after a `-` indicator.
+fn write_block(out: &mut String, yaml: &Yaml, indent: usize, inline: bool) {
+    match *yaml {
+        Yaml::Map(ref entries) if !entries.is_empty() => {
+            for (index, (key, value)) in entries.iter().enumerate() {
+                if index > 0 || !inline {
+                    out.extend((0..indent).map(|_| ' '));
+                }
+                write_string(out, key);
+                out.push(':');
+                if is_block(value) {
+                    out.push('\n');
+                    write_block(out, value, indent + 2, false);
+                } else {
+                    out.push(' ');
+                    write_inline(out, value);
+                    out.push('\n');
+                }
+            }
+        },
+        Yaml::Seq(ref items) if !items.is_empty() => {
+            for (index, item) in items.iter().enumerate() {
+                if index > 0 || !inline {
+                    out.extend((0..indent).map(|_| ' '));
+                }
+                out.push_str("- ");
+                if is_block(item) {
+                    write_block(out, item, indent + 2, true);
+                } else {
+                    write_inline(out, item);
+                    out.push('\n');
+                }
+            }
+        },
+        _ => {
+            write_inline(out, yaml);
+            out.push('\n');
+        },
+    }
+}
+
+impl Document {
+    /// Import a document from YAML, in the same way as JSON (see `from_json_value`):
+    /// mappings become nodes, with their `$arguments` as the node's arguments, and
+    /// sequences of mappings become runs of nodes with the same name.  Other values become
+    /// attributes, or at the top level of the document, nodes with the value as their
+    /// single argument.  An empty YAML document is an empty document.
+    ///
+    /// Scalars are read with the YAML 1.2 core schema, and timestamps as datetimes.
+    /// Anchors, aliases and merge keys (`<<`) are expanded, and `!!binary` scalars are
+    /// read as bytes.  Complex keys, multi-line plain scalars and other tags aren't
+    /// supported, and the YAML can only contain a single document.
+    ///
+    /// # Failures
+    /// Fails if the YAML is invalid or uses something that isn't supported, or if it isn't
+    /// a mapping, or has a `$arguments` key at the top level.
+    ///
+    /// # Examples
+    /// ```
+    /// # use figtree::types::{Document, Value};
+    /// let yaml = "
+    /// defaults: &defaults
+    ///   timeout: 30
+    /// server:
+    ///   $arguments: [web]
+    ///   <<: *defaults
+    ///   upstream:
+    ///     - host: a
+    ///     - host: b
+    /// ";
+    /// let doc = Document::from_yaml(yaml).unwrap();
+    /// assert_eq!(doc["server"].arguments(), &[Value::from("web")][..]);
+    /// assert_eq!(doc["server"].get("timeout"), Some(&Value::new_int(30)));
+    /// assert_eq!(doc["server"].children_named("upstream").count(), 2);
+    /// ```
+    pub fn from_yaml(text: &str) -> Result<Document, YamlError> {
+        let yaml = Parser::new(text).document()?;
+        let entries = match yaml {
+            Yaml::Map(ref entries) => entries,
+            Yaml::Scalar(Value::Null) => return Ok(Document::new()),
+            ref yaml => {
+                let message = format!("expected a mapping for the document, found {}", kind(yaml));
+                return Err(YamlError { message, position: None });
+            },
+        };
+        let mut document = Document::new();
+        for (key, value) in entries {
+            if key == ARGUMENTS_KEY {
+                let message = "a document can't have arguments".into();
+                return Err(YamlError { message, position: None });
+            }
+            match mappings(value) {
+                Some(mappings) => for mapping in mappings {
+                    document.append_node(key.as_str(), import_node(mapping));
+                },
+                None => {
+                    let mut node = Node::new();
+                    node.push_argument(yaml_value(value));
+                    document.append_node(key.as_str(), node);
+                },
+            }
+        }
+        Ok(document)
+    }
+
+    /// Write the document as YAML, in the same shape as `to_json`.  Nodes are written as
+    /// block mappings, with their arguments under `$arguments`, and nodes that share a
+    /// name as block sequences.  Strings are only quoted if they need to be, bytes are
+    /// written as `!!binary` scalars, and comments are lost.
+    ///
+    /// # Examples
+    /// ```
+    /// # use figtree::{Figtree, ParserOptions};
+    /// let options = ParserOptions::new().node_arguments(true).repeated_nodes(true);
+    /// let doc = Figtree::from_string("listen 80 { tls = false } listen 443 { tls = true }")
+    ///     .with_options(options).parse().unwrap();
+    /// assert_eq!(doc.to_yaml(), concat!(
+    ///     "listen:\n",
+    ///     "  - $arguments:\n",
+    ///     "      - 80\n",
+    ///     "    tls: false\n",
+    ///     "  - $arguments:\n",
+    ///     "      - 443\n",
+    ///     "    tls: true\n",
+    /// ));
+    /// ```
+    pub fn to_yaml(&self) -> String {
+        let mut entries = Vec::new();
+        insert_nodes(&mut entries, self.children());
+        let mut out = String::new();
+        write_block(&mut out, &Yaml::Map(entries), 0, false);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::super::figtree::Figtree;
+    use super::super::parser::ParserOptions;
+    use super::*;
+
+    fn parse(input: &str) -> Value {
+        yaml_value(&Parser::new(input).document().unwrap())
+    }
+
+    fn error(input: &str) -> String {
+        Parser::new(input).document().unwrap_err().to_string()
+    }
+
+    fn list(items: Vec<Value>) -> Value {
+        Value::List(items)
+    }
+
+    #[test]
+    fn resolves_scalars() {
+        assert_eq!(resolve("~"), Value::Null);
+        assert_eq!(resolve("True"), Value::Bool(true));
+        assert_eq!(resolve("-12"), Value::Int(-12));
+        assert_eq!(resolve("0x1f"), Value::Int(31));
+        assert_eq!(resolve("0o17"), Value::Int(15));
+        assert_eq!(resolve("1.5e3"), Value::Float(1500.0));
+        assert_eq!(resolve(".5"), Value::Float(0.5));
+        assert_eq!(resolve("-.inf"), Value::Float(f64::NEG_INFINITY));
+        assert!(resolve(".nan").get_float().is_some_and(f64::is_nan));
+        assert!(resolve("2024-01-01T12:00:00Z").is_datetime());
+        assert_eq!(resolve("1.2.3"), Value::from("1.2.3"));
+        assert_eq!(resolve("e5"), Value::from("e5"));
+        assert_eq!(resolve("0x"), Value::from("0x"));
+    }
+
+    #[test]
+    fn parses_block_collections() {
+        let input = concat!(
+            "# a comment\n",
+            "a: 1\n",
+            "b:\n",
+            "  c: two words  # comment\n",
+            "  d:\n",
+            "  - x\n",
+            "  - - y\n",
+            "    - z\n",
+            "e:\n",
+            "  - f: 1\n",
+            "    g: 2\n",
+            "  -\n",
+            "    f: 3\n",
+            "h:\n",
+            "i: 'it''s'\n",
+        );
+        let value = parse(input);
+        assert_eq!(value["a"], Value::Int(1));
+        assert_eq!(value["b"]["c"], Value::from("two words"));
+        assert_eq!(value["b"]["d"], list(vec![Value::from("x"),
+                                              list(vec![Value::from("y"), Value::from("z")])]));
+        assert_eq!(value["e"][0]["g"], Value::Int(2));
+        assert_eq!(value["e"][1]["f"], Value::Int(3));
+        assert_eq!(value["h"], Value::Null);
+        assert_eq!(value["i"], Value::from("it's"));
+    }
+
+    #[test]
+    fn parses_flow_collections() {
+        let value = parse("a: [1, 'b', {c: d, e}, []]\nb: {\"x\":1,\n  y: [2,],\n}\n");
+        assert_eq!(value["a"][1], Value::from("b"));
+        assert_eq!(value["a"][2]["c"], Value::from("d"));
+        assert_eq!(value["a"][2]["e"], Value::Null);
+        assert_eq!(value["a"][3], list(vec![]));
+        assert_eq!(value["b"]["x"], Value::Int(1));
+        assert_eq!(value["b"]["y"], list(vec![Value::Int(2)]));
+    }
+
+    #[test]
+    fn parses_quoted_and_block_scalars() {
+        let input = concat!(
+            "a: \"tab\\there \\u00e9\\x41\"\n",
+            "b: \"folded\n",
+            "  line\n",
+            "\n",
+            "  end\"\n",
+            "c: |\n",
+            "  line 1\n",
+            "    indented\n",
+            "\n",
+            "d: >-\n",
+            "  folded\n",
+            "  text\n",
+            "\n",
+            "  para\n",
+            "e: |+\n",
+            "  kept\n",
+            "\n",
+            "f: !!binary aGVsbG8=\n",
+            "g: !!str 123\n",
+        );
+        let value = parse(input);
+        assert_eq!(value["a"], Value::from("tab\there éA"));
+        assert_eq!(value["b"], Value::from("folded line\nend"));
+        assert_eq!(value["c"], Value::from("line 1\n  indented\n"));
+        assert_eq!(value["d"], Value::from("folded text\npara"));
+        assert_eq!(value["e"], Value::from("kept\n\n"));
+        assert_eq!(value["f"], Value::new_bytes(b"hello".to_vec()));
+        assert_eq!(value["g"], Value::from("123"));
+    }
+
+    #[test]
+    fn expands_anchors() {
+        let input = concat!(
+            "---\n",
+            "base: &base {a: 1, b: 2}\n",
+            "list: &list [x]\n",
+            "copy: *list\n",
+            "merged:\n",
+            "  <<: *base\n",
+            "  b: 3\n",
+            "...\n",
+        );
+        let value = parse(input);
+        assert_eq!(value["copy"], list(vec![Value::from("x")]));
+        assert_eq!(value["merged"]["a"], Value::Int(1));
+        assert_eq!(value["merged"]["b"], Value::Int(3));
+    }
+
+    #[test]
+    fn reports_errors() {
+        assert_eq!(error("a: 1\n b: 2\n"), "unexpected indentation (line 2, column 2)");
+        assert_eq!(error("a: b: c\n"),
+                   "a mapping can't start on the same line as a key (line 1, column 4)");
+        assert_eq!(error("a: 1\na: 2\n"), "duplicate key `a` (line 2, column 1)");
+        assert_eq!(error("a: *b\n"), "unknown alias `b` (line 1, column 4)");
+        assert_eq!(error("a: [1, 2\n"), "unclosed flow collection (line 1, column 4)");
+        assert_eq!(error("a: 'b\n"), "unclosed string (line 1, column 4)");
+        assert_eq!(error("a: !foo b\n"), "unsupported tag `!foo` (line 1, column 4)");
+        assert_eq!(error("a: 1\n---\nb: 2\n"),
+                   "only a single document can be imported (line 2, column 1)");
+        assert_eq!(error("? a\n: b\n"), "complex keys aren't supported (line 1, column 1)");
+    }
+
+    #[test]
+    fn imports_documents() {
+        let input = concat!(
+            "server:\n",
+            "  $arguments: [web, 2]\n",
+            "  ports: [80, 443]\n",
+            "  upstream:\n",
+            "    - host: a\n",
+            "    - host: b\n",
+            "  tls:\n",
+            "    cert: c.pem\n",
+            "  mixed: [{a: 1}, 2]\n",
+            "port: 8080\n",
+        );
+        let doc = Document::from_yaml(input).unwrap();
+        let server = &doc["server"];
+        assert_eq!(server.arguments(), &[Value::from("web"), Value::new_int(2)][..]);
+        assert_eq!(server.get("ports"), Some(&list(vec![Value::new_int(80), Value::new_int(443)])));
+        assert_eq!(server.children_named("upstream").count(), 2);
+        assert_eq!(server.child("tls").and_then(|tls| tls.get("cert")), Some(&Value::from("c.pem")));
+        assert!(server.get("mixed").is_some_and(Value::is_list));
+        assert_eq!(doc["port"].arguments(), &[Value::new_int(8080)][..]);
+        assert!(Document::from_yaml("# nothing\n").unwrap().is_empty());
+
+        let error = Document::from_yaml("[1]").unwrap_err();
+        assert_eq!(error.to_string(), "expected a mapping for the document, found a sequence");
+        let error = Document::from_yaml("$arguments: []").unwrap_err();
+        assert_eq!(error.to_string(), "a document can't have arguments");
+    }
+
+    #[test]
+    fn writes_documents() {
+        let input = "
+            server 'web' {
+                ports = [80, 443],
+                name = 'a: b',
+                count = '10',
+                empty = [],
+                ratio = 1.0,
+                timeout = 5s,
+                key = b\"\\x00\\xff\",
+                env = { HOME = '~', PATH = '/bin' }
+                upstream { host = 'a' }
+                upstream { host = 'b', weights = [[1, 2]] }
+                tls {}
+            }
+        ";
+        let options = ParserOptions::new().node_arguments(true).repeated_nodes(true);
+        let doc = Figtree::from_string(input).with_options(options).parse().unwrap();
+        assert_eq!(doc.to_yaml(), concat!(
+            "server:\n",
+            "  $arguments:\n",
+            "    - web\n",
+            "  ports:\n",
+            "    - 80\n",
+            "    - 443\n",
+            "  name: \"a: b\"\n",
+            "  count: \"10\"\n",
+            "  empty: []\n",
+            "  ratio: 1.0\n",
+            "  timeout:\n",
+            "    secs: 5\n",
+            "    nanos: 0\n",
+            "  key: !!binary AP8=\n",
+            "  env:\n",
+            "    HOME: \"~\"\n",
+            "    PATH: /bin\n",
+            "  upstream:\n",
+            "    - host: a\n",
+            "    - host: b\n",
+            "      weights:\n",
+            "        - - 1\n",
+            "          - 2\n",
+            "  tls: {}\n",
+        ));
+        assert_eq!(Document::new().to_yaml(), "{}\n");
+    }
+
+    #[test]
+    fn round_trips_documents() {
+        let input = "
+            a 1 'two' {
+                b = ['x', \"multi\\nline\", '#1', '-', true, null, 2024-01-01T00:00:00Z],
+                c = -1.5e-7,
+                d = 'null'
+                e { f = 1 }
+                e { f = 2 }
+            }
+            g {}
+        ";
+        let options = ParserOptions::new().node_arguments(true).repeated_nodes(true);
+        let doc = Figtree::from_string(input).with_options(options).parse().unwrap();
+        let yaml = doc.to_yaml();
+        assert_eq!(Document::from_yaml(&yaml).unwrap(), doc, "{}", yaml);
+
+        let mut node = Node::new();
+        node.insert_attr("d", Value::new_duration(Duration::new(1, 5)));
+        let mut dicts = Document::new();
+        dicts.append_node("a", node);
+        let imported = Document::from_yaml(&dicts.to_yaml()).unwrap();
+        assert_eq!(imported["a"].child("d").and_then(|d| d.get("nanos")), Some(&Value::new_int(5)));
+    }
+}
@@ -1,3 +1,6 @@
+// the tests are kept as they were written, before these lints existed
+#![allow(clippy::ok_expect, clippy::err_expect, clippy::redundant_static_lifetimes)]
+
 extern crate figtree;
 use figtree::Figtree;
 
@@ -1,3 +1,6 @@
+// the tests are kept as they were written, before these lints existed
+#![allow(clippy::ok_expect, clippy::err_expect, clippy::redundant_static_lifetimes)]
+
 extern crate figtree;
 use figtree::Figtree;
 
@@ -70,3 +73,30 @@ fn using_nulls() {
     assert_eq!(null_node.get_int(), None);
     assert!(null_node.is_null());
 }
+
+#[test]
+fn parsing_events_from_a_file() {
+    use figtree::{Parser, ParseEvent};
+
+    let parser = Parser::from_path(SAMPLE).expect("file does not exist");
+    let nodes = parser
+        .map(|result| result.expect("parsing error occurred").0)
+        .filter(|event| matches!(event, ParseEvent::NodeStart(_, _)))
+        .count();
+
+    assert_eq!(nodes, 2);
+}
+
+#[test]
+fn errors_name_the_file() {
+    use figtree::Origin;
+
+    let mut figgy = Figtree::from_filename(SAMPLE).expect("file does not exist");
+    let config = figgy.parse().expect("parsing error occurred");
+
+    assert_eq!(config.origin(), Some(&Origin::File(SAMPLE.into())));
+    let subnode = config.get_node("test").and_then(|node| node.get_node("subtest"))
+        .expect("subnode is not present");
+    let error = subnode.get_i64("nonexistent").expect_err("attribute is not null");
+    assert!(error.to_string().contains("(in tests/resources/sample.ft, at line"), "{}", error);
+}